@@ -1,3 +1,7 @@
+use std::time::Duration;
+
+use crate::frame::{NewConnectionIdFrame, SendFrame};
+
 mod connection_id;
 pub use connection_id::*;
 
@@ -24,3 +28,48 @@ impl<LOCAL, REMOTE> Registry<LOCAL, REMOTE> {
         Self { local, remote }
     }
 }
+
+impl<ISSUED, REMOTE> Registry<ArcLocalCids<ISSUED>, REMOTE>
+where
+    ISSUED: GenUniqueCid + SendFrame<NewConnectionIdFrame>,
+{
+    /// Rotate the local connection IDs if the `policy` requires after the migration,
+    /// see [`ArcLocalCids::rotate`].
+    pub fn on_migrated(&self, policy: &RotationPolicy) {
+        if policy.after_migration {
+            self.local.rotate();
+        }
+    }
+}
+
+/// The policy to rotate the connection IDs issued to the peer proactively.
+///
+/// Rotating the connection IDs makes it harder for the observers to link the packets of a
+/// long-lived connection together. The rotation issues as many new connection IDs as the peer's
+/// active_connection_id_limit, and requests the peer to retire the previous ones through the
+/// Retire Prior To field, see [`ArcLocalCids::rotate`].
+///
+/// By default, the connection IDs are never rotated proactively.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Rotate the connection IDs periodically with this interval after the handshake is done.
+    pub interval: Option<Duration>,
+    /// Rotate the connection IDs after the connection is migrated to a new path actively.
+    pub after_migration: bool,
+}
+
+impl RotationPolicy {
+    /// Rotate the connection IDs every `interval`.
+    pub fn every(interval: Duration) -> Self {
+        Self {
+            interval: Some(interval),
+            ..Self::default()
+        }
+    }
+
+    /// Also rotate the connection IDs after the connection is migrated.
+    pub fn with_after_migration(mut self) -> Self {
+        self.after_migration = true;
+        self
+    }
+}
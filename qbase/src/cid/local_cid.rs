@@ -29,6 +29,9 @@ where
     // it can be set to None and will be reset.
     // If this transport parameter is absent, a default of 2 is assumed.
     active_cid_limit: Option<u64>,
+    // The connection IDs of the sequence less than it are requested to be retired by the peer,
+    // it increases when the connection IDs are rotated.
+    retire_prior_to: u64,
 }
 
 impl<ISSUED> RawLocalCids<ISSUED>
@@ -36,11 +39,21 @@ where
     ISSUED: GenUniqueCid + SendFrame<NewConnectionIdFrame>,
 {
     /// Create a new local connection ID manager.
+    ///
+    /// No more connection ID is issued if `scid` is zero-length.
     fn new(scid: ConnectionId, issued_cids: ISSUED) -> Self {
         let mut cid_deque = IndexDeque::default();
         cid_deque
             .push_back(Some((scid, ResetToken::default())))
             .unwrap();
+        if scid.is_empty() {
+            return Self {
+                cid_deque,
+                issued_cids,
+                active_cid_limit: None,
+                retire_prior_to: 0,
+            };
+        }
 
         let new_cid = issued_cids.gen_unique_cid();
         let new_cid_frame =
@@ -53,6 +66,7 @@ where
             cid_deque,
             issued_cids,
             active_cid_limit: None,
+            retire_prior_to: 0,
         }
     }
 
@@ -70,17 +84,49 @@ where
                 format!("{} < 2", active_cid_limit),
             ));
         }
+        self.active_cid_limit = Some(active_cid_limit);
+        if self.is_zero_length() {
+            return Ok(());
+        }
         for _ in self.cid_deque.largest()..active_cid_limit {
             self.issue_new_cid();
         }
-        self.active_cid_limit = Some(active_cid_limit);
         Ok(())
     }
 
+    /// Whether the zero-length connection ID is used, which is the only one of the connection.
+    fn is_zero_length(&self) -> bool {
+        self.cid_deque
+            .get(0)
+            .is_some_and(|v| v.is_some_and(|(cid, _)| cid.is_empty()))
+    }
+
+    /// The number of the connection IDs that are active in the peer's view, which excludes the
+    /// ones requested to be retired by the Retire Prior To field.
+    fn active_count(&self) -> u64 {
+        self.cid_deque
+            .iter_with_idx()
+            .filter(|(seq, v)| *seq >= self.retire_prior_to && v.is_some())
+            .count() as u64
+    }
+
+    /// Issue new connection IDs and request the peer to retire all the previous ones,
+    /// see [`ArcLocalCids::rotate`].
+    fn rotate(&mut self) {
+        if self.is_zero_length() {
+            return;
+        }
+        self.retire_prior_to = self.cid_deque.largest();
+        for _ in 0..self.active_cid_limit.unwrap_or(2) {
+            self.issue_new_cid();
+        }
+    }
+
     /// Issue a new connection ID, for internal used only.
     fn issue_new_cid(&mut self) {
         let seq = VarInt::from_u64(self.cid_deque.largest()).unwrap();
-        let retire_prior_to = VarInt::from_u64(self.cid_deque.offset()).unwrap();
+        let retire_prior_to =
+            VarInt::from_u64(self.cid_deque.offset().max(self.retire_prior_to)).unwrap();
         let new_cid = self.issued_cids.gen_unique_cid();
         let new_cid_frame = NewConnectionIdFrame::new(new_cid, seq, retire_prior_to);
         self.issued_cids.send_frame([new_cid_frame]);
@@ -94,10 +140,22 @@ where
         &mut self,
         frame: &RetireConnectionIdFrame,
     ) -> Result<Option<ConnectionId>, Error> {
+        // An endpoint that provides a zero-length connection ID MUST treat receipt of a
+        // RETIRE_CONNECTION_ID frame as a connection error of type PROTOCOL_VIOLATION.
+        if self.is_zero_length() {
+            return Err(Error::new(
+                ErrorKind::ProtocolViolation,
+                frame.frame_type(),
+                "zero-length connection ID can not be retired",
+            ));
+        }
+        // Receipt of a RETIRE_CONNECTION_ID frame containing a sequence number greater than any
+        // previously sent to the peer MUST be treated as a connection error of type
+        // PROTOCOL_VIOLATION.
         let seq = frame.sequence.into_inner();
         if seq >= self.cid_deque.largest() {
             return Err(Error::new(
-                ErrorKind::ConnectionIdLimit,
+                ErrorKind::ProtocolViolation,
                 frame.frame_type(),
                 format!(
                     "Sequence({seq}) in RetireConnectionIdFrame exceeds the largest one({}) issued by us",
@@ -111,8 +169,11 @@ where
                 let n = self.cid_deque.iter().take_while(|v| v.is_none()).count();
                 self.cid_deque.advance(n);
 
-                // generates a new connection ID while retiring an old one.
-                self.issue_new_cid();
+                // generates a new connection ID while retiring an old one, unless the connection
+                // IDs have been replenished by the rotation.
+                if self.active_count() < self.active_cid_limit.unwrap_or(2) {
+                    self.issue_new_cid();
+                }
                 return Ok(Some(cid));
             }
         }
@@ -174,6 +235,30 @@ where
             .collect()
     }
 
+    /// Get the connection ID of the sequence number `seq` and its stateless reset token, return
+    /// [`None`] if it has not been issued or has been retired.
+    ///
+    /// The connection ID of the sequence number 1 is issued at creation, a server can advertise it
+    /// in the preferred_address transport parameter, see [Section 5.1.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-5.1.1)
+    /// of RFC 9000.
+    pub fn issued_cid(&self, seq: u64) -> Option<(ConnectionId, ResetToken)> {
+        self.0.lock().unwrap().cid_deque.get(seq).copied().flatten()
+    }
+
+    /// Get the sequence number of the active connection ID `cid`, return [`None`] if it is not
+    /// issued by this endpoint or has been retired.
+    ///
+    /// When the multipath extension is used, the packets received with different connection IDs
+    /// belong to different packet number spaces, which are identified by the sequence numbers.
+    pub fn seq_of(&self, cid: &ConnectionId) -> Option<u64> {
+        self.0
+            .lock()
+            .unwrap()
+            .cid_deque
+            .iter_with_idx()
+            .find_map(|(seq, v)| v.filter(|(id, _)| id == cid).map(|_| seq))
+    }
+
     /// Set the maximum number of active connection IDs.
     ///
     /// After fully obtaining the peer's connection parameters, extract the peer's
@@ -181,6 +266,19 @@ where
     pub fn set_limit(&self, active_cid_limit: u64) -> Result<(), Error> {
         self.0.lock().unwrap().set_limit(active_cid_limit)
     }
+
+    /// Rotate the connection IDs, see [Section 5.1.2](https://www.rfc-editor.org/rfc/rfc9000.html#section-5.1.2)
+    /// of RFC 9000.
+    ///
+    /// As many new connection IDs as the peer's active_connection_id_limit are issued, with the
+    /// Retire Prior To field requesting the peer to retire all the previous ones. The previous
+    /// connection IDs keep routing the packets until the peer retires them by sending the
+    /// [`RetireConnectionIdFrame`]s, which won't trigger issuing more connection IDs then.
+    ///
+    /// It does nothing if the zero-length connection ID is used.
+    pub fn rotate(&self) {
+        self.0.lock().unwrap().rotate()
+    }
 }
 
 impl<ISSUED> ReceiveFrame<RetireConnectionIdFrame> for ArcLocalCids<ISSUED>
@@ -230,6 +328,12 @@ mod tests {
     fn test_issue_cid() {
         let initial_scid = ConnectionId::random_gen(8);
         let local_cids = ArcLocalCids::new(initial_scid, IssuedCids::default());
+        let issued = local_cids.0.lock().unwrap().issued_cids.lock_guard()[0];
+        assert_eq!(
+            local_cids.issued_cid(1),
+            Some((issued.id, issued.reset_token))
+        );
+        assert_eq!(local_cids.issued_cid(2), None);
         let mut guard = local_cids.0.lock().unwrap();
 
         assert_eq!(guard.cid_deque.len(), 2);
@@ -238,6 +342,68 @@ mod tests {
         assert_eq!(guard.cid_deque.len(), 3);
     }
 
+    #[test]
+    fn test_zero_length_cid() {
+        let local_cids = ArcLocalCids::new(ConnectionId::default(), IssuedCids::default());
+        local_cids.set_limit(4).unwrap();
+        assert_eq!(local_cids.active_cids(), vec![ConnectionId::default()]);
+        assert_eq!(local_cids.seq_of(&ConnectionId::default()), Some(0));
+        assert!(local_cids
+            .0
+            .lock()
+            .unwrap()
+            .issued_cids
+            .lock_guard()
+            .is_empty());
+
+        let retire_frame = RetireConnectionIdFrame {
+            sequence: VarInt::from_u32(0),
+        };
+        assert!(local_cids.recv_frame(&retire_frame).is_err());
+    }
+
+    #[test]
+    fn test_rotate() {
+        let initial_scid = ConnectionId::random_gen(8);
+        let mut local_cids = RawLocalCids::new(initial_scid, IssuedCids::default());
+        local_cids.set_limit(3).unwrap();
+        assert_eq!(local_cids.issued_cids.lock_guard().len(), 2);
+
+        local_cids.rotate();
+        let issued = local_cids.issued_cids.lock_guard().clone();
+        assert_eq!(issued.len(), 5);
+        for (seq, frame) in issued.iter().enumerate().skip(2) {
+            assert_eq!(frame.sequence.into_inner(), seq as u64 + 1);
+            assert_eq!(frame.retire_prior_to.into_inner(), 3);
+        }
+        assert_eq!(local_cids.active_count(), 3);
+
+        // the previous connection IDs are retired without issuing more ones
+        for seq in 0..3 {
+            let retire_frame = RetireConnectionIdFrame {
+                sequence: VarInt::from_u32(seq),
+            };
+            assert!(local_cids
+                .recv_retire_cid_frame(&retire_frame)
+                .unwrap()
+                .is_some());
+        }
+        assert_eq!(local_cids.issued_cids.lock_guard().len(), 5);
+        assert_eq!(local_cids.cid_deque.offset(), 3);
+
+        // retiring the rotated one still replenishes
+        let retire_frame = RetireConnectionIdFrame {
+            sequence: VarInt::from_u32(3),
+        };
+        assert!(local_cids
+            .recv_retire_cid_frame(&retire_frame)
+            .unwrap()
+            .is_some());
+        let issued = local_cids.issued_cids.lock_guard().clone();
+        assert_eq!(issued.len(), 6);
+        assert_eq!(issued[5].retire_prior_to.into_inner(), 4);
+    }
+
     #[test]
     fn test_recv_retire_cid_frame() {
         let initial_scid = ConnectionId::random_gen(8);
@@ -275,5 +441,16 @@ mod tests {
         };
         let cid3 = local_cids.recv_retire_cid_frame(&retire_frame);
         assert!(cid3.is_ok());
+
+        let retire_frame = RetireConnectionIdFrame {
+            sequence: VarInt::from_u32(100),
+        };
+        assert_eq!(
+            local_cids
+                .recv_retire_cid_frame(&retire_frame)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::ProtocolViolation
+        );
     }
 }
@@ -88,19 +88,17 @@ where
         &mut self,
         frame: &NewConnectionIdFrame,
     ) -> Result<Option<ResetToken>, Error> {
-        let seq = frame.sequence.into_inner();
-        let retire_prior_to = frame.retire_prior_to.into_inner();
-        let active_len = seq.saturating_sub(retire_prior_to);
-        if active_len > self.active_cid_limit {
+        // An endpoint that is sending packets with a zero-length Destination Connection ID MUST
+        // treat receipt of a NEW_CONNECTION_ID frame as a connection error of type PROTOCOL_VIOLATION.
+        if self.is_zero_length() {
             return Err(Error::new(
-                crate::error::ErrorKind::ConnectionIdLimit,
+                crate::error::ErrorKind::ProtocolViolation,
                 frame.frame_type(),
-                format!(
-                    "{active_len} exceed active_cid_limit {}",
-                    self.active_cid_limit
-                ),
+                "the peer uses zero-length connection ID",
             ));
         }
+        let seq = frame.sequence.into_inner();
+        let retire_prior_to = frame.retire_prior_to.into_inner();
 
         // Discard the frame if the sequence number is less than the current offset.
         if frame.sequence < self.cid_deque.offset() {
@@ -109,13 +107,62 @@ where
 
         let id = frame.id;
         let token = frame.reset_token;
+        // A sequence number used for different connection IDs, or a connection ID repeated with
+        // a different sequence number or stateless reset token, see [Section 19.15](https://www.rfc-editor.org/rfc/rfc9000.html#section-19.15)
+        // of RFC 9000.
+        let conflicted = self.cid_deque.iter_with_idx().any(|(s, v)| {
+            v.is_some_and(|(_, cid, t)| (s == seq) != (cid == id) || (s == seq && t != token))
+        });
+        if conflicted {
+            return Err(Error::new(
+                crate::error::ErrorKind::ProtocolViolation,
+                frame.frame_type(),
+                format!("connection ID of sequence {seq} conflicts with the issued ones"),
+            ));
+        }
+
         self.cid_deque.insert(seq, Some((seq, id, token))).unwrap();
         self.retire_prior_to(retire_prior_to);
+
+        // After adding and retiring, the active connection IDs must not exceed the limit
+        let active_len = self.active_count();
+        if active_len > self.active_cid_limit {
+            return Err(Error::new(
+                crate::error::ErrorKind::ConnectionIdLimit,
+                frame.frame_type(),
+                format!(
+                    "{active_len} exceed active_cid_limit {}",
+                    self.active_cid_limit
+                ),
+            ));
+        }
         self.arrange_idle_cid();
 
         Ok(Some(token))
     }
 
+    /// The number of the active connection IDs issued by the peer, which are neither retired by
+    /// the Retire Prior To field nor by the paths.
+    fn active_count(&self) -> u64 {
+        self.cid_deque
+            .iter_with_idx()
+            .filter(|(seq, v)| {
+                v.is_some()
+                    && self
+                        .cid_cells
+                        .get(*seq)
+                        .is_none_or(|cell| !cell.0.lock().unwrap().is_retired())
+            })
+            .count() as u64
+    }
+
+    /// Whether the peer uses the zero-length connection ID, which is the only one of the peer.
+    fn is_zero_length(&self) -> bool {
+        self.cid_deque
+            .get(0)
+            .is_some_and(|v| v.is_some_and(|(_, cid, _)| cid.is_empty()))
+    }
+
     /// Arrange the idle cids to the front of the cid applys
     #[doc(hidden)]
     fn arrange_idle_cid(&mut self) {
@@ -205,7 +252,14 @@ where
     }
 
     /// Apply for a new connection ID, and return an [`ArcCidCell`], which may be not ready state.
+    ///
+    /// If the peer uses the zero-length connection ID, it's used on all paths and never retired,
+    /// see [Section 5.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-5.1) of RFC 9000.
     fn apply_dcid(&mut self) -> ArcCidCell<RETIRED> {
+        if self.is_zero_length() {
+            let state = CidState(Future::with(Some(ConnectionId::default())));
+            return ArcCidCell::new(self.retired_cids.clone(), 0, state);
+        }
         let state = if let Some(Some((_, cid, _))) = self.cid_deque.get(self.cursor) {
             self.cursor += 1;
             CidState(Future::with(Some(*cid)))
@@ -356,6 +410,20 @@ where
         self.0.lock().unwrap().poll_get_cid(cx)
     }
 
+    /// Get the connection ID if it is ready, [`None`] if it is not assigned yet or has been
+    /// retired.
+    pub fn try_get_cid(&self) -> Option<ConnectionId> {
+        self.0.lock().unwrap().state.0.try_get().flatten()
+    }
+
+    /// Get the sequence number of the connection ID assigned or to be assigned to this cell.
+    ///
+    /// Note that the sequence number changes when the connection ID is retired by the peer
+    /// with a [`NewConnectionIdFrame`] that retires prior to it.
+    pub fn seq(&self) -> u64 {
+        self.0.lock().unwrap().seq
+    }
+
     /// Getting the connection ID, if it is not ready, return a future
     #[inline]
     pub fn get_cid(&self) -> Self {
@@ -364,12 +432,23 @@ where
 
     /// When the Path is invalid, the connection id needs to be retired, and the Cell state
     /// is marked as no longer in use, with a [`RetireConnectionIdFrame`] being sent to peer.
+    ///
+    /// The zero-length connection ID is never retired to the peer.
     #[inline]
     pub fn retire(&self) {
         let mut guard = self.0.lock().unwrap();
 
         if !guard.state.is_retired() {
+            let zero_length = guard
+                .state
+                .0
+                .try_get()
+                .flatten()
+                .is_some_and(|cid| cid.is_empty());
             guard.state.retire();
+            if zero_length {
+                return;
+            }
             let sequence = VarInt::try_from(guard.seq)
                 .expect("Sequence of connection id is very hard to exceed VARINT_MAX");
             guard
@@ -437,6 +516,37 @@ mod tests {
         assert_eq!(cid_apply2.get_cid().poll_unpin(&mut cx), Poll::Pending);
     }
 
+    #[test]
+    fn test_zero_length_remote_cid() {
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let retired_cids = ArcAsyncDeque::<RetireConnectionIdFrame>::new();
+        let mut remote_cids = RawRemoteCids::new(ConnectionId::default(), 8, retired_cids.clone());
+
+        // All paths use the zero-length connection ID
+        let cid_apply0 = remote_cids.apply_dcid();
+        let cid_apply1 = remote_cids.apply_dcid();
+        assert_eq!(
+            cid_apply0.get_cid().poll_unpin(&mut cx),
+            Poll::Ready(Some(ConnectionId::default()))
+        );
+        assert_eq!(
+            cid_apply1.get_cid().poll_unpin(&mut cx),
+            Poll::Ready(Some(ConnectionId::default()))
+        );
+
+        cid_apply1.retire();
+        assert_eq!(retired_cids.len(), 0);
+
+        let frame = NewConnectionIdFrame {
+            sequence: VarInt::from_u32(1),
+            retire_prior_to: VarInt::from_u32(0),
+            id: ConnectionId::random_gen(8),
+            reset_token: ResetToken::random_gen(),
+        };
+        assert!(remote_cids.recv_new_cid_frame(&frame).is_err());
+    }
+
     #[test]
     fn test_retire_in_remote_cids() {
         let waker = futures::task::noop_waker();
@@ -548,4 +658,45 @@ mod tests {
             Poll::Ready(Some(cids[5]))
         );
     }
+
+    #[test]
+    fn test_active_cid_limit() {
+        let initial_dcid = ConnectionId::random_gen(8);
+        let retired_cids = ArcAsyncDeque::<RetireConnectionIdFrame>::new();
+        let mut remote_cids = RawRemoteCids::new(initial_dcid, 2, retired_cids.clone());
+
+        let cid1 = ConnectionId::random_gen(8);
+        let frame = NewConnectionIdFrame::new(cid1, VarInt::from_u32(1), VarInt::from_u32(0));
+        assert!(remote_cids.recv_new_cid_frame(&frame).is_ok());
+        // retransmitted one is accepted
+        assert!(remote_cids.recv_new_cid_frame(&frame).is_ok());
+
+        // the same sequence number for a different connection ID
+        let mut conflicted = frame;
+        conflicted.id = ConnectionId::random_gen(8);
+        assert_eq!(
+            remote_cids
+                .recv_new_cid_frame(&conflicted)
+                .unwrap_err()
+                .kind(),
+            crate::error::ErrorKind::ProtocolViolation
+        );
+
+        let frame2 = NewConnectionIdFrame::new(
+            ConnectionId::random_gen(8),
+            VarInt::from_u32(2),
+            VarInt::from_u32(0),
+        );
+        assert_eq!(
+            remote_cids.recv_new_cid_frame(&frame2).unwrap_err().kind(),
+            crate::error::ErrorKind::ConnectionIdLimit
+        );
+
+        // the connection ID retired by the path is no longer active
+        let mut remote_cids = RawRemoteCids::new(initial_dcid, 2, retired_cids);
+        let cell0 = remote_cids.apply_dcid();
+        assert!(remote_cids.recv_new_cid_frame(&frame).is_ok());
+        cell0.retire();
+        assert!(remote_cids.recv_new_cid_frame(&frame2).is_ok());
+    }
 }
@@ -1,10 +1,12 @@
 mod client;
 mod server;
 use std::{
+    collections::BTreeMap,
     net::{SocketAddrV4, SocketAddrV6},
     time::Duration,
 };
 
+use bytes::Bytes;
 pub use client::*;
 /// Ref. `<https://www.iana.org/assignments/quic/quic.xhtml>`
 // QUIC的config配置
@@ -15,7 +17,7 @@ pub use server::*;
 use super::varint::VarInt;
 use crate::{cid::ConnectionId, generate_validate, token::ResetToken};
 
-#[derive(Builder, Getters, CopyGetters, Setters, MutGetters, Debug, Clone, Copy, PartialEq)]
+#[derive(Builder, Getters, CopyGetters, Setters, MutGetters, Debug, Clone, PartialEq)]
 #[builder(
     default,
     setter(strip_option, into,),
@@ -61,9 +63,50 @@ pub struct Parameters {
     retry_source_connection_id: Option<ConnectionId>,
     #[getset(get_copy = "pub", set = "pub")]
     max_datagram_frame_size: VarInt,
-    // TOOD: 对此传输参数的支持
+    /// The grease_quic_bit transport parameter, the QUIC Bit of the packets sent to the endpoint
+    /// may be greased if it's advertised, see [RFC 9287](https://www.rfc-editor.org/rfc/rfc9287.html).
     #[getset(get_copy = "pub", set = "pub")]
     grease_quic_bit: bool,
+    /// The enable_multipath transport parameter, the multipath extension is used only if both
+    /// endpoints enable it, see [Section 3](https://www.ietf.org/archive/id/draft-ietf-quic-multipath-05.html#section-3)
+    /// of the Multipath Extension for QUIC.
+    #[getset(get_copy = "pub", set = "pub")]
+    enable_multipath: bool,
+    /// The min_ack_delay transport parameter in microseconds, the minimum ACK delay the endpoint
+    /// can use, [`None`] if the ACK frequency extension is not supported, see [Section 3](https://www.ietf.org/archive/id/draft-ietf-quic-ack-frequency-10.html#section-3)
+    /// of the QUIC Acknowledgment Frequency.
+    #[getset(get_copy = "pub", set = "pub")]
+    min_ack_delay: Option<VarInt>,
+    /// The reset_stream_at transport parameter, the RESET_STREAM_AT frame can be sent only if
+    /// both endpoints enable it, see [Section 3](https://www.ietf.org/archive/id/draft-ietf-quic-reliable-stream-reset-06.html#section-3)
+    /// of the Reliable QUIC Stream Resets.
+    #[getset(get_copy = "pub", set = "pub")]
+    reset_stream_at: bool,
+    /// The probability to disable the latency spin bit of a connection, which is not a transport
+    /// parameter and never sent to the peer.
+    ///
+    /// Each endpoint decides whether the spin bit is used for a connection on its own, and it must
+    /// be disabled for at least one in every 16 connections, see [Section 17.4](https://www.rfc-editor.org/rfc/rfc9000.html#section-17.4)
+    /// of RFC 9000. The spin bit of the disabled connection is always 0.
+    #[getset(get_copy = "pub", set = "pub")]
+    spin_bit_disable_probability: f32,
+    /// The maximum amount of the out-of-order CRYPTO data buffered in each packet number space,
+    /// which is not a transport parameter and never sent to the peer.
+    ///
+    /// The connection is closed with a CRYPTO_BUFFER_EXCEEDED error if the peer sends more, it must
+    /// be at least 4096 bytes, see [Section 7.5](https://www.rfc-editor.org/rfc/rfc9000.html#section-7.5)
+    /// of RFC 9000.
+    #[getset(get_copy = "pub", set = "pub")]
+    crypto_buffer_size: usize,
+    /// The transport parameters unknown to this crate, such as the ones of the experimental
+    /// extensions, keyed by their identifiers.
+    ///
+    /// The ones set locally are sent to the peer as they are, and the unknown ones received from
+    /// the peer are kept here for the application, except the reserved ones of `31 * N + 27`.
+    #[getset(get = "pub")]
+    custom_parameters: BTreeMap<VarInt, Bytes>,
+    #[getset(get = "pub", set = "pub")]
+    version_information: Option<VersionInformation>,
 }
 
 impl Default for Parameters {
@@ -80,18 +123,38 @@ impl Default for Parameters {
             initial_max_streams_bidi: VarInt::from_u32(100),
             initial_max_streams_uni: VarInt::from_u32(10),
             ack_delay_exponent: VarInt::from_u32(3),
-            max_ack_delay: VarInt::from_u32(1000),
+            max_ack_delay: VarInt::from_u32(25),
             disable_active_migration: false,
             preferred_address: None,
             active_connection_id_limit: VarInt::from_u32(2),
             initial_source_connection_id: None,
             retry_source_connection_id: None,
             max_datagram_frame_size: VarInt::from_u32(65535),
-            grease_quic_bit: false,
+            grease_quic_bit: true,
+            version_information: None,
+            enable_multipath: false,
+            min_ack_delay: None,
+            reset_stream_at: false,
+            spin_bit_disable_probability: 0.125,
+            crypto_buffer_size: 64 * 1024,
+            custom_parameters: BTreeMap::new(),
         }
     }
 }
 
+impl Parameters {
+    /// Get the value of the custom transport parameter `id`, see [`Parameters::custom_parameters`].
+    pub fn custom_parameter(&self, id: VarInt) -> Option<&Bytes> {
+        self.custom_parameters.get(&id)
+    }
+
+    /// Set the custom transport parameter `id` to be sent to the peer, which must not be any
+    /// transport parameter known to this crate, or a reserved one.
+    pub fn set_custom_parameter(&mut self, id: VarInt, value: impl Into<Bytes>) {
+        self.custom_parameters.insert(id, value.into());
+    }
+}
+
 #[macro_export(local_inner_macros)]
 macro_rules! generate_validate {
     ($t:ty) => {
@@ -106,9 +169,29 @@ macro_rules! generate_validate {
                 if self.max_ack_delay > 1 << 14 {
                     return Err("max_ack_delay must be at most 2^14");
                 }
+                // The min_ack_delay is in microseconds, while the max_ack_delay is in milliseconds
+                if self
+                    .min_ack_delay
+                    .is_some_and(|min| min.into_inner() > self.max_ack_delay.into_inner() * 1000)
+                {
+                    return Err("min_ack_delay must not be greater than max_ack_delay");
+                }
+                if !(0.0..=1.0).contains(&self.spin_bit_disable_probability) {
+                    return Err("spin_bit_disable_probability must be between 0 and 1");
+                }
+                if self.crypto_buffer_size < 4096 {
+                    return Err("crypto_buffer_size must be at least 4096");
+                }
                 if self.active_connection_id_limit < 2 {
                     return Err("active_connection_id_limit must be at least 2");
                 }
+                if self
+                    .custom_parameters
+                    .keys()
+                    .any(|id| $crate::config::ext::is_known_parameter(id.into_inner()))
+                {
+                    return Err("custom transport parameters must not use the known identifiers");
+                }
                 Ok(())
             }
         }
@@ -117,7 +200,12 @@ macro_rules! generate_validate {
 
 generate_validate!(Parameters);
 
-#[derive(Getters, Setters, MutGetters, Debug, PartialEq, Clone, Copy)]
+/// The preferred_address transport parameter, the server's address to which the client migrates
+/// after the handshake is confirmed, see [Section 9.6](https://www.rfc-editor.org/rfc/rfc9000.html#section-9.6)
+/// of RFC 9000.
+///
+/// The address of the family the server does not support is all zeros.
+#[derive(Getters, CopyGetters, Setters, MutGetters, Debug, PartialEq, Clone, Copy)]
 pub struct PreferredAddress {
     #[getset(get_copy = "pub", set = "pub")]
     address_v4: SocketAddrV4,
@@ -130,24 +218,102 @@ pub struct PreferredAddress {
 }
 
 impl PreferredAddress {
+    /// Create a preferred address whose connection ID and stateless reset token are left empty,
+    /// the server connection fills them with its connection ID of sequence number 1.
+    pub fn new(address_v4: SocketAddrV4, address_v6: SocketAddrV6) -> Self {
+        Self {
+            address_v4,
+            address_v6,
+            connection_id: ConnectionId::default(),
+            stateless_reset_token: ResetToken::default(),
+        }
+    }
+
     pub fn encoding_size(&self) -> usize {
         6 + 18 + self.connection_id.encoding_size() + self.stateless_reset_token.encoding_size()
     }
 }
 
+/// The version_information transport parameter, see
+/// [Section 3](https://www.rfc-editor.org/rfc/rfc9368#section-3)
+/// of [RFC 9368](https://www.rfc-editor.org/rfc/rfc9368).
+#[derive(Getters, CopyGetters, Debug, PartialEq, Eq, Clone)]
+pub struct VersionInformation {
+    /// The version used in the connection, namely the version of the packet carrying the client's
+    /// transport parameters, or the negotiated version of the server.
+    #[getset(get_copy = "pub")]
+    chosen_version: u32,
+    /// The versions the endpoint supports, in order of preference.
+    #[getset(get = "pub")]
+    available_versions: Vec<u32>,
+}
+
+impl VersionInformation {
+    pub fn new(chosen_version: u32, available_versions: Vec<u32>) -> Self {
+        Self {
+            chosen_version,
+            available_versions,
+        }
+    }
+
+    pub fn encoding_size(&self) -> usize {
+        4 * (1 + self.available_versions.len())
+    }
+}
+
 pub mod ext {
     use std::time::Duration;
 
     use bytes::BufMut;
     use nom::{bytes::complete::take, combinator::map};
 
-    use super::{Parameters, PreferredAddress};
+    use super::{Parameters, PreferredAddress, VersionInformation};
     use crate::{
         cid::{be_connection_id, ConnectionId, WriteConnectionId, MAX_CID_SIZE},
         token::{be_reset_token, ResetToken, WriteResetToken},
         varint::{be_varint, VarInt, WriteVarInt},
     };
 
+    /// The provisional identifier of the enable_multipath transport parameter.
+    const ENABLE_MULTIPATH: u64 = 0x0f739bbc1b666d05;
+    /// The provisional identifier of the min_ack_delay transport parameter.
+    const MIN_ACK_DELAY: u64 = 0xff04de1b;
+    /// The provisional identifier of the reset_stream_at transport parameter.
+    const RESET_STREAM_AT: u64 = 0x17f7586d2cb571;
+    /// The identifier of the grease_quic_bit transport parameter.
+    const GREASE_QUIC_BIT: u64 = 0x2ab2;
+
+    /// Whether the transport parameter `id` is defined in this crate or reserved, which can not be
+    /// used as a custom one, see [`Parameters::custom_parameters`].
+    pub fn is_known_parameter(id: u64) -> bool {
+        matches!(
+            id,
+            0x00..=0x11
+                | 0x20
+                | ENABLE_MULTIPATH
+                | MIN_ACK_DELAY
+                | RESET_STREAM_AT
+                | GREASE_QUIC_BIT
+        ) || is_reserved_parameter(id)
+    }
+
+    fn is_reserved_parameter(id: u64) -> bool {
+        id % 31 == 27
+    }
+
+    /// A reserved transport parameter with random identifier of `31 * N + 27` and random value, to
+    /// exercise the peer ignoring the unknown transport parameters, see [Reserved Transport Parameters](https://www.rfc-editor.org/rfc/rfc9000.html#name-reserved-transport-paramete)
+    /// of [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html).
+    fn put_reserved_parameter<T: BufMut>(buf: &mut T) {
+        let id = rand::random::<u32>() as u64 * 31 + 27;
+        let value = (0..rand::random::<u8>() % 17)
+            .map(|_| rand::random::<u8>())
+            .collect::<Vec<_>>();
+        buf.put_varint(&VarInt::from_u64(id).unwrap());
+        buf.put_varint(&VarInt::from_u32(value.len() as u32));
+        buf.put_slice(&value);
+    }
+
     pub fn be_parameters(input: &[u8]) -> nom::IResult<&[u8], Parameters> {
         let be_connection_id = |input, len: VarInt| {
             let len = len.into_inner() as usize;
@@ -176,6 +342,28 @@ pub mod ext {
             Ok((remain, Some(token)))
         };
 
+        fn be_version_information(
+            input: &[u8],
+            len: VarInt,
+        ) -> nom::IResult<&[u8], Option<VersionInformation>> {
+            let len = len.into_inner() as usize;
+            let (remain, bytes) = take(len)(input)?;
+            let mut versions = bytes
+                .chunks_exact(4)
+                .map(|v| u32::from_be_bytes([v[0], v[1], v[2], v[3]]));
+            // The Chosen Version is required, and it must not be 0
+            match versions.next() {
+                Some(chosen_version) if len.is_multiple_of(4) && chosen_version != 0 => Ok((
+                    remain,
+                    Some(VersionInformation::new(chosen_version, versions.collect())),
+                )),
+                _ => Err(nom::Err::Error(nom::error::make_error(
+                    input,
+                    nom::error::ErrorKind::Verify,
+                ))),
+            }
+        }
+
         let mut remain = input;
         let mut tp = Parameters::default();
         while !remain.is_empty() {
@@ -203,15 +391,35 @@ pub mod ext {
                 0x0e => (remain, tp.active_connection_id_limit) = be_varint(remain)?,
                 0x0f => (remain, tp.initial_source_connection_id) = be_connection_id(remain, len)?,
                 0x10 => (remain, tp.retry_source_connection_id) = be_connection_id(remain, len)?,
+                0x11 => (remain, tp.version_information) = be_version_information(remain, len)?,
                 0x20 => (remain, tp.max_datagram_frame_size) = be_varint(remain)?,
-                // 0x2ab2 => tp.grease_quic_bit = true,
-                _ => {
+                ENABLE_MULTIPATH if len.into_inner() == 0 => tp.enable_multipath = true,
+                ENABLE_MULTIPATH => {
+                    let enable_multipath: VarInt;
+                    (remain, enable_multipath) = be_varint(remain)?;
+                    tp.enable_multipath = enable_multipath.into_inner() != 0;
+                }
+                MIN_ACK_DELAY => {
+                    let min_ack_delay: VarInt;
+                    (remain, min_ack_delay) = be_varint(remain)?;
+                    tp.min_ack_delay = Some(min_ack_delay);
+                }
+                RESET_STREAM_AT => tp.reset_stream_at = true,
+                GREASE_QUIC_BIT => tp.grease_quic_bit = true,
+                id if is_reserved_parameter(id) => {
                     // Ref. `<https://www.rfc-editor.org/rfc/rfc9000.html#name-new-transport-parameters>
                     // An endpoint MUST ignore transport parameters that it does not support.
 
                     // take it, and ignore it
                     (remain, ..) = take(len)(remain)?;
                 }
+                _ => {
+                    // The unknown ones are left to the application
+                    let value: &[u8];
+                    (remain, value) = take(len)(remain)?;
+                    tp.custom_parameters
+                        .insert(tag, bytes::Bytes::copy_from_slice(value));
+                }
             }
         }
 
@@ -263,6 +471,7 @@ pub mod ext {
                     }
                 };
 
+            put_reserved_parameter(self);
             put_connection_id(self, 0x00, &params.original_destination_connection_id);
             put_varint(
                 self,
@@ -288,11 +497,42 @@ pub mod ext {
             put_varint(self, 0x0e, params.active_connection_id_limit);
             put_connection_id(self, 0x0f, &params.initial_source_connection_id);
             put_connection_id(self, 0x10, &params.retry_source_connection_id);
+            if let Some(info) = &params.version_information {
+                self.put_u8(0x11);
+                self.put_varint(&unsafe {
+                    VarInt::from_u64_unchecked(info.encoding_size() as u64)
+                });
+                self.put_u32(info.chosen_version);
+                for version in &info.available_versions {
+                    self.put_u32(*version);
+                }
+            }
             put_varint(self, 0x20, params.max_datagram_frame_size);
-            // if params.grease_quic_bit {
-            //     self.put_varint(&VarInt::from_u32(0x2ab2));
-            //     self.put_u8(0);
-            // }
+            if params.grease_quic_bit {
+                self.put_varint(&VarInt::from_u64(GREASE_QUIC_BIT).unwrap());
+                self.put_u8(0);
+            }
+            if params.enable_multipath {
+                self.put_varint(&VarInt::from_u64(ENABLE_MULTIPATH).unwrap());
+                self.put_u8(1);
+                self.put_u8(1);
+            }
+            if let Some(min_ack_delay) = params.min_ack_delay {
+                self.put_varint(&VarInt::from_u64(MIN_ACK_DELAY).unwrap());
+                self.put_varint(&unsafe {
+                    VarInt::from_u64_unchecked(min_ack_delay.encoding_size() as u64)
+                });
+                self.put_varint(&min_ack_delay);
+            }
+            if params.reset_stream_at {
+                self.put_varint(&VarInt::from_u64(RESET_STREAM_AT).unwrap());
+                self.put_u8(0);
+            }
+            for (id, value) in &params.custom_parameters {
+                self.put_varint(id);
+                self.put_varint(&VarInt::try_from(value.len()).unwrap());
+                self.put_slice(value);
+            }
         }
 
         fn put_preferred_address(&mut self, addr: &super::PreferredAddress) {
@@ -379,7 +619,10 @@ mod test {
             .initial_source_connection_id(init_cid)
             .retry_source_connection_id(init_cid)
             .max_datagram_frame_size(VarInt::from_u32(65535))
-            .grease_quic_bit(false)
+            .grease_quic_bit(true)
+            .enable_multipath(true)
+            .min_ack_delay(VarInt::from_u32(1000))
+            .reset_stream_at(true)
             .build()
             .unwrap()
             .into();
@@ -390,6 +633,87 @@ mod test {
         assert_eq!(params, params2);
     }
 
+    #[test]
+    fn version_information() {
+        let mut params = Parameters::default();
+        params.set_version_information(Some(VersionInformation::new(
+            0x6b3343cf,
+            vec![0x6b3343cf, 0x00000001],
+        )));
+        let mut buf = bytes::BytesMut::new();
+        buf.put_parameters(&params);
+        let params2 = ext::be_parameters(&buf).unwrap().1;
+        assert_eq!(params, params2);
+
+        // The length must be a positive multiple of 4, and the Chosen Version must not be 0
+        assert!(ext::be_parameters(&[0x11, 0x03, 0x00, 0x00, 0x01]).is_err());
+        assert!(ext::be_parameters(&[0x11, 0x00]).is_err());
+        assert!(ext::be_parameters(&[0x11, 0x04, 0x00, 0x00, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn enable_multipath() {
+        // The older drafts encode the enable_multipath parameter with an empty value
+        let tag = [0xcf, 0x73, 0x9b, 0xbc, 0x1b, 0x66, 0x6d, 0x05];
+        let params = ext::be_parameters(&[&tag[..], &[0x00]].concat()).unwrap().1;
+        assert!(params.enable_multipath());
+        let params = ext::be_parameters(&[&tag[..], &[0x01, 0x00]].concat())
+            .unwrap()
+            .1;
+        assert!(!params.enable_multipath());
+
+        let mut buf = bytes::BytesMut::new();
+        let mut params = Parameters::default();
+        params.set_enable_multipath(true);
+        buf.put_parameters(&params);
+        assert!(buf.ends_with(&[&tag[..], &[0x01, 0x01]].concat()));
+    }
+
+    #[test]
+    fn min_ack_delay() {
+        let mut buf = bytes::BytesMut::new();
+        let mut params = Parameters::default();
+        params.set_min_ack_delay(Some(VarInt::from_u32(1000)));
+        buf.put_parameters(&params);
+        assert!(buf.ends_with(&[0xc0, 0x00, 0x00, 0x00, 0xff, 0x04, 0xde, 0x1b, 0x02, 0x43, 0xe8]));
+        let params2 = ext::be_parameters(&buf).unwrap().1;
+        assert_eq!(params2.min_ack_delay(), Some(VarInt::from_u32(1000)));
+
+        // The min_ack_delay must not exceed the max_ack_delay of 25ms
+        params.set_min_ack_delay(Some(VarInt::from_u32(25_001)));
+        params.set_max_ack_delay(VarInt::from_u32(25));
+        assert!(params.validate().is_err());
+    }
+
+    #[test]
+    fn reset_stream_at() {
+        let mut buf = bytes::BytesMut::new();
+        let mut params = Parameters::default();
+        params.set_reset_stream_at(true);
+        buf.put_parameters(&params);
+        assert!(buf.ends_with(&[0xc0, 0x17, 0xf7, 0x58, 0x6d, 0x2c, 0xb5, 0x71, 0x00]));
+        let params2 = ext::be_parameters(&buf).unwrap().1;
+        assert!(params2.reset_stream_at());
+    }
+
+    #[test]
+    fn grease() {
+        let mut buf = bytes::BytesMut::new();
+        let mut params = Parameters::default();
+        params.set_grease_quic_bit(false);
+        buf.put_parameters(&params);
+        // 保留的传输参数被忽略
+        params.set_grease_quic_bit(true);
+        let buf = [
+            &[0x40, 0x1b, 0x02, 0x12, 0x34][..],
+            &[0x6a, 0xb2, 0x00],
+            &buf,
+        ]
+        .concat();
+        let params2 = ext::be_parameters(&buf).unwrap().1;
+        assert_eq!(params, params2);
+    }
+
     #[test]
     fn invalid_params() {
         let build_result = ClientParameters::builder()
@@ -406,6 +730,44 @@ mod test {
             .active_connection_id_limit(VarInt::from_u32(1))
             .build();
         assert!(build_result.is_err());
+
+        let build_result = ClientParameters::builder()
+            .spin_bit_disable_probability(1.5)
+            .build();
+        assert!(build_result.is_err());
+
+        let build_result = ClientParameters::builder()
+            .crypto_buffer_size(1024usize)
+            .build();
+        assert!(build_result.is_err());
+
+        let build_result = ClientParameters::builder()
+            .custom_parameters(BTreeMap::from([(
+                VarInt::from_u32(0x20),
+                Bytes::from_static(b"\x01"),
+            )]))
+            .build();
+        assert!(build_result.is_err());
+    }
+
+    #[test]
+    fn custom_parameters() {
+        let mut buf = bytes::BytesMut::new();
+        let mut params = Parameters::default();
+        params.set_custom_parameter(VarInt::from_u32(0x2b603742), &b"\x01"[..]);
+        params.validate().unwrap();
+        buf.put_parameters(&params);
+        assert!(buf.ends_with(&[0xab, 0x60, 0x37, 0x42, 0x01, 0x01]));
+        let params2 = ext::be_parameters(&buf).unwrap().1;
+        assert_eq!(
+            params2.custom_parameter(VarInt::from_u32(0x2b603742)),
+            Some(&Bytes::from_static(b"\x01"))
+        );
+        assert_eq!(params, params2);
+
+        // The reserved ones are neither custom parameters
+        params.set_custom_parameter(VarInt::from_u32(27), Bytes::new());
+        assert!(params.validate().is_err());
     }
 
     #[test]
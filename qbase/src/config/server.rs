@@ -43,9 +43,27 @@ pub struct ServerParameters {
     retry_source_connection_id: Option<ConnectionId>,
     #[getset(get_copy = "pub", set = "pub")]
     max_datagram_frame_size: VarInt,
-    // TOOD: 对此传输参数的支持
+    /// Whether the QUIC Bit can be greased by the peer, see [`Parameters::grease_quic_bit`].
     #[getset(get_copy = "pub", set = "pub")]
     grease_quic_bit: bool,
+    /// Whether the multipath extension is supported, see [`Parameters::enable_multipath`].
+    #[getset(get_copy = "pub", set = "pub")]
+    enable_multipath: bool,
+    /// The minimum ACK delay in microseconds, see [`Parameters::min_ack_delay`].
+    #[getset(get_copy = "pub", set = "pub")]
+    min_ack_delay: Option<VarInt>,
+    /// Whether the reliable stream reset extension is supported, see [`Parameters::reset_stream_at`].
+    #[getset(get_copy = "pub", set = "pub")]
+    reset_stream_at: bool,
+    /// The probability to disable the spin bit of a connection, see [`Parameters::spin_bit_disable_probability`].
+    #[getset(get_copy = "pub", set = "pub")]
+    spin_bit_disable_probability: f32,
+    /// The maximum amount of the out-of-order CRYPTO data buffered, see [`Parameters::crypto_buffer_size`].
+    #[getset(get_copy = "pub", set = "pub")]
+    crypto_buffer_size: usize,
+    /// The transport parameters unknown to this crate, see [`Parameters::custom_parameters`].
+    #[getset(get = "pub")]
+    custom_parameters: BTreeMap<VarInt, Bytes>,
 }
 
 impl ServerParameters {
@@ -108,6 +126,16 @@ impl ServerParametersBuilder {
                 .max_datagram_frame_size
                 .unwrap_or(default.max_datagram_frame_size),
             grease_quic_bit: this.grease_quic_bit.unwrap_or(default.grease_quic_bit),
+            enable_multipath: this.enable_multipath.unwrap_or(default.enable_multipath),
+            min_ack_delay: this.min_ack_delay.unwrap_or(default.min_ack_delay),
+            reset_stream_at: this.reset_stream_at.unwrap_or(default.reset_stream_at),
+            spin_bit_disable_probability: this
+                .spin_bit_disable_probability
+                .unwrap_or(default.spin_bit_disable_probability),
+            crypto_buffer_size: this
+                .crypto_buffer_size
+                .unwrap_or(default.crypto_buffer_size),
+            custom_parameters: this.custom_parameters.unwrap_or(default.custom_parameters),
         };
         params.validate()?;
         Ok(params)
@@ -136,6 +164,13 @@ impl From<ServerParameters> for Parameters {
             retry_source_connection_id: value.retry_source_connection_id,
             max_datagram_frame_size: value.max_datagram_frame_size,
             grease_quic_bit: value.grease_quic_bit,
+            enable_multipath: value.enable_multipath,
+            min_ack_delay: value.min_ack_delay,
+            reset_stream_at: value.reset_stream_at,
+            spin_bit_disable_probability: value.spin_bit_disable_probability,
+            crypto_buffer_size: value.crypto_buffer_size,
+            custom_parameters: value.custom_parameters,
+            version_information: None,
         }
     }
 }
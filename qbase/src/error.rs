@@ -65,6 +65,10 @@ pub enum ErrorKind {
     /// An endpoint is unlikely to receive a CONNECTION_CLOSE frame carrying this code
     /// except when the path does not support a large enough MTU.
     NoViablePath,
+    /// An endpoint detected an error in version negotiation, see
+    /// [Section 4](https://www.rfc-editor.org/rfc/rfc9368#section-4)
+    /// of [RFC 9368](https://www.rfc-editor.org/rfc/rfc9368).
+    VersionNegotiation,
     /// The cryptographic handshake failed.
     /// A range of 256 values is reserved for carrying error codes specific
     /// to the cryptographic handshake that is used.
@@ -94,6 +98,7 @@ impl Display for ErrorKind {
             ErrorKind::KeyUpdate => "Invalid packet protection update",
             ErrorKind::AeadLimitReached => "Excessive use of packet protection keys",
             ErrorKind::NoViablePath => "No viable network path exists",
+            ErrorKind::VersionNegotiation => "Error negotiating version",
             ErrorKind::Crypto(x) => return write!(f, "TLS alert code: {x}"),
         };
         write!(f, "{description}",)
@@ -131,6 +136,7 @@ impl TryFrom<VarInt> for ErrorKind {
             0x0e => ErrorKind::KeyUpdate,
             0x0f => ErrorKind::AeadLimitReached,
             0x10 => ErrorKind::NoViablePath,
+            0x11 => ErrorKind::VersionNegotiation,
             0x0100..=0x01ff => ErrorKind::Crypto((value.into_inner() & 0xff) as u8),
             other => return Err(InvalidErrorKind(other)),
         })
@@ -157,6 +163,7 @@ impl From<ErrorKind> for VarInt {
             ErrorKind::KeyUpdate => VarInt::from(0x0eu8),
             ErrorKind::AeadLimitReached => VarInt::from(0x0fu8),
             ErrorKind::NoViablePath => VarInt::from(0x10u8),
+            ErrorKind::VersionNegotiation => VarInt::from(0x11u8),
             ErrorKind::Crypto(x) => VarInt::from(0x0100u16 | x as u16),
         }
     }
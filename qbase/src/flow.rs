@@ -6,6 +6,7 @@ use std::{
         Arc, Mutex, MutexGuard,
     },
     task::{Context, Poll, Waker},
+    time::{Duration, Instant},
 };
 
 use futures::{task::AtomicWaker, Future};
@@ -26,6 +27,8 @@ use crate::{
 struct RawSendControler {
     total_sent: u64,
     max_data: u64,
+    // The limit reported by the DATA_BLOCKED frame, which is sent only once for each limit.
+    blocked: Option<u64>,
     blocked_waker: Option<Waker>,
     wakers: Vec<Waker>,
 }
@@ -35,6 +38,7 @@ impl RawSendControler {
         Self {
             total_sent: 0,
             max_data: initial_max_data,
+            blocked: None,
             blocked_waker: None,
             wakers: Vec::with_capacity(4),
         }
@@ -45,7 +49,8 @@ impl RawSendControler {
         cx: &mut Context<'_>,
     ) -> Poll<Result<DataBlockedFrame, QuicError>> {
         debug_assert!(self.total_sent <= self.max_data);
-        if self.total_sent == self.max_data {
+        if self.total_sent == self.max_data && self.blocked != Some(self.max_data) {
+            self.blocked = Some(self.max_data);
             Poll::Ready(Ok(DataBlockedFrame {
                 limit: VarInt::from_u64(self.total_sent)
                     .expect("max_data of flow controller is very very hard to exceed 2^62 - 1"),
@@ -243,6 +248,100 @@ impl Credit<'_> {
     }
 }
 
+/// The upper bound of the connection-level receive window grown by the [`WindowTuner`].
+pub const MAX_CONN_RECV_WINDOW: u64 = 24 << 20;
+/// The upper bound of the stream-level receive window grown by the [`WindowTuner`].
+pub const MAX_STREAM_RECV_WINDOW: u64 = 16 << 20;
+
+/// The RTT of the connection shared with the receive windows, which are tuned by the
+/// [`WindowTuner`] according to it.
+///
+/// It is updated with the smoothed RTT of the path that received an ACK most recently,
+/// it's unknown until then.
+#[derive(Debug, Default, Clone)]
+pub struct ArcRtt(Arc<AtomicU64>);
+
+impl ArcRtt {
+    /// Updates the RTT with the latest estimation of a path.
+    pub fn update(&self, rtt: Duration) {
+        let nanos = rtt.as_nanos().min(u64::MAX as u128) as u64;
+        self.0.store(nanos, Ordering::Release);
+    }
+
+    /// Returns the RTT, [`None`] if it is unknown yet.
+    pub fn get(&self) -> Option<Duration> {
+        match self.0.load(Ordering::Acquire) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+}
+
+/// Grows a receive window according to the RTT and the consumption rate, like the receive
+/// buffer autotuning of TCP.
+///
+/// The window is advertised again once half of it is consumed. If that happens within 2 RTTs
+/// since the last advertisement, the window limits the sender rather than the application, then
+/// it is doubled, up to the maximum. So the bulk transfers are not stalled by the initial window,
+/// while the windows of the idle or slowly read streams stay small.
+#[derive(Debug, Default)]
+pub struct WindowTuner {
+    window: u64,
+    max_window: u64,
+    last_update: Option<Instant>,
+    // Whether the peer reported it's blocked by the window since the last update
+    is_blocked: bool,
+    rtt: ArcRtt,
+}
+
+impl WindowTuner {
+    /// Creates a tuner starting from the `initial_window`, which is the initial limit advertised
+    /// in the transport parameters, and never grows beyond the `max_window`.
+    pub fn new(initial_window: u64, max_window: u64, rtt: ArcRtt) -> Self {
+        Self {
+            window: initial_window,
+            max_window: max_window.max(initial_window),
+            last_update: None,
+            is_blocked: false,
+            rtt,
+        }
+    }
+
+    /// The current window size.
+    pub fn window(&self) -> u64 {
+        self.window
+    }
+
+    /// Overrides the window, which takes effect upon the next update. The window can still grow
+    /// up to the maximum, or stays as is if it's larger than that.
+    pub fn set_window(&mut self, window: u64) {
+        self.window = window;
+        self.max_window = self.max_window.max(window);
+    }
+
+    /// Called when the peer reports it's blocked by the current window, with a DATA_BLOCKED or
+    /// STREAM_DATA_BLOCKED frame. The window is grown upon the next update then, however long it
+    /// took to consume it, since the window is the bottleneck of the transfer.
+    pub fn on_blocked(&mut self) {
+        self.is_blocked = true;
+    }
+
+    /// Called when the window is about to be advertised again, returns the window size to
+    /// advertise, which is grown if it was consumed in time.
+    pub fn on_update(&mut self, now: Instant) -> u64 {
+        let consumed_in_time = match (self.last_update, self.rtt.get()) {
+            (Some(last_update), Some(rtt)) => now.saturating_duration_since(last_update) < rtt * 2,
+            _ => false,
+        };
+        if consumed_in_time || self.is_blocked {
+            self.window = (self.window * 2).min(self.max_window);
+        }
+        self.is_blocked = false;
+        self.last_update = Some(now);
+        self.window
+    }
+}
+
 /// Overflow error, i.e. the flow control limit is exceeded while receiving.
 /// See [`ErrorKind::FlowControl`](`crate::error::ErrorKind::FlowControl`).
 #[derive(Debug, Clone, Copy, Error)]
@@ -254,18 +353,29 @@ pub struct Overflow(usize);
 struct RecvController {
     total_rcvd: AtomicU64,
     max_data: AtomicU64,
-    step: u64,
+    // The limit is increased once the remaining window is less than this, half of the window.
+    step: AtomicU64,
+    tuner: Mutex<WindowTuner>,
+    // The number of the DATA_BLOCKED frames received
+    blocked: AtomicU64,
     is_closed: AtomicBool,
     waker: AtomicWaker,
 }
 
 impl RecvController {
-    /// Creates a new [`RecvController`] with the specified `initial_max_data`.
-    fn with_initial(initial_max_data: u64) -> Self {
+    /// Creates a new [`RecvController`] with the specified `initial_max_data`, the window grows
+    /// according to the `rtt`.
+    fn with_initial(initial_max_data: u64, rtt: ArcRtt) -> Self {
         Self {
             total_rcvd: AtomicU64::new(0),
             max_data: AtomicU64::new(initial_max_data),
-            step: initial_max_data / 2,
+            step: AtomicU64::new(initial_max_data / 2),
+            tuner: Mutex::new(WindowTuner::new(
+                initial_max_data,
+                MAX_CONN_RECV_WINDOW,
+                rtt,
+            )),
+            blocked: AtomicU64::new(0),
             is_closed: AtomicBool::new(false),
             waker: AtomicWaker::new(),
         }
@@ -283,7 +393,7 @@ impl RecvController {
         let total_rcvd = self.total_rcvd.load(Ordering::Acquire);
         let max_data = self.max_data.load(Ordering::Acquire);
         if total_rcvd <= max_data {
-            if total_rcvd + self.step >= max_data {
+            if total_rcvd + self.step.load(Ordering::Acquire) >= max_data {
                 self.waker.wake();
             }
             Ok(amount)
@@ -300,10 +410,13 @@ impl RecvController {
             let max_data = self.max_data.load(Ordering::Acquire);
             let total_rcvd = self.total_rcvd.load(Ordering::Acquire);
 
-            if total_rcvd + self.step >= max_data {
-                self.max_data.fetch_add(self.step, Ordering::Release);
+            if total_rcvd + self.step.load(Ordering::Acquire) >= max_data {
+                let window = self.tuner.lock().unwrap().on_update(Instant::now());
+                self.step.store(window / 2, Ordering::Release);
+                let max_data = max_data.max(total_rcvd + window);
+                self.max_data.store(max_data, Ordering::Release);
                 Poll::Ready(Some(MaxDataFrame {
-                    max_data: VarInt::from_u64(self.max_data.load(Ordering::Acquire))
+                    max_data: VarInt::from_u64(max_data)
                         .expect("max_data of flow controller is very very hard to exceed 2^62 - 1"),
                 }))
             } else {
@@ -313,6 +426,16 @@ impl RecvController {
         }
     }
 
+    /// Handles the DATA_BLOCKED frame, the peer is blocked at `limit`.
+    fn on_data_blocked(&self, limit: u64) {
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+        // The frame of an old limit which has been raised is ignored
+        if limit >= self.max_data.load(Ordering::Acquire) {
+            self.tuner.lock().unwrap().on_blocked();
+            self.waker.wake();
+        }
+    }
+
     /// Terminate the receiver's flow control.
     fn terminate(&self) {
         if !self.is_closed.swap(true, Ordering::Release) {
@@ -343,8 +466,13 @@ pub struct ArcRecvController(Arc<RecvController>);
 
 impl ArcRecvController {
     /// Creates a new [`ArcRecvController`] with local `initial_max_data` transport parameter.
-    pub fn with_initial(initial_max_data: u64) -> Self {
-        Self(Arc::new(RecvController::with_initial(initial_max_data)))
+    ///
+    /// The receive window is grown by a [`WindowTuner`] according to the `rtt`.
+    pub fn with_initial(initial_max_data: u64, rtt: ArcRtt) -> Self {
+        Self(Arc::new(RecvController::with_initial(
+            initial_max_data,
+            rtt,
+        )))
     }
 
     /// Updates the total received data size and checks if the flow control limit is exceeded
@@ -367,22 +495,40 @@ impl ArcRecvController {
     pub fn terminate(&self) {
         self.0.terminate();
     }
+
+    /// The number of the [`DataBlockedFrame`]s received, namely how many times the peer was
+    /// blocked by the connection-level receive window.
+    pub fn blocked_count(&self) -> u64 {
+        self.0.blocked.load(Ordering::Relaxed)
+    }
 }
 
 /// [`ArcRecvController`] need to receive [`DataBlockedFrame`] from peer.
 ///
-/// However, the receiver may also not be able to immediately expand the receive window
-/// and must wait for the application layer to read the data to free up more space
-/// in the receive buffer.
+/// The receive window is the bottleneck if the peer is blocked at the current limit, then the
+/// window is doubled upon the next update, see [`WindowTuner::on_blocked`].
 impl ReceiveFrame<DataBlockedFrame> for ArcRecvController {
     type Output = ();
 
-    fn recv_frame(&self, _frame: &DataBlockedFrame) -> Result<Self::Output, QuicError> {
-        // Do nothing, just print a log
+    fn recv_frame(&self, frame: &DataBlockedFrame) -> Result<Self::Output, QuicError> {
+        self.0.on_data_blocked(frame.limit.into_inner());
         Ok(())
     }
 }
 
+/// The BLOCKED frames received from the peer, which means the local receive windows or the stream
+/// limits are the bottleneck of the peer, see [Section 4.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-4.1)
+/// of RFC 9000.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlockedStats {
+    /// The number of the DATA_BLOCKED frames, the peer is blocked by the connection-level window.
+    pub data_blocked: u64,
+    /// The number of the STREAM_DATA_BLOCKED frames, the peer is blocked by a stream's window.
+    pub stream_data_blocked: u64,
+    /// The number of the STREAMS_BLOCKED frames, the peer can't open more streams.
+    pub streams_blocked: u64,
+}
+
 /// `IncrLimit` future resolves when the receive window limit is increased,
 /// which is returned by [`ArcRecvController::incr_limit`].
 ///
@@ -404,6 +550,7 @@ impl Future for IncrLimit {
 pub struct FlowController {
     pub sender: ArcSendControler,
     pub recver: ArcRecvController,
+    rtt: ArcRtt,
 }
 
 impl FlowController {
@@ -412,10 +559,18 @@ impl FlowController {
     /// Unfortunately, at the beginning, the peer's `initial_max_data` is unknown.
     /// Therefore, peer's `initial_max_data` can be set to 0 initially,
     /// and then updated later after obtaining the peer's `initial_max_data` setting.
-    pub fn with_parameter(peer_initial_max_data: u64, local_initial_max_data: u64) -> Self {
+    ///
+    /// The receive window grows from the local `initial_max_data` according to the `rtt`, see
+    /// [`WindowTuner`].
+    pub fn with_parameter(
+        peer_initial_max_data: u64,
+        local_initial_max_data: u64,
+        rtt: ArcRtt,
+    ) -> Self {
         Self {
             sender: ArcSendControler::with_initial(peer_initial_max_data),
-            recver: ArcRecvController::with_initial(local_initial_max_data),
+            recver: ArcRecvController::with_initial(local_initial_max_data, rtt.clone()),
+            rtt,
         }
     }
 
@@ -437,6 +592,12 @@ impl FlowController {
         self.recver.clone()
     }
 
+    /// Returns the RTT which the receive windows are tuned by, it should be updated once the RTT
+    /// of a path is estimated.
+    pub fn rtt(&self) -> ArcRtt {
+        self.rtt.clone()
+    }
+
     /// Handles the error event of the QUIC connection.
     ///
     /// It will makes
@@ -447,3 +608,88 @@ impl FlowController {
         self.recver.terminate();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_tuning() {
+        let rtt = ArcRtt::default();
+        let mut tuner = WindowTuner::new(1000, 3000, rtt.clone());
+        let now = Instant::now();
+        // The window is not grown before the RTT is known
+        assert_eq!(tuner.on_update(now), 1000);
+        assert_eq!(tuner.on_update(now), 1000);
+
+        rtt.update(Duration::from_millis(100));
+        // Consumed within 2 RTTs, the window limits the sender
+        assert_eq!(tuner.on_update(now + Duration::from_millis(150)), 2000);
+        assert_eq!(tuner.on_update(now + Duration::from_millis(300)), 3000);
+        // The window is capped
+        assert_eq!(tuner.on_update(now + Duration::from_millis(350)), 3000);
+        // Consumed slowly, the window stays
+        assert_eq!(tuner.on_update(now + Duration::from_secs(2)), 3000);
+
+        // The peer is blocked by the window, it grows however long it took
+        let mut tuner = WindowTuner::new(1000, 3000, rtt);
+        tuner.on_update(now);
+        tuner.on_blocked();
+        assert_eq!(tuner.on_update(now + Duration::from_secs(2)), 2000);
+        assert_eq!(tuner.on_update(now + Duration::from_secs(4)), 2000);
+    }
+
+    #[test]
+    fn data_blocked() {
+        let sender = ArcSendControler::with_initial(100);
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut poll_blocked = || match Pin::new(&mut sender.would_block()).poll(&mut cx) {
+            Poll::Ready(Ok(frame)) => Some(frame.limit.into_inner()),
+            _ => None,
+        };
+        assert_eq!(poll_blocked(), None);
+        sender.credit().unwrap().post_sent(100);
+        // Reported only once for each limit
+        assert_eq!(poll_blocked(), Some(100));
+        assert_eq!(poll_blocked(), None);
+        sender.increase_limit(200);
+        sender.credit().unwrap().post_sent(100);
+        assert_eq!(poll_blocked(), Some(200));
+
+        let recver = ArcRecvController::with_initial(1000, ArcRtt::default());
+        recver.on_new_rcvd(1000).unwrap();
+        // The frame of an old limit doesn't grow the window
+        let frame = DataBlockedFrame {
+            limit: VarInt::from_u32(500),
+        };
+        recver.recv_frame(&frame).unwrap();
+        assert!(!recver.0.tuner.lock().unwrap().is_blocked);
+        let frame = DataBlockedFrame {
+            limit: VarInt::from_u32(1000),
+        };
+        recver.recv_frame(&frame).unwrap();
+        assert!(recver.0.tuner.lock().unwrap().is_blocked);
+        assert_eq!(recver.blocked_count(), 2);
+    }
+
+    #[test]
+    fn connection_window_tuning() {
+        let rtt = ArcRtt::default();
+        rtt.update(Duration::from_millis(100));
+        let recver = ArcRecvController::with_initial(1000, rtt);
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut poll_max_data = || match recver.0.poll_incr_limit(&mut cx) {
+            Poll::Ready(Some(frame)) => Some(frame.max_data.into_inner()),
+            _ => None,
+        };
+
+        recver.on_new_rcvd(400).unwrap();
+        assert_eq!(poll_max_data(), None);
+        recver.on_new_rcvd(200).unwrap();
+        assert_eq!(poll_max_data(), Some(1600));
+        // The window is consumed quickly, it's doubled
+        recver.on_new_rcvd(900).unwrap();
+        assert_eq!(poll_max_data(), Some(1500 + 2000));
+        assert!(recver.on_new_rcvd(2001).is_err());
+    }
+}
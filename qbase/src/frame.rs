@@ -6,21 +6,26 @@ use super::varint::VarInt;
 use crate::packet::r#type::Type;
 
 mod ack;
+mod ack_frequency;
+mod ack_mp;
 mod connection_close;
 mod crypto;
 mod data_blocked;
 mod datagram;
 mod handshake_done;
+mod immediate_ack;
 mod max_data;
 mod max_stream_data;
 mod max_streams;
 mod new_connection_id;
 mod new_token;
 mod padding;
+mod path_abandon;
 mod path_challenge;
 mod path_response;
 mod ping;
 mod reset_stream;
+mod reset_stream_at;
 mod retire_connection_id;
 mod stop_sending;
 mod stream;
@@ -32,7 +37,9 @@ pub mod error;
 /// IO module for frame encoding and decoding
 pub mod io;
 
-pub use ack::{AckFrame, EcnCounts};
+pub use ack::{AckFrame, EcnCodepoint, EcnCounts};
+pub use ack_frequency::AckFrequencyFrame;
+pub use ack_mp::AckMpFrame;
 pub use connection_close::ConnectionCloseFrame;
 pub use crypto::CryptoFrame;
 pub use data_blocked::DataBlockedFrame;
@@ -40,16 +47,19 @@ pub use datagram::DatagramFrame;
 #[doc(hidden)]
 pub use error::Error;
 pub use handshake_done::HandshakeDoneFrame;
+pub use immediate_ack::ImmediateAckFrame;
 pub use max_data::MaxDataFrame;
 pub use max_stream_data::MaxStreamDataFrame;
 pub use max_streams::MaxStreamsFrame;
 pub use new_connection_id::NewConnectionIdFrame;
 pub use new_token::NewTokenFrame;
 pub use padding::PaddingFrame;
+pub use path_abandon::PathAbandonFrame;
 pub use path_challenge::PathChallengeFrame;
 pub use path_response::PathResponseFrame;
 pub use ping::PingFrame;
 pub use reset_stream::ResetStreamFrame;
+pub use reset_stream_at::ResetStreamAtFrame;
 pub use retire_connection_id::RetireConnectionIdFrame;
 pub use stop_sending::StopSendingFrame;
 pub use stream::{ShouldCarryLength, StreamFrame, STREAM_FRAME_MAX_ENCODING_SIZE};
@@ -129,19 +139,41 @@ pub enum FrameType {
     HandshakeDone,
     /// DATAGRAM frame, see [`DatagramFrame`].
     Datagram(u8),
+    /// ACK_MP frame of the multipath extension, see [`AckMpFrame`].
+    AckMp(u8),
+    /// PATH_ABANDON frame of the multipath extension, see [`PathAbandonFrame`].
+    PathAbandon,
+    /// ACK_FREQUENCY frame of the ACK frequency extension, see [`AckFrequencyFrame`].
+    AckFrequency,
+    /// IMMEDIATE_ACK frame of the ACK frequency extension, see [`ImmediateAckFrame`].
+    ImmediateAck,
+    /// RESET_STREAM_AT frame of the reliable stream reset extension, see [`ResetStreamAtFrame`].
+    ResetStreamAt,
 }
 
 impl FrameType {
     /// Determine if a frame type belongs to the given packet_type
     pub fn belongs_to(&self, packet_type: Type) -> bool {
         use crate::packet::r#type::{
-            long::{Type::V1, Ver1},
+            long::{
+                Type::{V1, V2},
+                Ver1, Ver2,
+            },
             short::OneRtt,
         };
         // IH01
-        let i = matches!(packet_type, Type::Long(V1(Ver1::INITIAL)));
-        let h = matches!(packet_type, Type::Long(V1(Ver1::HANDSHAKE)));
-        let o = matches!(packet_type, Type::Long(V1(Ver1::ZERO_RTT)));
+        let i = matches!(
+            packet_type,
+            Type::Long(V1(Ver1::INITIAL) | V2(Ver2::INITIAL))
+        );
+        let h = matches!(
+            packet_type,
+            Type::Long(V1(Ver1::HANDSHAKE) | V2(Ver2::HANDSHAKE))
+        );
+        let o = matches!(
+            packet_type,
+            Type::Long(V1(Ver1::ZERO_RTT) | V2(Ver2::ZERO_RTT))
+        );
         let l = matches!(packet_type, Type::Short(OneRtt(_)));
 
         match self {
@@ -179,6 +211,16 @@ impl FrameType {
             }
             FrameType::HandshakeDone => l,
             FrameType::Datagram(_) => o | l,
+            // The multipath extension is negotiated during the handshake, its frames can only be
+            // sent in 1-RTT packets
+            FrameType::AckMp(_) => l,
+            FrameType::PathAbandon => l,
+            // The ACK frequency extension is negotiated during the handshake too
+            FrameType::AckFrequency => l,
+            FrameType::ImmediateAck => l,
+            // The reliable stream reset extension is remembered for 0-RTT like the other
+            // transport parameters, so the frame can be sent in 0-RTT packets
+            FrameType::ResetStreamAt => o | l,
         }
     }
 
@@ -186,81 +228,95 @@ impl FrameType {
     pub fn is_ack_eliciting(&self) -> bool {
         !matches!(
             self,
-            Self::Padding | Self::Ack(..) | Self::ConnectionClose(..)
+            Self::Padding | Self::Ack(..) | Self::AckMp(..) | Self::ConnectionClose(..)
         )
     }
 }
 
-impl TryFrom<u8> for FrameType {
+impl TryFrom<VarInt> for FrameType {
     type Error = Error;
 
-    fn try_from(frame_type: u8) -> Result<Self, Self::Error> {
-        Ok(match frame_type {
+    fn try_from(frame_type: VarInt) -> Result<Self, Self::Error> {
+        Ok(match frame_type.into_inner() {
             0x00 => FrameType::Padding,
             0x01 => FrameType::Ping,
             // The last bit is the ECN flag.
-            ty @ (0x02 | 0x03) => FrameType::Ack(ty & 0b1),
+            ty @ (0x02 | 0x03) => FrameType::Ack(ty as u8 & 0b1),
             0x04 => FrameType::ResetStream,
             0x05 => FrameType::StopSending,
             0x06 => FrameType::Crypto,
             0x07 => FrameType::NewToken,
             // The last three bits are the offset, length, and fin flag bits respectively.
-            ty @ 0x08..=0x0f => FrameType::Stream(ty & 0b111),
+            ty @ 0x08..=0x0f => FrameType::Stream(ty as u8 & 0b111),
             0x10 => FrameType::MaxData,
             0x11 => FrameType::MaxStreamData,
             // The last bit is the direction flag bit, 0 indicates bidirectional, 1 indicates unidirectional.
-            ty @ (0x12 | 0x13) => FrameType::MaxStreams(ty & 0b1),
+            ty @ (0x12 | 0x13) => FrameType::MaxStreams(ty as u8 & 0b1),
             0x14 => FrameType::DataBlocked,
             0x15 => FrameType::StreamDataBlocked,
             // The last bit is the direction flag bit, 0 indicates bidirectional, 1 indicates unidirectional.
-            ty @ (0x16 | 0x17) => FrameType::StreamsBlocked(ty & 0b1),
+            ty @ (0x16 | 0x17) => FrameType::StreamsBlocked(ty as u8 & 0b1),
             0x18 => FrameType::NewConnectionId,
             0x19 => FrameType::RetireConnectionId,
             0x1a => FrameType::PathChallenge,
             0x1b => FrameType::PathResponse,
             // The last bit is the layer flag bit, 0 indicates application layer, 1 indicates transport layer.
-            ty @ (0x1c | 0x1d) => FrameType::ConnectionClose(ty & 0x1),
+            ty @ (0x1c | 0x1d) => FrameType::ConnectionClose(ty as u8 & 0x1),
             0x1e => FrameType::HandshakeDone,
+            0x1f => FrameType::ImmediateAck,
+            0x24 => FrameType::ResetStreamAt,
             // The last bit is the length flag bit, 0 the length field is absent and the Datagram Data
             // field extends to the end of the packet, 1 the length field is present.
-            ty @ (0x30 | 0x31) => FrameType::Datagram(ty & 1),
-            _ => return Err(Self::Error::InvalidType(VarInt::from(frame_type))),
+            ty @ (0x30 | 0x31) => FrameType::Datagram(ty as u8 & 1),
+            0xaf => FrameType::AckFrequency,
+            // The last bit is the ECN flag.
+            ty @ (0x15228c00 | 0x15228c01) => FrameType::AckMp(ty as u8 & 0b1),
+            0x15228c05 => FrameType::PathAbandon,
+            _ => return Err(Self::Error::InvalidType(frame_type)),
         })
     }
 }
 
-impl From<FrameType> for u8 {
+impl From<FrameType> for VarInt {
     fn from(frame_type: FrameType) -> Self {
-        match frame_type {
+        let frame_type: u32 = match frame_type {
             FrameType::Padding => 0x00,
             FrameType::Ping => 0x01,
-            FrameType::Ack(ecn) => 0x02 | ecn,
+            FrameType::Ack(ecn) => 0x02 | ecn as u32,
             FrameType::ResetStream => 0x04,
             FrameType::StopSending => 0x05,
             FrameType::Crypto => 0x06,
             FrameType::NewToken => 0x07,
-            FrameType::Stream(flag) => 0x08 | flag,
+            FrameType::Stream(flag) => 0x08 | flag as u32,
             FrameType::MaxData => 0x10,
             FrameType::MaxStreamData => 0x11,
-            FrameType::MaxStreams(dir) => 0x12 | dir,
+            FrameType::MaxStreams(dir) => 0x12 | dir as u32,
             FrameType::DataBlocked => 0x14,
             FrameType::StreamDataBlocked => 0x15,
-            FrameType::StreamsBlocked(dir) => 0x16 | dir,
+            FrameType::StreamsBlocked(dir) => 0x16 | dir as u32,
             FrameType::NewConnectionId => 0x18,
             FrameType::RetireConnectionId => 0x19,
             FrameType::PathChallenge => 0x1a,
             FrameType::PathResponse => 0x1b,
-            FrameType::ConnectionClose(layer) => 0x1c | layer,
+            FrameType::ConnectionClose(layer) => 0x1c | layer as u32,
             FrameType::HandshakeDone => 0x1e,
-            FrameType::Datagram(with_len) => 0x30 | with_len,
-        }
+            FrameType::Datagram(with_len) => 0x30 | with_len as u32,
+            FrameType::AckMp(ecn) => 0x15228c00 | ecn as u32,
+            FrameType::PathAbandon => 0x15228c05,
+            FrameType::AckFrequency => 0xaf,
+            FrameType::ImmediateAck => 0x1f,
+            FrameType::ResetStreamAt => 0x24,
+        };
+        VarInt::from_u32(frame_type)
     }
 }
 
 /// Parse the frame type from the input buffer,
 /// [nom](https://docs.rs/nom/latest/nom/) parser style.
 pub fn be_frame_type(input: &[u8]) -> nom::IResult<&[u8], FrameType, Error> {
-    let (remain, frame_type) = nom::number::complete::be_u8(input)?;
+    // The frame types of the extensions may be encoded in more than one byte
+    let (remain, frame_type) = crate::varint::be_varint(input)
+        .map_err(|_| nom::Err::Error(Error::IncompleteType(format!("{input:02x?}"))))?;
     let frame_type = FrameType::try_from(frame_type).map_err(nom::Err::Error)?;
     Ok((remain, frame_type))
 }
@@ -271,6 +327,8 @@ pub fn be_frame_type(input: &[u8]) -> nom::IResult<&[u8], FrameType, Error> {
 pub enum StreamCtlFrame {
     /// RESET_STREAM frame, see [`ResetStreamFrame`].
     ResetStream(ResetStreamFrame),
+    /// RESET_STREAM_AT frame, see [`ResetStreamAtFrame`].
+    ResetStreamAt(ResetStreamAtFrame),
     /// STOP_SENDING frame, see [`StopSendingFrame`].
     StopSending(StopSendingFrame),
     /// MAX_STREAM_DATA frame, see [`MaxStreamDataFrame`].
@@ -301,6 +359,10 @@ pub enum ReliableFrame {
     HandshakeDone(HandshakeDoneFrame),
     /// STREAM control frame, see [`StreamCtlFrame`].
     Stream(StreamCtlFrame),
+    /// PATH_ABANDON frame, see [`PathAbandonFrame`].
+    PathAbandon(PathAbandonFrame),
+    /// ACK_FREQUENCY frame, see [`AckFrequencyFrame`].
+    AckFrequency(AckFrequencyFrame),
 }
 
 /// Sum type of all the frames.
@@ -340,6 +402,31 @@ pub enum Frame {
     Crypto(CryptoFrame, Bytes),
     /// DATAGRAM frame and its data, see [`DatagramFrame`].
     Datagram(DatagramFrame, Bytes),
+    /// ACK_MP frame, see [`AckMpFrame`].
+    AckMp(AckMpFrame),
+    /// PATH_ABANDON frame, see [`PathAbandonFrame`].
+    PathAbandon(PathAbandonFrame),
+    /// ACK_FREQUENCY frame, see [`AckFrequencyFrame`].
+    AckFrequency(AckFrequencyFrame),
+    /// IMMEDIATE_ACK frame, see [`ImmediateAckFrame`].
+    ImmediateAck(ImmediateAckFrame),
+}
+
+impl Frame {
+    /// Return whether the frame is a probing frame, see [Section 9.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-9.1)
+    /// of RFC 9000.
+    ///
+    /// A packet containing only probing frames is a probing packet, which does not make the peer
+    /// migrate to the path it's received from.
+    pub fn is_probing(&self) -> bool {
+        matches!(
+            self,
+            Frame::Padding(_)
+                | Frame::Challenge(_)
+                | Frame::Response(_)
+                | Frame::NewConnectionId(_)
+        )
+    }
 }
 
 /// Some modules that need send specific frames can implement `SendFrame` trait directly.
@@ -405,6 +492,7 @@ impl<T: BufMut> WriteFrame<StreamCtlFrame> for T {
     fn put_frame(&mut self, frame: &StreamCtlFrame) {
         match frame {
             StreamCtlFrame::ResetStream(frame) => self.put_frame(frame),
+            StreamCtlFrame::ResetStreamAt(frame) => self.put_frame(frame),
             StreamCtlFrame::StopSending(frame) => self.put_frame(frame),
             StreamCtlFrame::MaxStreamData(frame) => self.put_frame(frame),
             StreamCtlFrame::MaxStreams(frame) => self.put_frame(frame),
@@ -424,6 +512,8 @@ impl<T: BufMut> WriteFrame<ReliableFrame> for T {
             ReliableFrame::RetireConnectionId(frame) => self.put_frame(frame),
             ReliableFrame::HandshakeDone(frame) => self.put_frame(frame),
             ReliableFrame::Stream(frame) => self.put_frame(frame),
+            ReliableFrame::PathAbandon(frame) => self.put_frame(frame),
+            ReliableFrame::AckFrequency(frame) => self.put_frame(frame),
         }
     }
 }
@@ -101,7 +101,7 @@ impl AckFrame {
 /// The counts of Explicit Congestion Notification (ECN) types.
 ///
 /// See [ecn-counts](https://www.rfc-editor.org/rfc/rfc9000.html#name-ecn-counts) of QUIC RFC 9000.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
 pub struct EcnCounts {
     pub ect0: VarInt,
     pub ect1: VarInt,
@@ -110,9 +110,48 @@ pub struct EcnCounts {
 
 impl EcnCounts {
     /// Calculates the encoding size of the [`EcnCounts`] struct.
-    fn encoding_size(&self) -> usize {
+    pub fn encoding_size(&self) -> usize {
         self.ect0.encoding_size() + self.ect1.encoding_size() + self.ce.encoding_size()
     }
+
+    /// Count a received packet with the ECN codepoint `ecn` in the IP header of its datagram.
+    pub fn on_rcvd(&mut self, ecn: EcnCodepoint) {
+        let count = match ecn {
+            EcnCodepoint::Ect0 => &mut self.ect0,
+            EcnCodepoint::Ect1 => &mut self.ect1,
+            EcnCodepoint::Ce => &mut self.ce,
+        };
+        *count = VarInt::from_u64(count.into_inner() + 1).expect("ECN count never exceed limit");
+    }
+}
+
+/// The ECN codepoints in the IP header, see [RFC 3168](https://www.rfc-editor.org/rfc/rfc3168#section-5).
+///
+/// The Not-ECT codepoint is represented by the absence of the codepoint.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum EcnCodepoint {
+    Ect1 = 0b01,
+    Ect0 = 0b10,
+    Ce = 0b11,
+}
+
+impl EcnCodepoint {
+    /// Get the codepoint from the lowest 2 bits of the IPv4 TOS field or the IPv6 Traffic Class
+    /// field, [`None`] if it's Not-ECT.
+    pub fn from_bits(tos: u8) -> Option<Self> {
+        match tos & 0b11 {
+            0b01 => Some(Self::Ect1),
+            0b10 => Some(Self::Ect0),
+            0b11 => Some(Self::Ce),
+            _ => None,
+        }
+    }
+
+    /// The bits to be set in the IPv4 TOS field or the IPv6 Traffic Class field.
+    pub fn bits(self) -> u8 {
+        self as u8
+    }
 }
 
 /// Parser for parsing an ACK frame with the given ECN flag,
@@ -160,6 +199,26 @@ pub(super) fn be_ecn_counts(input: &[u8]) -> nom::IResult<&[u8], EcnCounts> {
     )(input)
 }
 
+/// Write the fields following the frame type of an ACK frame, which are shared with the ACK_MP
+/// frame of the multipath extension.
+pub(super) fn put_ack_fields<T: bytes::BufMut>(buf: &mut T, frame: &AckFrame) {
+    buf.put_varint(&frame.largest);
+    buf.put_varint(&frame.delay);
+
+    let ack_range_count = VarInt::try_from(frame.ranges.len()).unwrap();
+    buf.put_varint(&ack_range_count);
+    buf.put_varint(&frame.first_range);
+    for (gap, ack) in &frame.ranges {
+        buf.put_varint(gap);
+        buf.put_varint(ack);
+    }
+    if let Some(ecn) = &frame.ecn {
+        buf.put_varint(&ecn.ect0);
+        buf.put_varint(&ecn.ect1);
+        buf.put_varint(&ecn.ce);
+    }
+}
+
 impl<T: bytes::BufMut> super::io::WriteFrame<AckFrame> for T {
     fn put_frame(&mut self, frame: &AckFrame) {
         let mut frame_type = ACK_FRAME_TYPE;
@@ -167,21 +226,7 @@ impl<T: bytes::BufMut> super::io::WriteFrame<AckFrame> for T {
             frame_type |= ECN_OPT;
         }
         self.put_u8(frame_type);
-        self.put_varint(&frame.largest);
-        self.put_varint(&frame.delay);
-
-        let ack_range_count = VarInt::try_from(frame.ranges.len()).unwrap();
-        self.put_varint(&ack_range_count);
-        self.put_varint(&frame.first_range);
-        for (gap, ack) in &frame.ranges {
-            self.put_varint(gap);
-            self.put_varint(ack);
-        }
-        if let Some(ecn) = &frame.ecn {
-            self.put_varint(&ecn.ect0);
-            self.put_varint(&ecn.ect1);
-            self.put_varint(&ecn.ce);
-        }
+        put_ack_fields(self, frame);
     }
 }
 
@@ -189,7 +234,9 @@ impl<T: bytes::BufMut> super::io::WriteFrame<AckFrame> for T {
 mod tests {
     use nom::combinator::flat_map;
 
-    use super::{ack_frame_with_flag, be_ecn_counts, AckFrame, EcnCounts, ACK_FRAME_TYPE};
+    use super::{
+        ack_frame_with_flag, be_ecn_counts, AckFrame, EcnCodepoint, EcnCounts, ACK_FRAME_TYPE,
+    };
     use crate::{
         frame::io::WriteFrame,
         varint::{be_varint, VarInt},
@@ -284,4 +331,30 @@ mod tests {
         assert_eq!(iter.next(), Some(911..=951));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn test_ecn_codepoint() {
+        assert_eq!(EcnCodepoint::from_bits(0), None);
+        assert_eq!(
+            EcnCodepoint::from_bits(0xb8 | 0b10),
+            Some(EcnCodepoint::Ect0)
+        );
+        for ecn in [EcnCodepoint::Ect0, EcnCodepoint::Ect1, EcnCodepoint::Ce] {
+            assert_eq!(EcnCodepoint::from_bits(ecn.bits()), Some(ecn));
+        }
+
+        let mut counts = EcnCounts::default();
+        counts.on_rcvd(EcnCodepoint::Ect0);
+        counts.on_rcvd(EcnCodepoint::Ect0);
+        counts.on_rcvd(EcnCodepoint::Ce);
+        assert_eq!(
+            counts,
+            EcnCounts {
+                ect0: VarInt::from_u32(2),
+                ect1: VarInt::from_u32(0),
+                ce: VarInt::from_u32(1),
+            }
+        );
+        assert_eq!(counts.encoding_size(), 3);
+    }
 }
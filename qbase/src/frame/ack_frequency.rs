@@ -0,0 +1,108 @@
+use crate::varint::{be_varint, VarInt, WriteVarInt};
+
+/// ACK_FREQUENCY frame of the ACK frequency extension.
+///
+/// ```text
+/// ACK_FREQUENCY Frame {
+///   Type (i) = 0xaf,
+///   Sequence Number (i),
+///   Ack-Eliciting Threshold (i),
+///   Request Max Ack Delay (i),
+///   Reordering Threshold (i),
+/// }
+/// ```
+///
+/// The sender asks the receiver to send an acknowledgment after receiving more than
+/// `ack_eliciting_threshold` ack-eliciting packets, or after `request_max_ack_delay`
+/// microseconds, which must not be less than the min_ack_delay advertised by the receiver.
+/// The frames with a sequence number not larger than the largest one received are ignored.
+///
+/// See [ACK_FREQUENCY Frame](https://www.ietf.org/archive/id/draft-ietf-quic-ack-frequency-10.html#name-ack_frequency-frame)
+/// of [QUIC Acknowledgment Frequency](https://www.ietf.org/archive/id/draft-ietf-quic-ack-frequency-10.html)
+/// for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckFrequencyFrame {
+    pub sequence: VarInt,
+    pub ack_eliciting_threshold: VarInt,
+    pub request_max_ack_delay: VarInt,
+    pub reordering_threshold: VarInt,
+}
+
+const ACK_FREQUENCY_FRAME_TYPE: u8 = 0xaf;
+
+impl super::BeFrame for AckFrequencyFrame {
+    fn frame_type(&self) -> super::FrameType {
+        super::FrameType::AckFrequency
+    }
+
+    fn max_encoding_size(&self) -> usize {
+        // The frame type takes 2 bytes
+        2 + 8 + 8 + 8 + 8
+    }
+
+    fn encoding_size(&self) -> usize {
+        2 + self.sequence.encoding_size()
+            + self.ack_eliciting_threshold.encoding_size()
+            + self.request_max_ack_delay.encoding_size()
+            + self.reordering_threshold.encoding_size()
+    }
+}
+
+/// Parse an ACK_FREQUENCY frame from the input buffer,
+/// [nom](https://docs.rs/nom/latest/nom/) parser style.
+pub fn be_ack_frequency_frame(input: &[u8]) -> nom::IResult<&[u8], AckFrequencyFrame> {
+    use nom::{combinator::map, sequence::tuple};
+    map(
+        tuple((be_varint, be_varint, be_varint, be_varint)),
+        |(sequence, ack_eliciting_threshold, request_max_ack_delay, reordering_threshold)| {
+            AckFrequencyFrame {
+                sequence,
+                ack_eliciting_threshold,
+                request_max_ack_delay,
+                reordering_threshold,
+            }
+        },
+    )(input)
+}
+
+impl<T: bytes::BufMut> super::io::WriteFrame<AckFrequencyFrame> for T {
+    fn put_frame(&mut self, frame: &AckFrequencyFrame) {
+        self.put_varint(&VarInt::from(ACK_FREQUENCY_FRAME_TYPE));
+        self.put_varint(&frame.sequence);
+        self.put_varint(&frame.ack_eliciting_threshold);
+        self.put_varint(&frame.request_max_ack_delay);
+        self.put_varint(&frame.reordering_threshold);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{be_ack_frequency_frame, AckFrequencyFrame};
+    use crate::{
+        frame::{be_frame_type, io::WriteFrame, BeFrame, FrameType},
+        varint::VarInt,
+    };
+
+    #[test]
+    fn test_ack_frequency_frame() {
+        let frame = AckFrequencyFrame {
+            sequence: VarInt::from_u32(1),
+            ack_eliciting_threshold: VarInt::from_u32(10),
+            request_max_ack_delay: VarInt::from_u32(25_000),
+            reordering_threshold: VarInt::from_u32(1),
+        };
+        let mut buf = Vec::new();
+        buf.put_frame(&frame);
+        assert_eq!(
+            buf,
+            vec![0x40, 0xaf, 0x01, 0x0a, 0x80, 0x00, 0x61, 0xa8, 0x01]
+        );
+        assert_eq!(frame.encoding_size(), buf.len());
+
+        let (remain, frame_type) = be_frame_type(&buf).unwrap();
+        assert_eq!(frame_type, FrameType::AckFrequency);
+        let (remain, parsed) = be_ack_frequency_frame(remain).unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(parsed, frame);
+    }
+}
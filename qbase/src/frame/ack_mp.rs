@@ -0,0 +1,118 @@
+use super::ack::{ack_frame_with_flag, put_ack_fields, AckFrame};
+use crate::varint::{be_varint, VarInt, WriteVarInt};
+
+/// ACK_MP frame of the multipath extension.
+///
+/// ```text
+/// ACK_MP Frame {
+///   Type (i) = 0x15228c00..0x15228c01,
+///   Destination Connection ID Sequence Number (i),
+///   Largest Acknowledged (i),
+///   ACK Delay (i),
+///   ACK Range Count (i),
+///   First ACK Range (i),
+///   ACK Range (..) ...,
+///   [ECN Counts (..)],
+/// }
+/// ```
+///
+/// When multipath is negotiated, each connection ID identifies a packet number space of the 1-RTT
+/// packets, and the ACK_MP frame acknowledges the packets whose Destination Connection ID is of
+/// the sequence number `dcid_seq`, which can be sent on any path. The ACK frame acknowledges the
+/// packets of sequence number 0.
+///
+/// See [ACK_MP Frame](https://www.ietf.org/archive/id/draft-ietf-quic-multipath-05.html#name-ack_mp-frame)
+/// of [Multipath Extension for QUIC](https://www.ietf.org/archive/id/draft-ietf-quic-multipath-05.html)
+/// for more details.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AckMpFrame {
+    pub dcid_seq: VarInt,
+    pub ack: AckFrame,
+}
+
+const ACK_MP_FRAME_TYPE: u32 = 0x15228c00;
+
+const ECN_OPT: u32 = 0x1;
+
+impl super::BeFrame for AckMpFrame {
+    fn frame_type(&self) -> super::FrameType {
+        super::FrameType::AckMp(if self.ack.ecn.is_some() { 1 } else { 0 })
+    }
+
+    fn max_encoding_size(&self) -> usize {
+        // The frame type takes 4 bytes, while the one of the ACK frame takes 1 byte
+        3 + 8 + self.ack.max_encoding_size()
+    }
+
+    fn encoding_size(&self) -> usize {
+        3 + self.dcid_seq.encoding_size() + self.ack.encoding_size()
+    }
+}
+
+/// Parser for parsing an ACK_MP frame with the given ECN flag,
+/// [nom](https://docs.rs/nom/latest/nom/) parser style.
+pub fn ack_mp_frame_with_flag(ecn_flag: u8) -> impl Fn(&[u8]) -> nom::IResult<&[u8], AckMpFrame> {
+    move |input: &[u8]| {
+        let (input, dcid_seq) = be_varint(input)?;
+        let (input, ack) = ack_frame_with_flag(ecn_flag)(input)?;
+        Ok((input, AckMpFrame { dcid_seq, ack }))
+    }
+}
+
+impl<T: bytes::BufMut> super::io::WriteFrame<AckMpFrame> for T {
+    fn put_frame(&mut self, frame: &AckMpFrame) {
+        let mut frame_type = ACK_MP_FRAME_TYPE;
+        if frame.ack.ecn.is_some() {
+            frame_type |= ECN_OPT;
+        }
+        self.put_varint(&VarInt::from_u32(frame_type));
+        self.put_varint(&frame.dcid_seq);
+        put_ack_fields(self, &frame.ack);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ack_mp_frame_with_flag, AckMpFrame};
+    use crate::{
+        frame::{be_frame_type, io::WriteFrame, AckFrame, BeFrame, EcnCounts, FrameType},
+        varint::VarInt,
+    };
+
+    #[test]
+    fn test_ack_mp_frame() {
+        let frame = AckMpFrame {
+            dcid_seq: VarInt::from_u32(2),
+            ack: AckFrame {
+                largest: VarInt::from_u32(0x1234),
+                delay: VarInt::from_u32(0x1234),
+                first_range: VarInt::from_u32(0x1234),
+                ranges: vec![(VarInt::from_u32(3), VarInt::from_u32(20))],
+                ecn: Some(EcnCounts {
+                    ect0: VarInt::from_u32(0x1234),
+                    ect1: VarInt::from_u32(0x1234),
+                    ce: VarInt::from_u32(0x1234),
+                }),
+            },
+        };
+        assert_eq!(frame.frame_type(), FrameType::AckMp(1));
+
+        let mut buf = Vec::new();
+        buf.put_frame(&frame);
+        assert_eq!(
+            buf,
+            vec![
+                0x95, 0x22, 0x8c, 0x01, 0x02, // type and sequence
+                0x52, 0x34, 0x52, 0x34, 0x01, 0x52, 0x34, 3, 20, // ack
+                0x52, 0x34, 0x52, 0x34, 0x52, 0x34 // ecn
+            ]
+        );
+        assert_eq!(frame.encoding_size(), buf.len());
+
+        let (remain, frame_type) = be_frame_type(&buf).unwrap();
+        assert_eq!(frame_type, FrameType::AckMp(1));
+        let (remain, parsed) = ack_mp_frame_with_flag(1)(remain).unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(parsed, frame);
+    }
+}
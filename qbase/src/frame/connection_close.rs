@@ -45,7 +45,9 @@ impl super::BeFrame for ConnectionCloseFrame {
 
     fn encoding_size(&self) -> usize {
         1 + VarInt::from(self.error_kind).encoding_size()
-            + self.frame_type.is_some()  as usize
+            + self
+                .frame_type
+                .map_or(0, |frame_type| VarInt::from(frame_type).encoding_size())
             // reason's length could not exceed 16KB.
             + VarInt::try_from(self.reason.len()).unwrap().encoding_size()
             + self.reason.len()
@@ -114,7 +116,7 @@ impl<T: bytes::BufMut> super::io::WriteFrame<ConnectionCloseFrame> for T {
         self.put_u8(CONNECTION_CLOSE_FRAME_TYPE | layer);
         self.put_varint(&frame.error_kind.into());
         if let Some(frame_type) = frame.frame_type {
-            self.put_u8(frame_type.into());
+            self.put_varint(&frame_type.into());
         }
         self.put_varint(&VarInt::from_u32(frame.reason.len() as u32));
         self.put_slice(frame.reason.as_bytes());
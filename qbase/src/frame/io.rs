@@ -1,13 +1,15 @@
 use bytes::Bytes;
 
 use super::{
-    ack::ack_frame_with_flag, connection_close::connection_close_frame_at_layer,
+    ack::ack_frame_with_flag, ack_frequency::be_ack_frequency_frame,
+    ack_mp::ack_mp_frame_with_flag, connection_close::connection_close_frame_at_layer,
     crypto::be_crypto_frame, data_blocked::be_data_blocked_frame,
     datagram::datagram_frame_with_flag, max_data::be_max_data_frame,
     max_stream_data::be_max_stream_data_frame, max_streams::max_streams_frame_with_dir,
     new_connection_id::be_new_connection_id_frame, new_token::be_new_token_frame,
-    path_challenge::be_path_challenge_frame, path_response::be_path_response_frame,
-    reset_stream::be_reset_stream_frame, retire_connection_id::be_retire_connection_id_frame,
+    path_abandon::be_path_abandon_frame, path_challenge::be_path_challenge_frame,
+    path_response::be_path_response_frame, reset_stream::be_reset_stream_frame,
+    reset_stream_at::be_reset_stream_at_frame, retire_connection_id::be_retire_connection_id_frame,
     stop_sending::be_stop_sending_frame, stream::stream_frame_with_flag,
     stream_data_blocked::be_stream_data_blocked_frame,
     streams_blocked::streams_blocked_frame_with_dir, *,
@@ -43,7 +45,14 @@ fn complete_frame(
         FrameType::HandshakeDone => Ok((input, Frame::HandshakeDone(HandshakeDoneFrame))),
         FrameType::NewToken => map(be_new_token_frame, Frame::NewToken)(input),
         FrameType::Ack(ecn) => map(ack_frame_with_flag(ecn), Frame::Ack)(input),
+        FrameType::AckMp(ecn) => map(ack_mp_frame_with_flag(ecn), Frame::AckMp)(input),
+        FrameType::PathAbandon => map(be_path_abandon_frame, Frame::PathAbandon)(input),
+        FrameType::AckFrequency => map(be_ack_frequency_frame, Frame::AckFrequency)(input),
+        FrameType::ImmediateAck => Ok((input, Frame::ImmediateAck(ImmediateAckFrame))),
         FrameType::ResetStream => map(be_reset_stream_frame, |f| Frame::StreamCtl(f.into()))(input),
+        FrameType::ResetStreamAt => {
+            map(be_reset_stream_at_frame, |f| Frame::StreamCtl(f.into()))(input)
+        }
         FrameType::StopSending => map(be_stop_sending_frame, |f| Frame::StreamCtl(f.into()))(input),
         FrameType::MaxStreamData => {
             map(be_max_stream_data_frame, |f| Frame::StreamCtl(f.into()))(input)
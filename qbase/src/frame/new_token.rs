@@ -25,12 +25,11 @@ impl super::BeFrame for NewTokenFrame {
     }
 
     fn max_encoding_size(&self) -> usize {
-        // token's length could not exceed 20
-        1 + 1 + self.token.len()
+        1 + 8 + self.token.len()
     }
 
     fn encoding_size(&self) -> usize {
-        1 + 1 + self.token.len()
+        1 + VarInt::from_u32(self.token.len() as u32).encoding_size() + self.token.len()
     }
 }
 
@@ -59,7 +58,7 @@ impl<T: bytes::BufMut> super::io::WriteFrame<NewTokenFrame> for T {
 }
 #[cfg(test)]
 mod tests {
-    use crate::frame::io::WriteFrame;
+    use crate::frame::{io::WriteFrame, BeFrame};
 
     #[test]
     fn test_read_new_token_frame() {
@@ -78,5 +77,13 @@ mod tests {
         };
         buf.put_frame(&frame);
         assert_eq!(buf, vec![0x07, 0x02, 0x01, 0x02]);
+        assert_eq!(frame.encoding_size(), buf.len());
+
+        let frame = super::NewTokenFrame {
+            token: vec![0; 100],
+        };
+        buf.clear();
+        buf.put_frame(&frame);
+        assert_eq!(frame.encoding_size(), buf.len());
     }
 }
@@ -0,0 +1,117 @@
+use std::borrow::Cow;
+
+use crate::varint::{be_varint, VarInt, WriteVarInt};
+
+/// PATH_ABANDON frame of the multipath extension.
+///
+/// ```text
+/// PATH_ABANDON Frame {
+///   Type (i) = 0x15228c05,
+///   Destination Connection ID Sequence Number (i),
+///   Error Code (i),
+///   Reason Phrase Length (i),
+///   Reason Phrase (..),
+/// }
+/// ```
+///
+/// The path to abandon is identified by the sequence number of the Destination Connection ID
+/// used by the receiver of the frame to send packets on it, after which the connection IDs used
+/// on the path should be retired.
+///
+/// See [PATH_ABANDON Frame](https://www.ietf.org/archive/id/draft-ietf-quic-multipath-05.html#name-path_abandon-frame)
+/// of [Multipath Extension for QUIC](https://www.ietf.org/archive/id/draft-ietf-quic-multipath-05.html)
+/// for more details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathAbandonFrame {
+    pub dcid_seq: VarInt,
+    pub error_code: VarInt,
+    pub reason: Cow<'static, str>,
+}
+
+const PATH_ABANDON_FRAME_TYPE: u32 = 0x15228c05;
+
+impl PathAbandonFrame {
+    /// Create a PATH_ABANDON frame to abandon the path on which the peer sends packets with the
+    /// connection ID of sequence number `dcid_seq`.
+    pub fn new(dcid_seq: VarInt, error_code: VarInt, reason: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            dcid_seq,
+            error_code,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl super::BeFrame for PathAbandonFrame {
+    fn frame_type(&self) -> super::FrameType {
+        super::FrameType::PathAbandon
+    }
+
+    fn max_encoding_size(&self) -> usize {
+        4 + 8 + 8 + 8 + self.reason.len()
+    }
+
+    fn encoding_size(&self) -> usize {
+        4 + self.dcid_seq.encoding_size()
+            + self.error_code.encoding_size()
+            + VarInt::try_from(self.reason.len())
+                .expect("reason phrase is never that long")
+                .encoding_size()
+            + self.reason.len()
+    }
+}
+
+/// Parse a PATH_ABANDON frame from the input buffer,
+/// [nom](https://docs.rs/nom/latest/nom/) parser style.
+pub fn be_path_abandon_frame(input: &[u8]) -> nom::IResult<&[u8], PathAbandonFrame> {
+    use nom::bytes::streaming::take;
+
+    let (remain, dcid_seq) = be_varint(input)?;
+    let (remain, error_code) = be_varint(remain)?;
+    let (remain, reason_length) = be_varint(remain)?;
+    let (remain, reason) = take(reason_length.into_inner() as usize)(remain)?;
+    let reason = String::from_utf8_lossy(reason).into_owned();
+    Ok((
+        remain,
+        PathAbandonFrame {
+            dcid_seq,
+            error_code,
+            reason: Cow::Owned(reason),
+        },
+    ))
+}
+
+impl<T: bytes::BufMut> super::io::WriteFrame<PathAbandonFrame> for T {
+    fn put_frame(&mut self, frame: &PathAbandonFrame) {
+        self.put_varint(&VarInt::from_u32(PATH_ABANDON_FRAME_TYPE));
+        self.put_varint(&frame.dcid_seq);
+        self.put_varint(&frame.error_code);
+        self.put_varint(&VarInt::try_from(frame.reason.len()).unwrap());
+        self.put_slice(frame.reason.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{be_path_abandon_frame, PathAbandonFrame};
+    use crate::{
+        frame::{io::WriteFrame, BeFrame},
+        varint::VarInt,
+    };
+
+    #[test]
+    fn test_path_abandon_frame() {
+        let frame = PathAbandonFrame::new(VarInt::from_u32(1), VarInt::from_u32(0), "bye");
+        let mut buf = Vec::new();
+        buf.put_frame(&frame);
+        assert_eq!(
+            buf,
+            vec![0x95, 0x22, 0x8c, 0x05, 0x01, 0x00, 0x03, b'b', b'y', b'e']
+        );
+        assert_eq!(frame.encoding_size(), buf.len());
+
+        let (remain, parsed) = be_path_abandon_frame(&buf[4..]).unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(parsed, frame);
+    }
+}
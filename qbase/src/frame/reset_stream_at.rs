@@ -0,0 +1,120 @@
+use crate::{
+    streamid::{be_streamid, StreamId, WriteStreamId},
+    varint::{be_varint, VarInt, WriteVarInt},
+};
+
+/// RESET_STREAM_AT frame of the reliable stream reset extension.
+///
+/// ```text
+/// RESET_STREAM_AT Frame {
+///   Type (i) = 0x24,
+///   Stream ID (i),
+///   Application Protocol Error Code (i),
+///   Final Size (i),
+///   Reliable Size (i),
+/// }
+/// ```
+///
+/// Unlike the RESET_STREAM frame, the data before the Reliable Size are still delivered to the
+/// application of the receiver reliably, and the reset takes effect after them. A RESET_STREAM_AT
+/// frame with a Reliable Size of 0 is equivalent to a RESET_STREAM frame.
+///
+/// See [RESET_STREAM_AT Frame](https://www.ietf.org/archive/id/draft-ietf-quic-reliable-stream-reset-06.html#name-reset_stream_at-frame)
+/// of [Reliable QUIC Stream Resets](https://www.ietf.org/archive/id/draft-ietf-quic-reliable-stream-reset-06.html)
+/// for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResetStreamAtFrame {
+    pub stream_id: StreamId,
+    pub app_error_code: VarInt,
+    pub final_size: VarInt,
+    pub reliable_size: VarInt,
+}
+
+const RESET_STREAM_AT_FRAME_TYPE: u8 = 0x24;
+
+impl super::BeFrame for ResetStreamAtFrame {
+    fn frame_type(&self) -> super::FrameType {
+        super::FrameType::ResetStreamAt
+    }
+
+    fn max_encoding_size(&self) -> usize {
+        1 + 8 + 8 + 8 + 8
+    }
+
+    fn encoding_size(&self) -> usize {
+        1 + self.stream_id.encoding_size()
+            + self.app_error_code.encoding_size()
+            + self.final_size.encoding_size()
+            + self.reliable_size.encoding_size()
+    }
+}
+
+/// Parse a RESET_STREAM_AT frame from the input buffer,
+/// [nom](https://docs.rs/nom/latest/nom/) parser style.
+pub fn be_reset_stream_at_frame(input: &[u8]) -> nom::IResult<&[u8], ResetStreamAtFrame> {
+    use nom::{combinator::map, sequence::tuple};
+    map(
+        tuple((be_streamid, be_varint, be_varint, be_varint)),
+        |(stream_id, app_error_code, final_size, reliable_size)| ResetStreamAtFrame {
+            stream_id,
+            app_error_code,
+            final_size,
+            reliable_size,
+        },
+    )(input)
+}
+
+impl<T: bytes::BufMut> super::io::WriteFrame<ResetStreamAtFrame> for T {
+    fn put_frame(&mut self, frame: &ResetStreamAtFrame) {
+        self.put_u8(RESET_STREAM_AT_FRAME_TYPE);
+        self.put_streamid(&frame.stream_id);
+        self.put_varint(&frame.app_error_code);
+        self.put_varint(&frame.final_size);
+        self.put_varint(&frame.reliable_size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{be_reset_stream_at_frame, ResetStreamAtFrame, RESET_STREAM_AT_FRAME_TYPE};
+    use crate::{
+        frame::{be_frame_type, io::WriteFrame, BeFrame, FrameType},
+        varint::VarInt,
+    };
+
+    #[test]
+    fn test_reset_stream_at_frame() {
+        let frame = ResetStreamAtFrame {
+            stream_id: VarInt::from_u32(0x1234).into(),
+            app_error_code: VarInt::from_u32(0x5678),
+            final_size: VarInt::from_u32(0x9abc),
+            reliable_size: VarInt::from_u32(0x10),
+        };
+        let mut buf = Vec::new();
+        buf.put_frame(&frame);
+        assert_eq!(
+            buf,
+            vec![
+                RESET_STREAM_AT_FRAME_TYPE,
+                0x52,
+                0x34,
+                0x80,
+                0,
+                0x56,
+                0x78,
+                0x80,
+                0,
+                0x9a,
+                0xbc,
+                0x10
+            ]
+        );
+        assert_eq!(frame.encoding_size(), buf.len());
+
+        let (remain, frame_type) = be_frame_type(&buf).unwrap();
+        assert_eq!(frame_type, FrameType::ResetStreamAt);
+        let (remain, parsed) = be_reset_stream_at_frame(remain).unwrap();
+        assert!(remain.is_empty());
+        assert_eq!(parsed, frame);
+    }
+}
@@ -1,28 +1,33 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-};
+use std::sync::Arc;
 
 use crate::{
     error::{Error, ErrorKind},
     frame::{HandshakeDoneFrame, ReceiveFrame, SendFrame},
     streamid::Role,
+    util::Future,
 };
 
 /// The completion flag for the client handshake.
 ///
-/// The client considers the handshake complete only after
+/// The client considers the handshake complete, namely confirmed, only after
 /// receiving the [`HandshakeDoneFrame`] from the server.
-/// In the QUIC protocol, there are no tasks that specifically
-/// require waiting for the client handshake to complete.
-/// Instead, it simply queries the handshake status.
+/// In the QUIC protocol, most tasks simply query the handshake status,
+/// only a few ones need to wait for the handshake to be confirmed,
+/// such as migrating to the server's preferred address.
 #[derive(Debug, Default, Clone)]
-pub struct ClientHandshake(Arc<AtomicBool>);
+pub struct ClientHandshake(Arc<Future<()>>);
 
 impl ClientHandshake {
     /// Check if the client handshake is complete.
     pub fn is_handshake_done(&self) -> bool {
-        self.0.load(Ordering::Acquire)
+        self.0.try_get().is_some()
+    }
+
+    /// Wait for the client handshake to be complete.
+    ///
+    /// Only one task can wait for it at the same time.
+    pub async fn confirmed(&self) {
+        self.0.get().await
     }
 
     /// Receive the HANDSHAKE_DONE frame.
@@ -30,8 +35,7 @@ impl ClientHandshake {
     /// Once the client receives the HANDSHAKE_DONE frame,
     /// it marks the completion of the client handshake.
     pub fn recv_handshake_done_frame(&self, _frame: &HandshakeDoneFrame) {
-        let _has_done = self.0.swap(true, Ordering::AcqRel);
-        if !_has_done {
+        if self.0.assign(()).is_ok() {
             log::trace!("Client handshake is done");
         }
     }
@@ -56,7 +60,7 @@ pub struct ServerHandshake<T>
 where
     T: SendFrame<HandshakeDoneFrame> + Clone,
 {
-    is_done: Arc<AtomicBool>,
+    is_done: Arc<Future<()>>,
     output: T,
 }
 
@@ -70,14 +74,21 @@ where
     /// see [`ServerHandshake`].
     pub fn new(output: T) -> Self {
         ServerHandshake {
-            is_done: Arc::new(AtomicBool::new(false)),
+            is_done: Arc::new(Future::new()),
             output,
         }
     }
 
     /// Check if the server handshake is complete.
     pub fn is_handshake_done(&self) -> bool {
-        self.is_done.load(Ordering::Acquire)
+        self.is_done.try_get().is_some()
+    }
+
+    /// Wait for the server handshake to be complete.
+    ///
+    /// Only one task can wait for it at the same time.
+    pub async fn confirmed(&self) {
+        self.is_done.get().await
     }
 
     /// Actively set the server's handshake status to complete.
@@ -92,11 +103,7 @@ where
     /// servers should send the [`HandshakeDoneFrame`] immediately.
     /// See [`ServerHandshake`].
     pub fn done(&self) {
-        if self
-            .is_done
-            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
-            .is_ok()
-        {
+        if self.is_done.assign(()).is_ok() {
             log::trace!("Server handshake is done");
             self.output.send_frame([HandshakeDoneFrame]);
         }
@@ -150,6 +157,18 @@ where
         }
     }
 
+    /// Wait for the handshake to be confirmed, namely the client receives the
+    /// [`HandshakeDoneFrame`], or the server completes the handshake, see [Section 4.1.2](https://www.rfc-editor.org/rfc/rfc9001.html#section-4.1.2)
+    /// of RFC 9001.
+    ///
+    /// Only one task can wait for it at the same time.
+    pub async fn confirmed(&self) {
+        match self {
+            Handshake::Client(h) => h.confirmed().await,
+            Handshake::Server(h) => h.confirmed().await,
+        }
+    }
+
     /// Return the role of this handshake signal.
     pub fn role(&self) -> Role {
         match self {
@@ -219,6 +238,21 @@ mod tests {
         assert!(handshake.is_handshake_done());
     }
 
+    #[tokio::test]
+    async fn test_client_handshake_confirmed() {
+        let handshake = super::ClientHandshake::default();
+        let confirmed = tokio::spawn({
+            let handshake = handshake.clone();
+            async move { handshake.confirmed().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!confirmed.is_finished());
+
+        handshake.recv_handshake_done_frame(&HandshakeDoneFrame);
+        confirmed.await.unwrap();
+        assert!(handshake.is_handshake_done());
+    }
+
     #[test]
     fn test_server_handshake() {
         let handshake = super::Handshake::new_server(ArcAsyncDeque::new());
@@ -231,6 +265,23 @@ mod tests {
         assert!(handshake.is_handshake_done());
     }
 
+    #[tokio::test]
+    async fn test_server_handshake_confirmed() {
+        let handshake = super::Handshake::new_server(ArcAsyncDeque::new());
+        let confirmed = tokio::spawn({
+            let handshake = handshake.clone();
+            async move { handshake.confirmed().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!confirmed.is_finished());
+
+        if let crate::handshake::Handshake::Server(server_handshake) = &handshake {
+            server_handshake.done();
+        }
+        confirmed.await.unwrap();
+        assert!(handshake.is_handshake_done());
+    }
+
     #[test]
     fn test_server_recv_handshake_done_frame() {
         let handshake = super::Handshake::new_server(ArcAsyncDeque::new());
@@ -16,6 +16,7 @@ pub use signal::{KeyPhaseBit, SpinBit};
 pub mod r#type;
 #[doc(hidden)]
 pub use r#type::{
+    long::{grease_version, is_reserved_version, QUIC_V1, QUIC_V2},
     GetPacketNumberLength, LongSpecificBits, ShortSpecificBits, Type, LONG_RESERVED_MASK,
     SHORT_RESERVED_MASK,
 };
@@ -81,11 +82,22 @@ impl GetType for DataPacket {
     }
 }
 
+impl DataPacket {
+    /// Whether the QUIC Bit of the packet is cleared by the peer, which is only allowed if the
+    /// grease_quic_bit transport parameter was advertised to it, see [RFC 9287](https://www.rfc-editor.org/rfc/rfc9287.html).
+    ///
+    /// Otherwise, such packet is not a valid packet of this version and must be discarded.
+    pub fn is_quic_bit_greased(&self) -> bool {
+        self.bytes[0] & r#type::FIXED_BIT == 0
+    }
+}
+
 /// The sum type of all QUIC packets.
 #[derive(Debug, Clone)]
 pub enum Packet {
     VN(VersionNegotiationHeader),
-    Retry(RetryHeader),
+    // Retry(header, bytes), the bytes of the whole packet are kept to verify the integrity tag
+    Retry(RetryHeader, BytesMut),
     // Data(header, bytes, payload_offset)
     Data(DataPacket),
 }
@@ -183,8 +195,13 @@ pub mod io {
             _ => unreachable!("parsing packet header never generates error or failure"),
         })?;
         match header {
-            Header::VN(header) => Ok(Packet::VN(header)),
-            Header::Retry(header) => Ok(Packet::Retry(header)),
+            // Version Negotiation and Retry packets can not be coalesced with other packets, they
+            // occupy the rest of the datagram
+            Header::VN(header) => {
+                datagram.clear();
+                Ok(Packet::VN(header))
+            }
+            Header::Retry(header) => Ok(Packet::Retry(header, datagram.split())),
             Header::Initial(header) => {
                 let (bytes, offset) = be_payload(pkty, datagram, remain.len())?;
                 Ok(Packet::Data(DataPacket {
@@ -117,3 +117,21 @@ pub fn decrypt_packet(
     // should return plain.len()
     Ok(plain.len())
 }
+
+/// Decrypt the body of a packet with the nonce of the path `path_id`, which is used by the
+/// multipath extension for the packets received with a connection ID of a nonzero sequence number.
+///
+/// Other than the nonce, it is the same as [`decrypt_packet`].
+pub fn decrypt_packet_for_path(
+    key: &dyn PacketKey,
+    path_id: u32,
+    pn: u64,
+    pkt_buf: &mut [u8],
+    body_offset: usize,
+) -> Result<usize, Error> {
+    let (aad, body) = pkt_buf.split_at_mut(body_offset);
+    let plain = key
+        .decrypt_in_place_for_path(path_id, pn, aad, body)
+        .map_err(|_| Error::DecryptPacketFailure)?;
+    Ok(plain.len())
+}
@@ -2,7 +2,7 @@ use std::ops::Deref;
 
 use rustls::quic::{HeaderProtectionKey, PacketKey};
 
-use super::{KeyPhaseBit, LongSpecificBits, ShortSpecificBits};
+use super::{r#type::FIXED_BIT, KeyPhaseBit, LongSpecificBits, ShortSpecificBits};
 
 /// Encrypt the packet body, applicable to both long and short packets.
 ///
@@ -24,6 +24,25 @@ pub fn encrypt_packet(key: &dyn PacketKey, pn: u64, pkt_buf: &mut [u8], body_off
     tag_buf.copy_from_slice(tag.as_ref());
 }
 
+/// Encrypt the packet body with the nonce of the path `path_id`, which is used by the
+/// multipath extension for the packets sent with a connection ID of a nonzero sequence number.
+///
+/// Other than the nonce, it is the same as [`encrypt_packet`].
+pub fn encrypt_packet_for_path(
+    key: &dyn PacketKey,
+    path_id: u32,
+    pn: u64,
+    pkt_buf: &mut [u8],
+    body_offset: usize,
+) {
+    let (aad, body_tag) = pkt_buf.split_at_mut(body_offset);
+    let (body, tag_buf) = body_tag.split_at_mut(body_tag.len() - key.tag_len());
+    let tag = key
+        .encrypt_in_place_for_path(path_id, pn, aad, body)
+        .unwrap();
+    tag_buf.copy_from_slice(tag.as_ref());
+}
+
 /// Add header protection, applicable to both long and short packets.
 /// Mainly protects the Reserved Bits and Packet Number Length in the packet header,
 /// as well as the Packet Number.
@@ -71,5 +90,16 @@ pub fn encode_short_first_byte(first_byte: &mut u8, pn_len: usize, key_phase: Ke
     *first_byte |= specific_bits.deref();
 }
 
+/// Randomly clear the QUIC Bit of the first byte, only if the peer advertised the grease_quic_bit
+/// transport parameter, see [RFC 9287](https://www.rfc-editor.org/rfc/rfc9287.html).
+///
+/// The QUIC Bit is not protected by the header protection, but it is authenticated as the
+/// associated data, so it must be greased before encrypting the packet body.
+pub fn grease_quic_bit(first_byte: &mut u8) {
+    if rand::random::<bool>() {
+        *first_byte &= !FIXED_BIT;
+    }
+}
+
 #[cfg(test)]
 mod tests {}
@@ -8,8 +8,6 @@ use super::r#type::Type;
 pub enum Error {
     #[error("Unsupport version {0}")]
     UnsupportedVersion(u32),
-    #[error("Invalid fixed bit in long header")]
-    InvalidFixedBit,
     #[error("Incomplete packet type: {0}")]
     IncompleteType(String),
     #[error("Incomplete packet header {0:?}: {1}")]
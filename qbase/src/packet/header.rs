@@ -100,7 +100,7 @@ pub mod io {
             Type::Long(long_ty) => {
                 let (remain, dcid) = be_connection_id(input)?;
                 let (remain, scid) = be_connection_id(remain)?;
-                let builder = LongHeaderBuilder { dcid, scid };
+                let builder = LongHeaderBuilder::with_cid(dcid, scid);
                 builder.parse(long_ty, remain)
             }
             Type::Short(OneRtt(spin)) => {
@@ -1,7 +1,11 @@
 use deref_derive::{Deref, DerefMut};
 
 use super::*;
-use crate::{cid::ConnectionId, varint::VarInt};
+use crate::{
+    cid::ConnectionId,
+    packet::r#type::long::{QUIC_V1, QUIC_V2},
+    varint::VarInt,
+};
 
 /// The long header structure, whose specific contents are determined by the
 /// concrete packet type, including VN/Retry/Initial/0Rtt/Handshake packet.
@@ -23,14 +27,29 @@ use crate::{cid::ConnectionId, varint::VarInt};
 ///
 /// See [Long Header Packet Format](https://www.rfc-editor.org/rfc/rfc9000.html#name-long-header-packets)
 /// of [QUIC](https://www.rfc-editor.org/rfc/rfc9000.html) for more details.
-#[derive(Debug, Default, Clone, Deref, DerefMut)]
+///
+/// The `version` determines how the packet type is encoded, and the initial keys to
+/// protect the Initial packets.
+#[derive(Debug, Clone, Deref, DerefMut)]
 pub struct LongHeader<T> {
+    pub version: u32,
     pub dcid: ConnectionId,
     pub scid: ConnectionId,
     #[deref]
     pub specific: T,
 }
 
+impl<T: Default> Default for LongHeader<T> {
+    fn default() -> Self {
+        Self {
+            version: QUIC_V1,
+            dcid: ConnectionId::default(),
+            scid: ConnectionId::default(),
+            specific: T::default(),
+        }
+    }
+}
+
 impl<T> super::GetDcid for LongHeader<T> {
     fn get_dcid(&self) -> &ConnectionId {
         &self.dcid
@@ -82,6 +101,37 @@ impl Retry {
         retry.integrity.copy_from_slice(integrity);
         retry
     }
+
+    /// The AEAD_AES_128_GCM key and nonce used to compute the Retry Integrity Tag of the
+    /// given `version`, return [`None`] if the version is not supported.
+    ///
+    /// See [Retry Packet Integrity](https://www.rfc-editor.org/rfc/rfc9001.html#name-retry-packet-integrity)
+    /// of [RFC 9001](https://www.rfc-editor.org/rfc/rfc9001.html), and
+    /// [Retry Integrity Tag](https://www.rfc-editor.org/rfc/rfc9369.html#name-retry-integrity-tag)
+    /// of [RFC 9369](https://www.rfc-editor.org/rfc/rfc9369.html) for version 2.
+    pub fn integrity_key(version: u32) -> Option<(&'static [u8; 16], &'static [u8; 12])> {
+        match version {
+            QUIC_V1 => Some((
+                &[
+                    0xbe, 0x0c, 0x69, 0x0b, 0x9f, 0x66, 0x57, 0x5a, 0x1d, 0x76, 0x6b, 0x54, 0xe3,
+                    0x68, 0xc8, 0x4e,
+                ],
+                &[
+                    0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
+                ],
+            )),
+            QUIC_V2 => Some((
+                &[
+                    0x8f, 0xb4, 0xb0, 0x1b, 0x56, 0xac, 0x48, 0xe2, 0x60, 0xfb, 0xcb, 0xce, 0xad,
+                    0x7c, 0xcc, 0x92,
+                ],
+                &[
+                    0xd8, 0x69, 0x69, 0xbc, 0x2d, 0x7c, 0x6d, 0x99, 0x90, 0xef, 0xb0, 0x4a,
+                ],
+            )),
+            _ => None,
+        }
+    }
 }
 
 /// The specific contents of the initial packet, which just includes a token.
@@ -154,12 +204,18 @@ impl<S: EncodeHeader> EncodeHeader for LongHeader<S> {
     }
 }
 
+impl GetType for VersionNegotiationHeader {
+    fn get_type(&self) -> Type {
+        Type::Long(LongType::VersionNegotiation)
+    }
+}
+
 macro_rules! bind_type {
     ($($type:ty => $value:expr),*) => {
         $(
             impl GetType for $type {
                 fn get_type(&self) -> Type {
-                    $value
+                    Type::Long(LongType::with_version(self.version, $value))
                 }
             }
         )*
@@ -167,11 +223,10 @@ macro_rules! bind_type {
 }
 
 bind_type!(
-    VersionNegotiationHeader => Type::Long(LongType::VersionNegotiation),
-    RetryHeader => Type::Long(LongType::V1(Version::<1, _>(v1::Type::Retry))),
-    InitialHeader => Type::Long(LongType::V1(Version::<1, _>(v1::Type::Initial))),
-    ZeroRttHeader => Type::Long(LongType::V1(Version::<1, _>(v1::Type::ZeroRtt))),
-    HandshakeHeader => Type::Long(LongType::V1(Version::<1, _>(v1::Type::Handshake)))
+    RetryHeader => v1::Type::Retry,
+    InitialHeader => v1::Type::Initial,
+    ZeroRttHeader => v1::Type::ZeroRtt,
+    HandshakeHeader => v1::Type::Handshake
 );
 
 /// The sum type of long packets that carry data,
@@ -186,8 +241,6 @@ pub enum DataHeader {
 
 /// The io module provides functions for parsing and writing long headers.
 pub mod io {
-    use std::ops::Deref;
-
     use bytes::BufMut;
     use nom::{
         bytes::streaming::take,
@@ -202,7 +255,7 @@ pub mod io {
         cid::WriteConnectionId,
         packet::r#type::{
             io::WritePacketType,
-            long::{v1::Type as LongV1Type, Type as LongType},
+            long::{v1::Type as LongV1Type, GetVersion, Type as LongType},
         },
         varint::{be_varint, WriteVarInt},
     };
@@ -257,15 +310,28 @@ pub mod io {
     /// let handshake_header = LongHeaderBuilder::with_cid(dcid, scid).handshake();
     /// ```
     pub struct LongHeaderBuilder {
+        pub(crate) version: u32,
         pub(crate) dcid: ConnectionId,
         pub(crate) scid: ConnectionId,
     }
 
     impl LongHeaderBuilder {
         /// Create a new long header builder with the given destination
-        /// and source connection IDs.
+        /// and source connection IDs, in QUIC version 1.
         pub fn with_cid(dcid: ConnectionId, scid: ConnectionId) -> Self {
-            Self { dcid, scid }
+            Self {
+                version: QUIC_V1,
+                dcid,
+                scid,
+            }
+        }
+
+        /// Set the QUIC version of the long header, such as [`QUIC_V2`].
+        ///
+        /// [`QUIC_V2`]: crate::packet::r#type::long::QUIC_V2
+        pub fn version(mut self, version: u32) -> Self {
+            self.version = version;
+            self
         }
 
         /// Build into a version negotiation header.
@@ -297,6 +363,7 @@ pub mod io {
         /// Return the specific long header.
         pub fn wrap<T>(self, specific: T) -> LongHeader<T> {
             LongHeader {
+                version: self.version,
                 dcid: self.dcid,
                 scid: self.scid,
                 specific,
@@ -307,13 +374,14 @@ pub mod io {
         /// [nom](https://docs.rs/nom/latest/nom/) parser style.
         ///
         /// The input buffer would be the remaining data of the buffer.
-        pub fn parse(self, ty: LongType, input: &[u8]) -> nom::IResult<&[u8], Header> {
+        pub fn parse(mut self, ty: LongType, input: &[u8]) -> nom::IResult<&[u8], Header> {
+            self.version = ty.get_version();
             match ty {
                 LongType::VersionNegotiation => {
                     let (remain, versions) = be_version_negotiation(input)?;
                     Ok((remain, Header::VN(self.wrap(versions))))
                 }
-                LongType::V1(ty) => match ty.deref() {
+                LongType::V1(Version(ty)) | LongType::V2(Version(ty)) => match ty {
                     LongV1Type::Retry => {
                         let (remain, retry) = be_retry(input)?;
                         Ok((remain, Header::Retry(self.wrap(retry))))
@@ -507,4 +575,37 @@ mod tests {
         buf.put_specific(&initial_long_header.specific);
         assert_eq!(buf, vec![0x03, 0x00, 0x00, 0x00,]);
     }
+
+    #[test]
+    fn test_write_v2_long_header() {
+        use super::{LongHeaderBuilder, WriteLongHeader};
+        use crate::{
+            cid::ConnectionId,
+            packet::{
+                header::{io::be_header, GetType, Header},
+                r#type::{
+                    long::{Type, Ver2, QUIC_V2},
+                    Type as PacketType,
+                },
+            },
+        };
+
+        let mut buf = Vec::<u8>::new();
+        let handshake_header =
+            LongHeaderBuilder::with_cid(ConnectionId::default(), ConnectionId::default())
+                .version(QUIC_V2)
+                .handshake();
+        assert_eq!(
+            handshake_header.get_type(),
+            PacketType::Long(Type::V2(Ver2::HANDSHAKE))
+        );
+        buf.put_long_header(&handshake_header);
+        assert_eq!(buf, vec![0xf0, 0x6b, 0x33, 0x43, 0xcf, 0x00, 0x00]);
+
+        let ty = PacketType::Long(Type::V2(Ver2::HANDSHAKE));
+        match be_header(ty, 0, &buf[5..]).unwrap().1 {
+            Header::Handshake(header) => assert_eq!(header.version, QUIC_V2),
+            _ => panic!("unexpected header"),
+        }
+    }
 }
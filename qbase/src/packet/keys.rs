@@ -4,6 +4,7 @@ use std::{
     pin::Pin,
     sync::{Arc, Mutex, MutexGuard},
     task::{Context, Poll, Waker},
+    time::{Duration, Instant},
 };
 
 use rustls::quic::{HeaderProtectionKey, Keys, PacketKey, Secrets};
@@ -134,6 +135,18 @@ impl ArcKeys {
         }
     }
 
+    /// Replace the ready keys with new `keys`.
+    ///
+    /// This is used when the client receives a Retry packet, the Initial keys are derived from
+    /// the new Destination Connection ID chosen by the server then. Nothing happens if the keys
+    /// are not ready, or have been invalidated.
+    pub fn revise_keys(&self, keys: Keys) {
+        let mut state = self.lock_guard();
+        if let KeysState::Ready(ready) = &mut *state {
+            *ready = Arc::new(keys);
+        }
+    }
+
     /// Retire the keys, which means that the keys are no longer available.
     ///
     /// This is used when the connection enters the closing state or draining state.
@@ -191,6 +204,19 @@ pub struct OneRttPacketKeys {
     secrets: Secrets,
     remote: [Option<Arc<dyn PacketKey>>; 2],
     local: Arc<dyn PacketKey>,
+    // The keys of the next phase, derived in advance to try decrypting the packets which may
+    // be sent after the peer initiated a key update, not used until the decryption succeeds.
+    next: Option<(Arc<dyn PacketKey>, Arc<dyn PacketKey>)>,
+    // When to discard the remote keys of the previous phase
+    old_remote_expiry: Option<Instant>,
+    // The smallest packet number received in the current phase
+    first_rcvd_pn: Option<u64>,
+    // The first packet number sent in the current phase, and whether the peer has acknowledged
+    // any packet sent in the current phase, which is required to initiate the next key update.
+    first_sent_pn: Option<u64>,
+    phase_acked: bool,
+    // The number of packets encrypted by the local key, to respect the confidentiality limit
+    encrypted: u64,
 }
 
 impl OneRttPacketKeys {
@@ -203,19 +229,53 @@ impl OneRttPacketKeys {
             secrets,
             remote: [Some(Arc::from(remote)), None],
             local: Arc::from(local),
+            next: None,
+            old_remote_expiry: None,
+            first_rcvd_pn: None,
+            first_sent_pn: None,
+            // No key update has been initiated, the first one is only limited by the handshake
+            phase_acked: true,
+            encrypted: 0,
         }
     }
 
+    fn next_keys(&mut self) -> &(Arc<dyn PacketKey>, Arc<dyn PacketKey>) {
+        let secrets = &mut self.secrets;
+        self.next.get_or_insert_with(|| {
+            let key_set = secrets.next_packet_keys();
+            (Arc::from(key_set.remote), Arc::from(key_set.local))
+        })
+    }
+
     /// Proactively update the 1-RTT packet key locally.
     /// Or be informed by the peer to update the key.
     ///
     /// The key phase bit will be toggled and sent to the peer,
     /// informing the peer to update the key to next 1-RTT packet key too.
+    /// The remote key of the previous phase is retained to decrypt the delayed packets.
     pub fn update(&mut self) {
+        self.next_keys();
+        let (remote, local) = self.next.take().unwrap();
         self.cur_phase.toggle();
-        let key_set = self.secrets.next_packet_keys();
-        self.remote[self.cur_phase.as_index()] = Some(Arc::from(key_set.remote));
-        self.local = Arc::from(key_set.local);
+        self.remote[self.cur_phase.as_index()] = Some(remote);
+        self.local = local;
+        self.old_remote_expiry = None;
+        self.first_rcvd_pn = None;
+        self.first_sent_pn = None;
+        self.phase_acked = false;
+        self.encrypted = 0;
+    }
+
+    /// Initiate a key update locally, return false if it's not allowed yet, because no packet
+    /// sent in the current phase has been acknowledged by the peer.
+    ///
+    /// The handshake must be confirmed before initiating a key update.
+    pub fn initiate_update(&mut self) -> bool {
+        if !self.phase_acked {
+            return false;
+        }
+        self.update();
+        true
     }
 
     /// Old key must be phased out within a certain period of time.
@@ -225,25 +285,92 @@ impl OneRttPacketKeys {
     /// fail to decrypt the packet in future.
     pub fn phase_out(&mut self) {
         self.remote[(!self.cur_phase).as_index()].take();
+        self.old_remote_expiry = None;
+    }
+
+    // Whether a packet in the other key phase is a delayed one in the previous phase,
+    // otherwise it's protected by the keys of the next phase.
+    fn is_previous_phase(&self, pn: u64) -> bool {
+        self.remote[(!self.cur_phase).as_index()].is_some()
+            && self.first_rcvd_pn.is_none_or(|first| pn < first)
     }
 
     /// Get the remote key to decrypt the incoming 1-RTT packet.
-    /// If the key phase is not the current key phase, update the key, see [`Self::update`].
+    ///
+    /// If the key phase is not the current key phase, return the key of the previous phase if the
+    /// packet is a delayed one, otherwise the key of the next phase. The key update initiated by
+    /// the peer takes effect after the packet is decrypted successfully, see [`Self::on_rcvd`].
     ///
     /// Return `Arc<PacketKey>` to decrypt the incoming 1-RTT packet.
-    pub fn get_remote(&mut self, key_phase: KeyPhaseBit, _pn: u64) -> Arc<dyn PacketKey> {
-        if key_phase != self.cur_phase && self.remote[key_phase.as_index()].is_none() {
+    pub fn get_remote(&mut self, key_phase: KeyPhaseBit, pn: u64) -> Arc<dyn PacketKey> {
+        if self
+            .old_remote_expiry
+            .is_some_and(|expiry| expiry <= Instant::now())
+        {
+            self.phase_out();
+        }
+        if key_phase == self.cur_phase || self.is_previous_phase(pn) {
+            return self.remote[key_phase.as_index()].clone().unwrap();
+        }
+        self.next_keys().0.clone()
+    }
+
+    /// Be informed that the 1-RTT packet `pn` in `key_phase` has been decrypted successfully by
+    /// the key from [`Self::get_remote`].
+    ///
+    /// If it's protected by the keys of the next phase, the peer has initiated a key update, the
+    /// local keys are updated too. The remote key of the previous phase will be discarded after
+    /// three times of `pto` since the first packet in the new phase is received.
+    pub fn on_rcvd(&mut self, key_phase: KeyPhaseBit, pn: u64, pto: Duration) {
+        if key_phase != self.cur_phase {
+            if self.is_previous_phase(pn) {
+                return;
+            }
             self.update();
         }
-        self.remote[key_phase.as_index()].clone().unwrap()
+        if self.first_rcvd_pn.is_none() && self.remote[(!self.cur_phase).as_index()].is_some() {
+            self.old_remote_expiry = Some(Instant::now() + 3 * pto);
+        }
+        self.first_rcvd_pn = Some(self.first_rcvd_pn.map_or(pn, |first| first.min(pn)));
+    }
+
+    /// Be informed that the largest packet number `largest` acknowledged by the peer.
+    pub fn on_pkt_acked(&mut self, largest: u64) {
+        if self.first_sent_pn.is_some_and(|first| largest >= first) {
+            self.phase_acked = true;
+        }
     }
 
-    /// Get the local current key to encrypt the outgoing packet.
+    /// Get the local current key to encrypt the outgoing packet `pn`.
+    ///
+    /// The key is updated proactively if the number of encrypted packets is approaching the
+    /// confidentiality limit of the AEAD.
     ///
     /// Return `Arc<PacketKey>` to encrypt the outgoing 1-RTT packet.
-    pub fn get_local(&self) -> (KeyPhaseBit, Arc<dyn PacketKey>) {
+    pub fn get_local(&mut self, pn: u64) -> (KeyPhaseBit, Arc<dyn PacketKey>) {
+        let limit = self.local.confidentiality_limit();
+        if self.encrypted >= limit - limit / 16 {
+            self.initiate_update();
+        }
+        self.encrypted += 1;
+        self.first_sent_pn.get_or_insert(pn);
         (self.cur_phase, self.local.clone())
     }
+
+    /// Whether the local key can only encrypt one more packet within the confidentiality limit of
+    /// the AEAD, because the key update was not allowed in time.
+    ///
+    /// No more 1-RTT packet should be sent then, but the one carrying the CONNECTION_CLOSE frame
+    /// of an AEAD_LIMIT_REACHED error, see [Section 6.6](https://www.rfc-editor.org/rfc/rfc9001#section-6.6)
+    /// of RFC 9001.
+    pub fn is_exhausted(&self) -> bool {
+        self.encrypted + 1 >= self.local.confidentiality_limit()
+    }
+
+    /// The current key phase.
+    pub fn key_phase(&self) -> KeyPhaseBit {
+        self.cur_phase
+    }
 }
 
 /// The packet encryption and decryption keys for 1-RTT packets, which will still
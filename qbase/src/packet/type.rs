@@ -10,7 +10,7 @@ pub mod short;
 /// Header form bit
 const HEADER_FORM_MASK: u8 = 0x80;
 /// The next bit (0x40) of byte 0 is set to 1, unless the packet is a Version Negotiation packet.
-const FIXED_BIT: u8 = 0x40;
+pub(crate) const FIXED_BIT: u8 = 0x40;
 
 /// Reserved bits mask for long headers, for the 5th and 6th bits of the first byte of the long header
 pub const LONG_RESERVED_MASK: u8 = 0x0C;
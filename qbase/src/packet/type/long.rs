@@ -2,6 +2,26 @@ use deref_derive::Deref;
 
 /// Supports IQuic version 1, if other versions are supported in the future, add them here.
 pub mod v1;
+/// IQuic version 2 reuses the packet types of version 1, with different encodings.
+mod v2;
+
+/// The version number of QUIC version 1, see [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html).
+pub const QUIC_V1: u32 = 0x0000_0001;
+/// The version number of QUIC version 2, see [RFC 9369](https://www.rfc-editor.org/rfc/rfc9369.html).
+pub const QUIC_V2: u32 = 0x6b33_43cf;
+
+/// Whether the `version` is reserved to exercise the version negotiation, which is in the pattern
+/// of `0x?a?a?a?a`, see [Versions](https://www.rfc-editor.org/rfc/rfc9000.html#name-versions)
+/// of [RFC 9000](https://www.rfc-editor.org/rfc/rfc9000.html).
+pub fn is_reserved_version(version: u32) -> bool {
+    version & 0x0f0f_0f0f == 0x0a0a_0a0a
+}
+
+/// Generate a random reserved version, which is never chosen by the peer and only used for
+/// greasing.
+pub fn grease_version() -> u32 {
+    rand::random::<u32>() & 0xf0f0_f0f0 | 0x0a0a_0a0a
+}
 
 /// The long packet header contains version information, so the 32-bit
 /// version number info is also one part of the versioned packet type.
@@ -39,13 +59,59 @@ impl Version<1, v1::Type> {
 /// Represent the packet types in the IQuic version 1, including Retry/Initial/0-RTT/Handshake.
 pub type Ver1 = Version<1, v1::Type>;
 
+/// Mainly define the long packet types of the IQuic version 2.
+impl Version<QUIC_V2, v1::Type> {
+    /// Retry packet type of the IQuic version 2.
+    pub const RETRY: Self = Self(v1::Type::Retry);
+    /// Initial packet type of the IQuic version 2.
+    pub const INITIAL: Self = Self(v1::Type::Initial);
+    /// 0-RTT packet type of the IQuic version 2.
+    pub const ZERO_RTT: Self = Self(v1::Type::ZeroRtt);
+    /// Handshake packet type of the IQuic version 2.
+    pub const HANDSHAKE: Self = Self(v1::Type::Handshake);
+}
+
+/// Represent the packet types in the IQuic version 2, which are the same as version 1.
+pub type Ver2 = Version<QUIC_V2, v1::Type>;
+
 /// The sum types of the long packets.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
     VersionNegotiation,
     V1(Version<1, v1::Type>),
+    V2(Version<QUIC_V2, v1::Type>),
     // in the future, add other versions here
-    // V2(v2::HeaderType),
+}
+
+impl Type {
+    /// Create the long packet type of `ty` in the given `version`, the versions other than
+    /// version 2 are treated as version 1.
+    pub fn with_version(version: u32, ty: v1::Type) -> Self {
+        match version {
+            QUIC_V2 => Type::V2(Version(ty)),
+            _ => Type::V1(Version(ty)),
+        }
+    }
+
+    /// Return the specific packet type regardless of the version, [`None`] for the
+    /// version negotiation packet.
+    pub fn specific(&self) -> Option<v1::Type> {
+        match self {
+            Type::VersionNegotiation => None,
+            Type::V1(ty) => Some(ty.0),
+            Type::V2(ty) => Some(ty.0),
+        }
+    }
+}
+
+impl GetVersion for Type {
+    fn get_version(&self) -> u32 {
+        match self {
+            Type::VersionNegotiation => 0,
+            Type::V1(ty) => ty.get_version(),
+            Type::V2(ty) => ty.get_version(),
+        }
+    }
 }
 
 /// The io module provides the functions to parse and write the long packet type.
@@ -71,6 +137,12 @@ pub mod io {
                         ty.try_into().map_err(nom::Err::Error)?,
                     )),
                 )),
+                QUIC_V2 => Ok((
+                    remain,
+                    Type::V2(Version::<QUIC_V2, v1::Type>(
+                        v2::decode(ty).map_err(nom::Err::Error)?,
+                    )),
+                )),
                 v => Err(nom::Err::Error(Error::UnsupportedVersion(v))),
             }
         }
@@ -94,6 +166,10 @@ pub mod io {
                     self.put_u8(LONG_HEADER_BIT | FIXED_BIT | ty);
                     self.put_u32(1);
                 }
+                Type::V2(Version::<QUIC_V2, _>(ty)) => {
+                    self.put_u8(LONG_HEADER_BIT | FIXED_BIT | v2::encode(*ty));
+                    self.put_u32(QUIC_V2);
+                }
             }
         }
     }
@@ -101,7 +177,7 @@ pub mod io {
 
 #[cfg(test)]
 mod tests {
-    use crate::packet::r#type::long::Ver1;
+    use crate::packet::r#type::long::{Ver1, Ver2};
 
     #[test]
     fn test_read_long_type() {
@@ -118,6 +194,31 @@ mod tests {
         assert_eq!(ty, Type::VersionNegotiation);
     }
 
+    #[test]
+    fn test_grease_version() {
+        use super::{grease_version, is_reserved_version, QUIC_V1, QUIC_V2};
+
+        for _ in 0..16 {
+            assert!(is_reserved_version(grease_version()));
+        }
+        assert!(is_reserved_version(0x1a2a_3a4a));
+        assert!(!is_reserved_version(QUIC_V1));
+        assert!(!is_reserved_version(QUIC_V2));
+    }
+
+    #[test]
+    fn test_read_v2_long_type() {
+        use super::{io::parse_long_type, Type};
+
+        let buf = vec![0x6b, 0x33, 0x43, 0xcf];
+        let (remain, ty) = parse_long_type(0xd0)(&buf).unwrap();
+        assert_eq!(remain.len(), 0);
+        assert_eq!(ty, Type::V2(Ver2::INITIAL));
+
+        let (_, ty) = parse_long_type(0xc0)(&buf).unwrap();
+        assert_eq!(ty, Type::V2(Ver2::RETRY));
+    }
+
     #[test]
     #[should_panic]
     fn test_read_long_type_with_wrong_version() {
@@ -140,6 +241,16 @@ mod tests {
         assert_eq!(buf, vec![0xc0, 0x00, 0x00, 0x00, 0x01]);
     }
 
+    #[test]
+    fn test_write_v2_long_type() {
+        use super::Type;
+        use crate::packet::r#type::long::io::WriteLongType;
+
+        let mut buf = vec![];
+        buf.put_long_type(&Type::V2(Ver2::HANDSHAKE));
+        assert_eq!(buf, vec![0xf0, 0x6b, 0x33, 0x43, 0xcf]);
+    }
+
     #[test]
     fn test_write_version_negotiation_long_type() {
         use super::Type;
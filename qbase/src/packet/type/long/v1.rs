@@ -1,4 +1,4 @@
-use crate::packet::{error::Error, r#type::FIXED_BIT};
+use crate::packet::error::Error;
 
 /// Long packet types. The 3th and 4th bits of the first byte of the long header
 /// represent the specific packet type.
@@ -37,10 +37,9 @@ impl From<Type> for u8 {
 impl TryFrom<u8> for Type {
     type Error = Error;
 
+    // The QUIC Bit may be greased by the peer, see [RFC 9287](https://www.rfc-editor.org/rfc/rfc9287.html),
+    // so it is not checked here.
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        if value & FIXED_BIT == 0 {
-            return Err(Error::InvalidFixedBit);
-        }
         match value & LONG_PACKET_TYPE_MASK {
             INITIAL_PACKET_TYPE => Ok(Type::Initial),
             ZERO_RTT_PACKET_TYPE => Ok(Type::ZeroRtt),
@@ -57,12 +56,12 @@ mod tests {
     #[test]
     fn test_try_from() {
         use super::Type;
-        use crate::packet::error::Error;
 
         assert_eq!(Type::try_from(0xc0), Ok(Type::Initial));
         assert_eq!(Type::try_from(0xd0), Ok(Type::ZeroRtt));
         assert_eq!(Type::try_from(0xe0), Ok(Type::Handshake));
         assert_eq!(Type::try_from(0xf0), Ok(Type::Retry));
-        assert_eq!(Type::try_from(0x00), Err(Error::InvalidFixedBit));
+        // 忽略被grease的QUIC Bit
+        assert_eq!(Type::try_from(0xa0), Ok(Type::Handshake));
     }
 }
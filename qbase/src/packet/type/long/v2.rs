@@ -0,0 +1,46 @@
+use super::v1::Type;
+use crate::packet::error::Error;
+
+// QUIC version 2 has the same long packet types as version 1, but they are
+// encoded with different bits, see [Long Header Packet Types](https://www.rfc-editor.org/rfc/rfc9369.html#name-long-header-packet-types)
+// of [RFC 9369](https://www.rfc-editor.org/rfc/rfc9369.html).
+const LONG_PACKET_TYPE_MASK: u8 = 0x30;
+const RETRY_PACKET_TYPE: u8 = 0x00;
+const INITIAL_PACKET_TYPE: u8 = 0x10;
+const ZERO_RTT_PACKET_TYPE: u8 = 0x20;
+const HANDSHAKE_PACKET_TYPE: u8 = 0x30;
+
+/// Encode the long packet type into the 3th and 4th bits of the first byte in version 2.
+pub(super) fn encode(ty: Type) -> u8 {
+    match ty {
+        Type::Retry => RETRY_PACKET_TYPE,
+        Type::Initial => INITIAL_PACKET_TYPE,
+        Type::ZeroRtt => ZERO_RTT_PACKET_TYPE,
+        Type::Handshake => HANDSHAKE_PACKET_TYPE,
+    }
+}
+
+/// Decode the long packet type from the first byte in version 2, the QUIC Bit may be greased.
+pub(super) fn decode(value: u8) -> Result<Type, Error> {
+    match value & LONG_PACKET_TYPE_MASK {
+        RETRY_PACKET_TYPE => Ok(Type::Retry),
+        INITIAL_PACKET_TYPE => Ok(Type::Initial),
+        ZERO_RTT_PACKET_TYPE => Ok(Type::ZeroRtt),
+        HANDSHAKE_PACKET_TYPE => Ok(Type::Handshake),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(0xc0), Ok(Type::Retry));
+        assert_eq!(decode(0xd0), Ok(Type::Initial));
+        assert_eq!(decode(0xe0), Ok(Type::ZeroRtt));
+        assert_eq!(decode(0xf0), Ok(Type::Handshake));
+        assert_eq!(decode(0x90), Ok(Type::Initial));
+    }
+}
@@ -6,7 +6,10 @@ use std::{
 
 use thiserror::Error;
 
-use super::varint::{be_varint, VarInt, WriteVarInt};
+use super::{
+    frame::{MaxStreamsFrame, StreamsBlockedFrame},
+    varint::{be_varint, VarInt, WriteVarInt},
+};
 
 /// Roles in the QUIC protocol, including client and server.
 ///
@@ -261,10 +264,11 @@ impl Iterator for NeedCreate {
 /// Local stream IDs management.
 #[derive(Debug)]
 struct LocalStreamIds {
-    role: Role,                 // Our role
-    max: [StreamId; 2],         // The maximum stream ID we can create
-    unallocated: [StreamId; 2], // The stream ID that we have not used
+    role: Role,                     // Our role
+    max: [StreamId; 2],             // The maximum stream ID we can create
+    unallocated: [StreamId; 2],     // The stream ID that we have not used
     wakers: [Option<Waker>; 2], // Used for waiting for the MaxStream frame notification from peer when we have exhausted the creation of stream IDs
+    blocked: [Option<StreamId>; 2], // The limit reported to peer by the STREAMS_BLOCKED frame
 }
 
 impl LocalStreamIds {
@@ -282,6 +286,7 @@ impl LocalStreamIds {
                 StreamId::new(role, Dir::Uni, 0),
             ],
             wakers: [None, None],
+            blocked: [None, None],
         }
     }
 
@@ -320,6 +325,48 @@ impl LocalStreamIds {
             Poll::Pending
         }
     }
+
+    fn streams_blocked(&mut self, dir: Dir) -> Option<StreamsBlockedFrame> {
+        let idx = dir as usize;
+        let max = self.max[idx];
+        if self.unallocated[idx] <= max || self.blocked[idx] == Some(max) {
+            return None;
+        }
+        self.blocked[idx] = Some(max);
+        Some(match dir {
+            Dir::Bi => StreamsBlockedFrame::Bi(max),
+            Dir::Uni => StreamsBlockedFrame::Uni(max),
+        })
+    }
+}
+
+/// The policy to replenish the streams the peer can create with the MAX_STREAMS frames, see
+/// [Section 4.6](https://www.rfc-editor.org/rfc/rfc9000.html#section-4.6) of RFC 9000.
+///
+/// The limit is raised by one for each stream of the peer closed, namely both of its sending and
+/// receiving parts have ended, so the streams of the peer open at a time never exceed the initial
+/// limit, `initial_max_streams_bidi` or `initial_max_streams_uni` of the local transport
+/// parameters. The default replenishes the streams as soon as each one is closed, without the
+/// limit over the connection lifetime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxStreamsPolicy {
+    /// A MAX_STREAMS frame is sent once this many streams of the peer are closed since the last
+    /// one, so the frames are fewer, at the cost of the streams the peer can open meanwhile.
+    /// 1 by default, which sends a frame for each stream closed.
+    pub batch: u64,
+    /// The limit is never raised beyond this, so the peer can create no more than this many
+    /// streams in each direction over the connection lifetime, unless the initial limit is larger.
+    /// [`None`] by default.
+    pub lifetime_limit: Option<u64>,
+}
+
+impl Default for MaxStreamsPolicy {
+    fn default() -> Self {
+        Self {
+            batch: 1,
+            lifetime_limit: None,
+        }
+    }
 }
 
 /// Remote stream IDs management.
@@ -329,7 +376,8 @@ struct RemoteStreamIds {
     max: [StreamId; 2],         // The maximum stream ID that peer can create
     unallocated: [StreamId; 2], // The stream ID that peer has not used
     concurrency: [u64; 2],      // The concurrency of streams that peer can create
-    wakers: [Option<Waker>; 2], // When the stream ID created by peer is close to the upper limit, wake up to update the upper limit in time.
+    closed: [u64; 2],           // The number of streams created by peer that have been closed
+    policy: MaxStreamsPolicy,   // When to raise the limit as the streams are closed
 }
 
 impl RemoteStreamIds {
@@ -347,7 +395,8 @@ impl RemoteStreamIds {
                 StreamId::new(role, Dir::Uni, 0),
             ],
             concurrency: [max_bi_streams, max_uni_streams],
-            wakers: [None, None],
+            closed: [0, 0],
+            policy: MaxStreamsPolicy::default(),
         }
     }
 
@@ -370,29 +419,28 @@ impl RemoteStreamIds {
             let start = *cur;
             *cur = unsafe { sid.next_unchecked() };
             log::debug!("unallocated: {:?}", self.unallocated[idx]);
-            let step = self.concurrency[idx] >> 1;
-            if sid.id() + step > max.id() {
-                if let Some(waker) = self.wakers[idx].take() {
-                    waker.wake();
-                }
-            }
             Ok(AcceptSid::New(NeedCreate { start, end: sid }))
         }
     }
 
-    fn poll_extend_sid(&mut self, cx: &mut Context<'_>, dir: Dir) -> Poll<Option<VarInt>> {
+    fn on_stream_closed(&mut self, dir: Dir) -> Option<MaxStreamsFrame> {
         let idx = dir as usize;
-        let step = self.concurrency[idx] >> 1;
-        if self.max[idx].id() > MAX_STREAM_ID {
-            Poll::Ready(None)
-        } else if self.unallocated[idx].id() + step >= self.max[idx].id() {
-            self.max[idx].saturating_add(step);
-            Poll::Ready(Some(self.max[idx].into()))
-        } else {
-            assert!(self.wakers[idx].is_none());
-            self.wakers[idx] = Some(cx.waker().clone());
-            Poll::Pending
+        self.closed[idx] += 1;
+        let lifetime_limit = self.policy.lifetime_limit.unwrap_or(MAX_STREAM_ID);
+        let target = (self.concurrency[idx] + self.closed[idx])
+            .min(lifetime_limit)
+            .min(MAX_STREAM_ID);
+        let max = self.max[idx].id();
+        // The rest of the streams are replenished at once upon reaching the lifetime limit
+        if target <= max || (target - max < self.policy.batch && target < lifetime_limit) {
+            return None;
         }
+        self.max[idx].saturating_add(target - max);
+        let max_streams = VarInt::from_u64(target).expect("stream limit must not exceed 2^60");
+        Some(match dir {
+            Dir::Bi => MaxStreamsFrame::Bi(max_streams),
+            Dir::Uni => MaxStreamsFrame::Uni(max_streams),
+        })
     }
 }
 
@@ -448,6 +496,16 @@ impl ArcLocalStreamIds {
     pub fn poll_alloc_sid(&self, cx: &mut Context<'_>, dir: Dir) -> Poll<Option<StreamId>> {
         self.0.lock().unwrap().poll_alloc_sid(cx, dir)
     }
+
+    /// Returns a [`StreamsBlockedFrame`] to be sent to the peer, if the stream IDs in the `dir`
+    /// direction are exhausted. It should be called once [`ArcLocalStreamIds::poll_alloc_sid`]
+    /// returned Pending, namely a new stream is wanted but not allowed.
+    ///
+    /// It's returned only once for each limit, until the peer raises the limit and the new stream
+    /// IDs are exhausted again.
+    pub fn streams_blocked(&self, dir: Dir) -> Option<StreamsBlockedFrame> {
+        self.0.lock().unwrap().streams_blocked(dir)
+    }
 }
 
 /// Shared remote stream IDs, mainly controls and monitors the stream IDs
@@ -513,19 +571,23 @@ impl ArcRemoteStreamIds {
         self.0.lock().unwrap().try_accept_sid(sid)
     }
 
-    /// Asynchronously extend the maximum stream ID that peer can create in the `dir` direction.
-    ///
-    /// In fact, when receiving the peer's ResetStreamFrame or sending a ResetStreamFrame,
-    /// it signifies the end of a stream.
-    /// At that point, it is necessary to check whether the stream was created by the peer
-    /// and whether the peer's maximum stream ID needs to be updated.
-    /// There is no need to spawn an asynchronous task to constantly check.
+    /// Set the policy to raise the maximum stream ID that peer can create, see [`MaxStreamsPolicy`].
+    pub fn set_policy(&self, policy: MaxStreamsPolicy) {
+        self.0.lock().unwrap().policy = policy;
+    }
+
+    /// Returns the policy to raise the maximum stream ID that peer can create.
+    pub fn policy(&self) -> MaxStreamsPolicy {
+        self.0.lock().unwrap().policy
+    }
+
+    /// Called when a stream created by peer in the `dir` direction is closed, that is, both its
+    /// sending part and receiving part have ended.
     ///
-    /// In the future, just update the peer's maximum stream ID limit immediately
-    /// when a stream created by the peer ends, and send a MAX_STREAMS frame to the peer.
-    #[deprecated]
-    pub fn poll_extend_sid(&self, cx: &mut Context<'_>, dir: Dir) -> Poll<Option<VarInt>> {
-        self.0.lock().unwrap().poll_extend_sid(cx, dir)
+    /// Returns a [`MaxStreamsFrame`] to be sent to the peer if the limit is raised according to
+    /// the [`MaxStreamsPolicy`].
+    pub fn on_stream_closed(&self, dir: Dir) -> Option<MaxStreamsFrame> {
+        self.0.lock().unwrap().on_stream_closed(dir)
     }
 }
 
@@ -618,6 +680,31 @@ mod tests {
         assert!(local.0.lock().unwrap().wakers[1].is_some());
     }
 
+    #[test]
+    fn test_streams_blocked() {
+        let StreamIds { local, remote: _ } = StreamIds::new(Role::Client, 0, 0);
+        let waker = empty_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(local.poll_alloc_sid(&mut cx, Dir::Uni).is_ready());
+        assert_eq!(local.streams_blocked(Dir::Bi), None);
+        assert_eq!(local.poll_alloc_sid(&mut cx, Dir::Uni), Poll::Pending);
+        assert_eq!(
+            local.streams_blocked(Dir::Uni),
+            Some(StreamsBlockedFrame::Uni(StreamId(2)))
+        );
+        assert_eq!(local.streams_blocked(Dir::Uni), None);
+        assert_eq!(local.streams_blocked(Dir::Bi), None);
+
+        // Blocked again after the limit is raised
+        local.permit_max_sid(Dir::Uni, 1);
+        assert!(local.poll_alloc_sid(&mut cx, Dir::Uni).is_ready());
+        assert_eq!(local.poll_alloc_sid(&mut cx, Dir::Uni), Poll::Pending);
+        assert_eq!(
+            local.streams_blocked(Dir::Uni),
+            Some(StreamsBlockedFrame::Uni(StreamId(6)))
+        );
+    }
+
     #[test]
     fn test_try_accept_sid() {
         let StreamIds { local: _, remote } = StreamIds::new(Role::Client, 10, 5);
@@ -661,4 +748,49 @@ mod tests {
         let result = remote.try_accept_sid(StreamId(65));
         assert_eq!(result, Err(ExceedLimitError(StreamId(65), StreamId(41))));
     }
+
+    #[test]
+    fn test_max_streams_policy() {
+        let StreamIds { local: _, remote } = StreamIds::new(Role::Client, 2, 2);
+        // Raise the limit for each stream closed by default
+        assert_eq!(
+            remote.on_stream_closed(Dir::Bi),
+            Some(MaxStreamsFrame::Bi(VarInt::from_u32(3)))
+        );
+        assert!(remote.try_accept_sid(StreamId(13)).is_ok());
+
+        // In batches, and never beyond the lifetime limit
+        remote.set_policy(MaxStreamsPolicy {
+            batch: 2,
+            lifetime_limit: Some(6),
+        });
+        assert_eq!(remote.on_stream_closed(Dir::Uni), None);
+        assert_eq!(
+            remote.on_stream_closed(Dir::Uni),
+            Some(MaxStreamsFrame::Uni(VarInt::from_u32(4)))
+        );
+        assert_eq!(remote.on_stream_closed(Dir::Uni), None);
+        assert_eq!(
+            remote.on_stream_closed(Dir::Uni),
+            Some(MaxStreamsFrame::Uni(VarInt::from_u32(6)))
+        );
+        for _ in 0..4 {
+            assert_eq!(remote.on_stream_closed(Dir::Uni), None);
+        }
+        assert_eq!(
+            remote.try_accept_sid(StreamId(31)),
+            Err(ExceedLimitError(StreamId(31), StreamId(27)))
+        );
+
+        // The rest of the streams are replenished at once upon reaching the lifetime limit
+        assert_eq!(remote.on_stream_closed(Dir::Bi), None);
+        assert_eq!(
+            remote.on_stream_closed(Dir::Bi),
+            Some(MaxStreamsFrame::Bi(VarInt::from_u32(5)))
+        );
+        assert_eq!(
+            remote.on_stream_closed(Dir::Bi),
+            Some(MaxStreamsFrame::Bi(VarInt::from_u32(6)))
+        );
+    }
 }
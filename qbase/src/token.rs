@@ -1,4 +1,8 @@
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+};
 
 use bytes::BufMut;
 use nom::{bytes::complete::take, IResult};
@@ -60,13 +64,75 @@ pub trait TokenSink: Send + Sync {
 }
 
 pub trait TokenProvider: Send + Sync {
-    fn provide_new_token(&self, server_name: &str) -> Vec<u8>;
+    /// The token to be sent in a NEW_TOKEN frame to the client at `remote` once the handshake is
+    /// done, an empty token means not to send it.
+    fn provide_new_token(&self, server_name: &str, remote: SocketAddr) -> Vec<u8>;
 
     fn provide_retry_token(&self, server_name: &str) -> Vec<u8>;
 
-    // A token sent in a NEW_TOKEN frame or a Retry packet MUST be constructed in
-    // a way that allows the server to identify how it was provided to a client
-    fn validate_token(&self, server_name: String, token: &[u8]) -> bool;
+    /// Whether the `token` carried by the Initial packet from `remote` was provided in a NEW_TOKEN
+    /// frame, so that the address can be considered validated.
+    ///
+    /// A token sent in a NEW_TOKEN frame or a Retry packet MUST be constructed in a way that
+    /// allows the server to identify how it was provided to a client.
+    fn validate_token(&self, server_name: String, remote: SocketAddr, token: &[u8]) -> bool;
+}
+
+/// The default number of tokens kept for each server by [`MemoryTokenSink`].
+const DEFAULT_TOKENS_PER_SERVER: usize = 4;
+
+/// A [`TokenSink`] keeping the tokens from NEW_TOKEN frames in memory, grouped by server name.
+///
+/// At most `capacity` tokens are kept for each server, the oldest ones are dropped. The latest token
+/// is taken out for a new connection, so that each token is used only once, which prevents the
+/// connections from being linked by the token, see
+/// [Section 8.1.3](https://www.rfc-editor.org/rfc/rfc9000#section-8.1.3) of RFC 9000.
+#[derive(Debug)]
+pub struct MemoryTokenSink {
+    capacity: usize,
+    tokens: Mutex<HashMap<String, VecDeque<Vec<u8>>>>,
+}
+
+impl MemoryTokenSink {
+    /// Create a sink keeping at most `capacity` tokens for each server.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tokens: Mutex::default(),
+        }
+    }
+}
+
+impl Default for MemoryTokenSink {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_TOKENS_PER_SERVER)
+    }
+}
+
+impl TokenSink for MemoryTokenSink {
+    fn sink(&self, server_name: &str, token: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut tokens = self.tokens.lock().unwrap();
+        let tokens = tokens.entry(server_name.to_owned()).or_default();
+        if tokens.len() == self.capacity {
+            tokens.pop_front();
+        }
+        tokens.push_back(token);
+    }
+
+    fn get_token(&self, server_name: &str) -> Vec<u8> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let Some(server_tokens) = tokens.get_mut(server_name) else {
+            return Vec::new();
+        };
+        let token = server_tokens.pop_back().unwrap_or_default();
+        if server_tokens.is_empty() {
+            tokens.remove(server_name);
+        }
+        token
+    }
 }
 
 #[derive(Clone)]
@@ -112,6 +178,11 @@ impl ReceiveFrame<NewTokenFrame> for ArcTokenRegistry {
     fn recv_frame(&self, frame: &NewTokenFrame) -> Result<Self::Output, crate::error::Error> {
         let guard = self.0.lock().unwrap();
         match &*guard {
+            TokenRegistry::Client(_) if frame.token.is_empty() => Err(Error::new(
+                ErrorKind::FrameEncoding,
+                frame.frame_type(),
+                "Received NewTokenFrame with an empty token",
+            )),
             TokenRegistry::Client((server_name, client)) => {
                 client.sink(server_name, frame.token.clone());
                 Ok(())
@@ -136,7 +207,7 @@ impl TokenSink for DefaultTokenRegistry {
 }
 
 impl TokenProvider for DefaultTokenRegistry {
-    fn provide_new_token(&self, _: &str) -> Vec<u8> {
+    fn provide_new_token(&self, _: &str, _: SocketAddr) -> Vec<u8> {
         Vec::new()
     }
 
@@ -144,7 +215,7 @@ impl TokenProvider for DefaultTokenRegistry {
         Vec::new()
     }
 
-    fn validate_token(&self, _: String, _: &[u8]) -> bool {
+    fn validate_token(&self, _: String, _: SocketAddr, _: &[u8]) -> bool {
         false
     }
 }
@@ -183,6 +254,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_memory_token_sink() {
+        use super::{MemoryTokenSink, TokenSink};
+
+        let sink = MemoryTokenSink::with_capacity(2);
+        assert!(sink.get_token("example.com").is_empty());
+        sink.sink("example.com", vec![1]);
+        sink.sink("example.com", vec![2]);
+        sink.sink("example.com", vec![3]);
+        sink.sink("example.org", vec![4]);
+
+        assert_eq!(sink.get_token("example.com"), vec![3]);
+        assert_eq!(sink.get_token("example.com"), vec![2]);
+        assert!(sink.get_token("example.com").is_empty());
+        assert_eq!(sink.get_token("example.org"), vec![4]);
+    }
+
     #[test]
     fn test_write_reset_token() {
         use super::WriteResetToken;
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+/// The strategy to acknowledge the ack-eliciting packets received on a path.
+///
+/// The Initial and Handshake packets are always acknowledged immediately. The peer's
+/// ACK_FREQUENCY frame, if the extension is negotiated, overrides the delay, the threshold and the
+/// reordering behavior in the Data space, unless [`AckPolicy::ack_every_packet`] is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckPolicy {
+    /// The maximum time to delay the ACK of an ack-eliciting packet. It should not exceed the
+    /// max_ack_delay transport parameter advertised to the peer, [`None`] means the default delay
+    /// of the congestion controller.
+    pub max_ack_delay: Option<Duration>,
+    /// An ACK frame is sent without delay once more than this many ack-eliciting packets are
+    /// received since the last acknowledgment, 1 by default as RFC 9000 recommends.
+    pub ack_eliciting_threshold: u64,
+    /// Acknowledge the out-of-order ack-eliciting packets immediately.
+    pub immediate_on_reordering: bool,
+    /// Acknowledge the ack-eliciting packets marked with ECN-CE immediately, so that the peer
+    /// reacts to the congestion in time.
+    pub immediate_on_ce: bool,
+    /// Acknowledge every ack-eliciting packet immediately, for the latency-critical uses at the
+    /// cost of more ACK frames.
+    pub ack_every_packet: bool,
+}
+
+impl Default for AckPolicy {
+    fn default() -> Self {
+        Self {
+            max_ack_delay: None,
+            ack_eliciting_threshold: 1,
+            immediate_on_reordering: true,
+            immediate_on_ce: true,
+            ack_every_packet: false,
+        }
+    }
+}
+
+impl AckPolicy {
+    /// Acknowledge every ack-eliciting packet immediately, see [`AckPolicy::ack_every_packet`].
+    pub fn every_packet() -> Self {
+        Self {
+            max_ack_delay: Some(Duration::ZERO),
+            ack_eliciting_threshold: 0,
+            ack_every_packet: true,
+            ..Self::default()
+        }
+    }
+}
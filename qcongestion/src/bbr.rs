@@ -3,10 +3,13 @@ use std::{
     time::{Duration, Instant},
 };
 
+use rand::{rngs::StdRng, SeedableRng};
+
 use crate::{
     congestion::{AckedPkt, Algorithm, SentPkt, MSS},
     delivery_rate::Rate,
     min_max::MinMax,
+    CongestionState, CongestionWindowConfig,
 };
 
 mod model;
@@ -36,8 +39,6 @@ pub(crate) const INITIAL_CWND: u64 = 80 * MSS as u64;
 // The minimal cwnd value BBR tries to target using: 4 packets, or 4 * SMSS
 const MIN_PIPE_CWND_PKTS: usize = 4;
 
-const MINIMUM_WINDOW_PACKETS: usize = 2;
-
 // BBR State
 //
 // https://datatracker.ietf.org/doc/html/draft-cardwell-iccrg-bbr-congestion-control-00#section-3.4
@@ -61,6 +62,10 @@ pub(crate) struct Bbr {
     // Cwnd: The transport sender's congestion window, which limits the
     // amount of data in flight.
     cwnd: u64,
+    // The congestion window before any feedback, InitialCwnd.
+    initial_cwnd: u64,
+    // The minimum congestion window, the cwnd never drops below it.
+    min_cwnd: u64,
     // BBR.BtlBw: BBR's estimated bottleneck bandwidth available to the transport
     // flow, estimated from the maximum delivery rate sample in a sliding window.
     btlbw: u64,
@@ -114,12 +119,16 @@ pub(crate) struct Bbr {
     cycle_stamp: Instant,
     // Current index of pacing_gain_cycle[].
     cycle_index: usize,
+    // Picks the phase to start the gain cycling.
+    rng: StdRng,
     // The upper bound on the volume of data BBR allows in flight.
     target_cwnd: u64,
     // Whether in the recovery mode.
     in_recovery: bool,
     // Time of the last recovery event starts.
     recovery_epoch_start: Option<Instant>,
+    // The round in which the congestion window was last reduced for the ECN-CE marks.
+    ecn_ce_round: Option<u64>,
     // Ack time.
     ack_time: Instant,
     // Newly marked lost data size in bytes.
@@ -141,13 +150,21 @@ pub(crate) struct Bbr {
 }
 
 impl Bbr {
+    #[cfg(test)]
     pub fn new() -> Self {
+        Self::with_window(&CongestionWindowConfig::default())
+    }
+
+    pub fn with_window(window: &CongestionWindowConfig) -> Self {
         let now = Instant::now();
+        let initial_cwnd = window.initial(INITIAL_CWND);
         let mut bbr = Bbr {
             state: BbrStateMachine::Startup,
             pacing_rate: 0,
             send_quantum: 0,
-            cwnd: INITIAL_CWND,
+            cwnd: initial_cwnd,
+            initial_cwnd,
+            min_cwnd: window.minimum(),
             btlbw: 0,
             btlbwfilter: MinMax::default(),
             delivery_rate: Rate::default(),
@@ -169,9 +186,11 @@ impl Bbr {
             is_idle_restart: false,
             cycle_stamp: now,
             cycle_index: 0,
+            rng: StdRng::from_entropy(),
             target_cwnd: 0,
             in_recovery: false,
             recovery_epoch_start: None,
+            ecn_ce_round: None,
             ack_time: now,
             newly_lost_bytes: 0,
             newly_acked_bytes: 0,
@@ -184,6 +203,13 @@ impl Bbr {
         bbr.on_connection_init();
         bbr
     }
+
+    /// Seeds the random phase to start the gain cycling, so the simulations are reproducible.
+    #[cfg(test)]
+    pub(crate) fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
 }
 
 impl Algorithm for Bbr {
@@ -213,7 +239,7 @@ impl Algorithm for Bbr {
             self.packet_delivered = self
                 .packet_delivered
                 .max(self.delivery_rate.delivered() as u64);
-            self.update_model_and_state(&mut ack);
+            self.update_model_and_state(&mut ack, now);
         }
 
         self.delivery_rate.generate_rate_sample();
@@ -232,11 +258,38 @@ impl Algorithm for Bbr {
         self.update_control_parameters();
     }
 
-    fn on_congestion_event(&mut self, _: &SentPkt, _: Instant) {
+    fn on_congestion_event(&mut self, lost: &SentPkt, _: Instant) {
+        // The lost packet is no longer in flight, or the ProbeRTT never drains the inflight
+        self.bytes_in_flight = self.bytes_in_flight.saturating_sub(lost.size as u64);
         // todo: enter_recovery
         // update newly lost bytes, set BBR.packet_conservation = true
     }
 
+    fn on_ecn_ce(&mut self, _: &SentPkt, _: Instant) {
+        // The marks mean the queue at the bottleneck is building up, at most once per round:
+        // the pipe is filled, and the window drops to the estimated BDP
+        if self.ecn_ce_round == Some(self.round_count) {
+            return;
+        }
+        self.ecn_ce_round = Some(self.round_count);
+        self.is_filled_pipe = true;
+        self.cwnd = self.cwnd.min(self.inflight(1.0)).max(self.min_pipe_cwnd());
+    }
+
+    fn on_app_limited(&mut self, app_limited: bool) {
+        // The packets sent until the ones in flight are acknowledged are marked as app-limited,
+        // so their delivery rates don't lower the estimated bandwidth
+        if app_limited {
+            self.delivery_rate.update_app_limited(true);
+        }
+    }
+
+    fn on_persistent_congestion(&mut self, _: Instant) {
+        // Like upon the retransmission timeout, see 4.2.3.4 Modulating cwnd in Loss Recovery
+        self.save_cwnd();
+        self.cwnd = self.min_cwnd;
+    }
+
     fn cwnd(&self) -> u64 {
         self.cwnd
     }
@@ -244,6 +297,20 @@ impl Algorithm for Bbr {
     fn pacing_rate(&self) -> Option<u64> {
         Some(self.pacing_rate)
     }
+
+    fn delivery_rate(&self) -> Option<u64> {
+        Some(self.btlbw)
+    }
+
+    fn state(&self) -> CongestionState {
+        if self.in_recovery {
+            CongestionState::Recovery
+        } else if self.state == BbrStateMachine::Startup {
+            CongestionState::SlowStart
+        } else {
+            CongestionState::CongestionAvoidance
+        }
+    }
 }
 
 impl Bbr {
@@ -253,13 +320,13 @@ impl Bbr {
     }
 
     // 3.5.2.  Per-ACK Steps
-    fn update_model_and_state(&mut self, ack: &mut AckedPkt) {
+    fn update_model_and_state(&mut self, ack: &mut AckedPkt, now: Instant) {
         self.update_btlbw(ack);
-        self.check_cycle_phase();
+        self.check_cycle_phase(now);
         self.check_full_pipe();
-        self.check_drain();
-        self.update_rtprop();
-        self.check_probe_rtt();
+        self.check_drain(now);
+        self.update_rtprop(now);
+        self.check_probe_rtt(now);
     }
 
     fn update_control_parameters(&mut self) {
@@ -357,6 +424,60 @@ mod tests {
         assert_eq!(bbr.pacing_rate, (bbr.btlbw as f64 * bbr.pacing_gain) as u64);
     }
 
+    #[test]
+    fn test_bbr_congestion_event() {
+        let mut bbr = super::Bbr::new();
+        let now = Instant::now();
+        let mut sent = SentPkt {
+            size: MSS,
+            time_sent: now,
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            bbr.on_sent(&mut sent, MSS, now);
+        }
+        // 丢失的包不再计入在途的数据
+        bbr.on_congestion_event(&sent, now);
+        assert_eq!(bbr.bytes_in_flight, 2 * MSS as u64);
+        for _ in 0..3 {
+            bbr.on_congestion_event(&sent, now);
+        }
+        assert_eq!(bbr.bytes_in_flight, 0);
+    }
+
+    #[test]
+    fn test_bbr_ecn_ce() {
+        let mut bbr = super::Bbr::new();
+        let mut now = Instant::now();
+        let rtt = Duration::from_millis(100);
+        simulate_round_trip(&mut bbr, now, rtt, 0, 10, MSS);
+        now += Duration::from_secs(1);
+        simulate_round_trip(&mut bbr, now, rtt, 10, 40, MSS);
+        assert_eq!(bbr.state, BbrStateMachine::Startup);
+
+        let bdp = bbr.inflight(1.0);
+        assert!(bbr.cwnd > bdp);
+        let marked = SentPkt {
+            pn: 39,
+            size: MSS,
+            time_sent: now,
+            ..Default::default()
+        };
+        bbr.on_ecn_ce(&marked, now + rtt);
+        assert!(bbr.is_filled_pipe);
+        assert_eq!(bbr.cwnd, bdp);
+
+        // Only once per round
+        bbr.cwnd = 2 * bdp;
+        bbr.on_ecn_ce(&marked, now + rtt);
+        assert_eq!(bbr.cwnd, 2 * bdp);
+
+        // Leave the startup on the next ACK
+        now += Duration::from_secs(1);
+        simulate_round_trip(&mut bbr, now, rtt, 40, 50, MSS);
+        assert_ne!(bbr.state, BbrStateMachine::Startup);
+    }
+
     pub(super) fn simulate_round_trip(
         bbr: &mut super::Bbr,
         start_time: Instant,
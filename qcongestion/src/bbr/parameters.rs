@@ -4,17 +4,14 @@
 
 use std::time::Duration;
 
-use super::{
-    Bbr, BbrStateMachine, INITIAL_CWND, MINIMUM_WINDOW_PACKETS, MIN_PIPE_CWND_PKTS, MSS,
-    SEND_QUANTUM_THRESHOLD_PACING_RATE,
-};
+use super::{Bbr, BbrStateMachine, MIN_PIPE_CWND_PKTS, MSS, SEND_QUANTUM_THRESHOLD_PACING_RATE};
 use crate::rtt::INITIAL_RTT;
 
 impl Bbr {
     // 4.2.1.  Pacing Rate
     pub(super) fn init_pacing_rate(&mut self) {
         let srtt = INITIAL_RTT;
-        let nominal_bandwidth = INITIAL_CWND as f64 / srtt.as_secs_f64();
+        let nominal_bandwidth = self.initial_cwnd as f64 / srtt.as_secs_f64();
         self.pacing_rate = (self.pacing_gain * nominal_bandwidth) as u64;
     }
 
@@ -45,7 +42,7 @@ impl Bbr {
     // 4.2.3.2.  Target cwnd
     pub fn inflight(&self, gain: f64) -> u64 {
         if self.rtprop == Duration::MAX {
-            return INITIAL_CWND;
+            return self.initial_cwnd;
         }
 
         let quanta = 3 * self.send_quantum;
@@ -75,7 +72,7 @@ impl Bbr {
             self.cwnd = self
                 .cwnd
                 .saturating_sub(self.newly_lost_bytes)
-                .max((MSS * MINIMUM_WINDOW_PACKETS) as u64);
+                .max(self.min_cwnd);
         }
 
         if self.packet_conservation {
@@ -101,7 +98,7 @@ impl Bbr {
             if self.is_filled_pipe {
                 self.cwnd = self.target_cwnd.min(self.cwnd + self.newly_acked_bytes);
             } else if self.cwnd < self.target_cwnd
-                || self.delivery_rate.delivered() < INITIAL_CWND as usize
+                || self.delivery_rate.delivered() < self.initial_cwnd as usize
             {
                 self.cwnd += self.newly_acked_bytes;
             }
@@ -113,7 +110,7 @@ impl Bbr {
 
     /// The minimal cwnd value BBR tries to target, in bytes
     pub(super) fn min_pipe_cwnd(&self) -> u64 {
-        (MIN_PIPE_CWND_PKTS * MSS) as u64
+        ((MIN_PIPE_CWND_PKTS * MSS) as u64).max(self.min_cwnd)
     }
 }
 
@@ -121,6 +118,7 @@ impl Bbr {
 mod tests {
 
     use super::*;
+    use crate::bbr::INITIAL_CWND;
 
     #[test]
     fn test_init_pacing_rate() {
@@ -68,43 +68,42 @@ impl Bbr {
         self.cwnd_gain = HIGH_GAIN; // maintain cwnd
     }
 
-    pub(super) fn check_drain(&mut self) {
+    pub(super) fn check_drain(&mut self, now: Instant) {
         if self.state == BbrStateMachine::Startup && self.is_filled_pipe {
             self.enter_drain()
         }
         if self.state == BbrStateMachine::Drain && self.bytes_in_flight <= self.inflight(1.0) {
-            self.enter_probe_bw();
+            self.enter_probe_bw(now);
         }
     }
 
     // 4.3.4.  ProbeBW
-    pub fn enter_probe_bw(&mut self) {
+    pub fn enter_probe_bw(&mut self, now: Instant) {
         self.state = BbrStateMachine::ProbeBW;
         self.pacing_gain = 1.0;
         self.cwnd_gain = 2.0;
 
         // 随机从一个阶段开始
-        self.cycle_index = GAIN_CYCLE_LEN - 1 - rand::thread_rng().gen_range(0..GAIN_CYCLE_LEN - 1);
-        self.advance_cycle_phase()
+        self.cycle_index = GAIN_CYCLE_LEN - 1 - self.rng.gen_range(0..GAIN_CYCLE_LEN - 1);
+        self.advance_cycle_phase(now)
     }
 
     // On each ACK BBR runs BBRCheckCyclePhase(), to see if it's time to
     // advance to the next gain cycle phase:
-    pub(super) fn check_cycle_phase(&mut self) {
-        if self.state == BbrStateMachine::ProbeBW && self.is_next_cycle_phase() {
-            self.advance_cycle_phase();
+    pub(super) fn check_cycle_phase(&mut self, now: Instant) {
+        if self.state == BbrStateMachine::ProbeBW && self.is_next_cycle_phase(now) {
+            self.advance_cycle_phase(now);
         }
     }
 
-    fn advance_cycle_phase(&mut self) {
-        self.cycle_stamp = Instant::now();
+    fn advance_cycle_phase(&mut self, now: Instant) {
+        self.cycle_stamp = now;
         self.cycle_index = (self.cycle_index + 1) % GAIN_CYCLE_LEN;
         self.pacing_gain = PACING_GAIN_CYCLE[self.cycle_index];
     }
 
     // 是否要进入下一阶段
-    fn is_next_cycle_phase(&mut self) -> bool {
-        let now = Instant::now();
+    fn is_next_cycle_phase(&mut self, now: Instant) -> bool {
         let is_full_length = now.saturating_duration_since(self.cycle_stamp) > self.rtprop;
 
         // pacing_gain == 1.0 持续 rtprop
@@ -135,7 +134,7 @@ impl Bbr {
     }
 
     // 4.3.5.  ProbeRTT
-    pub(super) fn check_probe_rtt(&mut self) {
+    pub(super) fn check_probe_rtt(&mut self, now: Instant) {
         if self.state != BbrStateMachine::ProbeRTT
             && self.is_rtprop_expired
             && !self.is_idle_restart
@@ -146,7 +145,7 @@ impl Bbr {
         }
 
         if self.state == BbrStateMachine::ProbeRTT {
-            self.handle_probe_rtt();
+            self.handle_probe_rtt(now);
         }
 
         self.is_idle_restart = false;
@@ -159,11 +158,10 @@ impl Bbr {
         self.cwnd_gain = 1.0;
     }
 
-    fn handle_probe_rtt(&mut self) {
+    fn handle_probe_rtt(&mut self, now: Instant) {
         // C.app_limited = (BW.delivered + packets_in_flight) ? : 1
         self.delivery_rate.update_app_limited(true);
 
-        let now = Instant::now();
         if let Some(probe_rtt_done_stamp) = self.probe_rtt_done_stamp {
             if self.is_round_start {
                 self.probe_rtt_round_done = true;
@@ -182,9 +180,9 @@ impl Bbr {
         }
     }
 
-    fn exit_probe_rtt(&mut self, _: Instant) {
+    fn exit_probe_rtt(&mut self, now: Instant) {
         if self.is_filled_pipe {
-            self.enter_probe_bw();
+            self.enter_probe_bw(now);
         } else {
             self.enter_startup();
         }
@@ -251,13 +249,13 @@ mod tests {
         bbr.init();
         bbr.is_filled_pipe = true;
         bbr.bytes_in_flight = 100;
-        bbr.check_drain();
+        bbr.check_drain(Instant::now());
         assert_eq!(bbr.state, BbrStateMachine::Drain);
 
         let mut bbr = super::Bbr::new();
         bbr.init();
         bbr.is_filled_pipe = true;
-        bbr.check_drain();
+        bbr.check_drain(Instant::now());
         assert_eq!(bbr.state, BbrStateMachine::ProbeBW);
     }
 
@@ -265,7 +263,7 @@ mod tests {
     fn test_bbr_enter_probe_bw() {
         let mut bbr = super::Bbr::new();
         bbr.init();
-        bbr.enter_probe_bw();
+        bbr.enter_probe_bw(Instant::now());
         assert_eq!(bbr.state, BbrStateMachine::ProbeBW);
         assert_eq!(bbr.cwnd_gain, 2.0);
     }
@@ -275,11 +273,11 @@ mod tests {
         let mut bbr = super::Bbr::new();
         bbr.init();
         bbr.cycle_index = 0;
-        bbr.advance_cycle_phase();
+        bbr.advance_cycle_phase(Instant::now());
         assert_eq!(bbr.pacing_gain, 0.75);
 
         bbr.cycle_index = 7;
-        bbr.advance_cycle_phase();
+        bbr.advance_cycle_phase(Instant::now());
         assert_eq!(bbr.pacing_gain, 1.25)
     }
 
@@ -287,21 +285,21 @@ mod tests {
     fn test_bbr_is_next_cycle_phase() {
         let mut bbr = super::Bbr::new();
         bbr.init();
-        bbr.enter_probe_bw();
+        bbr.enter_probe_bw(Instant::now());
         let now = Instant::now();
 
         bbr.pacing_gain = 1.0;
         bbr.cycle_stamp = now - Duration::from_secs(1);
-        assert!(bbr.is_next_cycle_phase());
+        assert!(bbr.is_next_cycle_phase(now));
 
         bbr.pacing_gain = 0.75;
         bbr.cycle_stamp = now - Duration::from_secs(1);
         bbr.prior_bytes_in_flight = 100;
-        assert!(bbr.is_next_cycle_phase());
+        assert!(bbr.is_next_cycle_phase(now));
 
         bbr.pacing_gain = 1.25;
         bbr.cycle_stamp = now - Duration::from_secs(1);
-        assert!(bbr.is_next_cycle_phase());
+        assert!(bbr.is_next_cycle_phase(now));
     }
 
     #[test]
@@ -6,28 +6,82 @@ use std::{
     time::{Duration, Instant},
 };
 
-use qbase::frame::{AckFrame, EcnCounts};
+use qbase::{
+    frame::{AckFrame, AckFrequencyFrame, EcnCodepoint},
+    varint::VarInt,
+};
 use qrecovery::space::Epoch;
 
 use crate::{
-    bbr::{self, INITIAL_CWND},
+    bbr,
+    cubic::Cubic,
+    ecn::Ecn,
+    loss_detection::Reordering,
     new_reno::NewReno,
     pacing::{self, Pacer},
     rtt::{ArcRtt, INITIAL_RTT},
-    MayLoss, RetirePktRecord,
+    AckPolicy, CongestionState, CongestionWindowConfig, LossDetectionConfig, MayLoss, Observer,
+    RetirePktRecord, SendRateLimit,
 };
 
 const K_GRANULARITY: Duration = Duration::from_millis(1);
-const K_PACKET_THRESHOLD: usize = 3;
+const K_PERSISTENT_CONGESTION_THRESHOLD: u32 = 3;
 const MAX_SENT_DELAY: Duration = Duration::from_millis(30);
+// The number of ack-eliciting packets sent upon the PTO, see Section 6.2.4 of RFC 9002.
+const MAX_PTO_PROBES: usize = 2;
+// The peer is asked to acknowledge this many times per congestion window at least.
+const ACKS_PER_CWND: u64 = 32;
+// The maximum number of ack-eliciting packets the peer is asked to receive before acknowledging.
+const MAX_ACK_ELICITING_THRESHOLD: u64 = 10;
+// The maximum ACK delay the peer is asked to use, the default max_ack_delay of RFC 9000.
+const MAX_REQUEST_ACK_DELAY: Duration = Duration::from_millis(25);
+// The max_ack_delay and ack_delay_exponent of the peer before its transport parameters are received,
+// the defaults of RFC 9000.
+const DEFAULT_PEER_MAX_ACK_DELAY: Duration = Duration::from_millis(25);
+const DEFAULT_PEER_ACK_DELAY_EXPONENT: u8 = 3;
 
 ///  default datagram size in bytes.
 pub const MSS: usize = 1200;
 
 /// The [`CongestionAlgorithm`] enum represents different congestion control algorithms that can be used.
+///
+/// Each path of a connection runs its own instance of the algorithm.
+#[derive(Clone, Default)]
 pub enum CongestionAlgorithm {
+    /// BBR, see [BBR Congestion Control](https://datatracker.ietf.org/doc/draft-ietf-ccwg-bbr/).
+    #[default]
     Bbr,
+    /// NewReno, see [Section 7](https://www.rfc-editor.org/rfc/rfc9002.html#section-7) of RFC 9002.
     NewReno,
+    /// CUBIC, see [RFC 9438](https://www.rfc-editor.org/rfc/rfc9438.html).
+    ///
+    /// It grows the window faster than NewReno on the links with large bandwidth-delay products.
+    Cubic,
+    /// A custom algorithm, the factory is called to create an instance for each path, the
+    /// [`CongestionWindowConfig`] does not apply to it.
+    Custom(Arc<dyn Fn() -> Box<dyn Algorithm> + Send + Sync>),
+}
+
+impl std::fmt::Debug for CongestionAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bbr => write!(f, "Bbr"),
+            Self::NewReno => write!(f, "NewReno"),
+            Self::Cubic => write!(f, "Cubic"),
+            Self::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+impl CongestionAlgorithm {
+    fn new_instance(&self, window: &CongestionWindowConfig) -> Box<dyn Algorithm> {
+        match self {
+            CongestionAlgorithm::Bbr => Box::new(bbr::Bbr::with_window(window)),
+            CongestionAlgorithm::NewReno => Box::new(NewReno::with_window(window)),
+            CongestionAlgorithm::Cubic => Box::new(Cubic::with_window(window)),
+            CongestionAlgorithm::Custom(factory) => factory(),
+        }
+    }
 }
 
 /// Imple RFC 9002 Appendix A. Loss Recovery
@@ -40,7 +94,13 @@ pub struct CongestionController {
     // The number of times a PTO has been sent without receiving an acknowledgment.
     // Use to pto backoff
     pto_count: u32,
+    // The thresholds of the loss detection and the backoff of the PTO.
+    loss_detection: LossDetectionConfig,
+    // The maximum time to delay the acknowledgments sent.
     max_ack_delay: Duration,
+    // The max_ack_delay and ack_delay_exponent transport parameters of the peer.
+    peer_max_ack_delay: Duration,
+    peer_ack_delay_exponent: u8,
     // The time the most recent ack-eliciting packet was sent.
     time_of_last_ack_eliciting_packet: [Option<Instant>; Epoch::count()],
     // The largest packet number acknowledged in the packet number space so far.
@@ -52,6 +112,8 @@ pub struct CongestionController {
     sent_packets: [VecDeque<SentPkt>; Epoch::count()],
     // pacer is used to control the burst rate
     pacer: pacing::Pacer,
+    // The maximum rate to send, shared by the paths of the connection.
+    send_rate_limit: SendRateLimit,
     // The time the last packet was sent.
     last_sent_time: Instant,
     // Records of received packets for each epoch.
@@ -66,20 +128,37 @@ pub struct CongestionController {
     has_handshake_keys: bool,
     // Whether the handshake is complete.
     is_handshake_done: bool,
+    // The sum of the size in bytes of all sent packets that are in flight.
+    bytes_in_flight: usize,
+    // The PMTU probes that have been acknowledged or declared lost, as (pn, is_acked).
+    mtu_probe_outcomes: Vec<(u64, bool)>,
+    // The number of probe packets to send in each space upon the PTO.
+    pto_probes: [usize; Epoch::count()],
+    // The reordering observed, which the thresholds of the loss detection adapt to.
+    reordering: Reordering,
+    // The ECN validation of the path.
+    ecn: Ecn,
+    // The min_ack_delay of the peer, the ACK_FREQUENCY frames are sent only if the peer supports it.
+    peer_min_ack_delay: Option<Duration>,
+    // The last ACK_FREQUENCY frame requested and the time it's requested.
+    ack_frequency: Option<(AckFrequencyFrame, Instant)>,
+    // The hooks to observe the controller, and the congestion window and the state last reported.
+    observer: Option<Arc<dyn Observer>>,
+    observed: (u64, CongestionState),
 }
 
 impl CongestionController {
     // A.4. Initialization
     fn new(
         algorithm: CongestionAlgorithm,
+        window: CongestionWindowConfig,
         max_ack_delay: Duration,
         loss: [Box<dyn MayLoss>; 3],
         retire: [Box<dyn RetirePktRecord>; 3],
     ) -> Self {
-        let algorithm: Box<dyn Algorithm> = match algorithm {
-            CongestionAlgorithm::Bbr => Box::new(bbr::Bbr::new()),
-            CongestionAlgorithm::NewReno => Box::new(NewReno::new()),
-        };
+        let algorithm = algorithm.new_instance(&window);
+        let cwnd = algorithm.cwnd();
+        let state = algorithm.state();
 
         let now = Instant::now();
         CongestionController {
@@ -87,7 +166,10 @@ impl CongestionController {
             rtt: ArcRtt::new(),
             loss_timer: LossDetectionTimer::default(),
             max_ack_delay,
+            peer_max_ack_delay: DEFAULT_PEER_MAX_ACK_DELAY,
+            peer_ack_delay_exponent: DEFAULT_PEER_ACK_DELAY_EXPONENT,
             pto_count: 0,
+            loss_detection: LossDetectionConfig::default(),
             time_of_last_ack_eliciting_packet: [None, None, None],
             largest_acked_packet: [None, None, None],
             loss_time: [None, None, None],
@@ -97,13 +179,23 @@ impl CongestionController {
                 RcvdRecords::new(Epoch::Handshake),
                 RcvdRecords::new(Epoch::Data),
             ],
-            pacer: Pacer::new(INITIAL_RTT, INITIAL_CWND, MSS, now, None),
+            pacer: Pacer::new(INITIAL_RTT, cwnd, MSS, now, None),
+            send_rate_limit: SendRateLimit::default(),
             last_sent_time: now,
             send_waker: None,
             loss_handlers: loss,
             retire_handlers: retire,
             has_handshake_keys: false,
             is_handshake_done: false,
+            bytes_in_flight: 0,
+            mtu_probe_outcomes: Vec::new(),
+            pto_probes: [0; Epoch::count()],
+            reordering: Reordering::default(),
+            ecn: Ecn::new(),
+            peer_min_ack_delay: None,
+            ack_frequency: None,
+            observer: None,
+            observed: (cwnd, state),
         }
     }
 
@@ -118,9 +210,13 @@ impl CongestionController {
         now: Instant,
     ) {
         let mut sent = SentPkt::new(pn, sent_bytes, now);
+        sent.is_ecn_marked = self.ecn.on_sent();
         if in_flight {
+            sent.in_flight = true;
+            self.bytes_in_flight += sent_bytes;
             if ack_eliciting {
                 self.time_of_last_ack_eliciting_packet[space] = Some(now);
+                self.pto_probes[space] = self.pto_probes[space].saturating_sub(1);
             }
             self.algorithm.on_sent(&mut sent, sent_bytes, now);
             self.set_loss_timer();
@@ -132,6 +228,7 @@ impl CongestionController {
         }
         self.sent_packets[space].push_back(sent);
         self.pacer.on_sent(sent_bytes as u64);
+        self.send_rate_limit.on_sent(sent_bytes as u64);
     }
 
     // A.6. On Receiving a Datagram
@@ -148,29 +245,43 @@ impl CongestionController {
     pub fn on_ack_rcvd(&mut self, space: Epoch, ack_frame: &AckFrame, now: Instant) {
         let largest_acked: u64 = ack_frame.largest.into();
 
+        let is_largest_increased =
+            self.largest_acked_packet[space].is_none_or(|largest| largest_acked > largest);
+        // The packets acknowledged after the largest one before are reordered
+        let (newly_acked_packets, latest_rtt, newly_acked_marked) =
+            self.get_newly_acked_packets(space, ack_frame, now);
         self.largest_acked_packet[space] =
             Some(largest_acked.max(self.largest_acked_packet[space].unwrap_or(0)));
-
-        let (newly_acked_packets, latest_rtt) = self.get_newly_acked_packets(space, ack_frame);
         if newly_acked_packets.is_empty() {
             return;
         }
 
-        let ack_delay = Duration::from_millis(ack_frame.delay.into());
         if let Some(latest_rtt) = latest_rtt {
-            self.rtt.update(latest_rtt, ack_delay);
+            let ack_delay = self.ack_delay(space, ack_frame);
+            self.rtt
+                .update(latest_rtt, ack_delay, self.peer_max_ack_delay(), now);
         }
 
-        // Process ECN information if present.
-        if let Some(ecn) = ack_frame.ecn {
-            self.process_ecn(space, ecn)
+        // Process ECN information, the ACK frames reordered are ignored.
+        if is_largest_increased {
+            self.process_ecn(
+                space,
+                ack_frame,
+                &newly_acked_packets,
+                newly_acked_marked,
+                now,
+            );
         }
 
-        let lost_packets = self.remove_loss_packets(space, now);
+        let (lost_packets, persistent_congestion) = self.remove_loss_packets(space, now);
         if !lost_packets.is_empty() {
-            self.on_packets_lost(lost_packets.into_iter(), space);
+            self.on_packets_lost(lost_packets.into_iter(), space, now);
+        }
+        if persistent_congestion {
+            self.on_persistent_congestion(now);
         }
         self.algorithm.on_ack(newly_acked_packets, now);
+        self.observe();
 
         if self.server_completed_address_validation() {
             self.pto_count = 0;
@@ -182,10 +293,13 @@ impl CongestionController {
         &mut self,
         epoch: Epoch,
         ack_frame: &AckFrame,
-    ) -> (VecDeque<AckedPkt>, Option<Duration>) {
+        now: Instant,
+    ) -> (VecDeque<AckedPkt>, Option<Duration>, u64) {
         let mut newly_acked_packets: VecDeque<AckedPkt> = VecDeque::new();
         let largest_acked: u64 = ack_frame.largest.into();
+        let prior_largest_acked = self.largest_acked_packet[epoch];
         let mut latest_rtt = None;
+        let mut newly_acked_marked = 0;
         for range in ack_frame.iter() {
             for pn in range {
                 let acked: Option<AckedPkt> = self.sent_packets[epoch]
@@ -193,9 +307,30 @@ impl CongestionController {
                     .ok()
                     .map(|idx| {
                         self.rcvd_records[epoch].ack(pn, &self.retire_handlers);
+                        let sent = &self.sent_packets[epoch][idx];
+                        if !sent.is_acked {
+                            if sent.in_flight {
+                                self.bytes_in_flight -= sent.size;
+                            }
+                            if sent.is_mtu_probe {
+                                self.mtu_probe_outcomes.push((pn, true));
+                            }
+                            if sent.is_ecn_marked {
+                                newly_acked_marked += 1;
+                            }
+                            if let Some(prior) = prior_largest_acked.filter(|l| pn < *l) {
+                                self.reordering.on_reordered(prior, pn);
+                            }
+                        }
                         self.sent_packets[epoch][idx].is_acked = true;
-                        self.sent_packets[epoch][idx].clone().into()
+                        let mut acked: AckedPkt = self.sent_packets[epoch][idx].clone().into();
+                        acked.rtt = now.saturating_duration_since(acked.time_sent);
+                        acked
                     });
+                if let Some(prior) = prior_largest_acked.filter(|_| acked.is_none()) {
+                    // The packet declared lost may be acknowledged at last
+                    self.reordering.on_acked(epoch, prior, pn);
+                }
                 if let Some(ack) = acked {
                     // largest is newly ackd, update latest_rtt
                     if pn == largest_acked {
@@ -206,18 +341,53 @@ impl CongestionController {
             }
         }
         self.slide_sent_packets(epoch);
-        (newly_acked_packets, latest_rtt)
+        (newly_acked_packets, latest_rtt, newly_acked_marked)
     }
 
     // A.8. Setting the Loss Detection Timer
-    fn on_packets_lost(&mut self, packets: impl Iterator<Item = SentPkt>, epoch: Epoch) {
-        let now = Instant::now();
+    fn on_packets_lost(
+        &mut self,
+        packets: impl Iterator<Item = SentPkt>,
+        epoch: Epoch,
+        now: Instant,
+    ) {
+        self.reordering.on_loss_event();
         for lost in packets {
-            self.algorithm.on_congestion_event(&lost, now);
+            if lost.in_flight {
+                self.bytes_in_flight -= lost.size;
+            }
+            self.ecn.on_lost(lost.is_ecn_marked);
+            if let Some(observer) = &self.observer {
+                observer.on_packet_lost(epoch, &lost);
+            }
+            // The loss of a PMTU probe is not a congestion signal, see [Section 14.4](https://www.rfc-editor.org/rfc/rfc9000.html#section-14.4)
+            if lost.is_mtu_probe {
+                self.mtu_probe_outcomes.push((lost.pn, false));
+            } else {
+                self.reordering.on_lost(epoch, lost.pn);
+                self.algorithm.on_congestion_event(&lost, now);
+            }
             self.loss_handlers[epoch].may_loss(lost.pn);
         }
     }
 
+    // The congestion window collapses to the minimum, since the losses last so long that the
+    // network is considered changed, see [Section 7.6](https://www.rfc-editor.org/rfc/rfc9002.html#section-7.6)
+    // of RFC 9002.
+    fn on_persistent_congestion(&mut self, now: Instant) {
+        self.algorithm.on_persistent_congestion(now);
+        self.rtt.on_persistent_congestion();
+    }
+
+    // The losses spanning longer than this duration establish the persistent congestion, the
+    // max_ack_delay is included irrespective of the packet number space.
+    fn persistent_congestion_duration(&self) -> Duration {
+        let smoothed_rtt = self.rtt.smoothed_rtt();
+        let rttvar = self.rtt.rttvar();
+        (smoothed_rtt + std::cmp::max(K_GRANULARITY, rttvar * 4) + self.peer_max_ack_delay())
+            * K_PERSISTENT_CONGESTION_THRESHOLD
+    }
+
     fn set_loss_timer(&mut self) {
         let (earliest_loss_time, _) = self.get_loss_time_and_space();
         if let Some(earliest_loss_time) = earliest_loss_time {
@@ -240,34 +410,61 @@ impl CongestionController {
         let (earliest_loss_time, space) = self.get_loss_time_and_space();
         // lost timeout
         if earliest_loss_time.is_some() {
-            let loss_packet = self.remove_loss_packets(space, now);
+            let (loss_packet, persistent_congestion) = self.remove_loss_packets(space, now);
             assert!(!loss_packet.is_empty());
-            self.on_packets_lost(loss_packet.into_iter(), space);
+            self.on_packets_lost(loss_packet.into_iter(), space, now);
+            if persistent_congestion {
+                self.on_persistent_congestion(now);
+            }
+            self.observe();
             self.set_loss_timer();
             return;
         }
 
         // probe timeout
-        let _ = if self.no_ack_eliciting_in_flight() {
+        if self.no_ack_eliciting_in_flight() {
             assert!(!self.server_completed_address_validation());
             // Client sends an anti-deadlock packet: Initial is padded
             // to earn more anti-amplification credit,
             // a Handshake packet proves address ownership.
-            if self.has_handshake_keys {
-                Some(Epoch::Handshake)
+            let space = if self.has_handshake_keys {
+                Epoch::Handshake
             } else {
-                Some(Epoch::Initial)
-            }
-        } else if self.get_pto_timeout().is_some() {
-            Some(Epoch::Data)
-        } else {
-            None
-        };
-        self.pto_count += 1;
+                Epoch::Initial
+            };
+            self.pto_probes[space] = 1;
+            self.on_pto(space);
+        } else if let Some((_, space)) = self.get_pto_time_and_space() {
+            self.pto_probes[space] = MAX_PTO_PROBES;
+            self.retransmit_oldest(space);
+            self.on_pto(space);
+        }
 
         self.set_loss_timer();
     }
 
+    fn on_pto(&mut self, space: Epoch) {
+        self.pto_count += 1;
+        if let Some(observer) = &self.observer {
+            observer.on_pto(space, self.pto_count);
+        }
+    }
+
+    // Reports the changes of the congestion window and the state since the last report.
+    fn observe(&mut self) {
+        let Some(observer) = &self.observer else {
+            return;
+        };
+        let (cwnd, state) = (self.algorithm.cwnd(), self.algorithm.state());
+        if state != self.observed.1 {
+            observer.on_state_changed(self.observed.1, state);
+        }
+        if cwnd != self.observed.0 {
+            observer.on_cwnd_updated(cwnd, self.bytes_in_flight);
+        }
+        self.observed = (cwnd, state);
+    }
+
     fn get_loss_time_and_space(&self) -> (Option<Instant>, Epoch) {
         let mut time = self.loss_time[Epoch::Initial];
         let mut space = Epoch::Initial;
@@ -288,18 +485,42 @@ impl CongestionController {
         let mut duration = smoothed_rtt + std::cmp::max(K_GRANULARITY, rttvar * 4);
         // 握手已完成, 则应该考虑 max_ack_delay
         if epoch == Epoch::Data && self.is_handshake_done {
-            duration += self.max_ack_delay
+            duration += self.peer_max_ack_delay()
+        }
+        duration.saturating_mul(self.loss_detection.pto_factor(self.pto_count))
+    }
+
+    // The probe packets carry the data of the oldest packets in flight, which are more likely to
+    // be lost, instead of only a PING frame. The packets are not declared lost, they may still be
+    // acknowledged.
+    // See https://www.rfc-editor.org/rfc/rfc9002.html#section-6.2.4
+    fn retransmit_oldest(&mut self, space: Epoch) {
+        let oldest = self.sent_packets[space]
+            .iter()
+            .filter(|sent| !sent.is_acked && sent.in_flight && !sent.is_mtu_probe)
+            .take(MAX_PTO_PROBES);
+        for sent in oldest {
+            self.loss_handlers[space].may_loss(sent.pn);
         }
-        duration * 2_u32.pow(self.pto_count)
     }
 
     fn get_pto_timeout(&self) -> Option<Instant> {
+        self.get_pto_time_and_space().map(|(time, _)| time)
+    }
+
+    // A.8. Setting the Loss Detection Timer, GetPtoTimeAndSpace
+    fn get_pto_time_and_space(&self) -> Option<(Instant, Epoch)> {
         let mut duration = self.get_pto_time(Epoch::Initial);
         if self.no_ack_eliciting_in_flight() {
-            return Some(Instant::now() + duration);
+            let space = if self.has_handshake_keys {
+                Epoch::Handshake
+            } else {
+                Epoch::Initial
+            };
+            return Some((Instant::now() + duration, space));
         }
 
-        let mut pto_time = None;
+        let mut pto_time: Option<(Instant, Epoch)> = None;
         for &space in Epoch::iter() {
             if self.time_of_last_ack_eliciting_packet[space].is_none() {
                 continue;
@@ -310,26 +531,97 @@ impl CongestionController {
                 if !self.is_handshake_done {
                     return pto_time;
                 }
-                duration += self.max_ack_delay * 2_u32.pow(self.pto_count);
+                duration += self
+                    .peer_max_ack_delay()
+                    .saturating_mul(self.loss_detection.pto_factor(self.pto_count));
             }
             let new_time = self.time_of_last_ack_eliciting_packet[space].unwrap() + duration;
-            if pto_time.is_none() || new_time < pto_time.unwrap() {
-                pto_time = Some(new_time);
+            if pto_time.is_none_or(|(time, _)| new_time < time) {
+                pto_time = Some((new_time, space));
             }
         }
         pto_time
     }
 
-    fn remove_loss_packets(&mut self, space: Epoch, now: Instant) -> Vec<SentPkt> {
+    // The peer may delay the acknowledgments up to the max ACK delay requested by the ACK_FREQUENCY frame
+    fn peer_max_ack_delay(&self) -> Duration {
+        match self.ack_frequency {
+            Some((frame, _)) => self.peer_max_ack_delay.max(Duration::from_micros(
+                frame.request_max_ack_delay.into_inner(),
+            )),
+            None => self.peer_max_ack_delay,
+        }
+    }
+
+    // The ACK Delay field is scaled by the peer's ack_delay_exponent, see [Section 19.3](https://www.rfc-editor.org/rfc/rfc9000.html#section-19.3)
+    // of RFC 9000. The Initial packets are acknowledged without delay, the field is ignored in
+    // the Initial space, see [Section 5.3](https://www.rfc-editor.org/rfc/rfc9002.html#section-5.3)
+    // of RFC 9002.
+    fn ack_delay(&self, space: Epoch, ack_frame: &AckFrame) -> Duration {
+        if space == Epoch::Initial {
+            return Duration::ZERO;
+        }
+        let delay = ack_frame.delay.into_inner();
+        Duration::from_micros(delay.saturating_mul(1 << self.peer_ack_delay_exponent))
+    }
+
+    // Ask the peer to send fewer ACKs as the congestion window grows, see [Section 8](https://www.ietf.org/archive/id/draft-ietf-quic-ack-frequency-10.html#section-8)
+    // of QUIC Acknowledgment Frequency.
+    fn need_ack_frequency(&mut self, now: Instant) -> Option<AckFrequencyFrame> {
+        let peer_min_ack_delay = self.peer_min_ack_delay?;
+        if !self.is_handshake_done {
+            return None;
+        }
+        let srtt = self.rtt.smoothed_rtt();
+        let threshold = ack_eliciting_threshold(self.algorithm.cwnd());
+        let sequence = match self.ack_frequency {
+            // 每个RTT至多调整一次，避免拥塞窗口抖动时频繁发送
+            Some((last, _)) if last.ack_eliciting_threshold.into_inner() == threshold => {
+                return None
+            }
+            Some((_, time)) if now.saturating_duration_since(time) < srtt => return None,
+            Some((last, _)) => last.sequence.into_inner() + 1,
+            // 默认每收到2个需确认的数据包就发送确认
+            None if threshold == 1 => return None,
+            None => 0,
+        };
+        let max_ack_delay = (srtt / 4)
+            .min(MAX_REQUEST_ACK_DELAY)
+            .max(peer_min_ack_delay);
+        let frame = AckFrequencyFrame {
+            sequence: VarInt::from_u64(sequence).expect("sequence number never exceed VARINT_MAX"),
+            ack_eliciting_threshold: VarInt::from_u64(threshold).unwrap(),
+            request_max_ack_delay: VarInt::from_u64(max_ack_delay.as_micros() as u64)
+                .expect("max ack delay never exceed VARINT_MAX"),
+            reordering_threshold: VarInt::from_u32(1),
+        };
+        self.ack_frequency = Some((frame, now));
+        Some(frame)
+    }
+
+    // Returns the lost packets, and whether they establish the persistent congestion.
+    fn remove_loss_packets(&mut self, space: Epoch, now: Instant) -> (Vec<SentPkt>, bool) {
         assert!(self.largest_acked_packet[space].is_some());
         let largest_acked = self.largest_acked_packet[space].unwrap();
         self.loss_time[space] = None;
 
-        let loss_delay = self.rtt.loss_delay();
+        let reordering_window = self.reordering.window(
+            &self.loss_detection,
+            self.rtt.min_rtt(),
+            self.rtt.smoothed_rtt(),
+        );
+        let loss_delay =
+            self.rtt.loss_delay(self.loss_detection.time_threshold) + reordering_window;
+        let packet_threshold = self.reordering.packet_threshold(&self.loss_detection);
         let lost_send_time = now.checked_sub(loss_delay).unwrap();
 
         let mut loss_packets = Vec::new();
         let mut loss_pn = Vec::new();
+        // 连续丢失的在途数据包的最早发送时间，以及其中跨越的最长时间，仅考虑首个RTT样本之后发送的
+        // 数据包；只检查本空间内的数据包是否连续丢失
+        let first_rtt_sample = self.rtt.first_rtt_sample();
+        let mut lost_since: Option<Instant> = None;
+        let mut longest_lost_span = None;
 
         let mut largest_ack_index = 0;
         while largest_ack_index != self.sent_packets[space].len()
@@ -342,20 +634,26 @@ impl CongestionController {
         while i != self.sent_packets[space].len() && self.sent_packets[space][i].pn < largest_acked
         {
             if self.sent_packets[space][i].is_acked {
+                lost_since = None;
                 i += 1;
                 continue;
             }
             // 距离 largest ack index 相差超过 threshold 即为丢包
             if self.sent_packets[space][i].time_sent <= lost_send_time
-                || largest_ack_index - i >= K_PACKET_THRESHOLD
+                || (largest_ack_index - i) as u64 >= packet_threshold
             {
                 if let Some(loss) = self.sent_packets[space].remove(i) {
+                    if loss.in_flight && first_rtt_sample.is_some_and(|t| loss.time_sent > t) {
+                        let since = *lost_since.get_or_insert(loss.time_sent);
+                        longest_lost_span = longest_lost_span.max(Some(loss.time_sent - since));
+                    }
                     let pn = loss.pn;
                     loss_pn.push(pn);
                     loss_packets.push(loss);
                     largest_ack_index -= 1;
                 }
             } else {
+                lost_since = None;
                 let loss_time = self.sent_packets[space][i].time_sent + loss_delay;
                 self.loss_time[space] = match self.loss_time[space] {
                     Some(lt) => Some(lt.min(loss_time)),
@@ -366,7 +664,24 @@ impl CongestionController {
         }
 
         self.slide_sent_packets(space);
-        loss_packets
+        let persistent_congestion =
+            longest_lost_span.is_some_and(|span| span > self.persistent_congestion_duration());
+        (loss_packets, persistent_congestion)
+    }
+
+    // A.11. Upon Dropping Initial or Handshake Keys
+    fn discard_space(&mut self, space: Epoch) {
+        for sent in self.sent_packets[space].drain(..) {
+            if !sent.is_acked && sent.in_flight {
+                self.bytes_in_flight -= sent.size;
+            }
+        }
+        self.time_of_last_ack_eliciting_packet[space] = None;
+        self.loss_time[space] = None;
+        self.pto_probes[space] = 0;
+        self.reordering.discard_space(space);
+        self.pto_count = 0;
+        self.set_loss_timer();
     }
 
     fn slide_sent_packets(&mut self, space: Epoch) {
@@ -391,11 +706,41 @@ impl CongestionController {
         self.has_handshake_keys || self.is_handshake_done
     }
 
-    fn process_ecn(&mut self, _: Epoch, _: EcnCounts) {
-        todo!()
+    // A.7. On Receiving an Acknowledgment, ProcessECN
+    fn process_ecn(
+        &mut self,
+        space: Epoch,
+        ack_frame: &AckFrame,
+        newly_acked_packets: &VecDeque<AckedPkt>,
+        newly_acked_marked: u64,
+        now: Instant,
+    ) {
+        if !self.ecn.on_ack(space, newly_acked_marked, ack_frame.ecn) {
+            return;
+        }
+        // The CE count increases, respond to the congestion with the largest newly acked packet
+        let largest_acked: u64 = ack_frame.largest.into();
+        if let Some(acked) = newly_acked_packets.iter().find(|p| p.pn == largest_acked) {
+            let sent = SentPkt {
+                pn: acked.pn,
+                time_sent: acked.time_sent,
+                size: acked.size,
+                ..Default::default()
+            };
+            self.algorithm.on_ecn_ce(&sent, now);
+            if let Some(observer) = &self.observer {
+                observer.on_ecn_ce(space, &sent);
+            }
+        }
     }
 }
 
+/// The number of ack-eliciting packets the peer is asked to receive before acknowledging, for the
+/// congestion window of `cwnd` bytes.
+fn ack_eliciting_threshold(cwnd: u64) -> u64 {
+    (cwnd / MSS as u64 / ACKS_PER_CWND).clamp(1, MAX_ACK_ELICITING_THRESHOLD)
+}
+
 /// Shared congestion controller
 #[derive(Clone)]
 pub struct ArcCC(Arc<Mutex<CongestionController>>);
@@ -403,12 +748,14 @@ pub struct ArcCC(Arc<Mutex<CongestionController>>);
 impl ArcCC {
     pub fn new(
         algorithm: CongestionAlgorithm,
+        window: CongestionWindowConfig,
         max_ack_delay: Duration,
         loss: [Box<dyn MayLoss>; 3],
         retire: [Box<dyn RetirePktRecord>; 3],
     ) -> Self {
         ArcCC(Arc::new(Mutex::new(CongestionController::new(
             algorithm,
+            window,
             max_ack_delay,
             loss,
             retire,
@@ -440,7 +787,15 @@ impl super::CongestionControl for ArcCC {
         let cwnd = guard.algorithm.cwnd();
         let mtu = MSS;
         let rate = guard.algorithm.pacing_rate();
-        let tokens = guard.pacer.schedule(srtt, cwnd, mtu, now, rate);
+        let mut tokens = guard.pacer.schedule(srtt, cwnd, mtu, now, rate);
+        if let Some(available) = guard.send_rate_limit.available(mtu, now) {
+            tokens = tokens.min(available);
+        }
+        // The probe packets are not blocked by the congestion control
+        let probes = guard.pto_probes.iter().sum::<usize>();
+        if probes > 0 {
+            return Poll::Ready(tokens.max(probes * mtu));
+        }
         if tokens >= mtu {
             return Poll::Ready(tokens);
         }
@@ -469,6 +824,10 @@ impl super::CongestionControl for ArcCC {
         guard.rcvd_records[space].need_ack(guard.max_ack_delay)
     }
 
+    fn need_probe(&self, space: Epoch) -> bool {
+        self.0.lock().unwrap().pto_probes[space] > 0
+    }
+
     fn on_pkt_sent(
         &self,
         epoch: Epoch,
@@ -488,22 +847,102 @@ impl super::CongestionControl for ArcCC {
         }
     }
 
+    fn on_mtu_probe_sent(&self, pn: u64, sent_bytes: usize) {
+        let mut guard = self.0.lock().unwrap();
+        let now = Instant::now();
+        guard.on_packet_sent(pn, Epoch::Data, true, true, sent_bytes, now);
+        if let Some(sent) = guard.sent_packets[Epoch::Data].back_mut() {
+            sent.is_mtu_probe = true;
+        }
+        guard.last_sent_time = now;
+    }
+
+    fn on_app_limited(&self, app_limited: bool) {
+        let mut guard = self.0.lock().unwrap();
+        let app_limited = app_limited && (guard.bytes_in_flight as u64) < guard.algorithm.cwnd();
+        guard.algorithm.on_app_limited(app_limited);
+    }
+
+    fn take_mtu_probe_outcomes(&self) -> Vec<(u64, bool)> {
+        std::mem::take(&mut self.0.lock().unwrap().mtu_probe_outcomes)
+    }
+
+    fn ecn(&self) -> Option<EcnCodepoint> {
+        self.0.lock().unwrap().ecn.codepoint()
+    }
+
     fn on_ack(&self, space: Epoch, ack_frame: &AckFrame) {
         let mut guard = self.0.lock().unwrap();
         let now = Instant::now();
         guard.on_ack_rcvd(space, ack_frame, now);
     }
 
-    fn on_pkt_rcvd(&self, epoch: Epoch, pn: u64, is_ack_eliciting: bool) {
+    fn on_pkt_rcvd(
+        &self,
+        epoch: Epoch,
+        pn: u64,
+        is_ack_eliciting: bool,
+        ecn: Option<EcnCodepoint>,
+    ) {
         if !is_ack_eliciting {
             return;
         }
         let mut guard = self.0.lock().unwrap();
+        if ecn == Some(EcnCodepoint::Ce) {
+            guard.rcvd_records[epoch].on_ce_rcvd();
+        }
         guard.rcvd_records[epoch].on_pkt_rcvd(pn);
         let now = Instant::now();
         guard.on_datagram_rcvd(now);
     }
 
+    fn on_ack_frequency(&self, frame: &AckFrequencyFrame) {
+        let mut guard = self.0.lock().unwrap();
+        guard.rcvd_records[Epoch::Data].on_ack_frequency(frame);
+    }
+
+    fn set_ack_policy(&self, policy: AckPolicy) {
+        let mut guard = self.0.lock().unwrap();
+        for records in guard.rcvd_records.iter_mut() {
+            records.set_policy(policy);
+        }
+    }
+
+    fn set_peer_ack_delay(&self, max_ack_delay: Duration, ack_delay_exponent: u8) {
+        let mut guard = self.0.lock().unwrap();
+        guard.peer_max_ack_delay = max_ack_delay;
+        guard.peer_ack_delay_exponent = ack_delay_exponent;
+    }
+
+    fn set_loss_detection(&self, config: LossDetectionConfig) {
+        self.0.lock().unwrap().loss_detection = config;
+    }
+
+    fn set_observer(&self, observer: Arc<dyn Observer>) {
+        let mut guard = self.0.lock().unwrap();
+        guard.observed = (guard.algorithm.cwnd(), guard.algorithm.state());
+        guard.observer = Some(observer);
+    }
+
+    fn set_send_rate_limit(&self, limit: SendRateLimit) {
+        self.0.lock().unwrap().send_rate_limit = limit;
+    }
+
+    fn on_immediate_ack(&self) {
+        let mut guard = self.0.lock().unwrap();
+        guard.rcvd_records[Epoch::Data].need_ack = true;
+    }
+
+    fn enable_ack_frequency(&self, peer_min_ack_delay: Duration) {
+        let mut guard = self.0.lock().unwrap();
+        guard.peer_min_ack_delay = Some(peer_min_ack_delay);
+    }
+
+    fn need_ack_frequency(&self) -> Option<AckFrequencyFrame> {
+        let mut guard = self.0.lock().unwrap();
+        guard.need_ack_frequency(Instant::now())
+    }
+
     fn pto_time(&self, epoch: Epoch) -> Duration {
         self.0.lock().unwrap().get_pto_time(epoch)
     }
@@ -517,6 +956,42 @@ impl super::CongestionControl for ArcCC {
         let mut guard = self.0.lock().unwrap();
         guard.is_handshake_done = true;
         guard.rtt.on_handshake_done();
+        // 握手确认后，Initial和Handshake空间的密钥都已丢弃，其中在途的数据包不再重传
+        guard.discard_space(Epoch::Initial);
+        guard.discard_space(Epoch::Handshake);
+    }
+
+    fn smoothed_rtt(&self) -> Duration {
+        self.0.lock().unwrap().rtt.smoothed_rtt()
+    }
+
+    fn min_rtt(&self) -> Duration {
+        self.0.lock().unwrap().rtt.min_rtt()
+    }
+
+    fn cwnd(&self) -> u64 {
+        self.0.lock().unwrap().algorithm.cwnd()
+    }
+
+    fn pacing_rate(&self) -> u64 {
+        let guard = self.0.lock().unwrap();
+        let rate = Pacer::pacing_rate(
+            guard.rtt.smoothed_rtt(),
+            guard.algorithm.cwnd(),
+            guard.algorithm.pacing_rate(),
+        );
+        guard
+            .send_rate_limit
+            .rate()
+            .map_or(rate, |limit| rate.min(limit))
+    }
+
+    fn delivery_rate(&self) -> Option<u64> {
+        self.0.lock().unwrap().algorithm.delivery_rate()
+    }
+
+    fn bytes_in_flight(&self) -> usize {
+        self.0.lock().unwrap().bytes_in_flight
     }
 }
 
@@ -529,6 +1004,15 @@ struct RcvdRecords {
     last_ack_sent: Option<(u64, u64)>,
     largest_recv_time: Option<(u64, Instant)>,
     rcvd_queue: VecDeque<u64>,
+    // The number of ack-eliciting packets received since the last ACK sent.
+    ack_eliciting_count: u64,
+    // The acknowledgment behavior requested by the peer's ACK_FREQUENCY frame, see [`AckFrequencyFrame`].
+    ack_frequency_seq: Option<u64>,
+    ack_eliciting_threshold: u64,
+    request_max_ack_delay: Option<Duration>,
+    reordering_threshold: u64,
+    // The local acknowledgment strategy, overridden by the peer's ACK_FREQUENCY frame.
+    policy: AckPolicy,
 }
 
 impl RcvdRecords {
@@ -539,12 +1023,54 @@ impl RcvdRecords {
             last_ack_sent: None,
             largest_recv_time: None,
             rcvd_queue: VecDeque::new(),
+            ack_eliciting_count: 0,
+            ack_frequency_seq: None,
+            // An ACK frame should be sent after receiving at least two ack-eliciting packets,
+            // and the out-of-order packets are acknowledged immediately, as RFC 9000 recommends.
+            ack_eliciting_threshold: 1,
+            request_max_ack_delay: None,
+            reordering_threshold: 1,
+            policy: AckPolicy::default(),
+        }
+    }
+
+    /// Sets the local acknowledgment strategy, which takes effect unless the peer has requested
+    /// another one by an ACK_FREQUENCY frame.
+    fn set_policy(&mut self, policy: AckPolicy) {
+        self.policy = policy;
+        if self.ack_frequency_seq.is_none() {
+            self.ack_eliciting_threshold = policy.ack_eliciting_threshold;
+            self.reordering_threshold = policy.immediate_on_reordering as u64;
+        }
+    }
+
+    /// Called before an ack-eliciting packet marked with ECN-CE is recorded.
+    fn on_ce_rcvd(&mut self) {
+        if self.policy.immediate_on_ce {
+            self.need_ack = true;
         }
     }
 
+    /// Updates the acknowledgment behavior as requested by the peer, unless the frame is reordered.
+    fn on_ack_frequency(&mut self, frame: &AckFrequencyFrame) {
+        let sequence = frame.sequence.into_inner();
+        if self.ack_frequency_seq.is_some_and(|seq| seq >= sequence) {
+            return;
+        }
+        self.ack_frequency_seq = Some(sequence);
+        self.ack_eliciting_threshold = frame.ack_eliciting_threshold.into_inner();
+        self.request_max_ack_delay = Some(Duration::from_micros(
+            frame.request_max_ack_delay.into_inner(),
+        ));
+        self.reordering_threshold = frame.reordering_threshold.into_inner();
+    }
+
     fn on_pkt_rcvd(&mut self, pn: u64) {
         // An endpoint MUST acknowledge all ack-eliciting Initial and Handshake packets immediately
-        if self.epoch == Epoch::Initial || self.epoch == Epoch::Handshake {
+        if self.epoch == Epoch::Initial
+            || self.epoch == Epoch::Handshake
+            || self.policy.ack_every_packet
+        {
             self.need_ack = true;
         }
         // See [Section 13.2.1](https://www.rfc-editor.org/rfc/rfc9000.html#name-sending-ack-frames)
@@ -552,8 +1078,14 @@ impl RcvdRecords {
         // 1. When the received packet has a packet number less than another ack-eliciting packet that has been received
         // 2. when the packet has a packet number larger than the highest-numbered ack-eliciting packet that has been
         // received and there are missing packets between that packet and this packet.
+        //
+        // The reordering threshold of 0 requested by the peer means the out-of-order packets are
+        // not acknowledged immediately.
+        self.ack_eliciting_count += 1;
         if let Some(&largest) = self.rcvd_queue.back() {
-            if pn < largest || pn - largest > 1 {
+            if self.reordering_threshold > 0
+                && (pn < largest || pn - largest > self.reordering_threshold)
+            {
                 self.need_ack = true;
             }
             if pn >= largest {
@@ -577,9 +1109,13 @@ impl RcvdRecords {
     /// Checks whether an ACK frame needs to be sent.
     /// Returns [`Some`] if it's time to send an ACK based on the maximum delay.
     fn need_ack(&self, max_delay: Duration) -> Option<(u64, Instant)> {
-        if self.need_ack {
+        if self.need_ack || self.ack_eliciting_count > self.ack_eliciting_threshold {
             return self.largest_recv_time;
         }
+        let max_delay = self
+            .request_max_ack_delay
+            .or(self.policy.max_ack_delay)
+            .unwrap_or(max_delay);
         // All ack-eliciting 0-RTT and 1-RTT packets  MUST acknowledge within its advertised max_ack_delay
         if let Some((largest, recv_time)) = self.largest_recv_time {
             let now = Instant::now();
@@ -596,6 +1132,7 @@ impl RcvdRecords {
         self.last_ack_sent = Some((pn, largest_acked));
         self.largest_recv_time = None;
         self.need_ack = false;
+        self.ack_eliciting_count = 0;
     }
 
     /// Processes an acknowledged (ACK) packet.
@@ -615,6 +1152,7 @@ impl RcvdRecords {
     }
 }
 
+/// A packet newly acknowledged, fed to the [`Algorithm`].
 #[derive(Clone)]
 pub struct AckedPkt {
     pub pn: u64,
@@ -643,6 +1181,7 @@ impl From<SentPkt> for AckedPkt {
     }
 }
 
+/// A packet sent and tracked by the congestion controller, fed to the [`Algorithm`].
 #[derive(Eq, Clone, Debug)]
 pub struct SentPkt {
     pub pn: u64,
@@ -655,6 +1194,9 @@ pub struct SentPkt {
     pub tx_in_flight: usize,
     pub lost: u64,
     pub is_acked: bool,
+    pub in_flight: bool,
+    pub is_mtu_probe: bool,
+    pub is_ecn_marked: bool,
 }
 
 impl Default for SentPkt {
@@ -670,6 +1212,9 @@ impl Default for SentPkt {
             tx_in_flight: 0,
             lost: 0,
             is_acked: false,
+            in_flight: false,
+            is_mtu_probe: false,
+            is_ecn_marked: false,
         }
     }
 }
@@ -687,6 +1232,9 @@ impl SentPkt {
             tx_in_flight: 0,
             lost: 0,
             is_acked: false,
+            in_flight: false,
+            is_mtu_probe: false,
+            is_ecn_marked: false,
         }
     }
 }
@@ -709,16 +1257,56 @@ impl Ord for SentPkt {
     }
 }
 
+/// The congestion control algorithm of a path, which decides the congestion window and the
+/// pacing rate, while the loss detection and the acknowledgments are handled by the congestion
+/// controller, see [`CongestionAlgorithm::Custom`].
 pub trait Algorithm: Send {
+    /// Called when an ack-eliciting or in-flight packet is sent, the algorithm can record the
+    /// delivery rate sample state in the `sent` packet.
     fn on_sent(&mut self, sent: &mut SentPkt, sent_bytes: usize, now: Instant);
 
+    /// Called with the packets newly acknowledged by an ACK frame.
     fn on_ack(&mut self, packet: VecDeque<AckedPkt>, now: Instant);
 
+    /// Called when the `lost` packet is declared lost.
     fn on_congestion_event(&mut self, lost: &SentPkt, now: Instant);
 
+    /// Called when an ACK frame reports the increase of the ECN-CE count, `largest_acked` is the
+    /// largest packet acknowledged by the frame.
+    ///
+    /// The loss-based algorithms respond as if the packet is lost by default, see
+    /// [Section 7.1](https://www.rfc-editor.org/rfc/rfc9002.html#section-7.1) of RFC 9002.
+    fn on_ecn_ce(&mut self, largest_acked: &SentPkt, now: Instant) {
+        self.on_congestion_event(largest_acked, now);
+    }
+
+    /// Called each time the sending stops, `app_limited` is true if the congestion window is
+    /// underutilized since the sender has nothing more to send. The window should not grow then,
+    /// and the bandwidth samples should be marked as application-limited.
+    fn on_app_limited(&mut self, app_limited: bool);
+
+    /// Called when the persistent congestion is established, the congestion window should collapse
+    /// to the minimum, see [Section 7.6.2](https://www.rfc-editor.org/rfc/rfc9002.html#section-7.6.2)
+    /// of RFC 9002.
+    fn on_persistent_congestion(&mut self, now: Instant);
+
+    /// The congestion window in bytes.
     fn cwnd(&self) -> u64;
 
+    /// The pacing rate in bytes per second, [`None`] to derive it from the congestion window and
+    /// the smoothed RTT.
     fn pacing_rate(&self) -> Option<u64>;
+
+    /// The estimated bandwidth of the path in bytes per second, [`None`] if the algorithm does not
+    /// sample the delivery rate.
+    fn delivery_rate(&self) -> Option<u64>;
+
+    /// The current phase of the algorithm, reported to the [`Observer`] when it changes.
+    ///
+    /// The algorithms not tracking their phases stay in [`CongestionState::CongestionAvoidance`].
+    fn state(&self) -> CongestionState {
+        CongestionState::CongestionAvoidance
+    }
 }
 
 #[derive(Default)]
@@ -740,11 +1328,15 @@ impl LossDetectionTimer {
     }
 }
 
+#[cfg(test)]
+mod simulation;
+
 #[cfg(test)]
 mod tests {
-    use qbase::varint::VarInt;
+    use qbase::{frame::EcnCounts, varint::VarInt};
 
     use super::*;
+    use crate::{bbr::INITIAL_CWND, CongestionControl};
 
     #[test]
     fn test_on_packet_sent_multiple_packets() {
@@ -791,14 +1383,15 @@ mod tests {
         congestion.largest_acked_packet[space] = Some(5);
         congestion.sent_packets[space][4].is_acked = true;
         congestion.sent_packets[space].pop_back();
-        let lost_packets = congestion.remove_loss_packets(space, now);
+        let (lost_packets, _) = congestion.remove_loss_packets(space, now);
         assert_eq!(lost_packets.len(), 2);
         for (i, lost) in lost_packets.iter().enumerate() {
             assert_eq!(lost.pn, i as u64 + 1);
         }
         assert_eq!(congestion.sent_packets[space].len(), 2);
         // loss delay =  333*1.25
-        let loss_packets = congestion.remove_loss_packets(space, now + Duration::from_millis(417));
+        let (loss_packets, _) =
+            congestion.remove_loss_packets(space, now + Duration::from_millis(417));
         // 3,4 因为超时丢包
         assert_eq!(loss_packets.len(), 2);
         for (i, lost) in loss_packets.iter().enumerate() {
@@ -806,6 +1399,81 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_ack_delay() {
+        let mut congestion = create_congestion_controller_for_test();
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(0),
+            delay: VarInt::from_u32(1000),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        };
+        // Initial空间中的ack delay被忽略
+        assert_eq!(
+            congestion.ack_delay(Epoch::Initial, &ack_frame),
+            Duration::ZERO
+        );
+        assert_eq!(
+            congestion.ack_delay(Epoch::Data, &ack_frame),
+            Duration::from_millis(8)
+        );
+        congestion.peer_ack_delay_exponent = 0;
+        assert_eq!(
+            congestion.ack_delay(Epoch::Handshake, &ack_frame),
+            Duration::from_millis(1)
+        );
+
+        // 对端通告的max_ack_delay计入PTO
+        congestion.is_handshake_done = true;
+        let pto = congestion.get_pto_time(Epoch::Data);
+        congestion.peer_max_ack_delay = Duration::from_millis(100);
+        assert_eq!(
+            congestion.get_pto_time(Epoch::Data),
+            pto + Duration::from_millis(75)
+        );
+    }
+
+    #[test]
+    fn test_persistent_congestion() {
+        let mut congestion = create_congestion_controller_for_test();
+        congestion.peer_max_ack_delay = Duration::from_millis(100);
+        let space = Epoch::Data;
+        // 首个RTT样本之前发送的数据包不计入持续拥塞
+        congestion.on_packet_sent(0, space, true, true, 1000, Instant::now());
+        congestion.rtt.update(
+            Duration::from_millis(100),
+            Duration::ZERO,
+            Duration::ZERO,
+            Instant::now(),
+        );
+        let now = Instant::now() + Duration::from_millis(1);
+        for i in 1..=5 {
+            let time_sent = now + Duration::from_secs(i - 1);
+            congestion.on_packet_sent(i, space, true, true, 1000, time_sent);
+        }
+        congestion.largest_acked_packet[space] = Some(5);
+        congestion.sent_packets[space][5].is_acked = true;
+        // 丢失的数据包跨越3s，超过了持续拥塞的时长1.2s
+        let (lost_packets, persistent_congestion) =
+            congestion.remove_loss_packets(space, now + Duration::from_secs(5));
+        assert_eq!(lost_packets.len(), 5);
+        assert!(persistent_congestion);
+
+        // 中间的数据包被确认，丢包不再连续
+        for i in 6..=10 {
+            let time_sent = now + Duration::from_secs(i - 1);
+            congestion.on_packet_sent(i, space, true, true, 1000, time_sent);
+        }
+        congestion.largest_acked_packet[space] = Some(10);
+        congestion.sent_packets[space][2].is_acked = true;
+        congestion.sent_packets[space][4].is_acked = true;
+        let (lost_packets, persistent_congestion) =
+            congestion.remove_loss_packets(space, now + Duration::from_secs(10));
+        assert_eq!(lost_packets.len(), 3);
+        assert!(!persistent_congestion);
+    }
+
     #[test]
     fn test_on_ack_received() {
         let now = Instant::now();
@@ -878,6 +1546,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bytes_in_flight() {
+        let now = Instant::now();
+        let mut congestion = create_congestion_controller_for_test();
+        for i in 1..=3 {
+            congestion.on_packet_sent(i, Epoch::Data, true, true, 1000, now);
+        }
+        // ack-only packet is not in flight
+        congestion.on_packet_sent(4, Epoch::Data, false, false, 50, now);
+        assert_eq!(congestion.bytes_in_flight, 3000);
+
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(2),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        assert_eq!(congestion.bytes_in_flight, 2000);
+        // duplicate ack does not change bytes in flight
+        congestion.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        assert_eq!(congestion.bytes_in_flight, 2000);
+    }
+
     #[test]
     fn test_ack_record() {
         let max_ack_delay = Duration::from_millis(100);
@@ -924,6 +1617,91 @@ mod tests {
         assert_eq!(ack_reocrd.rcvd_queue, vec![11]);
     }
 
+    #[test]
+    fn test_ack_frequency_record() {
+        let max_ack_delay = Duration::from_secs(1);
+        let mut ack_record = RcvdRecords::new(Epoch::Data);
+        ack_record.on_pkt_rcvd(0);
+        assert!(ack_record.need_ack(max_ack_delay).is_none());
+        // 默认收到2个需确认的数据包就发送确认
+        ack_record.on_pkt_rcvd(1);
+        assert_eq!(ack_record.need_ack(max_ack_delay).unwrap().0, 1);
+        ack_record.on_ack_sent(0, 1);
+
+        let frame = AckFrequencyFrame {
+            sequence: VarInt::from_u32(1),
+            ack_eliciting_threshold: VarInt::from_u32(3),
+            request_max_ack_delay: VarInt::from_u32(25_000),
+            reordering_threshold: VarInt::from_u32(0),
+        };
+        ack_record.on_ack_frequency(&frame);
+        // 乱序的旧帧被忽略
+        ack_record.on_ack_frequency(&AckFrequencyFrame {
+            sequence: VarInt::from_u32(0),
+            ..frame
+        });
+        assert_eq!(ack_record.ack_eliciting_threshold, 3);
+        for pn in [2, 3, 5] {
+            ack_record.on_pkt_rcvd(pn);
+        }
+        assert!(ack_record.need_ack(max_ack_delay).is_none());
+        ack_record.on_pkt_rcvd(4);
+        assert_eq!(ack_record.need_ack(max_ack_delay).unwrap().0, 5);
+    }
+
+    #[test]
+    fn test_ack_policy() {
+        let max_ack_delay = Duration::from_secs(1);
+        let mut ack_record = RcvdRecords::new(Epoch::Data);
+        ack_record.set_policy(AckPolicy {
+            ack_eliciting_threshold: 2,
+            immediate_on_reordering: false,
+            ..AckPolicy::default()
+        });
+        for pn in [0, 2] {
+            ack_record.on_pkt_rcvd(pn);
+        }
+        assert!(ack_record.need_ack(max_ack_delay).is_none());
+        ack_record.on_ce_rcvd();
+        ack_record.on_pkt_rcvd(3);
+        assert_eq!(ack_record.need_ack(max_ack_delay).unwrap().0, 3);
+        ack_record.on_ack_sent(0, 3);
+
+        ack_record.set_policy(AckPolicy::every_packet());
+        ack_record.on_ack_frequency(&AckFrequencyFrame {
+            sequence: VarInt::from_u32(0),
+            ack_eliciting_threshold: VarInt::from_u32(10),
+            request_max_ack_delay: VarInt::from_u32(25_000),
+            reordering_threshold: VarInt::from_u32(0),
+        });
+        ack_record.on_pkt_rcvd(4);
+        assert_eq!(ack_record.need_ack(max_ack_delay).unwrap().0, 4);
+    }
+
+    #[test]
+    fn test_need_ack_frequency() {
+        assert_eq!(ack_eliciting_threshold(INITIAL_CWND), 2);
+        assert_eq!(ack_eliciting_threshold(MSS as u64), 1);
+        assert_eq!(
+            ack_eliciting_threshold(u64::MAX),
+            MAX_ACK_ELICITING_THRESHOLD
+        );
+
+        let mut congestion = create_congestion_controller_for_test();
+        let now = Instant::now();
+        assert!(congestion.need_ack_frequency(now).is_none());
+        congestion.peer_min_ack_delay = Some(Duration::from_millis(1));
+        assert!(congestion.need_ack_frequency(now).is_none());
+        congestion.is_handshake_done = true;
+        let frame = congestion.need_ack_frequency(now).unwrap();
+        assert_eq!(frame.sequence, VarInt::from_u32(0));
+        assert_eq!(frame.ack_eliciting_threshold, VarInt::from_u32(2));
+        // 333ms / 4 exceeds the max requested ACK delay
+        assert_eq!(frame.request_max_ack_delay, VarInt::from_u32(25_000));
+        // The threshold does not change
+        assert!(congestion.need_ack_frequency(now).is_none());
+    }
+
     struct Mock;
     impl MayLoss for Mock {
         fn may_loss(&self, _: u64) {}
@@ -933,9 +1711,206 @@ mod tests {
         fn retire(&self, _: u64) {}
     }
 
+    #[test]
+    fn test_discard_space() {
+        let mut congestion = create_congestion_controller_for_test();
+        let now = Instant::now();
+        congestion.on_packet_sent(0, Epoch::Handshake, true, true, 1200, now);
+        congestion.on_packet_sent(0, Epoch::Data, true, true, 1200, now);
+        assert_eq!(congestion.bytes_in_flight, 2400);
+
+        congestion.discard_space(Epoch::Handshake);
+        assert!(congestion.sent_packets[Epoch::Handshake].is_empty());
+        assert!(congestion.time_of_last_ack_eliciting_packet[Epoch::Handshake].is_none());
+        assert_eq!(congestion.bytes_in_flight, 1200);
+        assert_eq!(congestion.sent_packets[Epoch::Data].len(), 1);
+    }
+
+    #[test]
+    fn test_mtu_probe_outcomes() {
+        let mut congestion = create_congestion_controller_for_test();
+        let now = Instant::now();
+        for pn in 0..2 {
+            congestion.on_packet_sent(pn, Epoch::Data, true, true, 1400, now);
+            congestion.sent_packets[Epoch::Data][pn as usize].is_mtu_probe = true;
+        }
+        let cwnd = congestion.algorithm.cwnd();
+
+        let lost = congestion.sent_packets[Epoch::Data].pop_front().unwrap();
+        congestion.on_packets_lost([lost].into_iter(), Epoch::Data, now);
+        assert_eq!(congestion.algorithm.cwnd(), cwnd);
+
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(1),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        assert_eq!(congestion.bytes_in_flight, 0);
+        assert_eq!(congestion.mtu_probe_outcomes, vec![(0, false), (1, true)]);
+    }
+
+    #[test]
+    fn test_ecn_congestion_event() {
+        let mut congestion = CongestionController::new(
+            CongestionAlgorithm::NewReno,
+            CongestionWindowConfig::default(),
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+        );
+        let now = Instant::now();
+        assert_eq!(congestion.ecn.codepoint(), Some(EcnCodepoint::Ect0));
+        for pn in 0..2 {
+            congestion.on_packet_sent(pn, Epoch::Data, true, true, 1000, now);
+            assert!(congestion.sent_packets[Epoch::Data][pn as usize].is_ecn_marked);
+        }
+        let cwnd = congestion.algorithm.cwnd();
+
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(1),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(1),
+            ranges: vec![],
+            ecn: Some(EcnCounts {
+                ect0: VarInt::from_u32(1),
+                ect1: VarInt::from_u32(0),
+                ce: VarInt::from_u32(1),
+            }),
+        };
+        congestion.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+        assert!(congestion.algorithm.cwnd() < cwnd);
+        assert_eq!(congestion.ecn.codepoint(), Some(EcnCodepoint::Ect0));
+    }
+
+    #[derive(Clone, Default)]
+    struct Retransmitted(Arc<Mutex<Vec<u64>>>);
+    impl MayLoss for Retransmitted {
+        fn may_loss(&self, pn: u64) {
+            self.0.lock().unwrap().push(pn);
+        }
+    }
+
+    #[test]
+    fn test_pto_probes() {
+        let retransmitted = Retransmitted::default();
+        let mut congestion = CongestionController::new(
+            CongestionAlgorithm::NewReno,
+            CongestionWindowConfig::default(),
+            Duration::from_millis(100),
+            [
+                Box::new(Mock),
+                Box::new(retransmitted.clone()),
+                Box::new(Mock),
+            ],
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+        );
+        congestion.has_handshake_keys = true;
+        let now = Instant::now();
+        for pn in 0..3 {
+            congestion.on_packet_sent(pn, Epoch::Handshake, true, true, 1000, now);
+        }
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(0),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        };
+        congestion.on_ack_rcvd(Epoch::Handshake, &ack_frame, now);
+
+        congestion.on_loss_timeout(now + Duration::from_secs(1));
+        assert_eq!(congestion.pto_count, 1);
+        assert_eq!(congestion.pto_probes[Epoch::Handshake], MAX_PTO_PROBES);
+        // The data of the oldest packets in flight is sent in the probes, they are still in flight
+        assert_eq!(*retransmitted.0.lock().unwrap(), vec![1, 2]);
+        assert_eq!(congestion.bytes_in_flight, 2000);
+
+        // Only the ack-eliciting packets count as the probes
+        congestion.on_packet_sent(3, Epoch::Handshake, false, false, 50, now);
+        assert_eq!(congestion.pto_probes[Epoch::Handshake], MAX_PTO_PROBES);
+        congestion.on_packet_sent(4, Epoch::Handshake, true, true, 1000, now);
+        congestion.on_packet_sent(5, Epoch::Handshake, true, true, 1000, now);
+        assert_eq!(congestion.pto_probes[Epoch::Handshake], 0);
+    }
+
+    #[derive(Default)]
+    struct Events(Mutex<Vec<String>>);
+
+    impl Observer for Events {
+        fn on_cwnd_updated(&self, cwnd: u64, _: usize) {
+            self.0.lock().unwrap().push(format!("cwnd {cwnd}"));
+        }
+
+        fn on_packet_lost(&self, space: Epoch, lost: &SentPkt) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("lost {space:?} {}", lost.pn));
+        }
+
+        fn on_pto(&self, space: Epoch, pto_count: u32) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("pto {space:?} {pto_count}"));
+        }
+
+        fn on_state_changed(&self, old: CongestionState, new: CongestionState) {
+            self.0.lock().unwrap().push(format!("{old:?} -> {new:?}"));
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let cc = ArcCC::new(
+            CongestionAlgorithm::NewReno,
+            CongestionWindowConfig::default(),
+            Duration::from_millis(100),
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+            [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
+        );
+        let events = Arc::new(Events::default());
+        cc.set_observer(events.clone());
+
+        let mut congestion = cc.0.lock().unwrap();
+        congestion.has_handshake_keys = true;
+        let now = Instant::now();
+        for pn in 0..5 {
+            congestion.on_packet_sent(pn, Epoch::Handshake, true, true, MSS, now);
+        }
+        let ack_frame = AckFrame {
+            largest: VarInt::from_u32(4),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u32(0),
+            ranges: vec![],
+            ecn: None,
+        };
+        // 包4被确认，包0和包1超过了包阈值而被判定丢失，拥塞窗口减半进入恢复期
+        congestion.on_ack_rcvd(Epoch::Handshake, &ack_frame, now);
+        // 包2和包3超过了时间阈值而丢失，在恢复期中窗口不变；此后再无包在途，超时即为PTO
+        congestion.on_loss_timeout(now + Duration::from_secs(1));
+        congestion.on_loss_timeout(now + Duration::from_secs(2));
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            [
+                "lost Handshake 0",
+                "lost Handshake 1",
+                "SlowStart -> Recovery",
+                &format!("cwnd {}", 5 * MSS),
+                "lost Handshake 2",
+                "lost Handshake 3",
+                "pto Handshake 1",
+            ]
+        );
+    }
+
     fn create_congestion_controller_for_test() -> CongestionController {
         CongestionController::new(
             CongestionAlgorithm::Bbr,
+            CongestionWindowConfig::default(),
             Duration::from_millis(100),
             [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
             [Box::new(Mock), Box::new(Mock), Box::new(Mock)],
@@ -0,0 +1,269 @@
+//! A deterministic simulation to validate the congestion control algorithms.
+//!
+//! The controller sends full-sized packets in the Data space as fast as its congestion window
+//! allows, over a link of the given bandwidth, propagation delay, bottleneck buffer and random
+//! loss, and the receiver acknowledges each packet immediately. The time is virtual, and the
+//! randomness is seeded, so a run always produces the same [`Report`], which the algorithms are
+//! checked against as the baselines of the throughput and the latency.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::*;
+
+/// The bottleneck link between the sender and the receiver, the ACKs return without queuing.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Link {
+    /// The bandwidth in bytes per second.
+    pub(crate) bandwidth: u64,
+    /// The one-way propagation delay.
+    pub(crate) delay: Duration,
+    /// The drop-tail buffer in front of the link in bytes.
+    pub(crate) buffer: u64,
+    /// The probability that a packet is dropped randomly, besides the buffer overflows.
+    pub(crate) loss: f64,
+    /// Each packet is delayed randomly up to this after the link, so they are reordered like
+    /// being routed over the different paths by ECMP.
+    pub(crate) jitter: Duration,
+}
+
+/// The outcome of a simulation run.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Report {
+    /// The bytes acknowledged per second.
+    pub(crate) throughput: u64,
+    /// The average RTT of the packets acknowledged, including the queuing delay.
+    pub(crate) mean_rtt: Duration,
+    /// The packets dropped by the link, either randomly or by the buffer overflows.
+    pub(crate) dropped: usize,
+    /// The packets declared lost by the controller, the ones more than dropped are spurious.
+    pub(crate) lost: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Event {
+    // The packet reaches the receiver, who acknowledges it.
+    Arrive { pn: u64, sent: Instant },
+    // The ACK of the packet reaches the sender.
+    Ack { pn: u64, sent: Instant },
+}
+
+/// Runs the `algorithm` over the `link` for `duration` of the virtual time.
+pub(crate) fn run(
+    algorithm: CongestionAlgorithm,
+    loss_detection: LossDetectionConfig,
+    link: Link,
+    duration: Duration,
+    seed: u64,
+) -> Report {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let algorithm = match algorithm {
+        // The phase to start the gain cycling is random too
+        CongestionAlgorithm::Bbr => {
+            CongestionAlgorithm::Custom(Arc::new(move || Box::new(bbr::Bbr::new().with_seed(seed))))
+        }
+        algorithm => algorithm,
+    };
+    let lost = Lost::default();
+    let mut cc = CongestionController::new(
+        algorithm,
+        CongestionWindowConfig::default(),
+        Duration::ZERO,
+        [Box::new(Ignore), Box::new(Ignore), Box::new(lost.clone())],
+        [Box::new(Ignore), Box::new(Ignore), Box::new(Ignore)],
+    );
+    cc.loss_detection = loss_detection;
+    cc.has_handshake_keys = true;
+    cc.is_handshake_done = true;
+
+    let start = Instant::now();
+    let end = start + duration;
+    let mut now = start;
+    let mut events = BinaryHeap::new();
+    let mut next_pn = 0;
+    // The time the link finishes transmitting the packets queued.
+    let mut link_free = start;
+    let (mut acked, mut rtt_sum, mut dropped) = (0u64, Duration::ZERO, 0);
+
+    while now < end {
+        // Send as many packets as the window allows, or the probes upon the PTO
+        while (cc.bytes_in_flight + MSS) as u64 <= cc.algorithm.cwnd()
+            || cc.pto_probes[Epoch::Data] > 0
+        {
+            let pn = next_pn;
+            next_pn += 1;
+            cc.on_packet_sent(pn, Epoch::Data, true, true, MSS, now);
+
+            let queued = link_free.saturating_duration_since(now).as_secs_f64();
+            if rng.gen_bool(link.loss) || (queued * link.bandwidth as f64) as u64 > link.buffer {
+                dropped += 1;
+                continue;
+            }
+            link_free =
+                link_free.max(now) + Duration::from_secs_f64(MSS as f64 / link.bandwidth as f64);
+            let jitter = link.jitter.mul_f64(rng.gen_range(0.0..1.0));
+            events.push(Reverse((
+                link_free + link.delay + jitter,
+                Event::Arrive { pn, sent: now },
+            )));
+        }
+
+        let timeout = cc.loss_timer.timeout;
+        match events.peek() {
+            Some(Reverse((time, _))) if timeout.is_none_or(|timeout| *time <= timeout) => {
+                let Some(Reverse((time, event))) = events.pop() else {
+                    unreachable!()
+                };
+                now = time;
+                match event {
+                    Event::Arrive { pn, sent } => {
+                        events.push(Reverse((now + link.delay, Event::Ack { pn, sent })));
+                    }
+                    Event::Ack { pn, sent } => {
+                        let ack_frame = AckFrame {
+                            largest: VarInt::from_u64(pn).unwrap(),
+                            delay: VarInt::from_u32(0),
+                            first_range: VarInt::from_u32(0),
+                            ranges: vec![],
+                            ecn: None,
+                        };
+                        cc.on_ack_rcvd(Epoch::Data, &ack_frame, now);
+                        acked += MSS as u64;
+                        rtt_sum += now - sent;
+                    }
+                }
+            }
+            _ => {
+                // The timer fires once the time passes the timeout
+                now = timeout.expect("nothing in flight") + Duration::from_micros(1);
+                cc.on_loss_timeout(now);
+            }
+        }
+    }
+
+    let packets = (acked / MSS as u64).max(1) as u32;
+    Report {
+        throughput: (acked as f64 / duration.as_secs_f64()) as u64,
+        mean_rtt: rtt_sum / packets,
+        dropped,
+        lost: lost.0.load(Ordering::Relaxed),
+    }
+}
+
+#[derive(Default, Clone)]
+struct Lost(Arc<AtomicUsize>);
+
+impl MayLoss for Lost {
+    fn may_loss(&self, _: u64) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct Ignore;
+
+impl MayLoss for Ignore {
+    fn may_loss(&self, _: u64) {}
+}
+
+impl RetirePktRecord for Ignore {
+    fn retire(&self, _: u64) {}
+}
+
+// 10Mbps, 40ms RTT, the buffer is one BDP
+const LINK: Link = Link {
+    bandwidth: 1_250_000,
+    delay: Duration::from_millis(20),
+    buffer: 50_000,
+    loss: 0.0,
+    jitter: Duration::ZERO,
+};
+const DURATION: Duration = Duration::from_secs(20);
+const SECS_5: Duration = Duration::from_secs(5);
+
+fn run_all(link: Link) -> [Report; 3] {
+    [
+        CongestionAlgorithm::NewReno,
+        CongestionAlgorithm::Cubic,
+        CongestionAlgorithm::Bbr,
+    ]
+    .map(|algorithm| run(algorithm, LossDetectionConfig::default(), link, DURATION, 1))
+}
+
+#[test]
+fn deterministic() {
+    let link = Link { loss: 0.01, ..LINK };
+    for algorithm in [CongestionAlgorithm::NewReno, CongestionAlgorithm::Bbr] {
+        let first = run(algorithm.clone(), Default::default(), link, SECS_5, 7);
+        let second = run(algorithm, Default::default(), link, SECS_5, 7);
+        assert_eq!(first.throughput, second.throughput);
+        assert_eq!(first.dropped, second.dropped);
+    }
+}
+
+#[test]
+fn clean_link() {
+    let base_rtt = LINK.delay * 2;
+    // All the algorithms fill the link, the queuing delay is bounded by the buffer
+    for report in run_all(LINK) {
+        assert!(report.throughput >= LINK.bandwidth * 95 / 100, "{report:?}");
+        assert!(report.mean_rtt < base_rtt * 2, "{report:?}");
+    }
+}
+
+#[test]
+fn lossy_link() {
+    let link = Link { loss: 0.01, ..LINK };
+    let base_rtt = link.delay * 2;
+    let [reno, cubic, bbr] = run_all(link);
+    // The loss-based algorithms back off upon the random losses, and the queue stays empty
+    for report in [reno, cubic] {
+        assert!(report.throughput >= link.bandwidth / 4, "{report:?}");
+        assert!(report.mean_rtt < base_rtt * 5 / 4, "{report:?}");
+    }
+    assert!(cubic.throughput > reno.throughput);
+    // BBR is not sensitive to the random losses
+    assert!(bbr.throughput >= link.bandwidth * 95 / 100, "{bbr:?}");
+}
+
+#[test]
+fn reordering_link() {
+    // The packets are reordered by up to a quarter of the RTT, and nothing is dropped
+    let link = Link {
+        buffer: u64::MAX,
+        jitter: Duration::from_millis(10),
+        ..LINK
+    };
+    let adaptive = run(
+        CongestionAlgorithm::Cubic,
+        LossDetectionConfig::default(),
+        link,
+        DURATION,
+        1,
+    );
+    let fixed = run(
+        CongestionAlgorithm::Cubic,
+        LossDetectionConfig {
+            adaptive_reordering: false,
+            ..Default::default()
+        },
+        link,
+        DURATION,
+        1,
+    );
+    // All the losses are spurious, each of which reduces the window of the fixed thresholds
+    assert_eq!(adaptive.dropped + fixed.dropped, 0);
+    assert!(adaptive.lost * 4 < fixed.lost, "{adaptive:?} {fixed:?}");
+    assert!(
+        adaptive.throughput > fixed.throughput * 2,
+        "{adaptive:?} {fixed:?}"
+    );
+}
@@ -0,0 +1,300 @@
+use std::{collections::VecDeque, time::Instant};
+
+use crate::{
+    congestion::{AckedPkt, Algorithm, SentPkt, MSS},
+    hystart::HyStart,
+    CongestionState, CongestionWindowConfig,
+};
+
+// The same initial window as NewReno
+// See https://datatracker.ietf.org/doc/html/rfc9438#section-4.9
+const INIT_CWND: u64 = 10 * MSS as u64;
+const INFINITRE_SSTHRESH: u64 = u64::MAX;
+// The multiplicative decrease factor
+// See https://datatracker.ietf.org/doc/html/rfc9438#section-4.6
+const BETA_CUBIC: f64 = 0.7;
+// The constant that determines the aggressiveness of the window increase
+// See https://datatracker.ietf.org/doc/html/rfc9438#section-5.1
+const C_CUBIC: f64 = 0.4;
+// The additive increase factor of the Reno-friendly region
+// See https://datatracker.ietf.org/doc/html/rfc9438#section-4.3
+const ALPHA_CUBIC: f64 = 3.0 * (1.0 - BETA_CUBIC) / (1.0 + BETA_CUBIC);
+
+/// CUBIC congestion control, see [RFC 9438](https://www.rfc-editor.org/rfc/rfc9438.html).
+///
+/// The windows in the formulas of the RFC are in segments, they are converted to bytes by [`MSS`].
+pub(super) struct Cubic {
+    // Congestion window in bytes.
+    cwnd: u64,
+    // Slow start threshold in bytes.
+    ssthresh: u64,
+    // The minimum congestion window in bytes.
+    min_cwnd: u64,
+    // The window size just before the window is reduced in the last congestion event, in segments.
+    w_max: f64,
+    // The window size right before the last congestion event, in segments, w_max is this one
+    // unless the fast convergence lowers it.
+    cwnd_prior: f64,
+    // The time period that the window function takes to increase to w_max, in seconds.
+    k: f64,
+    // The estimate of the window of Reno, in segments.
+    w_est: f64,
+    // The time when the current congestion avoidance stage started.
+    epoch_start: Option<Instant>,
+    // The time at which the most recent loss recovery period started.
+    recovery_start_time: Option<Instant>,
+    // Whether no packet sent after the recovery period started has been acknowledged.
+    in_recovery: bool,
+    // Whether the window is underutilized since the sender has nothing more to send.
+    app_limited: bool,
+    // The delay-based slow start exit.
+    hystart: HyStart,
+}
+
+impl Cubic {
+    #[cfg(test)]
+    pub(super) fn new() -> Self {
+        Self::with_window(&CongestionWindowConfig::default())
+    }
+
+    pub(super) fn with_window(window: &CongestionWindowConfig) -> Self {
+        Cubic {
+            cwnd: window.initial(INIT_CWND),
+            ssthresh: INFINITRE_SSTHRESH,
+            min_cwnd: window.minimum(),
+            w_max: 0.0,
+            cwnd_prior: 0.0,
+            k: 0.0,
+            w_est: 0.0,
+            epoch_start: None,
+            recovery_start_time: None,
+            in_recovery: false,
+            app_limited: false,
+            hystart: HyStart::default(),
+        }
+    }
+
+    fn in_congestion_recovery(&self, sent_time: &Instant) -> bool {
+        self.recovery_start_time
+            .as_ref()
+            .is_some_and(|recovery_start_time| sent_time <= recovery_start_time)
+    }
+
+    fn segments(&self) -> f64 {
+        self.cwnd as f64 / MSS as f64
+    }
+
+    // W_cubic(t) = C * (t - K)^3 + W_max
+    fn w_cubic(&self, t: f64) -> f64 {
+        C_CUBIC * (t - self.k).powi(3) + self.w_max
+    }
+
+    fn on_per_ack(&mut self, ack: &AckedPkt, now: Instant) {
+        if self.in_congestion_recovery(&ack.time_sent) {
+            return;
+        }
+        self.in_recovery = false;
+        // The window is not grown while it's underutilized
+        // See https://datatracker.ietf.org/doc/html/rfc9438#section-5.8
+        if self.app_limited {
+            return;
+        }
+        // In slow start
+        if self.cwnd < self.ssthresh {
+            match self.hystart.on_ack(ack) {
+                Some(increase) => self.cwnd += increase,
+                None => self.ssthresh = self.cwnd,
+            }
+            return;
+        }
+
+        // Congestion avoidance
+        let epoch_start = *self.epoch_start.get_or_insert_with(|| {
+            // Enter the congestion avoidance without any congestion event
+            self.w_max = self.cwnd as f64 / MSS as f64;
+            self.cwnd_prior = self.w_max;
+            self.k = 0.0;
+            self.w_est = self.w_max;
+            now
+        });
+        let cwnd = self.segments();
+        let acked = ack.size as f64 / MSS as f64;
+        let t = now.saturating_duration_since(epoch_start).as_secs_f64();
+
+        // Reno grows at the same pace once its window reaches the one before the congestion event
+        // See https://datatracker.ietf.org/doc/html/rfc9438#section-4.3
+        let alpha = if self.w_est >= self.cwnd_prior {
+            1.0
+        } else {
+            ALPHA_CUBIC
+        };
+        self.w_est += alpha * acked / cwnd;
+        let w_cubic = self.w_cubic(t);
+        let new_cwnd = if w_cubic < self.w_est {
+            // Reno-friendly region
+            self.w_est
+        } else {
+            // Concave or convex region, the target is bounded in [cwnd, 1.5 * cwnd]
+            let target = self
+                .w_cubic(t + ack.rtt.as_secs_f64())
+                .clamp(cwnd, 1.5 * cwnd);
+            cwnd + (target - cwnd) * acked / cwnd
+        };
+        self.cwnd = self.cwnd.max((new_cwnd * MSS as f64) as u64);
+    }
+}
+
+impl Algorithm for Cubic {
+    fn on_sent(&mut self, _: &mut SentPkt, _: usize, now: Instant) {
+        self.hystart.on_sent(now);
+    }
+
+    fn on_ack(&mut self, packet: VecDeque<AckedPkt>, now: Instant) {
+        for acked in packet {
+            self.on_per_ack(&acked, now);
+        }
+    }
+
+    fn on_congestion_event(&mut self, lost: &SentPkt, now: Instant) {
+        if self.in_congestion_recovery(&lost.time_sent) {
+            return;
+        }
+        self.recovery_start_time = Some(now);
+        self.in_recovery = true;
+
+        let cwnd = self.segments();
+        self.cwnd_prior = cwnd;
+        // Fast convergence, release the bandwidth for the new flows
+        self.w_max = if cwnd < self.w_max {
+            cwnd * (1.0 + BETA_CUBIC) / 2.0
+        } else {
+            cwnd
+        };
+        self.ssthresh = ((self.cwnd as f64 * BETA_CUBIC) as u64).max(self.min_cwnd);
+        self.cwnd = self.ssthresh;
+        self.k = (self.w_max * (1.0 - BETA_CUBIC) / C_CUBIC).cbrt();
+        self.w_est = self.segments();
+        self.epoch_start = Some(now);
+    }
+
+    fn on_app_limited(&mut self, app_limited: bool) {
+        self.app_limited = app_limited;
+    }
+
+    fn on_persistent_congestion(&mut self, _: Instant) {
+        self.cwnd = self.min_cwnd;
+        self.recovery_start_time = None;
+        self.in_recovery = false;
+        // Restart the congestion avoidance like a new connection
+        self.epoch_start = None;
+    }
+
+    fn cwnd(&self) -> u64 {
+        self.cwnd
+    }
+
+    fn pacing_rate(&self) -> Option<u64> {
+        None
+    }
+
+    fn delivery_rate(&self) -> Option<u64> {
+        None
+    }
+
+    fn state(&self) -> CongestionState {
+        if self.in_recovery {
+            CongestionState::Recovery
+        } else if self.cwnd < self.ssthresh {
+            CongestionState::SlowStart
+        } else {
+            CongestionState::CongestionAvoidance
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_cubic_slow_start() {
+        let mut cubic = Cubic::new();
+        let now = Instant::now();
+        cubic.on_ack(generate_acks(0, 10, now), now);
+        assert_eq!(cubic.cwnd, 20 * MSS as u64);
+    }
+
+    #[test]
+    fn test_cubic_congestion_event() {
+        let mut cubic = Cubic::new();
+        let now = Instant::now();
+        cubic.on_ack(generate_acks(0, 10, now), now);
+
+        let lost = SentPkt {
+            pn: 11,
+            size: MSS,
+            time_sent: now,
+            ..Default::default()
+        };
+        let time_lost = now + Duration::from_millis(100);
+        cubic.on_congestion_event(&lost, time_lost);
+        assert_eq!(cubic.cwnd, 14 * MSS as u64);
+        assert_eq!(cubic.ssthresh, 14 * MSS as u64);
+        assert_eq!(cubic.w_max, 20.0);
+
+        // The packets sent before the recovery don't reduce the window again
+        cubic.on_congestion_event(&lost, time_lost + Duration::from_millis(10));
+        assert_eq!(cubic.cwnd, 14 * MSS as u64);
+
+        // Another congestion event before reaching w_max triggers the fast convergence
+        let lost = SentPkt {
+            time_sent: time_lost + Duration::from_millis(1),
+            ..lost
+        };
+        cubic.on_congestion_event(&lost, time_lost + Duration::from_millis(200));
+        assert!(cubic.w_max < 14.0);
+    }
+
+    #[test]
+    fn test_cubic_congestion_avoidance() {
+        let mut cubic = Cubic::new();
+        let now = Instant::now();
+        let lost = SentPkt {
+            size: MSS,
+            time_sent: now,
+            ..Default::default()
+        };
+        cubic.on_congestion_event(&lost, now);
+        let reduced = cubic.cwnd;
+
+        // The window grows towards w_max, and then beyond it as time goes by
+        let mut time = now;
+        let mut pn = 1;
+        for _ in 0..100 {
+            time += Duration::from_millis(100);
+            let n = (cubic.cwnd / MSS as u64) as usize;
+            cubic.on_ack(generate_acks(pn, pn + n, time), time);
+            pn += n;
+        }
+        assert!(cubic.cwnd > reduced);
+        assert!(cubic.cwnd as f64 > cubic.w_max * MSS as f64);
+    }
+
+    fn generate_acks(start: usize, end: usize, now: Instant) -> VecDeque<AckedPkt> {
+        let mut acks = VecDeque::with_capacity(end - start);
+        for i in start..end {
+            let sent = SentPkt {
+                pn: i as u64,
+                size: MSS,
+                time_sent: now,
+                ..Default::default()
+            };
+            let mut ack: AckedPkt = sent.into();
+            ack.rtt = Duration::from_millis(100);
+            acks.push_back(ack);
+        }
+        acks
+    }
+}
@@ -0,0 +1,191 @@
+use qbase::frame::{EcnCodepoint, EcnCounts};
+use qrecovery::space::Epoch;
+
+/// The number of packets marked with ECT(0) for testing whether the path supports ECN, the marking
+/// stops after them until the path is validated.
+const ECN_TESTING_PACKETS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EcnState {
+    /// Sending the first packets marked with ECT(0).
+    Testing,
+    /// The testing packets are sent, waiting for them to be acknowledged.
+    Unknown,
+    /// The path supports ECN, all packets are marked with ECT(0).
+    Capable,
+    /// The validation failed, the packets are not marked anymore.
+    Failed,
+}
+
+/// The ECN validation of a path, see [Section 13.4.2](https://www.rfc-editor.org/rfc/rfc9000.html#section-13.4.2)
+/// of RFC 9000.
+///
+/// Packets are marked with ECT(0) while testing or after the path is validated. The validation
+/// fails if the ECN counts in the ACK frames decrease, or do not cover the newly acknowledged
+/// packets marked with ECT(0), or if all the testing packets are lost. The increase of the CE
+/// count is a congestion signal.
+///
+/// The ECN counts of a packet number space are accumulated across all paths using the space, a
+/// path may see the increases caused by the others, which only makes the validation more lenient.
+#[derive(Debug)]
+pub(crate) struct Ecn {
+    state: EcnState,
+    /// Whether the packets being sent are marked with ECT(0), updated once per burst only, so
+    /// that the records agree with the datagrams actually sent.
+    marking: bool,
+    marked_sent: u64,
+    marked_lost: u64,
+    /// The largest ECN counts received in each packet number space.
+    counts: [EcnCounts; Epoch::count()],
+}
+
+impl Ecn {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: EcnState::Testing,
+            marking: true,
+            marked_sent: 0,
+            marked_lost: 0,
+            counts: [EcnCounts::default(); Epoch::count()],
+        }
+    }
+
+    /// The codepoint to mark the datagrams of the next burst.
+    pub(crate) fn codepoint(&mut self) -> Option<EcnCodepoint> {
+        if self.state == EcnState::Testing && self.marked_sent >= ECN_TESTING_PACKETS {
+            self.state = EcnState::Unknown;
+        }
+        self.marking = matches!(self.state, EcnState::Testing | EcnState::Capable);
+        self.marking.then_some(EcnCodepoint::Ect0)
+    }
+
+    /// Returns whether the packet being sent is marked with ECT(0).
+    pub(crate) fn on_sent(&mut self) -> bool {
+        if self.marking {
+            self.marked_sent += 1;
+        }
+        self.marking
+    }
+
+    pub(crate) fn on_lost(&mut self, is_marked: bool) {
+        if !is_marked {
+            return;
+        }
+        self.marked_lost += 1;
+        if self.state == EcnState::Unknown && self.marked_lost >= self.marked_sent {
+            self.state = EcnState::Failed;
+        }
+    }
+
+    /// Validate the ECN counts in an ACK frame which increases the largest acknowledged packet
+    /// number, `newly_acked_marked` is the number of newly acknowledged packets marked with ECT(0).
+    ///
+    /// Returns whether the CE count increases, which should be treated as a congestion event.
+    pub(crate) fn on_ack(
+        &mut self,
+        epoch: Epoch,
+        newly_acked_marked: u64,
+        counts: Option<EcnCounts>,
+    ) -> bool {
+        if self.state == EcnState::Failed {
+            return false;
+        }
+        let Some(counts) = counts else {
+            if newly_acked_marked > 0 {
+                self.state = EcnState::Failed;
+            }
+            return false;
+        };
+
+        let last = self.counts[epoch];
+        let (ect0, ect1, ce) = (
+            counts.ect0.into_inner(),
+            counts.ect1.into_inner(),
+            counts.ce.into_inner(),
+        );
+        let (last_ect0, last_ect1, last_ce) = (
+            last.ect0.into_inner(),
+            last.ect1.into_inner(),
+            last.ce.into_inner(),
+        );
+        // ECT(1) is never sent, and the counts never decrease
+        if ect0 < last_ect0 || ce < last_ce || ect1 != last_ect1 {
+            self.state = EcnState::Failed;
+            return false;
+        }
+        if (ect0 - last_ect0) + (ce - last_ce) < newly_acked_marked {
+            self.state = EcnState::Failed;
+            return false;
+        }
+
+        self.counts[epoch] = counts;
+        if newly_acked_marked > 0 && matches!(self.state, EcnState::Testing | EcnState::Unknown) {
+            self.state = EcnState::Capable;
+        }
+        ce > last_ce
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qbase::varint::VarInt;
+
+    use super::*;
+
+    fn counts(ect0: u32, ce: u32) -> Option<EcnCounts> {
+        Some(EcnCounts {
+            ect0: VarInt::from_u32(ect0),
+            ect1: VarInt::from_u32(0),
+            ce: VarInt::from_u32(ce),
+        })
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut ecn = Ecn::new();
+        for _ in 0..ECN_TESTING_PACKETS {
+            assert_eq!(ecn.codepoint(), Some(EcnCodepoint::Ect0));
+            assert!(ecn.on_sent());
+        }
+        // Stop marking until validated
+        assert_eq!(ecn.codepoint(), None);
+        assert!(!ecn.on_sent());
+
+        assert!(!ecn.on_ack(Epoch::Data, 2, counts(2, 0)));
+        assert_eq!(ecn.codepoint(), Some(EcnCodepoint::Ect0));
+        assert!(ecn.on_ack(Epoch::Data, 3, counts(4, 1)));
+        assert!(!ecn.on_ack(Epoch::Data, 1, counts(5, 1)));
+
+        // The counts decrease
+        assert!(!ecn.on_ack(Epoch::Data, 1, counts(4, 1)));
+        assert_eq!(ecn.codepoint(), None);
+    }
+
+    #[test]
+    fn test_validation_failed() {
+        // The marks are cleared by the path
+        let mut ecn = Ecn::new();
+        assert_eq!(ecn.codepoint(), Some(EcnCodepoint::Ect0));
+        ecn.on_sent();
+        ecn.on_sent();
+        ecn.on_ack(Epoch::Initial, 2, counts(1, 0));
+        assert_eq!(ecn.codepoint(), None);
+
+        // The peer does not report ECN counts
+        let mut ecn = Ecn::new();
+        ecn.codepoint();
+        ecn.on_sent();
+        ecn.on_ack(Epoch::Initial, 1, None);
+        assert_eq!(ecn.codepoint(), None);
+
+        // All the testing packets are lost
+        let mut ecn = Ecn::new();
+        while ecn.codepoint().is_some() {
+            ecn.on_sent();
+        }
+        for _ in 0..ECN_TESTING_PACKETS {
+            ecn.on_lost(true);
+        }
+        assert_eq!(ecn.state, EcnState::Failed);
+    }
+}
@@ -0,0 +1,178 @@
+use std::time::{Duration, Instant};
+
+use crate::congestion::{AckedPkt, MSS};
+
+// See https://datatracker.ietf.org/doc/html/rfc9406#section-4.3
+const MIN_RTT_THRESH: Duration = Duration::from_millis(4);
+const MAX_RTT_THRESH: Duration = Duration::from_millis(16);
+const MIN_RTT_DIVISOR: u32 = 8;
+const N_RTT_SAMPLE: usize = 8;
+const CSS_GROWTH_DIVISOR: u64 = 4;
+const CSS_ROUNDS: usize = 5;
+// The limit of the window increase per ACK, in segments, since the controllers are not paced
+const L: u64 = 8;
+
+/// HyStart++, the delay-based slow start exit of the loss-based controllers,
+/// see [RFC 9406](https://www.rfc-editor.org/rfc/rfc9406.html).
+///
+/// Once the RTT of a round trip increases noticeably over the last one, the slow start is
+/// slowed down into the conservative slow start, which lasts a few rounds before the congestion
+/// avoidance, unless the RTT decreases again.
+///
+/// The rounds are delimited by the sending time instead of the packet number, since the
+/// packets of the different packet number spaces share the controller.
+#[derive(Debug, Default)]
+pub(super) struct HyStart {
+    // The time when the last packet was sent.
+    last_sent_time: Option<Instant>,
+    // The round ends once the packet sent after this time is acknowledged.
+    window_end: Option<Instant>,
+    last_round_min_rtt: Option<Duration>,
+    current_round_min_rtt: Option<Duration>,
+    rtt_sample_count: usize,
+    // The minimum RTT when the conservative slow start is entered, None in the slow start.
+    css_baseline_min_rtt: Option<Duration>,
+    // The number of the rounds completed in the conservative slow start.
+    css_rounds: usize,
+}
+
+impl HyStart {
+    pub(super) fn on_sent(&mut self, now: Instant) {
+        self.last_sent_time = Some(now);
+    }
+
+    /// Called for each acknowledged packet in the slow start, returns how many bytes the window
+    /// should grow, or [`None`] if the slow start should be exited.
+    pub(super) fn on_ack(&mut self, ack: &AckedPkt) -> Option<u64> {
+        if self
+            .window_end
+            .is_none_or(|window_end| ack.time_sent > window_end)
+            && !self.start_round()
+        {
+            return None;
+        }
+
+        let current_round_min_rtt = self
+            .current_round_min_rtt
+            .map_or(ack.rtt, |rtt| rtt.min(ack.rtt));
+        self.current_round_min_rtt = Some(current_round_min_rtt);
+        self.rtt_sample_count += 1;
+
+        let increase = (ack.size as u64).min(L * MSS as u64);
+        match self.css_baseline_min_rtt {
+            None => {
+                if let Some(last_round_min_rtt) = self
+                    .last_round_min_rtt
+                    .filter(|_| self.rtt_sample_count >= N_RTT_SAMPLE)
+                {
+                    let rtt_thresh = (last_round_min_rtt / MIN_RTT_DIVISOR)
+                        .clamp(MIN_RTT_THRESH, MAX_RTT_THRESH);
+                    if current_round_min_rtt >= last_round_min_rtt + rtt_thresh {
+                        // Enter the conservative slow start
+                        self.css_baseline_min_rtt = Some(current_round_min_rtt);
+                        self.css_rounds = 0;
+                        return Some(increase / CSS_GROWTH_DIVISOR);
+                    }
+                }
+                Some(increase)
+            }
+            Some(css_baseline_min_rtt) => {
+                if self.rtt_sample_count >= N_RTT_SAMPLE
+                    && current_round_min_rtt < css_baseline_min_rtt
+                {
+                    // The RTT increase was spurious, resume the slow start
+                    self.css_baseline_min_rtt = None;
+                    return Some(increase);
+                }
+                Some(increase / CSS_GROWTH_DIVISOR)
+            }
+        }
+    }
+
+    // Returns false if the conservative slow start lasts enough rounds.
+    fn start_round(&mut self) -> bool {
+        self.window_end = self.last_sent_time;
+        self.last_round_min_rtt = self.current_round_min_rtt.take();
+        self.rtt_sample_count = 0;
+        if self.css_baseline_min_rtt.is_some() {
+            self.css_rounds += 1;
+            if self.css_rounds >= CSS_ROUNDS {
+                self.css_baseline_min_rtt = None;
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::congestion::SentPkt;
+
+    // Send a round of `n` packets and acknowledge them with the RTT `rtt`, returns the total
+    // increase, or None if the slow start is exited.
+    fn round(hystart: &mut HyStart, n: usize, rtt: Duration, now: &mut Instant) -> Option<u64> {
+        let time_sent = *now;
+        hystart.on_sent(time_sent);
+        *now += rtt;
+        let mut total = 0;
+        for pn in 0..n {
+            let mut ack: AckedPkt = SentPkt {
+                pn: pn as u64,
+                size: MSS,
+                time_sent,
+                ..Default::default()
+            }
+            .into();
+            ack.rtt = rtt;
+            total += hystart.on_ack(&ack)?;
+        }
+        Some(total)
+    }
+
+    #[test]
+    fn exit_slow_start() {
+        let mut hystart = HyStart::default();
+        let mut now = Instant::now();
+        let rtt = Duration::from_millis(100);
+        assert_eq!(
+            round(&mut hystart, 10, rtt, &mut now),
+            Some(10 * MSS as u64)
+        );
+        // The increase of RTT is less than the threshold, 12.5ms
+        let rtt = Duration::from_millis(110);
+        assert_eq!(
+            round(&mut hystart, 10, rtt, &mut now),
+            Some(10 * MSS as u64)
+        );
+
+        // Enter the conservative slow start after N_RTT_SAMPLE samples
+        let rtt = Duration::from_millis(130);
+        let increase = round(&mut hystart, 10, rtt, &mut now).unwrap();
+        assert_eq!(
+            increase,
+            7 * MSS as u64 + 3 * MSS as u64 / CSS_GROWTH_DIVISOR
+        );
+        assert_eq!(hystart.css_baseline_min_rtt, Some(rtt));
+
+        for _ in 1..CSS_ROUNDS {
+            let increase = round(&mut hystart, 10, rtt, &mut now).unwrap();
+            assert_eq!(increase, 10 * MSS as u64 / CSS_GROWTH_DIVISOR);
+        }
+        assert_eq!(round(&mut hystart, 10, rtt, &mut now), None);
+    }
+
+    #[test]
+    fn spurious_css() {
+        let mut hystart = HyStart::default();
+        let mut now = Instant::now();
+        round(&mut hystart, 10, Duration::from_millis(100), &mut now);
+        round(&mut hystart, 10, Duration::from_millis(130), &mut now);
+        assert!(hystart.css_baseline_min_rtt.is_some());
+
+        // The RTT drops below the baseline, back to the slow start
+        round(&mut hystart, 10, Duration::from_millis(120), &mut now);
+        assert_eq!(hystart.css_baseline_min_rtt, None);
+    }
+}
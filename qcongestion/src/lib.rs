@@ -1,21 +1,45 @@
 use std::{
+    sync::Arc,
     task::{Context, Poll},
     time::{Duration, Instant},
 };
 
-pub use congestion::{ArcCC, CongestionAlgorithm, MSS};
-use qbase::frame::AckFrame;
+pub use ack_policy::AckPolicy;
+pub use congestion::{AckedPkt, Algorithm, ArcCC, CongestionAlgorithm, SentPkt, MSS};
+pub use loss_detection::LossDetectionConfig;
+pub use observer::{CongestionState, Observer};
+pub use pacing::SendRateLimit;
+use qbase::frame::{AckFrame, AckFrequencyFrame, EcnCodepoint};
 use qrecovery::space::Epoch;
+pub use window::CongestionWindowConfig;
 
+mod ack_policy;
 mod bbr;
 mod congestion;
+mod cubic;
 mod delivery_rate;
+mod ecn;
+mod hystart;
+mod loss_detection;
 mod min_max;
 mod new_reno;
+mod observer;
 mod pacing;
 mod rtt;
+mod window;
 
 /// The [`CongestionControl`] trait defines the interface for congestion control algorithms.
+///
+/// Each path owns an independent instance, with its own RTT estimator, loss detection, ECN
+/// validation and congestion window, so a new path, including the one migrated to, starts from
+/// the initial state. The instances share nothing but the packet number spaces and this interface.
+///
+/// The connection-level flow control limits the new stream data sent on all paths together, while
+/// the congestion window of each path limits all the bytes in flight on it. A path blocked by the
+/// flow control leaves its window underutilized, which is reported by [`on_app_limited`], so the
+/// window does not grow without being tested.
+///
+/// [`on_app_limited`]: CongestionControl::on_app_limited
 pub trait CongestionControl {
     /// Performs a periodic tick to drive the congestion control algorithm.
     fn do_tick(&self);
@@ -30,6 +54,13 @@ pub trait CongestionControl {
     /// An [`Option`] containing the largest packet ID and the time it was received if an AckFrame is needed.
     fn need_ack(&self, space: Epoch) -> Option<(u64, Instant)>;
 
+    /// Checks if a probe packet should be sent in the given epoch upon the PTO.
+    /// # Returns
+    /// True if the next packet of the epoch must be ack-eliciting, a PING frame should be sent if
+    /// there is nothing else to send. The data of the oldest packets in flight has been queued for
+    /// retransmission, so the probes carry it if any.
+    fn need_probe(&self, space: Epoch) -> bool;
+
     /// Records the sending of a packet, which may affect congestion control state.
     /// # Parameters
     /// - `pn`: The packet number of the sent packet.
@@ -47,6 +78,27 @@ pub trait CongestionControl {
         ack: Option<u64>,
     );
 
+    /// Records the sending of a PMTU probe packet in the Data space, which is ack-eliciting and in
+    /// flight, but its loss is not considered a congestion signal.
+    fn on_mtu_probe_sent(&self, pn: u64, sent_bytes: usize);
+
+    /// Records whether the sending stopped because the sender has nothing more to send, while the
+    /// congestion window allows more, it should be called each time the sending stops. The
+    /// congestion window is not grown while it is underutilized, see [Section 7.8](https://www.rfc-editor.org/rfc/rfc9002.html#section-7.8)
+    /// of RFC 9002.
+    fn on_app_limited(&self, app_limited: bool);
+
+    /// Takes the PMTU probes that have been acknowledged or declared lost since the last call.
+    /// # Returns
+    /// The packet numbers of the probes, and whether each of them was acknowledged.
+    fn take_mtu_probe_outcomes(&self) -> Vec<(u64, bool)>;
+
+    /// The ECN codepoint to mark the datagrams of the next burst, the packets sent until the next
+    /// call are recorded as marked with it.
+    /// # Returns
+    /// [`EcnCodepoint::Ect0`] if the path is being tested or validated to support ECN, or [`None`].
+    fn ecn(&self) -> Option<EcnCodepoint>;
+
     /// Updates the congestion control state upon receiving an AckFrame.
     fn on_ack(&self, space: Epoch, ack_frame: &AckFrame);
 
@@ -54,7 +106,42 @@ pub trait CongestionControl {
     /// # Parameters
     /// - `pn`: The packet number of the received packet.
     /// - `is_ack_elicition`: A boolean indicating whether the received packet is ack-eliciting.
-    fn on_pkt_rcvd(&self, space: Epoch, pn: u64, is_ack_elicition: bool);
+    /// - `ecn`: The ECN codepoint of the datagram carrying the packet.
+    fn on_pkt_rcvd(&self, space: Epoch, pn: u64, is_ack_elicition: bool, ecn: Option<EcnCodepoint>);
+
+    /// Sets the strategy to acknowledge the received packets, see [`AckPolicy`].
+    fn set_ack_policy(&self, policy: AckPolicy);
+
+    /// Sets the max_ack_delay and ack_delay_exponent transport parameters of the peer, which are
+    /// used to sample the RTT from the ACK Delay field and to compute the PTO.
+    fn set_peer_ack_delay(&self, max_ack_delay: Duration, ack_delay_exponent: u8);
+
+    /// Sets the thresholds of the loss detection and the backoff of the PTO, see
+    /// [`LossDetectionConfig`].
+    fn set_loss_detection(&self, config: LossDetectionConfig);
+
+    /// Sets the hooks to observe the congestion window, the losses, the ECN-CE marks, the PTOs and
+    /// the state transitions of the controller, see [`Observer`].
+    fn set_observer(&self, observer: Arc<dyn Observer>);
+
+    /// Sets the maximum rate to send the packets, which is shared with the other paths of the
+    /// connection, see [`SendRateLimit`].
+    fn set_send_rate_limit(&self, limit: SendRateLimit);
+
+    /// Updates the acknowledgment behavior of the Data space upon receiving an ACK_FREQUENCY frame.
+    fn on_ack_frequency(&self, frame: &AckFrequencyFrame);
+
+    /// Sends an AckFrame in the Data space without delay upon receiving an IMMEDIATE_ACK frame.
+    fn on_immediate_ack(&self);
+
+    /// Enables requesting the peer to send fewer ACKs, once the peer advertised its min_ack_delay.
+    fn enable_ack_frequency(&self, peer_min_ack_delay: Duration);
+
+    /// Checks if an AckFrequencyFrame should be sent to adjust the acknowledgment rate of the peer
+    /// to the congestion window.
+    /// # Returns
+    /// An [`Option`] containing the AckFrequencyFrame to send, the frame is considered sent once returned.
+    fn need_ack_frequency(&self) -> Option<AckFrequencyFrame>;
 
     /// Retrieves the current path's PTO duration.
     /// # Returns
@@ -66,6 +153,26 @@ pub trait CongestionControl {
 
     /// Indicates that the handshake process has been completed.
     fn on_handshake_done(&self);
+
+    /// Retrieves the current smoothed RTT of the path.
+    fn smoothed_rtt(&self) -> Duration;
+
+    /// Retrieves the minimum RTT observed on the path, zero if no RTT sample has been taken.
+    fn min_rtt(&self) -> Duration;
+
+    /// Retrieves the current congestion window in bytes.
+    fn cwnd(&self) -> u64;
+
+    /// Retrieves the current pacing rate of the path in bytes per second, capped by the send rate
+    /// limit.
+    fn pacing_rate(&self) -> u64;
+
+    /// Retrieves the bandwidth estimated from the delivery rate samples in bytes per second,
+    /// [`None`] if the congestion control algorithm does not sample it.
+    fn delivery_rate(&self) -> Option<u64>;
+
+    /// Retrieves the number of bytes sent in packets that are in flight and not yet acknowledged or lost.
+    fn bytes_in_flight(&self) -> usize;
 }
 
 /// The [`MayLoss`] trait is used to handle potential packet losses.
@@ -0,0 +1,216 @@
+use std::{collections::VecDeque, time::Duration};
+
+use qrecovery::space::Epoch;
+
+// The reordering window grows by min_rtt/4 for each spurious loss, up to this many times.
+const MAX_REO_WND_MULT: u32 = 8;
+// The reordering window shrinks back after this many loss events without spurious loss.
+const REO_WND_PERSIST: u32 = 16;
+// The packet threshold adapted to the reordering distance is capped by this.
+const MAX_PACKET_THRESHOLD: u64 = 64;
+// The number of the packets declared lost remembered in each space, to tell the spurious losses.
+const MAX_LOST_RECORDS: usize = 64;
+
+/// The tunables to detect the lost packets and to schedule the probe timeout on a path, see
+/// [Section 6](https://www.rfc-editor.org/rfc/rfc9002.html#section-6) of RFC 9002.
+///
+/// The defaults are the recommended values of RFC 9002. The networks reordering the packets
+/// heavily may use the larger thresholds to avoid the spurious retransmissions, and the ones
+/// with long and varying RTTs, such as satellite links, may cap the backoff of the PTO.
+///
+/// The thresholds are the lower bounds if the reordering is adapted to, see
+/// [`LossDetectionConfig::adaptive_reordering`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LossDetectionConfig {
+    /// A packet is declared lost once a packet sent this many packets after it is acknowledged,
+    /// kPacketThreshold of RFC 9002, which recommends no less than 3.
+    pub packet_threshold: u64,
+    /// A packet is declared lost once it was sent this many RTTs before an acknowledged packet,
+    /// kTimeThreshold of RFC 9002, it should not be less than 1.
+    pub time_threshold: f32,
+    /// The PTO duration is multiplied by this factor for each consecutive PTO, 2 by default.
+    pub pto_backoff: u32,
+    /// The PTO duration stops growing after this many consecutive PTOs, so the probes are still
+    /// sent in time after a long outage. The PTO backs off without the limit by default.
+    pub max_pto_backoffs: u32,
+    /// Once the packets are observed reordered, the thresholds adapt to the reordering like
+    /// RACK-TLP, see [Section 6.2](https://www.rfc-editor.org/rfc/rfc8985.html#section-6.2) of
+    /// RFC 8985, enabled by default:
+    ///
+    /// - the packet threshold grows to the largest reordering distance observed;
+    /// - the time threshold is widened by a reordering window, a quarter of the min RTT, which
+    ///   grows for each spurious loss, a packet acknowledged after declared lost, and shrinks
+    ///   back after 16 loss events without that. The window is no larger than the smoothed RTT.
+    pub adaptive_reordering: bool,
+}
+
+impl Default for LossDetectionConfig {
+    fn default() -> Self {
+        Self {
+            packet_threshold: 3,
+            time_threshold: 9.0 / 8.0,
+            pto_backoff: 2,
+            max_pto_backoffs: u32::MAX,
+            adaptive_reordering: true,
+        }
+    }
+}
+
+impl LossDetectionConfig {
+    /// The factor to multiply the PTO duration by after `pto_count` consecutive PTOs.
+    pub(crate) fn pto_factor(&self, pto_count: u32) -> u32 {
+        self.pto_backoff
+            .saturating_pow(pto_count.min(self.max_pto_backoffs))
+    }
+}
+
+/// The reordering observed on a path, which the loss detection adapts to, see
+/// [`LossDetectionConfig::adaptive_reordering`].
+#[derive(Debug, Default)]
+pub(crate) struct Reordering {
+    // The largest reordering distance observed, in packets.
+    distance: u64,
+    // Whether any reordering has been observed, the reordering window is zero until then.
+    is_observed: bool,
+    // The multiplier of the reordering window, the window is min_rtt/4 * (mult + 1).
+    mult: u32,
+    // The loss events since the last spurious loss.
+    loss_events: u32,
+    // The packets declared lost recently in each space, in ascending order.
+    lost: [VecDeque<u64>; Epoch::count()],
+}
+
+impl Reordering {
+    /// Called when the packet `pn` is newly acknowledged, while a packet after it has been.
+    pub(crate) fn on_reordered(&mut self, largest_acked: u64, pn: u64) {
+        self.is_observed = true;
+        self.distance = self.distance.max(largest_acked.saturating_sub(pn));
+    }
+
+    pub(crate) fn on_lost(&mut self, space: Epoch, pn: u64) {
+        let lost = &mut self.lost[space];
+        if let Err(index) = lost.binary_search(&pn) {
+            lost.insert(index, pn);
+        }
+        if lost.len() > MAX_LOST_RECORDS {
+            lost.pop_front();
+        }
+    }
+
+    /// Called when the packet `pn` not in flight is acknowledged, returns whether it was declared
+    /// lost spuriously, which means it's reordered.
+    pub(crate) fn on_acked(&mut self, space: Epoch, largest_acked: u64, pn: u64) -> bool {
+        let Ok(index) = self.lost[space].binary_search(&pn) else {
+            return false;
+        };
+        self.lost[space].remove(index);
+        self.on_reordered(largest_acked, pn);
+        self.mult = (self.mult + 1).min(MAX_REO_WND_MULT);
+        self.loss_events = 0;
+        true
+    }
+
+    /// Called when some packets are declared lost upon an ACK or a timeout.
+    pub(crate) fn on_loss_event(&mut self) {
+        self.loss_events += 1;
+        if self.loss_events >= REO_WND_PERSIST {
+            self.mult = 0;
+            self.loss_events = 0;
+        }
+    }
+
+    pub(crate) fn discard_space(&mut self, space: Epoch) {
+        self.lost[space].clear();
+    }
+
+    pub(crate) fn packet_threshold(&self, config: &LossDetectionConfig) -> u64 {
+        if !config.adaptive_reordering {
+            return config.packet_threshold;
+        }
+        let adapted = (self.distance + 1).min(MAX_PACKET_THRESHOLD);
+        config.packet_threshold.max(adapted)
+    }
+
+    /// The reordering window to widen the time threshold.
+    pub(crate) fn window(
+        &self,
+        config: &LossDetectionConfig,
+        min_rtt: Duration,
+        smoothed_rtt: Duration,
+    ) -> Duration {
+        if !config.adaptive_reordering || !self.is_observed {
+            return Duration::ZERO;
+        }
+        (min_rtt / 4 * (self.mult + 1)).min(smoothed_rtt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pto_factor() {
+        let config = LossDetectionConfig::default();
+        assert_eq!(config.pto_factor(0), 1);
+        assert_eq!(config.pto_factor(3), 8);
+        assert_eq!(config.pto_factor(40), u32::MAX);
+
+        let config = LossDetectionConfig {
+            pto_backoff: 3,
+            max_pto_backoffs: 2,
+            ..config
+        };
+        assert_eq!(config.pto_factor(1), 3);
+        assert_eq!(config.pto_factor(5), 9);
+    }
+
+    #[test]
+    fn reordering() {
+        let config = LossDetectionConfig::default();
+        let (min_rtt, srtt) = (Duration::from_millis(40), Duration::from_millis(50));
+        let mut reordering = Reordering::default();
+        assert_eq!(reordering.packet_threshold(&config), 3);
+        assert_eq!(reordering.window(&config, min_rtt, srtt), Duration::ZERO);
+
+        reordering.on_reordered(10, 5);
+        assert_eq!(reordering.packet_threshold(&config), 6);
+        assert_eq!(
+            reordering.window(&config, min_rtt, srtt),
+            Duration::from_millis(10)
+        );
+
+        // The spurious losses widen the window, up to the smoothed RTT
+        reordering.on_lost(Epoch::Data, 3);
+        reordering.on_lost(Epoch::Data, 1);
+        assert!(!reordering.on_acked(Epoch::Data, 10, 2));
+        assert!(reordering.on_acked(Epoch::Data, 10, 3));
+        assert!(reordering.on_acked(Epoch::Data, 10, 1));
+        assert!(!reordering.on_acked(Epoch::Data, 10, 1));
+        assert_eq!(
+            reordering.window(&config, min_rtt, srtt),
+            Duration::from_millis(30)
+        );
+        for _ in 0..MAX_REO_WND_MULT {
+            reordering.on_lost(Epoch::Data, 20);
+            reordering.on_acked(Epoch::Data, 30, 20);
+        }
+        assert_eq!(reordering.window(&config, min_rtt, srtt), srtt);
+
+        // And shrinks back after the loss events without spurious loss
+        for _ in 0..REO_WND_PERSIST {
+            reordering.on_loss_event();
+        }
+        assert_eq!(
+            reordering.window(&config, min_rtt, srtt),
+            Duration::from_millis(10)
+        );
+
+        let config = LossDetectionConfig {
+            adaptive_reordering: false,
+            ..config
+        };
+        assert_eq!(reordering.packet_threshold(&config), 3);
+        assert_eq!(reordering.window(&config, min_rtt, srtt), Duration::ZERO);
+    }
+}
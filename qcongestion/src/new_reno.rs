@@ -1,6 +1,10 @@
 use std::{collections::VecDeque, time::Instant};
 
-use crate::congestion::{AckedPkt, Algorithm, MSS};
+use crate::{
+    congestion::{AckedPkt, Algorithm, MSS},
+    hystart::HyStart,
+    CongestionState, CongestionWindowConfig,
+};
 
 // The upper bound for the initial window will be
 // min (10*MSS, max (2*MSS, 14600))
@@ -14,20 +18,37 @@ pub(super) struct NewReno {
     cwnd: u64,
     // Slow start threshold.
     ssthresh: u64,
+    // The minimum congestion window.
+    min_cwnd: u64,
     // The number of bytes that have been ACKed.
     // https://datatracker.ietf.org/doc/html/rfc3465#autoid-3
     bytes_acked: u64,
     // The time at which the most recent loss recovery period started.
     recovery_start_time: Option<Instant>,
+    // Whether no packet sent after the recovery period started has been acknowledged.
+    in_recovery: bool,
+    // Whether the window is underutilized since the sender has nothing more to send.
+    app_limited: bool,
+    // The delay-based slow start exit.
+    hystart: HyStart,
 }
 
 impl NewReno {
+    #[cfg(test)]
     pub(super) fn new() -> Self {
+        Self::with_window(&CongestionWindowConfig::default())
+    }
+
+    pub(super) fn with_window(window: &CongestionWindowConfig) -> Self {
         NewReno {
-            cwnd: INIT_CWND,
+            cwnd: window.initial(INIT_CWND),
             ssthresh: INFINITRE_SSTHRESH,
+            min_cwnd: window.minimum(),
             bytes_acked: 0,
             recovery_start_time: None,
+            in_recovery: false,
+            app_limited: false,
+            hystart: HyStart::default(),
         }
     }
 
@@ -42,9 +63,17 @@ impl NewReno {
         if self.in_congestion_recovery(&ack.time_sent) {
             return;
         }
+        self.in_recovery = false;
+        // The window is not grown while it's underutilized
+        if self.app_limited {
+            return;
+        }
         // In slow start
         if self.cwnd < self.ssthresh {
-            self.cwnd += ack.size as u64;
+            match self.hystart.on_ack(ack) {
+                Some(increase) => self.cwnd += increase,
+                None => self.ssthresh = self.cwnd,
+            }
 
             if self.cwnd >= self.ssthresh {
                 // Exiting slow start
@@ -65,7 +94,9 @@ impl NewReno {
 }
 
 impl Algorithm for NewReno {
-    fn on_sent(&mut self, _: &mut crate::congestion::SentPkt, _: usize, _: std::time::Instant) {}
+    fn on_sent(&mut self, _: &mut crate::congestion::SentPkt, _: usize, now: std::time::Instant) {
+        self.hystart.on_sent(now);
+    }
 
     fn on_ack(&mut self, packet: VecDeque<AckedPkt>, _: std::time::Instant) {
         for acked in packet {
@@ -78,13 +109,25 @@ impl Algorithm for NewReno {
             return;
         }
         self.recovery_start_time = Some(now);
+        self.in_recovery = true;
         self.cwnd = (self.cwnd as f64 * LOSS_REDUCTION_FACTOR) as u64;
-        self.cwnd = self.cwnd.max(2 * MSS as u64);
+        self.cwnd = self.cwnd.max(self.min_cwnd);
 
         self.bytes_acked = (self.bytes_acked as f64 * LOSS_REDUCTION_FACTOR) as u64;
         self.ssthresh = self.cwnd;
     }
 
+    fn on_app_limited(&mut self, app_limited: bool) {
+        self.app_limited = app_limited;
+    }
+
+    fn on_persistent_congestion(&mut self, _: std::time::Instant) {
+        self.cwnd = self.min_cwnd;
+        self.bytes_acked = 0;
+        self.recovery_start_time = None;
+        self.in_recovery = false;
+    }
+
     fn cwnd(&self) -> u64 {
         self.cwnd
     }
@@ -92,6 +135,20 @@ impl Algorithm for NewReno {
     fn pacing_rate(&self) -> Option<u64> {
         None
     }
+
+    fn delivery_rate(&self) -> Option<u64> {
+        None
+    }
+
+    fn state(&self) -> CongestionState {
+        if self.in_recovery {
+            CongestionState::Recovery
+        } else if self.cwnd < self.ssthresh {
+            CongestionState::SlowStart
+        } else {
+            CongestionState::CongestionAvoidance
+        }
+    }
 }
 
 #[cfg(test)]
@@ -108,6 +165,18 @@ mod tests {
         assert_eq!(reno.recovery_start_time, None);
     }
 
+    #[test]
+    fn test_reno_window_config() {
+        let window = CongestionWindowConfig {
+            initial_window: Some(32),
+            minimum_window: 4,
+        };
+        let mut reno = NewReno::with_window(&window);
+        assert_eq!(reno.cwnd, 32 * MSS as u64);
+        reno.on_persistent_congestion(Instant::now());
+        assert_eq!(reno.cwnd, 4 * MSS as u64);
+    }
+
     #[test]
     fn test_reno_slow_start() {
         let mut reno = NewReno::new();
@@ -144,6 +213,19 @@ mod tests {
         assert_eq!(reno.cwnd, pre_cwnd + MSS as u64);
     }
 
+    #[test]
+    fn test_reno_app_limited() {
+        let mut reno = NewReno::new();
+        let now = Instant::now();
+        reno.on_app_limited(true);
+        reno.on_ack(generate_acks(0, 10), now);
+        assert_eq!(reno.cwnd, INIT_CWND);
+
+        reno.on_app_limited(false);
+        reno.on_ack(generate_acks(10, 20), now);
+        assert_eq!(reno.cwnd, INIT_CWND + 10 * MSS as u64);
+    }
+
     #[test]
     fn test_reno_congestion_event() {
         let mut reno = NewReno::new();
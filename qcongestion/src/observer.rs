@@ -0,0 +1,54 @@
+use qrecovery::space::Epoch;
+
+use crate::SentPkt;
+
+/// The phases of the congestion controller, reported by [`Algorithm::state`].
+///
+/// [`Algorithm::state`]: crate::Algorithm::state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CongestionState {
+    /// The window grows exponentially, until the network capacity is approached, the Startup of BBR.
+    SlowStart,
+    /// The window grows slowly or is kept around the estimated capacity of the network.
+    CongestionAvoidance,
+    /// The window is reduced upon a congestion event, and is not grown until a packet sent after
+    /// that is acknowledged.
+    Recovery,
+}
+
+/// The hooks to observe the congestion controller of a path, without hard-coding the logging or
+/// the metrics into the controller, see [`CongestionControl::set_observer`].
+///
+/// The hooks are called with the controller locked, they should return quickly and must not call
+/// back into the controller. All the hooks do nothing by default.
+///
+/// [`CongestionControl::set_observer`]: crate::CongestionControl::set_observer
+pub trait Observer: Send + Sync {
+    /// Called when the congestion window changes, with the new window and the bytes in flight.
+    fn on_cwnd_updated(&self, cwnd: u64, bytes_in_flight: usize) {
+        let _ = (cwnd, bytes_in_flight);
+    }
+
+    /// Called for each packet declared lost in the packet number space, including the PMTU
+    /// probes, whose loss is not a congestion signal.
+    fn on_packet_lost(&self, space: Epoch, lost: &SentPkt) {
+        let _ = (space, lost);
+    }
+
+    /// Called when the peer reports the increase of the ECN-CE count in the space, the `largest_acked`
+    /// is the packet the controller responds to the congestion with.
+    fn on_ecn_ce(&self, space: Epoch, largest_acked: &SentPkt) {
+        let _ = (space, largest_acked);
+    }
+
+    /// Called when the probe timeout fires in the space, `pto_count` is the number of the
+    /// consecutive PTOs including this one.
+    fn on_pto(&self, space: Epoch, pto_count: u32) {
+        let _ = (space, pto_count);
+    }
+
+    /// Called when the controller transitions from the phase `old` to `new`.
+    fn on_state_changed(&self, old: CongestionState, new: CongestionState) {
+        let _ = (old, new);
+    }
+}
@@ -1,7 +1,13 @@
-use std::time::{Duration, Instant};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 //  The burst  interval in milliseconds
 const BURST_INTERVAL: Duration = Duration::from_millis(1);
+// The bytes of this interval can be sent in a burst under the send rate limit, the paths are
+// ticked to send at this interval at least.
+const RATE_LIMIT_BURST_INTERVAL: Duration = Duration::from_millis(10);
 const MIN_BURST_SIZE: u64 = 10;
 const MAX_BURST_SIZE: u64 = 128;
 // Using a value for N that is small, but at least 1 (for example, 1.25)
@@ -60,12 +66,7 @@ impl Pacer {
             return mtu;
         }
 
-        let rate = match rate {
-            Some(r) => r,
-            // RFC 9002 7.7. Pacing
-            // rate = N * congestion_window / smoothed_rtt
-            None => (N * cwnd as f64 / srtt.as_secs_f64()) as u64,
-        };
+        let rate = Pacer::pacing_rate(srtt, cwnd, rate);
 
         // Update the last_burst_time and tokens
         let elapsed = now.duration_since(self.last_burst_time);
@@ -79,6 +80,17 @@ impl Pacer {
         self.tokens.min(mtu as u64) as usize
     }
 
+    // The pacing rate in bytes per second, derived from the congestion window if the algorithm
+    // doesn't provide one.
+    pub(super) fn pacing_rate(srtt: Duration, cwnd: u64, rate: Option<u64>) -> u64 {
+        match rate {
+            Some(r) => r,
+            // RFC 9002 7.7. Pacing
+            // rate = N * congestion_window / smoothed_rtt
+            None => (N * cwnd as f64 / srtt.as_secs_f64()) as u64,
+        }
+    }
+
     fn calculate_capacity(smoothed_rtt: Duration, cwnd: u64, mtu: usize, rate: Option<u64>) -> u64 {
         let rtt = smoothed_rtt.as_nanos().max(1);
 
@@ -94,6 +106,81 @@ impl Pacer {
     }
 }
 
+#[derive(Debug)]
+struct TokenBucket {
+    rate: Option<u64>,
+    tokens: u64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn capacity(&self, rate: u64, mtu: usize) -> u64 {
+        ((rate as f64 * RATE_LIMIT_BURST_INTERVAL.as_secs_f64()) as u64).max(mtu as u64)
+    }
+}
+
+/// The maximum rate to send the packets of a connection, in bytes per second.
+///
+/// The limit is shared by all the paths of the connection, and enforced by their pacers
+/// independent of the congestion windows, so that a server can throttle a connection without
+/// relying on the congestion signals. It's unlimited by default.
+#[derive(Debug, Clone)]
+pub struct SendRateLimit(Arc<Mutex<TokenBucket>>);
+
+impl Default for SendRateLimit {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl SendRateLimit {
+    /// Create a limit of `rate` bytes per second, [`None`] for no limit.
+    pub fn new(rate: Option<u64>) -> Self {
+        let bucket = TokenBucket {
+            rate: None,
+            tokens: 0,
+            last_refill: Instant::now(),
+        };
+        let limit = Self(Arc::new(Mutex::new(bucket)));
+        limit.set_rate(rate);
+        limit
+    }
+
+    /// Change the maximum rate, which takes effect on all the paths immediately.
+    pub fn set_rate(&self, rate: Option<u64>) {
+        let mut bucket = self.0.lock().unwrap();
+        bucket.rate = rate;
+        bucket.tokens = rate.map_or(0, |rate| bucket.capacity(rate, crate::MSS));
+        bucket.last_refill = Instant::now();
+    }
+
+    /// The maximum rate in bytes per second, [`None`] if unlimited.
+    pub fn rate(&self) -> Option<u64> {
+        self.0.lock().unwrap().rate
+    }
+
+    // The bytes allowed to be sent now, None if unlimited.
+    pub(super) fn available(&self, mtu: usize, now: Instant) -> Option<usize> {
+        let mut bucket = self.0.lock().unwrap();
+        let rate = bucket.rate?;
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        let new_tokens = (elapsed.as_secs_f64() * rate as f64) as u64;
+        bucket.tokens = bucket
+            .tokens
+            .saturating_add(new_tokens)
+            .min(bucket.capacity(rate, mtu));
+        bucket.last_refill = bucket.last_refill.max(now);
+        Some(bucket.tokens as usize)
+    }
+
+    pub(super) fn on_sent(&self, packet_size: u64) {
+        let mut bucket = self.0.lock().unwrap();
+        if bucket.rate.is_some() {
+            bucket.tokens = bucket.tokens.saturating_sub(packet_size);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::{Duration, Instant};
@@ -187,6 +274,36 @@ mod tests {
         assert_eq!(packet_size, 1500);
     }
 
+    #[test]
+    fn test_pacing_rate() {
+        let srtt = Duration::from_millis(100);
+        // 1.25 * 12000 / 0.1
+        assert_eq!(Pacer::pacing_rate(srtt, 12_000, None), 150_000);
+        assert_eq!(Pacer::pacing_rate(srtt, 12_000, Some(1_000)), 1_000);
+    }
+
+    #[test]
+    fn test_send_rate_limit() {
+        let limit = SendRateLimit::default();
+        let now = Instant::now();
+        assert_eq!(limit.available(1500, now), None);
+
+        // 1MB/s, 10 KB in a burst at most
+        limit.set_rate(Some(1_000_000));
+        let now = Instant::now();
+        assert_eq!(limit.available(1500, now), Some(10_000));
+        limit.on_sent(9_000);
+        assert_eq!(limit.available(1500, now), Some(1_000));
+        let shared = limit.clone();
+        shared.on_sent(1_500);
+        assert_eq!(limit.available(1500, now), Some(0));
+
+        let now = now + Duration::from_millis(2);
+        assert_eq!(limit.available(1500, now), Some(2_000));
+        let now = now + Duration::from_secs(1);
+        assert_eq!(limit.available(1500, now), Some(10_000));
+    }
+
     #[test]
     fn test_schedule_with_rate() {
         let srtt = Duration::from_millis(100);
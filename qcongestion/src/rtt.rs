@@ -5,50 +5,60 @@ use std::{
 
 pub const INITIAL_RTT: Duration = Duration::from_millis(333);
 const GRANULARITY: Duration = Duration::from_millis(1);
-const TIME_THRESHOLD: f32 = 1.125;
 
 #[derive(Debug, Clone)]
 pub struct RawRtt {
-    max_ack_delay: Duration,
     first_rtt_sample: Option<Instant>,
     latest_rtt: Duration,
     smoothed_rtt: Duration,
     rttvar: Duration,
     min_rtt: Duration,
     is_handshake_confirmed: bool,
+    // The min_rtt is set to the next RTT sample after the persistent congestion.
+    reset_min_rtt: bool,
 }
 
 impl Default for RawRtt {
     fn default() -> Self {
         Self {
-            max_ack_delay: Duration::from_millis(0),
             first_rtt_sample: None,
             latest_rtt: Duration::from_millis(0),
             smoothed_rtt: INITIAL_RTT,
             rttvar: INITIAL_RTT / 2,
             min_rtt: Duration::from_millis(0),
             is_handshake_confirmed: false,
+            reset_min_rtt: false,
         }
     }
 }
 
 impl RawRtt {
-    fn update(&mut self, latest_rtt: Duration, mut ack_delay: Duration) {
+    fn update(
+        &mut self,
+        latest_rtt: Duration,
+        mut ack_delay: Duration,
+        max_ack_delay: Duration,
+        now: Instant,
+    ) {
         self.latest_rtt = latest_rtt;
         if self.first_rtt_sample.is_none() {
             self.min_rtt = latest_rtt;
             self.smoothed_rtt = latest_rtt;
             self.rttvar = latest_rtt / 2;
-            self.first_rtt_sample = Some(Instant::now());
+            self.first_rtt_sample = Some(now);
             return;
         }
 
         // min_rtt ignores acknowledgment delay.
-        self.min_rtt = std::cmp::min(self.min_rtt, latest_rtt);
+        if std::mem::take(&mut self.reset_min_rtt) {
+            self.min_rtt = latest_rtt;
+        } else {
+            self.min_rtt = std::cmp::min(self.min_rtt, latest_rtt);
+        }
 
         // Limit ack_delay by max_ack_delay after handshake confirmation.
         if self.is_handshake_confirmed {
-            ack_delay = std::cmp::min(ack_delay, self.max_ack_delay);
+            ack_delay = std::cmp::min(ack_delay, max_ack_delay);
         }
 
         // Adjust for acknowledgment delay if plausible.
@@ -70,9 +80,9 @@ impl RawRtt {
         self.is_handshake_confirmed = true;
     }
 
-    fn loss_delay(&self) -> Duration {
+    fn loss_delay(&self, time_threshold: f32) -> Duration {
         std::cmp::max(
-            std::cmp::max(self.latest_rtt, self.smoothed_rtt).mul_f32(TIME_THRESHOLD),
+            std::cmp::max(self.latest_rtt, self.smoothed_rtt).mul_f32(time_threshold),
             GRANULARITY,
         )
     }
@@ -87,12 +97,34 @@ impl ArcRtt {
         Self(Arc::new(Mutex::new(RawRtt::default())))
     }
 
-    pub fn update(&self, latest_rtt: Duration, ack_delay: Duration) {
-        self.0.lock().unwrap().update(latest_rtt, ack_delay);
+    /// Update the RTT estimates with the RTT sample `latest_rtt`, the `ack_delay` reported by the
+    /// peer is limited by the peer's `max_ack_delay` once the handshake is confirmed.
+    pub fn update(
+        &self,
+        latest_rtt: Duration,
+        ack_delay: Duration,
+        max_ack_delay: Duration,
+        now: Instant,
+    ) {
+        self.0
+            .lock()
+            .unwrap()
+            .update(latest_rtt, ack_delay, max_ack_delay, now);
+    }
+
+    pub fn loss_delay(&self, time_threshold: f32) -> Duration {
+        self.0.lock().unwrap().loss_delay(time_threshold)
     }
 
-    pub fn loss_delay(&self) -> Duration {
-        self.0.lock().unwrap().loss_delay()
+    /// The time when the first RTT sample was taken.
+    pub fn first_rtt_sample(&self) -> Option<Instant> {
+        self.0.lock().unwrap().first_rtt_sample
+    }
+
+    /// The min_rtt may be too small for the network changed, it is set to the next RTT sample,
+    /// see [Section 5.2](https://www.rfc-editor.org/rfc/rfc9002.html#section-5.2) of RFC 9002.
+    pub fn on_persistent_congestion(&self) {
+        self.0.lock().unwrap().reset_min_rtt = true;
     }
 
     pub fn on_handshake_done(&self) {
@@ -106,6 +138,10 @@ impl ArcRtt {
     pub fn rttvar(&self) -> Duration {
         self.0.lock().unwrap().rttvar
     }
+
+    pub fn min_rtt(&self) -> Duration {
+        self.0.lock().unwrap().min_rtt
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,68 @@
+use crate::MSS;
+
+/// The bounds of the congestion window of a path, in packets of [`MSS`] bytes.
+///
+/// The controlled environments such as datacenters may start with a larger initial window to
+/// finish the short transfers in fewer round trips, while the constrained networks should keep
+/// the defaults of RFC 9002. They apply to the built-in algorithms only, the algorithms of
+/// [`CongestionAlgorithm::Custom`] decide their own windows.
+///
+/// [`CongestionAlgorithm::Custom`]: crate::CongestionAlgorithm::Custom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CongestionWindowConfig {
+    /// The congestion window before any feedback from the peer. [`None`] for the default of the
+    /// algorithm, 10 packets for NewReno and CUBIC as recommended by RFC 9002, and 80 for BBR.
+    pub initial_window: Option<u64>,
+    /// The congestion window never drops below it, and collapses to it upon the persistent
+    /// congestion, kMinimumWindow of RFC 9002, which recommends 2.
+    pub minimum_window: u64,
+}
+
+impl Default for CongestionWindowConfig {
+    fn default() -> Self {
+        Self {
+            initial_window: None,
+            minimum_window: 2,
+        }
+    }
+}
+
+impl CongestionWindowConfig {
+    /// The initial window in bytes, `default` is the one of the algorithm in bytes.
+    pub(crate) fn initial(&self, default: u64) -> u64 {
+        self.initial_window
+            .map_or(default, |packets| packets * MSS as u64)
+            .max(self.minimum())
+    }
+
+    /// The minimum window in bytes, one packet at least.
+    pub(crate) fn minimum(&self) -> u64 {
+        self.minimum_window.max(1) * MSS as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_bounds() {
+        let config = CongestionWindowConfig::default();
+        assert_eq!(config.initial(10 * MSS as u64), 10 * MSS as u64);
+        assert_eq!(config.minimum(), 2 * MSS as u64);
+
+        let config = CongestionWindowConfig {
+            initial_window: Some(32),
+            minimum_window: 4,
+        };
+        assert_eq!(config.initial(10 * MSS as u64), 32 * MSS as u64);
+        assert_eq!(config.minimum(), 4 * MSS as u64);
+
+        // The initial window is no less than the minimum one
+        let config = CongestionWindowConfig {
+            initial_window: Some(1),
+            minimum_window: 0,
+        };
+        assert_eq!(config.initial(10 * MSS as u64), MSS as u64);
+    }
+}
@@ -1,48 +1,73 @@
 use std::{
     borrow::Cow,
     fmt::Debug,
+    future::Future,
     io,
     ops::{Deref, DerefMut},
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, MutexGuard, Weak},
     time::{Duration, Instant},
 };
 
+use crate::usc::QuicIo;
+use bytes::Bytes;
 use closing::ClosingConnection;
 use draining::DrainingConnection;
-use futures::{channel::mpsc, StreamExt};
+use futures::{channel::mpsc, stream, Stream, StreamExt};
+use multipath::Scheduler;
 use qbase::{
-    cid::{self, ConnectionId},
+    cid::{self, ConnectionId, RotationPolicy},
     config::Parameters,
     error::{Error, ErrorKind},
-    packet::{DataPacket, RetryHeader},
-    streamid::Role,
+    flow::BlockedStats,
+    frame::{EcnCodepoint, PathAbandonFrame, SendFrame},
+    packet::{DataPacket, RetryHeader, VersionNegotiationHeader},
+    streamid::{MaxStreamsPolicy, Role, StreamId},
     token::ArcTokenRegistry,
+    varint::VarInt,
+};
+use qcongestion::{
+    AckPolicy, CongestionAlgorithm, CongestionControl, CongestionWindowConfig, LossDetectionConfig,
 };
-use qcongestion::CongestionControl;
 use qrecovery::{
-    recv::Reader, reliable::ArcReliableFrameDeque, send::Writer, space::Epoch, streams,
+    recv::Reader,
+    reliable::ArcReliableFrameDeque,
+    send::{DropPolicy, SendBufferLimits, Writer},
+    space::Epoch,
+    streams::{self, StreamStats},
+};
+use qunreliable::{
+    DatagramPriority, DatagramQueueLimits, DatagramReader, DatagramStats, DatagramWriter,
 };
-use qudp::ArcUsc;
-use qunreliable::{DatagramReader, DatagramWriter};
 use raw::RawConnection;
+use rustls::pki_types::{CertificateDer, ServerName};
 use tokio::task::JoinHandle;
+use versions::Versions;
 
 use crate::{
     connection::ConnState::{Closed, Closing, Draining, Raw},
-    path::pathway::Pathway,
+    error::ConnError,
+    path::{pathway::Pathway, PathError, PathInfo},
     router::{Router, RouterRegistry},
-    tls::ArcTlsSession,
+    tls::{self, ArcTlsSession, ClientSessionStore, KeyLog},
 };
 
 pub mod closing;
 pub mod draining;
+pub mod multipath;
 pub mod parameters;
 pub mod raw;
 pub mod scope;
+pub mod settings;
 pub mod transmit;
+pub mod versions;
 
-pub type PacketEntry = mpsc::UnboundedSender<(DataPacket, Pathway, ArcUsc)>;
-pub type RcvdPackets = mpsc::UnboundedReceiver<(DataPacket, Pathway, ArcUsc)>;
+pub use settings::ConnectionSettings;
+
+/// The received packets, with the pathways and the ECN codepoints of the datagrams carrying them.
+pub type PacketEntry =
+    mpsc::UnboundedSender<(DataPacket, Pathway, Option<EcnCodepoint>, Arc<dyn QuicIo>)>;
+pub type RcvdPackets =
+    mpsc::UnboundedReceiver<(DataPacket, Pathway, Option<EcnCodepoint>, Arc<dyn QuicIo>)>;
 
 pub type ArcLocalCids = cid::ArcLocalCids<RouterRegistry<ArcReliableFrameDeque>>;
 pub type ArcRemoteCids = cid::ArcRemoteCids<ArcReliableFrameDeque>;
@@ -52,7 +77,7 @@ pub type DataStreams = streams::DataStreams<ArcReliableFrameDeque>;
 pub type Handshake = qbase::handshake::Handshake<ArcReliableFrameDeque>;
 
 enum ConnState {
-    Raw(RawConnection),
+    Raw(Box<RawConnection>),
     Closing(ClosingConnection),
     Draining(DrainingConnection),
     Closed,
@@ -96,7 +121,14 @@ impl ConnState {
             }
             (hs, one_rtt) => {
                 let local_cids = raw_conn.cid_registry.local.active_cids();
-                let closing_connection = ClosingConnection::new(error, local_cids, hs, one_rtt);
+                let version = raw_conn.versions.version();
+                let cids = raw_conn.pathes.iter().find_map(|path| path.cids());
+                let closing_connection =
+                    ClosingConnection::new(error, local_cids, hs, one_rtt, version, cids);
+                // 进入closing状态时，先在各条路径上发送一次CCF
+                for path in raw_conn.pathes.iter() {
+                    closing_connection.send_ccf_packets(*path.key(), path.usc().clone());
+                }
                 Closing(closing_connection)
             }
         };
@@ -161,9 +193,28 @@ impl ConnState {
         }
     }
 }
+/// The statistics of a connection, see [`ArcConnection::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// The datagrams sent and received, see [`DatagramStats`].
+    pub datagrams: DatagramStats,
+}
+
 #[derive(Clone)]
 pub struct ArcConnection(Arc<Mutex<ConnState>>);
 
+/// A weak reference to an [`ArcConnection`], which doesn't keep the connection alive, see
+/// [`ArcConnection::downgrade`].
+#[derive(Clone)]
+pub struct WeakConnection(Weak<Mutex<ConnState>>);
+
+impl WeakConnection {
+    /// Return the [`ArcConnection`], or `None` if the connection has been dropped.
+    pub fn upgrade(&self) -> Option<ArcConnection> {
+        self.0.upgrade().map(ArcConnection)
+    }
+}
+
 impl Debug for ArcConnection {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "QUIC Connection")
@@ -171,61 +222,186 @@ impl Debug for ArcConnection {
 }
 
 impl ArcConnection {
+    /// Create a client connection to the server `server_name`.
+    ///
+    /// The `server_name` can be a DNS name or an IP address, for the latter the SNI extension is
+    /// not sent, and the server certificate is verified against the IP address.
+    ///
+    /// The connection is established in the version chosen by the client [`Versions`], such as
+    /// [`QUIC_V1`] or [`QUIC_V2`]. If the server does not support it, the handshake is restarted
+    /// in another supported version after receiving the Version Negotiation packet, see
+    /// [`ArcConnection::recv_version_negotiation`].
+    ///
+    /// [`QUIC_V1`]: qbase::packet::QUIC_V1
+    /// [`QUIC_V2`]: qbase::packet::QUIC_V2
+    #[allow(clippy::too_many_arguments)]
     pub fn new_client(
+        versions: Versions,
         scid: ConnectionId,
-        server_name: String,
-        mut parameters: Parameters,
+        server_name: ServerName<'static>,
+        parameters: Parameters,
         tls_config: Arc<rustls::ClientConfig>,
         token_registry: ArcTokenRegistry,
+        key_log: Option<Arc<dyn KeyLog>>,
+        session_store: Option<Arc<dyn ClientSessionStore>>,
+        settings: ConnectionSettings,
     ) -> Self {
-        let Ok(server_name) = server_name.try_into() else {
-            panic!("server_name is not valid")
+        let restart = {
+            let server_name = server_name.clone();
+            let parameters = parameters.clone();
+            let tls_config = tls_config.clone();
+            let token_registry = token_registry.clone();
+            let key_log = key_log.clone();
+            let session_store = session_store.clone();
+            move |versions, dcid, settings| {
+                Self::new_raw_client(
+                    versions,
+                    scid,
+                    dcid,
+                    server_name,
+                    parameters,
+                    tls_config,
+                    token_registry,
+                    key_log,
+                    session_store,
+                    settings,
+                )
+            }
         };
+        let mut raw_conn = Self::new_raw_client(
+            versions,
+            scid,
+            ConnectionId::random_gen(8),
+            server_name,
+            parameters,
+            tls_config,
+            token_registry,
+            key_log,
+            session_store,
+            settings,
+        );
+        raw_conn.restart = Some(Box::new(restart));
+        raw_conn.into()
+    }
 
+    #[allow(clippy::too_many_arguments)]
+    fn new_raw_client(
+        versions: Versions,
+        scid: ConnectionId,
+        dcid: ConnectionId,
+        server_name: ServerName<'static>,
+        mut parameters: Parameters,
+        tls_config: Arc<rustls::ClientConfig>,
+        token_registry: ArcTokenRegistry,
+        key_log: Option<Arc<dyn KeyLog>>,
+        session_store: Option<Arc<dyn ClientSessionStore>>,
+        settings: ConnectionSettings,
+    ) -> RawConnection {
+        let version = versions.version();
         parameters.set_initial_source_connection_id(Some(scid));
-
-        let dcid = ConnectionId::random_gen(8);
-        let tls_session = ArcTlsSession::new_client(server_name, tls_config.clone(), &parameters);
-        let initial_keys =
-            ArcTlsSession::initial_keys(tls_config.crypto_provider(), rustls::Side::Client, dcid);
-        let raw_conn = RawConnection::new(
+        parameters.set_version_information(Some(versions.version_information()));
+
+        let new_tls_session = {
+            let tls_config = tls_config.clone();
+            move |parameters: &Parameters| {
+                ArcTlsSession::new_client(
+                    version,
+                    server_name,
+                    tls_config,
+                    parameters,
+                    scid,
+                    key_log,
+                    session_store,
+                )
+            }
+        };
+        let initial_keys = ArcTlsSession::initial_keys(
+            tls_config.crypto_provider(),
+            version,
+            rustls::Side::Client,
+            dcid,
+        );
+        let mut raw_conn = RawConnection::new(
             Role::Client,
+            versions,
             parameters,
-            tls_session,
+            new_tls_session,
             scid,
             dcid,
             initial_keys,
             token_registry,
+            settings,
         );
-        raw_conn.into()
+        raw_conn.derive_initial_keys = Some(Box::new(move |dcid| {
+            ArcTlsSession::initial_keys(
+                tls_config.crypto_provider(),
+                version,
+                rustls::Side::Client,
+                dcid,
+            )
+        }));
+        raw_conn
     }
 
-    pub fn add_initial_path(&self, pathway: Pathway, usc: ArcUsc) {
+    pub fn add_initial_path(&self, pathway: Pathway, usc: Arc<dyn QuicIo>) {
         let guard = self.0.lock().unwrap();
         if let Raw(ref conn) = *guard {
             _ = conn.pathes.get_or_create(pathway, usc);
         }
     }
 
+    /// Add the initial path whose address has been validated before the connection is created,
+    /// such as by a Retry token, so that the server is not limited by the anti-amplification
+    /// limit on it.
+    pub fn add_validated_initial_path(&self, pathway: Pathway, usc: Arc<dyn QuicIo>) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(ref conn) = *guard {
+            conn.pathes
+                .get_or_create(pathway, usc)
+                .anti_amplifier
+                .grant();
+        }
+    }
+
+    /// Create a server connection in the version negotiated by the [`Versions`], the
+    /// `initial_keys` must be derived in the same version.
+    ///
+    /// If the connection is upgraded from the version of the first Initial packet from the client,
+    /// the initial keys of the original version should be provided by
+    /// [`ArcConnection::accept_original_version`].
+    ///
+    /// The original_destination_connection_id transport parameter must be set in `parameters`,
+    /// which is the Destination Connection ID of the client's first Initial packet, or the one
+    /// sealed in the Retry token if the address has been validated by a Retry packet. The
+    /// retry_source_connection_id should be set in the latter case.
+    #[allow(clippy::too_many_arguments)]
     pub fn new_server(
+        versions: Versions,
         initial_scid: ConnectionId,
         initial_dcid: ConnectionId,
         mut parameters: Parameters,
         initial_keys: rustls::quic::Keys,
         tls_config: Arc<rustls::ServerConfig>,
         token_registry: ArcTokenRegistry,
+        key_log: Option<Arc<dyn KeyLog>>,
+        settings: ConnectionSettings,
     ) -> Self {
-        parameters.set_original_destination_connection_id(Some(initial_dcid));
+        parameters.set_version_information(Some(versions.version_information()));
 
-        let tls_session = ArcTlsSession::new_server(tls_config.clone(), &parameters);
+        let version = versions.version();
+        let new_tls_session = move |parameters: &Parameters| {
+            ArcTlsSession::new_server(version, tls_config, parameters, initial_scid, key_log)
+        };
         let raw_conn = RawConnection::new(
             Role::Server,
+            versions,
             parameters,
-            tls_session,
+            new_tls_session,
             initial_scid,
             initial_dcid,
             initial_keys,
             token_registry,
+            settings,
         );
         raw_conn.into()
     }
@@ -248,6 +424,21 @@ impl ArcConnection {
     //     }
     // }
 
+    /// Get the transport parameters of the peer once they are received in the handshake, so that
+    /// the custom ones can be read, see [`Parameters::custom_parameters`].
+    pub async fn remote_parameters(&self) -> io::Result<Arc<Parameters>> {
+        let remote_params = {
+            let guard = self.0.lock().unwrap();
+            match guard.deref() {
+                Raw(raw) => raw.params.remote.clone(),
+                Closing(closing) => return Err(closing.error.clone())?,
+                Draining(draining) => return Err(draining.error.clone())?,
+                Closed => unreachable!(),
+            }
+        };
+        remote_params.read().await
+    }
+
     pub async fn open_bi_stream(&self) -> io::Result<Option<(Reader, Writer)>> {
         let (remote_params, data_streams, conn_error) = {
             let guard = self.0.lock().unwrap();
@@ -301,6 +492,50 @@ impl ArcConnection {
     }
 
     pub async fn accept_bi_stream(&self) -> io::Result<(Reader, Writer)> {
+        self.detached_accept_bi_stream().await
+    }
+
+    /// Same as [`accept_bi_stream`], but the returned future doesn't borrow the connection, so
+    /// the connection is not kept alive while waiting.
+    ///
+    /// [`accept_bi_stream`]: ArcConnection::accept_bi_stream
+    fn detached_accept_bi_stream(
+        &self,
+    ) -> impl Future<Output = io::Result<(Reader, Writer)>> + Send + 'static {
+        let guard = self.0.lock().unwrap();
+        let parts = match guard.deref() {
+            Raw(raw_conn) => Ok((
+                raw_conn.params.remote.clone(),
+                raw_conn.streams.clone(),
+                raw_conn.error.clone(),
+            )),
+            Closing(closing) => Err(closing.error.clone()),
+            Draining(draining) => Err(draining.error.clone()),
+            Closed => unreachable!(),
+        };
+        drop(guard);
+
+        async move {
+            let (remote_params, data_streams, conn_error) = parts?;
+            let remote_params = remote_params.read().await?;
+            let result = data_streams
+                .accept_bi(remote_params.initial_max_stream_data_bidi_local().into())
+                .await
+                .inspect_err(|e| conn_error.on_error(e.clone()))?;
+            Ok(result)
+        }
+    }
+
+    /// Accept a bidirectional stream once the peer has sent some data on it, with up to
+    /// `peek_len` bytes of the data, which are still readable from the [`Reader`].
+    ///
+    /// The streams without any data yet are not yielded, so the streams can be dispatched by
+    /// their preamble without a task spawned for each stream the peer opened speculatively. The
+    /// streams are yielded in the order the data arrives.
+    pub async fn accept_bi_stream_with_peek(
+        &self,
+        peek_len: usize,
+    ) -> io::Result<(Bytes, Reader, Writer)> {
         let (remote_params, data_streams, conn_error) = {
             let guard = self.0.lock().unwrap();
             let raw_conn = match guard.deref() {
@@ -320,30 +555,81 @@ impl ArcConnection {
         let remote_params = remote_params.read().await?;
 
         let result = data_streams
-            .accept_bi(remote_params.initial_max_stream_data_bidi_local().into())
+            .accept_bi_with_peek(
+                remote_params.initial_max_stream_data_bidi_local().into(),
+                peek_len,
+            )
             .await
             .inspect_err(|e| conn_error.on_error(e.clone()))?;
         Ok(result)
     }
 
     pub async fn accept_uni_stream(&self) -> io::Result<Reader> {
-        let (data_streams, conn_error) = {
-            let guard = self.0.lock().unwrap();
-            let raw_conn = match guard.deref() {
-                Raw(raw) => raw,
-                Closing(closing) => return Err(closing.error.clone())?,
-                Draining(draining) => return Err(draining.error.clone())?,
-                Closed => unreachable!(),
-            };
+        self.detached_accept_uni_stream().await
+    }
 
-            (raw_conn.streams.clone(), raw_conn.error.clone())
+    /// Same as [`accept_uni_stream`], but the returned future doesn't borrow the connection, so
+    /// the connection is not kept alive while waiting.
+    ///
+    /// [`accept_uni_stream`]: ArcConnection::accept_uni_stream
+    fn detached_accept_uni_stream(
+        &self,
+    ) -> impl Future<Output = io::Result<Reader>> + Send + 'static {
+        let guard = self.0.lock().unwrap();
+        let parts = match guard.deref() {
+            Raw(raw_conn) => Ok((raw_conn.streams.clone(), raw_conn.error.clone())),
+            Closing(closing) => Err(closing.error.clone()),
+            Draining(draining) => Err(draining.error.clone()),
+            Closed => unreachable!(),
         };
+        drop(guard);
+
+        async move {
+            let (data_streams, conn_error) = parts?;
+            let result = data_streams
+                .accept_uni()
+                .await
+                .inspect_err(|e| conn_error.on_error(e.clone()))?;
+            Ok(result)
+        }
+    }
 
-        let result = data_streams
-            .accept_uni()
-            .await
-            .inspect_err(|e| conn_error.on_error(e.clone()))?;
-        Ok(result)
+    /// Returns a [`Stream`] of the bidirectional streams opened by the peer.
+    ///
+    /// Each item is the result of [`accept_bi_stream`]. Once an error occurs, it will be yielded
+    /// and then the stream ends, because the connection is no longer usable. The stream doesn't
+    /// keep the connection alive, it ends too once the connection is dropped.
+    ///
+    /// [`accept_bi_stream`]: ArcConnection::accept_bi_stream
+    pub fn incoming_bi_streams(
+        &self,
+    ) -> impl Stream<Item = io::Result<(Reader, Writer)>> + Send + 'static {
+        // 只持有弱引用，以免连接在用户丢弃所有句柄后仍因这个Stream而存活；连接被释放后Stream结束
+        stream::unfold(Some(self.downgrade()), |conn| async move {
+            let accept = conn.as_ref()?.upgrade()?.detached_accept_bi_stream();
+            match accept.await {
+                Ok(stream) => Some((Ok(stream), conn)),
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// Returns a [`Stream`] of the unidirectional streams opened by the peer.
+    ///
+    /// Each item is the result of [`accept_uni_stream`]. Once an error occurs, it will be yielded
+    /// and then the stream ends, because the connection is no longer usable. The stream doesn't
+    /// keep the connection alive, it ends too once the connection is dropped.
+    ///
+    /// [`accept_uni_stream`]: ArcConnection::accept_uni_stream
+    pub fn incoming_uni_streams(&self) -> impl Stream<Item = io::Result<Reader>> + Send + 'static {
+        // 只持有弱引用，以免连接在用户丢弃所有句柄后仍因这个Stream而存活；连接被释放后Stream结束
+        stream::unfold(Some(self.downgrade()), |conn| async move {
+            let accept = conn.as_ref()?.upgrade()?.detached_accept_uni_stream();
+            match accept.await {
+                Ok(reader) => Some((Ok(reader), conn)),
+                Err(e) => Some((Err(e), None)),
+            }
+        })
     }
 
     pub fn datagram_reader(&self) -> io::Result<DatagramReader> {
@@ -357,6 +643,26 @@ impl ArcConnection {
         }
     }
 
+    /// Creates a broadcast [`DatagramReader`], which receives a copy of every datagram with its
+    /// own queue bounded by the `limits`, unlike the unique one returned by
+    /// [`ArcConnection::datagram_reader`].
+    pub fn subscribe_datagrams(&self, limits: DatagramQueueLimits) -> io::Result<DatagramReader> {
+        let guard = self.0.lock().unwrap();
+
+        match guard.deref() {
+            Raw(raw) => raw.datagrams.subscribe(limits),
+            Closing(closing) => Err(closing.error.clone())?,
+            Draining(draining) => Err(draining.error.clone())?,
+            Closed => unreachable!(),
+        }
+    }
+
+    /// Returns a [`DatagramWriter`] once the peer's `max_datagram_frame_size` is known.
+    ///
+    /// The client remembered the transport parameters of the server by the previous connection
+    /// gets the writer immediately, and the datagrams are sent in the 0-RTT packets before the
+    /// handshake completes. If the early data is rejected, the datagrams in the 0-RTT packets are
+    /// lost like the other unreliable ones, and the datagrams queued are sent in the 1-RTT packets.
     pub async fn datagram_writer(&self) -> io::Result<DatagramWriter> {
         let (remote_params, datagram_flow) = {
             let guard = self.0.lock().unwrap();
@@ -370,6 +676,10 @@ impl ArcConnection {
             (raw_conn.params.remote.clone(), raw_conn.datagrams.clone())
         };
 
+        // 已得知对端的限制，包括客户端记住的服务端参数，无需等待握手即可发送数据报
+        if let Some(writer) = datagram_flow.try_writer()? {
+            return Ok(writer);
+        }
         let remote_params = remote_params.read().await?;
         datagram_flow.writer(remote_params.max_datagram_frame_size().into())
     }
@@ -413,7 +723,8 @@ impl ArcConnection {
                         let mut closing_conn = closing.clone();
                         async move {
                             let mut rcvd_packets = handle.await.unwrap();
-                            while let Some((packet, pathway, usc)) = rcvd_packets.next().await {
+                            while let Some((packet, pathway, _ecn, usc)) = rcvd_packets.next().await
+                            {
                                 closing_conn.recv_packet_via_pathway(packet, pathway, usc);
                             }
                         }
@@ -482,34 +793,731 @@ impl ArcConnection {
         }
     }
 
-    pub fn recv_retry_packet(&self, retry: &RetryHeader) {
+    /// Handle the Retry packet from the server, client only, the whole `packet` is required to
+    /// verify the integrity tag.
+    ///
+    /// The client sends the Initial packets again to the new Destination Connection ID chosen by
+    /// the server, carrying the token in the Retry packet. The packet is discarded if it's not
+    /// authenticated, or any packet has been received from the server, or a Retry packet has been
+    /// followed already, see [Section 17.2.5.2](https://www.rfc-editor.org/rfc/rfc9000.html#section-17.2.5.2)
+    /// of RFC 9000.
+    pub fn recv_retry_packet(&self, retry: &RetryHeader, packet: &[u8]) {
         let guard = self.0.lock().unwrap();
-        if let Raw(ref conn) = *guard {
-            *conn.token.lock().unwrap() = retry.token.to_vec();
-            conn.cid_registry.remote.revise_initial_dcid(retry.scid);
-            let sent_record = conn.initial.space.sent_packets();
-            let mut guard = sent_record.recv();
-            for i in 0..guard.largest_pn() {
-                for frame in guard.may_loss_pkt(i) {
-                    conn.initial.crypto_stream.outgoing().may_loss_data(&frame);
+        let Raw(ref conn) = *guard else {
+            return;
+        };
+        let Some(derive_initial_keys) = &conn.derive_initial_keys else {
+            return;
+        };
+        let mut retry_scid = conn.retry_scid.lock().unwrap();
+        if retry_scid.is_some()
+            || retry.version != conn.versions.version()
+            || retry.token.is_empty()
+            || retry.scid == conn.original_dcid
+            || conn.initial.space.rcvd_packets().has_rcvd()
+            || !tls::retry::verify_integrity(&conn.original_dcid, retry, packet)
+        {
+            log::debug!("discard the Retry packet");
+            return;
+        }
+        *retry_scid = Some(retry.scid);
+        *conn.token.lock().unwrap() = retry.token.to_vec();
+        conn.cid_registry.remote.revise_initial_dcid(retry.scid);
+        conn.initial
+            .keys
+            .revise_keys(derive_initial_keys(retry.scid));
+        let sent_record = conn.initial.space.sent_packets();
+        let mut guard = sent_record.recv();
+        for i in 0..guard.largest_pn() {
+            for frame in guard.may_loss_pkt(i) {
+                conn.initial.crypto_stream.outgoing().may_loss_data(&frame);
+            }
+        }
+    }
+
+    /// Handle the Version Negotiation packet from the server, client only.
+    ///
+    /// If the version in use is not offered, the handshake is restarted in the most preferred
+    /// version offered by the server, the streams and datagrams obtained before are abandoned.
+    /// The connection fails with a VERSION_NEGOTIATION_ERROR if no offered version is supported.
+    ///
+    /// The packet is ignored if any packet has been received from the server, or the handshake has
+    /// been restarted already, see [Section 6.2](https://www.rfc-editor.org/rfc/rfc9000.html#section-6.2)
+    /// of RFC 9000.
+    pub fn recv_version_negotiation(&self, vn: &VersionNegotiationHeader) {
+        let mut guard = self.0.lock().unwrap();
+        let Raw(raw_conn) = guard.deref_mut() else {
+            return;
+        };
+        if raw_conn.initial.space.rcvd_packets().has_rcvd() || raw_conn.restart.is_none() {
+            return;
+        }
+        let versions = match raw_conn.versions.on_version_negotiation(&vn.versions) {
+            Ok(Some(versions)) => versions,
+            Ok(None) => return,
+            Err(e) => {
+                raw_conn.error.on_error(e);
+                return;
+            }
+        };
+        // A new connection attempt, the server has not kept any state for the original one
+        self.restart(guard, versions, ConnectionId::random_gen(8));
+    }
+
+    /// Handle the version of an Initial packet from the server, client only.
+    ///
+    /// If the server upgraded the connection to another compatible version, the handshake is
+    /// restarted in that version like [`ArcConnection::recv_version_negotiation`], see
+    /// [`Versions::on_upgrade`]. It's ignored if any packet has been received from the server, or
+    /// the handshake has been restarted already.
+    pub fn recv_initial_version(&self, version: u32) {
+        let mut guard = self.0.lock().unwrap();
+        let Raw(raw_conn) = guard.deref_mut() else {
+            return;
+        };
+        if raw_conn.initial.space.rcvd_packets().has_rcvd() || raw_conn.restart.is_none() {
+            return;
+        }
+        if let Some(versions) = raw_conn.versions.on_upgrade(version) {
+            // The server derived the initial keys of the upgraded version from the original
+            // Destination Connection ID, and routes the Initial packets sent to it to the upgraded
+            // connection, so the restarted attempt keeps using it
+            let dcid = raw_conn.original_dcid;
+            self.restart(guard, versions, dcid);
+        }
+    }
+
+    /// Abandon the current attempt of the client connection, and restart the handshake in the
+    /// `versions` with the first flight sent to `dcid`, the settings of the connection are kept.
+    fn restart(
+        &self,
+        mut guard: MutexGuard<'_, ConnState>,
+        versions: Versions,
+        dcid: ConnectionId,
+    ) {
+        let Raw(raw_conn) = guard.deref_mut() else {
+            return;
+        };
+        let restart = raw_conn.restart.take().unwrap();
+
+        // Abandon the current attempt, its error is not treated as the connection's
+        let error = Error::with_default_fty(
+            ErrorKind::VersionNegotiation,
+            format!("restart in version {:#x}", versions.version()),
+        );
+        log::info!("Connection is restarted: {}", error);
+        raw_conn.error.set_app_error(error.clone());
+        raw_conn.datagrams.on_conn_error(&error);
+        raw_conn.flow_ctrl.on_conn_error(&error);
+        raw_conn.streams.on_conn_error(&error);
+        raw_conn.params.on_conn_error(&error);
+        raw_conn.tls_session.abort();
+        raw_conn.notify.notify_waiters();
+        let pathes = raw_conn
+            .pathes
+            .iter()
+            .map(|entry| {
+                entry.value().inactivate();
+                (*entry.key(), entry.value().usc().clone())
+            })
+            .collect::<Vec<_>>();
+        let local_cids = &raw_conn.cid_registry.local;
+        local_cids.active_cids().iter().for_each(Router::remove);
+
+        let settings = raw_conn.settings.lock().unwrap().clone();
+        let mut new_conn = restart(versions, dcid, settings);
+        new_conn.cid_rotation = raw_conn.cid_rotation.clone();
+        for (pathway, usc) in pathes {
+            _ = new_conn.pathes.get_or_create(pathway, usc);
+        }
+        let conn_error = new_conn.error.clone();
+        **raw_conn = new_conn;
+        drop(guard);
+        self.watch_error(conn_error);
+    }
+
+    /// Accept the Initial packets from the client in the `original` version protected by the
+    /// initial `keys`, after the server upgraded the connection to a compatible version.
+    pub fn accept_original_version(&self, original: u32, keys: rustls::quic::Keys) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.initial.accept_original_version(original, keys);
+        }
+    }
+
+    /// Returns a snapshot of all the paths of the connection.
+    ///
+    /// The RTT and bandwidth estimates are the ones at the moment, such as for the adaptive
+    /// bitrate or hedging the requests, call it again to get the latest ones.
+    ///
+    /// If the connection is closing or has been closed, there will be no path.
+    pub fn paths(&self) -> Vec<PathInfo> {
+        let guard = self.0.lock().unwrap();
+        match guard.deref() {
+            Raw(raw_conn) => raw_conn
+                .pathes
+                .iter()
+                .map(|entry| entry.value().info(*entry.key()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns how many times the peer reported it's blocked by the local receive windows or the
+    /// stream limits, see [`BlockedStats`].
+    ///
+    /// The receive windows grow upon these reports, a steadily increasing count means the maximum
+    /// windows are too small for the transfer. Return the default if the connection is not active.
+    pub fn blocked_stats(&self) -> BlockedStats {
+        let guard = self.0.lock().unwrap();
+        match guard.deref() {
+            Raw(raw_conn) => BlockedStats {
+                data_blocked: raw_conn.flow_ctrl.recver.blocked_count(),
+                ..raw_conn.streams.blocked_stats()
+            },
+            _ => BlockedStats::default(),
+        }
+    }
+
+    /// Returns the statistics of the connection, such as the datagrams sent, received and dropped,
+    /// see [`ConnectionStats`].
+    ///
+    /// Return the default if the connection is not active.
+    pub fn stats(&self) -> ConnectionStats {
+        let guard = self.0.lock().unwrap();
+        match guard.deref() {
+            Raw(raw_conn) => ConnectionStats {
+                datagrams: raw_conn.datagrams.stats(),
+            },
+            _ => ConnectionStats::default(),
+        }
+    }
+
+    /// Returns the statistics of the stream `sid`, such as the data sent, acknowledged and read,
+    /// and the flow control limits of both sides, to find out why a stream is stuck.
+    ///
+    /// Return [`None`] if the stream is not active, or the connection is not active.
+    pub fn stream_stats(&self, sid: StreamId) -> Option<StreamStats> {
+        let guard = self.0.lock().unwrap();
+        match guard.deref() {
+            Raw(raw_conn) => raw_conn.streams.stream_stats(sid),
+            _ => None,
+        }
+    }
+
+    /// Validate the path of `pathway` on demand, and resolve with the round-trip time measured by
+    /// the PATH_CHALLENGE and PATH_RESPONSE frames, see [Section 8.2](https://www.rfc-editor.org/rfc/rfc9000.html#section-8.2)
+    /// of RFC 9000.
+    ///
+    /// It's useful for checking the connectivity of an idle path, or deciding how to schedule the
+    /// data across the paths. Unlike the validation of a new path, the path is not inactivated if
+    /// the peer does not respond, the caller decides what to do with it.
+    pub fn validate_path(
+        &self,
+        pathway: Pathway,
+    ) -> impl Future<Output = Result<Duration, PathError>> + Send {
+        let path = {
+            let guard = self.0.lock().unwrap();
+            match guard.deref() {
+                Raw(raw_conn) => raw_conn.pathes.get(&pathway).map(|p| p.value().clone()),
+                _ => None,
+            }
+        };
+        async move {
+            let path = path.ok_or(PathError::NotFound)?;
+            tokio::select! {
+                result = path.probe() => result,
+                _ = path.inactivated() => Err(PathError::Inactivated),
+            }
+        }
+    }
+
+    /// Migrate the connection to the socket `usc`, client only, see [Section 9](https://www.rfc-editor.org/rfc/rfc9000.html#section-9)
+    /// of RFC 9000.
+    ///
+    /// A new path from the socket to the server's address is created with a fresh Destination
+    /// Connection ID, so that the peer and the observers can not link the paths together. Once the
+    /// new path is validated by PATH_CHALLENGE/PATH_RESPONSE, the old paths are abandoned and their
+    /// connection IDs are retired, otherwise the new path is abandoned and an error is returned.
+    ///
+    /// The migration is only allowed after the handshake is confirmed, and if the server has not
+    /// sent the disable_active_migration transport parameter.
+    pub async fn migrate(&self, usc: Arc<dyn QuicIo>) -> io::Result<()> {
+        let remote_params = {
+            let guard = self.0.lock().unwrap();
+            match guard.deref() {
+                Raw(raw) => raw.params.remote.clone(),
+                Closing(closing) => return Err(closing.error.clone())?,
+                Draining(draining) => return Err(draining.error.clone())?,
+                Closed => unreachable!(),
+            }
+        };
+        if remote_params.read().await?.disable_active_migration() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the server disabled the active migration",
+            ));
+        }
+
+        let (new_path, pathway, pathes) = {
+            let guard = self.0.lock().unwrap();
+            let raw_conn = match guard.deref() {
+                Raw(raw) => raw,
+                Closing(closing) => return Err(closing.error.clone())?,
+                Draining(draining) => return Err(draining.error.clone())?,
+                Closed => unreachable!(),
+            };
+            if raw_conn.handshake.role() != Role::Client {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "only the client can initiate the migration",
+                ));
+            }
+            if !raw_conn.handshake.is_handshake_done() {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "the handshake has not been confirmed",
+                ));
+            }
+            let Some(remote) = raw_conn
+                .pathes
+                .iter()
+                .next()
+                .map(|path| path.key().remote_addr())
+            else {
+                return Err(io::Error::new(io::ErrorKind::NotConnected, "no path"));
+            };
+            let pathway = Pathway::Direct {
+                local: usc.local_addr(),
+                remote,
+            };
+            if raw_conn.pathes.contains_key(&pathway) {
+                return Ok(());
+            }
+            (
+                raw_conn.pathes.get_or_create(pathway, usc),
+                pathway,
+                raw_conn.pathes.clone(),
+            )
+        };
+
+        if !new_path.validated().await {
+            new_path.inactivate();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the new path failed to be validated",
+            ));
+        }
+        for path in pathes.iter().filter(|path| *path.key() != pathway) {
+            path.inactivate();
+        }
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            // 多路径下连接ID标识着各路径的包号空间，不能轮换
+            if !raw_conn.data.multipath.is_enabled() {
+                let policy = *raw_conn.cid_rotation.lock().unwrap();
+                raw_conn.cid_registry.on_migrated(&policy);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a path from the socket `usc` to the server's address, client only, see [Multipath Extension for QUIC](https://www.ietf.org/archive/id/draft-ietf-quic-multipath-05.html).
+    ///
+    /// Unlike [`ArcConnection::migrate`], the existing paths are kept and used at the same time,
+    /// each path uses its own Destination Connection ID and packet number spaces. It's only
+    /// allowed after the handshake is confirmed, and if both endpoints enabled the multipath
+    /// extension. An error is returned if the new path fails to be validated.
+    pub async fn add_path(&self, usc: Arc<dyn QuicIo>) -> io::Result<()> {
+        let remote_params = {
+            let guard = self.0.lock().unwrap();
+            match guard.deref() {
+                Raw(raw) => raw.params.remote.clone(),
+                Closing(closing) => return Err(closing.error.clone())?,
+                Draining(draining) => return Err(draining.error.clone())?,
+                Closed => unreachable!(),
+            }
+        };
+        if remote_params.read().await?.disable_active_migration() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the server disabled the active migration",
+            ));
+        }
+
+        let new_path = {
+            let guard = self.0.lock().unwrap();
+            let raw_conn = match guard.deref() {
+                Raw(raw) => raw,
+                Closing(closing) => return Err(closing.error.clone())?,
+                Draining(draining) => return Err(draining.error.clone())?,
+                Closed => unreachable!(),
+            };
+            if raw_conn.handshake.role() != Role::Client {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "only the client can add a path",
+                ));
+            }
+            if !raw_conn.handshake.is_handshake_done() {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "the handshake has not been confirmed",
+                ));
+            }
+            if !raw_conn.data.multipath.is_enabled() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "the multipath extension is not negotiated",
+                ));
+            }
+            let Some(remote) = raw_conn
+                .pathes
+                .iter()
+                .next()
+                .map(|path| path.key().remote_addr())
+            else {
+                return Err(io::Error::new(io::ErrorKind::NotConnected, "no path"));
+            };
+            let pathway = Pathway::Direct {
+                local: usc.local_addr(),
+                remote,
+            };
+            if raw_conn.pathes.contains_key(&pathway) {
+                return Ok(());
+            }
+            raw_conn.pathes.get_or_create(pathway, usc)
+        };
+
+        if !new_path.validated().await {
+            new_path.inactivate();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the new path failed to be validated",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Abandon the path of `pathway` with a PATH_ABANDON frame, while the other paths are still
+    /// used, see [Section 4.3](https://www.ietf.org/archive/id/draft-ietf-quic-multipath-05.html#section-4.3)
+    /// of Multipath Extension for QUIC.
+    ///
+    /// The packets in flight on the path are retransmitted on the other paths. The last path can
+    /// not be abandoned, close the connection instead.
+    pub fn abandon_path(&self, pathway: Pathway) -> io::Result<()> {
+        let guard = self.0.lock().unwrap();
+        let raw_conn = match guard.deref() {
+            Raw(raw) => raw,
+            Closing(closing) => return Err(closing.error.clone())?,
+            Draining(draining) => return Err(draining.error.clone())?,
+            Closed => unreachable!(),
+        };
+        if !raw_conn.data.multipath.is_enabled() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the multipath extension is not negotiated",
+            ));
+        }
+        let Some(path) = raw_conn
+            .pathes
+            .get(&pathway)
+            .map(|path| path.value().clone())
+        else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such path"));
+        };
+        if raw_conn.pathes.len() <= 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the last path can not be abandoned",
+            ));
+        }
+        // 先使该路径失活，PATH_ABANDON帧才会经由其他路径发送；对端从未在该路径上发包，则无需告知
+        path.inactivate();
+        if let Some(seq) = path.seqs.rcvd() {
+            let dcid_seq = VarInt::from_u64(seq).expect("sequence number never exceed VARINT_MAX");
+            raw_conn.reliable_frames.send_frame([PathAbandonFrame::new(
+                dcid_seq,
+                VarInt::from_u32(0),
+                "",
+            )]);
+        }
+        Ok(())
+    }
+
+    /// Set how the stream data are scheduled across the validated paths when the multipath
+    /// extension is used, see [`Scheduler`].
+    pub fn set_scheduler(&self, scheduler: Scheduler) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.data.multipath.set_scheduler(scheduler);
+        }
+    }
+
+    /// Returns the certificate chain presented by the peer, the first one is the end-entity
+    /// certificate.
+    ///
+    /// On the server side, it's the authenticated identity of the client when the client
+    /// certificate is required. Return [`None`] if the peer does not present a certificate, the
+    /// handshake has not progressed far enough, or the connection is no longer active.
+    pub fn peer_identity(&self) -> Option<Vec<CertificateDer<'static>>> {
+        let guard = self.0.lock().unwrap();
+        match guard.deref() {
+            Raw(raw_conn) => raw_conn.tls_session.peer_certificates(),
+            _ => None,
+        }
+    }
+
+    /// Returns the application protocol negotiated by ALPN, so that a server serving multiple
+    /// protocols can dispatch the connection, for example h3 or a custom protocol.
+    ///
+    /// Return [`None`] if ALPN is not used, the handshake has not progressed far enough, or the
+    /// connection is no longer active.
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        let guard = self.0.lock().unwrap();
+        match guard.deref() {
+            Raw(raw_conn) => raw_conn.tls_session.alpn_protocol(),
+            _ => None,
+        }
+    }
+
+    /// Export `len` bytes of keying material derived from the TLS exporter secret, with the `label`
+    /// and `context`, for the application protocols which derive their own keys from the QUIC
+    /// session, for example DTLS-SRTP, token binding and MASQUE.
+    ///
+    /// Return an error if the handshake has not completed, or the connection is no longer active.
+    pub fn export_keying_material(
+        &self,
+        label: &[u8],
+        context: &[u8],
+        len: usize,
+    ) -> io::Result<Vec<u8>> {
+        let guard = self.0.lock().unwrap();
+        match guard.deref() {
+            Raw(raw_conn) => raw_conn
+                .tls_session
+                .export_keying_material(label, context, len)
+                .map_err(io::Error::other),
+            Closing(closing) => Err(closing.error.clone())?,
+            Draining(draining) => Err(draining.error.clone())?,
+            Closed => unreachable!(),
+        }
+    }
+
+    /// Initiate a 1-RTT key update, the packets sent afterwards are protected by the keys of the
+    /// next phase, see [Section 6](https://www.rfc-editor.org/rfc/rfc9001#section-6) of RFC 9001.
+    ///
+    /// The keys are also updated automatically before the AEAD confidentiality limit is reached,
+    /// this is mainly used for testing. Return false if the handshake has not been confirmed, the
+    /// previous key update has not been acknowledged by the peer, or the connection is no longer
+    /// active.
+    pub fn initiate_key_update(&self) -> bool {
+        let guard = self.0.lock().unwrap();
+        let Raw(raw_conn) = guard.deref() else {
+            return false;
+        };
+        if !raw_conn.handshake.is_handshake_done() {
+            return false;
+        }
+        match raw_conn.data.one_rtt_keys.get_local_keys() {
+            Some((_, pk)) => pk.lock_guard().initiate_update(),
+            None => false,
+        }
+    }
+
+    /// Limit the total payload size of the 0-RTT packets to be accepted before the handshake
+    /// completes, the 0-RTT packets beyond the limit are discarded.
+    ///
+    /// It's meaningful only for servers which accept early data, the client will retransmit the
+    /// discarded data in 1-RTT packets. Unlimited by default.
+    pub fn set_max_early_data(&self, max_early_data: usize) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn
+                .data
+                .early_data_quota
+                .store(max_early_data, std::sync::atomic::Ordering::Release);
+        }
+    }
+
+    /// Abort the connection with a CONNECTION_REFUSED error if the handshake is not completed within
+    /// `timeout`, so that stalled half-open handshakes do not occupy the resources forever.
+    ///
+    /// It should be called right after the connection is created, the timer starts when this
+    /// function is called. The handshake is considered completed when the client receives the
+    /// HANDSHAKE_DONE frame, and when the server receives the client's TLS Finished message.
+    pub fn set_handshake_timeout(&self, timeout: Duration) {
+        // The handshake may be restarted after version negotiation, check the current attempt. The
+        // timer doesn't keep the connection alive, it's done if the connection is dropped before
+        let conn = self.downgrade();
+        tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            let Some(conn) = conn.upgrade() else {
+                return;
+            };
+            let guard = conn.0.lock().unwrap();
+            if let Raw(raw_conn) = guard.deref() {
+                if !raw_conn.handshake.is_handshake_done() {
+                    let error =
+                        Error::with_default_fty(ErrorKind::ConnectionRefused, "handshake timeout");
+                    raw_conn.error.on_error(error);
                 }
             }
+        });
+    }
+
+    /// Set the strategy to acknowledge the packets received on all the paths, including the ones
+    /// created later, see [`AckPolicy`].
+    pub fn set_ack_policy(&self, policy: AckPolicy) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.settings.lock().unwrap().ack_policy = policy;
+            for path in raw_conn.pathes.iter() {
+                path.cc.set_ack_policy(policy);
+            }
         }
     }
 
-    pub fn is_active(&self) -> bool {
+    /// Set the thresholds of the loss detection and the backoff of the PTO on all the paths,
+    /// including the ones created later, see [`LossDetectionConfig`].
+    pub fn set_loss_detection(&self, config: LossDetectionConfig) {
         let guard = self.0.lock().unwrap();
-        !matches!(&*guard, ConnState::Raw(..))
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.settings.lock().unwrap().loss_detection = config;
+            for path in raw_conn.pathes.iter() {
+                path.cc.set_loss_detection(config);
+            }
+        }
     }
-}
 
-impl From<RawConnection> for ArcConnection {
-    fn from(raw_conn: RawConnection) -> Self {
-        let conn_error = raw_conn.error.clone();
-        let conn = ArcConnection(Arc::new(Mutex::new(ConnState::Raw(raw_conn))));
+    /// Set the policy to replenish the streams the peer can create as its streams are closed, so
+    /// the number of the streams the peer can create at a time and over the connection lifetime
+    /// can be bounded, see [`MaxStreamsPolicy`].
+    pub fn set_max_streams_policy(&self, policy: MaxStreamsPolicy) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.settings.lock().unwrap().max_streams_policy = policy;
+            raw_conn.streams.set_max_streams_policy(policy);
+        }
+    }
+
+    /// Set the bounds of the data buffered to send by each stream and by all the streams of the
+    /// connection, the writes are pending once reaching the bounds until the data is acknowledged,
+    /// see [`SendBufferLimits`].
+    pub fn set_send_buffer_limits(&self, limits: SendBufferLimits) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.settings.lock().unwrap().send_buffer_limits = limits;
+            raw_conn.streams.set_send_buffer_limits(limits);
+        }
+    }
+
+    /// Set the bounds of the datagrams received but not read by the application yet, the
+    /// datagrams beyond the bounds are dropped, see [`DatagramQueueLimits`].
+    pub fn set_datagram_queue_limits(&self, limits: DatagramQueueLimits) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.settings.lock().unwrap().datagram_queue_limits = limits;
+            raw_conn.datagrams.set_queue_limits(limits);
+        }
+    }
+
+    /// Set how the datagrams to send are scheduled against the stream data in the packets, see
+    /// [`DatagramPriority`].
+    pub fn set_datagram_priority(&self, priority: DatagramPriority) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.settings.lock().unwrap().datagram_priority = priority;
+            raw_conn.datagrams.set_priority(priority);
+        }
+    }
+
+    /// Set the default [`DropPolicy`] of the streams opened or accepted afterwards, what to do
+    /// with a stream if its [`Writer`] is dropped before finished or cancelled. Each [`Writer`]
+    /// can change its own by [`Writer::set_drop_policy`].
+    pub fn set_drop_policy(&self, policy: DropPolicy) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.settings.lock().unwrap().drop_policy = policy;
+            raw_conn.streams.set_drop_policy(policy);
+        }
+    }
+
+    /// Set the maximum rate to send the packets of the connection in bytes per second, [`None`] to
+    /// remove the limit.
+    ///
+    /// The limit is shared by all the paths and enforced by their pacers independent of the
+    /// congestion windows, it takes effect immediately, see [`SendRateLimit`](qcongestion::SendRateLimit).
+    pub fn set_max_send_rate(&self, rate: Option<u64>) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.settings.lock().unwrap().max_send_rate = rate;
+            raw_conn.send_rate_limit.set_rate(rate);
+        }
+    }
+
+    /// Set the congestion control algorithm of the paths created later, see [`CongestionAlgorithm`].
+    ///
+    /// The paths already created keep their algorithms, so it should be set before the initial
+    /// path is added.
+    pub fn set_congestion_algorithm(&self, algorithm: CongestionAlgorithm) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.settings.lock().unwrap().congestion_algorithm = algorithm;
+        }
+    }
+
+    /// Set the initial and the minimum congestion window of the paths created later, see
+    /// [`CongestionWindowConfig`].
+    ///
+    /// Like [`ArcConnection::set_congestion_algorithm`], it should be set before the initial path
+    /// is added.
+    pub fn set_congestion_window(&self, config: CongestionWindowConfig) {
+        let guard = self.0.lock().unwrap();
+        if let Raw(raw_conn) = guard.deref() {
+            raw_conn.settings.lock().unwrap().congestion_window = config;
+        }
+    }
+
+    /// Set the policy to rotate the connection IDs issued to the peer, see [`RotationPolicy`].
+    ///
+    /// The periodic rotation starts after the handshake is done, and stops once the connection is
+    /// closed or dropped, the task doesn't keep the connection alive. It should be called once right
+    /// after the connection is created.
+    pub fn set_cid_rotation(&self, policy: RotationPolicy) {
+        {
+            let guard = self.0.lock().unwrap();
+            let Raw(raw_conn) = guard.deref() else {
+                return;
+            };
+            *raw_conn.cid_rotation.lock().unwrap() = policy;
+        }
+        let Some(interval) = policy.interval else {
+            return;
+        };
+        let conn = self.downgrade();
+        tokio::spawn(async move {
+            let mut ticker =
+                tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                let Some(conn) = conn.upgrade() else {
+                    break;
+                };
+                let guard = conn.0.lock().unwrap();
+                let Raw(raw_conn) = guard.deref() else {
+                    break;
+                };
+                if raw_conn.handshake.is_handshake_done() {
+                    raw_conn.rotate_cids();
+                }
+            }
+        });
+    }
 
+    fn watch_error(&self, conn_error: ConnError) {
         tokio::spawn({
-            let conn = conn.clone();
+            let conn = self.clone();
             async move {
                 let (err, kind) = conn_error.did_error_occur().await;
                 if kind != crate::error::ConnErrorKind::Application {
@@ -523,9 +1531,222 @@ impl From<RawConnection> for ArcConnection {
                 }
             }
         });
+    }
 
+    /// Whether the handshake is confirmed, namely the client received the HANDSHAKE_DONE frame, or
+    /// the server completed the handshake, see [Section 4.1.2](https://www.rfc-editor.org/rfc/rfc9001.html#section-4.1.2)
+    /// of RFC 9001.
+    ///
+    /// The Handshake keys are discarded then, and the key update and the migration are allowed
+    /// only after it. Return false if the connection is no longer active.
+    pub fn is_handshake_confirmed(&self) -> bool {
+        let guard = self.0.lock().unwrap();
+        matches!(&*guard, Raw(raw_conn) if raw_conn.handshake.is_handshake_done())
+    }
+
+    /// Create a [`WeakConnection`] to the connection, for the tasks or the records which should not
+    /// keep the connection alive.
+    pub fn downgrade(&self) -> WeakConnection {
+        WeakConnection(Arc::downgrade(&self.0))
+    }
+
+    /// Whether the connection is still handshaking, which is neither completed nor terminated.
+    pub fn is_handshaking(&self) -> bool {
+        let guard = self.0.lock().unwrap();
+        matches!(&*guard, Raw(raw_conn) if !raw_conn.handshake.is_handshake_done())
+    }
+
+    pub fn is_active(&self) -> bool {
+        let guard = self.0.lock().unwrap();
+        !matches!(&*guard, ConnState::Raw(..))
+    }
+}
+
+impl From<RawConnection> for ArcConnection {
+    fn from(raw_conn: RawConnection) -> Self {
+        let conn_error = raw_conn.error.clone();
+        let conn = ArcConnection(Arc::new(Mutex::new(ConnState::Raw(Box::new(raw_conn)))));
+        conn.watch_error(conn_error);
         conn
     }
 }
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::{
+        io::{IoSlice, IoSliceMut},
+        net::SocketAddr,
+        task::{Context, Poll},
+    };
+
+    use bytes::BytesMut;
+    use qbase::packet::{header::long, DataHeader, LongHeaderBuilder, QUIC_V1, QUIC_V2};
+    use rustls::{
+        pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer},
+        Side,
+    };
+
+    use super::*;
+    use crate::usc::PacketHeader;
+
+    // 丢弃发送的数据报，收不到任何数据报
+    struct MockIo;
+
+    impl QuicIo for MockIo {
+        fn local_addr(&self) -> SocketAddr {
+            "127.0.0.1:4433".parse().unwrap()
+        }
+
+        fn poll_send(
+            &self,
+            _: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+            _: &PacketHeader,
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(bufs.len()))
+        }
+
+        fn poll_recv(
+            &self,
+            _: &mut Context<'_>,
+            _: &mut [IoSliceMut<'_>],
+            _: &mut [PacketHeader],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Pending
+        }
+    }
+
+    fn new_client(versions: Versions) -> ArcConnection {
+        let tls_config =
+            rustls::ClientConfig::builder_with_provider(crate::tls::default_provider())
+                .with_protocol_versions(&[&rustls::version::TLS13])
+                .unwrap()
+                .with_root_certificates(rustls::RootCertStore::empty())
+                .with_no_client_auth();
+        ArcConnection::new_client(
+            versions,
+            ConnectionId::random_gen(8),
+            ServerName::try_from("localhost").unwrap(),
+            Parameters::default(),
+            Arc::new(tls_config),
+            ArcTokenRegistry::default_sink("localhost".to_owned()),
+            None,
+            None,
+            ConnectionSettings::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_no_cid_rotation_with_multipath() {
+        let conn = new_client(Versions::client(&[QUIC_V1]));
+        let guard = conn.0.lock().unwrap();
+        let Raw(raw_conn) = guard.deref() else {
+            panic!("the connection should be raw");
+        };
+
+        // 未协商多路径时，轮换会签发新的连接ID
+        let cids = raw_conn.cid_registry.local.active_cids();
+        raw_conn.rotate_cids();
+        let rotated = raw_conn.cid_registry.local.active_cids();
+        assert!(rotated.len() > cids.len());
+
+        // 协商多路径后，连接ID标识着各路径的包号空间，不再轮换
+        raw_conn.data.multipath.enable();
+        raw_conn.rotate_cids();
+        assert_eq!(raw_conn.cid_registry.local.active_cids(), rotated);
+    }
+
+    #[tokio::test]
+    async fn test_incoming_streams_not_keep_conn_alive() {
+        let conn = new_client(Versions::client(&[QUIC_V1]));
+        let strong_count = Arc::strong_count(&conn.0);
+        let mut bi_streams = Box::pin(conn.incoming_bi_streams());
+        let mut uni_streams = Box::pin(conn.incoming_uni_streams());
+        // 正在等待对方打开流时，也不应持有连接
+        assert!(futures::poll!(bi_streams.next()).is_pending());
+        assert!(futures::poll!(uni_streams.next()).is_pending());
+        assert_eq!(Arc::strong_count(&conn.0), strong_count);
+    }
+
+    #[tokio::test]
+    async fn test_restart_to_follow_upgrade() {
+        let conn = new_client(Versions::client(&[QUIC_V1, QUIC_V2]));
+        let original_dcid = {
+            let guard = conn.0.lock().unwrap();
+            let Raw(raw_conn) = guard.deref() else {
+                panic!("the connection should be raw");
+            };
+            raw_conn.original_dcid
+        };
+
+        // 跟随服务端兼容升级的版本重新握手，仍使用最初的目标连接ID，服务端据此推导初始密钥
+        conn.recv_initial_version(QUIC_V2);
+        let guard = conn.0.lock().unwrap();
+        let Raw(raw_conn) = guard.deref() else {
+            panic!("the connection should be raw");
+        };
+        assert_eq!(raw_conn.versions.version(), QUIC_V2);
+        assert_eq!(raw_conn.original_dcid, original_dcid);
+    }
+
+    #[tokio::test]
+    async fn test_discarded_datagram_raises_credit() {
+        let cert = rcgen::generate_simple_self_signed(["localhost".to_owned()]).unwrap();
+        let cert_der = CertificateDer::from(cert.cert.der().to_vec());
+        let key_der = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()));
+        let tls_config = rustls::ServerConfig::builder_with_provider(tls::default_provider())
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert_der], key_der)
+            .unwrap();
+
+        let (scid, dcid) = (ConnectionId::random_gen(8), ConnectionId::random_gen(8));
+        let initial_keys =
+            ArcTlsSession::initial_keys(&tls::default_provider(), QUIC_V1, Side::Server, dcid);
+        let conn = ArcConnection::new_server(
+            Versions::server(QUIC_V1, &[QUIC_V1], None),
+            scid,
+            dcid,
+            Parameters::default(),
+            initial_keys,
+            Arc::new(tls_config),
+            ArcTokenRegistry::default_provider(),
+            None,
+            ConnectionSettings::default(),
+        );
+
+        let usc: Arc<dyn QuicIo> = Arc::new(MockIo);
+        let pathway = Pathway::Direct {
+            local: usc.local_addr(),
+            remote: "127.0.0.1:8443".parse().unwrap(),
+        };
+        conn.add_initial_path(pathway, usc.clone());
+        let credit = || {
+            let guard = conn.0.lock().unwrap();
+            let Raw(raw_conn) = guard.deref() else {
+                panic!("the connection should be raw");
+            };
+            let credit = raw_conn
+                .pathes
+                .get(&pathway)
+                .unwrap()
+                .anti_amplifier
+                .credit();
+            credit
+        };
+        let before = credit().unwrap();
+
+        // 超过max_udp_payload_size的数据报被丢弃，但其字节仍计入抗放大限制
+        let mut bytes = BytesMut::zeroed(2000);
+        bytes[0] = 0xc0;
+        let header = LongHeaderBuilder::with_cid(scid, dcid).initial(vec![]);
+        let packet = DataPacket {
+            header: DataHeader::Long(long::DataHeader::Initial(header)),
+            bytes,
+            offset: 0,
+        };
+        assert!(Router::try_to_route_packet_from(packet, pathway, None, &usc).is_ok());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(credit().unwrap() >= before + 2000);
+    }
+}
@@ -2,24 +2,33 @@ use std::{
     future::Future,
     ops::DerefMut,
     pin::Pin,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc, Mutex,
-    },
+    sync::{Arc, Mutex},
     task::{Context, Poll, Waker},
-    time::{Duration, Instant},
 };
 
+use crate::usc::QuicIo;
+use bytes::Bytes;
 use qbase::{
     cid::ConnectionId,
     error::Error,
+    frame::ConnectionCloseFrame,
     packet::{long, DataHeader, DataPacket},
 };
-use qudp::ArcUsc;
 
 use super::scope::{data::ClosingOneRttScope, handshake::ClosingHandshakeScope, RecvPacket};
-use crate::path::pathway::Pathway;
-
+use crate::path::{pathway::Pathway, ViaPathway};
+
+/// The size limit of the datagram the CCF packets coalesced into, the reason phrase is truncated
+/// to fit in it.
+const MAX_CCF_PACKETS_SIZE: usize = 1200;
+
+/// The connection in the closing state, see [Section 10.2.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-10.2.1)
+/// of RFC 9000.
+///
+/// The CONNECTION_CLOSE frame is assembled into one packet for each packet number space whose keys
+/// are still available when entering the closing state, which are coalesced into one datagram and
+/// cached. The datagram is sent to answer the packets received in the closing state, after a
+/// progressively increasing number of them.
 #[derive(Clone)]
 pub struct ClosingConnection {
     pub local_cids: Vec<ConnectionId>,
@@ -27,43 +36,91 @@ pub struct ClosingConnection {
     pub one_rtt: Option<ClosingOneRttScope>,
     pub error: Error,
 
-    pub rcvd_packets: Arc<AtomicUsize>,
-    pub last_send_ccf: Arc<Mutex<Instant>>,
+    pub ccf_packets: Bytes,
+    pub backoff: Arc<Mutex<ResponseBackoff>>,
     pub revd_ccf: RcvdCcf,
 }
 
 impl ClosingConnection {
+    /// Create the closing connection, and assemble the CCF packets sent with the `cids`, which are
+    /// the source and destination connection IDs. No CCF packet is sent if the `cids` is [`None`].
     pub fn new(
         error: Error,
         local_cids: Vec<ConnectionId>,
         hs: Option<ClosingHandshakeScope>,
         one_rtt: Option<ClosingOneRttScope>,
+        version: u32,
+        cids: Option<(ConnectionId, ConnectionId)>,
     ) -> Self {
+        let ccf_packets = cids.map_or_else(Bytes::new, |(scid, dcid)| {
+            Self::assemble_ccf_packets(&error, hs.as_ref(), one_rtt.as_ref(), version, scid, dcid)
+        });
         Self {
             local_cids,
             hs,
             one_rtt,
             error,
-            rcvd_packets: Arc::new(AtomicUsize::new(0)),
-            last_send_ccf: Arc::new(Mutex::new(Instant::now())),
+            ccf_packets,
+            backoff: Arc::new(Mutex::new(ResponseBackoff::default())),
             revd_ccf: RcvdCcf::default(),
         }
     }
 
-    // 记录收到的包数量，和收包时间，判断是否需要重发CCF；
-    pub fn recv_packet_via_pathway(&mut self, packet: DataPacket, _pathway: Pathway, _usc: ArcUsc) {
-        self.rcvd_packets.fetch_add(1, Ordering::Release);
-        // TODO: 数值从配置中读取, 还是直接固定值?
-        let mut last_send_ccf = self.last_send_ccf.lock().unwrap();
-        if self.rcvd_packets.load(Ordering::Relaxed) > 5
-            || last_send_ccf.elapsed() > Duration::from_millis(100)
-        {
-            self.rcvd_packets.store(0, Ordering::Release);
-            *last_send_ccf = Instant::now();
-            // TODO: 调用 usc 直接发送 报文
-            // usc.poll_send_via_pathway(iovecs, pathway, cx);
+    fn assemble_ccf_packets(
+        error: &Error,
+        hs: Option<&ClosingHandshakeScope>,
+        one_rtt: Option<&ClosingOneRttScope>,
+        version: u32,
+        scid: ConnectionId,
+        dcid: ConnectionId,
+    ) -> Bytes {
+        let mut ccf = ConnectionCloseFrame::from(error.clone());
+        // 原因短语过长时截断，保证CCF包能放进一个数据报
+        if ccf.reason.len() > MAX_CCF_PACKETS_SIZE / 4 {
+            let mut end = MAX_CCF_PACKETS_SIZE / 4;
+            while !ccf.reason.is_char_boundary(end) {
+                end -= 1;
+            }
+            ccf.reason = ccf.reason[..end].to_owned().into();
+        }
+
+        let mut buf = vec![0u8; MAX_CCF_PACKETS_SIZE];
+        let mut len = 0;
+        // 对端可能还没有1RTT密钥，Handshake包在前，1RTT包在后，合并成一个数据报
+        if let Some(hs) = hs {
+            len += hs
+                .assemble_ccf_packet(&mut buf[len..], version, scid, dcid, &ccf)
+                .unwrap_or(0);
+        }
+        if let Some(one_rtt) = one_rtt {
+            len += one_rtt
+                .assemble_ccf_packet(&mut buf[len..], dcid, &ccf)
+                .unwrap_or(0);
+        }
+        buf.truncate(len);
+        buf.into()
+    }
+
+    /// Send the cached CCF packets via the `pathway`.
+    pub fn send_ccf_packets(&self, pathway: Pathway, mut usc: Arc<dyn QuicIo>) {
+        if self.ccf_packets.is_empty() {
+            return;
+        }
+        if let Err(e) = usc.sync_send_via_path_way(self.ccf_packets.to_vec(), pathway) {
+            log::warn!("failed to send the CCF packets via {pathway:?}: {e}");
+        }
+    }
+
+    // 收到的包数量达到阈值后重发CCF，每次重发后阈值翻倍
+    pub fn recv_packet_via_pathway(
+        &mut self,
+        packet: DataPacket,
+        pathway: Pathway,
+        usc: Arc<dyn QuicIo>,
+    ) {
+        if self.backoff.lock().unwrap().on_rcvd_packet() {
+            self.send_ccf_packets(pathway, usc);
         }
-        drop(last_send_ccf);
 
         match packet.header {
             DataHeader::Short(_) => self.parse_1rtt_packet(packet),
@@ -93,6 +150,36 @@ impl ClosingConnection {
     }
 }
 
+/// Answer the packets received in the closing state exponentially less often, which limits the
+/// rate of the packets generated in the closing state.
+#[derive(Debug)]
+pub struct ResponseBackoff {
+    rcvd_packets: u32,
+    threshold: u32,
+}
+
+impl Default for ResponseBackoff {
+    fn default() -> Self {
+        Self {
+            rcvd_packets: 0,
+            threshold: 1,
+        }
+    }
+}
+
+impl ResponseBackoff {
+    /// Called when a packet is received, return whether the CCF packets should be sent to answer.
+    pub fn on_rcvd_packet(&mut self) -> bool {
+        self.rcvd_packets += 1;
+        if self.rcvd_packets < self.threshold {
+            return false;
+        }
+        self.rcvd_packets = 0;
+        self.threshold = self.threshold.saturating_mul(2);
+        true
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 enum RcvdCcfState {
     #[default]
@@ -132,3 +219,17 @@ impl Future for RcvdCcf {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ResponseBackoff;
+
+    #[test]
+    fn response_backoff() {
+        let mut backoff = ResponseBackoff::default();
+        let answered = (1..=16)
+            .filter(|_| backoff.on_rcvd_packet())
+            .collect::<Vec<_>>();
+        assert_eq!(answered, vec![1, 3, 7, 15]);
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use qbase::frame::StreamFrame;
+use qrecovery::space::DataSpace;
+
+/// The maximum number of the stream frames waiting to be duplicated on a path, the older ones are
+/// dropped once exceeded, since the original ones are still sent reliably.
+const MAX_DUPLICATES: usize = 64;
+
+/// How the stream data are scheduled across the validated paths when the multipath extension is
+/// used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduler {
+    /// Each path sends the stream data not sent yet, so the data are striped across the paths
+    /// according to their congestion windows.
+    #[default]
+    Stripe,
+    /// The stream data sent on a path are also sent on each other validated path, which trades
+    /// bandwidth for latency and resilience. The duplicates are sent best-effort, they are not
+    /// retransmitted if lost.
+    Duplicate,
+}
+
+impl From<u8> for Scheduler {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Scheduler::Duplicate,
+            _ => Scheduler::Stripe,
+        }
+    }
+}
+
+impl From<Scheduler> for u8 {
+    fn from(scheduler: Scheduler) -> Self {
+        match scheduler {
+            Scheduler::Stripe => 0,
+            Scheduler::Duplicate => 1,
+        }
+    }
+}
+
+/// The sequence numbers of the connection IDs identifying the packet number spaces used by a path.
+///
+/// The packets sent on the path belong to the space of the Destination Connection ID, which is
+/// fixed when the path is created, and the packets received on it belong to the space of the
+/// local connection ID used by the peer, which is learned from the received packets.
+///
+/// Changing the connection ID on a path is not supported while the multipath extension is used,
+/// the packet number spaces are not switched then.
+#[derive(Debug, Clone)]
+pub struct PathSeqs {
+    sent: u64,
+    rcvd: Arc<AtomicU64>,
+}
+
+impl PathSeqs {
+    pub fn new(sent: u64) -> Self {
+        Self {
+            sent,
+            rcvd: Arc::new(AtomicU64::new(u64::MAX)),
+        }
+    }
+
+    /// The sequence number of the Destination Connection ID of the packets sent on the path.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// The sequence number of the local connection ID of the packets received on the path,
+    /// [`None`] if no packet has been received on it.
+    pub fn rcvd(&self) -> Option<u64> {
+        Some(self.rcvd.load(Ordering::Acquire)).filter(|seq| *seq != u64::MAX)
+    }
+
+    pub fn set_rcvd(&self, seq: u64) {
+        self.rcvd.store(seq, Ordering::Release);
+    }
+}
+
+struct Multipath {
+    enabled: AtomicBool,
+    // 序号为0的连接ID对应的空间，即未启用多路径时唯一的1RTT空间
+    space: DataSpace,
+    spaces: DashMap<u64, DataSpace>,
+    scheduler: AtomicU8,
+    duplicates: DashMap<u64, VecDeque<(StreamFrame, Bytes)>>,
+}
+
+/// The state of the multipath extension of a connection, see [Multipath Extension for QUIC](https://www.ietf.org/archive/id/draft-ietf-quic-multipath-05.html).
+///
+/// Once both endpoints enable the enable_multipath transport parameter, each connection ID
+/// identifies a packet number space of the 1-RTT packets, and the packets are protected with the
+/// nonce derived from the sequence number of the connection ID, see [Section 7.2](https://www.ietf.org/archive/id/draft-ietf-quic-multipath-05.html#section-7.2)
+/// of the draft. The space of sequence number 0 is the one used before the extension is negotiated,
+/// and the only one if it is not.
+///
+/// Sending on the multiple paths at the same time, the key update is only tracked in the space of
+/// sequence number 0.
+#[derive(Clone)]
+pub struct ArcMultipath(Arc<Multipath>);
+
+impl ArcMultipath {
+    pub fn new(space: DataSpace) -> Self {
+        Self(Arc::new(Multipath {
+            enabled: AtomicBool::new(false),
+            space,
+            spaces: DashMap::new(),
+            scheduler: AtomicU8::new(Scheduler::default().into()),
+            duplicates: DashMap::new(),
+        }))
+    }
+
+    /// Called when both endpoints enabled the multipath extension.
+    pub fn enable(&self) {
+        self.0.enabled.store(true, Ordering::Release);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.enabled.load(Ordering::Acquire)
+    }
+
+    /// Get the packet number space of the connection ID of sequence number `seq`.
+    pub fn space(&self, seq: u64) -> DataSpace {
+        if seq == 0 || !self.is_enabled() {
+            return self.0.space.clone();
+        }
+        self.0
+            .spaces
+            .entry(seq)
+            .or_insert_with(|| {
+                let space = DataSpace::with_capacity(16);
+                let ack_delay_exponent = self.0.space.rcvd_packets().ack_delay_exponent();
+                space
+                    .rcvd_packets()
+                    .set_ack_delay_exponent(ack_delay_exponent);
+                space
+            })
+            .clone()
+    }
+
+    /// The path ID to derive the nonce of the packets whose connection ID is of sequence number
+    /// `seq`, [`None`] means the nonce of RFC 9001 is used.
+    pub fn path_id(&self, seq: u64) -> Option<u32> {
+        if seq == 0 || !self.is_enabled() {
+            return None;
+        }
+        // path id是连接ID序号的低32位
+        Some(seq as u32)
+    }
+
+    pub fn scheduler(&self) -> Scheduler {
+        self.0.scheduler.load(Ordering::Acquire).into()
+    }
+
+    pub fn set_scheduler(&self, scheduler: Scheduler) {
+        self.0.scheduler.store(scheduler.into(), Ordering::Release);
+        if scheduler == Scheduler::Stripe {
+            self.0.duplicates.clear();
+        }
+    }
+
+    /// Register a path sending packets with the connection ID of sequence number `seq` to receive
+    /// the duplicated stream data.
+    pub fn join(&self, seq: u64) {
+        self.0.duplicates.entry(seq).or_default();
+    }
+
+    /// Stop duplicating the stream data to the path of sequence number `seq`, and drop the pending
+    /// ones.
+    pub fn leave(&self, seq: u64) {
+        self.0.duplicates.remove(&seq);
+    }
+
+    /// Duplicate the stream frame sent on the path of sequence number `seq` to the other paths,
+    /// if the [`Scheduler::Duplicate`] is used.
+    pub fn duplicate(&self, seq: u64, frame: &StreamFrame, data: &[u8]) {
+        if !self.is_enabled() || self.scheduler() != Scheduler::Duplicate {
+            return;
+        }
+        let data = Bytes::copy_from_slice(data);
+        for mut queue in self.0.duplicates.iter_mut().filter(|q| *q.key() != seq) {
+            if queue.len() >= MAX_DUPLICATES {
+                queue.pop_front();
+            }
+            queue.push_back((frame.clone(), data.clone()));
+        }
+    }
+
+    /// Take the next stream frame to be duplicated on the path of sequence number `seq`, if it
+    /// can be written into `capacity` bytes.
+    pub fn next_duplicate(&self, seq: u64, capacity: usize) -> Option<(StreamFrame, Bytes)> {
+        use qbase::frame::BeFrame;
+
+        let mut queue = self.0.duplicates.get_mut(&seq)?;
+        let (frame, _) = queue.front()?;
+        let mut frame = frame.clone();
+        frame.carry_length();
+        if frame.encoding_size() > capacity {
+            return None;
+        }
+        let (_, data) = queue.pop_front()?;
+        Some((frame, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qbase::{frame::StreamFrame, streamid::StreamId, varint::VarInt};
+    use qrecovery::space::DataSpace;
+
+    use super::{ArcMultipath, PathSeqs, Scheduler};
+
+    #[test]
+    fn spaces() {
+        let multipath = ArcMultipath::new(DataSpace::with_capacity(16));
+        // 未启用多路径时，所有序号共用同一个空间
+        multipath.space(1).sent_packets().send().record_trivial();
+        assert_eq!(multipath.space(0).sent_packets().send().next_pn().0, 1);
+        assert_eq!(multipath.path_id(1), None);
+
+        multipath.enable();
+        assert_eq!(multipath.space(2).sent_packets().send().next_pn().0, 0);
+        assert_eq!(multipath.space(0).sent_packets().send().next_pn().0, 1);
+        assert_eq!(multipath.path_id(0), None);
+        assert_eq!(multipath.path_id(2), Some(2));
+
+        let seqs = PathSeqs::new(2);
+        assert_eq!(seqs.rcvd(), None);
+        seqs.set_rcvd(0);
+        assert_eq!(seqs.rcvd(), Some(0));
+    }
+
+    #[test]
+    fn duplicate() {
+        let multipath = ArcMultipath::new(DataSpace::with_capacity(16));
+        multipath.enable();
+        multipath.join(0);
+        multipath.join(1);
+
+        let frame = StreamFrame::new(StreamId::from(VarInt::from_u32(0)), 0, 5);
+        multipath.duplicate(0, &frame, b"hello");
+        assert!(multipath.next_duplicate(0, 100).is_none());
+
+        multipath.set_scheduler(Scheduler::Duplicate);
+        multipath.duplicate(0, &frame, b"hello");
+        assert!(multipath.next_duplicate(0, 100).is_none());
+        // 副本须携带长度字段，空间不足时留待下次发送
+        assert!(multipath.next_duplicate(1, 7).is_none());
+        let (duplicate, data) = multipath.next_duplicate(1, 100).unwrap();
+        assert_eq!(duplicate.range(), frame.range());
+        assert_eq!(&data[..], b"hello");
+        assert!(multipath.next_duplicate(1, 100).is_none());
+
+        multipath.duplicate(0, &frame, b"hello");
+        multipath.leave(1);
+        assert!(multipath.next_duplicate(1, 100).is_none());
+    }
+}
@@ -1,40 +1,72 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::Duration,
+};
 
 use futures::channel::mpsc;
 use qbase::{
-    cid::ConnectionId,
-    config::Parameters,
-    flow::FlowController,
+    cid::{ConnectionId, RotationPolicy},
+    config::{Parameters, PreferredAddress},
+    error::{Error, ErrorKind},
+    flow::{ArcRtt, FlowController},
+    frame::{NewConnectionIdFrame, NewTokenFrame, ReceiveFrame, SendFrame},
     packet::keys::ArcKeys,
     streamid::Role,
     token::{ArcTokenRegistry, TokenRegistry},
+    varint::VarInt,
 };
-use qcongestion::{MayLoss, RetirePktRecord};
+use qcongestion::{ArcCC, CongestionControl, MayLoss, RetirePktRecord, SendRateLimit};
 use qrecovery::reliable::ArcReliableFrameDeque;
 use qunreliable::DatagramFlow;
 use rustls::quic::Keys;
 use tokio::{sync::Notify, task::JoinHandle};
 
 use super::{
-    parameters::ConnParameters,
+    multipath::PathSeqs,
+    parameters::{ConnParameters, RemoteParameters},
     scope::{
-        data::{DataMayLoss, DataScope},
+        data::{DataMayLoss, DataScope, PathRcvdRecords},
         handshake::{HandshakeMayloss, HandshakeScope},
         initial::{InitialMayLoss, InitialScope},
+        RcvdPacketFilter,
     },
+    settings::ConnectionSettings,
+    versions::Versions,
     ArcLocalCids, ArcRemoteCids, CidRegistry, DataStreams, Handshake, RcvdPackets,
 };
 use crate::{
     error::ConnError,
-    path::{pathway::Pathway, ArcPath, ArcPathes, RawPath},
+    path::{pathway::Pathway, ArcPath, ArcPathes, ArcPmtud, RawPath},
     router::Router,
     tls::ArcTlsSession,
 };
 
+/// Create a new attempt of the client connection in the given versions, with the Destination
+/// Connection ID of its first flight and the settings of the current attempt, to restart the
+/// handshake after receiving a Version Negotiation packet or following the upgrade of the server.
+pub type Restart =
+    Box<dyn FnOnce(Versions, ConnectionId, ConnectionSettings) -> RawConnection + Send>;
+
+/// Derive the initial keys of the client from the Destination Connection ID, to follow the one
+/// chosen by the server in a Retry packet.
+pub type DeriveInitialKeys = Box<dyn Fn(ConnectionId) -> Keys + Send>;
+
 pub struct RawConnection {
+    pub versions: Versions,
+    pub restart: Option<Restart>,
+    pub derive_initial_keys: Option<DeriveInitialKeys>,
+    /// The Destination Connection ID of the first Initial packet of the client.
+    pub original_dcid: ConnectionId,
+    /// The Source Connection ID of the Retry packet followed by the client.
+    pub retry_scid: Arc<Mutex<Option<ConnectionId>>>,
     pub token: Arc<Mutex<Vec<u8>>>,
     pub pathes: ArcPathes,
     pub cid_registry: CidRegistry,
+    pub cid_rotation: Arc<Mutex<RotationPolicy>>,
+    /// The settings of the connection, the paths created later follow the current ones.
+    pub settings: Arc<Mutex<ConnectionSettings>>,
+    pub send_rate_limit: SendRateLimit,
     // handshake done的信号
     pub handshake: Handshake,
     pub flow_ctrl: FlowController,
@@ -55,14 +87,17 @@ pub struct RawConnection {
 }
 
 impl RawConnection {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         role: Role,
-        local_params: Parameters,
-        tls_session: ArcTlsSession,
+        versions: Versions,
+        mut local_params: Parameters,
+        new_tls_session: impl FnOnce(&Parameters) -> ArcTlsSession,
         initial_scid: ConnectionId,
         initial_dcid: ConnectionId,
         initial_keys: Keys,
         token_registry: ArcTokenRegistry,
+        settings: ConnectionSettings,
     ) -> Self {
         let (initial_packets_entry, rcvd_initial_packets) = mpsc::unbounded();
         let (zero_rtt_packets_entry, rcvd_0rtt_packets) = mpsc::unbounded();
@@ -70,19 +105,37 @@ impl RawConnection {
         let (one_rtt_packets_entry, rcvd_1rtt_packets) = mpsc::unbounded();
 
         let reliable_frames = ArcReliableFrameDeque::with_capacity(0);
-        let initial = InitialScope::new(ArcKeys::with_keys(initial_keys));
+        let version = versions.version();
+        let initial = InitialScope::new(version, ArcKeys::with_keys(initial_keys));
         let hs = HandshakeScope::default();
         let data = DataScope::default();
+        for crypto_stream in [
+            &initial.crypto_stream,
+            &hs.crypto_stream,
+            &data.crypto_stream,
+        ] {
+            crypto_stream.set_rcvbuf_size(local_params.crypto_buffer_size());
+        }
+        // 发出的ack帧中的ack delay按本地通告的ack_delay_exponent缩放
+        let ack_delay_exponent = local_params.ack_delay_exponent().into_inner() as u8;
+        for rcvd_packets in [
+            initial.space.rcvd_packets(),
+            hs.space.rcvd_packets(),
+            data.space.rcvd_packets(),
+        ] {
+            rcvd_packets.set_ack_delay_exponent(ack_delay_exponent);
+        }
 
+        let packet_entries = [
+            initial_packets_entry,
+            zero_rtt_packets_entry,
+            hs_packets_entry,
+            one_rtt_packets_entry,
+        ];
         let router_registry = Router::registry(
             initial_scid,
             reliable_frames.clone(),
-            [
-                initial_packets_entry,
-                zero_rtt_packets_entry,
-                hs_packets_entry,
-                one_rtt_packets_entry,
-            ],
+            packet_entries.clone(),
         );
         let local_cids = ArcLocalCids::new(initial_scid, router_registry);
         let remote_cids = ArcRemoteCids::new(
@@ -90,9 +143,25 @@ impl RawConnection {
             local_params.active_connection_id_limit().into(),
             reliable_frames.clone(),
         );
+        // The connection ID in the preferred_address carries the sequence number 1, which has been
+        // issued by the local connection IDs, it's also sent in a NEW_CONNECTION_ID frame, which is
+        // harmless since the connection ID and the reset token are the same
+        if let Some(mut preferred_address) = local_params.preferred_address() {
+            let (cid, reset_token) = local_cids
+                .issued_cid(1)
+                .expect("the connection ID of sequence 1 is issued at creation");
+            preferred_address.set_connection_id(cid);
+            preferred_address.set_stateless_reset_token(reset_token);
+            local_params.set_preferred_address(Some(preferred_address));
+        }
+        let tls_session = new_tls_session(&local_params);
         let cid_registry = CidRegistry::new(local_cids, remote_cids);
         let handshake = Handshake::new(role, reliable_frames.clone());
-        let flow_ctrl = FlowController::with_parameter(65535, 65535);
+        let flow_ctrl = FlowController::with_parameter(
+            65535,
+            local_params.initial_max_data().into(),
+            ArcRtt::default(),
+        );
         let conn_error = ConnError::default();
 
         let streams = DataStreams::new(
@@ -100,8 +169,19 @@ impl RawConnection {
             // 流数量
             &local_params,
             Default::default(),
+            flow_ctrl.rtt(),
         );
+        streams.set_max_streams_policy(settings.max_streams_policy);
+        streams.set_send_buffer_limits(settings.send_buffer_limits);
+        streams.set_drop_policy(settings.drop_policy);
         let datagrams = DatagramFlow::new(0);
+        datagrams.set_queue_limits(settings.datagram_queue_limits);
+        datagrams.set_priority(settings.datagram_priority);
+        // 依据上次连接记住的服务端参数，握手完成之前即可在0-RTT包中发送数据报，0-RTT被拒绝则留待1-RTT包；
+        // 接受0-RTT的服务端所通告的限制不会小于记住的，握手完成后以其为准
+        if let Some(remembered) = tls_session.remembered_parameters() {
+            datagrams.set_remote_max_frame_size(remembered.max_datagram_frame_size().into());
+        }
 
         let token = match &*token_registry.lock_guard() {
             TokenRegistry::Client((server_name, client)) => {
@@ -109,8 +189,16 @@ impl RawConnection {
             }
             TokenRegistry::Server(_) => Arc::new(Mutex::new(vec![])),
         };
+        // The peer's max_udp_payload_size limits the path MTU discovery of all paths
+        let max_datagram_size = ArcPmtud::new_max();
+        let remote_params = RemoteParameters::new();
+        let notify = Arc::new(Notify::new());
+        let send_rate_limit = SendRateLimit::new(settings.max_send_rate);
+        let settings = Arc::new(Mutex::new(settings));
         let path_creator = Box::new({
+            let remote_params = remote_params.clone();
             let cid_registry = cid_registry.clone();
+            let max_datagram_size = max_datagram_size.clone();
             let flow_ctrl = flow_ctrl.clone();
             let handshake = handshake.clone();
 
@@ -124,9 +212,11 @@ impl RawConnection {
                 let token = token.clone();
                 move |path: &RawPath| {
                     (
-                        initial.reader(token.clone()),
-                        hs.reader(),
+                        initial.reader(version, token.clone(), role),
+                        hs.reader(version),
                         data.reader(
+                            version,
+                            path.seqs.clone(),
                             path.challenge_sndbuf(),
                             path.response_sndbuf(),
                             reliable_frames.clone(),
@@ -144,16 +234,25 @@ impl RawConnection {
             let initial_may_loss =
                 InitialMayLoss::new(initial.space.clone(), initial.crypto_stream.outgoing());
             let hs_may_loss = HandshakeMayloss::new(hs.space.clone(), hs.crypto_stream.outgoing());
-            let data_may_loss = DataMayLoss::new(
-                data.space.clone(),
-                reliable_frames.clone(),
-                streams.clone(),
-                data.crypto_stream.outgoing(),
-            );
+            let reliable_frames = reliable_frames.clone();
+            let streams = streams.clone();
+            let datagrams = datagrams.clone();
+            let notify = notify.clone();
+            let settings = settings.clone();
+            let send_rate_limit = send_rate_limit.clone();
 
             move |pathway, usc| {
                 let scid = cid_registry.local.active_cids()[0];
                 let dcid = cid_registry.remote.apply_dcid();
+                // 启用多路径后，路径按目标连接ID的序号使用各自的包号空间
+                let seqs = PathSeqs::new(dcid.seq());
+                let data_may_loss = DataMayLoss::new(
+                    data.multipath.space(seqs.sent()),
+                    reliable_frames.clone(),
+                    streams.clone(),
+                    datagrams.clone(),
+                    data.crypto_stream.outgoing(),
+                );
                 let loss: [Box<dyn MayLoss>; 3] = [
                     Box::new(initial_may_loss.clone()),
                     Box::new(hs_may_loss.clone()),
@@ -162,15 +261,80 @@ impl RawConnection {
                 let retire: [Box<dyn RetirePktRecord>; 3] = [
                     Box::new(initial.clone()),
                     Box::new(hs.clone()),
-                    Box::new(data.clone()),
+                    Box::new(PathRcvdRecords::new(data.multipath.clone(), seqs.clone())),
                 ];
 
-                let path = ArcPath::new(usc, scid, dcid, loss, retire);
-                if !handshake.is_handshake_done() {
-                    if role == Role::Client {
-                        path.anti_amplifier.grant();
+                // 每条路径独享一个拥塞控制器，各自估计RTT、检测丢包、验证ECN，迁移到新路径即重置拥塞状态
+                let settings = settings.lock().unwrap().clone();
+                let cc = ArcCC::new(
+                    settings.congestion_algorithm,
+                    settings.congestion_window,
+                    Duration::from_micros(100),
+                    loss,
+                    retire,
+                );
+                cc.set_ack_policy(settings.ack_policy);
+                cc.set_loss_detection(settings.loss_detection);
+                cc.set_send_rate_limit(send_rate_limit.clone());
+                let path =
+                    ArcPath::new(usc, scid, dcid, seqs.clone(), cc, max_datagram_size.clone());
+                data.multipath.join(seqs.sent());
+                // 多路径下路径独占其发包空间，路径失活后，其中在途的数据包都视为丢失，由其他路径重传
+                tokio::spawn({
+                    let path = path.clone();
+                    let multipath = data.multipath.clone();
+                    async move {
+                        path.inactivated().await;
+                        multipath.leave(seqs.sent());
+                        if multipath.is_enabled() {
+                            data_may_loss.may_loss_all();
+                        }
                     }
-                } else {
+                });
+                // 按对端的max_ack_delay和ack_delay_exponent采样RTT、计算PTO；
+                // 对端通告了min_ack_delay后，路径按拥塞窗口请求对端降低确认频率
+                tokio::spawn({
+                    let cc = path.cc.clone();
+                    let remote_params = remote_params.clone();
+                    async move {
+                        let Ok(remote_params) = remote_params.read().await else {
+                            return;
+                        };
+                        cc.set_peer_ack_delay(
+                            Duration::from_millis(remote_params.max_ack_delay().into_inner()),
+                            remote_params.ack_delay_exponent().into_inner() as u8,
+                        );
+                        if let Some(min_ack_delay) = remote_params.min_ack_delay() {
+                            cc.enable_ack_frequency(Duration::from_micros(
+                                min_ack_delay.into_inner(),
+                            ));
+                        }
+                    }
+                });
+                // The client trusts the server's addresses, only the server is limited by the
+                // anti-amplification limit on a new path
+                if role == Role::Client {
+                    path.anti_amplifier.grant();
+                }
+                // 使用零长度连接ID时，按四元组路由数据包，直到路径失活或者连接结束
+                if scid.is_empty() {
+                    Router::route_pathway(path.usc(), &pathway, packet_entries.clone());
+                    tokio::spawn({
+                        let path = path.clone();
+                        let usc = path.usc().clone();
+                        let notify = notify.clone();
+                        let packet_entries = packet_entries.clone();
+                        async move {
+                            tokio::select! {
+                                _ = path.inactivated() => {}
+                                _ = notify.notified() => {}
+                            }
+                            Router::unroute_pathway(&usc, &pathway, &packet_entries);
+                        }
+                    });
+                }
+                if handshake.is_handshake_done() {
+                    path.cc.on_handshake_done();
                     path.begin_validation();
                 }
                 path.begin_sending(pathway, &flow_ctrl, &gen_readers);
@@ -188,18 +352,17 @@ impl RawConnection {
         let validate = {
             let tls_session = tls_session.clone();
             let token_registry = token_registry.clone();
-            move |initial_token: &[u8], path: ArcPath| {
+            move |initial_token: &[u8], remote: SocketAddr, path: ArcPath| {
                 if let TokenRegistry::Server(provider) = &*token_registry.lock_guard() {
-                    if let Some(server_name) = tls_session.server_name() {
-                        if provider.validate_token(server_name, initial_token) {
-                            path.anti_amplifier.grant();
-                        }
+                    let server_name = tls_session.server_name().unwrap_or_default();
+                    if provider.validate_token(server_name, remote, initial_token) {
+                        path.anti_amplifier.grant();
                     }
                 }
             }
         };
 
-        let notify = Arc::new(Notify::new());
+        let rcvd_filter = RcvdPacketFilter::new(&local_params);
         let join_initial = initial.build(
             rcvd_initial_packets,
             &pathes,
@@ -207,42 +370,151 @@ impl RawConnection {
             &notify,
             &conn_error,
             validate,
+            rcvd_filter.clone(),
         );
 
-        let join_hs = hs.build(rcvd_hs_packets, &pathes, &notify, &conn_error);
+        let join_hs = hs.build(
+            rcvd_hs_packets,
+            &pathes,
+            &notify,
+            &conn_error,
+            rcvd_filter.clone(),
+        );
+
+        // See [RFC 9000 section 8.1.3](https://www.rfc-editor.org/rfc/rfc9000.html#name-address-validation-for-futu)
+        // The server issues a token in the NEW_TOKEN frame after the handshake, so that the client
+        // can use it in the Initial packets of a future connection to skip the address validation.
+        let issue_new_token = {
+            let tls_session = tls_session.clone();
+            let token_registry = token_registry.clone();
+            let pathes = pathes.clone();
+            let reliable_frames = reliable_frames.clone();
+            move || {
+                let TokenRegistry::Server(provider) = &*token_registry.lock_guard() else {
+                    return;
+                };
+                let Some(remote) = pathes.iter().next().map(|path| path.key().remote_addr()) else {
+                    return;
+                };
+                let server_name = tls_session.server_name().unwrap_or_default();
+                let token = provider.provide_new_token(&server_name, remote);
+                if !token.is_empty() {
+                    reliable_frames.send_frame([NewTokenFrame { token }]);
+                }
+            }
+        };
 
-        let remote_params = tls_session.keys_upgrade(
+        tls_session.keys_upgrade(
+            remote_params.clone(),
             [
                 &initial.crypto_stream,
                 &hs.crypto_stream,
                 &data.crypto_stream,
             ],
+            data.zero_rtt_keys.clone(),
             hs.keys.clone(),
             data.one_rtt_keys.clone(),
             conn_error.clone(),
             handshake.clone(),
+            issue_new_token,
         );
 
+        let enable_multipath = local_params.enable_multipath();
+        let min_ack_delay = local_params
+            .min_ack_delay()
+            .map(|delay| Duration::from_micros(delay.into_inner()));
+        let reset_stream_at = local_params.reset_stream_at();
+        // 每个连接独立地随机决定是否禁用自旋位
+        let spin_bit = rand::random::<f32>() >= local_params.spin_bit_disable_probability();
         let params = ConnParameters::new(local_params.into(), remote_params.clone());
+        let retry_scid = Arc::new(Mutex::new(None));
+        // 客户端收到HANDSHAKE_DONE帧、服务端完成握手时，握手得到确认，此后丢弃Handshake密钥，
+        // 各路径不再跟踪Initial和Handshake空间的数据包，并开始为1RTT数据包设置PTO
+        tokio::spawn({
+            let handshake = handshake.clone();
+            let hs_keys = hs.keys.clone();
+            let pathes = pathes.clone();
+            let remote_params = remote_params.clone();
+            let notify = notify.clone();
+            async move {
+                tokio::select! {
+                    _ = handshake.confirmed() => {}
+                    _ = notify.notified() => return,
+                }
+                _ = hs_keys.invalid();
+                for path in pathes.iter() {
+                    path.cc.on_handshake_done();
+                }
+                if role == Role::Client {
+                    let Ok(remote_params) = remote_params.read().await else {
+                        return;
+                    };
+                    if let Some(preferred_address) = remote_params.preferred_address() {
+                        migrate_to_preferred_address(pathes, preferred_address).await;
+                    }
+                }
+            }
+        });
         tokio::spawn({
             let streams = streams.clone();
+            let datagrams = datagrams.clone();
+            let flow_ctrl = flow_ctrl.clone();
             let conn_error = conn_error.clone();
             let cid_registry = cid_registry.clone();
+            let versions = versions.clone();
+            let retry_scid = retry_scid.clone();
+            let multipath = data.multipath.clone();
+            let grease_quic_bit = data.grease_quic_bit.clone();
             async move {
                 let remote_params = remote_params.read().await;
                 let Ok(remote_params) = remote_params else {
                     return;
                 };
+                if let Err(e) = versions.validate(remote_params.version_information().as_ref()) {
+                    conn_error.on_error(e);
+                    return;
+                }
+                if role == Role::Client {
+                    let retry_scid = *retry_scid.lock().unwrap();
+                    if let Err(e) = authenticate_cids(&remote_params, initial_dcid, retry_scid) {
+                        conn_error.on_error(e);
+                        return;
+                    }
+                }
 
+                flow_ctrl.reset_send_window(remote_params.initial_max_data().into());
                 let max_bidi_sid = remote_params.initial_max_streams_bidi().into();
                 let max_uni_sid = remote_params.initial_max_streams_uni().into();
                 let active_cid_limit = remote_params.active_connection_id_limit().into();
+                ArcPmtud::set_max(
+                    &max_datagram_size,
+                    remote_params.max_udp_payload_size().into_inner(),
+                );
 
-                streams.premit_max_sid(qbase::streamid::Dir::Bi, max_uni_sid);
-                streams.premit_max_sid(qbase::streamid::Dir::Uni, max_bidi_sid);
+                streams.premit_max_sid(qbase::streamid::Dir::Bi, max_bidi_sid);
+                streams.premit_max_sid(qbase::streamid::Dir::Uni, max_uni_sid);
+                datagrams.set_remote_max_frame_size(remote_params.max_datagram_frame_size().into());
                 if let Err(e) = cid_registry.local.set_limit(active_cid_limit) {
                     conn_error.on_error(e);
                 }
+                if enable_multipath && remote_params.enable_multipath() {
+                    multipath.enable();
+                }
+                if reset_stream_at && remote_params.reset_stream_at() {
+                    streams.enable_reset_stream_at();
+                }
+                if remote_params.grease_quic_bit() {
+                    grease_quic_bit.store(true, Ordering::Release);
+                }
+
+                if let Some(preferred_address) = remote_params.preferred_address() {
+                    if role == Role::Server {
+                        return;
+                    }
+                    if let Err(e) = accept_preferred_cid(&cid_registry, &preferred_address) {
+                        conn_error.on_error(e);
+                    }
+                }
             }
         });
 
@@ -259,13 +531,25 @@ impl RawConnection {
             rcvd_0rtt_packets,
             rcvd_1rtt_packets,
             token_registry,
+            min_ack_delay,
+            reset_stream_at,
+            spin_bit,
+            rcvd_filter,
         );
         let join_handles = [join_initial, join_0rtt, join_hs, join_1rtt];
 
         Self {
+            versions,
+            restart: None,
+            derive_initial_keys: None,
+            original_dcid: initial_dcid,
+            retry_scid,
             token,
             pathes,
             cid_registry,
+            cid_rotation: Arc::default(),
+            settings,
+            send_rate_limit,
             handshake,
             flow_ctrl,
             streams,
@@ -287,4 +571,99 @@ impl RawConnection {
             path.update_recv_time();
         }
     }
+
+    /// Rotate the connection IDs issued to the peer, see [`ArcLocalCids::rotate`].
+    ///
+    /// It does nothing once the multipath extension is negotiated, each connection ID identifies
+    /// a packet number space of a path then, which can't be switched on the path.
+    pub fn rotate_cids(&self) {
+        if self.data.multipath.is_enabled() {
+            return;
+        }
+        self.cid_registry.local.rotate();
+    }
+}
+
+/// Authenticate the connection IDs the client used before receiving the server's Initial packets,
+/// by the transport parameters of the server, see
+/// [Section 7.3](https://www.rfc-editor.org/rfc/rfc9000#section-7.3) of RFC 9000.
+fn authenticate_cids(
+    server_params: &Parameters,
+    original_dcid: ConnectionId,
+    retry_scid: Option<ConnectionId>,
+) -> Result<(), Error> {
+    if *server_params.original_destination_connection_id() != Some(original_dcid) {
+        return Err(Error::with_default_fty(
+            ErrorKind::TransportParameter,
+            "original_destination_connection_id mismatch",
+        ));
+    }
+    if server_params.retry_source_connection_id() != retry_scid {
+        return Err(Error::with_default_fty(
+            ErrorKind::TransportParameter,
+            "retry_source_connection_id mismatch",
+        ));
+    }
+    Ok(())
+}
+
+/// Accept the connection ID in the server's preferred_address transport parameter, whose sequence
+/// number is 1, see [Section 5.1.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-5.1.1)
+/// of RFC 9000.
+///
+/// A server using the zero-length connection ID must not provide a preferred address.
+fn accept_preferred_cid(
+    cid_registry: &CidRegistry,
+    preferred_address: &PreferredAddress,
+) -> Result<(), Error> {
+    if preferred_address.connection_id() == ConnectionId::default() {
+        return Err(Error::with_default_fty(
+            ErrorKind::TransportParameter,
+            "zero-length connection ID in preferred_address",
+        ));
+    }
+    let frame = NewConnectionIdFrame {
+        sequence: VarInt::from_u32(1),
+        retire_prior_to: VarInt::from_u32(0),
+        id: preferred_address.connection_id(),
+        reset_token: preferred_address.stateless_reset_token(),
+    };
+    cid_registry.remote.recv_frame(&frame).map(|_| ())
+}
+
+/// Migrate to the server's preferred address, called once the handshake is confirmed, see
+/// [Section 9.6](https://www.rfc-editor.org/rfc/rfc9000.html#section-9.6) of RFC 9000.
+///
+/// The address in the same family as the current path is chosen, and the new path only sends the
+/// probing frames until it's validated, then the original path is abandoned. If the validation
+/// fails, the connection keeps using the original path. The disable_active_migration transport
+/// parameter does not prohibit this migration.
+async fn migrate_to_preferred_address(pathes: ArcPathes, preferred_address: PreferredAddress) {
+    let Some((pathway, usc)) = pathes
+        .iter()
+        .next()
+        .map(|path| (*path.key(), path.usc().clone()))
+    else {
+        return;
+    };
+    let remote = match pathway.remote_addr() {
+        SocketAddr::V4(_) => SocketAddr::V4(preferred_address.address_v4()),
+        SocketAddr::V6(_) => SocketAddr::V6(preferred_address.address_v6()),
+    };
+    // 全零的地址表示服务端未提供该地址族的地址
+    if remote.ip().is_unspecified() || remote.port() == 0 || remote == pathway.remote_addr() {
+        return;
+    }
+    let new_pathway = Pathway::Direct {
+        local: pathway.local_addr(),
+        remote,
+    };
+    let new_path = pathes.get_or_create(new_pathway, usc);
+    if !new_path.validated().await {
+        new_path.inactivate();
+        return;
+    }
+    for path in pathes.iter().filter(|path| *path.key() != new_pathway) {
+        path.inactivate();
+    }
 }
@@ -2,17 +2,116 @@ pub mod data;
 pub mod handshake;
 pub mod initial;
 
-use std::future::Future;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 pub use data::{ClosingOneRttScope, DataScope};
 pub use handshake::{ClosingHandshakeScope, HandshakeScope};
 pub use initial::InitialScope;
 use qbase::{
-    frame::{Frame, FrameReader},
+    config::Parameters,
+    error::{Error as QuicError, ErrorKind},
+    frame::{Frame, FrameReader, FrameType},
     packet::{decrypt::decrypt_packet, header::GetType, DataPacket},
+    streamid::Role,
 };
 use tokio::sync::Notify;
 
+use crate::path::{pathway::Pathway, Pathes};
+
+/// The restrictions on the received packets, determined by the local transport parameters
+/// advertised to the peer. The packets violating them are discarded silently.
+///
+/// The packets failed to be authenticated are also counted, which is shared by all the packet
+/// number spaces of the connection.
+#[derive(Debug, Clone)]
+pub struct RcvdPacketFilter {
+    /// The max_udp_payload_size advertised, the peer must not send any larger packet.
+    pub max_udp_payload_size: usize,
+    /// Whether the grease_quic_bit transport parameter is advertised, the packets with the QUIC
+    /// Bit cleared are not valid otherwise, see [RFC 9287](https://www.rfc-editor.org/rfc/rfc9287.html).
+    pub tolerate_greased_quic_bit: bool,
+    /// Whether the disable_active_migration transport parameter is advertised by the server.
+    pub disable_active_migration: bool,
+    forged_packets: Arc<AtomicU64>,
+}
+
+impl RcvdPacketFilter {
+    pub fn new(params: &Parameters) -> Self {
+        Self {
+            max_udp_payload_size: usize::try_from(params.max_udp_payload_size().into_inner())
+                .unwrap_or(usize::MAX),
+            tolerate_greased_quic_bit: params.grease_quic_bit(),
+            disable_active_migration: params.disable_active_migration(),
+            forged_packets: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Be informed that a received packet failed to be authenticated by the key whose AEAD has the
+    /// integrity limit `integrity_limit`.
+    ///
+    /// Return an AEAD_LIMIT_REACHED error if the number of the forged packets during the connection
+    /// reaches the limit, see [Section 6.6](https://www.rfc-editor.org/rfc/rfc9001#section-6.6) of
+    /// RFC 9001.
+    pub fn on_decrypt_failed(&self, integrity_limit: u64) -> Result<(), QuicError> {
+        let forged = self.forged_packets.fetch_add(1, Ordering::AcqRel) + 1;
+        if forged >= integrity_limit {
+            return Err(aead_limit_reached(
+                "too many packets failed to be authenticated",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Count the received `packet` into the anti-amplification limit of the path via `pathway`
+    /// first, then check it against the restrictions. Return whether the bytes are counted, see
+    /// [`Pathes::on_rcvd`], or [`None`] if the packet should be discarded.
+    ///
+    /// The discarded packets are counted too, the server must count all the bytes received in
+    /// the datagrams attributed to the connection, see [Section 8.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-8.1)
+    /// of RFC 9000.
+    pub fn on_rcvd(&self, packet: &DataPacket, pathes: &Pathes, pathway: &Pathway) -> Option<bool> {
+        let counted = pathes.on_rcvd(pathway, packet.bytes.len());
+        self.accept(packet).then_some(counted)
+    }
+
+    fn accept(&self, packet: &DataPacket) -> bool {
+        packet.bytes.len() <= self.max_udp_payload_size
+            && (self.tolerate_greased_quic_bit || !packet.is_quic_bit_greased())
+    }
+
+    /// Whether a new path can be created for an authenticated packet received via the unknown
+    /// `pathway`, see [Section 9](https://www.rfc-editor.org/rfc/rfc9000.html#section-9) of RFC 9000.
+    ///
+    /// The client discards the packets from the unknown server addresses. The client must not
+    /// migrate before the handshake is confirmed, nor after it if the server disabled the active
+    /// migration, the packets are dropped then, except the ones sent to the server's preferred
+    /// address, which is not used by any path yet.
+    pub fn accept_new_path(
+        &self,
+        role: Role,
+        is_handshake_done: bool,
+        pathes: &Pathes,
+        pathway: &Pathway,
+    ) -> bool {
+        match role {
+            Role::Client => false,
+            Role::Server => {
+                is_handshake_done
+                    && !(self.disable_active_migration
+                        && pathes
+                            .iter()
+                            .any(|path| path.key().local_addr() == pathway.local_addr()))
+            }
+        }
+    }
+}
+
 pub trait RecvPacket {
     fn has_rcvd_ccf(&self, packet: DataPacket) -> bool;
 
@@ -22,7 +121,9 @@ pub trait RecvPacket {
         mut packet: DataPacket,
         body_offset: usize,
     ) -> bool {
-        decrypt_packet(key, pn, packet.bytes.as_mut(), body_offset).unwrap();
+        if decrypt_packet(key, pn, packet.bytes.as_mut(), body_offset).is_err() {
+            return false;
+        }
         let body = packet.bytes.split_off(body_offset);
         FrameReader::new(body.freeze(), packet.header.get_type())
             .filter_map(|frame| frame.ok())
@@ -30,6 +131,21 @@ pub trait RecvPacket {
     }
 }
 
+/// The peer acknowledged a packet number never used, which may be skipped deliberately to detect
+/// the optimistic acknowledgments, see [Section 13.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-13.1)
+/// of RFC 9000.
+fn ack_unsent_packet(frame_type: FrameType) -> QuicError {
+    QuicError::new(
+        ErrorKind::ProtocolViolation,
+        frame_type,
+        "acknowledged a packet number never sent",
+    )
+}
+
+fn aead_limit_reached(reason: &'static str) -> QuicError {
+    QuicError::with_default_fty(ErrorKind::AeadLimitReached, reason)
+}
+
 async fn any<F, T>(fut: F, notify: &Notify) -> Option<T>
 where
     F: Future<Output = Option<T>>,
@@ -39,3 +155,21 @@ where
         v = fut => v,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use qbase::{config::Parameters, error::ErrorKind};
+
+    use super::RcvdPacketFilter;
+
+    #[test]
+    fn forged_packets() {
+        let filter = RcvdPacketFilter::new(&Parameters::default());
+        // 各个空间共用同一个计数
+        let other_space = filter.clone();
+        assert!(filter.on_decrypt_failed(3).is_ok());
+        assert!(other_space.on_decrypt_failed(3).is_ok());
+        let error = filter.on_decrypt_failed(3).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AeadLimitReached);
+    }
+}
@@ -1,38 +1,54 @@
-use std::sync::Arc;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use bytes::Bytes;
+use bytes::{BufMut, Bytes};
 use futures::{channel::mpsc, StreamExt};
 use qbase::{
+    cid::ConnectionId,
     error::{Error as QuicError, ErrorKind},
     flow,
     frame::{
-        AckFrame, BeFrame, Frame, FrameReader, PathChallengeFrame, PathResponseFrame, ReceiveFrame,
-        ReliableFrame, SendFrame, StreamCtlFrame, StreamFrame,
+        io::WriteFrame, AckFrame, BeFrame, ConnectionCloseFrame, Frame, FrameReader, FrameType,
+        PathChallengeFrame, PathResponseFrame, ReceiveFrame, ReliableFrame, SendFrame,
+        StreamCtlFrame, StreamFrame,
     },
     handshake::Handshake,
     packet::{
         decrypt::{
-            decrypt_packet, remove_protection_of_long_packet, remove_protection_of_short_packet,
+            decrypt_packet, decrypt_packet_for_path, remove_protection_of_long_packet,
+            remove_protection_of_short_packet,
         },
-        header::GetType,
+        encrypt::{encode_short_first_byte, encrypt_packet, protect_header},
+        header::{GetDcid, GetType, WriteShortHeader},
         keys::{ArcKeys, ArcOneRttKeys, ArcOneRttPacketKeys, HeaderProtectionKeys},
         r#type::Type,
-        DataPacket, PacketNumber,
+        DataHeader, DataPacket, EncodeHeader, OneRttHeader, PacketNumber, SpinBit,
+        WritePacketNumber,
     },
+    streamid::Role,
     token::ArcTokenRegistry,
 };
 use qcongestion::{CongestionControl, MayLoss, RetirePktRecord};
 use qrecovery::{
-    crypto::{CryptoStream, CryptoStreamOutgoing},
+    crypto::{CryptoStream, CryptoStreamOutgoing, DEFAULT_CRYPTO_BUFFER_SIZE},
     reliable::{ArcRcvdPktRecords, ArcReliableFrameDeque, GuaranteedFrame},
     space::{DataSpace, Epoch},
 };
 use qunreliable::DatagramFlow;
 use tokio::{sync::Notify, task::JoinHandle};
 
-use super::any;
+use super::{ack_unsent_packet, aead_limit_reached, any, RcvdPacketFilter};
 use crate::{
-    connection::{transmit::data::DataSpaceReader, CidRegistry, DataStreams, RcvdPackets},
+    connection::{
+        multipath::{ArcMultipath, PathSeqs},
+        transmit::data::DataSpaceReader,
+        ArcLocalCids, CidRegistry, DataStreams, RcvdPackets,
+    },
     error::ConnError,
     path::{ArcPathes, RawPath, SendBuffer},
     pipe,
@@ -42,18 +58,29 @@ use crate::{
 #[derive(Clone)]
 pub struct DataScope {
     pub zero_rtt_keys: ArcKeys,
+    /// The remaining bytes of 0-RTT packet payload can be accepted
+    pub early_data_quota: Arc<AtomicUsize>,
     pub one_rtt_keys: ArcOneRttKeys,
     pub space: DataSpace,
+    /// The packet number spaces of the multipath extension, including the `space` above.
+    pub multipath: ArcMultipath,
     pub crypto_stream: CryptoStream,
+    /// Whether the QUIC Bit of the sent 1-RTT packets can be greased, set once the peer advertised
+    /// the grease_quic_bit transport parameter.
+    pub grease_quic_bit: Arc<AtomicBool>,
 }
 
 impl Default for DataScope {
     fn default() -> Self {
+        let space = DataSpace::with_capacity(16);
         Self {
             zero_rtt_keys: ArcKeys::new_pending(),
+            early_data_quota: Arc::new(AtomicUsize::new(usize::MAX)),
             one_rtt_keys: ArcOneRttKeys::new_pending(),
-            space: DataSpace::with_capacity(16),
-            crypto_stream: CryptoStream::new(4096, 4096),
+            multipath: ArcMultipath::new(space.clone()),
+            space,
+            crypto_stream: CryptoStream::new(4096, DEFAULT_CRYPTO_BUFFER_SIZE),
+            grease_quic_bit: Arc::new(AtomicBool::new(false)),
         }
     }
 }
@@ -74,6 +101,10 @@ impl DataScope {
         rcvd_0rtt_packets: RcvdPackets,
         rcvd_1rtt_packets: RcvdPackets,
         recv_new_token: ArcTokenRegistry,
+        min_ack_delay: Option<Duration>,
+        reset_stream_at: bool,
+        spin_bit: bool,
+        rcvd_filter: RcvdPacketFilter,
     ) -> (JoinHandle<RcvdPackets>, JoinHandle<RcvdPackets>) {
         let (ack_frames_entry, rcvd_ack_frames) = mpsc::unbounded();
         // 连接级的
@@ -91,11 +122,76 @@ impl DataScope {
 
         let dispatch_data_frame = {
             let conn_error = conn_error.clone();
+            let multipath = self.multipath.clone();
+            let pathes = pathes.clone();
+            // 流控的接收窗口按最近收到ACK的路径的RTT自动增长
+            let rtt = flow_ctrl.rtt();
             move |frame: Frame, pty: Type, path: &RawPath| match frame {
+                // 启用多路径后，ACK帧确认的是序号为0的连接ID的空间中的数据包，由使用该连接ID发包的路径处理
+                Frame::Ack(f) if multipath.space(0).sent_packets().recv().acks_unsent(&f) => {
+                    conn_error.on_error(ack_unsent_packet(f.frame_type()))
+                }
+                Frame::Ack(f) if multipath.is_enabled() => {
+                    if let Some((_, path)) = pathes.find_by_sent_seq(0) {
+                        path.cc.on_ack(Epoch::Data, &f);
+                        rtt.update(path.cc.smoothed_rtt());
+                    }
+                    _ = ack_frames_entry.unbounded_send((0, f))
+                }
                 Frame::Ack(f) => {
                     path.cc.on_ack(Epoch::Data, &f);
-                    _ = ack_frames_entry.unbounded_send(f)
+                    rtt.update(path.cc.smoothed_rtt());
+                    _ = ack_frames_entry.unbounded_send((0, f))
+                }
+                Frame::AckMp(f) if !multipath.is_enabled() => {
+                    conn_error.on_error(multipath_not_negotiated(f.frame_type()))
+                }
+                Frame::PathAbandon(f) if !multipath.is_enabled() => {
+                    conn_error.on_error(multipath_not_negotiated(f.frame_type()))
+                }
+                Frame::AckMp(f)
+                    if multipath
+                        .space(f.dcid_seq.into_inner())
+                        .sent_packets()
+                        .recv()
+                        .acks_unsent(&f.ack) =>
+                {
+                    conn_error.on_error(ack_unsent_packet(f.frame_type()))
                 }
+                Frame::AckMp(f) => {
+                    let seq = f.dcid_seq.into_inner();
+                    if let Some((_, path)) = pathes.find_by_sent_seq(seq) {
+                        path.cc.on_ack(Epoch::Data, &f.ack);
+                        rtt.update(path.cc.smoothed_rtt());
+                    }
+                    _ = ack_frames_entry.unbounded_send((seq, f.ack))
+                }
+                // 对端放弃的路径，由对端收包所用的连接ID标识，即本端发包的目标连接ID，
+                // 路径失活后，其中在途的数据包都视为丢失
+                Frame::PathAbandon(f) => {
+                    if let Some((_, path)) = pathes.find_by_sent_seq(f.dcid_seq.into_inner()) {
+                        path.inactivate();
+                    }
+                }
+                // 本端未通告min_ack_delay时，对端不得发送ACK频率扩展的帧
+                Frame::AckFrequency(f) => match min_ack_delay {
+                    Some(min_ack_delay)
+                        if Duration::from_micros(f.request_max_ack_delay.into_inner())
+                            >= min_ack_delay =>
+                    {
+                        path.cc.on_ack_frequency(&f)
+                    }
+                    Some(_) => conn_error.on_error(QuicError::new(
+                        ErrorKind::ProtocolViolation,
+                        f.frame_type(),
+                        "the requested max ack delay is less than min_ack_delay",
+                    )),
+                    None => conn_error.on_error(ack_frequency_not_negotiated(f.frame_type())),
+                },
+                Frame::ImmediateAck(f) if min_ack_delay.is_none() => {
+                    conn_error.on_error(ack_frequency_not_negotiated(f.frame_type()))
+                }
+                Frame::ImmediateAck(_) => path.cc.on_immediate_ack(),
                 Frame::NewToken(f) => _ = new_token_frames_entry.unbounded_send(f),
                 Frame::MaxData(f) => _ = max_data_frames_entry.unbounded_send(f),
                 Frame::NewConnectionId(f) => _ = new_cid_frames_entry.unbounded_send(f),
@@ -104,6 +200,9 @@ impl DataScope {
                 Frame::DataBlocked(f) => _ = data_blocked_frames_entry.unbounded_send(f),
                 Frame::Challenge(f) => path.recv_challenge(f),
                 Frame::Response(f) => path.recv_response(f),
+                Frame::StreamCtl(StreamCtlFrame::ResetStreamAt(f)) if !reset_stream_at => {
+                    conn_error.on_error(reset_stream_at_not_negotiated(f.frame_type()))
+                }
                 Frame::StreamCtl(f) => _ = stream_ctrl_frames_entry.unbounded_send(f),
                 Frame::Stream(f, data) => _ = stream_frames_entry.unbounded_send((f, data)),
                 Frame::Crypto(f, bytes) => _ = crypto_frames_entry.unbounded_send((f, bytes)),
@@ -114,9 +213,16 @@ impl DataScope {
         };
         let on_data_acked = {
             let data_streams = streams.clone();
+            let datagrams = datagrams.clone();
             let crypto_stream_outgoing = self.crypto_stream.outgoing();
-            let sent_pkt_records = self.space.sent_packets();
-            move |ack_frame: &AckFrame| {
+            let multipath = self.multipath.clone();
+            let one_rtt_keys = self.one_rtt_keys.clone();
+            move |(seq, ack_frame): &(u64, AckFrame)| {
+                // 密钥更新只跟踪序号为0的连接ID的空间
+                if let Some((_, pk)) = one_rtt_keys.get_local_keys().filter(|_| *seq == 0) {
+                    pk.lock_guard().on_pkt_acked(ack_frame.largest.into_inner());
+                }
+                let sent_pkt_records = multipath.space(*seq).sent_packets();
                 let mut recv_guard = sent_pkt_records.recv();
                 recv_guard.update_largest(ack_frame.largest.into_inner());
 
@@ -132,6 +238,12 @@ impl DataScope {
                             GuaranteedFrame::Reliable(ReliableFrame::Stream(
                                 StreamCtlFrame::ResetStream(reset_frame),
                             )) => data_streams.on_reset_acked(reset_frame),
+                            GuaranteedFrame::Reliable(ReliableFrame::Stream(
+                                StreamCtlFrame::ResetStreamAt(reset_frame),
+                            )) => data_streams.on_reset_at_acked(reset_frame),
+                            GuaranteedFrame::Datagram(datagram) => {
+                                datagrams.on_datagram_acked(datagram.id)
+                            }
                             _ => { /* nothing to do */ }
                         }
                     }
@@ -142,7 +254,7 @@ impl DataScope {
         // Assemble the pipelines of frame processing
         // TODO: pipe rcvd_new_token_frames
         let local_cids_with_router = Router::revoke(cid_registry.local.clone());
-        pipe!(rcvd_retire_cid_frames |> local_cids_with_router, recv_frame);
+        pipe!(@error(conn_error) rcvd_retire_cid_frames |> local_cids_with_router, recv_frame);
         pipe!(@error(conn_error) rcvd_new_cid_frames |> cid_registry.remote, recv_frame);
         pipe!(rcvd_max_data_frames |> flow_ctrl.sender, recv_frame);
         pipe!(rcvd_data_blocked_frames |> flow_ctrl.recver, recv_frame);
@@ -168,13 +280,18 @@ impl DataScope {
             dispatch_data_frame.clone(),
             notify.clone(),
             conn_error.clone(),
+            rcvd_filter.clone(),
         );
         let join_handler1 = self.parse_rcvd_1rtt_packet_and_dispatch_frames(
             rcvd_1rtt_packets,
             pathes.clone(),
+            cid_registry.local.clone(),
+            handshake.clone(),
+            spin_bit,
             dispatch_data_frame,
             notify.clone(),
             conn_error.clone(),
+            rcvd_filter,
         );
         (join_handler0, join_handler1)
     }
@@ -186,13 +303,20 @@ impl DataScope {
         dispatch_frame: impl Fn(Frame, Type, &RawPath) + Send + 'static,
         notify: Arc<Notify>,
         conn_error: ConnError,
+        rcvd_filter: RcvdPacketFilter,
     ) -> JoinHandle<RcvdPackets> {
         tokio::spawn({
             let rcvd_pkt_records = self.space.rcvd_packets();
             let keys = self.zero_rtt_keys.clone();
+            let early_data_quota = self.early_data_quota.clone();
             async move {
-                while let Some((mut packet, pathway, usc)) = any(rcvd_packets.next(), &notify).await
+                while let Some((mut packet, pathway, ecn, _usc)) =
+                    any(rcvd_packets.next(), &notify).await
                 {
+                    let rcvd_bytes = packet.bytes.len();
+                    let Some(counted) = rcvd_filter.on_rcvd(&packet, &pathes, &pathway) else {
+                        continue;
+                    };
                     let pty = packet.header.get_type();
                     let Some(keys) = any(keys.get_remote_keys(), &notify).await else {
                         break;
@@ -216,16 +340,40 @@ impl DataScope {
                         Err(_e) => continue,
                     };
                     let body_offset = packet.offset + undecoded_pn.size();
-                    let pkt_len = decrypt_packet(
+                    let pkt_len = match decrypt_packet(
                         keys.remote.packet.as_ref(),
                         pn,
                         packet.bytes.as_mut(),
                         body_offset,
-                    )
-                    .unwrap();
+                    ) {
+                        Ok(pkt_len) => pkt_len,
+                        Err(_) => match rcvd_filter
+                            .on_decrypt_failed(keys.remote.packet.integrity_limit())
+                        {
+                            Ok(()) => continue,
+                            Err(e) => {
+                                conn_error.on_error(e);
+                                break;
+                            }
+                        },
+                    };
 
-                    let path = pathes.get_or_create(pathway, usc);
-                    path.on_rcvd(packet.bytes.len());
+                    // Discard the 0-RTT packets beyond the quota, the client will retransmit the
+                    // data in 1-RTT packets as they are never acknowledged
+                    if early_data_quota
+                        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |quota| {
+                            quota.checked_sub(pkt_len)
+                        })
+                        .is_err()
+                    {
+                        continue;
+                    }
+
+                    // 握手确认之前不允许迁移，来自未知路径的数据包直接丢弃
+                    let Some(path) = pathes.get(&pathway).map(|path| path.clone()) else {
+                        continue;
+                    };
+                    path.on_rcvd(if counted { 0 } else { rcvd_bytes });
 
                     let _header = packet.bytes.split_to(body_offset);
                     packet.bytes.truncate(pkt_len);
@@ -239,8 +387,8 @@ impl DataScope {
                         },
                     ) {
                         Ok(is_ack_packet) => {
-                            rcvd_pkt_records.register_pn(pn);
-                            path.cc.on_pkt_rcvd(Epoch::Data, pn, is_ack_packet);
+                            rcvd_pkt_records.register_pn(pn, ecn);
+                            path.cc.on_pkt_rcvd(Epoch::Data, pn, is_ack_packet, ecn);
                         }
                         Err(e) => conn_error.on_error(e),
                     }
@@ -250,20 +398,31 @@ impl DataScope {
         })
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn parse_rcvd_1rtt_packet_and_dispatch_frames(
         &self,
         mut rcvd_packets: RcvdPackets,
         pathes: ArcPathes,
+        local_cids: ArcLocalCids,
+        handshake: Handshake<ArcReliableFrameDeque>,
+        spin_bit: bool,
         dispatch_frame: impl Fn(Frame, Type, &RawPath) + Send + 'static,
         notify: Arc<Notify>,
         conn_error: ConnError,
+        rcvd_filter: RcvdPacketFilter,
     ) -> JoinHandle<RcvdPackets> {
         tokio::spawn({
-            let rcvd_pkt_records = self.space.rcvd_packets();
+            let multipath = self.multipath.clone();
             let keys = self.one_rtt_keys.clone();
+            let role = handshake.role();
             async move {
-                while let Some((mut packet, pathway, usc)) = any(rcvd_packets.next(), &notify).await
+                while let Some((mut packet, pathway, ecn, usc)) =
+                    any(rcvd_packets.next(), &notify).await
                 {
+                    let rcvd_bytes = packet.bytes.len();
+                    let Some(counted) = rcvd_filter.on_rcvd(&packet, &pathes, &pathway) else {
+                        continue;
+                    };
                     let pty = packet.header.get_type();
                     let Some((hpk, pk)) = any(keys.get_remote_keys(), &notify).await else {
                         break;
@@ -281,34 +440,119 @@ impl DataScope {
                         }
                     };
 
+                    // 启用多路径后，数据包所属的空间由其目标连接ID的序号决定
+                    let dcid_seq = local_cids.seq_of(packet.header.get_dcid());
+                    let seq = dcid_seq.unwrap_or(0);
+                    let rcvd_pkt_records = multipath.space(seq).rcvd_packets();
+                    let path_id = multipath.path_id(seq);
                     let pn = match rcvd_pkt_records.decode_pn(undecoded_pn) {
                         Ok(pn) => pn,
                         // TooOld/TooLarge/HasRcvd
                         Err(_e) => continue,
                     };
                     let body_offset = packet.offset + undecoded_pn.size();
-                    let pk = pk.lock_guard().get_remote(key_phase, pn);
-                    let pkt_len =
-                        decrypt_packet(pk.as_ref(), pn, packet.bytes.as_mut(), body_offset)
-                            .unwrap();
+                    let remote_pk = pk.lock_guard().get_remote(key_phase, pn);
+                    let decrypted = match path_id {
+                        Some(path_id) => decrypt_packet_for_path(
+                            remote_pk.as_ref(),
+                            path_id,
+                            pn,
+                            packet.bytes.as_mut(),
+                            body_offset,
+                        ),
+                        None => decrypt_packet(
+                            remote_pk.as_ref(),
+                            pn,
+                            packet.bytes.as_mut(),
+                            body_offset,
+                        ),
+                    };
+                    let pkt_len = match decrypted {
+                        Ok(pkt_len) => pkt_len,
+                        Err(_) => {
+                            match rcvd_filter.on_decrypt_failed(remote_pk.integrity_limit()) {
+                                Ok(()) => continue,
+                                Err(e) => {
+                                    conn_error.on_error(e);
+                                    break;
+                                }
+                            }
+                        }
+                    };
 
-                    let path = pathes.get_or_create(pathway, usc);
-                    path.on_rcvd(packet.bytes.len());
+                    // 来自新地址的数据包，需先验证该路径，同时验证的路径过多就丢弃
+                    if !pathes.contains_key(&pathway)
+                        && !rcvd_filter.accept_new_path(
+                            role,
+                            handshake.is_handshake_done(),
+                            &pathes,
+                            &pathway,
+                        )
+                    {
+                        continue;
+                    }
+                    let Some(path) = pathes.get_or_probe(pathway, usc) else {
+                        continue;
+                    };
+                    path.on_rcvd(if counted { 0 } else { rcvd_bytes });
+                    path.seqs.set_rcvd(seq);
+                    if path_id.is_none() {
+                        let pto = path.cc.pto_time(Epoch::Data);
+                        pk.lock_guard().on_rcvd(key_phase, pn, pto);
+                    }
+                    // 本地密钥用到了机密性上限，又没能及时更新，只能关闭连接
+                    if pk.lock_guard().is_exhausted() {
+                        conn_error.on_error(aead_limit_reached(
+                            "the confidentiality limit of the local key is reached",
+                        ));
+                        break;
+                    }
 
+                    let spin = match &packet.header {
+                        DataHeader::Short(hdr) => Some(hdr.spin),
+                        DataHeader::Long(_) => None,
+                    };
                     let _header = packet.bytes.split_to(body_offset);
                     packet.bytes.truncate(pkt_len);
 
                     match FrameReader::new(packet.bytes.freeze(), pty).try_fold(
-                        false,
-                        |is_ack_packet, frame| {
+                        (false, true),
+                        |(is_ack_packet, is_probing), frame| {
                             let (frame, is_ack_eliciting) = frame?;
+                            let is_probing = is_probing && frame.is_probing();
+                            // 不得退役承载该帧的数据包所用的目标连接ID
+                            if let Frame::RetireConnectionId(f) = &frame {
+                                if dcid_seq == Some(f.sequence.into_inner()) {
+                                    return Err(QuicError::new(
+                                        ErrorKind::ProtocolViolation,
+                                        f.frame_type(),
+                                        "retire the connection ID in use",
+                                    ));
+                                }
+                            }
                             dispatch_frame(frame, pty, &path);
-                            Ok(is_ack_packet || is_ack_eliciting)
+                            Ok((is_ack_packet || is_ack_eliciting, is_probing))
                         },
                     ) {
-                        Ok(is_ack_packet) => {
-                            rcvd_pkt_records.register_pn(pn);
-                            path.cc.on_pkt_rcvd(Epoch::Data, pn, is_ack_packet);
+                        Ok((is_ack_packet, is_probing)) => {
+                            // 服务端收到包号最大的非探测包，说明客户端已迁移到该路径，
+                            // 但须等到该路径验证通过后，才切换过去；启用多路径后，各路径同时使用
+                            if role == Role::Server
+                                && !multipath.is_enabled()
+                                && !is_probing
+                                && !path.is_probing()
+                                && rcvd_pkt_records.is_newest(pn)
+                            {
+                                pathes.migrate_to(&pathway);
+                            }
+                            // 禁用自旋位时，忽略收到的自旋位，发送的自旋位始终为0
+                            if let Some(spin) = spin.filter(|_| spin_bit) {
+                                if rcvd_pkt_records.is_newest(pn) {
+                                    path.on_rcvd_spin(spin, role);
+                                }
+                            }
+                            rcvd_pkt_records.register_pn(pn, ecn);
+                            path.cc.on_pkt_rcvd(Epoch::Data, pn, is_ack_packet, ecn);
                         }
                         Err(e) => conn_error.on_error(e),
                     }
@@ -372,8 +616,11 @@ impl DataScope {
         });
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn reader(
         &self,
+        version: u32,
+        seqs: PathSeqs,
         challenge_sndbuf: SendBuffer<PathChallengeFrame>,
         response_sndbuf: SendBuffer<PathResponseFrame>,
         reliable_frames: ArcReliableFrameDeque,
@@ -381,9 +628,14 @@ impl DataScope {
         datagrams: DatagramFlow,
     ) -> DataSpaceReader {
         DataSpaceReader {
-            space: self.space.clone(),
+            version,
+            space: self.multipath.space(seqs.sent()),
+            multipath: self.multipath.clone(),
+            path_id: self.multipath.path_id(seqs.sent()),
+            seqs,
             zero_rtt_keys: self.zero_rtt_keys.clone(),
             one_rtt_keys: self.one_rtt_keys.clone(),
+            grease_quic_bit: self.grease_quic_bit.clone(),
             challenge_sndbuf,
             response_sndbuf,
             crypto_stream_outgoing: self.crypto_stream.outgoing(),
@@ -394,9 +646,55 @@ impl DataScope {
     }
 }
 
-impl RetirePktRecord for DataScope {
+/// Receiving the frames of the multipath extension without negotiating it is a connection error
+/// of type PROTOCOL_VIOLATION.
+fn multipath_not_negotiated(frame_type: FrameType) -> QuicError {
+    QuicError::new(
+        ErrorKind::ProtocolViolation,
+        frame_type,
+        "the multipath extension is not negotiated",
+    )
+}
+
+/// Receiving the frames of the ACK frequency extension without advertising the min_ack_delay
+/// transport parameter is a connection error of type PROTOCOL_VIOLATION.
+fn ack_frequency_not_negotiated(frame_type: FrameType) -> QuicError {
+    QuicError::new(
+        ErrorKind::ProtocolViolation,
+        frame_type,
+        "the ACK frequency extension is not negotiated",
+    )
+}
+
+/// Receiving the RESET_STREAM_AT frame without advertising the reset_stream_at transport
+/// parameter is a connection error of type PROTOCOL_VIOLATION.
+fn reset_stream_at_not_negotiated(frame_type: FrameType) -> QuicError {
+    QuicError::new(
+        ErrorKind::ProtocolViolation,
+        frame_type,
+        "the reliable stream reset extension is not negotiated",
+    )
+}
+
+/// The received packet records of a path, in the packet number space of the local connection ID
+/// the peer sends packets with on the path.
+#[derive(Clone)]
+pub struct PathRcvdRecords {
+    multipath: ArcMultipath,
+    seqs: PathSeqs,
+}
+
+impl PathRcvdRecords {
+    pub fn new(multipath: ArcMultipath, seqs: PathSeqs) -> Self {
+        Self { multipath, seqs }
+    }
+}
+
+impl RetirePktRecord for PathRcvdRecords {
     fn retire(&self, pn: u64) {
-        self.space.rcvd_packets().write().retire(pn);
+        if let Some(seq) = self.seqs.rcvd() {
+            self.multipath.space(seq).rcvd_packets().write().retire(pn);
+        }
     }
 }
 
@@ -405,6 +703,7 @@ pub struct DataMayLoss {
     space: DataSpace,
     reliable_frames: ArcReliableFrameDeque,
     data_streams: DataStreams,
+    datagrams: DatagramFlow,
     outgoing: CryptoStreamOutgoing,
 }
 
@@ -413,24 +712,42 @@ impl DataMayLoss {
         space: DataSpace,
         reliable_frames: ArcReliableFrameDeque,
         data_streams: DataStreams,
+        datagrams: DatagramFlow,
         outgoing: CryptoStreamOutgoing,
     ) -> Self {
         Self {
             space,
             reliable_frames,
             data_streams,
+            datagrams,
             outgoing,
         }
     }
 }
+impl DataMayLoss {
+    /// Treat all the packets in flight as lost, which is called when the path sending them has
+    /// been abandoned.
+    pub fn may_loss_all(&self) {
+        for frame in self.space.sent_packets().recv().may_loss_all() {
+            self.on_frame_lost(frame);
+        }
+    }
+
+    fn on_frame_lost(&self, frame: GuaranteedFrame) {
+        match frame {
+            GuaranteedFrame::Stream(f) => self.data_streams.may_loss_data(&f),
+            GuaranteedFrame::Reliable(f) => self.reliable_frames.send_frame([f]),
+            GuaranteedFrame::Crypto(f) => self.outgoing.may_loss_data(&f),
+            // 数据报不重传，仅通知应用
+            GuaranteedFrame::Datagram(f) => self.datagrams.on_datagram_lost(f.id),
+        }
+    }
+}
+
 impl MayLoss for DataMayLoss {
     fn may_loss(&self, pn: u64) {
         for frame in self.space.sent_packets().recv().may_loss_pkt(pn) {
-            match frame {
-                GuaranteedFrame::Stream(f) => self.data_streams.may_loss_data(&f),
-                GuaranteedFrame::Reliable(f) => self.reliable_frames.send_frame([f]),
-                GuaranteedFrame::Crypto(f) => self.outgoing.may_loss_data(&f),
-            }
+            self.on_frame_lost(frame);
         }
     }
 }
@@ -439,8 +756,55 @@ impl MayLoss for DataMayLoss {
 pub struct ClosingOneRttScope {
     keys: (HeaderProtectionKeys, ArcOneRttPacketKeys),
     rcvd_pkt_records: ArcRcvdPktRecords,
-    // 发包时用得着
-    _next_sending_pn: (u64, PacketNumber),
+    // 组装CCF包时用得着
+    next_sending_pn: (u64, PacketNumber),
+}
+
+impl ClosingOneRttScope {
+    /// Assemble a 1-RTT packet carrying the CONNECTION_CLOSE frame `ccf` into `buf`, return the
+    /// size of the packet, or [`None`] if the `buf` is not large enough.
+    pub fn assemble_ccf_packet(
+        &self,
+        buf: &mut [u8],
+        dcid: ConnectionId,
+        ccf: &ConnectionCloseFrame,
+    ) -> Option<usize> {
+        let hdr = OneRttHeader {
+            spin: SpinBit::default(),
+            dcid,
+        };
+        let (pn, encoded_pn) = self.next_sending_pn;
+        let hdr_len = hdr.size();
+        let pn_len = encoded_pn.size();
+        let mut pk_guard = self.keys.1.lock_guard();
+        let (key_phase, pk) = pk_guard.get_local(pn);
+        let tag_len = pk.tag_len();
+        let body_len = ccf.encoding_size();
+        // payload(pn + body)长度不足20字节，填充之
+        let padding_len = 20usize.saturating_sub(pn_len + body_len + tag_len);
+        let pkt_size = hdr_len + pn_len + body_len + padding_len + tag_len;
+        if buf.len() < pkt_size {
+            return None;
+        }
+
+        let (mut hdr_buf, payload_tag) = buf.split_at_mut(hdr_len);
+        let (mut pn_buf, mut body_buf) = payload_tag.split_at_mut(pn_len);
+        hdr_buf.put_short_header(&hdr);
+        pn_buf.put_packet_number(encoded_pn);
+        body_buf.put_frame(ccf);
+        body_buf.put_bytes(0, padding_len);
+
+        encode_short_first_byte(&mut buf[0], pn_len, key_phase);
+        // 序号为0的连接ID的空间，使用RFC 9001的nonce
+        encrypt_packet(pk.as_ref(), pn, &mut buf[..pkt_size], hdr_len + pn_len);
+        protect_header(
+            self.keys.0.local.as_ref(),
+            &mut buf[..pkt_size],
+            hdr_len,
+            pn_len,
+        );
+        Some(pkt_size)
+    }
 }
 
 impl TryFrom<DataScope> for ClosingOneRttScope {
@@ -456,7 +820,7 @@ impl TryFrom<DataScope> for ClosingOneRttScope {
         Ok(Self {
             keys,
             rcvd_pkt_records,
-            _next_sending_pn: next_sending_pn,
+            next_sending_pn,
         })
     }
 }
@@ -1,24 +1,30 @@
 use std::sync::Arc;
 
+use bytes::BufMut;
 use futures::{channel::mpsc, StreamExt};
 use qbase::{
-    frame::{AckFrame, Frame, FrameReader, ReceiveFrame},
+    cid::ConnectionId,
+    frame::{
+        io::WriteFrame, AckFrame, BeFrame, ConnectionCloseFrame, Frame, FrameReader, ReceiveFrame,
+    },
     packet::{
         decrypt::{decrypt_packet, remove_protection_of_long_packet},
-        header::GetType,
+        encrypt::{encode_long_first_byte, encrypt_packet, protect_header},
+        header::{GetType, WriteLongHeader},
         keys::ArcKeys,
-        DataPacket, PacketNumber,
+        DataPacket, EncodeHeader, LongHeaderBuilder, PacketNumber, WritePacketNumber,
     },
+    varint::{EncodeBytes, VarInt, WriteVarInt},
 };
 use qcongestion::{CongestionControl, MayLoss, RetirePktRecord};
 use qrecovery::{
-    crypto::{CryptoStream, CryptoStreamOutgoing},
+    crypto::{CryptoStream, CryptoStreamOutgoing, DEFAULT_CRYPTO_BUFFER_SIZE},
     reliable::ArcRcvdPktRecords,
     space::{Epoch, HandshakeSpace},
 };
 use tokio::{sync::Notify, task::JoinHandle};
 
-use super::any;
+use super::{ack_unsent_packet, any, RcvdPacketFilter};
 use crate::{
     connection::{transmit::handshake::HandshakeSpaceReader, RcvdPackets},
     error::ConnError,
@@ -38,7 +44,7 @@ impl Default for HandshakeScope {
         Self {
             keys: ArcKeys::new_pending(),
             space: HandshakeSpace::with_capacity(16),
-            crypto_stream: CryptoStream::new(4096, 4096),
+            crypto_stream: CryptoStream::new(4096, DEFAULT_CRYPTO_BUFFER_SIZE),
         }
     }
 }
@@ -50,13 +56,18 @@ impl HandshakeScope {
         pathes: &ArcPathes,
         notify: &Arc<Notify>,
         conn_error: &ConnError,
+        rcvd_filter: RcvdPacketFilter,
     ) -> JoinHandle<RcvdPackets> {
         let (crypto_frames_entry, rcvd_crypto_frames) = mpsc::unbounded();
         let (ack_frames_entry, rcvd_ack_frames) = mpsc::unbounded();
 
         let dispatch_frame = {
             let conn_error = conn_error.clone();
+            let sent_pkt_records = self.space.sent_packets();
             move |frame: Frame, path: &RawPath| match frame {
+                Frame::Ack(f) if sent_pkt_records.recv().acks_unsent(&f) => {
+                    conn_error.on_error(ack_unsent_packet(f.frame_type()))
+                }
                 Frame::Ack(f) => {
                     path.cc.on_ack(Epoch::Initial, &f);
                     _ = ack_frames_entry.unbounded_send(f);
@@ -90,6 +101,7 @@ impl HandshakeScope {
             dispatch_frame,
             notify,
             conn_error,
+            rcvd_filter,
         )
     }
 
@@ -100,6 +112,7 @@ impl HandshakeScope {
         dispatch_frame: impl Fn(Frame, &RawPath) + Send + 'static,
         notify: &Arc<Notify>,
         conn_error: &ConnError,
+        rcvd_filter: RcvdPacketFilter,
     ) -> JoinHandle<RcvdPackets> {
         let pathes = pathes.clone();
         let conn_error = conn_error.clone();
@@ -108,8 +121,13 @@ impl HandshakeScope {
             let rcvd_pkt_records = self.space.rcvd_packets();
             let keys = self.keys.clone();
             async move {
-                while let Some((mut packet, pathway, usc)) = any(rcvd_packets.next(), &notify).await
+                while let Some((mut packet, pathway, ecn, _usc)) =
+                    any(rcvd_packets.next(), &notify).await
                 {
+                    let rcvd_bytes = packet.bytes.len();
+                    let Some(counted) = rcvd_filter.on_rcvd(&packet, &pathes, &pathway) else {
+                        continue;
+                    };
                     let pty = packet.header.get_type();
                     let Some(keys) = any(keys.get_remote_keys(), &notify).await else {
                         break;
@@ -133,16 +151,29 @@ impl HandshakeScope {
                         Err(_e) => continue,
                     };
                     let body_offset = packet.offset + undecoded_pn.size();
-                    let pkt_len = decrypt_packet(
+                    let pkt_len = match decrypt_packet(
                         keys.remote.packet.as_ref(),
                         pn,
                         packet.bytes.as_mut(),
                         body_offset,
-                    )
-                    .unwrap();
+                    ) {
+                        Ok(pkt_len) => pkt_len,
+                        Err(_) => match rcvd_filter
+                            .on_decrypt_failed(keys.remote.packet.integrity_limit())
+                        {
+                            Ok(()) => continue,
+                            Err(e) => {
+                                conn_error.on_error(e);
+                                break;
+                            }
+                        },
+                    };
 
-                    let path = pathes.get_or_create(pathway, usc);
-                    path.on_rcvd(packet.bytes.len());
+                    // 握手确认之前不允许迁移，来自未知路径的数据包直接丢弃
+                    let Some(path) = pathes.get(&pathway).map(|path| path.clone()) else {
+                        continue;
+                    };
+                    path.on_rcvd(if counted { 0 } else { rcvd_bytes });
 
                     let _header = packet.bytes.split_to(body_offset);
                     packet.bytes.truncate(pkt_len);
@@ -162,8 +193,9 @@ impl HandshakeScope {
                         },
                     ) {
                         Ok(is_ack_packet) => {
-                            rcvd_pkt_records.register_pn(pn);
-                            path.cc.on_pkt_rcvd(Epoch::Handshake, pn, is_ack_packet);
+                            rcvd_pkt_records.register_pn(pn, ecn);
+                            path.cc
+                                .on_pkt_rcvd(Epoch::Handshake, pn, is_ack_packet, ecn);
                         }
                         Err(e) => conn_error.on_error(e),
                     }
@@ -173,8 +205,9 @@ impl HandshakeScope {
         })
     }
 
-    pub fn reader(&self) -> HandshakeSpaceReader {
+    pub fn reader(&self, version: u32) -> HandshakeSpaceReader {
         HandshakeSpaceReader {
+            version,
             keys: self.keys.clone(),
             space: self.space.clone(),
             crypto_stream_outgoing: self.crypto_stream.outgoing(),
@@ -186,8 +219,63 @@ impl HandshakeScope {
 pub struct ClosingHandshakeScope {
     keys: Arc<rustls::quic::Keys>,
     rcvd_pkt_records: ArcRcvdPktRecords,
-    // 发包时用得着
-    _next_sending_pn: (u64, PacketNumber),
+    // 组装CCF包时用得着
+    next_sending_pn: (u64, PacketNumber),
+}
+
+impl ClosingHandshakeScope {
+    /// Assemble a Handshake packet carrying the CONNECTION_CLOSE frame `ccf` into `buf`, return the
+    /// size of the packet, or [`None`] if the `buf` is not large enough.
+    pub fn assemble_ccf_packet(
+        &self,
+        buf: &mut [u8],
+        version: u32,
+        scid: ConnectionId,
+        dcid: ConnectionId,
+        ccf: &ConnectionCloseFrame,
+    ) -> Option<usize> {
+        let hdr = LongHeaderBuilder::with_cid(dcid, scid)
+            .version(version)
+            .handshake();
+        let (pn, encoded_pn) = self.next_sending_pn;
+        // length字段固定2字节
+        let hdr_len = hdr.size() + 2;
+        let pn_len = encoded_pn.size();
+        let tag_len = self.keys.local.packet.tag_len();
+        let body_len = ccf.encoding_size();
+        // payload(pn + body)长度不足20字节，填充之
+        let padding_len = 20usize.saturating_sub(pn_len + body_len + tag_len);
+        let pkt_size = hdr_len + pn_len + body_len + padding_len + tag_len;
+        if buf.len() < pkt_size {
+            return None;
+        }
+
+        let (mut hdr_buf, payload_tag) = buf.split_at_mut(hdr_len);
+        let (mut pn_buf, mut body_buf) = payload_tag.split_at_mut(pn_len);
+        hdr_buf.put_long_header(&hdr);
+        hdr_buf.encode_varint(
+            &VarInt::try_from(pkt_size - hdr_len).unwrap(),
+            EncodeBytes::Two,
+        );
+        pn_buf.put_packet_number(encoded_pn);
+        body_buf.put_frame(ccf);
+        body_buf.put_bytes(0, padding_len);
+
+        encode_long_first_byte(&mut buf[0], pn_len);
+        encrypt_packet(
+            self.keys.local.packet.as_ref(),
+            pn,
+            &mut buf[..pkt_size],
+            hdr_len + pn_len,
+        );
+        protect_header(
+            self.keys.local.header.as_ref(),
+            &mut buf[..pkt_size],
+            hdr_len,
+            pn_len,
+        );
+        Some(pkt_size)
+    }
 }
 
 impl TryFrom<HandshakeScope> for ClosingHandshakeScope {
@@ -203,7 +291,7 @@ impl TryFrom<HandshakeScope> for ClosingHandshakeScope {
         Ok(Self {
             keys,
             rcvd_pkt_records,
-            _next_sending_pn: next_sending_pn,
+            next_sending_pn,
         })
     }
 }
@@ -1,23 +1,36 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
 
+use bytes::BufMut;
 use futures::{channel::mpsc, StreamExt};
 use qbase::{
-    frame::{AckFrame, Frame, FrameReader, ReceiveFrame},
+    cid::ConnectionId,
+    frame::{
+        io::WriteFrame, AckFrame, BeFrame, ConnectionCloseFrame, Frame, FrameReader, ReceiveFrame,
+    },
     packet::{
         decrypt::{decrypt_packet, remove_protection_of_long_packet},
-        header::{GetScid, GetType},
+        encrypt::{encode_long_first_byte, encrypt_packet, protect_header},
+        header::{GetScid, GetType, WriteLongHeader},
         keys::ArcKeys,
-        long, DataHeader,
+        long,
+        r#type::{long::GetVersion, Type},
+        DataHeader, EncodeHeader, LongHeaderBuilder, PacketNumber, WritePacketNumber,
     },
+    streamid::Role,
+    varint::{EncodeBytes, VarInt, WriteVarInt},
 };
 use qcongestion::{CongestionControl, MayLoss, RetirePktRecord};
 use qrecovery::{
-    crypto::{CryptoStream, CryptoStreamOutgoing},
+    crypto::{CryptoStream, CryptoStreamOutgoing, DEFAULT_CRYPTO_BUFFER_SIZE},
     space::{Epoch, InitialSpace},
 };
+use rustls::quic::Keys;
 use tokio::{sync::Notify, task::JoinHandle};
 
-use super::any;
+use super::{ack_unsent_packet, any, RcvdPacketFilter};
 use crate::{
     connection::{transmit::initial::InitialSpaceReader, ArcRemoteCids, RcvdPackets},
     error::ConnError,
@@ -25,26 +38,42 @@ use crate::{
     pipe,
 };
 
+/// The version and the initial keys of the client's first flight, if the server upgraded the
+/// connection to another version.
+type OriginalKeys = Arc<Mutex<Option<(u32, Arc<Keys>)>>>;
+
 #[derive(Clone)]
 pub struct InitialScope {
+    pub version: u32,
     pub keys: ArcKeys,
+    pub original_keys: OriginalKeys,
     pub space: InitialSpace,
     pub crypto_stream: CryptoStream,
 }
 
 impl InitialScope {
     // Initial keys应该是预先知道的，或者传入dcid，可以构造出来
-    pub fn new(keys: ArcKeys) -> Self {
+    pub fn new(version: u32, keys: ArcKeys) -> Self {
         let space = InitialSpace::with_capacity(16);
-        let crypto_stream = CryptoStream::new(4096, 4096);
+        let crypto_stream = CryptoStream::new(4096, DEFAULT_CRYPTO_BUFFER_SIZE);
 
         Self {
+            version,
             keys,
+            original_keys: Default::default(),
             space,
             crypto_stream,
         }
     }
 
+    /// Also accept the Initial packets in the `original` version protected by the `keys`, which
+    /// the client keeps sending until it receives the server's Initial packets in the upgraded
+    /// version.
+    pub fn accept_original_version(&self, original: u32, keys: Keys) {
+        *self.original_keys.lock().unwrap() = Some((original, Arc::new(keys)));
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn build(
         &self,
         rcvd_packets: RcvdPackets,
@@ -52,13 +81,19 @@ impl InitialScope {
         remote_cids: &ArcRemoteCids,
         notify: &Arc<Notify>,
         conn_error: &ConnError,
-        validate: impl Fn(&[u8], ArcPath) + Send + 'static,
+        validate: impl Fn(&[u8], SocketAddr, ArcPath) + Send + 'static,
+        rcvd_filter: RcvdPacketFilter,
     ) -> JoinHandle<RcvdPackets> {
         let (crypto_frames_entry, rcvd_crypto_frames) = mpsc::unbounded();
         let (ack_frames_entry, rcvd_ack_frames) = mpsc::unbounded();
 
-        let dispatch_frame = move |frame: Frame, path: &RawPath| {
-            match frame {
+        let dispatch_frame = {
+            let conn_error = conn_error.clone();
+            let sent_pkt_records = self.space.sent_packets();
+            move |frame: Frame, path: &RawPath| match frame {
+                Frame::Ack(f) if sent_pkt_records.recv().acks_unsent(&f) => {
+                    conn_error.on_error(ack_unsent_packet(f.frame_type()))
+                }
                 Frame::Ack(f) => {
                     path.cc.on_ack(Epoch::Initial, &f);
                     _ = ack_frames_entry.unbounded_send(f)
@@ -95,6 +130,7 @@ impl InitialScope {
             notify,
             conn_error,
             validate,
+            rcvd_filter,
         )
     }
 
@@ -107,23 +143,41 @@ impl InitialScope {
         dispatch_frame: impl Fn(Frame, &RawPath) + Send + 'static,
         notify: &Arc<Notify>,
         conn_error: &ConnError,
-        validate: impl Fn(&[u8], ArcPath) + Send + 'static,
+        validate: impl Fn(&[u8], SocketAddr, ArcPath) + Send + 'static,
+        rcvd_filter: RcvdPacketFilter,
     ) -> JoinHandle<RcvdPackets> {
         let pathes = pathes.clone();
         let conn_error = conn_error.clone();
         tokio::spawn({
             let rcvd_pkt_records = self.space.rcvd_packets();
+            let version = self.version;
             let keys = self.keys.clone();
+            let original_keys = self.original_keys.clone();
             let remote_cids = remote_cids.clone();
             let notify = notify.clone();
 
             async move {
-                while let Some((mut packet, pathway, usc)) = any(rcvd_packets.next(), &notify).await
+                while let Some((mut packet, pathway, ecn, _usc)) =
+                    any(rcvd_packets.next(), &notify).await
                 {
+                    let rcvd_bytes = packet.bytes.len();
+                    let Some(counted) = rcvd_filter.on_rcvd(&packet, &pathes, &pathway) else {
+                        continue;
+                    };
                     let pty = packet.header.get_type();
                     let Some(keys) = any(keys.get_remote_keys(), &notify).await else {
                         break;
                     };
+                    // The Initial packets in other versions are unexpected, except the ones in the
+                    // original version after the server upgraded the connection
+                    let keys = match pty {
+                        Type::Long(ty) if ty.get_version() == version => keys,
+                        Type::Long(ty) => match original_keys.lock().unwrap().as_ref() {
+                            Some((original, keys)) if *original == ty.get_version() => keys.clone(),
+                            _ => continue,
+                        },
+                        Type::Short(_) => unreachable!(),
+                    };
                     let undecoded_pn = match remove_protection_of_long_packet(
                         keys.remote.header.as_ref(),
                         packet.bytes.as_mut(),
@@ -143,16 +197,29 @@ impl InitialScope {
                         Err(_e) => continue,
                     };
                     let body_offset = packet.offset + undecoded_pn.size();
-                    let pkt_len = decrypt_packet(
+                    let pkt_len = match decrypt_packet(
                         keys.remote.packet.as_ref(),
                         pn,
                         packet.bytes.as_mut(),
                         body_offset,
-                    )
-                    .unwrap();
+                    ) {
+                        Ok(pkt_len) => pkt_len,
+                        Err(_) => match rcvd_filter
+                            .on_decrypt_failed(keys.remote.packet.integrity_limit())
+                        {
+                            Ok(()) => continue,
+                            Err(e) => {
+                                conn_error.on_error(e);
+                                break;
+                            }
+                        },
+                    };
 
-                    let path = pathes.get_or_create(pathway, usc);
-                    path.on_rcvd(packet.bytes.len());
+                    // 握手确认之前不允许迁移，来自未知路径的数据包直接丢弃
+                    let Some(path) = pathes.get(&pathway).map(|path| path.clone()) else {
+                        continue;
+                    };
+                    path.on_rcvd(if counted { 0 } else { rcvd_bytes });
 
                     let _header = packet.bytes.split_to(body_offset);
                     packet.bytes.truncate(pkt_len);
@@ -174,8 +241,8 @@ impl InitialScope {
                         },
                     ) {
                         Ok(is_ack_packet) => {
-                            rcvd_pkt_records.register_pn(pn);
-                            path.cc.on_pkt_rcvd(Epoch::Initial, pn, is_ack_packet);
+                            rcvd_pkt_records.register_pn(pn, ecn);
+                            path.cc.on_pkt_rcvd(Epoch::Initial, pn, is_ack_packet, ecn);
                         }
                         Err(e) => {
                             conn_error.on_error(e);
@@ -189,7 +256,7 @@ impl InitialScope {
                     // or in a previous connection using the NEW_TOKEN frame (see Section 8.1.3).
                     if let DataHeader::Long(long::DataHeader::Initial(initial)) = &packet.header {
                         if !initial.token.is_empty() {
-                            validate(&initial.token, path);
+                            validate(&initial.token, pathway.remote_addr(), path);
                         }
                     }
                 }
@@ -198,8 +265,15 @@ impl InitialScope {
         })
     }
 
-    pub fn reader(&self, token: Arc<Mutex<Vec<u8>>>) -> InitialSpaceReader {
+    pub fn reader(
+        &self,
+        version: u32,
+        token: Arc<Mutex<Vec<u8>>>,
+        role: Role,
+    ) -> InitialSpaceReader {
         InitialSpaceReader {
+            version,
+            role,
             token,
             keys: self.keys.clone(),
             space: self.space.clone(),
@@ -228,6 +302,62 @@ impl MayLoss for InitialMayLoss {
     }
 }
 
+/// Assemble an Initial packet carrying only the `ccf` into `buf`, protected by the initial `keys`,
+/// and return its size, or [`None`] if `buf` is too small.
+///
+/// It's for the server to close a connection before creating it, e.g. to reject an invalid Retry
+/// token with INVALID_TOKEN, so the packet number is always 0 and the token is empty.
+pub fn assemble_initial_ccf_packet(
+    buf: &mut [u8],
+    keys: &Keys,
+    version: u32,
+    scid: ConnectionId,
+    dcid: ConnectionId,
+    ccf: &ConnectionCloseFrame,
+) -> Option<usize> {
+    let hdr = LongHeaderBuilder::with_cid(dcid, scid)
+        .version(version)
+        .initial(Vec::new());
+    let (pn, encoded_pn) = (0, PacketNumber::encode(0, 0));
+    // length字段固定2字节
+    let hdr_len = hdr.size() + 2;
+    let pn_len = encoded_pn.size();
+    let tag_len = keys.local.packet.tag_len();
+    let body_len = ccf.encoding_size();
+    // payload(pn + body)长度不足20字节，填充之
+    let padding_len = 20usize.saturating_sub(pn_len + body_len + tag_len);
+    let pkt_size = hdr_len + pn_len + body_len + padding_len + tag_len;
+    if buf.len() < pkt_size {
+        return None;
+    }
+
+    let (mut hdr_buf, payload_tag) = buf.split_at_mut(hdr_len);
+    let (mut pn_buf, mut body_buf) = payload_tag.split_at_mut(pn_len);
+    hdr_buf.put_long_header(&hdr);
+    hdr_buf.encode_varint(
+        &VarInt::try_from(pkt_size - hdr_len).unwrap(),
+        EncodeBytes::Two,
+    );
+    pn_buf.put_packet_number(encoded_pn);
+    body_buf.put_frame(ccf);
+    body_buf.put_bytes(0, padding_len);
+
+    encode_long_first_byte(&mut buf[0], pn_len);
+    encrypt_packet(
+        keys.local.packet.as_ref(),
+        pn,
+        &mut buf[..pkt_size],
+        hdr_len + pn_len,
+    );
+    protect_header(
+        keys.local.header.as_ref(),
+        &mut buf[..pkt_size],
+        hdr_len,
+        pn_len,
+    );
+    Some(pkt_size)
+}
+
 impl RetirePktRecord for InitialScope {
     fn retire(&self, pn: u64) {
         self.space.rcvd_packets().write().retire(pn);
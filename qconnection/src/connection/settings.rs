@@ -0,0 +1,37 @@
+use qbase::streamid::MaxStreamsPolicy;
+use qcongestion::{AckPolicy, CongestionAlgorithm, CongestionWindowConfig, LossDetectionConfig};
+use qrecovery::send::{DropPolicy, SendBufferLimits};
+use qunreliable::{DatagramPriority, DatagramQueueLimits};
+
+/// The local settings of a connection beyond the transport parameters, which are not negotiated
+/// with the peer.
+///
+/// The settings are given when the connection is created, and can be changed later by the setters
+/// of [`ArcConnection`]. The client keeps the current ones as a whole if the handshake is restarted
+/// in another version.
+///
+/// [`ArcConnection`]: super::ArcConnection
+#[derive(Debug, Default, Clone)]
+pub struct ConnectionSettings {
+    /// The strategy to acknowledge the packets received on all the paths, see [`AckPolicy`].
+    pub ack_policy: AckPolicy,
+    /// The thresholds of the loss detection and the backoff of the PTO on all the paths, see
+    /// [`LossDetectionConfig`].
+    pub loss_detection: LossDetectionConfig,
+    /// The congestion control algorithm of each path, see [`CongestionAlgorithm`].
+    pub congestion_algorithm: CongestionAlgorithm,
+    /// The initial and the minimum congestion window of each path, see [`CongestionWindowConfig`].
+    pub congestion_window: CongestionWindowConfig,
+    /// The policy to replenish the streams the peer can create, see [`MaxStreamsPolicy`].
+    pub max_streams_policy: MaxStreamsPolicy,
+    /// The bounds of the data buffered to send by the streams, see [`SendBufferLimits`].
+    pub send_buffer_limits: SendBufferLimits,
+    /// The default [`DropPolicy`] of the streams opened or accepted.
+    pub drop_policy: DropPolicy,
+    /// The bounds of the datagrams received but not read yet, see [`DatagramQueueLimits`].
+    pub datagram_queue_limits: DatagramQueueLimits,
+    /// How the datagrams are scheduled against the stream data, see [`DatagramPriority`].
+    pub datagram_priority: DatagramPriority,
+    /// The maximum rate to send the packets in bytes per second, [`None`] for no limit.
+    pub max_send_rate: Option<u64>,
+}
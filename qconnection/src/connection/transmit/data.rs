@@ -1,34 +1,93 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 use bytes::BufMut;
 use qbase::{
     cid::ConnectionId,
-    frame::{PathChallengeFrame, PathResponseFrame},
+    frame::{
+        io::{WriteDataFrame, WriteFrame},
+        PathChallengeFrame, PathResponseFrame, PingFrame,
+    },
     packet::{
         encrypt::{
-            encode_long_first_byte, encode_short_first_byte, encrypt_packet, protect_header,
+            encode_long_first_byte, encode_short_first_byte, encrypt_packet,
+            encrypt_packet_for_path, grease_quic_bit, protect_header,
         },
         header::{WriteLongHeader, WriteShortHeader},
         keys::{ArcKeys, ArcOneRttKeys, ArcOneRttPacketKeys},
-        EncodeHeader, LongHeaderBuilder, OneRttHeader, SpinBit, WritePacketNumber,
+        EncodeHeader, KeyPhaseBit, LongHeaderBuilder, OneRttHeader, SpinBit, WritePacketNumber,
     },
     varint::{EncodeBytes, VarInt, WriteVarInt},
 };
 use qrecovery::{
     crypto::CryptoStreamOutgoing,
-    reliable::{ArcReliableFrameDeque, GuaranteedFrame},
+    reliable::{ArcReliableFrameDeque, GuaranteedFrame, TrackedDatagram},
     space::DataSpace,
 };
-use qunreliable::DatagramFlow;
-use rustls::quic::HeaderProtectionKey;
+use qunreliable::{DatagramFlow, DatagramPriority};
+use rustls::quic::{HeaderProtectionKey, PacketKey};
+
+use crate::{
+    connection::{
+        multipath::{ArcMultipath, PathSeqs},
+        DataStreams,
+    },
+    path::SendBuffer,
+};
+
+/// Decides whether a datagram or a stream frame is written next into the packet.
+struct Schedule {
+    priority: DatagramPriority,
+    // 交替发送时，下一个是否轮到datagram
+    datagram_turn: bool,
+    streams_done: bool,
+    datagrams_done: bool,
+}
 
-use crate::{connection::DataStreams, path::SendBuffer};
+impl Schedule {
+    fn new(priority: DatagramPriority) -> Self {
+        Self {
+            priority,
+            datagram_turn: false,
+            streams_done: false,
+            datagrams_done: false,
+        }
+    }
+
+    /// Returns whether to write a datagram next, or [`None`] if neither can be written anymore.
+    fn next(&mut self) -> Option<bool> {
+        let pick_datagram = match (self.streams_done, self.datagrams_done) {
+            (true, true) => return None,
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => match self.priority {
+                DatagramPriority::AfterStreams => false,
+                DatagramPriority::BeforeStreams => true,
+                DatagramPriority::Interleaved => self.datagram_turn,
+            },
+        };
+        self.datagram_turn = !pick_datagram;
+        Some(pick_datagram)
+    }
+}
 
 #[derive(Clone)]
 pub struct DataSpaceReader {
+    pub(crate) version: u32,
+    // 路径发送数据包所用的空间，启用多路径后，由路径的目标连接ID序号决定
     pub(crate) space: DataSpace,
+    pub(crate) multipath: ArcMultipath,
+    pub(crate) seqs: PathSeqs,
+    pub(crate) path_id: Option<u32>,
     pub(crate) zero_rtt_keys: ArcKeys,
     pub(crate) one_rtt_keys: ArcOneRttKeys,
+    // 对端通告了grease_quic_bit传输参数后，随机清除1RTT包的QUIC Bit
+    pub(crate) grease_quic_bit: Arc<AtomicBool>,
     // 数据源
     pub(crate) challenge_sndbuf: SendBuffer<PathChallengeFrame>,
     pub(crate) response_sndbuf: SendBuffer<PathResponseFrame>,
@@ -40,12 +99,33 @@ pub struct DataSpaceReader {
 }
 
 impl DataSpaceReader {
+    /// The 1-RTT keys to encrypt the packets, [`None`] if they are not ready, or the local key has
+    /// been used up to the confidentiality limit.
     pub fn one_rtt_keys(&self) -> Option<(Arc<dyn HeaderProtectionKey>, ArcOneRttPacketKeys)> {
-        self.one_rtt_keys.get_local_keys()
+        self.one_rtt_keys
+            .get_local_keys()
+            .filter(|(_, pk)| !pk.lock_guard().is_exhausted())
+    }
+
+    /// Encrypt the 1-RTT packet, with the nonce of the path if it is sent in a packet number space
+    /// of the multipath extension.
+    fn encrypt_packet(&self, pk: &dyn PacketKey, pn: u64, pkt_buf: &mut [u8], body_offset: usize) {
+        match self.path_id {
+            Some(path_id) => encrypt_packet_for_path(pk, path_id, pn, pkt_buf, body_offset),
+            None => encrypt_packet(pk, pn, pkt_buf, body_offset),
+        }
+    }
+
+    /// Encode the first byte of the 1-RTT packet, the QUIC Bit may be greased.
+    fn encode_short_first_byte(&self, first_byte: &mut u8, pn_len: usize, key_phase: KeyPhaseBit) {
+        encode_short_first_byte(first_byte, pn_len, key_phase);
+        if self.grease_quic_bit.load(Ordering::Acquire) {
+            grease_quic_bit(first_byte);
+        }
     }
 
     /// Returns (pn, is_ack_eliciting, is_just_ack, sent_size, fresh_bytes, in_flight, sent_ack) or None
-    #[allow(clippy::type_complexity)]
+    #[allow(clippy::type_complexity, clippy::too_many_arguments)]
     pub fn try_read_1rtt(
         &self,
         buf: &mut [u8],
@@ -53,6 +133,7 @@ impl DataSpaceReader {
         dcid: ConnectionId,
         spin: SpinBit,
         ack_pkt: Option<(u64, Instant)>,
+        probe: bool,
         (hpk, pk): (Arc<dyn HeaderProtectionKey>, ArcOneRttPacketKeys),
     ) -> Option<(u64, bool, bool, usize, usize, bool, Option<u64>)> {
         // 0. 检查1rtt keys是否有效，没有则回退到0rtt包
@@ -102,10 +183,19 @@ impl DataSpaceReader {
         // 4. 检查是否需要发送Ack，若是，且符合（constraints + buf）节制，生成ack并写入，但发送记录并不记录
         let mut sent_ack = None;
         if let Some((largest, recv_time)) = ack_pkt {
-            let rcvd_pkt_records = self.space.rcvd_packets();
-            let n = rcvd_pkt_records
-                .read_ack_frame_util(body_buf, largest, recv_time)
-                .unwrap();
+            // 启用多路径后，确认非0序号连接ID的空间中收到的数据包，要用ACK_MP帧
+            let rcvd_seq = self.seqs.rcvd().unwrap_or(0);
+            let rcvd_pkt_records = self.multipath.space(rcvd_seq).rcvd_packets();
+            let n = match self.multipath.path_id(rcvd_seq) {
+                Some(_) => rcvd_pkt_records.read_ack_mp_frame_util(
+                    body_buf,
+                    VarInt::from_u64(rcvd_seq).expect("sequence number never exceed VARINT_MAX"),
+                    largest,
+                    recv_time,
+                ),
+                None => rcvd_pkt_records.read_ack_frame_util(body_buf, largest, recv_time),
+            }
+            .unwrap();
             send_guard.record_trivial();
             sent_ack = Some(largest);
             body_buf = &mut body_buf[n..];
@@ -131,21 +221,58 @@ impl DataSpaceReader {
             in_flight = true;
         }
 
-        // 8. 检查DataStreams是否需要发送，若有，且符合（constraints + buf）节制，写入，burst、发包记录都记录
+        // 8. 多路径冗余调度时，先发送其他路径上发过的流数据副本，副本不占流量控制额度，丢了也不重传
+        let sent_seq = self.seqs.sent();
+        while let Some((frame, data)) = self
+            .multipath
+            .next_duplicate(sent_seq, body_buf.remaining_mut())
+        {
+            body_buf.put_data_frame(&frame, &data);
+            send_guard.record_trivial();
+            is_ack_eliciting = true;
+            is_just_ack = false;
+            in_flight = true;
+        }
+
+        // 9. 检查DataStreams和Datagrams是否需要发送，按照DatagramPriority决定二者的先后，若有，
+        //    且符合（constraints + buf）节制，写入，burst、发包记录都记录
         let mut fresh_bytes = 0;
-        while let Some((frame, n, m)) = self.streams.try_read_data(body_buf, flow_limit) {
-            send_guard.record_frame(GuaranteedFrame::Stream(frame));
-            flow_limit -= m;
-            fresh_bytes += m;
-            body_buf = &mut body_buf[n..];
+        let mut schedule = Schedule::new(self.datagrams.priority());
+        while let Some(pick_datagram) = schedule.next() {
+            if pick_datagram {
+                let Some((frame, n, id)) = self.datagrams.try_read_tracked_datagram(body_buf)
+                else {
+                    schedule.datagrams_done = true;
+                    continue;
+                };
+                // 跟踪的数据报随包记录，以便包被确认或丢失时通知应用
+                if let Some(id) = id {
+                    send_guard
+                        .record_frame(GuaranteedFrame::Datagram(TrackedDatagram { frame, id }));
+                }
+                body_buf = &mut body_buf[n..];
+            } else {
+                let Some((frame, n, m)) = self.streams.try_read_data(body_buf, flow_limit) else {
+                    schedule.streams_done = true;
+                    continue;
+                };
+                // 流数据位于所写帧的末尾
+                self.multipath
+                    .duplicate(sent_seq, &frame, &body_buf[n - frame.len()..n]);
+                send_guard.record_frame(GuaranteedFrame::Stream(frame));
+                flow_limit -= m;
+                fresh_bytes += m;
+                body_buf = &mut body_buf[n..];
+            }
             is_ack_eliciting = true;
             is_just_ack = false;
             in_flight = true;
         }
 
-        // 9. 检查Datagrams是否需要发送，若有，且符合(constraints + buf) 节制，写入，burst、发包记录都记录
-        while let Some((_frame, n)) = self.datagrams.try_read_datagram(body_buf) {
-            body_buf = &mut body_buf[n..];
+        // 10. PTO超时须发送可引起确认的探测包，若无数据可携带，则发送PING帧
+        if probe && !is_ack_eliciting && body_buf.remaining_mut() > 0 {
+            body_buf.put_frame(&PingFrame);
+            send_guard.record_trivial();
             is_ack_eliciting = true;
             is_just_ack = false;
             in_flight = true;
@@ -171,10 +298,10 @@ impl DataSpaceReader {
         pn_buf.put_packet_number(encoded_pn);
 
         // 11 保护包头，加密数据
-        let pk_guard = pk.lock_guard();
-        let (key_phase, pk) = pk_guard.get_local();
-        encode_short_first_byte(&mut buf[0], pn_len, key_phase);
-        encrypt_packet(pk.as_ref(), pn, &mut buf[..sent_size], hdr_len + pn_len);
+        let mut pk_guard = pk.lock_guard();
+        let (key_phase, pk) = pk_guard.get_local(pn);
+        self.encode_short_first_byte(&mut buf[0], pn_len, key_phase);
+        self.encrypt_packet(pk.as_ref(), pn, &mut buf[..sent_size], hdr_len + pn_len);
         protect_header(hpk.as_ref(), &mut buf[..sent_size], hdr_len, pn_len);
 
         Some((
@@ -188,6 +315,113 @@ impl DataSpaceReader {
         ))
     }
 
+    /// Read a PMTU probe packet filling the whole `buf`, which only contains a PING frame and the
+    /// PADDING frames, see [Section 14.4](https://www.rfc-editor.org/rfc/rfc9000.html#section-14.4)
+    /// of RFC 9000.
+    ///
+    /// Returns (pn, sent_size) or None
+    pub fn try_read_mtu_probe(
+        &self,
+        buf: &mut [u8],
+        dcid: ConnectionId,
+        spin: SpinBit,
+        (hpk, pk): (Arc<dyn HeaderProtectionKey>, ArcOneRttPacketKeys),
+    ) -> Option<(u64, usize)> {
+        let hdr = OneRttHeader { spin, dcid };
+        if buf.len() < hdr.size() + 20 {
+            return None;
+        }
+        let (mut hdr_buf, payload_tag) = buf.split_at_mut(hdr.size());
+        let payload_tag_len = payload_tag.len();
+        let tag_len = pk.tag_len();
+        let payload_buf = &mut payload_tag[..payload_tag_len - tag_len];
+
+        let sent_pkt_records = self.space.sent_packets();
+        let mut send_guard = sent_pkt_records.send();
+        let (pn, encoded_pn) = send_guard.next_pn();
+        if payload_buf.remaining_mut() <= encoded_pn.size() {
+            return None;
+        }
+        let (mut pn_buf, mut body_buf) = payload_buf.split_at_mut(encoded_pn.size());
+        // 探测包丢失了也无需重传，只需记录下pn
+        body_buf.put_frame(&PingFrame);
+        send_guard.record_trivial();
+        drop(send_guard);
+        body_buf.put_bytes(0, body_buf.remaining_mut());
+
+        let hdr_len = hdr_buf.len();
+        let pn_len = pn_buf.len();
+        hdr_buf.put_short_header(&hdr);
+        pn_buf.put_packet_number(encoded_pn);
+
+        let sent_size = buf.len();
+        let mut pk_guard = pk.lock_guard();
+        let (key_phase, pk) = pk_guard.get_local(pn);
+        self.encode_short_first_byte(&mut buf[0], pn_len, key_phase);
+        self.encrypt_packet(pk.as_ref(), pn, &mut buf[..sent_size], hdr_len + pn_len);
+        protect_header(hpk.as_ref(), &mut buf[..sent_size], hdr_len, pn_len);
+
+        Some((pn, sent_size))
+    }
+
+    /// Read a probing packet filling the whole `buf`, which only contains the PATH_CHALLENGE and
+    /// PATH_RESPONSE frames and the PADDING frames, sent on a path being validated.
+    ///
+    /// The datagram carrying a PATH_CHALLENGE or PATH_RESPONSE frame should be expanded to at least
+    /// 1200 bytes unless limited by the anti-amplification limit, see [Section 8.2.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-8.2.1)
+    /// of RFC 9000.
+    ///
+    /// Returns (pn, sent_size) or None
+    pub fn try_read_probing(
+        &self,
+        buf: &mut [u8],
+        dcid: ConnectionId,
+        spin: SpinBit,
+        (hpk, pk): (Arc<dyn HeaderProtectionKey>, ArcOneRttPacketKeys),
+    ) -> Option<(u64, usize)> {
+        let hdr = OneRttHeader { spin, dcid };
+        if buf.len() < hdr.size() + 20 {
+            return None;
+        }
+        let (mut hdr_buf, payload_tag) = buf.split_at_mut(hdr.size());
+        let payload_tag_len = payload_tag.len();
+        let tag_len = pk.tag_len();
+        let payload_buf = &mut payload_tag[..payload_tag_len - tag_len];
+
+        let sent_pkt_records = self.space.sent_packets();
+        let mut send_guard = sent_pkt_records.send();
+        let (pn, encoded_pn) = send_guard.next_pn();
+        if payload_buf.remaining_mut() <= encoded_pn.size() {
+            return None;
+        }
+        let (mut pn_buf, mut body_buf) = payload_buf.split_at_mut(encoded_pn.size());
+        // 路径帧丢失了也无需重传，路径验证任务会重发Challenge
+        let n = self.challenge_sndbuf.try_read(body_buf);
+        body_buf = &mut body_buf[n..];
+        let m = self.response_sndbuf.try_read(body_buf);
+        body_buf = &mut body_buf[m..];
+        if n + m == 0 {
+            return None;
+        }
+        send_guard.record_trivial();
+        drop(send_guard);
+        body_buf.put_bytes(0, body_buf.remaining_mut());
+
+        let hdr_len = hdr_buf.len();
+        let pn_len = pn_buf.len();
+        hdr_buf.put_short_header(&hdr);
+        pn_buf.put_packet_number(encoded_pn);
+
+        let sent_size = buf.len();
+        let mut pk_guard = pk.lock_guard();
+        let (key_phase, pk) = pk_guard.get_local(pn);
+        self.encode_short_first_byte(&mut buf[0], pn_len, key_phase);
+        self.encrypt_packet(pk.as_ref(), pn, &mut buf[..sent_size], hdr_len + pn_len);
+        protect_header(hpk.as_ref(), &mut buf[..sent_size], hdr_len, pn_len);
+
+        Some((pn, sent_size))
+    }
+
     /// Returns (pn, is_ack_eliciting, sent_size, fresh_bytes, in_flight) or None
     pub fn try_read_0rtt(
         &self,
@@ -200,7 +434,9 @@ impl DataSpaceReader {
         let k = self.zero_rtt_keys.get_local_keys()?;
 
         // 2. 生成包头，预留2字节len，根据包头大小，配合constraints、剩余空间，检查是否能发送，不能的话，直接返回
-        let hdr = LongHeaderBuilder::with_cid(dcid, scid).zero_rtt();
+        let hdr = LongHeaderBuilder::with_cid(dcid, scid)
+            .version(self.version)
+            .zero_rtt();
         // length字段预留2字节, 20字节为最小Payload长度，为了保护包头的Sample至少16字节
         if buf.len() < hdr.size() + 2 + 20 {
             return None;
@@ -242,27 +478,39 @@ impl DataSpaceReader {
             in_flight = true;
         }
 
-        // 6. 检查DataStreams是否需要发送，若有，且符合（constraints + buf）节制，写入，burst、发包记录都记录
-        // TODO: 要注意和Datagrams的公平了
+        // 6. 检查DataStreams和Datagrams是否需要发送，按照DatagramPriority决定二者的先后，若有，
+        //    且符合（constraints + buf）节制，写入，burst、发包记录都记录
         let mut fresh_bytes = 0;
-        while let Some((frame, n, m)) = self.streams.try_read_data(body_buf, flow_limit) {
-            send_guard.record_frame(GuaranteedFrame::Stream(frame));
-            body_buf = &mut body_buf[n..];
-            flow_limit -= m;
-            fresh_bytes += m;
-            is_ack_eliciting = true;
-            in_flight = true;
-        }
-
-        // 7. 检查Datagrams是否需要发送，若有，且符合(constraints + buf) 节制，写入，burst、发包记录都记录
-        while let Some((_frame, n)) = self.datagrams.try_read_datagram(body_buf) {
-            body_buf = &mut body_buf[n..];
+        let mut schedule = Schedule::new(self.datagrams.priority());
+        while let Some(pick_datagram) = schedule.next() {
+            if pick_datagram {
+                let Some((frame, n, id)) = self.datagrams.try_read_tracked_datagram(body_buf)
+                else {
+                    schedule.datagrams_done = true;
+                    continue;
+                };
+                // 跟踪的数据报随包记录，以便包被确认或丢失时通知应用
+                if let Some(id) = id {
+                    send_guard
+                        .record_frame(GuaranteedFrame::Datagram(TrackedDatagram { frame, id }));
+                }
+                body_buf = &mut body_buf[n..];
+            } else {
+                let Some((frame, n, m)) = self.streams.try_read_data(body_buf, flow_limit) else {
+                    schedule.streams_done = true;
+                    continue;
+                };
+                send_guard.record_frame(GuaranteedFrame::Stream(frame));
+                body_buf = &mut body_buf[n..];
+                flow_limit -= m;
+                fresh_bytes += m;
+            }
             is_ack_eliciting = true;
             in_flight = true;
         }
         drop(send_guard); // 持有这把锁的时间越短越好，毕竟下面的加密可能会有点耗时
 
-        // 8. 填充，保护头部，加密
+        // 7. 填充，保护头部，加密
         let hdr_len = hdr_buf.len();
         let pn_len = pn_buf.len();
         let mut body_len = body_size - body_buf.remaining_mut();
@@ -303,3 +551,39 @@ impl DataSpaceReader {
         Some((pn, is_ack_eliciting, sent_size, fresh_bytes, in_flight))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picks(priority: DatagramPriority, streams: usize, datagrams: usize) -> Vec<bool> {
+        let (mut streams_left, mut datagrams_left) = (streams, datagrams);
+        let mut schedule = Schedule::new(priority);
+        let mut picks = vec![];
+        while let Some(pick_datagram) = schedule.next() {
+            let left = match pick_datagram {
+                true => &mut datagrams_left,
+                false => &mut streams_left,
+            };
+            if *left == 0 {
+                match pick_datagram {
+                    true => schedule.datagrams_done = true,
+                    false => schedule.streams_done = true,
+                }
+                continue;
+            }
+            *left -= 1;
+            picks.push(pick_datagram);
+        }
+        picks
+    }
+
+    #[test]
+    fn test_schedule() {
+        use DatagramPriority::*;
+        assert_eq!(picks(AfterStreams, 2, 1), [false, false, true]);
+        assert_eq!(picks(BeforeStreams, 2, 1), [true, false, false]);
+        assert_eq!(picks(Interleaved, 3, 2), [false, true, false, true, false]);
+        assert_eq!(picks(Interleaved, 1, 3), [false, true, true, true]);
+    }
+}
@@ -3,6 +3,7 @@ use std::time::Instant;
 use bytes::BufMut;
 use qbase::{
     cid::ConnectionId,
+    frame::{io::WriteFrame, PingFrame},
     packet::{
         encrypt::{encode_long_first_byte, encrypt_packet, protect_header},
         header::WriteLongHeader,
@@ -15,6 +16,7 @@ use qrecovery::{crypto::CryptoStreamOutgoing, space::HandshakeSpace};
 
 #[derive(Clone)]
 pub struct HandshakeSpaceReader {
+    pub(crate) version: u32,
     pub(crate) keys: ArcKeys,
     pub(crate) space: HandshakeSpace,
     pub(crate) crypto_stream_outgoing: CryptoStreamOutgoing,
@@ -27,12 +29,15 @@ impl HandshakeSpaceReader {
         scid: ConnectionId,
         dcid: ConnectionId,
         ack_pkt: Option<(u64, Instant)>,
+        probe: bool,
     ) -> Option<(u64, bool, bool, usize, bool, Option<u64>)> {
         // 1. 判定keys是否有效，无效或者尚未拿到，直接返回
         let k = self.keys.get_local_keys()?;
 
         // 2. 生成包头，预留2字节len，根据包头大小，配合constraints、剩余空间，检查是否能发送，不能的话，直接返回
-        let hdr = LongHeaderBuilder::with_cid(dcid, scid).handshake();
+        let hdr = LongHeaderBuilder::with_cid(dcid, scid)
+            .version(self.version)
+            .handshake();
         // length字段预留2字节, 20字节为最小Payload长度，为了保护包头的Sample至少16字节
         if buf.len() < hdr.size() + 2 + 20 {
             return None;
@@ -76,6 +81,15 @@ impl HandshakeSpaceReader {
             is_just_ack = false;
             in_flight = true;
         }
+
+        // 6. PTO超时须发送可引起确认的探测包，若无数据可携带，则发送PING帧
+        if probe && !is_ack_eliciting && body_buf.remaining_mut() > 0 {
+            body_buf.put_frame(&PingFrame);
+            send_guard.record_trivial();
+            is_just_ack = false;
+            is_ack_eliciting = true;
+            in_flight = true;
+        }
         drop(send_guard); // 持有这把锁的时间越短越好，毕竟下面的加密可能会有点耗时
 
         // 7. 填充，保护头部，加密
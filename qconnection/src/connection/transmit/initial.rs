@@ -6,22 +6,27 @@ use std::{
 use bytes::BufMut;
 use qbase::{
     cid::ConnectionId,
+    frame::{io::WriteFrame, PingFrame},
     packet::{
         encrypt::{encode_long_first_byte, encrypt_packet, protect_header},
         header::WriteLongHeader,
         keys::ArcKeys,
         EncodeHeader, LongHeaderBuilder, WritePacketNumber,
     },
+    streamid::Role,
     varint::{EncodeBytes, VarInt, WriteVarInt},
 };
 use qrecovery::{crypto::CryptoStreamOutgoing, space::InitialSpace};
 
 #[derive(Clone)]
 pub struct InitialSpaceReader {
+    pub(crate) version: u32,
     pub(crate) token: Arc<Mutex<Vec<u8>>>,
     pub(crate) keys: ArcKeys,
     pub(crate) space: InitialSpace,
     pub(crate) crypto_stream_outgoing: CryptoStreamOutgoing,
+    // 决定携带Initial包的数据报是否需要填充
+    pub(crate) role: Role,
 }
 
 impl InitialSpaceReader {
@@ -32,6 +37,7 @@ impl InitialSpaceReader {
         scid: ConnectionId,
         dcid: ConnectionId,
         ack_pkt: Option<(u64, Instant)>,
+        probe: bool,
     ) -> Option<(
         impl FnOnce(&mut [u8], usize) -> (u64, bool, bool, usize, bool, Option<u64>),
         usize,
@@ -42,7 +48,9 @@ impl InitialSpaceReader {
 
         // 2. 生成包头，预留2字节len，根据包头大小，配合constraints、剩余空间，检查是否能发送，不能的话，直接返回
         let token = self.token.lock().unwrap();
-        let hdr = LongHeaderBuilder::with_cid(dcid, scid).initial(token.clone());
+        let hdr = LongHeaderBuilder::with_cid(dcid, scid)
+            .version(self.version)
+            .initial(token.clone());
         // length字段预留2字节, 20字节为最小Payload长度，为了保护包头的Sample至少16字节
         if buf.len() < hdr.size() + 2 + 20 {
             return None;
@@ -86,6 +94,15 @@ impl InitialSpaceReader {
             is_ack_eliciting = true;
             in_flight = true;
         }
+
+        // 6. PTO超时须发送可引起确认的探测包，若无数据可携带，则发送PING帧
+        if probe && !is_ack_eliciting && body_buf.remaining_mut() > 0 {
+            body_buf.put_frame(&PingFrame);
+            send_guard.record_trivial();
+            is_just_ack = false;
+            is_ack_eliciting = true;
+            in_flight = true;
+        }
         drop(send_guard); // 持有这把锁的时间越短越好，毕竟下面的加密可能会有点耗时
 
         let hdr_len = hdr_buf.len();
@@ -101,7 +118,7 @@ impl InitialSpaceReader {
 
         Some((
             move |buf: &mut [u8], len: usize| -> (u64, bool, bool, usize, bool, Option<u64>) {
-                // 6. 填充，保护头部，加密
+                // 7. 填充，保护头部，加密
                 let (_hdr_buf, remain) = buf.split_at_mut(hdr_len - 2);
                 let (mut length_buf, remain) = remain.split_at_mut(2);
                 let (_pn_buf, remain) = remain.split_at_mut(pn_len);
@@ -0,0 +1,304 @@
+use qbase::{
+    config::VersionInformation,
+    error::{Error, ErrorKind},
+    packet::{grease_version, QUIC_V1, QUIC_V2},
+    streamid::Role,
+};
+
+/// The versions compatible with each other, the first flight of any of them can be converted to the
+/// others, see [RFC 9369](https://www.rfc-editor.org/rfc/rfc9369#section-4).
+const COMPATIBLE_VERSIONS: [u32; 2] = [QUIC_V1, QUIC_V2];
+
+/// The QUIC versions of a connection, used for the version negotiation defined in
+/// [RFC 9368](https://www.rfc-editor.org/rfc/rfc9368).
+///
+/// The client chooses a version to send its first flight, it may restart the handshake in another
+/// version after receiving a Version Negotiation packet, which is protected from downgrade attacks
+/// by validating the version_information transport parameter from the server. The server may
+/// upgrade the connection to a compatible version which is more preferred by itself, then the
+/// client restarts the handshake in the upgraded version, since its TLS session is bound to the
+/// chosen version.
+#[derive(Debug, Clone)]
+pub struct Versions {
+    role: Role,
+    /// The version used in the connection, the Chosen Version of the client, or the Negotiated
+    /// Version of the server.
+    version: u32,
+    /// The version of the first flight from the client, differs from `version` if the server
+    /// upgraded the connection.
+    original: u32,
+    /// The versions supported locally, in order of preference.
+    supported: Vec<u32>,
+    /// Whether the client has restarted the handshake after a Version Negotiation packet.
+    reacted_to_vn: bool,
+    /// Whether the client has restarted the handshake in the version upgraded by the server.
+    followed_upgrade: bool,
+}
+
+impl Versions {
+    /// The versions of a client preferring `preferred`, the versions not implemented are ignored,
+    /// and the most preferred one is chosen to send the first flight. [`QUIC_V1`] is used if none
+    /// of them is implemented.
+    pub fn client(preferred: &[u32]) -> Self {
+        let mut supported = preferred
+            .iter()
+            .copied()
+            .filter(|v| COMPATIBLE_VERSIONS.contains(v))
+            .collect::<Vec<_>>();
+        if supported.is_empty() {
+            supported.push(QUIC_V1);
+        }
+        Self {
+            role: Role::Client,
+            version: supported[0],
+            original: supported[0],
+            supported,
+            reacted_to_vn: false,
+            followed_upgrade: false,
+        }
+    }
+
+    /// The versions of a server supporting `supported`, to accept a connection whose first flight
+    /// is in the `original` version.
+    ///
+    /// The connection is upgraded to the most preferred version of the server that is compatible
+    /// with the `original` version and available to the client, according to the client's
+    /// version_information transport parameter `client_info`.
+    pub fn server(
+        original: u32,
+        supported: &[u32],
+        client_info: Option<&VersionInformation>,
+    ) -> Self {
+        let version = client_info
+            .and_then(|info| {
+                supported.iter().copied().find(|v| {
+                    COMPATIBLE_VERSIONS.contains(v) && info.available_versions().contains(v)
+                })
+            })
+            .filter(|_| COMPATIBLE_VERSIONS.contains(&original))
+            .unwrap_or(original);
+        Self {
+            role: Role::Server,
+            version,
+            original,
+            supported: supported.to_vec(),
+            reacted_to_vn: false,
+            followed_upgrade: false,
+        }
+    }
+
+    /// The version used in the connection.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The version of the first flight from the client.
+    pub fn original(&self) -> u32 {
+        self.original
+    }
+
+    /// Whether the server upgraded the connection to a version other than the original.
+    pub fn is_upgraded(&self) -> bool {
+        self.version != self.original
+    }
+
+    /// Whether the client has restarted the handshake, after a Version Negotiation packet or to
+    /// follow the upgrade of the server. The handshake is restarted at most once.
+    pub fn is_restarted(&self) -> bool {
+        self.reacted_to_vn || self.followed_upgrade
+    }
+
+    /// The version_information transport parameter to be sent to the peer.
+    ///
+    /// All the supported versions are advertised as available, see [`Versions::on_upgrade`] for
+    /// how the client follows an upgrade. Once the client has restarted the handshake, it can't
+    /// follow an upgrade any more, then only the chosen version is available.
+    /// A random reserved version is appended to the available versions for greasing, which is
+    /// never chosen by the peer.
+    pub fn version_information(&self) -> VersionInformation {
+        let mut available_versions = match self.role {
+            Role::Client if self.is_restarted() => vec![self.version],
+            Role::Client | Role::Server => self.supported.clone(),
+        };
+        available_versions.push(grease_version());
+        VersionInformation::new(self.version, available_versions)
+    }
+
+    /// Handle the versions offered in a Version Negotiation packet, client only.
+    ///
+    /// Return the versions to restart the handshake with, or [`None`] if the packet should be
+    /// ignored because it lists the version in use. It's an error if none of the offered versions
+    /// is supported.
+    pub fn on_version_negotiation(&self, offered: &[u32]) -> Result<Option<Self>, Error> {
+        if offered.contains(&self.version) {
+            return Ok(None);
+        }
+        let Some(version) = self.supported.iter().copied().find(|v| offered.contains(v)) else {
+            return Err(Error::with_default_fty(
+                ErrorKind::VersionNegotiation,
+                "no version offered by the server is supported",
+            ));
+        };
+        Ok(Some(Self {
+            version,
+            original: version,
+            reacted_to_vn: true,
+            ..self.clone()
+        }))
+    }
+
+    /// Handle the Initial packet from the server in `version`, client only.
+    ///
+    /// The TLS session can't switch to another version during the handshake, so the versions to
+    /// restart the handshake with are returned if the server upgraded the connection to a
+    /// compatible version, which is validated after the restart. [`None`] if it's the version in
+    /// use, unexpected, or the handshake has been restarted already.
+    pub fn on_upgrade(&self, version: u32) -> Option<Self> {
+        let is_upgrade = self.role == Role::Client
+            && version != self.version
+            && COMPATIBLE_VERSIONS.contains(&self.version)
+            && COMPATIBLE_VERSIONS.contains(&version)
+            && self.supported.contains(&version);
+        if !is_upgrade || self.is_restarted() {
+            return None;
+        }
+        Some(Self {
+            version,
+            original: version,
+            followed_upgrade: true,
+            ..self.clone()
+        })
+    }
+
+    /// Validate the version_information transport parameter received from the peer, see
+    /// [Section 4](https://www.rfc-editor.org/rfc/rfc9368#section-4) of RFC 9368.
+    pub fn validate(&self, peer_info: Option<&VersionInformation>) -> Result<(), Error> {
+        let mismatch = |reason| {
+            Err(Error::with_default_fty(
+                ErrorKind::VersionNegotiation,
+                reason,
+            ))
+        };
+        match (self.role, peer_info) {
+            (Role::Client, None) if self.is_restarted() => {
+                mismatch("missing version information after version negotiation")
+            }
+            (Role::Client, Some(info)) if info.chosen_version() != self.version => {
+                mismatch("server chose a different version")
+            }
+            (Role::Client, Some(info)) if self.reacted_to_vn => {
+                // Would have chosen the same version if the Version Negotiation packet had listed
+                // exactly the server's available versions, otherwise it's a downgrade attack.
+                let expected = self
+                    .supported
+                    .iter()
+                    .find(|v| info.available_versions().contains(v));
+                if expected != Some(&self.version) {
+                    return mismatch("version downgrade detected");
+                }
+                Ok(())
+            }
+            (Role::Client, Some(info)) if self.followed_upgrade => {
+                // The server would have upgraded to its most preferred version available to the
+                // client, otherwise the Initial packet in the upgraded version was forged
+                let expected = info
+                    .available_versions()
+                    .iter()
+                    .find(|v| COMPATIBLE_VERSIONS.contains(v) && self.supported.contains(v));
+                if expected != Some(&self.version) {
+                    return mismatch("version upgrade mismatches the server's preference");
+                }
+                Ok(())
+            }
+            (Role::Server, Some(info)) if info.chosen_version() != self.original => {
+                mismatch("chosen version mismatches the original version")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qbase::packet::is_reserved_version;
+
+    use super::*;
+
+    #[test]
+    fn test_client_versions() {
+        let versions = Versions::client(&[0x1a2a_3a4a, QUIC_V2, QUIC_V1]);
+        assert_eq!(versions.version(), QUIC_V2);
+        let info = versions.version_information();
+        assert_eq!(info.chosen_version(), QUIC_V2);
+        assert_eq!(info.available_versions()[..2], [QUIC_V2, QUIC_V1]);
+        assert!(is_reserved_version(info.available_versions()[2]));
+        assert_eq!(Versions::client(&[]).version(), QUIC_V1);
+
+        assert!(versions
+            .on_version_negotiation(&[QUIC_V1, QUIC_V2])
+            .unwrap()
+            .is_none());
+        assert!(versions.on_version_negotiation(&[0x1a2a_3a4a]).is_err());
+        let restarted = versions
+            .on_version_negotiation(&[QUIC_V1])
+            .unwrap()
+            .unwrap();
+        assert_eq!(restarted.version(), QUIC_V1);
+        // 重启之后无法再跟随服务端的升级，只通告所选的版本
+        let info = restarted.version_information();
+        assert_eq!(info.available_versions()[0], QUIC_V1);
+        assert!(is_reserved_version(info.available_versions()[1]));
+        assert!(restarted.on_upgrade(QUIC_V2).is_none());
+
+        assert!(versions.validate(None).is_ok());
+        assert!(restarted.validate(None).is_err());
+        let server_info = VersionInformation::new(QUIC_V1, vec![QUIC_V1]);
+        assert!(restarted.validate(Some(&server_info)).is_ok());
+        // The server supports QUIC_V2 actually, the Version Negotiation packet was forged
+        let server_info = VersionInformation::new(QUIC_V1, vec![QUIC_V1, QUIC_V2]);
+        assert!(restarted.validate(Some(&server_info)).is_err());
+        let server_info = VersionInformation::new(QUIC_V2, vec![QUIC_V2]);
+        assert!(restarted.validate(Some(&server_info)).is_err());
+    }
+
+    #[test]
+    fn test_client_follow_upgrade() {
+        let versions = Versions::client(&[QUIC_V1, QUIC_V2]);
+        assert!(versions.on_upgrade(QUIC_V1).is_none());
+        assert!(versions.on_upgrade(0x1a2a_3a4a).is_none());
+        let upgraded = versions.on_upgrade(QUIC_V2).unwrap();
+        assert_eq!(upgraded.version(), QUIC_V2);
+        assert!(upgraded.is_restarted());
+        assert!(upgraded.on_upgrade(QUIC_V1).is_none());
+
+        // 服务端确实更偏好升级后的版本
+        let server_info = VersionInformation::new(QUIC_V2, vec![QUIC_V2, QUIC_V1]);
+        assert!(upgraded.validate(Some(&server_info)).is_ok());
+        assert!(upgraded.validate(None).is_err());
+        // 服务端更偏好原来的版本，升级的Initial包是伪造的
+        let server_info = VersionInformation::new(QUIC_V2, vec![QUIC_V1, QUIC_V2]);
+        assert!(upgraded.validate(Some(&server_info)).is_err());
+    }
+
+    #[test]
+    fn test_server_versions() {
+        let supported = [QUIC_V2, QUIC_V1];
+        let client_info = VersionInformation::new(QUIC_V1, vec![QUIC_V1, QUIC_V2]);
+        let versions = Versions::server(QUIC_V1, &supported, Some(&client_info));
+        assert_eq!(versions.version(), QUIC_V2);
+        assert!(versions.is_upgraded());
+        let info = versions.version_information();
+        assert_eq!(info.chosen_version(), QUIC_V2);
+        assert_eq!(info.available_versions()[..2], [QUIC_V2, QUIC_V1]);
+        assert!(is_reserved_version(info.available_versions()[2]));
+        assert!(versions.validate(Some(&client_info)).is_ok());
+        assert!(versions.validate(None).is_ok());
+        let forged = VersionInformation::new(QUIC_V2, vec![QUIC_V1, QUIC_V2]);
+        assert!(versions.validate(Some(&forged)).is_err());
+
+        let client_info = VersionInformation::new(QUIC_V1, vec![QUIC_V1]);
+        let versions = Versions::server(QUIC_V1, &supported, Some(&client_info));
+        assert_eq!(versions.version(), QUIC_V1);
+        assert!(!Versions::server(QUIC_V1, &supported, None).is_upgraded());
+    }
+}
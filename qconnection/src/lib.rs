@@ -6,6 +6,7 @@ pub mod path;
 pub mod pipe;
 pub mod router;
 pub mod tls;
+pub mod usc;
 
 /// 发送报文的trait，但其实发送还有其他需要的形式，比如：
 /// - 携带ttl设置发送
@@ -2,18 +2,27 @@ use std::{
     future::Future,
     io::{self, IoSlice},
     pin::Pin,
-    sync::Arc,
+    sync::{atomic::AtomicUsize, Arc},
     task::{ready, Context, Poll},
+    time::Duration,
 };
 
 use dashmap::DashMap;
 use deref_derive::{Deref, DerefMut};
-use qbase::cid::{ArcCidCell, ConnectionId};
-use qcongestion::{CongestionControl, MayLoss, RetirePktRecord, MSS};
+use qbase::{
+    cid::{ArcCidCell, ConnectionId},
+    frame::EcnCodepoint,
+};
+use qcongestion::{ArcCC, CongestionControl, MSS};
 use qrecovery::reliable::ArcReliableFrameDeque;
-use qudp::ArcUsc;
+
+use crate::{
+    connection::multipath::PathSeqs,
+    usc::{PacketHeader, QuicIo},
+};
 
 mod anti_amplifier;
+mod mtu;
 mod raw;
 mod state;
 mod util;
@@ -22,51 +31,67 @@ pub mod pathway;
 pub mod read;
 
 pub use anti_amplifier::ArcAntiAmplifier;
+pub use mtu::{ArcPmtud, MAX_DATAGRAM_SIZE};
 pub use pathway::Pathway;
 pub use raw::RawPath;
 pub use util::{RecvBuffer, SendBuffer};
 
 pub trait ViaPathway {
+    /// Send the datagrams `bufs` via the `pathway`, marked with the ECN codepoint `ecn`.
     fn poll_send_via_pathway(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         bufs: &[IoSlice<'_>],
         pathway: Pathway,
+        ecn: Option<EcnCodepoint>,
     ) -> Poll<io::Result<usize>>;
 
     fn sync_send_via_path_way(&mut self, iovec: Vec<u8>, pathway: Pathway) -> io::Result<()>;
 }
 
-impl ViaPathway for ArcUsc {
+impl ViaPathway for Arc<dyn QuicIo> {
     fn poll_send_via_pathway<'a>(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         bufs: &[IoSlice<'_>],
         pathway: Pathway,
+        ecn: Option<EcnCodepoint>,
     ) -> Poll<io::Result<usize>> {
         // todo: append relay hdr
-        let hdr = qudp::PacketHeader {
+        // qudp splits the datagrams into the batches of the same size to send by GSO
+        let seg_size = bufs.first().map_or(MSS, |buf| buf.len());
+        let hdr = PacketHeader {
             src: pathway.local_addr(),
             dst: pathway.dst_addr(),
             ttl: 64,
-            ecn: None,
-            seg_size: MSS as u16,
+            ecn,
+            seg_size: seg_size as u16,
+            stride: 0,
             gso: true,
         };
-        ArcUsc::poll_send(self.get_mut(), bufs, &hdr, cx)
+        self.poll_send(cx, bufs, &hdr)
     }
 
     fn sync_send_via_path_way(&mut self, iovec: Vec<u8>, pathway: Pathway) -> io::Result<()> {
         // todo: append relay hdr
-        let hdr = qudp::PacketHeader {
+        let hdr = PacketHeader {
             src: pathway.local_addr(),
             dst: pathway.dst_addr(),
             ttl: 64,
             ecn: None,
-            seg_size: MSS as u16,
+            seg_size: iovec.len() as u16,
+            stride: 0,
             gso: true,
         };
-        self.sync_send(iovec, &hdr)
+        // 发送不能等待，交由一个单独的任务去发送
+        let usc = self.clone();
+        tokio::spawn(async move {
+            let bufs = [IoSlice::new(&iovec)];
+            if let Err(e) = core::future::poll_fn(|cx| usc.poll_send(cx, &bufs, &hdr)).await {
+                log::warn!("failed to send the packet via {pathway:?}: {e}");
+            }
+        });
+        Ok(())
     }
 }
 
@@ -75,6 +100,7 @@ pub trait ViaPathWayExt: ViaPathway {
         &'s mut self,
         iovecs: &'s [IoSlice<'s>],
         pathway: Pathway,
+        ecn: Option<EcnCodepoint>,
     ) -> SendViaPathWay<'s, Self>
     where
         Self: Unpin,
@@ -83,6 +109,7 @@ pub trait ViaPathWayExt: ViaPathway {
             sender: self,
             iovecs,
             pathway,
+            ecn,
         }
     }
 
@@ -90,6 +117,7 @@ pub trait ViaPathWayExt: ViaPathway {
         &'s mut self,
         iovecs: &'s [IoSlice<'s>],
         pathway: Pathway,
+        ecn: Option<EcnCodepoint>,
     ) -> SendAllViaPathWay<'s, Self>
     where
         Self: Unpin,
@@ -98,6 +126,7 @@ pub trait ViaPathWayExt: ViaPathway {
             sender: self,
             iovecs,
             pathway,
+            ecn,
         }
     }
 }
@@ -108,6 +137,7 @@ pub struct SendViaPathWay<'s, S: ?Sized> {
     sender: &'s mut S,
     iovecs: &'s [IoSlice<'s>],
     pathway: Pathway,
+    ecn: Option<EcnCodepoint>,
 }
 
 impl<S: Unpin + ?Sized> Unpin for SendViaPathWay<'_, S> {}
@@ -117,7 +147,7 @@ impl<S: ViaPathway + Unpin + ?Sized> Future for SendViaPathWay<'_, S> {
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
-        Pin::new(&mut *this.sender).poll_send_via_pathway(cx, this.iovecs, this.pathway)
+        Pin::new(&mut *this.sender).poll_send_via_pathway(cx, this.iovecs, this.pathway, this.ecn)
     }
 }
 
@@ -125,6 +155,7 @@ pub struct SendAllViaPathWay<'s, S: ?Sized> {
     sender: &'s mut S,
     iovecs: &'s [IoSlice<'s>],
     pathway: Pathway,
+    ecn: Option<EcnCodepoint>,
 }
 
 impl<S: Unpin + ?Sized> Unpin for SendAllViaPathWay<'_, S> {}
@@ -136,8 +167,12 @@ impl<S: ViaPathway + Unpin + ?Sized> Future for SendAllViaPathWay<'_, S> {
         let this = self.get_mut();
         let iovecs = &mut this.iovecs;
         while !iovecs.is_empty() {
-            let send_once =
-                Pin::new(&mut *this.sender).poll_send_via_pathway(cx, iovecs, this.pathway);
+            let send_once = Pin::new(&mut *this.sender).poll_send_via_pathway(
+                cx,
+                iovecs,
+                this.pathway,
+                this.ecn,
+            );
             let n = ready!(send_once)?;
             *iovecs = &iovecs[n..];
         }
@@ -145,32 +180,82 @@ impl<S: ViaPathway + Unpin + ?Sized> Future for SendAllViaPathWay<'_, S> {
     }
 }
 
+/// A snapshot of the state of a path, returned by [`ArcConnection::paths`].
+///
+/// [`ArcConnection::paths`]: crate::connection::ArcConnection::paths
+#[derive(Debug, Clone, Copy)]
+pub struct PathInfo {
+    pub pathway: Pathway,
+    /// Whether the peer's address on this path has been validated.
+    pub is_validated: bool,
+    pub smoothed_rtt: Duration,
+    /// The minimum RTT observed on this path, zero if no RTT sample has been taken.
+    pub min_rtt: Duration,
+    pub cwnd: u64,
+    /// The rate at which the packets are paced on this path, in bytes per second.
+    pub pacing_rate: u64,
+    /// The bandwidth estimated from the delivery rate samples, in bytes per second. [`None`] if
+    /// the congestion control algorithm does not sample it, only BBR does.
+    pub delivery_rate: Option<u64>,
+    pub bytes_in_flight: usize,
+    /// The remaining anti-amplification credit, [`None`] if the path has been validated.
+    pub anti_amplification_credit: Option<usize>,
+    /// The size of the datagrams sent on this path, raised by the path MTU discovery.
+    pub max_datagram_size: usize,
+}
+
+/// The error of the path validation triggered by [`ArcConnection::validate_path`].
+///
+/// [`ArcConnection::validate_path`]: crate::connection::ArcConnection::validate_path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum PathError {
+    #[error("the path does not exist")]
+    NotFound,
+    #[error("the path is being validated")]
+    Validating,
+    #[error("the peer did not respond to the PATH_CHALLENGE")]
+    Unresponsive,
+    #[error("the path has been inactivated")]
+    Inactivated,
+}
+
 #[derive(Clone, Deref)]
 pub struct ArcPath(Arc<RawPath>);
 
 impl ArcPath {
     pub fn new(
-        usc: ArcUsc,
+        usc: Arc<dyn QuicIo>,
         scid: ConnectionId,
         dcid: ArcCidCell<ArcReliableFrameDeque>,
-        loss: [Box<dyn MayLoss>; 3],
-        retire: [Box<dyn RetirePktRecord>; 3],
+        seqs: PathSeqs,
+        cc: ArcCC,
+        max_datagram_size: Arc<AtomicUsize>,
     ) -> Self {
-        Self(Arc::new(RawPath::new(usc, scid, dcid, loss, retire)))
+        Self(Arc::new(RawPath::new(
+            usc,
+            scid,
+            dcid,
+            seqs,
+            cc,
+            max_datagram_size,
+        )))
     }
 }
 
+/// The maximum number of paths being validated at the same time, see [`Pathes::get_or_probe`].
+pub const MAX_PROBING_PATHS: usize = 4;
+
 #[derive(Deref, DerefMut)]
 pub struct Pathes {
     #[deref]
     map: DashMap<Pathway, ArcPath>,
-    creator: Box<dyn Fn(Pathway, ArcUsc) -> ArcPath + Send + Sync + 'static>,
+    creator: Box<dyn Fn(Pathway, Arc<dyn QuicIo>) -> ArcPath + Send + Sync + 'static>,
     on_no_path: Arc<dyn Fn() + Send + Sync + 'static>,
 }
 
 impl Pathes {
     fn new(
-        creator: Box<dyn Fn(Pathway, ArcUsc) -> ArcPath + Send + Sync + 'static>,
+        creator: Box<dyn Fn(Pathway, Arc<dyn QuicIo>) -> ArcPath + Send + Sync + 'static>,
         on_no_path: Arc<dyn Fn() + Send + Sync + 'static>,
     ) -> Self {
         Self {
@@ -180,7 +265,7 @@ impl Pathes {
         }
     }
 
-    pub fn get_or_create(&self, pathway: Pathway, usc: ArcUsc) -> ArcPath {
+    pub fn get_or_create(&self, pathway: Pathway, usc: Arc<dyn QuicIo>) -> ArcPath {
         let pathes = self.map.clone();
         let on_no_path = self.on_no_path.clone();
 
@@ -210,6 +295,67 @@ impl Pathes {
             .value()
             .clone()
     }
+
+    /// Get the path of `pathway`, or create a path to probe the new peer's address, return [`None`]
+    /// if there are too many paths being validated.
+    ///
+    /// A packet from a new peer's address may be a spoofed one, or the peer is probing a new path,
+    /// or the peer's address changed due to NAT rebinding. The new path is validated before any
+    /// non-probing frame being sent on it, the packets from more new addresses are dropped while
+    /// [`MAX_PROBING_PATHS`] paths are being validated, so that an attacker can not make the
+    /// endpoint send PATH_CHALLENGE frames to many addresses, see [Section 9.3](https://www.rfc-editor.org/rfc/rfc9000.html#section-9.3)
+    /// of RFC 9000.
+    pub fn get_or_probe(&self, pathway: Pathway, usc: Arc<dyn QuicIo>) -> Option<ArcPath> {
+        if let Some(path) = self.map.get(&pathway) {
+            return Some(path.value().clone());
+        }
+        let probing = self.map.iter().filter(|path| path.is_probing()).count();
+        if probing >= MAX_PROBING_PATHS {
+            return None;
+        }
+        Some(self.get_or_create(pathway, usc))
+    }
+
+    /// Find the path sending packets with the Destination Connection ID of sequence number `seq`,
+    /// which identifies the path when the multipath extension is used.
+    pub fn find_by_sent_seq(&self, seq: u64) -> Option<(Pathway, ArcPath)> {
+        self.map
+            .iter()
+            .find(|path| path.seqs.sent() == seq)
+            .map(|path| (*path.key(), path.value().clone()))
+    }
+
+    /// Migrate to the path of `pathway`, inactivate all the other paths.
+    ///
+    /// It's called by the server when a non-probing packet with the largest packet number is received
+    /// via a validated path, see [Section 9.3](https://www.rfc-editor.org/rfc/rfc9000.html#section-9.3)
+    /// of RFC 9000. Each path has its own congestion controller and RTT estimator, so the congestion
+    /// state is reset after the migration, as required by [Section 9.4](https://www.rfc-editor.org/rfc/rfc9000.html#section-9.4).
+    pub fn migrate_to(&self, pathway: &Pathway) {
+        if self.map.len() <= 1 {
+            return;
+        }
+        for path in self.map.iter().filter(|path| path.key() != pathway) {
+            path.inactivate();
+        }
+    }
+
+    /// Count the `amount` bytes of a packet received via `pathway` into the anti-amplification
+    /// limit of the path before processing the packet, return whether the path exists.
+    ///
+    /// A server MUST count all the bytes received in datagrams attributed to the connection,
+    /// including the packets discarded later, see [Section 8.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-8.1)
+    /// of RFC 9000. Only the existing paths are credited, a new path is created only after a packet is
+    /// authenticated, and the packet should be counted then.
+    pub fn on_rcvd(&self, pathway: &Pathway, amount: usize) -> bool {
+        match self.map.get(pathway) {
+            Some(path) => {
+                path.anti_amplifier.on_rcvd(amount);
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 #[derive(Clone, Deref)]
@@ -217,7 +363,7 @@ pub struct ArcPathes(Arc<Pathes>);
 
 impl ArcPathes {
     pub fn new(
-        creator: Box<dyn Fn(Pathway, ArcUsc) -> ArcPath + Send + Sync + 'static>,
+        creator: Box<dyn Fn(Pathway, Arc<dyn QuicIo>) -> ArcPath + Send + Sync + 'static>,
         on_no_path: Arc<dyn Fn() + Send + Sync + 'static>,
     ) -> Self {
         Self(Arc::new(Pathes::new(creator, on_no_path)))
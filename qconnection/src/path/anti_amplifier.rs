@@ -72,9 +72,29 @@ impl<const N: usize> AntiAmplifier<N> {
         }
     }
 
+    /// Consume the credit, sending more than the credit just exhausts it rather than wrapping
+    /// around to an unlimited credit.
     pub fn on_sent(&self, amount: usize) {
         if self.state.load(Ordering::Acquire) == Self::NORMAL {
-            self.credit.fetch_sub(amount, Ordering::AcqRel);
+            _ = self
+                .credit
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |credit| {
+                    Some(credit.saturating_sub(amount))
+                });
+        }
+    }
+
+    /// Returns whether the address has been validated, after which no limit applies.
+    pub fn is_granted(&self) -> bool {
+        self.state.load(Ordering::Acquire) == Self::GRANTED
+    }
+
+    /// Returns the remaining credit, or [`None`] if the address has been validated.
+    pub fn credit(&self) -> Option<usize> {
+        match self.state.load(Ordering::Acquire) {
+            Self::GRANTED => None,
+            Self::ABORTED => Some(0),
+            _ => Some(self.credit.load(Ordering::Acquire)),
         }
     }
 
@@ -178,4 +198,29 @@ mod tests {
         anti_amplifier.on_sent(5);
         assert_eq!(anti_amplifier.credit.load(Ordering::Acquire), 1);
     }
+
+    #[test]
+    fn test_sent_beyond_credit() {
+        let anti_amplifier = ArcAntiAmplifier::<3>::default();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        anti_amplifier.on_rcvd(1);
+        anti_amplifier.on_sent(5);
+        assert_eq!(anti_amplifier.credit(), Some(0));
+        assert_eq!(anti_amplifier.poll_balance(&mut cx), Poll::Pending);
+
+        anti_amplifier.on_rcvd(1);
+        assert_eq!(anti_amplifier.credit(), Some(3));
+    }
+
+    #[test]
+    fn test_credit() {
+        let anti_amplifier = ArcAntiAmplifier::<3>::default();
+        anti_amplifier.on_rcvd(2);
+        assert!(!anti_amplifier.is_granted());
+        assert_eq!(anti_amplifier.credit(), Some(6));
+
+        anti_amplifier.grant();
+        assert!(anti_amplifier.is_granted());
+        assert_eq!(anti_amplifier.credit(), None);
+    }
 }
@@ -0,0 +1,215 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use qcongestion::MSS;
+
+/// The largest datagram to be sent on a path, which is the UDP payload size in an Ethernet frame
+/// over IPv6, and it fits the receive buffers.
+pub const MAX_DATAGRAM_SIZE: usize = 1452;
+
+/// A probe is considered failed after it has been lost this many times in a row.
+const MAX_PROBES: u8 = 3;
+
+/// The search completes once the range to search is not larger than this.
+const SEARCH_GRANULARITY: usize = 20;
+
+/// How long to wait before probing a larger size again after the search completes.
+const PMTU_RAISE_TIMER: Duration = Duration::from_secs(600);
+
+/// Datagram Packetization Layer Path MTU Discovery, see
+/// [RFC 8899](https://www.rfc-editor.org/rfc/rfc8899) and
+/// [Section 14.3](https://www.rfc-editor.org/rfc/rfc9000.html#section-14.3) of RFC 9000.
+///
+/// The path starts with datagrams of [`MSS`] bytes, which every QUIC path must support. Probe
+/// packets padded to a larger size are sent one at a time, the size is searched in binary between
+/// the confirmed size and the peer's max_udp_payload_size. Once a probe is acknowledged, the path
+/// sends datagrams of that size. The loss of a probe is not a congestion signal, after
+/// [`MAX_PROBES`] consecutive losses the size is considered too large.
+///
+/// The search is restarted after [`PMTU_RAISE_TIMER`], in case the path MTU has increased. Black
+/// hole detection is not implemented yet, the path falls back to [`MSS`] only if it's recreated.
+#[derive(Debug)]
+struct Pmtud {
+    /// The confirmed size of the datagrams sent on the path.
+    plpmtu: usize,
+    /// The largest size that might be supported, the upper bound of the search.
+    search_high: usize,
+    /// The packet number and the size of the probe in flight.
+    probe: Option<(u64, usize)>,
+    /// The times the probe of the current size has been lost.
+    lost_probes: u8,
+    /// When to restart the search after it completed.
+    raise_time: Option<Instant>,
+}
+
+impl Pmtud {
+    fn new() -> Self {
+        Self {
+            plpmtu: MSS,
+            search_high: MAX_DATAGRAM_SIZE,
+            probe: None,
+            lost_probes: 0,
+            raise_time: None,
+        }
+    }
+
+    fn next_probe_size(&mut self, max: usize, now: Instant) -> Option<usize> {
+        // Nothing to search, or the peer's max_udp_payload_size is unknown yet
+        if self.probe.is_some() || max <= self.plpmtu + SEARCH_GRANULARITY {
+            return None;
+        }
+        if let Some(raise_time) = self.raise_time {
+            if now < raise_time {
+                return None;
+            }
+            self.raise_time = None;
+            self.search_high = MAX_DATAGRAM_SIZE;
+        }
+        let search_high = self.search_high.min(max);
+        if search_high <= self.plpmtu + SEARCH_GRANULARITY {
+            self.raise_time = Some(now + PMTU_RAISE_TIMER);
+            return None;
+        }
+        Some(self.plpmtu + (search_high - self.plpmtu).div_ceil(2))
+    }
+
+    fn on_probe_sent(&mut self, pn: u64, size: usize) {
+        self.probe = Some((pn, size));
+    }
+
+    fn on_probe_acked(&mut self, pn: u64) {
+        if let Some((_, size)) = self.probe.take_if(|(probe_pn, _)| *probe_pn == pn) {
+            self.plpmtu = self.plpmtu.max(size);
+            self.lost_probes = 0;
+        }
+    }
+
+    fn on_probe_lost(&mut self, pn: u64) {
+        if let Some((_, size)) = self.probe.take_if(|(probe_pn, _)| *probe_pn == pn) {
+            self.lost_probes += 1;
+            if self.lost_probes >= MAX_PROBES {
+                self.search_high = size - 1;
+                self.lost_probes = 0;
+            }
+        }
+    }
+}
+
+/// The shared [`Pmtud`] of a path, limited by the peer's max_udp_payload_size.
+#[derive(Debug, Clone)]
+pub struct ArcPmtud {
+    pmtud: Arc<Mutex<Pmtud>>,
+    /// The peer's max_udp_payload_size, not larger than [`MAX_DATAGRAM_SIZE`], and the search
+    /// does not start until it's known.
+    max: Arc<AtomicUsize>,
+}
+
+impl ArcPmtud {
+    /// Create the [`ArcPmtud`] of a path, `max` is shared by all paths of the connection, and will
+    /// be set once the peer's transport parameters are received, see [`ArcPmtud::new_max`].
+    pub fn new(max: Arc<AtomicUsize>) -> Self {
+        Self {
+            pmtud: Arc::new(Mutex::new(Pmtud::new())),
+            max,
+        }
+    }
+
+    /// The limit to be shared by all paths of a connection before the peer's transport parameters
+    /// are received.
+    pub fn new_max() -> Arc<AtomicUsize> {
+        Arc::new(AtomicUsize::new(MSS))
+    }
+
+    /// Update the shared `max` with the peer's max_udp_payload_size.
+    pub fn set_max(max: &AtomicUsize, max_udp_payload_size: u64) {
+        let max_udp_payload_size = usize::try_from(max_udp_payload_size).unwrap_or(usize::MAX);
+        max.store(
+            max_udp_payload_size.clamp(MSS, MAX_DATAGRAM_SIZE),
+            Ordering::Release,
+        );
+    }
+
+    /// The size of the datagrams to be sent on the path.
+    pub fn plpmtu(&self) -> usize {
+        self.pmtud.lock().unwrap().plpmtu
+    }
+
+    /// The size of the probe to be sent now, or [`None`] if a probe is in flight, or the search
+    /// has completed.
+    pub fn next_probe_size(&self) -> Option<usize> {
+        let max = self.max.load(Ordering::Acquire);
+        self.pmtud
+            .lock()
+            .unwrap()
+            .next_probe_size(max, Instant::now())
+    }
+
+    pub fn on_probe_sent(&self, pn: u64, size: usize) {
+        self.pmtud.lock().unwrap().on_probe_sent(pn, size);
+    }
+
+    /// Feed back whether the probe packet `pn` has been acknowledged or declared lost.
+    pub fn on_probe_outcome(&self, pn: u64, is_acked: bool) {
+        let mut pmtud = self.pmtud.lock().unwrap();
+        if is_acked {
+            pmtud.on_probe_acked(pn);
+        } else {
+            pmtud.on_probe_lost(pn);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let now = Instant::now();
+        let mut pmtud = Pmtud::new();
+        // The path MTU is actually 1400 bytes
+        let mut pn = 0;
+        while let Some(size) = pmtud.next_probe_size(MAX_DATAGRAM_SIZE, now) {
+            assert!(size > pmtud.plpmtu && size <= MAX_DATAGRAM_SIZE);
+            for _ in 0..MAX_PROBES {
+                pmtud.on_probe_sent(pn, size);
+                assert_eq!(pmtud.next_probe_size(MAX_DATAGRAM_SIZE, now), None);
+                if size <= 1400 {
+                    pmtud.on_probe_acked(pn);
+                    pn += 1;
+                    break;
+                }
+                pmtud.on_probe_lost(pn);
+                pn += 1;
+            }
+        }
+        assert!(pmtud.plpmtu <= 1400 && pmtud.plpmtu > 1400 - SEARCH_GRANULARITY);
+
+        // Search again after the raise timer expires
+        assert_eq!(pmtud.next_probe_size(MAX_DATAGRAM_SIZE, now), None);
+        let later = now + PMTU_RAISE_TIMER;
+        assert!(pmtud.next_probe_size(MAX_DATAGRAM_SIZE, later).is_some());
+    }
+
+    #[test]
+    fn test_limited_by_peer() {
+        let now = Instant::now();
+        let mut pmtud = Pmtud::new();
+        assert_eq!(pmtud.next_probe_size(MSS, now), None);
+
+        let size = pmtud.next_probe_size(1300, now).unwrap();
+        assert_eq!(size, 1250);
+        pmtud.on_probe_sent(0, size);
+        // A late outcome of another packet is ignored
+        pmtud.on_probe_acked(1);
+        assert_eq!(pmtud.plpmtu, MSS);
+        pmtud.on_probe_acked(0);
+        assert_eq!(pmtud.plpmtu, 1250);
+        assert_eq!(pmtud.next_probe_size(1300, now), Some(1275));
+    }
+}
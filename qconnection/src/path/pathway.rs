@@ -1,4 +1,7 @@
-use std::{hash::Hash, net::SocketAddr};
+use std::{
+    hash::Hash,
+    net::{Ipv6Addr, SocketAddr},
+};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct RelayAddr {
@@ -42,15 +45,28 @@ impl Pathway {
     }
 }
 
+/// The IPv4-mapped IPv6 address is the same as the IPv4 one, the dual-stack socket sends and
+/// receives the IPv4 datagrams by it.
+fn canonical(addr: &SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+/// The addresses are compared in the canonical form, so the peer's IPv4 address and its
+/// IPv4-mapped form are the same path. The unspecified local address matches any address of the
+/// same port in the same family, and the IPv6 one matches the IPv4 addresses too, which are
+/// received by the dual-stack socket.
 impl PartialEq for Pathway {
     fn eq(&self, other: &Self) -> bool {
         let match_local = |l: &SocketAddr, r: &SocketAddr| {
+            let (l, r) = (canonical(l), canonical(r));
             if l.ip().is_unspecified() | r.ip().is_unspecified() {
-                l.is_ipv4() == r.is_ipv4() && l.port() == r.port()
+                let is_dual_stack = [l, r].iter().any(|a| a.ip() == Ipv6Addr::UNSPECIFIED);
+                (l.is_ipv4() == r.is_ipv4() || is_dual_stack) && l.port() == r.port()
             } else {
                 l == r
             }
         };
+        let match_remote = |l: &SocketAddr, r: &SocketAddr| canonical(l) == canonical(r);
         match (self, other) {
             (
                 Self::Direct {
@@ -61,7 +77,7 @@ impl PartialEq for Pathway {
                     local: r_local,
                     remote: r_remote,
                 },
-            ) => l_remote == r_remote && match_local(l_local, r_local),
+            ) => match_remote(l_remote, r_remote) && match_local(l_local, r_local),
             (
                 Self::Relay {
                     local: l_local,
@@ -72,9 +88,10 @@ impl PartialEq for Pathway {
                     remote: r_remote,
                 },
             ) => {
-                l_remote == r_remote
+                match_remote(&l_remote.addr, &r_remote.addr)
+                    && match_remote(&l_remote.agent, &r_remote.agent)
                     && match_local(&l_local.addr, &r_local.addr)
-                    && l_local.agent == r_local.agent
+                    && match_remote(&l_local.agent, &r_local.agent)
             }
             _ => false,
         }
@@ -234,7 +251,43 @@ mod tests {
                 local: v6addr_mapped1,
                 remote: v6addr_mapped2
             }
-        )
+        );
+
+        // 双栈套接字收到的IPv4映射地址，与IPv4地址是同一条路径
+        let v4addr1: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let v4addr2: SocketAddr = "127.0.0.1:5678".parse().unwrap();
+        assert_eq!(
+            Pathway::Direct {
+                local: v6addr_unspec1,
+                remote: v4addr2
+            },
+            Pathway::Direct {
+                local: v6addr_mapped1,
+                remote: v6addr_mapped2
+            }
+        );
+        assert_eq!(
+            Pathway::Direct {
+                local: v4addr1,
+                remote: v4addr2
+            },
+            Pathway::Direct {
+                local: v6addr_mapped1,
+                remote: v6addr_mapped2
+            }
+        );
+        // 绑定在0.0.0.0上的套接字收不到IPv6的数据报
+        let v4addr_unspec1: SocketAddr = "0.0.0.0:1234".parse().unwrap();
+        assert_ne!(
+            Pathway::Direct {
+                local: v4addr_unspec1,
+                remote: v6addr1
+            },
+            Pathway::Direct {
+                local: v6addr1,
+                remote: v6addr1
+            }
+        );
     }
 
     #[test]
@@ -1,34 +1,46 @@
 use std::{
-    sync::{atomic::AtomicBool, Arc},
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
+use crate::usc::QuicIo;
 use qbase::{
     cid::{ArcCidCell, ConnectionId},
     flow::FlowController,
     frame::{PathChallengeFrame, PathResponseFrame},
+    packet::SpinBit,
+    streamid::Role,
+    util::Future,
 };
-use qcongestion::{ArcCC, CongestionAlgorithm, CongestionControl, MayLoss, RetirePktRecord};
+use qcongestion::{ArcCC, CongestionControl};
 use qrecovery::{reliable::ArcReliableFrameDeque, space::Epoch};
-use qudp::ArcUsc;
 use tokio::time::timeout;
 
 use super::{
     anti_amplifier::{ArcAntiAmplifier, ANTI_FACTOR},
+    mtu::ArcPmtud,
     read::ReadIntoDatagrams,
     state::ArcPathState,
     util::{RecvBuffer, SendBuffer},
-    Pathway, ViaPathWayExt,
+    PathError, PathInfo, Pathway, ViaPathWayExt,
 };
-use crate::connection::transmit::{
-    data::DataSpaceReader, handshake::HandshakeSpaceReader, initial::InitialSpaceReader,
+use crate::connection::{
+    multipath::PathSeqs,
+    transmit::{
+        data::DataSpaceReader, handshake::HandshakeSpaceReader, initial::InitialSpaceReader,
+    },
 };
 
 #[derive(Clone)]
 pub struct RawPath {
     pub anti_amplifier: ArcAntiAmplifier<ANTI_FACTOR>,
     pub cc: ArcCC,
-    pub(super) usc: ArcUsc,
+    pub pmtud: ArcPmtud,
+    pub seqs: PathSeqs,
+    pub(super) usc: Arc<dyn QuicIo>,
     pub(super) dcid: ArcCidCell<ArcReliableFrameDeque>,
     pub(super) scid: ConnectionId,
     pub(super) spin: Arc<AtomicBool>,
@@ -36,32 +48,36 @@ pub struct RawPath {
     pub(super) response_sndbuf: SendBuffer<PathResponseFrame>,
     pub(super) response_rcvbuf: RecvBuffer<PathResponseFrame>,
     pub(super) state: ArcPathState,
+    pub(super) validation: Arc<Future<bool>>,
+    pub(super) probing: Arc<AtomicBool>,
 }
 
 impl RawPath {
+    /// Create a path owning the congestion controller `cc`, which should not be shared with the
+    /// other paths, see [`CongestionControl`].
     pub fn new(
-        usc: ArcUsc,
+        usc: Arc<dyn QuicIo>,
         scid: ConnectionId,
         dcid: ArcCidCell<ArcReliableFrameDeque>,
-        loss: [Box<dyn MayLoss>; 3],
-        retire: [Box<dyn RetirePktRecord>; 3],
+        seqs: PathSeqs,
+        cc: ArcCC,
+        max_datagram_size: Arc<AtomicUsize>,
     ) -> Self {
         Self {
+            seqs,
             usc,
             dcid: dcid.clone(),
             scid,
-            cc: ArcCC::new(
-                CongestionAlgorithm::Bbr,
-                Duration::from_micros(100),
-                loss,
-                retire,
-            ),
+            cc,
             anti_amplifier: ArcAntiAmplifier::<ANTI_FACTOR>::default(),
+            pmtud: ArcPmtud::new(max_datagram_size),
             spin: Arc::new(AtomicBool::new(false)),
             challenge_sndbuf: SendBuffer::default(),
             response_sndbuf: SendBuffer::default(),
             response_rcvbuf: RecvBuffer::default(),
             state: ArcPathState::new(dcid),
+            validation: Arc::default(),
+            probing: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -74,6 +90,16 @@ impl RawPath {
         self.response_sndbuf.write(frame.into());
     }
 
+    /// 收到包号最大的1RTT数据包时，更新该路径上发送的自旋位：服务端回显收到的自旋位，
+    /// 客户端则发送其反转值，见[RFC 9000 Section 17.4](https://www.rfc-editor.org/rfc/rfc9000.html#section-17.4)
+    pub fn on_rcvd_spin(&self, spin: SpinBit, role: Role) {
+        let spin = match role {
+            Role::Client => !spin,
+            Role::Server => spin,
+        };
+        self.spin.store(spin == SpinBit::One, Ordering::Relaxed);
+    }
+
     pub fn begin_validation(&self) {
         let anti_amplifier = self.anti_amplifier.clone();
         let challenge_sndbuf = self.challenge_sndbuf.clone();
@@ -81,6 +107,10 @@ impl RawPath {
         // THINK: 这里应该只需要一个ArcRtt，并不需congestion controller出面
         let congestion_ctrl = self.cc.clone();
         let state = self.state.clone();
+        let validation = self.validation.clone();
+        // 验证通过前，只发送探测帧
+        let probing = self.probing.clone();
+        probing.store(true, Ordering::Release);
         tokio::spawn(async move {
             let challenge = PathChallengeFrame::random();
             for _ in 0..3 {
@@ -89,32 +119,81 @@ impl RawPath {
                 match timeout(pto, response_rcvbuf.receive()).await {
                     Ok(Some(response)) if *response == *challenge => {
                         anti_amplifier.grant();
+                        probing.store(false, Ordering::Release);
+                        _ = validation.assign(true);
                         return;
                     }
                     // 外部发生变化，导致路径验证任务作废
-                    Ok(None) => return,
+                    Ok(None) => {
+                        _ = validation.assign(false);
+                        return;
+                    }
                     // 超时或者收到不对的response，按"停-等协议"，继续再发一次Challenge，最多3次
                     _ => continue,
                 }
             }
             anti_amplifier.abort();
             state.to_inactive();
+            _ = validation.assign(false);
         });
     }
 
+    /// 应用层按需发起的路径验证：发送新的PATH_CHALLENGE，收到匹配的PATH_RESPONSE后，返回往返时间
+    ///
+    /// 与[`RawPath::begin_validation`]一样，超时重发，最多3次；但不影响路径的状态，验证失败也不会使
+    /// 路径失活。路径正在进行首次验证时，直接返回[`PathError::Validating`]，以免两者争抢PATH_RESPONSE
+    pub async fn probe(&self) -> Result<Duration, PathError> {
+        if self.is_probing() {
+            return Err(PathError::Validating);
+        }
+        let challenge = PathChallengeFrame::random();
+        for _ in 0..3 {
+            let pto = self.cc.pto_time(Epoch::Data);
+            let sent_time = Instant::now();
+            self.challenge_sndbuf.write(challenge);
+            let deadline = sent_time + pto;
+            // 不匹配的response可能是此前的Challenge的，忽略之，直到超时
+            loop {
+                match tokio::time::timeout_at(deadline.into(), self.response_rcvbuf.receive()).await
+                {
+                    Ok(Some(response)) if *response == *challenge => return Ok(sent_time.elapsed()),
+                    Ok(Some(_)) => continue,
+                    Ok(None) => return Err(PathError::Inactivated),
+                    Err(_) => break,
+                }
+            }
+        }
+        Err(PathError::Unresponsive)
+    }
+
+    /// Wait for the result of the path validation started by [`RawPath::begin_validation`],
+    /// return whether the peer responded to the PATH_CHALLENGE.
+    pub async fn validated(&self) -> bool {
+        self.validation.get().await
+    }
+
+    /// Whether the path is being validated, only the probing frames are sent on it until the
+    /// validation succeeds.
+    pub fn is_probing(&self) -> bool {
+        self.probing.load(Ordering::Acquire)
+    }
+
     pub fn begin_sending<G>(&self, pathway: Pathway, flow_ctrl: &FlowController, gen_readers: G)
     where
         G: Fn(&RawPath) -> (InitialSpaceReader, HandshakeSpaceReader, DataSpaceReader),
     {
         let mut usc = self.usc.clone();
         let state = self.state.clone();
+        let cc = self.cc.clone();
         let space_readers = gen_readers(self);
         let read_into_datagram = ReadIntoDatagrams {
             scid: self.scid,
             dcid: self.dcid.clone(),
             cc: self.cc.clone(),
             anti_amplifier: self.anti_amplifier.clone(),
+            pmtud: self.pmtud.clone(),
             spin: self.spin.clone(),
+            probing: self.probing.clone(),
             send_flow_ctrl: flow_ctrl.sender(),
             initial_space_reader: space_readers.0,
             handshake_space_reader: space_readers.1,
@@ -124,12 +203,16 @@ impl RawPath {
         tokio::spawn(async move {
             let mut datagrams = Vec::with_capacity(4);
             loop {
+                // 本次装填的数据包，都按此ECN标记发送
+                let ecn = cc.ecn();
+                // 每次装填满一批，减少系统调用
+                let batch_size = usc.max_segments();
                 let io_vecs = tokio::select! {
                     _ = state.has_been_inactivated() => break,
-                    io_vecs = read_into_datagram.read(&mut datagrams) => io_vecs,
+                    io_vecs = read_into_datagram.read(&mut datagrams, batch_size) => io_vecs,
                 };
                 let Some(io_vecs) = io_vecs else { break };
-                let send_all = usc.send_all_via_pathway(&io_vecs, pathway);
+                let send_all = usc.send_all_via_pathway(&io_vecs, pathway, ecn);
                 if let Err(_udp_error) = send_all.await {
                     state.to_inactive();
                     break;
@@ -158,4 +241,41 @@ impl RawPath {
     pub fn update_recv_time(&self) {
         self.state.update_recv_time()
     }
+
+    /// The socket which the path sends packets through.
+    pub fn usc(&self) -> &Arc<dyn QuicIo> {
+        &self.usc
+    }
+
+    /// Stop sending packets on the path, and retire the connection ID it used.
+    pub fn inactivate(&self) {
+        self.state.to_inactive()
+    }
+
+    /// Wait until the path is inactivated.
+    pub async fn inactivated(&self) {
+        self.state.has_been_inactivated().await
+    }
+
+    /// The source and destination connection IDs of the packets sent on the path, [`None`] if the
+    /// destination connection ID is not available.
+    pub fn cids(&self) -> Option<(ConnectionId, ConnectionId)> {
+        Some((self.scid, self.dcid.try_get_cid()?))
+    }
+
+    /// Take a snapshot of the path's state.
+    pub fn info(&self, pathway: Pathway) -> PathInfo {
+        PathInfo {
+            pathway,
+            is_validated: self.anti_amplifier.is_granted(),
+            smoothed_rtt: self.cc.smoothed_rtt(),
+            min_rtt: self.cc.min_rtt(),
+            cwnd: self.cc.cwnd(),
+            pacing_rate: self.cc.pacing_rate(),
+            delivery_rate: self.cc.delivery_rate(),
+            bytes_in_flight: self.cc.bytes_in_flight(),
+            anti_amplification_credit: self.anti_amplifier.credit(),
+            max_datagram_size: self.pmtud.plpmtu(),
+        }
+    }
 }
@@ -10,13 +10,16 @@ use std::{
 use qbase::{
     cid::{ArcCidCell, ConnectionId},
     flow::ArcSendControler,
+    frame::SendFrame,
     packet::SpinBit,
+    streamid::Role,
 };
 use qcongestion::{ArcCC, CongestionControl, MSS};
 use qrecovery::{reliable::ArcReliableFrameDeque, space::Epoch};
 
 use super::{
     anti_amplifier::ANTI_FACTOR,
+    mtu::{ArcPmtud, MAX_DATAGRAM_SIZE},
     util::{ApplyConstraints, Constraints},
     ArcAntiAmplifier,
 };
@@ -28,8 +31,10 @@ pub struct ReadIntoDatagrams {
     pub(super) scid: ConnectionId,
     pub(super) dcid: ArcCidCell<ArcReliableFrameDeque>,
     pub(super) spin: Arc<AtomicBool>,
+    pub(super) probing: Arc<AtomicBool>,
     pub(super) cc: ArcCC,
     pub(super) anti_amplifier: ArcAntiAmplifier<ANTI_FACTOR>,
+    pub(super) pmtud: ArcPmtud,
     pub(super) send_flow_ctrl: ArcSendControler,
     pub(super) initial_space_reader: InitialSpaceReader,
     pub(super) handshake_space_reader: HandshakeSpaceReader,
@@ -48,10 +53,11 @@ impl ReadIntoDatagrams {
         let send_quota = buffer.len();
 
         let ack_pkt = self.cc.need_ack(Epoch::Initial);
+        let probe = self.cc.need_probe(Epoch::Initial);
         // 按顺序发，先发Initial空间的，到Initial数据包
         if let Some((padding, len, is_just_ack)) = self
             .initial_space_reader
-            .try_read(buffer, self.scid, dcid, ack_pkt)
+            .try_read(buffer, self.scid, dcid, ack_pkt, probe)
         {
             // 若真的只包含ack， 后续只会追加padding，追加的padding也可以看成是新的InitialPacket数据包
             constraints.commit(len, is_just_ack);
@@ -61,9 +67,20 @@ impl ReadIntoDatagrams {
                 self.read_other_space(constraints, flow_limit, remain, dcid)
             };
 
-            let padding_len = if wrote == 0 { MSS.min(send_quota) } else { 0 };
+            // 客户端携带Initial包的数据报，以及服务端携带ack-eliciting Initial包的数据报，都须扩充至
+            // 至少1200字节。合并在其后的其他空间的数据包也算在内，只有不足的部分才需要填充到Initial包中，
+            // 为此要将其后的数据包整体后移
+            let min_size = if self.initial_space_reader.role == Role::Client || !is_just_ack {
+                MSS.min(send_quota)
+            } else {
+                0
+            };
+            let padding_len = min_size.saturating_sub(len + wrote);
+            if padding_len > 0 && wrote > 0 {
+                buffer.copy_within(len..len + wrote, len + padding_len);
+            }
             let (pn, is_ack_eliciting, is_just_ack, sent_bytes, in_flight, sent_ack) =
-                padding(buffer, padding_len);
+                padding(buffer, len + padding_len);
             self.cc.on_pkt_sent(
                 Epoch::Initial,
                 pn,
@@ -129,7 +146,12 @@ impl ReadIntoDatagrams {
 
         // 最后尝试写1rtt数据包
         if let Some(keys) = one_rtt_keys {
+            // 拥塞窗口变大后，请求对端降低确认频率，ACK_FREQUENCY帧作为可靠帧发送，丢了会重传
+            if let Some(frame) = self.cc.need_ack_frequency() {
+                self.data_space_reader.reliable_frames.send_frame([frame]);
+            }
             let ack_pkt = self.cc.need_ack(Epoch::Data);
+            let probe = self.cc.need_probe(Epoch::Data);
             let spin = self.spin.load(Ordering::Relaxed);
             let spin = SpinBit::from(spin);
             if let Some((
@@ -142,7 +164,7 @@ impl ReadIntoDatagrams {
                 sent_ack,
             )) = self
                 .data_space_reader
-                .try_read_1rtt(buffer, flow_limit, dcid, spin, ack_pkt, keys)
+                .try_read_1rtt(buffer, flow_limit, dcid, spin, ack_pkt, probe, keys)
             {
                 self.cc.on_pkt_sent(
                     Epoch::Data,
@@ -169,9 +191,10 @@ impl ReadIntoDatagrams {
     ) -> usize {
         // 再尝试写handshake空间的
         let ack_pkt = self.cc.need_ack(Epoch::Handshake);
+        let probe = self.cc.need_probe(Epoch::Handshake);
         if let Some((pn, is_ack_eliciting, is_just_ack, sent_bytes, in_flight, sent_ack)) = self
             .handshake_space_reader
-            .try_read(buffer, self.scid, dcid, ack_pkt)
+            .try_read(buffer, self.scid, dcid, ack_pkt, probe)
         {
             self.cc.on_pkt_sent(
                 Epoch::Handshake,
@@ -187,11 +210,60 @@ impl ReadIntoDatagrams {
         0
     }
 
+    /// Send a PMTU probe alone, once the path has been validated and the 1-RTT keys are ready.
+    fn read_mtu_probe(
+        &self,
+        constraints: &Constraints,
+        datagram: &mut [u8],
+        dcid: ConnectionId,
+    ) -> Option<usize> {
+        if !self.anti_amplifier.is_granted() {
+            return None;
+        }
+        let keys = self.data_space_reader.one_rtt_keys()?;
+        let size = self.pmtud.next_probe_size()?;
+        // 探测包不可被截短，拥塞控制不允许时，下次再探测
+        let buffer = (&mut datagram[..size]).apply(constraints);
+        if buffer.len() < size {
+            return None;
+        }
+        let spin = SpinBit::from(self.spin.load(Ordering::Relaxed));
+        let (pn, sent_bytes) = self
+            .data_space_reader
+            .try_read_mtu_probe(buffer, dcid, spin, keys)?;
+        self.cc.on_mtu_probe_sent(pn, sent_bytes);
+        self.pmtud.on_probe_sent(pn, sent_bytes);
+        Some(sent_bytes)
+    }
+
+    /// Send a probing packet alone while the path is being validated, the non-probing frames are
+    /// not sent until the peer's address is validated.
+    fn read_probing(
+        &self,
+        constraints: &Constraints,
+        datagram: &mut [u8],
+        dcid: ConnectionId,
+    ) -> Option<usize> {
+        let keys = self.data_space_reader.one_rtt_keys()?;
+        let buffer = datagram.apply(constraints);
+        let spin = SpinBit::from(self.spin.load(Ordering::Relaxed));
+        let (pn, sent_bytes) = self
+            .data_space_reader
+            .try_read_probing(buffer, dcid, spin, keys)?;
+        self.cc
+            .on_pkt_sent(Epoch::Data, pn, true, sent_bytes, true, None);
+        Some(sent_bytes)
+    }
+
     fn poll_read_inner(
         &self,
         cx: &mut Context<'_>,
-        buffers: &mut Vec<[u8; MSS]>,
-    ) -> Poll<Option<(usize, usize)>> {
+        buffers: &mut Vec<[u8; MAX_DATAGRAM_SIZE]>,
+        max_datagrams: usize,
+    ) -> Poll<Option<(usize, usize, usize)>> {
+        for (pn, is_acked) in self.cc.take_mtu_probe_outcomes() {
+            self.pmtud.on_probe_outcome(pn, is_acked);
+        }
         let send_quota = core::task::ready!(self.cc.poll_send(cx));
         let Some(dcid) = core::task::ready!(self.dcid.poll_get_cid(cx)) else {
             return Poll::Ready(None);
@@ -210,6 +282,24 @@ impl ReadIntoDatagrams {
         let flow_limit = send_flow_credit.available();
         let mut constraints = Constraints::new(credit_limit, send_quota);
 
+        if buffers.is_empty() {
+            buffers.push([0; MAX_DATAGRAM_SIZE]);
+        }
+        if self.probing.load(Ordering::Acquire) {
+            let datagram = &mut buffers[0][..self.pmtud.plpmtu()];
+            let Some(sent_bytes) = self.read_probing(&constraints, datagram, dcid) else {
+                return Poll::Pending;
+            };
+            self.anti_amplifier.on_sent(sent_bytes);
+            return Poll::Ready(Some((1, sent_bytes, sent_bytes)));
+        }
+        if let Some(sent_bytes) = self.read_mtu_probe(&constraints, &mut buffers[0], dcid) {
+            self.anti_amplifier.on_sent(sent_bytes);
+            return Poll::Ready(Some((1, sent_bytes, sent_bytes)));
+        }
+        // 已确认的路径MTU，除了最后一个，每个数据报都是这么大
+        let mtu = self.pmtud.plpmtu();
+
         // 遍历，填充每一个包
 
         let mut total_bytes = 0;
@@ -217,13 +307,16 @@ impl ReadIntoDatagrams {
 
         let mut buffers_used = 0;
         let mut last_buffer_written = 0;
+        // 拥塞控制仍允许发送，却已无数据可发，或新数据受限于各路径共享的连接级流量控制
+        let mut app_limited = false;
 
-        while constraints.is_available() {
+        // 至多装填一批，由一次系统调用发出，剩余的下次再装填
+        while constraints.is_available() && buffers_used < max_datagrams.max(1) {
             let datagram = match buffers.get_mut(buffers_used) {
-                Some(buffer) => buffer,
+                Some(buffer) => &mut buffer[..mtu],
                 None => {
-                    buffers.push([0; MSS]);
-                    &mut buffers[buffers_used]
+                    buffers.push([0; MAX_DATAGRAM_SIZE]);
+                    &mut buffers[buffers_used][..mtu]
                 }
             };
 
@@ -233,6 +326,7 @@ impl ReadIntoDatagrams {
             // TODO: 若因没有数据可发，将waker挂载到数据控制器上一份，包括帧数据、流数据，
             //       一旦有任何数据发送，唤醒该任务发一次
             if datagram_size == 0 {
+                app_limited = true;
                 break;
             }
             total_bytes += datagram_size;
@@ -244,20 +338,22 @@ impl ReadIntoDatagrams {
             match remaining.len() {
                 0 => continue,
                 // 如果数据报没有没填满，需要填充padding帧，否则datagram会被之前的数据污染
-                len if len == MSS - datagram_size => {
+                len if len == mtu - datagram_size => {
                     /* use qbase::frame::io::WriteFrame;
                     use qbase::frame::PaddingFrame;
                     for _ in 0..remaining.remaining_mut() {
                         remaining.put_frame(&PaddingFrame);
                     } */
                     remaining.fill(0);
-                    constraints.commit(MSS - datagram_size, false);
+                    constraints.commit(mtu - datagram_size, false);
                 }
                 // 如果拥塞控制，抗放大限制不允许填充帧，那本次装填就此结结束
                 _ => break,
             }
         }
 
+        // 拥塞窗口未被用满时，不据此增长拥塞窗口，也不据此估计带宽
+        self.cc.on_app_limited(app_limited);
         if buffers_used == 0 {
             // 就算Constraints允许发送，但也不一定真的有数据供发送
             return Poll::Pending;
@@ -267,16 +363,22 @@ impl ReadIntoDatagrams {
         self.anti_amplifier.on_sent(total_bytes);
         send_flow_credit.post_sent(total_fresh_bytes);
         // 返回这个后，datagrams肯定等着被发送了
-        Poll::Ready(Some((buffers_used, last_buffer_written)))
+        Poll::Ready(Some((buffers_used, mtu, last_buffer_written)))
     }
 
-    pub async fn read<'ds>(&self, buffers: &'ds mut Vec<[u8; MSS]>) -> Option<Vec<IoSlice<'ds>>> {
-        let (buffers_used, last_buffer_written) =
-            core::future::poll_fn(|cx| self.poll_read_inner(cx, buffers)).await?;
+    /// Fills up to `max_datagrams` datagrams, a batch sent by a syscall, as many as the
+    /// constraints allow, returns [`None`] once the path or the connection is closed.
+    pub async fn read<'ds>(
+        &self,
+        buffers: &'ds mut Vec<[u8; MAX_DATAGRAM_SIZE]>,
+        max_datagrams: usize,
+    ) -> Option<Vec<IoSlice<'ds>>> {
+        let (buffers_used, mtu, last_buffer_written) =
+            core::future::poll_fn(|cx| self.poll_read_inner(cx, buffers, max_datagrams)).await?;
 
         debug_assert!(buffers_used > 0);
         let datagrams = (0..buffers_used - 1)
-            .map(|i| IoSlice::new(&buffers[i]))
+            .map(|i| IoSlice::new(&buffers[i][..mtu]))
             .chain(Some(IoSlice::new(
                 &buffers[buffers_used - 1][..last_buffer_written],
             )))
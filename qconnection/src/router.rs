@@ -1,18 +1,31 @@
-use std::sync::LazyLock;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, LazyLock},
+};
 
+use crate::{connection::PacketEntry, path::pathway::Pathway, usc::QuicIo};
 use dashmap::DashMap;
 use qbase::{
     cid::{ConnectionId, GenUniqueCid},
     error::Error,
-    frame::{NewConnectionIdFrame, ReceiveFrame, RetireConnectionIdFrame, SendFrame},
+    frame::{EcnCodepoint, NewConnectionIdFrame, ReceiveFrame, RetireConnectionIdFrame, SendFrame},
     packet::{header::GetDcid, long, DataHeader, DataPacket},
 };
-use qudp::ArcUsc;
-
-use crate::{connection::PacketEntry, path::pathway::Pathway};
 
 /// Global Router for managing connections.
 static ROUTER: LazyLock<DashMap<ConnectionId, [PacketEntry; 4]>> = LazyLock::new(DashMap::new);
+/// The connections using zero-length connection IDs, which are routed by the 4-tuple, namely the
+/// local address of the socket and the remote address.
+static PATHWAY_ROUTER: LazyLock<DashMap<(SocketAddr, SocketAddr), [PacketEntry; 4]>> =
+    LazyLock::new(DashMap::new);
+
+/// The key of the connection routed by the 4-tuple, the IPv4-mapped remote address received by
+/// the dual-stack socket is the same as the IPv4 one.
+fn pathway_key(usc: &Arc<dyn QuicIo>, pathway: &Pathway) -> (SocketAddr, SocketAddr) {
+    let remote = pathway.remote_addr();
+    let remote = SocketAddr::new(remote.ip().to_canonical(), remote.port());
+    (usc.local_addr(), remote)
+}
 
 #[derive(Clone, Debug)]
 pub struct Router;
@@ -21,22 +34,61 @@ impl Router {
     pub fn try_to_route_packet_from(
         packet: DataPacket,
         pathway: Pathway,
-        usc: &ArcUsc,
+        ecn: Option<EcnCodepoint>,
+        usc: &Arc<dyn QuicIo>,
     ) -> Result<(), DataPacket> {
-        let dcid = packet.header.get_dcid();
-        let Some(entries) = ROUTER.get(dcid) else {
-            return Err(packet);
-        };
         let index = match packet.header {
             DataHeader::Long(long::DataHeader::Initial(_)) => 0,
             DataHeader::Long(long::DataHeader::ZeroRtt(_)) => 1,
             DataHeader::Long(long::DataHeader::Handshake(_)) => 2,
             DataHeader::Short(_) => 3,
         };
-        _ = entries[index].unbounded_send((packet, pathway, usc.clone()));
+        let dcid = packet.header.get_dcid();
+        if dcid.is_empty() {
+            let Some(entries) = PATHWAY_ROUTER.get(&pathway_key(usc, &pathway)) else {
+                return Err(packet);
+            };
+            _ = entries[index].unbounded_send((packet, pathway, ecn, usc.clone()));
+            return Ok(());
+        }
+        let Some(entries) = ROUTER.get(dcid) else {
+            return Err(packet);
+        };
+        _ = entries[index].unbounded_send((packet, pathway, ecn, usc.clone()));
         Ok(())
     }
 
+    /// Route the packets received by `usc` from the remote address of `pathway`, for the
+    /// connection using zero-length connection IDs.
+    pub fn route_pathway(
+        usc: &Arc<dyn QuicIo>,
+        pathway: &Pathway,
+        packet_entries: [PacketEntry; 4],
+    ) {
+        PATHWAY_ROUTER.insert(pathway_key(usc, pathway), packet_entries);
+    }
+
+    /// Stop routing the packets by the 4-tuple, if they are still routed to `packet_entries`.
+    pub fn unroute_pathway(
+        usc: &Arc<dyn QuicIo>,
+        pathway: &Pathway,
+        packet_entries: &[PacketEntry; 4],
+    ) {
+        PATHWAY_ROUTER.remove_if(&pathway_key(usc, pathway), |_, entries| {
+            entries[0].same_receiver(&packet_entries[0])
+        });
+    }
+
+    /// Stop routing all the packets received by the exclusive `usc` by the 4-tuple, once the
+    /// connection owning it is dropped, which may be not closed and has not unrouted its paths.
+    pub fn unroute_io(usc: &Arc<dyn QuicIo>) {
+        let local = usc.local_addr();
+        PATHWAY_ROUTER.retain(|(addr, _), _| *addr != local);
+    }
+
+    /// Register the packet entries of the connection whose initial source connection ID is `scid`,
+    /// the zero-length one is not registered, since such connection is routed by
+    /// [`Router::route_pathway`] instead.
     pub fn registry<ISSUED>(
         scid: ConnectionId,
         issued_cids: ISSUED,
@@ -45,7 +97,9 @@ impl Router {
     where
         ISSUED: SendFrame<NewConnectionIdFrame>,
     {
-        ROUTER.insert(scid, packet_entries.clone());
+        if !scid.is_empty() {
+            ROUTER.insert(scid, packet_entries.clone());
+        }
         RouterRegistry {
             issued_cids,
             packet_entries,
@@ -1,4 +1,5 @@
 use core::{
+    fmt,
     ops::DerefMut,
     task::{Context, Poll, Waker},
 };
@@ -11,13 +12,18 @@ use qbase::{
         Parameters,
     },
     error::{Error, ErrorKind},
-    packet::keys::{ArcKeys, ArcOneRttKeys},
+    packet::{
+        keys::{ArcKeys, ArcOneRttKeys},
+        QUIC_V2,
+    },
 };
 use qrecovery::{crypto::CryptoStream, space::Epoch};
 use rustls::{
+    client::Resumption,
     crypto::CryptoProvider,
+    pki_types::{CertificateDer, EchConfigListBytes, ServerName},
     quic::{KeyChange, Keys},
-    Side,
+    PeerIncompatible, Side,
 };
 use thiserror::Error;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -27,27 +33,177 @@ use crate::{
     error::ConnError,
 };
 
+mod aead;
+pub mod ocsp;
+pub mod retry;
+pub mod rpk;
+pub mod session;
+pub mod session_store;
+pub mod sni;
+pub mod ticketer;
+
+pub use ocsp::OcspStapler;
+pub use retry::AddressValidator;
+pub use rpk::RawPublicKeyVerifier;
+pub use session::Session;
+pub use session_store::{ClientSessionStore, MemorySessionStore};
+pub use ticketer::{RotatingTicketer, TicketKey};
+
 #[derive(Debug, Error, Clone, Copy)]
 #[error("TLS session is aborted")]
 pub struct Aborted;
 
-type TlsConnection = rustls::quic::Connection;
+#[cfg(not(any(feature = "ring", feature = "aws-lc-rs")))]
+compile_error!("No crypto provider is enabled, enable feature `ring`, `aws-lc-rs` or `fips`");
+
+/// The crypto provider used when it's not specified explicitly.
+///
+/// The process-level default provider is preferred if installed, otherwise it's determined by
+/// the enabled features: `fips` takes precedence over `aws-lc-rs`, which takes precedence over
+/// `ring`. The FIPS provider only contains the FIPS-approved algorithms.
+pub fn default_provider() -> Arc<CryptoProvider> {
+    if let Some(provider) = CryptoProvider::get_default() {
+        return provider.clone();
+    }
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "fips")] {
+            Arc::new(rustls::crypto::default_fips_provider())
+        } else if #[cfg(feature = "aws-lc-rs")] {
+            Arc::new(rustls::crypto::aws_lc_rs::default_provider())
+        } else {
+            Arc::new(rustls::crypto::ring::default_provider())
+        }
+    }
+}
+
+/// A hook to receive the TLS secrets of each connection.
+///
+/// Unlike the [`rustls::KeyLog`] set in the TLS config, which is shared by all connections, the
+/// secrets delivered to [`KeyLog::log`] are tagged with the initial source connection ID of local,
+/// so that the packet captures of specific connections can be decrypted.
+///
+/// It is implemented for closures with the same signature as [`KeyLog::log`].
+pub trait KeyLog: Send + Sync {
+    /// Log the given `secret`, the parameters are the same as [`rustls::KeyLog::log`].
+    fn log(&self, scid: &ConnectionId, label: &str, client_random: &[u8], secret: &[u8]);
+}
+
+impl<F> KeyLog for F
+where
+    F: Fn(&ConnectionId, &str, &[u8], &[u8]) + Send + Sync,
+{
+    fn log(&self, scid: &ConnectionId, label: &str, client_random: &[u8], secret: &[u8]) {
+        (self)(scid, label, client_random, secret)
+    }
+}
+
+/// Adapt the [`KeyLog`] of a connection to [`rustls::KeyLog`].
+struct ConnKeyLog {
+    scid: ConnectionId,
+    key_log: Arc<dyn KeyLog>,
+}
+
+impl fmt::Debug for ConnKeyLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnKeyLog")
+            .field("scid", &self.scid)
+            .finish()
+    }
+}
+
+impl rustls::KeyLog for ConnKeyLog {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        self.key_log.log(&self.scid, label, client_random, secret)
+    }
+}
 
 #[derive(Debug)]
 struct RawTlsSession {
-    tls_conn: TlsConnection,
+    tls_conn: Box<dyn Session>,
     read_waker: Option<Waker>,
     /// Optimize: avoid reading transport parameters repeatedly, because the rustls willnot consume
     /// the bytes of transport parameters after reading them.
     params_read: bool,
+    /// Client only, to remember the transport parameters of the server for resumption.
+    session_store: Option<(Arc<dyn ClientSessionStore>, ServerName<'static>)>,
+    /// Whether the 0-RTT keys have been taken, they are derived once for a session.
+    zero_rtt_keys_taken: bool,
+    /// Client only, the ECH retry configs of the server, remembered if the server rejects ECH.
+    ech_retry: EchRetryScanner,
+}
+
+/// Picks the ECH retry configs out of the EncryptedExtensions sent by the server.
+///
+/// The handshake messages are carried in plaintext by the CRYPTO frames in QUIC, so the configs
+/// are parsed from the bytes directly, instead of encoding the ones in the rustls error again.
+#[derive(Debug, Default)]
+struct EchRetryScanner {
+    /// The bytes of the incomplete handshake message.
+    pending: Vec<u8>,
+    /// Whether the EncryptedExtensions has been parsed.
+    done: bool,
+    retry_configs: Option<EchConfigListBytes<'static>>,
 }
 
-impl From<TlsConnection> for RawTlsSession {
-    fn from(tls_conn: TlsConnection) -> Self {
+impl EchRetryScanner {
+    const ENCRYPTED_EXTENSIONS: u8 = 8;
+    const ENCRYPTED_CLIENT_HELLO: u16 = 0xfe0d;
+
+    fn feed(&mut self, data: &[u8]) {
+        if self.done {
+            return;
+        }
+        self.pending.extend_from_slice(data);
+        // 逐个跳过EncryptedExtensions之前的握手消息，消息头为1字节类型和3字节长度
+        while let [msg_type, l0, l1, l2, ..] = self.pending[..] {
+            let end = 4 + u32::from_be_bytes([0, l0, l1, l2]) as usize;
+            if self.pending.len() < end {
+                return;
+            }
+            if msg_type == Self::ENCRYPTED_EXTENSIONS {
+                self.retry_configs = Self::parse_retry_configs(&self.pending[4..end]);
+                self.done = true;
+                self.pending = Vec::new();
+                return;
+            }
+            self.pending.drain(..end);
+        }
+    }
+
+    fn parse_retry_configs(mut body: &[u8]) -> Option<EchConfigListBytes<'static>> {
+        fn take<'b>(buf: &mut &'b [u8], n: usize) -> Option<&'b [u8]> {
+            let (taken, rest) = buf.split_at_checked(n)?;
+            *buf = rest;
+            Some(taken)
+        }
+        fn take_u16(buf: &mut &[u8]) -> Option<u16> {
+            take(buf, 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+        }
+
+        let len = take_u16(&mut body)? as usize;
+        let mut extensions = take(&mut body, len)?;
+        while !extensions.is_empty() {
+            let ext_type = take_u16(&mut extensions)?;
+            let len = take_u16(&mut extensions)? as usize;
+            let ext_data = take(&mut extensions, len)?;
+            // 服务端的ECH扩展即ECHConfigList格式的retry_configs
+            if ext_type == Self::ENCRYPTED_CLIENT_HELLO {
+                return Some(EchConfigListBytes::from(ext_data.to_vec()));
+            }
+        }
+        None
+    }
+}
+
+impl From<Box<dyn Session>> for RawTlsSession {
+    fn from(tls_conn: Box<dyn Session>) -> Self {
         Self {
             tls_conn,
             read_waker: None,
             params_read: false,
+            session_store: None,
+            zero_rtt_keys_taken: false,
+            ech_retry: EchRetryScanner::default(),
         }
     }
 }
@@ -60,13 +216,42 @@ impl RawTlsSession {
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<(), rustls::Error> {
-        self.tls_conn.read_hs(buf)
+        if self.session_store.is_some() {
+            self.ech_retry.feed(buf);
+        }
+        let result = self.tls_conn.read_hs(buf);
+        // The server rejected ECH, remember the retry configs for the next connection
+        if let Err(rustls::Error::PeerIncompatible(
+            PeerIncompatible::ServerRejectedEncryptedClientHello(Some(_)),
+        )) = &result
+        {
+            if let (Some((store, server_name)), Some(retry_configs)) =
+                (&self.session_store, self.ech_retry.retry_configs.take())
+            {
+                store.set_ech_retry_configs(server_name.clone(), retry_configs);
+            }
+        }
+        result
     }
 
     fn read(&mut self, buf: &mut Vec<u8>) -> Option<KeyChange> {
         self.tls_conn.write_hs(buf)
     }
 
+    fn take_zero_rtt_keys(&mut self) -> Option<Keys> {
+        if self.zero_rtt_keys_taken {
+            return None;
+        }
+        // Only one direction is used: the client sends and the server receives 0-RTT packets,
+        // so the same keys are used as both the local and remote keys.
+        let keys = Keys {
+            local: self.tls_conn.zero_rtt_keys()?,
+            remote: self.tls_conn.zero_rtt_keys()?,
+        };
+        self.zero_rtt_keys_taken = true;
+        Some(keys)
+    }
+
     fn get_transport_parameters(&mut self) -> Option<Result<Parameters, Error>> {
         if self.params_read {
             return None;
@@ -88,6 +273,9 @@ impl RawTlsSession {
             )));
         }
         self.params_read = true;
+        if let Some((store, server_name)) = &self.session_store {
+            store.set_remembered_parameters(server_name.clone(), params.clone());
+        }
         Some(Ok(params))
     }
 
@@ -99,14 +287,37 @@ impl RawTlsSession {
         self.tls_conn.is_handshaking()
     }
 
+    fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        self.tls_conn.peer_certificates()
+    }
+
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.tls_conn.alpn_protocol()
+    }
+
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: &[u8],
+    ) -> Result<(), rustls::Error> {
+        self.tls_conn.export_keying_material(output, label, context)
+    }
+
     fn server_name(&self) -> Option<&str> {
-        match &self.tls_conn {
-            TlsConnection::Server(server_conn) => server_conn.server_name(),
-            TlsConnection::Client(_) => None,
-        }
+        self.tls_conn.server_name()
     }
 }
 
+/// The key change, the 0-RTT keys, the transport parameters of the peer, and whether the session is
+/// still handshaking.
+type ReadTlsOutput = (
+    Option<KeyChange>,
+    Option<Keys>,
+    Option<Result<Parameters, Error>>,
+    bool,
+);
+
 struct ReadTls<'r> {
     tls_conn: &'r Mutex<Result<RawTlsSession, Aborted>>,
     buffer: &'r mut Vec<u8>,
@@ -114,7 +325,7 @@ struct ReadTls<'r> {
 }
 
 impl futures::Future for ReadTls<'_> {
-    type Output = Result<(Option<KeyChange>, Option<Result<Parameters, Error>>, bool), Aborted>;
+    type Output = Result<ReadTlsOutput, Aborted>;
 
     fn poll(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
@@ -136,47 +347,121 @@ impl futures::Future for ReadTls<'_> {
             None
         };
 
+        let zero_rtt_keys = tls_conn.take_zero_rtt_keys();
         let is_handshaking = tls_conn.is_handshaking();
 
-        Poll::Ready(Ok((key_change, remote_params, is_handshaking)))
+        Poll::Ready(Ok((
+            key_change,
+            zero_rtt_keys,
+            remote_params,
+            is_handshaking,
+        )))
     }
 }
 
+/// The TLS 1.3 session of a QUIC connection, backed by rustls by default, or any [`Session`]
+/// implementation.
+///
+/// The peer can be authenticated by X.509 certificates, or [RFC 7250] raw public keys(see
+/// [`rpk`]). External pre-shared keys(PSK-only handshakes) are not supported, because rustls only
+/// implements the PSKs for session resumption; for closed systems that want to skip certificates,
+/// raw public keys are the alternative.
+///
+/// [RFC 7250]: https://www.rfc-editor.org/rfc/rfc7250
 #[derive(Debug, Clone)]
 pub struct ArcTlsSession(Arc<Mutex<Result<RawTlsSession, Aborted>>>);
 
-impl ArcTlsSession {
-    const QUIC_VERSION: rustls::quic::Version = rustls::quic::Version::V1;
+/// The QUIC version used by rustls, which determines the initial salt and the key labels, the
+/// versions other than version 2 are treated as version 1.
+fn quic_version(version: u32) -> rustls::quic::Version {
+    match version {
+        QUIC_V2 => rustls::quic::Version::V2,
+        _ => rustls::quic::Version::V1,
+    }
+}
 
+impl ArcTlsSession {
+    /// Create a client TLS session in the QUIC `version`.
+    ///
+    /// If `key_log` is set, the secrets of this session will be delivered to it tagged with `scid`.
+    ///
+    /// If `session_store` is set, the session tickets and the transport parameters of the server
+    /// will be stored in it, and the stored ticket will be used to resume the handshake.
     pub fn new_client(
-        server_name: rustls::pki_types::ServerName<'static>,
-        tls_config: Arc<rustls::ClientConfig>,
+        version: u32,
+        server_name: ServerName<'static>,
+        mut tls_config: Arc<rustls::ClientConfig>,
         parameters: &Parameters,
+        scid: ConnectionId,
+        key_log: Option<Arc<dyn KeyLog>>,
+        session_store: Option<Arc<dyn ClientSessionStore>>,
     ) -> Self {
         let mut params_bytes = Vec::new();
         params_bytes.put_parameters(parameters);
 
+        if let Some(key_log) = key_log {
+            Arc::make_mut(&mut tls_config).key_log = Arc::new(ConnKeyLog { scid, key_log });
+        }
+        if let Some(store) = &session_store {
+            Arc::make_mut(&mut tls_config).resumption = Resumption::store(store.clone());
+        }
+
         let client_connection = rustls::quic::ClientConnection::new(
             tls_config,
-            Self::QUIC_VERSION,
-            server_name,
+            quic_version(version),
+            server_name.clone(),
             params_bytes,
         );
         let connection = rustls::quic::Connection::Client(client_connection.unwrap());
-        Self(Arc::new(Mutex::new(Ok(connection.into()))))
+        let mut raw_tls = RawTlsSession::from(Box::new(connection) as Box<dyn Session>);
+        raw_tls.session_store = session_store.map(|store| (store, server_name));
+        Self(Arc::new(Mutex::new(Ok(raw_tls))))
     }
 
-    pub fn new_server(tls_config: Arc<rustls::ServerConfig>, parameters: &Parameters) -> Self {
+    /// Create a server TLS session in the QUIC `version`.
+    ///
+    /// If `key_log` is set, the secrets of this session will be delivered to it tagged with `scid`.
+    pub fn new_server(
+        version: u32,
+        mut tls_config: Arc<rustls::ServerConfig>,
+        parameters: &Parameters,
+        scid: ConnectionId,
+        key_log: Option<Arc<dyn KeyLog>>,
+    ) -> Self {
         let mut params = Vec::new();
         params.put_parameters(parameters);
 
+        if let Some(key_log) = key_log {
+            Arc::make_mut(&mut tls_config).key_log = Arc::new(ConnKeyLog { scid, key_log });
+        }
+
         let server_connection =
-            rustls::quic::ServerConnection::new(tls_config, Self::QUIC_VERSION, params).unwrap();
+            rustls::quic::ServerConnection::new(tls_config, quic_version(version), params).unwrap();
         let connection = rustls::quic::Connection::Server(server_connection);
-        Self(Arc::new(Mutex::new(Ok(connection.into()))))
+        Self::with_session(connection)
     }
 
-    pub fn initial_keys(crypto_provider: &CryptoProvider, side: Side, cid: ConnectionId) -> Keys {
+    /// Create a TLS session backed by an alternative TLS library other than rustls.
+    ///
+    /// The `session` must be configured with the local transport parameters, and the QUIC version
+    /// of the connection.
+    pub fn with_session(session: impl Session + 'static) -> Self {
+        let session: Box<dyn Session> = Box::new(session);
+        Self(Arc::new(Mutex::new(Ok(session.into()))))
+    }
+
+    /// Derive the initial keys of the QUIC `version` from the `cid` with the given
+    /// `crypto_provider`.
+    ///
+    /// The initial packets are always protected by AES-128-GCM and SHA-256, both the ring and
+    /// aws-lc-rs(including FIPS) providers contain this cipher suite. The initial salt differs
+    /// between versions, see [RFC 9369](https://www.rfc-editor.org/rfc/rfc9369#section-3.3.1).
+    pub fn initial_keys(
+        crypto_provider: &CryptoProvider,
+        version: u32,
+        side: Side,
+        cid: ConnectionId,
+    ) -> Keys {
         let suite = crypto_provider
             .cipher_suites
             .iter()
@@ -187,8 +472,8 @@ impl ArcTlsSession {
                 _ => None,
             })
             .flatten()
-            .unwrap();
-        suite.keys(&cid, side, rustls::quic::Version::V1)
+            .expect("The crypto provider does not support TLS13_AES_128_GCM_SHA256");
+        suite.keys(&cid, side, quic_version(version))
     }
 
     pub fn abort(&self) {
@@ -210,17 +495,19 @@ impl ArcTlsSession {
         }
     }
 
-    /// 自托管密钥升级
+    /// 自托管密钥升级，对端的传输参数写入`remote_params`，服务端握手完成时，会调用一次`on_handshake_done`
+    #[allow(clippy::too_many_arguments)]
     pub fn keys_upgrade(
         &self,
+        remote_params: RemoteParameters,
         crypto_streams: [&CryptoStream; 3],
+        zero_rtt_keys: ArcKeys,
         handshake_keys: ArcKeys,
         one_rtt_keys: ArcOneRttKeys,
         conn_error: ConnError,
         handshake: Handshake,
-    ) -> RemoteParameters {
-        let remote_params = RemoteParameters::new();
-
+        on_handshake_done: impl FnOnce() + Send + 'static,
+    ) {
         let for_each_epoch = |epoch: Epoch| {
             let mut crypto_stream_reader = crypto_streams[epoch].reader();
             let tls_session = self.clone();
@@ -257,6 +544,7 @@ impl ArcTlsSession {
             let mut crypto_stream_writers =
                 Epoch::EPOCHS.map(|epoch| crypto_streams[epoch].writer());
             let crypto_stream_read_tasks = Epoch::EPOCHS.map(for_each_epoch);
+            let mut on_handshake_done = Some(on_handshake_done);
 
             async move {
                 let mut send_buf = Vec::with_capacity(1500);
@@ -264,11 +552,15 @@ impl ArcTlsSession {
                 loop {
                     let read_params = !remote_params.is_ready();
                     let read_result = tls_session.read(&mut send_buf, read_params).await;
-                    let (key_upgrade, params, is_handshaking) = match read_result {
+                    let (key_upgrade, zero_rtt, params, is_handshaking) = match read_result {
                         Ok(results) => results,
                         Err(_aborted) => break,
                     };
 
+                    if let Some(keys) = zero_rtt {
+                        zero_rtt_keys.set_keys(keys);
+                    }
+
                     if let Some(params) = params {
                         match params {
                             Ok(params) => remote_params.write(params.into()),
@@ -305,6 +597,9 @@ impl ArcTlsSession {
                     if !is_handshaking {
                         if let Handshake::Server(server_handshake) = &handshake {
                             server_handshake.done();
+                            if let Some(on_handshake_done) = on_handshake_done.take() {
+                                on_handshake_done();
+                            }
                         }
                     }
                 }
@@ -314,8 +609,63 @@ impl ArcTlsSession {
                 }
             }
         });
+    }
+
+    /// The transport parameters of the server remembered in the [`ClientSessionStore`] by the
+    /// previous connection, which can be used to send 0-RTT data.
+    ///
+    /// Return [`None`] if it's a server session, or no parameters are remembered.
+    pub fn remembered_parameters(&self) -> Option<Parameters> {
+        let guard = self.0.lock().unwrap();
+        let (store, server_name) = guard.as_ref().ok()?.session_store.as_ref()?;
+        store.remembered_parameters(server_name)
+    }
+
+    /// The certificate chain presented by the peer, the first one is the end-entity certificate.
+    ///
+    /// For the server, it's the authenticated client identity if the client certificate is
+    /// required, or [`None`] if the client does not present a certificate.
+    ///
+    /// Only available after the peer's certificate has been received and verified.
+    pub fn peer_certificates(&self) -> Option<Vec<CertificateDer<'static>>> {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .ok()
+            .and_then(RawTlsSession::peer_certificates)
+            .map(<[_]>::to_vec)
+    }
+
+    /// The application protocol negotiated by ALPN.
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .ok()
+            .and_then(RawTlsSession::alpn_protocol)
+            .map(<[_]>::to_vec)
+    }
 
-        remote_params
+    /// Derive `len` bytes of keying material from the exporter secret of the session with the
+    /// `label` and `context`, see [RFC 5705](https://www.rfc-editor.org/rfc/rfc5705) and
+    /// [Section 7.5](https://www.rfc-editor.org/rfc/rfc8446#section-7.5) of RFC 8446.
+    ///
+    /// Return an error if the handshake has not completed, or the session is aborted.
+    pub fn export_keying_material(
+        &self,
+        label: &[u8],
+        context: &[u8],
+        len: usize,
+    ) -> Result<Vec<u8>, rustls::Error> {
+        let guard = self.0.lock().unwrap();
+        let tls_session = guard
+            .as_ref()
+            .map_err(|aborted| rustls::Error::General(aborted.to_string()))?;
+        let mut output = vec![0u8; len];
+        tls_session.export_keying_material(&mut output, label, context)?;
+        Ok(output)
     }
 
     pub fn server_name(&self) -> Option<String> {
@@ -328,3 +678,133 @@ impl ArcTlsSession {
             .map(ToString::to_string)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use qbase::packet::{keys::ArcOneRttPacketKeys, KeyPhaseBit};
+    use rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+        quic::{ClientConnection, Connection, KeyChange, ServerConnection, Version},
+        version::TLS13,
+        ClientConfig, RootCertStore, ServerConfig,
+    };
+
+    use super::*;
+
+    // Complete a QUIC handshake in memory, and return the 1-RTT packet keys of both sides
+    fn handshake() -> (ArcOneRttPacketKeys, ArcOneRttPacketKeys) {
+        let cert = rcgen::generate_simple_self_signed(["localhost".to_owned()]).unwrap();
+        let cert_der = CertificateDer::from(cert.cert.der().to_vec());
+        let key_der = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()));
+        let mut roots = RootCertStore::empty();
+        roots.add(cert_der.clone()).unwrap();
+
+        let client_config = ClientConfig::builder_with_provider(default_provider())
+            .with_protocol_versions(&[&TLS13])
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_config = ServerConfig::builder_with_provider(default_provider())
+            .with_protocol_versions(&[&TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert_der], key_der)
+            .unwrap();
+        let server_name = "localhost".try_into().unwrap();
+        let client =
+            ClientConnection::new(Arc::new(client_config), Version::V1, server_name, vec![]);
+        let server = ServerConnection::new(Arc::new(server_config), Version::V1, vec![]);
+        let mut sessions: [Connection; 2] = [client.unwrap().into(), server.unwrap().into()];
+
+        let one_rtt_keys = [ArcOneRttKeys::new_pending(), ArcOneRttKeys::new_pending()];
+        let mut buf = Vec::new();
+        for round in 0..16 {
+            let (from, to) = (round % 2, 1 - round % 2);
+            buf.clear();
+            if let Some(KeyChange::OneRtt { keys, next }) = sessions[from].write_hs(&mut buf) {
+                one_rtt_keys[from].set_keys(keys, next);
+            }
+            if !buf.is_empty() {
+                sessions[to].read_hs(&buf).unwrap();
+            }
+        }
+        let [client, server] = one_rtt_keys.map(|keys| keys.get_local_keys().unwrap().1);
+        (client, server)
+    }
+
+    // Protect a packet by the local key of `from`, and return the key phase and the packet
+    fn seal(from: &ArcOneRttPacketKeys, pn: u64) -> (KeyPhaseBit, Vec<u8>) {
+        let (key_phase, key) = from.lock_guard().get_local(pn);
+        let mut payload = b"key update".to_vec();
+        let tag = key.encrypt_in_place(pn, &[], &mut payload).unwrap();
+        payload.extend_from_slice(tag.as_ref());
+        (key_phase, payload)
+    }
+
+    fn open(to: &ArcOneRttPacketKeys, pn: u64, (key_phase, mut packet): (KeyPhaseBit, Vec<u8>)) {
+        let key = to.lock_guard().get_remote(key_phase, pn);
+        assert!(key.decrypt_in_place(pn, &[], &mut packet).is_ok());
+        to.lock_guard()
+            .on_rcvd(key_phase, pn, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_key_update() {
+        let (client, server) = handshake();
+        open(&server, 0, seal(&client, 0));
+        let delayed = seal(&server, 0);
+
+        assert!(client.lock_guard().initiate_update());
+        // The key update has not been acknowledged
+        assert!(!client.lock_guard().initiate_update());
+        open(&server, 1, seal(&client, 1));
+        assert_eq!(server.lock_guard().key_phase(), KeyPhaseBit::One);
+
+        // The delayed packet is still decrypted by the keys of the previous phase
+        open(&client, 1, seal(&server, 1));
+        open(&client, 0, delayed);
+        assert_eq!(client.lock_guard().key_phase(), KeyPhaseBit::One);
+
+        client.lock_guard().on_pkt_acked(1);
+        assert!(client.lock_guard().initiate_update());
+        open(&server, 2, seal(&client, 2));
+        assert_eq!(server.lock_guard().key_phase(), KeyPhaseBit::Zero);
+
+        // A forged packet in the other phase does not update the keys
+        let (key_phase, mut packet) = seal(&client, 3);
+        packet[0] ^= 0xff;
+        let key = server.lock_guard().get_remote(!key_phase, 3);
+        assert!(key.decrypt_in_place(3, &[], &mut packet).is_err());
+        assert_eq!(server.lock_guard().key_phase(), KeyPhaseBit::Zero);
+    }
+
+    #[test]
+    fn test_ech_retry_scanner() {
+        let retry_configs = [0, 3, 0xaa, 0xbb, 0xcc];
+        let mut extensions = vec![0, 16, 0, 3, 2, b'h', b'3'];
+        extensions.extend_from_slice(&[0xfe, 0x0d, 0, retry_configs.len() as u8]);
+        extensions.extend_from_slice(&retry_configs);
+        let mut messages = vec![2, 0, 0, 3, 1, 2, 3];
+        messages.extend_from_slice(&[8, 0, 0, extensions.len() as u8 + 2, 0]);
+        messages.push(extensions.len() as u8);
+        messages.extend_from_slice(&extensions);
+
+        // 握手消息可能被拆分到多次写入中
+        let mut scanner = EchRetryScanner::default();
+        let (first, rest) = messages.split_at(9);
+        scanner.feed(first);
+        assert!(!scanner.done);
+        scanner.feed(rest);
+        assert!(scanner.done);
+        assert_eq!(scanner.retry_configs.as_deref(), Some(&retry_configs[..]));
+        assert!(scanner.pending.is_empty());
+
+        // 没有ECH扩展，即服务端没有提供retry_configs
+        let mut scanner = EchRetryScanner::default();
+        scanner.feed(&[8, 0, 0, 2, 0, 0]);
+        assert!(scanner.done);
+        assert!(scanner.retry_configs.is_none());
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+
+use rustls::{
+    crypto::{
+        cipher::{AeadKey, Iv, NONCE_LEN},
+        CryptoProvider, GetRandomFailed, SecureRandom,
+    },
+    quic::Algorithm,
+    CipherSuite,
+};
+
+/// An AES-256-GCM key of the crypto provider, to protect the tokens and the session tickets.
+///
+/// The sealed data is in format of `nonce || ciphertext`, the nonce is randomly generated by the
+/// provider for each seal. The AEAD of the TLS 1.3 cipher suite TLS13_AES_256_GCM_SHA384 is used,
+/// whose nonce is the IV when the packet number is 0.
+pub(crate) struct Aes256GcmKey {
+    key: [u8; 32],
+    algorithm: &'static dyn Algorithm,
+    secure_random: &'static dyn SecureRandom,
+}
+
+impl Aes256GcmKey {
+    /// Create the `key` of the `provider`, return an error if the provider does not support
+    /// TLS13_AES_256_GCM_SHA384.
+    pub fn new(provider: &CryptoProvider, key: [u8; 32]) -> Result<Self, rustls::Error> {
+        let algorithm = provider
+            .cipher_suites
+            .iter()
+            .find_map(|cs| match (cs.suite(), cs.tls13()) {
+                (CipherSuite::TLS13_AES_256_GCM_SHA384, Some(suite)) => suite.quic,
+                _ => None,
+            })
+            .ok_or_else(|| {
+                rustls::Error::General(
+                    "The crypto provider does not support TLS13_AES_256_GCM_SHA384".to_owned(),
+                )
+            })?;
+        Ok(Self {
+            key,
+            algorithm,
+            secure_random: provider.secure_random,
+        })
+    }
+
+    /// Fill `buf` with the random bytes from the provider.
+    pub fn fill_random(&self, buf: &mut [u8]) -> Result<(), GetRandomFailed> {
+        self.secure_random.fill(buf)
+    }
+
+    /// Encrypt the `plain` text, and authenticate it together with the `aad`.
+    pub fn seal(&self, aad: &[u8], plain: &[u8]) -> Option<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.fill_random(&mut nonce).ok()?;
+        let packet_key = self
+            .algorithm
+            .packet_key(AeadKey::from(self.key), Iv::from(nonce));
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + plain.len() + packet_key.tag_len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(plain);
+        let tag = packet_key
+            .encrypt_in_place(0, aad, &mut sealed[NONCE_LEN..])
+            .ok()?;
+        sealed.extend_from_slice(tag.as_ref());
+        Some(sealed)
+    }
+
+    /// Decrypt the `sealed` data, return the plain text if it's authentic with the `aad`.
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        let (nonce, ciphertext) = sealed.split_first_chunk::<NONCE_LEN>()?;
+        let packet_key = self
+            .algorithm
+            .packet_key(AeadKey::from(self.key), Iv::from(*nonce));
+
+        let mut plain = ciphertext.to_vec();
+        let len = packet_key.decrypt_in_place(0, aad, &mut plain).ok()?.len();
+        plain.truncate(len);
+        Some(plain)
+    }
+}
+
+impl fmt::Debug for Aes256GcmKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 不要泄露密钥
+        f.debug_struct("Aes256GcmKey").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::default_provider;
+
+    #[test]
+    fn test_seal_open() {
+        let key = Aes256GcmKey::new(&default_provider(), [1; 32]).unwrap();
+        let sealed = key.seal(b"aad", b"plain").unwrap();
+        assert_eq!(key.open(b"aad", &sealed).unwrap(), b"plain");
+        assert!(key.open(b"other", &sealed).is_none());
+        assert!(key.open(b"aad", &sealed[..NONCE_LEN]).is_none());
+    }
+
+    #[test]
+    fn test_unsupported_provider() {
+        // 不支持TLS13_AES_256_GCM_SHA384的密码库无法用于加密
+        let mut provider = CryptoProvider::clone(&default_provider());
+        provider
+            .cipher_suites
+            .retain(|cs| cs.suite() != CipherSuite::TLS13_AES_256_GCM_SHA384);
+        assert!(Aes256GcmKey::new(&provider, [1; 32]).is_err());
+    }
+}
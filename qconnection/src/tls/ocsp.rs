@@ -0,0 +1,72 @@
+use std::sync::{Arc, RwLock};
+
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+
+/// A certificate resolver whose stapled OCSP response can be replaced at any time.
+///
+/// OCSP responses expire, typically in a few days, so the server should fetch a fresh one from the
+/// responder and [`staple`] it before the previous expires. Handshakes started after stapling will
+/// carry the new response, so that clients enforcing revocation checks can validate the certificate
+/// without contacting the responder themselves.
+///
+/// [`staple`]: OcspStapler::staple
+#[derive(Debug)]
+pub struct OcspStapler {
+    certified_key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl OcspStapler {
+    pub fn new(certified_key: CertifiedKey) -> Self {
+        Self {
+            certified_key: RwLock::new(Arc::new(certified_key)),
+        }
+    }
+
+    /// Replace the stapled OCSP response with `ocsp`, an empty response means not stapling.
+    pub fn staple(&self, ocsp: Vec<u8>) {
+        let mut certified_key = self.certified_key.write().unwrap();
+        let mut new_key = CertifiedKey::clone(&certified_key);
+        new_key.ocsp = (!ocsp.is_empty()).then_some(ocsp);
+        *certified_key = Arc::new(new_key);
+    }
+
+    /// The OCSP response currently stapled.
+    pub fn ocsp(&self) -> Option<Vec<u8>> {
+        self.certified_key.read().unwrap().ocsp.clone()
+    }
+}
+
+impl ResolvesServerCert for OcspStapler {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.certified_key.read().unwrap().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    use super::*;
+
+    #[test]
+    fn test_staple() {
+        let cert = rcgen::generate_simple_self_signed(["localhost".to_owned()]).unwrap();
+        let key_der = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()));
+        let signing_key = crate::tls::default_provider()
+            .key_provider
+            .load_private_key(key_der)
+            .unwrap();
+        let cert_chain = vec![CertificateDer::from(cert.cert.der().to_vec())];
+        let stapler = OcspStapler::new(CertifiedKey::new(cert_chain, signing_key));
+        assert_eq!(stapler.ocsp(), None);
+
+        stapler.staple(vec![1, 2, 3]);
+        assert_eq!(stapler.ocsp(), Some(vec![1, 2, 3]));
+
+        stapler.staple(Vec::new());
+        assert_eq!(stapler.ocsp(), None);
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use qbase::{
+    cid::{ConnectionId, MAX_CID_SIZE},
+    packet::{header::long::io::WriteLongHeader, long::Retry, RetryHeader},
+    token::TokenProvider,
+};
+
+use super::{aead::Aes256GcmKey, default_provider};
+
+/// Compute the Retry Integrity Tag of the `retry` packet to be sent, which responds to the client's
+/// Initial packet whose Destination Connection ID is `odcid`, see
+/// [Section 5.8](https://www.rfc-editor.org/rfc/rfc9001#section-5.8) of RFC 9001.
+///
+/// Return [`None`] if the version of the `retry` packet is not supported. The integrity field of
+/// the `retry` packet is ignored.
+pub fn integrity_tag(odcid: &ConnectionId, retry: &RetryHeader) -> Option<[u8; 16]> {
+    let mut packet = Vec::with_capacity(64 + retry.token.len());
+    packet.put_long_header(retry);
+    packet.truncate(packet.len() - retry.integrity.len());
+    compute_tag(retry.version, odcid, &packet)
+}
+
+/// Whether the integrity tag of the received Retry packet is valid, the client should discard the
+/// packet otherwise.
+///
+/// The tag is verified against the received `packet` bytes rather than the parsed `retry` header,
+/// because the unused bits of the first byte are protected too.
+pub fn verify_integrity(odcid: &ConnectionId, retry: &RetryHeader, packet: &[u8]) -> bool {
+    let Some(without_tag) = packet.len().checked_sub(retry.integrity.len()) else {
+        return false;
+    };
+    compute_tag(retry.version, odcid, &packet[..without_tag])
+        .is_some_and(|tag| tag == packet[without_tag..])
+}
+
+fn compute_tag(version: u32, odcid: &ConnectionId, retry_packet: &[u8]) -> Option<[u8; 16]> {
+    let (key, nonce) = Retry::integrity_key(version)?;
+    // The Retry Pseudo-Packet: ODCID Length || ODCID || the Retry packet without the tag
+    let mut pseudo_packet = Vec::with_capacity(1 + odcid.len() + retry_packet.len());
+    pseudo_packet.push(odcid.len() as u8);
+    pseudo_packet.extend_from_slice(odcid);
+    pseudo_packet.extend_from_slice(retry_packet);
+
+    seal_tag(key, nonce, &pseudo_packet)
+}
+
+/// The AES-128-GCM tag of the empty plain text with the `aad`.
+///
+/// The 128-bit key can't be passed to the AEAD of the rustls [`CryptoProvider`], so the crypto
+/// library of the enabled provider feature computes it directly.
+///
+/// [`CryptoProvider`]: rustls::crypto::CryptoProvider
+fn seal_tag(key: &[u8; 16], nonce: &[u8; 12], aad: &[u8]) -> Option<[u8; 16]> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "aws-lc-rs")] {
+            use aws_lc_rs::aead::{self, LessSafeKey, UnboundKey, AES_128_GCM};
+        } else {
+            use ring::aead::{self, LessSafeKey, UnboundKey, AES_128_GCM};
+        }
+    }
+    let key = LessSafeKey::new(UnboundKey::new(&AES_128_GCM, key).ok()?);
+    let tag = key
+        .seal_in_place_separate_tag(
+            aead::Nonce::assume_unique_for_key(*nonce),
+            aead::Aad::from(aad),
+            &mut [],
+        )
+        .ok()?;
+    tag.as_ref().try_into().ok()
+}
+
+/// How long a token carried by the Retry packet is valid, the client should echo it immediately.
+const RETRY_TOKEN_LIFETIME: Duration = Duration::from_secs(10);
+
+/// How long a token sent in the NEW_TOKEN frame is valid, for the future connections of the client.
+const NEW_TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The first byte of the token, to identify how it was provided to the client.
+const RETRY_TOKEN: u8 = 0;
+const NEW_TOKEN: u8 = 1;
+
+/// Issue and validate the address validation tokens sent in Retry packets and NEW_TOKEN frames,
+/// see [Section 8.1](https://www.rfc-editor.org/rfc/rfc9000#section-8.1) of RFC 9000.
+///
+/// The token is encrypted by the AES-256-GCM of the [`default_provider`], in format of
+/// `kind || nonce || ciphertext`. The issued time, and the original Destination Connection ID from
+/// the client for a Retry token, are sealed in the ciphertext, and the client address is
+/// authenticated as the associated data, so that the server does not need to keep any state before
+/// the client returns the token. To validate the tokens issued by other nodes of a fleet, all the
+/// servers should share the same key.
+///
+/// A Retry token is bound to the client's IP address and port, while a NEW_TOKEN token is bound to
+/// the IP address and the server name only, because the client will use another port in the future
+/// connection. It's issued by the [`TokenProvider`] implementation.
+pub struct AddressValidator {
+    key: Aes256GcmKey,
+}
+
+impl AddressValidator {
+    /// Create a validator which protects the tokens with the AES-256-GCM `key`, return an error if
+    /// the [`default_provider`] does not support AES-256-GCM.
+    pub fn new(key: [u8; 32]) -> Result<Self, rustls::Error> {
+        Ok(Self {
+            key: Aes256GcmKey::new(&default_provider(), key)?,
+        })
+    }
+
+    /// Create a validator with a random key.
+    pub fn generate() -> Result<Self, rustls::Error> {
+        let mut key = [0u8; 32];
+        default_provider().secure_random.fill(&mut key)?;
+        Self::new(key)
+    }
+
+    /// Issue a token to be sent in the Retry packet to the client at the `remote` address, whose
+    /// first Initial packet was sent to `odcid`.
+    pub fn issue_retry_token(&self, remote: SocketAddr, odcid: &ConnectionId) -> Vec<u8> {
+        self.issue_retry_token_at(remote, odcid, SystemTime::now())
+    }
+
+    fn issue_retry_token_at(
+        &self,
+        remote: SocketAddr,
+        odcid: &ConnectionId,
+        now: SystemTime,
+    ) -> Vec<u8> {
+        let mut plain = Vec::with_capacity(1 + odcid.len());
+        plain.push(odcid.len() as u8);
+        plain.extend_from_slice(odcid);
+        let aad = associated_data(RETRY_TOKEN, remote.ip(), &remote.port().to_be_bytes());
+        self.seal(RETRY_TOKEN, &aad, now, plain)
+    }
+
+    /// Validate the `token` carried by the Initial packet from the `remote` address.
+    ///
+    /// Return the original Destination Connection ID sealed in the token if it was issued in a
+    /// Retry packet to the same address, and has not expired. The server must send it in the
+    /// original_destination_connection_id transport parameter.
+    pub fn validate_retry_token(&self, remote: SocketAddr, token: &[u8]) -> Option<ConnectionId> {
+        self.validate_retry_token_at(remote, token, SystemTime::now())
+    }
+
+    /// Whether the `token` is in the format of the tokens issued in Retry packets, no matter it's
+    /// authentic or not.
+    ///
+    /// A server should close the connection with INVALID_TOKEN if such a token fails to validate,
+    /// instead of dropping the packet, because the client can't try again without it.
+    pub fn is_retry_token(token: &[u8]) -> bool {
+        token.first() == Some(&RETRY_TOKEN)
+    }
+
+    fn validate_retry_token_at(
+        &self,
+        remote: SocketAddr,
+        token: &[u8],
+        now: SystemTime,
+    ) -> Option<ConnectionId> {
+        let aad = associated_data(RETRY_TOKEN, remote.ip(), &remote.port().to_be_bytes());
+        let plain = self.open(RETRY_TOKEN, &aad, token, now, RETRY_TOKEN_LIFETIME)?;
+        let (&len, odcid) = plain.split_first()?;
+        if len as usize != odcid.len() || odcid.len() > MAX_CID_SIZE {
+            return None;
+        }
+        Some(ConnectionId::from_slice(odcid))
+    }
+
+    fn issue_new_token_at(
+        &self,
+        server_name: &str,
+        remote: SocketAddr,
+        now: SystemTime,
+    ) -> Vec<u8> {
+        let aad = associated_data(NEW_TOKEN, remote.ip(), server_name.as_bytes());
+        self.seal(NEW_TOKEN, &aad, now, Vec::new())
+    }
+
+    fn validate_new_token_at(
+        &self,
+        server_name: &str,
+        remote: SocketAddr,
+        token: &[u8],
+        now: SystemTime,
+    ) -> bool {
+        let aad = associated_data(NEW_TOKEN, remote.ip(), server_name.as_bytes());
+        self.open(NEW_TOKEN, &aad, token, now, NEW_TOKEN_LIFETIME)
+            .is_some_and(|plain| plain.is_empty())
+    }
+
+    /// Seal the issued time `now` followed by the `plain` text into a token of `kind`.
+    fn seal(&self, kind: u8, aad: &[u8], now: SystemTime, plain: Vec<u8>) -> Vec<u8> {
+        let issued = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let mut issued_plain = Vec::with_capacity(8 + plain.len());
+        issued_plain.extend_from_slice(&issued.to_be_bytes());
+        issued_plain.extend_from_slice(&plain);
+        let sealed = self
+            .key
+            .seal(aad, &issued_plain)
+            .expect("failed to seal the token");
+
+        let mut token = Vec::with_capacity(1 + sealed.len());
+        token.push(kind);
+        token.extend_from_slice(&sealed);
+        token
+    }
+
+    /// Open the `token` of `kind`, return the plain text after the issued time if it's authentic
+    /// and has not expired.
+    fn open(
+        &self,
+        kind: u8,
+        aad: &[u8],
+        token: &[u8],
+        now: SystemTime,
+        lifetime: Duration,
+    ) -> Option<Vec<u8>> {
+        let (&token_kind, sealed) = token.split_first()?;
+        if token_kind != kind {
+            return None;
+        }
+        let plain = self.key.open(aad, sealed)?;
+
+        let (issued, rest) = plain.split_first_chunk::<8>()?;
+        let issued = UNIX_EPOCH + Duration::from_secs(u64::from_be_bytes(*issued));
+        match now.duration_since(issued) {
+            Ok(elapsed) if elapsed <= lifetime => {}
+            // Tolerate the clock skew among the nodes of a fleet
+            Err(e) if e.duration() <= RETRY_TOKEN_LIFETIME => {}
+            _ => return None,
+        }
+        Some(rest.to_vec())
+    }
+}
+
+impl TokenProvider for AddressValidator {
+    fn provide_new_token(&self, server_name: &str, remote: SocketAddr) -> Vec<u8> {
+        self.issue_new_token_at(server_name, remote, SystemTime::now())
+    }
+
+    fn provide_retry_token(&self, _server_name: &str) -> Vec<u8> {
+        // Retry tokens are bound to the original Destination Connection ID, see issue_retry_token
+        Vec::new()
+    }
+
+    fn validate_token(&self, server_name: String, remote: SocketAddr, token: &[u8]) -> bool {
+        self.validate_new_token_at(&server_name, remote, token, SystemTime::now())
+    }
+}
+
+impl fmt::Debug for AddressValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 不要泄露密钥
+        f.debug_struct("AddressValidator").finish_non_exhaustive()
+    }
+}
+
+/// The kind of the token, the client's IP address and the `extra` data to be bound, which are
+/// authenticated but not encrypted.
+fn associated_data(kind: u8, ip: IpAddr, extra: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(1 + 16 + extra.len());
+    aad.push(kind);
+    match ip {
+        IpAddr::V4(ip) => aad.extend_from_slice(&ip.octets()),
+        IpAddr::V6(ip) => aad.extend_from_slice(&ip.octets()),
+    }
+    aad.extend_from_slice(extra);
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use qbase::packet::header::long::io::LongHeaderBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_integrity_tag() {
+        // The Retry packet in Appendix A.4 of RFC 9001, whose unused bits are all set
+        let odcid = ConnectionId::from_slice(&[0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08]);
+        let packet = [
+            0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x08, 0xf0, 0x67, 0xa5, 0x50, 0x2a, 0x42, 0x62,
+            0xb5, 0x74, 0x6f, 0x6b, 0x65, 0x6e, 0x04, 0xa2, 0x65, 0xba, 0x2e, 0xff, 0x4d, 0x82,
+            0x90, 0x58, 0xfb, 0x3f, 0x0f, 0x24, 0x96, 0xba,
+        ];
+        let scid = ConnectionId::from_slice(&packet[7..15]);
+        let mut retry = LongHeaderBuilder::with_cid(ConnectionId::default(), scid)
+            .retry(b"token".to_vec(), packet[20..].try_into().unwrap());
+        assert!(verify_integrity(&odcid, &retry, &packet));
+        assert!(!verify_integrity(&scid, &retry, &packet));
+        assert!(!verify_integrity(&odcid, &retry, &packet[1..]));
+
+        retry.integrity = integrity_tag(&odcid, &retry).unwrap();
+        let mut encoded = Vec::new();
+        encoded.put_long_header(&retry);
+        assert!(verify_integrity(&odcid, &retry, &encoded));
+        encoded[0] |= 0x0f;
+        assert!(!verify_integrity(&odcid, &retry, &encoded));
+    }
+
+    #[test]
+    fn test_retry_token() {
+        let validator = AddressValidator::generate().unwrap();
+        let remote = "127.0.0.1:4433".parse().unwrap();
+        let odcid = ConnectionId::random_gen(8);
+        let token = validator.issue_retry_token(remote, &odcid);
+        assert_eq!(validator.validate_retry_token(remote, &token), Some(odcid));
+
+        let other = "127.0.0.1:4434".parse().unwrap();
+        assert_eq!(validator.validate_retry_token(other, &token), None);
+        assert_eq!(validator.validate_retry_token(remote, &token[1..]), None);
+        assert_eq!(validator.validate_retry_token(remote, &[]), None);
+        assert!(AddressValidator::is_retry_token(&token));
+        assert!(!AddressValidator::is_retry_token(&[]));
+        let new_token = validator.provide_new_token("localhost", remote);
+        assert!(!AddressValidator::is_retry_token(&new_token));
+        let another = AddressValidator::generate().unwrap();
+        assert_eq!(another.validate_retry_token(remote, &token), None);
+
+        let past = SystemTime::now() - RETRY_TOKEN_LIFETIME * 2;
+        let expired = validator.issue_retry_token_at(remote, &odcid, past);
+        assert_eq!(validator.validate_retry_token(remote, &expired), None);
+    }
+
+    #[test]
+    fn test_new_token() {
+        let validator = AddressValidator::generate().unwrap();
+        let remote = "127.0.0.1:4433".parse().unwrap();
+        let token = validator.provide_new_token("localhost", remote);
+        assert!(validator.validate_token("localhost".to_owned(), remote, &token));
+
+        // The client connects from another port in the future
+        let other_port = "127.0.0.1:4434".parse().unwrap();
+        assert!(validator.validate_token("localhost".to_owned(), other_port, &token));
+        let other_ip = "127.0.0.2:4433".parse().unwrap();
+        assert!(!validator.validate_token("localhost".to_owned(), other_ip, &token));
+        assert!(!validator.validate_token("example.com".to_owned(), remote, &token));
+
+        // Tokens of different kinds are not interchangeable
+        let retry_token = validator.issue_retry_token(remote, &ConnectionId::random_gen(8));
+        assert!(!validator.validate_token("localhost".to_owned(), remote, &retry_token));
+        assert_eq!(validator.validate_retry_token(remote, &token), None);
+
+        let past = SystemTime::now() - NEW_TOKEN_LIFETIME * 2;
+        let expired = validator.issue_new_token_at("localhost", remote, past);
+        assert!(!validator.validate_token("localhost".to_owned(), remote, &expired));
+    }
+}
@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{verify_tls13_signature_with_raw_key, CryptoProvider},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, SubjectPublicKeyInfoDer, UnixTime},
+    server::danger::{ClientCertVerified, ClientCertVerifier},
+    sign::CertifiedKey,
+    CertificateError, DigitallySignedStruct, DistinguishedName, Error, PeerIncompatible,
+    SignatureScheme,
+};
+
+/// Verify the peer authenticated with a [RFC 7250] raw public key instead of a X.509 certificate.
+///
+/// The peer is trusted only if its public key is one of the trusted keys, it can be used as both a
+/// [`ServerCertVerifier`] for the client and a [`ClientCertVerifier`] for the server.
+///
+/// [RFC 7250]: https://www.rfc-editor.org/rfc/rfc7250
+#[derive(Debug)]
+pub struct RawPublicKeyVerifier {
+    trusted: Vec<SubjectPublicKeyInfoDer<'static>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl RawPublicKeyVerifier {
+    pub fn new(
+        trusted: impl IntoIterator<Item = SubjectPublicKeyInfoDer<'static>>,
+        provider: Arc<CryptoProvider>,
+    ) -> Self {
+        Self {
+            trusted: trusted.into_iter().collect(),
+            provider,
+        }
+    }
+
+    fn verify_raw_public_key(&self, end_entity: &CertificateDer<'_>) -> Result<(), Error> {
+        // 使用原始公钥时，end_entity就是对端的SubjectPublicKeyInfo
+        if self
+            .trusted
+            .iter()
+            .any(|key| key.as_ref() == end_entity.as_ref())
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidCertificate(CertificateError::UnknownIssuer))
+        }
+    }
+
+    fn verify_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature_with_raw_key(
+            message,
+            &SubjectPublicKeyInfoDer::from(cert.as_ref()),
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+}
+
+impl ServerCertVerifier for RawPublicKeyVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        self.verify_raw_public_key(end_entity)
+            .map(|_| ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        Err(PeerIncompatible::Tls12NotOffered.into())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.verify_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+
+    fn requires_raw_public_keys(&self) -> bool {
+        true
+    }
+}
+
+impl ClientCertVerifier for RawPublicKeyVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, Error> {
+        self.verify_raw_public_key(end_entity)
+            .map(|_| ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        Err(PeerIncompatible::Tls12NotOffered.into())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.verify_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+
+    fn requires_raw_public_keys(&self) -> bool {
+        true
+    }
+}
+
+/// Load the private key, and use its public key as the raw public key to authenticate local.
+pub fn raw_public_key(
+    provider: &CryptoProvider,
+    key_der: PrivateKeyDer<'static>,
+) -> Result<Arc<CertifiedKey>, Error> {
+    let signing_key = provider.key_provider.load_private_key(key_der)?;
+    let public_key = signing_key
+        .public_key()
+        .ok_or(Error::General("unable to get the public key".into()))?;
+    let raw_public_key = CertificateDer::from(public_key.as_ref().to_vec());
+    Ok(Arc::new(CertifiedKey::new(
+        vec![raw_public_key],
+        signing_key,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_raw_public_key() {
+        let trusted = SubjectPublicKeyInfoDer::from(vec![1, 2, 3]);
+        let verifier = RawPublicKeyVerifier::new([trusted], crate::tls::default_provider());
+        assert!(verifier
+            .verify_raw_public_key(&CertificateDer::from(vec![1, 2, 3]))
+            .is_ok());
+        assert!(verifier
+            .verify_raw_public_key(&CertificateDer::from(vec![3, 2, 1]))
+            .is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use core::fmt;
+
+use rustls::{
+    pki_types::CertificateDer,
+    quic::{Connection, DirectionalKeys, KeyChange},
+    AlertDescription, Error,
+};
+
+/// The operations of a TLS 1.3 backend required by [`ArcTlsSession`] to drive the QUIC handshake,
+/// see [RFC 9001](https://www.rfc-editor.org/rfc/rfc9001).
+///
+/// The connection only interacts with the TLS library through this trait, so that alternative
+/// backends, such as BoringSSL or s2n-tls, can be used by wrapping them to implement it and
+/// creating the session with [`ArcTlsSession::with_session`]. It's implemented for the rustls's
+/// [`Connection`] by default.
+///
+/// The packet protection keys are still expressed in the rustls's [`KeyChange`], whose keys are
+/// trait objects, so a backend only needs to implement [`PacketKey`] and [`HeaderProtectionKey`]
+/// for its own ciphers. Errors that are not from rustls can be reported as [`Error::General`].
+///
+/// [`ArcTlsSession`]: super::ArcTlsSession
+/// [`ArcTlsSession::with_session`]: super::ArcTlsSession::with_session
+/// [`PacketKey`]: rustls::quic::PacketKey
+/// [`HeaderProtectionKey`]: rustls::quic::HeaderProtectionKey
+pub trait Session: fmt::Debug + Send {
+    /// Consume the handshake data received in CRYPTO frames.
+    fn read_hs(&mut self, plaintext: &[u8]) -> Result<(), Error>;
+
+    /// Write the handshake data to be sent in CRYPTO frames into `buf`, and return the keys if
+    /// the session moves to the next epoch after producing them.
+    fn write_hs(&mut self, buf: &mut Vec<u8>) -> Option<KeyChange>;
+
+    /// The keys for 0-RTT packets, available once the client sends, or the server accepts the
+    /// early data.
+    fn zero_rtt_keys(&self) -> Option<DirectionalKeys>;
+
+    /// The encoded transport parameters of the peer, available once received.
+    fn quic_transport_parameters(&self) -> Option<&[u8]>;
+
+    /// The TLS alert to be sent to the peer after an error, which is carried as a CRYPTO_ERROR.
+    fn alert(&self) -> Option<AlertDescription>;
+
+    fn is_handshaking(&self) -> bool;
+
+    /// The certificate chain(or the raw public key) presented by the peer.
+    fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]>;
+
+    /// The application protocol negotiated by ALPN.
+    fn alpn_protocol(&self) -> Option<&[u8]>;
+
+    /// The server name requested by the client, server only.
+    fn server_name(&self) -> Option<&str>;
+
+    /// Fill `output` with keying material derived from the exporter secret.
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: &[u8],
+    ) -> Result<(), Error>;
+}
+
+impl Session for Connection {
+    fn read_hs(&mut self, plaintext: &[u8]) -> Result<(), Error> {
+        Connection::read_hs(self, plaintext)
+    }
+
+    fn write_hs(&mut self, buf: &mut Vec<u8>) -> Option<KeyChange> {
+        Connection::write_hs(self, buf)
+    }
+
+    fn zero_rtt_keys(&self) -> Option<DirectionalKeys> {
+        Connection::zero_rtt_keys(self)
+    }
+
+    fn quic_transport_parameters(&self) -> Option<&[u8]> {
+        Connection::quic_transport_parameters(self)
+    }
+
+    fn alert(&self) -> Option<AlertDescription> {
+        Connection::alert(self)
+    }
+
+    fn is_handshaking(&self) -> bool {
+        // CommonState::is_handshaking
+        (**self).is_handshaking()
+    }
+
+    fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        (**self).peer_certificates()
+    }
+
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        (**self).alpn_protocol()
+    }
+
+    fn server_name(&self) -> Option<&str> {
+        match self {
+            Connection::Server(server_conn) => server_conn.server_name(),
+            Connection::Client(_) => None,
+        }
+    }
+
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: &[u8],
+    ) -> Result<(), Error> {
+        Connection::export_keying_material(self, output, label, Some(context)).map(|_| ())
+    }
+}
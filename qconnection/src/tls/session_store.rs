@@ -0,0 +1,205 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use qbase::config::Parameters;
+use rustls::{
+    client::{ClientSessionMemoryCache, Tls12ClientSessionValue, Tls13ClientSessionValue},
+    pki_types::{EchConfigListBytes, ServerName},
+    NamedGroup,
+};
+
+/// The storage of the resumption states(session tickets) of clients.
+///
+/// Besides the TLS session values stored by [`rustls::client::ClientSessionStore`], the QUIC
+/// transport parameters of the server are also remembered, which are required by the client to
+/// send 0-RTT data on resumed connection. So are the ECH retry configs sent by the server when it
+/// rejects the Encrypted Client Hello.
+///
+/// The default implementation is [`MemorySessionStore`], which is only available in the process.
+/// Implement this trait to persist the tickets, so that the handshake can be resumed after restart.
+pub trait ClientSessionStore: rustls::client::ClientSessionStore {
+    /// Remember the transport parameters of the server `server_name`.
+    fn set_remembered_parameters(&self, server_name: ServerName<'static>, params: Parameters);
+
+    /// Get the transport parameters of the server `server_name` most recently passed to
+    /// [`set_remembered_parameters`].
+    ///
+    /// [`set_remembered_parameters`]: ClientSessionStore::set_remembered_parameters
+    fn remembered_parameters(&self, server_name: &ServerName<'_>) -> Option<Parameters>;
+
+    /// Remember the ECH configs which the server `server_name` asks the client to retry with,
+    /// after it rejected the Encrypted Client Hello.
+    fn set_ech_retry_configs(
+        &self,
+        server_name: ServerName<'static>,
+        retry_configs: EchConfigListBytes<'static>,
+    );
+
+    /// Get the ECH configs most recently passed to [`set_ech_retry_configs`].
+    ///
+    /// [`set_ech_retry_configs`]: ClientSessionStore::set_ech_retry_configs
+    fn ech_retry_configs(
+        &self,
+        server_name: &ServerName<'_>,
+    ) -> Option<EchConfigListBytes<'static>>;
+}
+
+#[derive(Debug, Default)]
+struct Remembered {
+    params: Option<Parameters>,
+    ech_retry_configs: Option<EchConfigListBytes<'static>>,
+}
+
+/// An in-memory [`ClientSessionStore`], stores the tickets, transport parameters and ECH retry
+/// configs of at most `size` servers.
+#[derive(Debug)]
+pub struct MemorySessionStore {
+    tickets: ClientSessionMemoryCache,
+    remembered: Mutex<(
+        HashMap<ServerName<'static>, Remembered>,
+        VecDeque<ServerName<'static>>,
+    )>,
+    size: usize,
+}
+
+impl MemorySessionStore {
+    pub fn new(size: usize) -> Self {
+        Self {
+            tickets: ClientSessionMemoryCache::new(size),
+            remembered: Mutex::default(),
+            size,
+        }
+    }
+
+    fn remember(&self, server_name: ServerName<'static>, update: impl FnOnce(&mut Remembered)) {
+        let mut guard = self.remembered.lock().unwrap();
+        let (remembered, order) = &mut *guard;
+        if !remembered.contains_key(&server_name) {
+            order.push_back(server_name.clone());
+            // 超出容量，淘汰最早记住的服务器
+            while order.len() > self.size {
+                if let Some(oldest) = order.pop_front() {
+                    remembered.remove(&oldest);
+                }
+            }
+        }
+        update(remembered.entry(server_name).or_default());
+    }
+
+    fn recall<T>(
+        &self,
+        server_name: &ServerName<'_>,
+        get: impl FnOnce(&Remembered) -> Option<T>,
+    ) -> Option<T> {
+        let guard = self.remembered.lock().unwrap();
+        guard.0.get(&server_name.to_owned()).and_then(get)
+    }
+}
+
+impl Default for MemorySessionStore {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl rustls::client::ClientSessionStore for MemorySessionStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.tickets.set_kx_hint(server_name, group)
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.tickets.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.tickets.set_tls12_session(server_name, value)
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.tickets.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.tickets.remove_tls12_session(server_name)
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: Tls13ClientSessionValue,
+    ) {
+        self.tickets.insert_tls13_ticket(server_name, value)
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<Tls13ClientSessionValue> {
+        self.tickets.take_tls13_ticket(server_name)
+    }
+}
+
+impl ClientSessionStore for MemorySessionStore {
+    fn set_remembered_parameters(&self, server_name: ServerName<'static>, params: Parameters) {
+        self.remember(server_name, |remembered| remembered.params = Some(params))
+    }
+
+    fn remembered_parameters(&self, server_name: &ServerName<'_>) -> Option<Parameters> {
+        self.recall(server_name, |remembered| remembered.params.clone())
+    }
+
+    fn set_ech_retry_configs(
+        &self,
+        server_name: ServerName<'static>,
+        retry_configs: EchConfigListBytes<'static>,
+    ) {
+        self.remember(server_name, |remembered| {
+            remembered.ech_retry_configs = Some(retry_configs)
+        })
+    }
+
+    fn ech_retry_configs(
+        &self,
+        server_name: &ServerName<'_>,
+    ) -> Option<EchConfigListBytes<'static>> {
+        self.recall(server_name, |remembered| {
+            remembered.ech_retry_configs.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remembered_parameters() {
+        let store = MemorySessionStore::new(1);
+        let a = ServerName::try_from("a.example.com").unwrap();
+        let b = ServerName::try_from("b.example.com").unwrap();
+
+        store.set_remembered_parameters(a.clone(), Parameters::default());
+        assert!(store.remembered_parameters(&a).is_some());
+
+        store.set_remembered_parameters(b.clone(), Parameters::default());
+        assert!(store.remembered_parameters(&a).is_none());
+        assert!(store.remembered_parameters(&b).is_some());
+    }
+
+    #[test]
+    fn test_ech_retry_configs() {
+        let store = MemorySessionStore::new(1);
+        let a = ServerName::try_from("a.example.com").unwrap();
+        assert!(store.ech_retry_configs(&a).is_none());
+
+        store.set_remembered_parameters(a.clone(), Parameters::default());
+        store.set_ech_retry_configs(a.clone(), EchConfigListBytes::from(vec![1, 2, 3]));
+        assert!(store.remembered_parameters(&a).is_some());
+        assert_eq!(
+            store.ech_retry_configs(&a).as_deref(),
+            Some([1, 2, 3].as_slice())
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use qbase::{
+    frame::{Frame, FrameReader},
+    packet::{
+        decrypt::{decrypt_packet, remove_protection_of_long_packet},
+        header::GetType,
+        DataPacket,
+    },
+};
+use rustls::quic::Keys;
+
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+const EXTENSION_QUIC_TRANSPORT_PARAMETERS: u16 = 0x0039;
+const SERVER_NAME_TYPE_HOST_NAME: u8 = 0x00;
+
+/// Peek the server name carried in the ClientHello of the first Initial packet from the client,
+/// before creating the TLS session, so that the server can select the TLS config by SNI.
+///
+/// The `keys` are the initial keys of the server derived from the original destination connection
+/// ID chosen by the client. The packet is not modified.
+///
+/// Return [`None`] if the packet cannot be decrypted, the ClientHello is not fully carried in the
+/// packet, or the client does not send the SNI extension.
+pub fn peek_server_name(packet: &DataPacket, keys: &Keys) -> Option<String> {
+    let client_hello = peek_client_hello(packet, keys)?;
+    parse_server_name(&client_hello).map(ToOwned::to_owned)
+}
+
+/// Peek the ClientHello handshake message carried in the first Initial packet from the client, the
+/// `keys` are the same as [`peek_server_name`].
+///
+/// The returned message may be truncated if it's not fully carried in the packet.
+pub fn peek_client_hello(packet: &DataPacket, keys: &Keys) -> Option<Vec<u8>> {
+    let mut bytes = packet.bytes.clone();
+    let undecoded_pn = remove_protection_of_long_packet(
+        keys.remote.header.as_ref(),
+        bytes.as_mut(),
+        packet.offset,
+    )
+    .ok()??;
+    // The first Initial packet from the client, there is no packet received before
+    let pn = undecoded_pn.decode(0);
+    let body_offset = packet.offset + undecoded_pn.size();
+    let body_len =
+        decrypt_packet(keys.remote.packet.as_ref(), pn, bytes.as_mut(), body_offset).ok()?;
+    let _header = bytes.split_to(body_offset);
+    bytes.truncate(body_len);
+
+    // Reassemble the beginning of the CRYPTO stream
+    let mut crypto_frames = FrameReader::new(bytes.freeze(), packet.header.get_type())
+        .filter_map(|frame| match frame {
+            Ok((Frame::Crypto(f, data), _)) => Some((f.offset.into_inner(), data)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    crypto_frames.sort_by_key(|(offset, _)| *offset);
+    let mut client_hello = Vec::new();
+    for (offset, data) in crypto_frames {
+        let end = offset as usize + data.len();
+        if offset as usize > client_hello.len() {
+            break;
+        }
+        if end > client_hello.len() {
+            client_hello.extend_from_slice(&data[client_hello.len() - offset as usize..]);
+        }
+    }
+
+    Some(client_hello)
+}
+
+/// Parse the host name in the server_name extension of the ClientHello handshake message.
+///
+/// See [Section 4.1.2](https://www.rfc-editor.org/rfc/rfc8446#section-4.1.2) of RFC 8446 and
+/// [Section 3](https://www.rfc-editor.org/rfc/rfc6066#section-3) of RFC 6066.
+pub fn parse_server_name(client_hello: &[u8]) -> Option<&str> {
+    let mut extension = Reader(parse_extension(client_hello, EXTENSION_SERVER_NAME)?);
+    let mut server_names = Reader(extension.vec16()?);
+    while !server_names.0.is_empty() {
+        let name_type = server_names.u8()?;
+        let name = server_names.vec16()?;
+        if name_type == SERVER_NAME_TYPE_HOST_NAME {
+            return core::str::from_utf8(name).ok();
+        }
+    }
+    None
+}
+
+/// Parse the encoded transport parameters in the quic_transport_parameters extension of the
+/// ClientHello handshake message, see [Section 8.2](https://www.rfc-editor.org/rfc/rfc9001#section-8.2)
+/// of RFC 9001.
+pub fn parse_transport_parameters(client_hello: &[u8]) -> Option<&[u8]> {
+    parse_extension(client_hello, EXTENSION_QUIC_TRANSPORT_PARAMETERS)
+}
+
+/// Find the data of the extension in `extension_type` of the ClientHello handshake message.
+fn parse_extension(client_hello: &[u8], extension_type: u16) -> Option<&[u8]> {
+    let mut reader = Reader(client_hello);
+    if reader.u8()? != HANDSHAKE_CLIENT_HELLO {
+        return None;
+    }
+    let mut body = Reader(reader.vec24()?);
+    // legacy_version, random
+    body.take(2 + 32)?;
+    // legacy_session_id, cipher_suites, legacy_compression_methods
+    body.vec8()?;
+    body.vec16()?;
+    body.vec8()?;
+
+    let mut extensions = Reader(body.vec16()?);
+    while !extensions.0.is_empty() {
+        let ty = extensions.u16()?;
+        let extension = extensions.vec16()?;
+        if ty == extension_type {
+            return Some(extension);
+        }
+    }
+    None
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    /// Read a vector with 1 byte length prefix
+    fn vec8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()? as usize;
+        self.take(len)
+    }
+
+    /// Read a vector with 2 bytes length prefix
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.take(len)
+    }
+
+    /// Read a vector with 3 bytes length prefix
+    fn vec24(&mut self) -> Option<&'a [u8]> {
+        let b = self.take(3)?;
+        let len = u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_server_name() {
+        let root_store = rustls::RootCertStore::empty();
+        let config = rustls::ClientConfig::builder_with_provider(crate::tls::default_provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        let server_name = "example.com".try_into().unwrap();
+        let mut conn = rustls::ClientConnection::new(Arc::new(config), server_name).unwrap();
+
+        let mut record = Vec::new();
+        conn.write_tls(&mut record).unwrap();
+        // skip the header of the TLS record
+        assert_eq!(parse_server_name(&record[5..]), Some("example.com"));
+        assert_eq!(parse_server_name(&record[5..record.len() - 1]), None);
+        assert_eq!(parse_transport_parameters(&record[5..]), None);
+    }
+
+    #[test]
+    fn test_parse_transport_parameters() {
+        let root_store = rustls::RootCertStore::empty();
+        let config = rustls::ClientConfig::builder_with_provider(crate::tls::default_provider())
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        let server_name = "example.com".try_into().unwrap();
+        let mut conn = rustls::quic::ClientConnection::new(
+            Arc::new(config),
+            rustls::quic::Version::V1,
+            server_name,
+            vec![0x01, 0x02, 0x03],
+        )
+        .unwrap();
+
+        let mut client_hello = Vec::new();
+        conn.write_hs(&mut client_hello);
+        assert_eq!(
+            parse_transport_parameters(&client_hello),
+            Some(&[0x01, 0x02, 0x03][..])
+        );
+        assert_eq!(parse_server_name(&client_hello), Some("example.com"));
+    }
+}
@@ -0,0 +1,182 @@
+use std::{fmt, sync::RwLock, time::Duration};
+
+use rustls::server::ProducesTickets;
+
+use super::{aead::Aes256GcmKey, default_provider};
+
+/// The key used to encrypt and decrypt the session tickets issued by the server.
+///
+/// The ticket key is identified by its `name`, which is carried in the ticket in plaintext, so that
+/// the server can find the key to decrypt it. To support resumption across different nodes, all the
+/// servers in the fleet should share the same ticket keys.
+#[derive(Clone)]
+pub struct TicketKey {
+    name: [u8; 16],
+    key: [u8; 32],
+}
+
+impl TicketKey {
+    /// Create a ticket key with the given `name` and AES-256-GCM `key`.
+    pub fn new(name: [u8; 16], key: [u8; 32]) -> Self {
+        Self { name, key }
+    }
+
+    /// Generate a random ticket key by the [`default_provider`].
+    pub fn generate() -> Self {
+        let rng = default_provider().secure_random;
+        let mut name = [0u8; 16];
+        let mut key = [0u8; 32];
+        rng.fill(&mut name).expect("failed to generate ticket key");
+        rng.fill(&mut key).expect("failed to generate ticket key");
+        Self { name, key }
+    }
+
+    pub fn name(&self) -> &[u8; 16] {
+        &self.name
+    }
+
+    fn aead_key(&self) -> Result<Aes256GcmKey, rustls::Error> {
+        Aes256GcmKey::new(&default_provider(), self.key)
+    }
+}
+
+impl fmt::Debug for TicketKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 不要泄露密钥
+        f.debug_struct("TicketKey")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Keys {
+    current: (TicketKey, Aes256GcmKey),
+    previous: Option<(TicketKey, Aes256GcmKey)>,
+}
+
+/// A session ticket encrypter whose key can be rotated.
+///
+/// The ticket is encrypted by the current key, in format of `name || nonce || ciphertext`. After
+/// [`rotate`], the tickets encrypted by the previous key can still be decrypted, until the next
+/// rotation. So the key should be rotated at an interval not less than the ticket lifetime.
+///
+/// The AES-256-GCM of the [`default_provider`] is used, so that the ticketer works with either
+/// the `ring` or the `aws-lc-rs`(including FIPS) provider.
+///
+/// [`rotate`]: RotatingTicketer::rotate
+pub struct RotatingTicketer {
+    keys: RwLock<Keys>,
+    lifetime: Duration,
+}
+
+impl RotatingTicketer {
+    /// Create a ticketer which encrypts tickets with `key`, and tells the client that the tickets
+    /// are valid for `lifetime`.
+    ///
+    /// The lifetime will be truncated to 7 days, the maximum allowed by TLS 1.3.
+    ///
+    /// Return an error if the [`default_provider`] does not support AES-256-GCM.
+    pub fn new(key: TicketKey, lifetime: Duration) -> Result<Self, rustls::Error> {
+        const MAX_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+        let aead_key = key.aead_key()?;
+        Ok(Self {
+            keys: RwLock::new(Keys {
+                current: (key, aead_key),
+                previous: None,
+            }),
+            lifetime: lifetime.min(MAX_LIFETIME),
+        })
+    }
+
+    /// Replace the current key with `key`, the current key will be kept to decrypt the tickets
+    /// issued before, and the previous key will be discarded.
+    ///
+    /// Return an error if the [`default_provider`] does not support AES-256-GCM, the keys are
+    /// unchanged then.
+    pub fn rotate(&self, key: TicketKey) -> Result<(), rustls::Error> {
+        let aead_key = key.aead_key()?;
+        let mut keys = self.keys.write().unwrap();
+        let previous = std::mem::replace(&mut keys.current, (key, aead_key));
+        keys.previous = Some(previous);
+        Ok(())
+    }
+
+    /// The name of the key currently used to encrypt tickets.
+    pub fn current_key_name(&self) -> [u8; 16] {
+        self.keys.read().unwrap().current.0.name
+    }
+}
+
+impl fmt::Debug for RotatingTicketer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys = self.keys.read().unwrap();
+        f.debug_struct("RotatingTicketer")
+            .field("current", &keys.current.0)
+            .field("previous", &keys.previous.as_ref().map(|(key, _)| key))
+            .field("lifetime", &self.lifetime)
+            .finish()
+    }
+}
+
+impl ProducesTickets for RotatingTicketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.lifetime.as_secs() as u32
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let keys = self.keys.read().unwrap();
+        let (key, aead_key) = &keys.current;
+        let sealed = aead_key.seal(&key.name, plain)?;
+        let mut ticket = Vec::with_capacity(16 + sealed.len());
+        ticket.extend_from_slice(&key.name);
+        ticket.extend_from_slice(&sealed);
+        Some(ticket)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        let (name, sealed) = cipher.split_first_chunk::<16>()?;
+
+        let keys = self.keys.read().unwrap();
+        let (key, aead_key) = core::iter::once(&keys.current)
+            .chain(keys.previous.as_ref())
+            .find(|(key, _)| key.name == *name)?;
+        aead_key.open(&key.name, sealed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate() {
+        let ticketer =
+            RotatingTicketer::new(TicketKey::generate(), Duration::from_secs(3600)).unwrap();
+        assert_eq!(ticketer.lifetime(), 3600);
+
+        let first = ticketer.encrypt(b"session").unwrap();
+        assert_eq!(ticketer.decrypt(&first).unwrap(), b"session");
+
+        ticketer.rotate(TicketKey::generate()).unwrap();
+        let second = ticketer.encrypt(b"session").unwrap();
+        assert_eq!(ticketer.decrypt(&first).unwrap(), b"session");
+        assert_eq!(ticketer.decrypt(&second).unwrap(), b"session");
+
+        ticketer.rotate(TicketKey::generate()).unwrap();
+        assert!(ticketer.decrypt(&first).is_none());
+        assert_eq!(ticketer.decrypt(&second).unwrap(), b"session");
+    }
+
+    #[test]
+    fn test_shared_key() {
+        let key = TicketKey::new([1; 16], [2; 32]);
+        let node_a = RotatingTicketer::new(key.clone(), Duration::from_secs(60)).unwrap();
+        let node_b = RotatingTicketer::new(key, Duration::from_secs(60)).unwrap();
+        let ticket = node_a.encrypt(b"session").unwrap();
+        assert_eq!(node_b.decrypt(&ticket).unwrap(), b"session");
+    }
+}
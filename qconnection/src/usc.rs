@@ -0,0 +1,244 @@
+use std::{
+    io::{self, IoSlice, IoSliceMut},
+    net::SocketAddr,
+    task::{ready, Context, Poll},
+};
+
+use qbase::frame::EcnCodepoint;
+use qudp::ArcUsc;
+
+/// The size of each buffer passed to [`QuicIo::poll_recv`] by default, a datagram larger than it
+/// is truncated.
+pub use qudp::RECV_BUFFER_SIZE;
+
+/// The header of the datagrams sent or received by the [`QuicIo`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PacketHeader {
+    /// The local address when sending, or the peer's address when receiving.
+    pub src: SocketAddr,
+    /// The peer's address when sending, or the local address when receiving.
+    pub dst: SocketAddr,
+    pub ttl: u8,
+    /// The ECN codepoint marked on the datagrams, `None` for Not-ECT.
+    pub ecn: Option<EcnCodepoint>,
+    /// The size of the datagrams sent, or the bytes received in the buffer.
+    pub seg_size: u16,
+    /// The size of each datagram coalesced by GRO in the received buffer, the last one may be
+    /// shorter, 0 if the datagrams are not coalesced.
+    pub stride: u16,
+    /// Whether the datagrams of the same size can be sent by GSO.
+    pub gso: bool,
+}
+
+impl Default for PacketHeader {
+    fn default() -> Self {
+        Self {
+            src: SocketAddr::from(([0, 0, 0, 0], 0)),
+            dst: SocketAddr::from(([0, 0, 0, 0], 0)),
+            ttl: 64,
+            ecn: None,
+            seg_size: 0,
+            stride: 0,
+            gso: false,
+        }
+    }
+}
+
+impl PacketHeader {
+    /// Splits the buffer received with this header into the datagrams by the
+    /// [`PacketHeader::stride`], the buffer is truncated to the [`PacketHeader::seg_size`] first.
+    pub fn datagrams<'b>(&self, buf: &'b [u8]) -> impl Iterator<Item = &'b [u8]> {
+        let buf = &buf[..(self.seg_size as usize).min(buf.len())];
+        let stride = match self.stride {
+            0 => buf.len().max(1),
+            stride => stride as usize,
+        };
+        buf.chunks(stride)
+    }
+}
+
+impl From<&PacketHeader> for qudp::PacketHeader {
+    fn from(hdr: &PacketHeader) -> Self {
+        Self {
+            src: hdr.src,
+            dst: hdr.dst,
+            ttl: hdr.ttl,
+            ecn: hdr.ecn.map(EcnCodepoint::bits),
+            seg_size: hdr.seg_size,
+            stride: hdr.stride,
+            gso: hdr.gso,
+        }
+    }
+}
+
+impl From<&qudp::PacketHeader> for PacketHeader {
+    fn from(hdr: &qudp::PacketHeader) -> Self {
+        Self {
+            src: hdr.src,
+            dst: hdr.dst,
+            ttl: hdr.ttl,
+            ecn: hdr.ecn.and_then(EcnCodepoint::from_bits),
+            seg_size: hdr.seg_size,
+            stride: hdr.stride,
+            gso: hdr.gso,
+        }
+    }
+}
+
+/// The I/O the connections send and receive the datagrams through, [`ArcUsc`] of qudp by default.
+///
+/// Implement it to run QUIC over the custom sockets, such as the simulated networks, the proxied
+/// transports or the userspace network stacks. The paths send the datagrams by
+/// [`QuicIo::poll_send`], and the receive task of the endpoint calls [`QuicIo::poll_recv`] to
+/// deliver the packets to the connections by the [`Router`].
+///
+/// [`Router`]: crate::router::Router
+pub trait QuicIo: Send + Sync + 'static {
+    /// The local address the datagrams are sent from and received on, the packets received are
+    /// routed by it for the connections using zero-length connection IDs.
+    fn local_addr(&self) -> SocketAddr;
+
+    /// The most datagrams sent by a call of [`QuicIo::poll_send`], the paths fill a batch this
+    /// large each time, 1 by default.
+    fn max_segments(&self) -> usize {
+        1
+    }
+
+    /// Sends the datagrams in `bufs` to the [`PacketHeader::dst`], returns the number of the
+    /// datagrams sent, which are at the front of `bufs`. The datagrams are of the same size but
+    /// the last one may be shorter, the [`PacketHeader::gso`] tells they can be sent by GSO.
+    fn poll_send(
+        &self,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+        hdr: &PacketHeader,
+    ) -> Poll<io::Result<usize>>;
+
+    /// Receives the datagrams into `bufs`, returns the number of the buffers filled, each of
+    /// which is described by the header at the same index, see [`PacketHeader::datagrams`].
+    fn poll_recv(
+        &self,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+        hdrs: &mut [PacketHeader],
+    ) -> Poll<io::Result<usize>>;
+
+    /// The number and the size of the buffers passed to [`QuicIo::poll_recv`], one buffer of
+    /// [`RECV_BUFFER_SIZE`] bytes by default.
+    fn recv_buffers(&self) -> (usize, usize) {
+        (1, RECV_BUFFER_SIZE)
+    }
+}
+
+impl QuicIo for ArcUsc {
+    fn local_addr(&self) -> SocketAddr {
+        ArcUsc::local_addr(self)
+    }
+
+    fn max_segments(&self) -> usize {
+        self.send_batch_size()
+    }
+
+    fn poll_send(
+        &self,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+        hdr: &PacketHeader,
+    ) -> Poll<io::Result<usize>> {
+        ArcUsc::poll_send(self, bufs, &hdr.into(), cx)
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+        hdrs: &mut [PacketHeader],
+    ) -> Poll<io::Result<usize>> {
+        let mut usc_hdrs = [qudp::PacketHeader::default(); qudp::BATCH_SIZE];
+        let batch_size = bufs.len().min(hdrs.len()).min(qudp::BATCH_SIZE);
+        let msg_count = ready!(ArcUsc::poll_recv(
+            self,
+            &mut bufs[..batch_size],
+            &mut usc_hdrs[..batch_size],
+            cx
+        ))?;
+        for (hdr, usc_hdr) in hdrs.iter_mut().zip(&usc_hdrs[..msg_count]) {
+            *hdr = usc_hdr.into();
+        }
+        Poll::Ready(Ok(msg_count))
+    }
+
+    fn recv_buffers(&self) -> (usize, usize) {
+        ArcUsc::recv_buffers(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::path::{Pathway, ViaPathWayExt};
+
+    // 每次至多发送2个数据报，记录发送的数据报及其包头
+    #[derive(Default)]
+    struct MockIo(Mutex<Vec<(Vec<u8>, PacketHeader)>>);
+
+    impl QuicIo for MockIo {
+        fn local_addr(&self) -> SocketAddr {
+            "127.0.0.1:4433".parse().unwrap()
+        }
+
+        fn poll_send(
+            &self,
+            _: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+            hdr: &PacketHeader,
+        ) -> Poll<io::Result<usize>> {
+            let mut sent = self.0.lock().unwrap();
+            for buf in bufs.iter().take(2) {
+                sent.push((buf.to_vec(), *hdr));
+            }
+            Poll::Ready(Ok(bufs.len().min(2)))
+        }
+
+        fn poll_recv(
+            &self,
+            _: &mut Context<'_>,
+            _: &mut [IoSliceMut<'_>],
+            _: &mut [PacketHeader],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Pending
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_via_custom_io() {
+        let mock = Arc::new(MockIo::default());
+        let usc: Arc<dyn QuicIo> = mock.clone();
+        assert_eq!(usc.max_segments(), 1);
+        assert_eq!(usc.recv_buffers(), (1, RECV_BUFFER_SIZE));
+
+        let remote: SocketAddr = "127.0.0.1:8443".parse().unwrap();
+        let pathway = Pathway::Direct {
+            local: usc.local_addr(),
+            remote,
+        };
+        let payloads = (0..5u8).map(|i| vec![i; 100]).collect::<Vec<_>>();
+        let bufs = payloads.iter().map(|p| IoSlice::new(p)).collect::<Vec<_>>();
+        usc.clone()
+            .send_all_via_pathway(&bufs, pathway, Some(EcnCodepoint::Ect0))
+            .await
+            .unwrap();
+
+        // 分多次发送完所有数据报
+        let sent = mock.0.lock().unwrap();
+        assert_eq!(
+            sent.iter().map(|(p, ..)| p.clone()).collect::<Vec<_>>(),
+            payloads
+        );
+        assert!(sent
+            .iter()
+            .all(|(_, hdr)| hdr.dst == remote && hdr.ecn == Some(EcnCodepoint::Ect0)));
+    }
+}
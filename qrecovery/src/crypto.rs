@@ -166,8 +166,8 @@ mod recv {
 
     use bytes::{BufMut, Bytes};
     use qbase::{
-        error::Error,
-        frame::{CryptoFrame, ReceiveFrame},
+        error::{Error, ErrorKind},
+        frame::{BeFrame, CryptoFrame, ReceiveFrame},
         varint::VARINT_MAX,
     };
     use tokio::io::{AsyncRead, ReadBuf};
@@ -177,18 +177,36 @@ mod recv {
     #[derive(Debug)]
     pub(super) struct Recver {
         rcvbuf: RecvBuf,
+        // The maximum amount of data buffered beyond the read offset
+        rcvbuf_size: u64,
         read_waker: Option<Waker>,
     }
 
     impl Recver {
-        fn recv(&mut self, offset: u64, data: Bytes) {
-            assert!(offset + data.len() as u64 <= VARINT_MAX);
+        fn recv(&mut self, frame: &CryptoFrame, data: Bytes) -> Result<(), Error> {
+            let offset = frame.offset.into_inner();
+            let end = offset + data.len() as u64;
+            assert!(end <= VARINT_MAX);
+            // The out-of-order data must not grow the buffer without bound, see [Section 7.5](https://www.rfc-editor.org/rfc/rfc9000.html#section-7.5)
+            // of RFC 9000.
+            if end > self.rcvbuf.nread() + self.rcvbuf_size {
+                return Err(Error::new(
+                    ErrorKind::CryptoBufferExceeded,
+                    frame.frame_type(),
+                    format!(
+                        "{} bytes beyond the read offset exceed the crypto buffer size {}",
+                        end - self.rcvbuf.nread(),
+                        self.rcvbuf_size
+                    ),
+                ));
+            }
             self.rcvbuf.recv(offset, data);
             if self.rcvbuf.is_readable() {
                 if let Some(waker) = self.read_waker.take() {
                     waker.wake()
                 }
             }
+            Ok(())
         }
 
         fn poll_read<T: BufMut>(
@@ -230,25 +248,29 @@ mod recv {
         type Output = ();
 
         fn recv_frame(&self, (frame, data): &(CryptoFrame, Bytes)) -> Result<Self::Output, Error> {
-            self.0
-                .lock()
-                .unwrap()
-                .recv(frame.offset.into(), data.clone());
-            Ok(())
+            self.0.lock().unwrap().recv(frame, data.clone())
         }
     }
 
-    pub(super) fn create() -> ArcRecver {
+    pub(super) fn create(rcvbuf_size: usize) -> ArcRecver {
         Arc::new(Mutex::new(Recver {
             rcvbuf: RecvBuf::default(),
+            rcvbuf_size: rcvbuf_size as u64,
             read_waker: None,
         }))
     }
+
+    pub(super) fn set_rcvbuf_size(recver: &ArcRecver, rcvbuf_size: usize) {
+        recver.lock().unwrap().rcvbuf_size = rcvbuf_size as u64;
+    }
 }
 
 pub use recv::{CryptoStreamIncoming, CryptoStreamReader};
 pub use send::{CryptoStreamOutgoing, CryptoStreamWriter};
 
+/// The default maximum amount of the out-of-order CRYPTO data buffered in a packet number space.
+pub const DEFAULT_CRYPTO_BUFFER_SIZE: usize = 64 * 1024;
+
 /// Crypto data stream.
 #[derive(Debug, Clone)]
 pub struct CryptoStream {
@@ -258,13 +280,21 @@ pub struct CryptoStream {
 
 impl CryptoStream {
     /// Create a new instance of [`CryptoStream`] with the given buffer size.
-    pub fn new(sndbuf_size: usize, _rcvbuf_size: usize) -> Self {
+    ///
+    /// The `rcvbuf_size` limits the received data buffered beyond the offset read by the crypto
+    /// layer, receiving more results in a CRYPTO_BUFFER_EXCEEDED error.
+    pub fn new(sndbuf_size: usize, rcvbuf_size: usize) -> Self {
         Self {
             sender: send::create(sndbuf_size),
-            recver: recv::create(),
+            recver: recv::create(rcvbuf_size),
         }
     }
 
+    /// Change the limit of the received data buffered, see [`CryptoStream::new`].
+    pub fn set_rcvbuf_size(&self, rcvbuf_size: usize) {
+        recv::set_rcvbuf_size(&self.recver, rcvbuf_size);
+    }
+
     /// Create a [`CryptoStreamWriter`] which belong to this crypto stream.
     pub fn writer(&self) -> CryptoStreamWriter {
         CryptoStreamWriter(self.sender.clone())
@@ -289,6 +319,7 @@ impl CryptoStream {
 #[cfg(test)]
 mod tests {
     use qbase::{
+        error::ErrorKind,
         frame::{CryptoFrame, ReceiveFrame},
         varint::VarInt,
     };
@@ -298,7 +329,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_read() {
-        let crypto_stream: CryptoStream = CryptoStream::new(1000_0000, 0);
+        let crypto_stream: CryptoStream = CryptoStream::new(1000_0000, 16);
         crypto_stream
             .writer()
             .write_all(b"hello world")
@@ -319,4 +350,24 @@ mod tests {
         crypto_stream.reader().read_exact(&mut buf).await.unwrap();
         assert_eq!(&buf[..], b"hello world");
     }
+
+    #[test]
+    fn test_rcvbuf_exceeded() {
+        let crypto_stream = CryptoStream::new(0, 16);
+        let frame = |offset: u32, length: u32| {
+            (
+                CryptoFrame {
+                    offset: VarInt::from_u32(offset),
+                    length: VarInt::from_u32(length),
+                },
+                bytes::Bytes::from(vec![0u8; length as usize]),
+            )
+        };
+        assert!(crypto_stream.incoming().recv_frame(&frame(10, 6)).is_ok());
+        let error = crypto_stream
+            .incoming()
+            .recv_frame(&frame(12, 5))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::CryptoBufferExceeded);
+    }
 }
@@ -5,14 +5,15 @@ mod reader;
 mod recver;
 
 pub use incoming::{Incoming, IsStopped, UpdateWindow};
-use qbase::streamid::StreamId;
+use qbase::{flow::WindowTuner, streamid::StreamId};
 pub use rcvbuf::RecvBuf;
 pub use reader::Reader;
-pub use recver::ArcRecver;
+pub use recver::{ArcRecver, RecvState, RecvStats};
 
-/// Create the internal representations of [`Incoming`] and [`Reader`] with the given receiving buffer size.
+/// Create the internal representations of [`Incoming`] and [`Reader`] with the given receiving window.
 ///
-/// The size of the receiving buffer is the default flow control limit of a QUIC Stream.
-pub fn new(buf_size: u64, sid: StreamId) -> ArcRecver {
-    ArcRecver::new(buf_size, sid)
+/// The initial size of the window is the default flow control limit of a QUIC Stream, it's grown
+/// by the `tuner` as the data is read.
+pub fn new(tuner: WindowTuner, sid: StreamId) -> ArcRecver {
+    ArcRecver::new(tuner, sid)
 }
@@ -1,6 +1,6 @@
 use std::{
     future::Future,
-    ops::DerefMut,
+    ops::{Deref, DerefMut},
     pin::Pin,
     task::{Context, Poll},
 };
@@ -8,10 +8,11 @@ use std::{
 use bytes::Bytes;
 use qbase::{
     error::Error as QuicError,
-    frame::{ResetStreamFrame, StreamFrame},
+    error::ErrorKind,
+    frame::{BeFrame, ResetStreamAtFrame, ResetStreamFrame, StreamFrame},
 };
 
-use super::recver::{ArcRecver, Recver};
+use super::recver::{ArcRecver, RecvStats, Recver};
 use crate::streams::StreamReset;
 
 /// An struct for protocol layer to manage the receiving part of a stream.
@@ -63,6 +64,21 @@ impl Incoming {
         Ok(new_data_size)
     }
 
+    /// Returns the statistics of the receiving part of the stream, [`None`] if a connection error
+    /// occurred.
+    pub fn stats(&self) -> Option<RecvStats> {
+        self.0.recver().as_ref().ok().map(Recver::stats)
+    }
+
+    /// Returns whether all data sent by peer has been received, the stream frames received later
+    /// are just retransmissions.
+    pub fn is_all_rcvd(&self) -> bool {
+        matches!(
+            self.0.recver().deref(),
+            Ok(Recver::DataRcvd(_) | Recver::DataRead)
+        )
+    }
+
     /// Receive a stream reset frame from peer.
     ///
     /// If all data sent by the peer has not been received, receiving a stream reset frame will cause
@@ -83,15 +99,73 @@ impl Incoming {
                     *receiving_state =
                         Recver::ResetRcvd(StreamReset(reset_frame.app_error_code.into()));
                 }
+                // 带可靠大小重置的流，应用读完可靠部分后已进入重置状态，对方仍可能发来RESET_STREAM
                 _ => {
-                    log::error!("there is sth wrong, ignored recv_reset");
-                    unreachable!();
+                    log::debug!("ignored reset frame {:?}", reset_frame);
                 }
             }
         }
         Ok(())
     }
 
+    /// Receive a [`RESET_STREAM_AT frame`] from peer.
+    ///
+    /// Unlike [`Incoming::recv_reset`], the data before the reliable size are still received and
+    /// delivered to the application, the read calls will return an error after all the data before
+    /// the reliable size have been read.
+    ///
+    /// Return `true` if the stream is reset right away, because the data before the reliable size
+    /// have been read, or the stream has been closed.
+    ///
+    /// [`RESET_STREAM_AT frame`]: https://www.ietf.org/archive/id/draft-ietf-quic-reliable-stream-reset-06.html#name-reset_stream_at-frame
+    pub fn recv_reset_at(&self, reset_frame: &ResetStreamAtFrame) -> Result<bool, QuicError> {
+        if reset_frame.reliable_size > reset_frame.final_size {
+            return Err(QuicError::new(
+                ErrorKind::FrameEncoding,
+                reset_frame.frame_type(),
+                format!(
+                    "{} reset with a reliable size {} larger than the final size {}",
+                    reset_frame.stream_id, reset_frame.reliable_size, reset_frame.final_size
+                ),
+            ));
+        }
+        let mut recver = self.0.recver();
+        let inner = recver.deref_mut();
+        if let Ok(receiving_state) = inner {
+            let reset = StreamReset(reset_frame.app_error_code.into());
+            match receiving_state {
+                Recver::Recv(r) => {
+                    let size_known = r.recv_reset_at(reset_frame)?;
+                    if size_known.reliable_reset().is_some() {
+                        *receiving_state = Recver::ResetRcvd(reset);
+                        return Ok(true);
+                    }
+                    *receiving_state = Recver::SizeKnown(size_known);
+                }
+                Recver::SizeKnown(r) => {
+                    if r.recv_reset_at(reset_frame)? {
+                        *receiving_state = Recver::ResetRcvd(reset);
+                        return Ok(true);
+                    }
+                }
+                _ => {
+                    log::debug!("ignored reset frame {:?}", reset_frame);
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Called when the peer reports it's blocked at `limit` with a STREAM_DATA_BLOCKED frame.
+    ///
+    /// The receive window grows upon the next update if the peer is blocked by the current one.
+    pub fn on_data_blocked(&self, limit: u64) {
+        if let Ok(Recver::Recv(r)) = self.0.recver().deref_mut() {
+            r.on_data_blocked(limit);
+        }
+    }
+
     /// Called when a connecion error occured
     ///
     /// After the connection error occured, trying to read the data from [`Reader`] will result an
@@ -1,65 +1,45 @@
 //！ An implementation of the receiving buffer for stream data.
 
-use std::{collections::VecDeque, fmt};
+use std::{collections::BTreeMap, fmt};
 
-use bytes::{BufMut, Bytes};
-
-/// 一段连续的数据片段，每个片段都是Bytes
-#[derive(Debug, Default)]
-struct Segment {
-    offset: u64,
-    length: u64,
-    fragments: VecDeque<Bytes>,
-}
-
-impl fmt::Display for Segment {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{}..{}]", self.offset, self.offset + self.length)
-    }
-}
-
-impl Segment {
-    fn new_with_data(offset: u64, data: Bytes) -> Self {
-        Segment {
-            offset,
-            length: data.len() as u64,
-            fragments: vec![data].into(),
-        }
-    }
-
-    fn prepend(&mut self, data: Bytes) {
-        self.offset -= data.len() as u64;
-        self.length += data.len() as u64;
-        self.fragments.push_front(data);
-    }
-
-    fn append(&mut self, data: Bytes) {
-        self.length += data.len() as u64;
-        self.fragments.push_back(data);
-    }
-
-    fn take(&mut self) -> Self {
-        std::mem::take(self)
-    }
-}
+use bytes::{BufMut, Bytes, BytesMut};
 
 /// Received data of a stream is stored in [`RecvBuf`].
 ///
-/// The receiving buffer is relatively simple, as it receives segmented data
-/// that may not be continuous. It sequentially stores the received data
-/// fragments and then reassembles them into a continuous data stream for
-/// future reading by the application layer.
+/// The receiving buffer receives segmented data that may not be continuous, and reassembles them
+/// into a continuous data stream for future reading by the application layer.
+///
+/// The received fragments are kept in a range set ordered by their offsets, only the parts that
+/// fill the gaps between the data already received are stored, so the overlapping ranges are never
+/// buffered twice. Receiving a fragment costs O(log n) plus the number of the ranges it overlaps,
+/// which keeps the highly out-of-order delivery, such as the reordering or the multipath, cheap.
 #[derive(Default, Debug)]
 pub struct RecvBuf {
     nread: u64,
-    segments: VecDeque<Segment>,
+    // 从0开始连续接收到的数据的末尾，不小于nread
+    contiguous: u64,
+    // 互不重叠的数据片段，以片段的起始位置为键
+    fragments: BTreeMap<u64, Bytes>,
 }
 
 impl fmt::Display for RecvBuf {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "RecvBuf(offset={}, segments=[", self.nread)?;
-        for segment in &self.segments {
-            write!(f, "{}", segment)?;
+        // 相邻的片段合并成一段显示
+        let mut segment: Option<(u64, u64)> = None;
+        for (&start, frag) in &self.fragments {
+            let end = start + frag.len() as u64;
+            segment = match segment {
+                Some((seg_start, seg_end)) if seg_end == start => Some((seg_start, end)),
+                Some((seg_start, seg_end)) => {
+                    write!(f, "[{seg_start}..{seg_end}]")?;
+                    Some((start, end))
+                }
+                None => Some((start, end)),
+            };
+        }
+        if let Some((seg_start, seg_end)) = segment {
+            write!(f, "[{seg_start}..{seg_end}]")?;
         }
         write!(f, "])")
     }
@@ -68,7 +48,7 @@ impl fmt::Display for RecvBuf {
 impl RecvBuf {
     /// Returns whether the receiving buffer is empty.
     pub fn is_empty(&self) -> bool {
-        self.segments.is_empty()
+        self.fragments.is_empty()
     }
 
     /// Returns how many continuous data have been read.
@@ -141,160 +121,36 @@ impl RecvBuf {
             offset = self.nread;
         }
 
-        match self.segments.binary_search_by(|s| s.offset.cmp(&offset)) {
-            // 恰好落在一个片段上
-            Ok(seg_idx) => {
-                let new_data_size = self.overlap_seg(seg_idx, data);
-                self.try_merge(seg_idx);
-                new_data_size
-            }
-            // 并没有落在一个片段上，offset比任何一个片段都小
-            Err(0) => {
-                // 优先尝试添加到后一个片段之前
-                if let Some(new_data_size) = self.try_prepend(0, offset, &data) {
-                    return new_data_size;
-                }
-                self.insert(0, Segment::new_with_data(offset, data))
-            }
-            // segments[recommend].offset < offset
-            Err(recommend) => {
-                // 优先尝试添加到前一个片段之后
-                if let Some(new_data_size) = self.try_append(recommend - 1, offset, &data) {
-                    return new_data_size;
-                }
-                // 再尝试添加到后一个片段之前
-                if let Some(new_data_size) = self.try_prepend(recommend, offset, &data) {
-                    return new_data_size;
-                }
-                self.insert(recommend, Segment::new_with_data(offset, data))
-            }
-        }
-    }
-
-    // 返回片段之间是否有连接
-    // 如果有连接，返回 Some(new_data_size)，否则返回 None
-    fn try_prepend(&mut self, seg_idx: usize, offset: u64, data: &Bytes) -> Option<usize> {
-        let data_end = offset + data.len() as u64;
+        let end = offset + data.len() as u64;
         let mut new_data_size = 0;
-        match self.segments.get(seg_idx) {
-            Some(next_seg) if data_end >= next_seg.offset => {
-                let end = (next_seg.offset - offset) as usize;
-                let next_end = next_seg.offset + next_seg.length;
-                self.segments[seg_idx].prepend(data.slice(..end));
-                new_data_size += end;
-                // 有可能被追加在前的片段覆盖了整个段
-                if data_end > next_end {
-                    let remain = data.slice((next_end - offset) as usize..);
-                    if let Some(new_data) = self.try_append(seg_idx, next_end, &remain) {
-                        new_data_size += new_data;
-                    }
-                }
-                Some(new_data_size)
-            }
-            _ => None,
-        }
-    }
-
-    // 需要 offset >= segs[seg_idx].offset
-    // 返回片段之间是否有连接
-    // 如果有连接，返回 Some(new_data_size)，否则返回 None
-    fn try_append(&mut self, seg_idx: usize, offset: u64, data: &Bytes) -> Option<usize> {
-        let pre_seg = &self.segments[seg_idx];
-        let data_end = offset + data.len() as u64;
-        let pre_seg_end = pre_seg.offset + pre_seg.length;
-
-        // 已经完全被前一个片段包含，不需要处理
-        if data_end <= pre_seg_end {
-            return Some(0);
-        }
-
-        // 完全和前一个片段没有连接
-        if offset > pre_seg_end {
-            return None;
-        }
-
-        let start = if offset < pre_seg_end {
-            (pre_seg_end - offset) as usize
-        } else {
-            0
+        // 前一个片段可能覆盖了开头的部分
+        let mut pos = match self.fragments.range(..=offset).next_back() {
+            Some((&start, frag)) => offset.max(start + frag.len() as u64),
+            None => offset,
         };
-        // 可能这段数据已经覆盖了下一段数据
-        let next_offset = self.segments.get(seg_idx + 1).map(|seg| seg.offset);
-        let end = match next_offset {
-            Some(next_offset) => (next_offset - offset) as usize,
-            None => data.len(),
-        }
-        .min(data.len());
-
-        let mut new_data_size = 0;
-        new_data_size += end - start;
-        self.segments[seg_idx].append(data.slice(start..end));
-
-        // 有可能片段和下一个段有连接
-        if data.len() > end {
-            new_data_size += self.overlap_seg(seg_idx + 1, data.slice(end..));
-        }
-        self.try_merge(seg_idx);
-        Some(new_data_size)
-    }
-
-    fn insert(&mut self, at: usize, seg: Segment) -> usize {
-        let new_data_size = seg.length as usize;
-        self.segments.insert(at, seg);
-        new_data_size
-    }
-
-    // 不同于 append 和 prepend，这是对于和段从头开始重合的情况
-    // 是append的特化情况
-    // 返回新增的数据字节数
-    fn overlap_seg(&mut self, mut seg_idx: usize, mut data: Bytes) -> usize {
-        let mut new_data_size = 0;
-        loop {
-            let cur_seg = &self.segments[seg_idx];
-            let offset = cur_seg.offset;
-
-            if data.len() as u64 <= cur_seg.length {
-                break new_data_size;
+        // 只保存落在空隙中的部分，每次跳过一个已接收的片段
+        while pos < end {
+            let next = self
+                .fragments
+                .range(pos..)
+                .next()
+                .map(|(&start, frag)| (start, start + frag.len() as u64));
+            let gap_end = next.map_or(end, |(start, _)| start.min(end));
+            if gap_end > pos {
+                let range = (pos - offset) as usize..(gap_end - offset) as usize;
+                self.fragments.insert(pos, data.slice(range));
+                new_data_size += (gap_end - pos) as usize;
             }
-
-            let start = cur_seg.length as usize;
-
-            let end = match self.segments.get(seg_idx + 1).map(|next| next.offset) {
-                Some(next_offset) => (next_offset - offset) as usize,
-                None => data.len(),
+            match next {
+                Some((_, next_end)) => pos = next_end,
+                None => break,
             }
-            .min(data.len());
-
-            self.segments[seg_idx].append(data.slice(start..end));
-
-            new_data_size += end - start;
-            if data.len() > end {
-                data = data.slice(end..);
-                seg_idx += 1;
-            } else {
-                break new_data_size;
-            }
-
-            // 不在这里merge，而是在外部，是因为append可能多创造一个连接段
         }
-    }
-
-    fn try_merge(&mut self, mut idx: usize) {
-        let start_idx = idx;
-        let mut cur_seg = self.segments[idx].take();
-        while idx + 1 < self.segments.len() {
-            let next_seg = &mut self.segments[idx + 1];
-            if next_seg.offset != cur_seg.offset + cur_seg.length {
-                break;
-            }
 
-            cur_seg.fragments.append(&mut next_seg.fragments);
-            cur_seg.length += next_seg.length;
-            idx += 1;
+        while let Some(frag) = self.fragments.get(&self.contiguous) {
+            self.contiguous += frag.len() as u64;
         }
-
-        self.segments[idx] = cur_seg;
-        self.segments.drain(start_idx..idx);
+        new_data_size
     }
 
     /// Try to read continuous data from [`RecvBuf`] into the buffer passed in.
@@ -329,26 +185,100 @@ impl RecvBuf {
     ///
     /// ```
     pub fn try_read(&mut self, buf: &mut impl BufMut) -> Option<usize> {
-        let mut seg = self.segments.pop_front()?;
-        if seg.offset != self.nread {
-            self.segments.push_front(seg);
+        if !self.is_readable() {
             return None;
         }
         let origin = buf.remaining_mut();
-        while let Some(frag) = seg.fragments.pop_front() {
-            let n = buf.remaining_mut().min(frag.len());
-            buf.put_slice(&frag[..n]);
-            seg.offset += n as u64;
-            self.nread = seg.offset;
-            if n < frag.len() {
-                seg.fragments.push_front(frag.slice(n..));
-                self.segments.push_front(seg);
+        while buf.has_remaining_mut() {
+            let Some((_, frag)) = self.try_read_chunk(buf.remaining_mut()) else {
                 break;
-            }
+            };
+            buf.put_slice(&frag);
         }
         Some(origin - buf.remaining_mut())
     }
 
+    /// Try to read a chunk of continuous data from [`RecvBuf`] without copying.
+    ///
+    /// The returned [`Bytes`] references the received fragment directly, and is no longer than
+    /// `max_len`. The offset of the chunk in the stream is returned along with it.
+    ///
+    /// If the following data is not continuous or there is no data, this method returns [`None`].
+    /// An empty chunk is returned if `max_len` is 0 but there is data to read, so [`None`] always
+    /// means nothing is readable.
+    ///
+    /// # Example
+    ///
+    /// ``` rust
+    /// # use bytes::Bytes;
+    /// # use qrecovery::recv::RecvBuf;
+    /// let mut recvbuf = RecvBuf::default();
+    /// recvbuf.recv(0, Bytes::from("hello"));
+    /// recvbuf.recv(5, Bytes::from(" world"));
+    /// recvbuf.recv(12, Bytes::from("!"));
+    /// // recvbuf:  hello world !
+    /// // readable: ^^^^^^^^^^^
+    ///
+    /// assert_eq!(recvbuf.try_read_chunk(3), Some((0, Bytes::from("hel"))));
+    /// assert_eq!(recvbuf.try_read_chunk(usize::MAX), Some((3, Bytes::from("lo"))));
+    /// assert_eq!(recvbuf.try_read_chunk(usize::MAX), Some((5, Bytes::from(" world"))));
+    /// assert_eq!(recvbuf.try_read_chunk(usize::MAX), None);
+    /// assert_eq!(recvbuf.nread(), 11);
+    /// ```
+    pub fn try_read_chunk(&mut self, max_len: usize) -> Option<(u64, Bytes)> {
+        if !self.is_readable() {
+            return None;
+        }
+        if max_len == 0 {
+            return Some((self.nread, Bytes::new()));
+        }
+        let mut chunk = self.fragments.remove(&self.nread)?;
+        let offset = self.nread;
+        if chunk.len() > max_len {
+            self.fragments
+                .insert(offset + max_len as u64, chunk.split_off(max_len));
+        }
+        self.nread += chunk.len() as u64;
+        Some((offset, chunk))
+    }
+
+    /// Returns up to `max_len` bytes of the continuous data to read, without consuming them.
+    ///
+    /// The data is not copied if it's in a single fragment received.
+    ///
+    /// # Example
+    ///
+    /// ``` rust
+    /// # use bytes::Bytes;
+    /// # use qrecovery::recv::RecvBuf;
+    /// let mut recvbuf = RecvBuf::default();
+    /// recvbuf.recv(0, Bytes::from("GET "));
+    /// recvbuf.recv(4, Bytes::from("/index"));
+    /// assert_eq!(recvbuf.peek(3), Bytes::from("GET"));
+    /// assert_eq!(recvbuf.peek(6), Bytes::from("GET /i"));
+    /// assert_eq!(recvbuf.nread(), 0);
+    /// ```
+    pub fn peek(&self, max_len: usize) -> Bytes {
+        let len = (self.contiguous - self.nread).min(max_len as u64) as usize;
+        let mut fragments = self.fragments.range(self.nread..).map(|(_, frag)| frag);
+        match fragments.next() {
+            Some(first) if first.len() >= len => first.slice(..len),
+            Some(first) => {
+                let mut peeked = BytesMut::with_capacity(len);
+                peeked.extend_from_slice(first);
+                for frag in fragments {
+                    let n = (len - peeked.len()).min(frag.len());
+                    peeked.extend_from_slice(&frag[..n]);
+                    if peeked.len() == len {
+                        break;
+                    }
+                }
+                peeked.freeze()
+            }
+            None => Bytes::new(),
+        }
+    }
+
     /// The length of continuous data received, which can be compared with the final sizeknown as `SizeKnown`.
     ///
     /// If they match, it indicates that all the data has been received.
@@ -379,23 +309,13 @@ impl RecvBuf {
     /// assert_eq!(recvbuf.available(), 11);
     /// ```
     pub fn available(&self) -> u64 {
-        if self
-            .segments
-            .front()
-            .is_some_and(|seg| seg.offset == self.nread)
-        {
-            self.nread + self.segments[0].length
-        } else {
-            self.nread
-        }
+        self.contiguous
     }
 
     /// Once the received data becomes continuous, it becomes readable. If necessary (if the application
     /// layer is blocked on reading), it is necessary to notify the application layer to read.
     pub fn is_readable(&self) -> bool {
-        !self.segments.is_empty()
-            && self.segments[0].offset == self.nread
-            && self.segments[0].length > 0
+        self.contiguous > self.nread
     }
 }
 
@@ -403,36 +323,41 @@ impl RecvBuf {
 mod tests {
     use super::*;
 
+    fn fragments(buf: &RecvBuf) -> Vec<(u64, Bytes)> {
+        buf.fragments
+            .iter()
+            .map(|(&offset, frag)| (offset, frag.clone()))
+            .collect()
+    }
+
     #[test]
     fn test_recvbuf_recv() {
         let mut buf = RecvBuf::default();
         assert_eq!(buf.recv(0, Bytes::from("hello")), 5);
         assert_eq!(buf.recv(6, Bytes::from("world")), 5);
-
-        assert_eq!(buf.segments.len(), 2);
-        assert_eq!(buf.segments[0].offset, 0);
-        assert_eq!(buf.segments[0].length, 5);
-        assert_eq!(buf.segments[0].fragments.len(), 1);
-        assert_eq!(buf.segments[0].fragments[0], Bytes::from("hello"));
-        assert_eq!(buf.segments[1].offset, 6);
-        assert_eq!(buf.segments[1].length, 5);
-        assert_eq!(buf.segments[1].fragments.len(), 1);
-        assert_eq!(buf.segments[1].fragments[0], Bytes::from("world"));
+        assert_eq!(
+            fragments(&buf),
+            [(0, Bytes::from("hello")), (6, Bytes::from("world"))]
+        );
+        assert_eq!(buf.available(), 5);
 
         assert_eq!(buf.recv(5, Bytes::from(" ")), 1);
-        assert_eq!(buf.segments.len(), 1);
-        assert_eq!(buf.segments[0].offset, 0);
-        assert_eq!(buf.segments[0].length, 11);
-        assert_eq!(buf.segments[0].fragments.len(), 3);
-        assert_eq!(buf.segments[0].fragments[0], Bytes::from("hello"));
-        assert_eq!(buf.segments[0].fragments[1], Bytes::from(" "));
-        assert_eq!(buf.segments[0].fragments[2], Bytes::from("world"));
+        assert_eq!(
+            fragments(&buf),
+            [
+                (0, Bytes::from("hello")),
+                (5, Bytes::from(" ")),
+                (6, Bytes::from("world"))
+            ]
+        );
+        assert_eq!(buf.available(), 11);
 
         assert_eq!(buf.recv(12, Bytes::from("hello")), 5);
         assert_eq!(buf.recv(6, Bytes::from("world.hell")), 1);
-        assert_eq!(buf.segments[0].fragments[2], Bytes::from("world"));
-        assert_eq!(buf.segments[0].fragments[3], Bytes::from("."));
-        assert_eq!(buf.segments[0].fragments[4], Bytes::from("hello"));
+        assert_eq!(fragments(&buf)[3], (11, Bytes::from(".")));
+        assert_eq!(fragments(&buf)[4], (12, Bytes::from("hello")));
+        assert_eq!(buf.available(), 17);
+        assert_eq!(buf.to_string(), "RecvBuf(offset=0, segments=[[0..17]])");
     }
 
     #[test]
@@ -442,13 +367,14 @@ mod tests {
         assert_eq!(buf.recv(6, Bytes::from("world")), 5);
         assert_eq!(buf.recv(5, Bytes::from(" wor")), 1);
 
-        assert_eq!(buf.segments.len(), 1);
-        assert_eq!(buf.segments[0].offset, 0);
-        assert_eq!(buf.segments[0].length, 11);
-        assert_eq!(buf.segments[0].fragments.len(), 3);
-        assert_eq!(buf.segments[0].fragments[0], Bytes::from("hello"));
-        assert_eq!(buf.segments[0].fragments[1], Bytes::from(" "));
-        assert_eq!(buf.segments[0].fragments[2], Bytes::from("world"));
+        assert_eq!(
+            fragments(&buf),
+            [
+                (0, Bytes::from("hello")),
+                (5, Bytes::from(" ")),
+                (6, Bytes::from("world"))
+            ]
+        );
     }
 
     #[test]
@@ -458,14 +384,16 @@ mod tests {
         assert_eq!(buf.recv(6, Bytes::from("world")), 5);
         assert_eq!(buf.recv(5, Bytes::from(" world!")), 2);
 
-        assert_eq!(buf.segments.len(), 1);
-        assert_eq!(buf.segments[0].offset, 0);
-        assert_eq!(buf.segments[0].length, 12);
-        assert_eq!(buf.segments[0].fragments.len(), 4);
-        assert_eq!(buf.segments[0].fragments[0], Bytes::from("hello"));
-        assert_eq!(buf.segments[0].fragments[1], Bytes::from(" "));
-        assert_eq!(buf.segments[0].fragments[2], Bytes::from("world"));
-        assert_eq!(buf.segments[0].fragments[3], Bytes::from("!"));
+        assert_eq!(
+            fragments(&buf),
+            [
+                (0, Bytes::from("hello")),
+                (5, Bytes::from(" ")),
+                (6, Bytes::from("world")),
+                (11, Bytes::from("!"))
+            ]
+        );
+        assert_eq!(buf.available(), 12);
     }
 
     #[test]
@@ -474,12 +402,11 @@ mod tests {
         assert_eq!(buf.recv(2, Bytes::from("4514")), 4);
         assert_eq!(buf.recv(0, Bytes::from("1199")), 2);
 
-        assert_eq!(buf.segments.len(), 1);
-        assert_eq!(buf.segments[0].offset, 0);
-        assert_eq!(buf.segments[0].length, 6);
-        assert_eq!(buf.segments[0].fragments.len(), 2);
-        assert_eq!(buf.segments[0].fragments[0], Bytes::from("11"));
-        assert_eq!(buf.segments[0].fragments[1], Bytes::from("4514"));
+        assert_eq!(
+            fragments(&buf),
+            [(0, Bytes::from("11")), (2, Bytes::from("4514"))]
+        );
+        assert_eq!(buf.available(), 6);
     }
 
     #[test]
@@ -489,17 +416,20 @@ mod tests {
         assert_eq!(buf.recv(7, Bytes::from("world")), 5);
         assert_eq!(buf.recv(6, Bytes::from(" world!")), 2);
 
-        assert_eq!(buf.segments.len(), 2);
-        assert_eq!(buf.segments[0].offset, 0);
-        assert_eq!(buf.segments[0].length, 5);
-        assert_eq!(buf.segments[0].fragments.len(), 1);
-        assert_eq!(buf.segments[0].fragments[0], Bytes::from("hello"));
-        assert_eq!(buf.segments[1].offset, 6);
-        assert_eq!(buf.segments[1].length, 7);
-        assert_eq!(buf.segments[1].fragments.len(), 3);
-        assert_eq!(buf.segments[1].fragments[0], Bytes::from(" "));
-        assert_eq!(buf.segments[1].fragments[1], Bytes::from("world"));
-        assert_eq!(buf.segments[1].fragments[2], Bytes::from("!"));
+        assert_eq!(
+            fragments(&buf),
+            [
+                (0, Bytes::from("hello")),
+                (6, Bytes::from(" ")),
+                (7, Bytes::from("world")),
+                (12, Bytes::from("!"))
+            ]
+        );
+        assert_eq!(buf.available(), 5);
+        assert_eq!(
+            buf.to_string(),
+            "RecvBuf(offset=0, segments=[[0..5][6..13]])"
+        );
     }
 
     #[test]
@@ -509,31 +439,27 @@ mod tests {
         assert_eq!(buf.recv(9, Bytes::from("you")), 3);
         assert_eq!(buf.recv(5, Bytes::from("are")), 3);
 
-        assert_eq!(buf.segments.len(), 3);
-        assert_eq!(buf.segments[0].offset, 0);
-        assert_eq!(buf.segments[0].length, 3);
-        assert_eq!(buf.segments[0].fragments.len(), 1);
-        assert_eq!(buf.segments[0].fragments[0], Bytes::from("how"));
-        assert_eq!(buf.segments[1].offset, 5);
-        assert_eq!(buf.segments[1].length, 3);
-        assert_eq!(buf.segments[1].fragments.len(), 1);
-        assert_eq!(buf.segments[1].fragments[0], Bytes::from("are"));
-        assert_eq!(buf.segments[2].offset, 9);
-        assert_eq!(buf.segments[2].length, 3);
-        assert_eq!(buf.segments[2].fragments.len(), 1);
-        assert_eq!(buf.segments[2].fragments[0], Bytes::from("you"));
+        assert_eq!(
+            fragments(&buf),
+            [
+                (0, Bytes::from("how")),
+                (5, Bytes::from("are")),
+                (9, Bytes::from("you"))
+            ]
+        );
 
         assert_eq!(buf.recv(3, Bytes::from("w are you")), 3);
-
-        assert_eq!(buf.segments.len(), 1);
-        assert_eq!(buf.segments[0].offset, 0);
-        assert_eq!(buf.segments[0].length, 12);
-        assert_eq!(buf.segments[0].fragments.len(), 5);
-        assert_eq!(buf.segments[0].fragments[0], Bytes::from("how"));
-        assert_eq!(buf.segments[0].fragments[1], Bytes::from("w "));
-        assert_eq!(buf.segments[0].fragments[2], Bytes::from("are"));
-        assert_eq!(buf.segments[0].fragments[3], Bytes::from(" "));
-        assert_eq!(buf.segments[0].fragments[4], Bytes::from("you"));
+        assert_eq!(
+            fragments(&buf),
+            [
+                (0, Bytes::from("how")),
+                (3, Bytes::from("w ")),
+                (5, Bytes::from("are")),
+                (8, Bytes::from(" ")),
+                (9, Bytes::from("you"))
+            ]
+        );
+        assert_eq!(buf.available(), 12);
     }
 
     #[test]
@@ -554,6 +480,29 @@ mod tests {
         assert_eq!(dst[..11], b"hello world"[..]);
     }
 
+    #[test]
+    fn test_recvbuf_read_chunk() {
+        let mut rcvbuf = RecvBuf::default();
+        assert_eq!(rcvbuf.recv(0, Bytes::from("hello")), 5);
+        assert_eq!(rcvbuf.recv(6, Bytes::from("world")), 5);
+
+        assert_eq!(rcvbuf.try_read_chunk(2), Some((0, Bytes::from("he"))));
+        assert_eq!(rcvbuf.available(), 5);
+        assert_eq!(rcvbuf.try_read_chunk(10), Some((2, Bytes::from("llo"))));
+        assert_eq!(rcvbuf.try_read_chunk(10), None);
+
+        assert_eq!(rcvbuf.recv(5, Bytes::from(" ")), 1);
+        assert_eq!(rcvbuf.available(), 11);
+
+        let mut dst = [0u8; 3];
+        rcvbuf.try_read(&mut &mut dst[..]);
+        assert_eq!(&dst, b" wo");
+        assert_eq!(rcvbuf.available(), 11);
+        assert_eq!(rcvbuf.try_read_chunk(10), Some((8, Bytes::from("rld"))));
+        assert!(rcvbuf.is_empty());
+        assert_eq!(rcvbuf.nread(), 11);
+    }
+
     #[test]
     fn test_rcvbuf_recv_overlap_seg() {
         let mut buf = RecvBuf::default();
@@ -567,4 +516,43 @@ mod tests {
         assert_eq!(buf.recv(12, Bytes::from("00")), 2);
         assert_eq!(buf.recv(0, Bytes::from("hello world")), 7);
     }
+
+    #[test]
+    fn test_rcvbuf_read_empty_chunk() {
+        let mut buf = RecvBuf::default();
+        assert_eq!(buf.try_read_chunk(0), None);
+        buf.recv(0, Bytes::from("hello"));
+        // 不消耗数据，也不会被误认为没有可读的数据
+        assert_eq!(buf.try_read_chunk(0), Some((0, Bytes::new())));
+        assert_eq!(buf.nread(), 0);
+        assert_eq!(buf.try_read_chunk(5), Some((0, Bytes::from("hello"))));
+        assert_eq!(buf.try_read_chunk(0), None);
+    }
+
+    #[test]
+    fn test_rcvbuf_recv_out_of_order() {
+        let data = (0..=255u8).cycle().take(4096).collect::<Bytes>();
+        let mut buf = RecvBuf::default();
+        // 倒序接收每隔一个的小片段，再用覆盖多个片段的大片段填补空隙
+        let mut new_data_size = 0;
+        for offset in (0..4096).step_by(2).rev() {
+            new_data_size += buf.recv(offset as u64, data.slice(offset..offset + 1));
+        }
+        assert_eq!(new_data_size, 2048);
+        assert_eq!(buf.available(), 1);
+        for offset in (0..4096).step_by(512) {
+            new_data_size += buf.recv(offset as u64, data.slice(offset..offset + 512));
+            // 重复的数据不会再被保存
+            assert_eq!(buf.recv(offset as u64, data.slice(offset..offset + 512)), 0);
+        }
+        assert_eq!(new_data_size, 4096);
+        assert_eq!(buf.fragments.values().map(Bytes::len).sum::<usize>(), 4096);
+        assert_eq!(buf.available(), 4096);
+
+        let mut dst = Vec::new();
+        assert_eq!(buf.try_read(&mut dst), Some(4096));
+        assert_eq!(dst, data);
+        assert!(buf.is_empty());
+        assert_eq!(buf.recv(1000, data.slice(1000..2000)), 0);
+    }
 }
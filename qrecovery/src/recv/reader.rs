@@ -1,12 +1,14 @@
 use std::{
     io,
-    ops::DerefMut,
+    ops::{Deref, DerefMut},
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
+use bytes::Bytes;
 use qbase::{streamid::StreamId, varint::VARINT_MAX};
-use tokio::io::{AsyncRead, ReadBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
 
 use super::recver::{ArcRecver, Recver};
 
@@ -88,10 +90,106 @@ impl Reader {
         }
     }
 
+    /// Returns up to `max_len` bytes of the data to read without consuming them, such as the
+    /// preamble of the stream to dispatch the stream by, see [`AcceptBiStreamWithPeek`].
+    ///
+    /// The data returned may be less than `max_len` if more data has not arrived yet, it's empty
+    /// if nothing is readable.
+    ///
+    /// [`AcceptBiStreamWithPeek`]: crate::streams::AcceptBiStreamWithPeek
+    pub fn peek(&self, max_len: usize) -> Bytes {
+        match self.0.recver().deref() {
+            Ok(recver) => recver.peek(max_len),
+            Err(_) => Bytes::new(),
+        }
+    }
+
     /// Returns the stream ID of the stream.
     pub fn stream_id(&self) -> StreamId {
         self.0.sid()
     }
+
+    /// Set the flow-control window of the stream, overriding the initial one of the transport
+    /// parameters, e.g. a large window for a bulk transfer while the control streams stay small.
+    ///
+    /// It's expected to be called right after the stream is opened or accepted. A larger window
+    /// is advertised to the peer with a [`MAX_STREAM_DATA frame`] immediately, and it still grows
+    /// if the data is read in time; the credit already advertised can't be taken back, so a
+    /// smaller window takes effect gradually as the data is read.
+    ///
+    /// If all data has been received, or the stream has been reset, this method will do nothing.
+    ///
+    /// [`MAX_STREAM_DATA frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-max_stream_data-frames
+    pub fn set_receive_window(&self, window: u64) {
+        if let Ok(Recver::Recv(r)) = self.0.recver().deref_mut() {
+            r.set_window(window);
+        }
+    }
+
+    /// Read the next chunk of ordered data without copying it into a caller-provided buffer.
+    ///
+    /// The chunk is a [`Bytes`] referencing the receiving buffer directly, no longer than
+    /// `max_len`, paired with its offset in the stream.
+    ///
+    /// Returns `Ok(None)` if all data from peer has been read and the stream has been closed. Like
+    /// [`read`], an error indicates that the stream has been reset, or closed duo to other reasons.
+    ///
+    /// Reading chunks can be mixed with [`read`].
+    ///
+    /// [`read`]: tokio::io::AsyncReadExt::read
+    pub async fn read_chunk(&mut self, max_len: usize) -> io::Result<Option<(u64, Bytes)>> {
+        core::future::poll_fn(|cx| self.poll_read_chunk(cx, max_len)).await
+    }
+
+    /// Read data into `buf` like [`read`], but fail with [`io::ErrorKind::TimedOut`] if no data is
+    /// available within `timeout`.
+    ///
+    /// Only the pending read is failed, the stream and the connection are not affected, it's okay
+    /// to read again after that.
+    ///
+    /// [`read`]: tokio::io::AsyncReadExt::read
+    pub async fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        match tokio::time::timeout(timeout, self.read(buf)).await {
+            Ok(result) => result,
+            Err(_elapsed) => Err(io::Error::new(io::ErrorKind::TimedOut, "read timed out")),
+        }
+    }
+
+    /// Poll version of [`Reader::read_chunk`].
+    pub fn poll_read_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+        max_len: usize,
+    ) -> Poll<io::Result<Option<(u64, Bytes)>>> {
+        let mut recver = self.0.recver();
+        let receiving_state = recver.as_mut().map_err(|e| e.clone())?;
+        match receiving_state {
+            Recver::Recv(r) => r.poll_read_chunk(cx, max_len),
+            Recver::SizeKnown(r) => match r.reliable_reset() {
+                Some(reset) => {
+                    *receiving_state = Recver::ResetRead(reset);
+                    Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, reset)))
+                }
+                None => r.poll_read_chunk(cx, max_len),
+            },
+            Recver::DataRcvd(r) => {
+                let chunk = r.read_chunk(max_len);
+                if r.is_all_read() {
+                    *receiving_state = Recver::DataRead;
+                }
+                Poll::Ready(Ok(chunk))
+            }
+            Recver::DataRead => Poll::Ready(Ok(None)),
+            Recver::ResetRcvd(reset) => {
+                let reset = *reset;
+                *receiving_state = Recver::ResetRead(reset);
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, reset)))
+            }
+            Recver::ResetRead(reset) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+            }
+        }
+    }
 }
 
 impl AsyncRead for Reader {
@@ -105,7 +203,14 @@ impl AsyncRead for Reader {
         // 能相当清楚地看到应用层读取数据驱动的接收状态演变
         match receiving_state {
             Recver::Recv(r) => r.poll_read(cx, buf),
-            Recver::SizeKnown(r) => r.poll_read(cx, buf),
+            // 带可靠大小重置的流，读完可靠部分的数据后，才返回重置错误
+            Recver::SizeKnown(r) => match r.reliable_reset() {
+                Some(reset) => {
+                    *receiving_state = Recver::ResetRead(reset);
+                    Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, reset)))
+                }
+                None => r.poll_read(cx, buf),
+            },
             Recver::DataRcvd(r) => {
                 r.poll_read(buf);
                 if r.is_all_read() {
@@ -153,4 +258,95 @@ impl Drop for Reader {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use qbase::{
+        error::ErrorKind,
+        flow::WindowTuner,
+        frame::{ResetStreamAtFrame, StreamFrame},
+        varint::VarInt,
+    };
+
+    use super::*;
+    use crate::recv::Incoming;
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut reader = Reader(ArcRecver::new(
+            WindowTuner::new(10, 10, Default::default()),
+            sid,
+        ));
+        let mut buf = [0u8; 10];
+        let result = reader
+            .read_timeout(&mut buf, Duration::from_millis(10))
+            .await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        reader.stop(0);
+    }
+
+    #[tokio::test]
+    async fn test_read_empty_chunk() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut reader = Reader(ArcRecver::new(
+            WindowTuner::new(20, 20, Default::default()),
+            sid,
+        ));
+        let incoming = Incoming(reader.0.clone());
+
+        let frame = StreamFrame::new(sid, 0, 5);
+        incoming
+            .recv_data(&frame, Bytes::from_static(b"hello"))
+            .unwrap();
+        // 有数据可读时，长度为0的读取立即返回空块，不会一直等待
+        let chunk = reader.read_chunk(0).await.unwrap();
+        assert_eq!(chunk, Some((0, Bytes::new())));
+
+        let mut frame = StreamFrame::new(sid, 5, 6);
+        frame.set_eos_flag(true);
+        incoming
+            .recv_data(&frame, Bytes::from_static(b" world"))
+            .unwrap();
+        // 数据全部收到后，空块也不会被当作流的结束
+        let chunk = reader.read_chunk(0).await.unwrap();
+        assert_eq!(chunk, Some((0, Bytes::new())));
+        let chunk = reader.read_chunk(usize::MAX).await.unwrap();
+        assert_eq!(chunk, Some((0, Bytes::from_static(b"hello"))));
+        let chunk = reader.read_chunk(usize::MAX).await.unwrap();
+        assert_eq!(chunk, Some((5, Bytes::from_static(b" world"))));
+        assert_eq!(reader.read_chunk(0).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_recv_reset_at() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut reader = Reader(ArcRecver::new(
+            WindowTuner::new(20, 20, Default::default()),
+            sid,
+        ));
+        let incoming = Incoming(reader.0.clone());
+
+        let reset = ResetStreamAtFrame {
+            stream_id: sid,
+            app_error_code: VarInt::from_u32(1),
+            final_size: VarInt::from_u32(11),
+            reliable_size: VarInt::from_u32(5),
+        };
+        assert!(!incoming.recv_reset_at(&reset).unwrap());
+        // 可靠大小之前的数据仍会交付，读完之后才返回重置错误
+        let frame = StreamFrame::new(sid, 0, 5);
+        incoming
+            .recv_data(&frame, Bytes::from_static(b"hello"))
+            .unwrap();
+        let mut buf = [0u8; 10];
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 5);
+        let result = reader.read(&mut buf).await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+
+        let invalid = ResetStreamAtFrame {
+            reliable_size: VarInt::from_u32(12),
+            ..reset
+        };
+        let result = incoming.recv_reset_at(&invalid);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::FrameEncoding);
+    }
+}
@@ -2,12 +2,14 @@ use std::{
     io,
     sync::{Arc, Mutex, MutexGuard},
     task::{Context, Poll, Waker},
+    time::Instant,
 };
 
 use bytes::{BufMut, Bytes};
 use qbase::{
     error::{Error, ErrorKind},
-    frame::{BeFrame, ResetStreamFrame, StreamFrame},
+    flow::WindowTuner,
+    frame::{BeFrame, ResetStreamAtFrame, ResetStreamFrame, StreamFrame},
     streamid::StreamId,
 };
 
@@ -22,22 +24,30 @@ pub(super) struct Recv {
     stop_waker: Option<Waker>,
     largest_data_offset: u64,
     max_data_size: u64,
+    // The window starts from the buffer size, and grows if the data is read in time
+    tuner: WindowTuner,
     buf_exceeds_half_waker: Option<Waker>,
 }
 
 impl Recv {
-    pub(super) fn with(buf_size: u64) -> Self {
+    pub(super) fn with(tuner: WindowTuner) -> Self {
         Self {
             rcvbuf: rcvbuf::RecvBuf::default(),
             read_waker: None,
             stop_state: None,
             stop_waker: None,
             largest_data_offset: 0,
-            max_data_size: buf_size,
+            max_data_size: tuner.window(),
+            tuner,
             buf_exceeds_half_waker: None,
         }
     }
 
+    // Whether less than half of the window remains unread.
+    fn need_update_window(&self) -> bool {
+        self.rcvbuf.nread() + self.tuner.window() / 2 > self.max_data_size
+    }
+
     pub(super) fn recv(&mut self, stream_frame: &StreamFrame, body: Bytes) -> Result<usize, Error> {
         let begin = stream_frame.offset();
 
@@ -69,14 +79,7 @@ impl Recv {
     ) -> Poll<io::Result<()>> {
         if self.rcvbuf.is_readable() {
             self.rcvbuf.try_read(buf);
-
-            let threshold = 1_000_000;
-            if self.rcvbuf.nread() + threshold > self.max_data_size {
-                if let Some(waker) = self.buf_exceeds_half_waker.take() {
-                    waker.wake()
-                }
-            }
-
+            self.on_data_read();
             Poll::Ready(Ok(()))
         } else {
             self.read_waker = Some(cx.waker().clone());
@@ -84,11 +87,36 @@ impl Recv {
         }
     }
 
+    pub(super) fn poll_read_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+        max_len: usize,
+    ) -> Poll<io::Result<Option<(u64, Bytes)>>> {
+        match self.rcvbuf.try_read_chunk(max_len) {
+            Some(chunk) => {
+                self.on_data_read();
+                Poll::Ready(Ok(Some(chunk)))
+            }
+            None => {
+                self.read_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn on_data_read(&mut self) {
+        if self.need_update_window() {
+            if let Some(waker) = self.buf_exceeds_half_waker.take() {
+                waker.wake()
+            }
+        }
+    }
+
     pub(super) fn poll_update_window(&mut self, cx: &mut Context<'_>) -> Poll<Option<u64>> {
         assert!(self.buf_exceeds_half_waker.is_none());
-        let threshold = 1_000_000;
-        if self.rcvbuf.nread() + threshold > self.max_data_size {
-            self.max_data_size = self.rcvbuf.nread() + threshold * 2;
+        if self.need_update_window() {
+            let window = self.tuner.on_update(Instant::now());
+            self.max_data_size = self.rcvbuf.nread() + window;
             Poll::Ready(Some(self.max_data_size))
         } else {
             self.buf_exceeds_half_waker = Some(cx.waker().clone());
@@ -96,6 +124,23 @@ impl Recv {
         }
     }
 
+    // 已经通告的额度无法收回，窗口改小只影响之后的更新
+    pub(super) fn set_window(&mut self, window: u64) {
+        self.tuner.set_window(window);
+        if self.need_update_window() {
+            if let Some(waker) = self.buf_exceeds_half_waker.take() {
+                waker.wake()
+            }
+        }
+    }
+
+    pub(super) fn on_data_blocked(&mut self, limit: u64) {
+        // The frame of an old limit which has been raised is ignored
+        if limit >= self.max_data_size {
+            self.tuner.on_blocked();
+        }
+    }
+
     pub(super) fn poll_stop(&mut self, cx: &mut Context<'_>) -> Poll<Option<u64>> {
         if let Some(err_code) = self.stop_state {
             Poll::Ready(Some(err_code))
@@ -130,6 +175,7 @@ impl Recv {
             stop_state: self.stop_state.take(),
             read_waker: self.read_waker.take(),
             stop_waker: self.stop_waker.take(),
+            reliable_reset: None,
         }
     }
 
@@ -160,6 +206,27 @@ impl Recv {
         self.wake_all();
         Ok(final_size)
     }
+
+    pub(super) fn recv_reset_at(
+        &mut self,
+        reset_frame: &ResetStreamAtFrame,
+    ) -> Result<SizeKnown, Error> {
+        let final_size = reset_frame.final_size.into_inner();
+        if final_size < self.largest_data_offset {
+            return Err(Error::new(
+                ErrorKind::FinalSize,
+                reset_frame.frame_type(),
+                format!(
+                    "{} reset with a wrong smaller final size {final_size} than the largest rcvd data offset {}",
+                    reset_frame.stream_id, self.largest_data_offset
+                ),
+            ));
+        }
+        // 可靠大小之前的数据仍要继续接收，流的最终大小已确定
+        let mut size_known = self.determin_size(final_size);
+        size_known.recv_reset_at(reset_frame)?;
+        Ok(size_known)
+    }
 }
 
 /// Once the size of the data stream is determined, MAX_STREAM_DATA will no longer
@@ -172,6 +239,8 @@ pub struct SizeKnown {
     stop_state: Option<u64>,
     stop_waker: Option<Waker>,
     total_size: u64,
+    // 对方带可靠大小重置了流，读完可靠大小之前的数据后，流即被重置
+    reliable_reset: Option<(u64, StreamReset)>,
 }
 
 impl SizeKnown {
@@ -236,6 +305,20 @@ impl SizeKnown {
         }
     }
 
+    pub(super) fn poll_read_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+        max_len: usize,
+    ) -> Poll<io::Result<Option<(u64, Bytes)>>> {
+        match self.rcvbuf.try_read_chunk(max_len) {
+            Some(chunk) => Poll::Ready(Ok(Some(chunk))),
+            None => {
+                self.read_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
     pub(super) fn poll_stop(&mut self, cx: &mut Context<'_>) -> Poll<Option<u64>> {
         if let Some(err_code) = self.stop_state {
             Poll::Ready(Some(err_code))
@@ -284,6 +367,48 @@ impl SizeKnown {
         self.wake_all();
         Ok(final_size)
     }
+
+    /// Return `true` if all the data before the reliable size have been read, the stream is reset
+    /// right away.
+    pub(super) fn recv_reset_at(
+        &mut self,
+        reset_frame: &ResetStreamAtFrame,
+    ) -> Result<bool, Error> {
+        let final_size = reset_frame.final_size.into_inner();
+        if final_size != self.total_size {
+            return Err(Error::new(
+                ErrorKind::FinalSize,
+                reset_frame.frame_type(),
+                format!(
+                    "{} change the final size from {} to {final_size}",
+                    reset_frame.stream_id, self.total_size
+                ),
+            ));
+        }
+        // 重传或者后续的RESET_STREAM_AT帧只能减小可靠大小
+        let reliable_size = match self.reliable_reset {
+            Some((reliable_size, _)) => reliable_size.min(reset_frame.reliable_size.into_inner()),
+            None => reset_frame.reliable_size.into_inner(),
+        };
+        self.reliable_reset = Some((
+            reliable_size,
+            StreamReset(reset_frame.app_error_code.into()),
+        ));
+        if self.rcvbuf.nread() >= reliable_size {
+            self.wake_all();
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Return the reset of the stream if it is reset with a reliable size, and all the data before
+    /// the reliable size have been read by the application.
+    pub(super) fn reliable_reset(&self) -> Option<StreamReset> {
+        self.reliable_reset
+            .filter(|(reliable_size, _)| self.rcvbuf.nread() >= *reliable_size)
+            .map(|(_, reset)| reset)
+    }
 }
 
 impl From<&mut SizeKnown> for DataRcvd {
@@ -331,11 +456,68 @@ impl DataRcvd {
         self.rcvbuf.try_read(buf);
     }
 
+    /// Read a chunk of the remaining data without copying, [`None`] means all data has been read.
+    pub(super) fn read_chunk(&mut self, max_len: usize) -> Option<(u64, Bytes)> {
+        self.rcvbuf.try_read_chunk(max_len)
+    }
+
     pub(super) fn is_all_read(&self) -> bool {
         self.rcvbuf.is_empty()
     }
 }
 
+/// The states of the receiving part of a stream, see [Section 3.2](https://www.rfc-editor.org/rfc/rfc9000.html#section-3.2)
+/// of RFC 9000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvState {
+    Recv,
+    SizeKnown,
+    DataRecvd,
+    ResetRecvd,
+    DataRead,
+    ResetRead,
+}
+
+/// The statistics of the receiving part of a stream, returned by [`Incoming::stats`].
+///
+/// The counters are zero once the data is no longer buffered, that is after
+/// [`RecvState::DataRead`], [`RecvState::ResetRecvd`] or [`RecvState::ResetRead`].
+///
+/// [`Incoming::stats`]: super::Incoming::stats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvStats {
+    pub state: RecvState,
+    /// The largest offset of the data received, the data before it may be incomplete.
+    pub largest_offset: u64,
+    /// The data received continuously from the beginning of the stream.
+    pub received: u64,
+    /// The data read by the application.
+    pub read: u64,
+    /// The flow control limit of the stream advertised to the peer, which makes no difference
+    /// once the final size is known, and is zero then.
+    pub max_stream_data: u64,
+    /// The final size of the stream, known once the fin is received.
+    pub final_size: Option<u64>,
+    /// The application error code the peer reset the stream with, the data before the reliable
+    /// size is still delivered if the stream is reset with a reliable size.
+    pub reset: Option<u64>,
+}
+
+impl RecvStats {
+    fn new(state: RecvState, rcvbuf: Option<&rcvbuf::RecvBuf>) -> Self {
+        let received = rcvbuf.map_or(0, rcvbuf::RecvBuf::available);
+        RecvStats {
+            state,
+            largest_offset: received,
+            received,
+            read: rcvbuf.map_or(0, rcvbuf::RecvBuf::nread),
+            max_stream_data: 0,
+            final_size: None,
+            reset: None,
+        }
+    }
+}
+
 /// Receiving stream state machine. In fact, here the state variables such as
 /// is_closed/is_reset are replaced by a state machine. This not only provides
 /// clearer semantics and aligns with the QUIC RFC specification but also
@@ -351,8 +533,65 @@ pub(super) enum Recver {
 }
 
 impl Recver {
-    pub(super) fn new(buf_size: u64) -> Self {
-        Self::Recv(Recv::with(buf_size))
+    pub(super) fn new(tuner: WindowTuner) -> Self {
+        Self::Recv(Recv::with(tuner))
+    }
+
+    /// 对方已经发送了可读的数据，或者流已经结束，否则等待数据到达
+    pub(super) fn poll_arrived(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match self {
+            Recver::Recv(r) if !r.rcvbuf.is_readable() => {
+                r.read_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            Recver::SizeKnown(r) if !r.rcvbuf.is_readable() => {
+                r.read_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            _ => Poll::Ready(()),
+        }
+    }
+
+    pub(super) fn peek(&self, max_len: usize) -> Bytes {
+        match self {
+            Recver::Recv(r) => r.rcvbuf.peek(max_len),
+            Recver::SizeKnown(r) => r.rcvbuf.peek(max_len),
+            Recver::DataRcvd(r) => r.rcvbuf.peek(max_len),
+            _ => Bytes::new(),
+        }
+    }
+
+    pub(super) fn stats(&self) -> RecvStats {
+        match self {
+            Recver::Recv(r) => RecvStats {
+                largest_offset: r.largest_data_offset,
+                max_stream_data: r.max_data_size,
+                ..RecvStats::new(RecvState::Recv, Some(&r.rcvbuf))
+            },
+            // 携带fin的帧已经收到，其末尾即是最大的偏移
+            Recver::SizeKnown(r) => RecvStats {
+                largest_offset: r.total_size,
+                final_size: Some(r.total_size),
+                reset: r.reliable_reset.map(|(_, reset)| reset.0),
+                ..RecvStats::new(RecvState::SizeKnown, Some(&r.rcvbuf))
+            },
+            Recver::DataRcvd(r) => {
+                let stats = RecvStats::new(RecvState::DataRecvd, Some(&r.rcvbuf));
+                RecvStats {
+                    final_size: Some(stats.received),
+                    ..stats
+                }
+            }
+            Recver::ResetRcvd(reset) => RecvStats {
+                reset: Some(reset.0),
+                ..RecvStats::new(RecvState::ResetRecvd, None)
+            },
+            Recver::DataRead => RecvStats::new(RecvState::DataRead, None),
+            Recver::ResetRead(reset) => RecvStats {
+                reset: Some(reset.0),
+                ..RecvStats::new(RecvState::ResetRead, None)
+            },
+        }
     }
 }
 
@@ -375,8 +614,8 @@ pub struct ArcRecver {
 
 impl ArcRecver {
     #[doc(hidden)]
-    pub(crate) fn new(buf_size: u64, sid: StreamId) -> Self {
-        let recver = Arc::new(Mutex::new(Ok(Recver::new(buf_size))));
+    pub(crate) fn new(tuner: WindowTuner, sid: StreamId) -> Self {
+        let recver = Arc::new(Mutex::new(Ok(Recver::new(tuner))));
         Self { recver, sid }
     }
 
@@ -387,7 +626,73 @@ impl ArcRecver {
     pub(super) fn sid(&self) -> StreamId {
         self.sid
     }
+
+    /// Poll whether the peer has sent the data to read, or the stream has ended, which is
+    /// always ready once a connection error occurred.
+    ///
+    /// It's used before the stream is accepted, the waker is replaced once the stream is read.
+    pub(crate) fn poll_arrived(&self, cx: &mut Context<'_>) -> Poll<()> {
+        match self.recver().as_mut() {
+            Ok(recver) => recver.poll_arrived(cx),
+            Err(_) => Poll::Ready(()),
+        }
+    }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::time::Duration;
+
+    use qbase::{flow::ArcRtt, varint::VarInt};
+
+    use super::*;
+
+    #[test]
+    fn test_window_tuning() {
+        let rtt = ArcRtt::default();
+        rtt.update(Duration::from_millis(100));
+        let mut recv = Recv::with(WindowTuner::new(100, 400, rtt));
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let cx = || Context::from_waker(futures::task::noop_waker_ref());
+        let mut offset = 0;
+        let mut recv_and_read = |recv: &mut Recv, len: usize| {
+            let frame = StreamFrame::new(sid, offset, len);
+            recv.recv(&frame, Bytes::from(vec![0; len])).unwrap();
+            offset += len as u64;
+            let mut buf = Vec::new();
+            _ = recv.poll_read(&mut cx(), &mut buf);
+        };
+        let poll_window = |recv: &mut Recv| match recv.poll_update_window(&mut cx()) {
+            Poll::Ready(window) => window,
+            Poll::Pending => {
+                recv.buf_exceeds_half_waker = None;
+                None
+            }
+        };
+
+        // 读完一半窗口之后才更新
+        recv_and_read(&mut recv, 50);
+        assert_eq!(poll_window(&mut recv), None);
+        recv_and_read(&mut recv, 1);
+        assert_eq!(poll_window(&mut recv), Some(151));
+        // 很快又读完了一半，窗口翻倍
+        recv_and_read(&mut recv, 51);
+        assert_eq!(poll_window(&mut recv), Some(102 + 200));
+        recv_and_read(&mut recv, 101);
+        assert_eq!(poll_window(&mut recv), Some(203 + 400));
+        // 窗口不超过上限
+        recv_and_read(&mut recv, 201);
+        assert_eq!(poll_window(&mut recv), Some(404 + 400));
+
+        // 放大窗口，立即更新，且之后可以超过原来的上限增长
+        recv.set_window(1000);
+        assert_eq!(poll_window(&mut recv), Some(404 + 1000));
+        recv_and_read(&mut recv, 501);
+        assert_eq!(poll_window(&mut recv), Some(905 + 1000));
+        // 缩小窗口，已通告的额度不变
+        recv.set_window(10);
+        assert_eq!(poll_window(&mut recv), None);
+        recv_and_read(&mut recv, 996);
+        assert_eq!(poll_window(&mut recv), Some(1901 + 20));
+    }
+}
@@ -5,7 +5,10 @@ use std::{
 };
 
 use enum_dispatch::enum_dispatch;
-use qbase::frame::{io::WriteFrame, BeFrame, CryptoFrame, ReliableFrame, SendFrame, StreamFrame};
+use qbase::frame::{
+    io::WriteFrame, BeFrame, CryptoFrame, DatagramFrame, FrameType, ReliableFrame, SendFrame,
+    StreamFrame,
+};
 
 mod rcvdpkt;
 pub use rcvdpkt::*;
@@ -14,13 +17,42 @@ pub use sentpkt::*;
 
 /// The kind of frame which guaratend to be received by peer.
 ///
-/// The bundle of [`StreamFrame`], [`CryptoFrame`] and [`ReliableFrame`].
+/// The bundle of [`StreamFrame`], [`CryptoFrame`] and [`ReliableFrame`], and the
+/// [`TrackedDatagram`] whose fate is reported rather than guaranteed.
 #[derive(Debug, Clone, Eq, PartialEq)]
 #[enum_dispatch(BeFrame)]
 pub enum GuaranteedFrame {
     Stream(StreamFrame),
     Crypto(CryptoFrame),
     Reliable(ReliableFrame),
+    Datagram(TrackedDatagram),
+}
+
+/// A datagram frame whose sender wants to know the fate of the packet containing it.
+///
+/// The datagram is never retransmitted, but it's recorded with the packet like the other
+/// [`GuaranteedFrame`]s, so the `id` of it can be reported once the packet is acknowledged or
+/// declared lost.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TrackedDatagram {
+    /// The datagram frame sent.
+    pub frame: DatagramFrame,
+    /// The id given by the datagram queue to report the fate to.
+    pub id: u64,
+}
+
+impl BeFrame for TrackedDatagram {
+    fn frame_type(&self) -> FrameType {
+        self.frame.frame_type()
+    }
+
+    fn max_encoding_size(&self) -> usize {
+        self.frame.max_encoding_size()
+    }
+
+    fn encoding_size(&self) -> usize {
+        self.frame.encoding_size()
+    }
 }
 
 /// A deque for data space to send reliable frames.
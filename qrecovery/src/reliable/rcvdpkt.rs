@@ -4,7 +4,7 @@ use std::{
 };
 
 use qbase::{
-    frame::{io::WriteFrame, AckFrame},
+    frame::{io::WriteFrame, AckFrame, AckMpFrame, EcnCodepoint, EcnCounts},
     packet::PacketNumber,
     util::IndexDeque,
     varint::{VarInt, VARINT_MAX},
@@ -51,19 +51,38 @@ pub enum InvalidPacketNumber {
     HasRcvd,
 }
 
+/// The default ack_delay_exponent transport parameter, see [Section 18.2](https://www.rfc-editor.org/rfc/rfc9000.html#section-18.2)
+/// of RFC 9000.
+pub const DEFAULT_ACK_DELAY_EXPONENT: u8 = 3;
+
 /// 纯碎的一个收包记录，主要用于：
 /// - 记录包有无收到
 /// - 根据某个largest pktno，生成ack frame（ack frame不能超过buf大小）
 /// - 确定记录不再需要，可以被丢弃，滑走
-#[derive(Debug, Default)]
+/// - 统计收到的数据包的ECN标记，收到过ECN标记的包后，ack frame要携带ECN计数
+#[derive(Debug)]
 struct RcvdPktRecords {
     queue: IndexDeque<State, VARINT_MAX>,
+    ecn: Option<EcnCounts>,
+    // ack frame中的ack delay以2的该次幂微秒为单位，即本地通告的ack_delay_exponent传输参数
+    ack_delay_exponent: u8,
+}
+
+impl Default for RcvdPktRecords {
+    fn default() -> Self {
+        Self {
+            queue: IndexDeque::default(),
+            ecn: None,
+            ack_delay_exponent: DEFAULT_ACK_DELAY_EXPONENT,
+        }
+    }
 }
 
 impl RcvdPktRecords {
     fn with_capacity(capacity: usize) -> Self {
         Self {
             queue: IndexDeque::with_capacity(capacity),
+            ..Self::default()
         }
     }
 
@@ -83,7 +102,10 @@ impl RcvdPktRecords {
         Ok(pn)
     }
 
-    fn on_rcvd_pn(&mut self, pn: u64) {
+    fn on_rcvd_pn(&mut self, pn: u64, ecn: Option<EcnCodepoint>) {
+        if let Some(ecn) = ecn {
+            self.ecn.get_or_insert_with(EcnCounts::default).on_rcvd(ecn);
+        }
         if let Some(record) = self.queue.get_mut(pn) {
             record.is_received = true;
         } else {
@@ -114,9 +136,16 @@ impl RcvdPktRecords {
         );
 
         let largest = VarInt::from_u64(largest).unwrap();
-        let delay = VarInt::from_u64(recv_time.elapsed().as_micros() as u64).unwrap();
-        // Minimum length with at least ACK frame type, largest, delay, range count, first_range (at least 1 byte for 0)
-        let min_len = 1 + largest.encoding_size() + delay.encoding_size() + 1 + 1;
+        let delay = recv_time.elapsed().as_micros() as u64 >> self.ack_delay_exponent;
+        let delay = VarInt::from_u64(delay).unwrap();
+        // Minimum length with at least ACK frame type, largest, delay, range count, first_range (at least 1 byte for 0),
+        // and the ECN counts if any
+        let min_len = 1
+            + largest.encoding_size()
+            + delay.encoding_size()
+            + 1
+            + 1
+            + self.ecn.map_or(0, |ecn| ecn.encoding_size());
         if capacity < min_len {
             return None;
         }
@@ -166,7 +195,7 @@ impl RcvdPktRecords {
             delay,
             first_range: unsafe { VarInt::from_u64_unchecked(first_range as u64) },
             ranges,
-            ecn: None,
+            ecn: self.ecn,
         })
     }
 
@@ -183,6 +212,21 @@ impl RcvdPktRecords {
         Some(buf_len - buf.len())
     }
 
+    fn read_ack_mp_frame_util(
+        &self,
+        mut buf: &mut [u8],
+        dcid_seq: VarInt,
+        largest: u64,
+        recv_time: Instant,
+    ) -> Option<usize> {
+        let buf_len = buf.len();
+        // ACK_MP帧比ACK帧多出3字节的帧类型，以及dcid_seq
+        let extra_len = 3 + dcid_seq.encoding_size();
+        let ack = self.gen_ack_frame_util((largest, recv_time), buf_len.checked_sub(extra_len)?)?;
+        buf.put_frame(&AckMpFrame { dcid_seq, ack });
+        Some(buf_len - buf.len())
+    }
+
     fn retire(&mut self, pn: u64) {
         if let Some(record) = self.queue.get_mut(pn) {
             record.inactivate();
@@ -214,6 +258,16 @@ impl ArcRcvdPktRecords {
         }
     }
 
+    /// Set the ack_delay_exponent transport parameter advertised to the peer, the ACK Delay field of
+    /// the ack frames is scaled down by it, [`DEFAULT_ACK_DELAY_EXPONENT`] by default.
+    pub fn set_ack_delay_exponent(&self, exponent: u8) {
+        self.inner.write().unwrap().ack_delay_exponent = exponent;
+    }
+
+    pub fn ack_delay_exponent(&self) -> u8 {
+        self.inner.read().unwrap().ack_delay_exponent
+    }
+
     /// Decode the pn from peer's packet to actual packer number.
     ///
     /// See [`RFC`](https://www.rfc-editor.org/rfc/rfc9000.html#name-sample-packet-number-decodi)
@@ -234,13 +288,31 @@ impl ArcRcvdPktRecords {
         self.inner.write().unwrap().decode_pn(encoded_pn)
     }
 
-    /// Register the packet has been recieved.
+    /// Register the packet has been recieved, `ecn` is the ECN codepoint of the datagram carrying it.
     ///
     /// The registered packet must be valid, successfully decrypted, and the frames in it must be
     /// valid.
+    ///
+    /// The ECN codepoints are counted and carried in the ack frames, see
+    /// [Section 13.4.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-13.4.1) of RFC 9000.
     // 当包号合法，且包被完全解密，且包中的帧都正确之后，记录该包已经收到。
-    pub fn register_pn(&self, pn: u64) {
-        self.inner.write().unwrap().on_rcvd_pn(pn);
+    pub fn register_pn(&self, pn: u64, ecn: Option<EcnCodepoint>) {
+        self.inner.write().unwrap().on_rcvd_pn(pn, ecn);
+    }
+
+    /// Whether the packet `pn` is newer than all the packets registered, which should be checked
+    /// before registering it.
+    ///
+    /// Only the non-probing packet with the largest packet number makes the peer migrate to the
+    /// path it's received from, see [Section 9.3](https://www.rfc-editor.org/rfc/rfc9000.html#section-9.3)
+    /// of RFC 9000.
+    pub fn is_newest(&self, pn: u64) -> bool {
+        pn >= self.inner.read().unwrap().queue.largest()
+    }
+
+    /// Whether any packet has been registered as received in this space.
+    pub fn has_rcvd(&self) -> bool {
+        self.inner.read().unwrap().queue.largest() > 0
     }
 
     /// Generate an ack frame which ack the received frames until `largest`.
@@ -260,6 +332,24 @@ impl ArcRcvdPktRecords {
             .read_ack_frame_util(buf, largest, recv_time)
     }
 
+    /// Generate an ACK_MP frame which ack the received frames until `largest`.
+    ///
+    /// It is the same as [`ArcRcvdPktRecords::read_ack_frame_util`], except that the frame is an
+    /// ACK_MP frame of the multipath extension, which acknowledges the packets received with the
+    /// connection ID of the sequence number `dcid_seq`.
+    pub fn read_ack_mp_frame_util(
+        &self,
+        buf: &mut [u8],
+        dcid_seq: VarInt,
+        largest: u64,
+        recv_time: Instant,
+    ) -> Option<usize> {
+        self.inner
+            .read()
+            .unwrap()
+            .read_ack_mp_frame_util(buf, dcid_seq, largest, recv_time)
+    }
+
     pub fn write(&self) -> ArcRcvdPktRecordsWriter<'_> {
         ArcRcvdPktRecordsWriter {
             guard: self.inner.write().unwrap(),
@@ -296,9 +386,11 @@ mod tests {
         let records = ArcRcvdPktRecords::default();
         assert_eq!(records.decode_pn(PacketNumber::encode(1, 0)), Ok(1));
         assert_eq!(records.inner.read().unwrap().queue.len(), 0);
+        assert!(!records.has_rcvd());
 
-        records.register_pn(1);
+        records.register_pn(1, None);
         assert_eq!(records.inner.read().unwrap().queue.len(), 2);
+        assert!(records.has_rcvd());
 
         assert_eq!(
             records.inner.read().unwrap().queue.get(0).unwrap(),
@@ -316,7 +408,10 @@ mod tests {
         );
 
         assert_eq!(records.decode_pn(PacketNumber::encode(30, 0)), Ok(30));
-        records.register_pn(30);
+        assert!(records.is_newest(30));
+        records.register_pn(30, Some(EcnCodepoint::Ect0));
+        assert!(!records.is_newest(30));
+        assert!(!records.is_newest(20));
         {
             let mut writer = records.write();
             for i in 5..10 {
@@ -338,4 +433,57 @@ mod tests {
             Err(InvalidPacketNumber::TooOld)
         );
     }
+
+    #[test]
+    fn test_ack_frame_with_ecn() {
+        let records = ArcRcvdPktRecords::default();
+        // The ACK delay is encoded in 4 bytes whenever the frames are generated
+        let now = Instant::now() - std::time::Duration::from_secs(1);
+        records.register_pn(0, None);
+        let mut buf = [0u8; 32];
+        let n = records.read_ack_frame_util(&mut buf, 0, now).unwrap();
+        // Not ECN capable yet, the frame type is 0x02
+        assert_eq!(buf[0], 0x02);
+
+        records.register_pn(1, Some(EcnCodepoint::Ect0));
+        records.register_pn(2, Some(EcnCodepoint::Ce));
+        let ack_frame = records
+            .inner
+            .read()
+            .unwrap()
+            .gen_ack_frame_util((2, now), 32)
+            .unwrap();
+        assert_eq!(
+            ack_frame.ecn,
+            Some(EcnCounts {
+                ect0: VarInt::from_u32(1),
+                ect1: VarInt::from_u32(0),
+                ce: VarInt::from_u32(1),
+            })
+        );
+        let m = records.read_ack_frame_util(&mut buf, 2, now).unwrap();
+        assert_eq!(buf[0], 0x03);
+        assert_eq!(m, n + 3);
+        // Not enough space for the ECN counts
+        assert!(records
+            .read_ack_frame_util(&mut buf[..m - 1], 2, now)
+            .is_none());
+    }
+
+    #[test]
+    fn test_ack_delay_exponent() {
+        let records = ArcRcvdPktRecords::default();
+        assert_eq!(records.ack_delay_exponent(), DEFAULT_ACK_DELAY_EXPONENT);
+        records.register_pn(0, None);
+        records.set_ack_delay_exponent(10);
+        let recv_time = Instant::now() - std::time::Duration::from_secs(1);
+        let ack_frame = records
+            .inner
+            .read()
+            .unwrap()
+            .gen_ack_frame_util((0, recv_time), 32)
+            .unwrap();
+        // 1s / 1024us
+        assert!((976..1000).contains(&ack_frame.delay.into_inner()));
+    }
 }
@@ -5,17 +5,26 @@ use std::{
 };
 
 use deref_derive::{Deref, DerefMut};
-use qbase::{packet::PacketNumber, util::IndexDeque, varint::VARINT_MAX};
+use qbase::{frame::AckFrame, packet::PacketNumber, util::IndexDeque, varint::VARINT_MAX};
+use rand::Rng;
+
+/// On average, one packet number is skipped every `SKIP_INTERVAL` packets sent.
+const SKIP_INTERVAL: u64 = 128;
+
+/// The number of the most recently skipped packet numbers remembered.
+const MAX_SKIPPED: usize = 8;
 
 /// 记录发送的数据包的状态，包括
 /// - Flighting: 数据包正在传输中
 /// - Acked: 数据包已经被确认
 /// - Lost: 数据包丢失
+/// - Skipped: 故意跳过的包号，没有数据包使用它
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum SentPktState {
     Flighting(u16),
     Acked(u16),
     Lost(u16),
+    Skipped,
 }
 
 impl SentPktState {
@@ -24,6 +33,7 @@ impl SentPktState {
             SentPktState::Flighting(n) => *n as usize,
             SentPktState::Acked(n) => *n as usize,
             SentPktState::Lost(n) => *n as usize,
+            SentPktState::Skipped => 0,
         }
     }
 
@@ -38,6 +48,7 @@ impl SentPktState {
                 *self = SentPktState::Acked(n);
                 n as usize
             }
+            SentPktState::Skipped => 0,
         }
     }
 
@@ -49,6 +60,7 @@ impl SentPktState {
             }
             SentPktState::Acked(_) => 0,
             SentPktState::Lost(_) => 0,
+            SentPktState::Skipped => 0,
         }
     }
 }
@@ -64,6 +76,9 @@ struct RawSentPktRecords<T> {
     // 记录着每个包的内容，其实是一个数字，该数字对应着queue中的record数量
     records: IndexDeque<SentPktState, VARINT_MAX>,
     largest_acked_pktno: u64,
+    // 下一个要跳过的包号，以及最近跳过的包号，对端确认了它们意味着对端在乐观确认
+    next_skipped_pn: u64,
+    skipped_pns: VecDeque<u64>,
 }
 
 impl<T: Clone> RawSentPktRecords<T> {
@@ -98,6 +113,18 @@ impl<T: Clone> RawSentPktRecords<T> {
             .range_mut(offset..offset + len)
             .map(|f| f.clone())
     }
+
+    fn may_loss_all(&mut self) -> Vec<T> {
+        let mut offset = 0;
+        let mut frames = Vec::new();
+        for state in self.records.iter_mut() {
+            let nframes = state.nframes();
+            let len = state.maybe_loss();
+            frames.extend(self.queue.range(offset..offset + len).cloned());
+            offset += nframes;
+        }
+        frames
+    }
 }
 
 impl<T> RawSentPktRecords<T> {
@@ -106,9 +133,35 @@ impl<T> RawSentPktRecords<T> {
             queue: VecDeque::with_capacity(capacity * 4),
             records: IndexDeque::with_capacity(capacity),
             largest_acked_pktno: 0,
+            next_skipped_pn: random_skip_distance(),
+            skipped_pns: VecDeque::with_capacity(MAX_SKIPPED),
         }
     }
 
+    fn acks_unsent(&self, ack_frame: &AckFrame) -> bool {
+        if ack_frame.largest.into_inner() >= self.records.largest() {
+            return true;
+        }
+        self.skipped_pns
+            .iter()
+            .any(|pn| ack_frame.iter().any(|range| range.contains(pn)))
+    }
+
+    fn skip_pn_if_scheduled(&mut self) {
+        let pn = self.records.largest();
+        if pn < self.next_skipped_pn {
+            return;
+        }
+        self.records
+            .push_back(SentPktState::Skipped)
+            .expect("packet number never overflow");
+        if self.skipped_pns.len() == MAX_SKIPPED {
+            self.skipped_pns.pop_front();
+        }
+        self.skipped_pns.push_back(pn);
+        self.next_skipped_pn = pn + 1 + random_skip_distance();
+    }
+
     fn auto_drain(&mut self) {
         let (n, f) = self
             .records
@@ -120,6 +173,10 @@ impl<T> RawSentPktRecords<T> {
     }
 }
 
+fn random_skip_distance() -> u64 {
+    rand::thread_rng().gen_range(1..=2 * SKIP_INTERVAL)
+}
+
 /// Records for sent packets and frames in them.
 ///
 /// [`DataStreams`] need to be aware of frame acknowledgment or possible loss, and so does [`CryptoStream`].
@@ -134,6 +191,11 @@ impl<T> RawSentPktRecords<T> {
 /// [`SendGuard`] and the [`RecvGuard`] are designed to be `Guard`, which means that they hold a
 /// [`MutexGuard`].
 ///
+/// Occasionally, a packet number is skipped instead of being used by a packet. An acknowledgment
+/// of a skipped packet number reveals that the peer is acknowledging the packets it did not
+/// receive to inflate the congestion window, see [Section 21.4](https://www.rfc-editor.org/rfc/rfc9000.html#section-21.4)
+/// of RFC 9000, check it with [`RecvGuard::acks_unsent`].
+///
 ///
 /// [`DataStreams`]: crate::streams::DataStreams
 /// [`CryptoStream`]: crate::crypto::CryptoStream
@@ -201,6 +263,19 @@ impl<T: Clone> RecvGuard<'_, T> {
         self.inner.may_loss_pkt(pn)
     }
 
+    /// Called when all the packets in flight should be considered lost, such as the path on which
+    /// they were sent has been abandoned, return the frames in those packets.
+    pub fn may_loss_all(&mut self) -> Vec<T> {
+        self.inner.may_loss_all()
+    }
+
+    /// Return whether the ack frame acknowledges a packet number which was never used, either not
+    /// reached yet or skipped deliberately. The peer should be considered to violate the protocol
+    /// then, and the ack frame must not be handled.
+    pub fn acks_unsent(&self, ack_frame: &AckFrame) -> bool {
+        self.inner.acks_unsent(ack_frame)
+    }
+
     /// Return the packet number of the last packet sent(the largest packet number).
     pub fn largest_pn(&self) -> u64 {
         self.inner.records.largest()
@@ -271,6 +346,45 @@ impl<T> Drop for SendGuard<'_, T> {
                 .records
                 .push_back(SentPktState::Flighting(nframes as u16))
                 .expect("packet number never overflow");
+            self.inner.skip_pn_if_scheduled();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use qbase::{frame::AckFrame, varint::VarInt};
+
+    use super::ArcSentPktRecords;
+
+    fn ack(largest: u64, first_range: u64) -> AckFrame {
+        AckFrame {
+            largest: VarInt::from_u64(largest).unwrap(),
+            delay: VarInt::from_u32(0),
+            first_range: VarInt::from_u64(first_range).unwrap(),
+            ranges: vec![],
+            ecn: None,
+        }
+    }
+
+    #[test]
+    fn skip_pn() {
+        let records = ArcSentPktRecords::<()>::with_capacity(16);
+        let mut pns = Vec::new();
+        for _ in 0..1024 {
+            let mut guard = records.send();
+            pns.push(guard.next_pn().0);
+            guard.record_trivial();
+        }
+        let skipped = (0..*pns.last().unwrap())
+            .rfind(|pn| !pns.contains(pn))
+            .unwrap();
+
+        let recv_guard = records.recv();
+        assert!(!recv_guard.acks_unsent(&ack(skipped - 1, 0)));
+        assert!(recv_guard.acks_unsent(&ack(skipped, 0)));
+        assert!(recv_guard.acks_unsent(&ack(skipped + 1, 1)));
+        // 尚未发送的包号也不能被确认
+        assert!(recv_guard.acks_unsent(&ack(recv_guard.largest_pn(), 0)));
+    }
+}
@@ -1,18 +1,21 @@
 //! Types for sending data on a Stream.
+mod budget;
 mod outgoing;
 mod sender;
 mod sndbuf;
 mod writer;
 
-pub use outgoing::{IsCancelled, Outgoing};
+pub use budget::{ArcSendBudget, SendBufferLimits};
+pub use outgoing::{IsBlocked, IsCancelled, Outgoing};
 use qbase::streamid::StreamId;
-pub use sender::ArcSender;
+pub use sender::{ArcSender, SendState, SendStats, DEFAULT_PRIORITY};
 pub use sndbuf::SendBuf;
-pub use writer::Writer;
+pub use writer::{DropPolicy, Writer};
 
 /// Create the internal representations of [`Outgoing`] and [`Writer`] with the given sending window size.
 ///
-/// The size of the sending window is the default flow control limit of a QUIC Stream.
-pub fn new(wnd_size: u64, sid: StreamId) -> ArcSender {
-    ArcSender::new(wnd_size, sid)
+/// The size of the sending window is the default flow control limit of a QUIC Stream, the data
+/// buffered is bounded by the `budget` shared by the streams of the connection.
+pub fn new(wnd_size: u64, sid: StreamId, budget: ArcSendBudget) -> ArcSender {
+    ArcSender::new(wnd_size, sid, budget)
 }
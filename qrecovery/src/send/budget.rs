@@ -0,0 +1,162 @@
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+/// The bounds of the data buffered to send, which is either unsent or unacknowledged by the peer.
+///
+/// Besides the flow control limits granted by the peer, the writes to a stream are pending once
+/// the data buffered by the stream, or by all the streams of the connection, reaches the bound,
+/// until the data is acknowledged. So the memory of an unbounded producer is still bounded.
+///
+/// The data in flight is buffered until acknowledged, so the bound of a stream should be no less
+/// than the bandwidth-delay product to saturate the path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendBufferLimits {
+    /// The bound of the data buffered by each stream, 4 MiB by default.
+    pub stream: usize,
+    /// The bound of the data buffered by all the streams of the connection, 16 MiB by default.
+    pub connection: usize,
+}
+
+impl Default for SendBufferLimits {
+    fn default() -> Self {
+        Self {
+            stream: 4 << 20,
+            connection: 16 << 20,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SendBudget {
+    limits: SendBufferLimits,
+    // The data buffered by all the streams
+    used: usize,
+    // The writers waiting for the data acknowledged
+    wakers: Vec<Waker>,
+}
+
+impl SendBudget {
+    fn available(&self, buffered: usize) -> usize {
+        let stream = self.limits.stream.saturating_sub(buffered);
+        let connection = self.limits.connection.saturating_sub(self.used);
+        stream.min(connection)
+    }
+
+    fn wake_all(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// The budget of the data buffered to send shared by all the streams of a connection, bounded by
+/// the [`SendBufferLimits`].
+///
+/// The budget is acquired before the data is written into the [`SendBuf`], and released once the
+/// data is acknowledged or the [`SendBuf`] is dropped.
+///
+/// [`SendBuf`]: super::SendBuf
+#[derive(Debug, Default, Clone)]
+pub struct ArcSendBudget(Arc<Mutex<SendBudget>>);
+
+impl ArcSendBudget {
+    /// Create a new [`ArcSendBudget`] with the given limits.
+    pub fn new(limits: SendBufferLimits) -> Self {
+        Self(Arc::new(Mutex::new(SendBudget {
+            limits,
+            ..Default::default()
+        })))
+    }
+
+    /// Returns the limits of the data buffered.
+    pub fn limits(&self) -> SendBufferLimits {
+        self.0.lock().unwrap().limits
+    }
+
+    /// Updates the limits, which take effect on the following writes.
+    ///
+    /// The data already buffered beyond the new limits is kept, the writes are pending until it's
+    /// acknowledged.
+    pub fn set_limits(&self, limits: SendBufferLimits) {
+        let mut budget = self.0.lock().unwrap();
+        budget.limits = limits;
+        budget.wake_all();
+    }
+
+    /// Returns the data buffered by all the streams.
+    pub fn used(&self) -> usize {
+        self.0.lock().unwrap().used
+    }
+
+    /// The amount of data can be buffered by a stream which has buffered `buffered` bytes.
+    pub(super) fn available(&self, buffered: usize) -> usize {
+        self.0.lock().unwrap().available(buffered)
+    }
+
+    /// Acquire the budget to buffer up to `n` bytes for a stream which has buffered `buffered`
+    /// bytes, returns the amount acquired, Pending if none is available.
+    pub(super) fn poll_acquire(
+        &self,
+        cx: &mut Context<'_>,
+        buffered: usize,
+        n: usize,
+    ) -> Poll<usize> {
+        let mut budget = self.0.lock().unwrap();
+        match budget.available(buffered).min(n) {
+            0 if n > 0 => {
+                if !budget.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    budget.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+            n => {
+                budget.used += n;
+                Poll::Ready(n)
+            }
+        }
+    }
+
+    /// Release the budget of `n` bytes, which is acknowledged or will never be sent.
+    pub(super) fn release(&self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let mut budget = self.0.lock().unwrap();
+        budget.used = budget.used.saturating_sub(n);
+        budget.wake_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_budget() {
+        let budget = ArcSendBudget::new(SendBufferLimits {
+            stream: 10,
+            connection: 15,
+        });
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        // 受单个流的限制
+        assert_eq!(budget.poll_acquire(&mut cx, 0, 20), Poll::Ready(10));
+        assert_eq!(budget.poll_acquire(&mut cx, 10, 1), Poll::Pending);
+        // 受整个连接的限制
+        assert_eq!(budget.poll_acquire(&mut cx, 0, 20), Poll::Ready(5));
+        assert_eq!(budget.poll_acquire(&mut cx, 0, 1), Poll::Pending);
+        assert_eq!(budget.poll_acquire(&mut cx, 0, 0), Poll::Ready(0));
+        assert_eq!(budget.0.lock().unwrap().wakers.len(), 1);
+
+        budget.release(8);
+        assert!(budget.0.lock().unwrap().wakers.is_empty());
+        assert_eq!(budget.used(), 7);
+        assert_eq!(budget.available(2), 8);
+        budget.set_limits(SendBufferLimits {
+            stream: 10,
+            connection: 5,
+        });
+        assert_eq!(budget.available(0), 0);
+    }
+}
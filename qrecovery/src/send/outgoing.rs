@@ -1,6 +1,6 @@
 use std::{
     future::Future,
-    ops::{DerefMut, Range},
+    ops::{Deref, DerefMut, Range},
     pin::Pin,
     task::{Context, Poll},
 };
@@ -15,7 +15,7 @@ use qbase::{
     varint::VARINT_MAX,
 };
 
-use super::sender::{ArcSender, DataSentSender, Sender, SendingSender};
+use super::sender::{ArcSender, DataSentSender, SendStats, Sender, SendingSender};
 use crate::streams::StreamReset;
 
 /// An struct for protocol layer to manage the sending part of a stream.
@@ -38,6 +38,12 @@ impl Outgoing {
         }
     }
 
+    /// Returns the statistics of the sending part of the stream, [`None`] if a connection error
+    /// occurred.
+    pub fn stats(&self) -> Option<SendStats> {
+        self.0.sender().as_ref().ok().map(Sender::stats)
+    }
+
     /// Read the data that the application has written into the buffer.
     ///
     /// See [`RawDataStreams::try_read_data`] for more about this method.
@@ -101,6 +107,7 @@ impl Outgoing {
                 }
                 Sender::Sending(s) => s.pick_up(predicate, flow_limit).map(write),
                 Sender::DataSent(s) => s.pick_up(predicate, flow_limit).map(write),
+                Sender::ResetAtSent(s) => s.pick_up(predicate, flow_limit).map(write),
                 _ => None,
             },
             Err(_) => None,
@@ -113,7 +120,8 @@ impl Outgoing {
     ///
     /// * `is_fin` indicates whether the acknowledged stream frame contains the `FIN` flag.
     ///
-    /// Return `true` if the stream is completely acknowledged, all data has been sent and received.
+    /// Return `true` if the stream is completely acknowledged, all data has been sent and received,
+    /// or the stream reset with a reliable size is acknowledged, see [`Outgoing::on_reset_acked`].
     ///
     /// [`SendBuf`]: crate::send::SendBuf
     pub fn on_data_acked(&self, range: &Range<u64>, is_fin: bool) -> bool {
@@ -135,6 +143,13 @@ impl Outgoing {
                         return true;
                     }
                 }
+                Sender::ResetAtSent(s) => {
+                    s.on_data_acked(range);
+                    if s.is_all_rcvd() {
+                        *sending_state = Sender::ResetRcvd(s.reset());
+                        return true;
+                    }
+                }
                 // ignore recv
                 _ => {}
             }
@@ -159,12 +174,32 @@ impl Outgoing {
                 Sender::DataSent(s) => {
                     s.may_loss_data(range);
                 }
+                Sender::ResetAtSent(s) => {
+                    s.may_loss_data(range);
+                }
                 // ignore loss
                 _ => (),
             }
         };
     }
 
+    /// Return the priority of the stream set by [`Writer::set_priority`].
+    ///
+    /// [`Writer::set_priority`]: super::Writer::set_priority
+    pub fn priority(&self) -> u8 {
+        self.0.priority()
+    }
+
+    /// Return whether the stream has data that may be lost and waits to be retransmitted.
+    pub fn has_lost_data(&self) -> bool {
+        match self.0.sender().deref() {
+            Ok(Sender::Sending(s)) => s.has_lost_data(),
+            Ok(Sender::DataSent(s)) => s.has_lost_data(),
+            Ok(Sender::ResetAtSent(s)) => s.has_lost_data(),
+            _ => false,
+        }
+    }
+
     /// Called when the [`STOP_SENDING frame`] sent by the peer is received.
     ///
     /// If the stream has not been closed, the stream will be reset and then a [`RESET_STREAM frame`] will
@@ -198,10 +233,15 @@ impl Outgoing {
         }
     }
 
-    /// Called When the [`RESET_STREAM frame`] previously sent to the peer is acknowledged
+    /// Called When the [`RESET_STREAM frame`] or the [`RESET_STREAM_AT frame`] previously sent to
+    /// the peer is acknowledged.
+    ///
+    /// Return `true` if the stream is completely reset. If the stream is reset with a reliable size,
+    /// it's not until all the data before the reliable size are acknowledged too.
     ///
     /// [`RESET_STREAM frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-reset_stream-frames
-    pub fn on_reset_acked(&self) {
+    /// [`RESET_STREAM_AT frame`]: https://www.ietf.org/archive/id/draft-ietf-quic-reliable-stream-reset-06.html#name-reset_stream_at-frame
+    pub fn on_reset_acked(&self) -> bool {
         let mut sender = self.0.sender();
         let inner = sender.deref_mut();
         if let Ok(sending_state) = inner {
@@ -209,6 +249,13 @@ impl Outgoing {
                 Sender::ResetSent(r) | Sender::ResetRcvd(r) => {
                     *sending_state = Sender::ResetRcvd(*r)
                 }
+                Sender::ResetAtSent(s) => {
+                    s.on_reset_acked();
+                    if !s.is_all_rcvd() {
+                        return false;
+                    }
+                    *sending_state = Sender::ResetRcvd(s.reset());
+                }
                 _ => {
                     unreachable!(
                     "If no RESET_STREAM has been sent, how can there be a received acknowledgment?"
@@ -216,6 +263,24 @@ impl Outgoing {
                 }
             }
         }
+        true
+    }
+
+    /// Give up sending the data before the reliable size, if the stream is reset with a reliable
+    /// size.
+    ///
+    /// Called when the peer does not support the reliable stream reset extension, a
+    /// [`RESET_STREAM frame`] will be sent instead, then the stream is reset like [`Writer::cancel`].
+    ///
+    /// [`RESET_STREAM frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-reset_stream-frames
+    /// [`Writer::cancel`]: crate::send::Writer::cancel
+    pub fn abandon_reliable_data(&self) {
+        let mut sender = self.0.sender();
+        if let Ok(sending_state) = sender.deref_mut() {
+            if let Sender::ResetAtSent(s) = sending_state {
+                *sending_state = Sender::ResetSent(s.reset());
+            }
+        }
     }
 
     /// When a connection-level error occurs, all data streams must be notified.
@@ -235,6 +300,13 @@ impl Outgoing {
         *inner = Err(err.clone());
     }
 
+    /// Wait for the application layer to be blocked by the flow control limit of the stream.
+    ///
+    /// See [`IsBlocked`]'s doc for more details.
+    pub fn is_blocked(&self) -> IsBlocked<'_> {
+        IsBlocked(&self.0)
+    }
+
     /// Wait for the application layer to cancel(reset) the stream.
     ///
     /// If the stream closed, this future will also complete.
@@ -245,6 +317,33 @@ impl Outgoing {
     }
 }
 
+/// A future that returns the flow control limit of the stream, which the application layer is
+/// blocked by.
+///
+/// This is used to notify the protocol layer to send a [`STREAM_DATA_BLOCKED frame`] to the peer,
+/// so that the peer knows its window is the bottleneck. Each limit is returned only once.
+///
+/// Created by [`Outgoing::is_blocked`].
+///
+/// Return [`None`] if all the data has been written, or the stream is reset or closed, the window
+/// doesn't matter any more.
+///
+/// [`STREAM_DATA_BLOCKED frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-stream_data_blocked-frames
+pub struct IsBlocked<'s>(&'s ArcSender);
+
+impl Future for IsBlocked<'_> {
+    type Output = Option<u64>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut sender = self.0.sender();
+        match sender.deref_mut() {
+            Ok(Sender::Ready(s)) => s.poll_blocked(cx).map(Some),
+            Ok(Sender::Sending(s)) => s.poll_blocked(cx).map(Some),
+            _ => Poll::Ready(None),
+        }
+    }
+}
+
 /// A future that returns whether the application layer wants to cancel the stream.
 ///
 /// This is used to notify the protocol layer to reset the stream, send a [`RESET_STREAM frame`]
@@ -255,8 +354,9 @@ impl Outgoing {
 /// This future complete when the application layer wants to cancel the stream, or the stream is
 /// closed duo to other reasons.
 ///
-/// If the application called [`cancel`], this future will return:
+/// If the application called [`cancel`] or [`cancel_at`], this future will return:
 /// * `u64`: The final size of the stream data that has been written by the application layer.
+/// * `u64`: The reliable size, the data before which are still sent reliably, 0 for [`cancel`].
 /// * `u64`: The error code that the application layer wants to send to the peer.
 ///
 /// If the application layer does not cancel the stream until the stream is closed, this method
@@ -264,11 +364,12 @@ impl Outgoing {
 ///
 /// [`RESET_STREAM frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-reset_stream-frames
 /// [`cancel`]: crate::send::Writer::cancel
+/// [`cancel_at`]: crate::send::Writer::cancel_at
 pub struct IsCancelled<'s>(&'s ArcSender);
 
 impl Future for IsCancelled<'_> {
-    // (u64, u64) -> (final_size, err_code)
-    type Output = Option<(u64, u64)>;
+    // (u64, u64, u64) -> (final_size, reliable_size, err_code)
+    type Output = Option<(u64, u64, u64)>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let mut sender = self.0.sender();
@@ -276,19 +377,31 @@ impl Future for IsCancelled<'_> {
         match inner {
             Ok(sending_state) => match sending_state {
                 Sender::Ready(s) => {
-                    let (final_size, err_code) = ready!(s.poll_cancel(cx));
-                    *sending_state = Sender::ResetSent(StreamReset(err_code));
-                    Poll::Ready(Some((final_size, err_code)))
+                    let (final_size, reliable_size, err_code) = ready!(s.poll_cancel(cx));
+                    *sending_state = if reliable_size > 0 {
+                        Sender::ResetAtSent(s.into())
+                    } else {
+                        Sender::ResetSent(StreamReset(err_code))
+                    };
+                    Poll::Ready(Some((final_size, reliable_size, err_code)))
                 }
                 Sender::Sending(s) => {
-                    let (final_size, err_code) = ready!(s.poll_cancel(cx));
-                    *sending_state = Sender::ResetSent(StreamReset(err_code));
-                    Poll::Ready(Some((final_size, err_code)))
+                    let (final_size, reliable_size, err_code) = ready!(s.poll_cancel(cx));
+                    *sending_state = if reliable_size > 0 {
+                        Sender::ResetAtSent(s.into())
+                    } else {
+                        Sender::ResetSent(StreamReset(err_code))
+                    };
+                    Poll::Ready(Some((final_size, reliable_size, err_code)))
                 }
                 Sender::DataSent(s) => {
-                    let (final_size, err_code) = ready!(s.poll_cancel(cx));
-                    *sending_state = Sender::ResetSent(StreamReset(err_code));
-                    Poll::Ready(Some((final_size, err_code)))
+                    let (final_size, reliable_size, err_code) = ready!(s.poll_cancel(cx));
+                    *sending_state = if reliable_size > 0 {
+                        Sender::ResetAtSent(s.into())
+                    } else {
+                        Sender::ResetSent(StreamReset(err_code))
+                    };
+                    Poll::Ready(Some((final_size, reliable_size, err_code)))
                 }
                 _ => Poll::Ready(None),
             },
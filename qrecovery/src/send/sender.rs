@@ -1,15 +1,83 @@
 use std::{
-    io,
+    io::{self, IoSlice},
     ops::Range,
-    sync::{Arc, Mutex, MutexGuard},
-    task::{Context, Poll, Waker},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    task::{ready, Context, Poll, Waker},
 };
 
 use qbase::{error::Error, streamid::StreamId, util::DescribeData};
 
-use super::sndbuf::SendBuf;
+use super::{sndbuf::SendBuf, ArcSendBudget, DropPolicy};
 use crate::streams::StreamReset;
 
+/// The priority of the streams not set by [`Writer::set_priority`], the same as the default urgency
+/// of [RFC 9218](https://www.rfc-editor.org/rfc/rfc9218.html#name-urgency).
+///
+/// [`Writer::set_priority`]: super::Writer::set_priority
+pub const DEFAULT_PRIORITY: u8 = 3;
+
+/// Write the slices into the [`SendBuf`] in order, until the sending window `wnd` is exhausted.
+fn write_vectored(sndbuf: &mut SendBuf, bufs: &[IoSlice<'_>], mut wnd: usize) -> usize {
+    let mut written = 0;
+    for buf in bufs {
+        if wnd == 0 {
+            break;
+        }
+        let n = std::cmp::min(wnd, buf.len());
+        written += sndbuf.write(&buf[..n]);
+        wnd -= n;
+    }
+    written
+}
+
+/// Whether the application is blocked by the flow control limit of the stream, which is reported
+/// to the peer by a STREAM_DATA_BLOCKED frame, once for each limit.
+#[derive(Debug, Default)]
+struct Blocked {
+    // The limit to be reported.
+    limit: Option<u64>,
+    // The limit reported last time.
+    reported: Option<u64>,
+    waker: Option<Waker>,
+}
+
+impl Blocked {
+    fn on_blocked(&mut self, limit: u64) {
+        if self.reported.is_none_or(|reported| reported < limit) {
+            self.limit = Some(limit);
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn poll_blocked(&mut self, cx: &mut Context<'_>, max_data_size: u64) -> Poll<u64> {
+        // Not blocked any more if the window has been updated
+        match self.limit.take() {
+            Some(limit) if limit == max_data_size => {
+                self.reported = Some(limit);
+                Poll::Ready(limit)
+            }
+            _ => {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Blocked {
+    // Let the protocol layer stop waiting once the stream leaves the states limited by the window
+    fn drop(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
 /// The "Ready" state represents a newly created stream that is able to accept data from the application.
 /// Stream data might be buffered in this state in preparation for sending.
 /// An implementation might choose to defer allocating a stream ID to a stream until it sends the first
@@ -18,23 +86,30 @@ use crate::streams::StreamReset;
 pub struct ReadySender {
     sndbuf: SendBuf,
     cancel_state: Option<u64>,
+    reliable_size: u64,
     flush_waker: Option<Waker>,
     shutdown_waker: Option<Waker>,
     cancel_waker: Option<Waker>,
+    finished_waker: Option<Waker>,
     writable_waker: Option<Waker>,
     max_data_size: u64,
+    blocked: Blocked,
 }
 
 impl ReadySender {
-    pub(super) fn with_wnd_size(wnd_size: u64) -> ReadySender {
+    pub(super) fn with_wnd_size(wnd_size: u64, budget: ArcSendBudget) -> ReadySender {
+        let capacity = (wnd_size as usize).min(budget.limits().stream);
         ReadySender {
-            sndbuf: SendBuf::with_capacity(wnd_size as usize),
+            sndbuf: SendBuf::with_budget(capacity, budget),
             cancel_state: None,
+            reliable_size: 0,
             flush_waker: None,
             shutdown_waker: None,
             cancel_waker: None,
+            finished_waker: None,
             writable_waker: None,
             max_data_size: wnd_size,
+            blocked: Blocked::default(),
         }
     }
 
@@ -59,6 +134,16 @@ impl ReadySender {
         }
     }
 
+    /// The amount of data can be written without pending, bounded by both the flow control and
+    /// the send buffer limits.
+    pub(super) fn capacity(&self) -> usize {
+        if self.cancel_state.is_some() {
+            return 0;
+        }
+        let wnd = self.max_data_size.saturating_sub(self.sndbuf.len());
+        (wnd as usize).min(self.sndbuf.available())
+    }
+
     pub(super) fn update_window(&mut self, max_data_size: u64) {
         if max_data_size > self.max_data_size {
             self.max_data_size = max_data_size;
@@ -72,6 +157,14 @@ impl ReadySender {
         &mut self,
         cx: &mut Context<'_>,
         buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[IoSlice::new(buf)])
+    }
+
+    pub(super) fn poll_write_vectored(
+        &mut self,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
     ) -> Poll<io::Result<usize>> {
         if let Some(err_code) = self.cancel_state {
             Poll::Ready(Err(io::Error::new(
@@ -81,10 +174,46 @@ impl ReadySender {
         } else {
             let send_buf_len = self.sndbuf.len();
             if send_buf_len < self.max_data_size {
-                let n = std::cmp::min((self.max_data_size - send_buf_len) as usize, buf.len());
-                Poll::Ready(Ok(self.sndbuf.write(&buf[..n])))
+                let wnd = (self.max_data_size - send_buf_len) as usize;
+                let len = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+                // 流控之外，还受发送缓冲区的上限约束，等待数据被确认
+                let n = ready!(self.sndbuf.poll_reserve(cx, wnd.min(len)));
+                Poll::Ready(Ok(write_vectored(&mut self.sndbuf, bufs, n)))
             } else {
                 self.writable_waker = Some(cx.waker().clone());
+                self.blocked.on_blocked(self.max_data_size);
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Like [`poll_write_vectored`], but the data of up to `max` bytes is filled into the
+    /// [`SendBuf`] directly by `fill`, see [`SendBuf::write_with`].
+    ///
+    /// [`poll_write_vectored`]: Self::poll_write_vectored
+    pub(super) fn poll_write_with<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        max: usize,
+        fill: F,
+    ) -> Poll<io::Result<usize>>
+    where
+        F: FnOnce(&mut [u8], &mut [u8]) -> io::Result<usize>,
+    {
+        if let Some(err_code) = self.cancel_state {
+            Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                format!("cancelled by app with error code {err_code}"),
+            )))
+        } else {
+            let send_buf_len = self.sndbuf.len();
+            if send_buf_len < self.max_data_size {
+                let wnd = (self.max_data_size - send_buf_len) as usize;
+                let n = ready!(self.sndbuf.poll_reserve(cx, wnd.min(max)));
+                Poll::Ready(self.sndbuf.write_reserved_with(n, fill))
+            } else {
+                self.writable_waker = Some(cx.waker().clone());
+                self.blocked.on_blocked(self.max_data_size);
                 Poll::Pending
             }
         }
@@ -118,10 +247,15 @@ impl ReadySender {
         self.shutdown_waker.is_some()
     }
 
+    /// 传输层使用，应用层被流控阻塞时，返回阻塞时的流控上限，以发送STREAM_DATA_BLOCKED帧
+    pub(super) fn poll_blocked(&mut self, cx: &mut Context<'_>) -> Poll<u64> {
+        self.blocked.poll_blocked(cx, self.max_data_size)
+    }
+
     /// 传输层使用，用于发送RST_STREAM帧后，将Sender置为ResetSent状态
-    pub(super) fn poll_cancel(&mut self, cx: &mut Context<'_>) -> Poll<(u64, u64)> {
+    pub(super) fn poll_cancel(&mut self, cx: &mut Context<'_>) -> Poll<(u64, u64, u64)> {
         if let Some(err_code) = self.cancel_state {
-            Poll::Ready((self.sndbuf.len(), err_code))
+            Poll::Ready((self.sndbuf.len(), self.reliable_size, err_code))
         } else {
             self.cancel_waker = Some(cx.waker().clone());
             Poll::Pending
@@ -129,12 +263,35 @@ impl ReadySender {
     }
 
     /// 应用层使用，取消发送流
-    pub(super) fn cancel(&mut self, err_code: u64) {
+    pub(super) fn cancel(&mut self, err_code: u64, reliable_size: u64) {
         assert!(self.cancel_state.is_none());
         self.cancel_state = Some(err_code);
+        // 被丢弃的数据无法可靠地送达
+        let reliable_end = self.sndbuf.first_dropped().unwrap_or(u64::MAX);
+        self.reliable_size = reliable_size.min(self.sndbuf.len()).min(reliable_end);
         if let Some(waker) = self.cancel_waker.take() {
             waker.wake();
         }
+        if let Some(waker) = self.finished_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// 应用层使用，等待对端确认收到所有数据和fin。尚未shutdown，只能等待
+    pub(super) fn poll_finished(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(err_code) = self.cancel_state {
+            Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                format!("cancelled by app with error code {err_code}"),
+            )))
+        } else {
+            self.finished_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    pub(super) fn discard_before(&mut self, offset: u64) {
+        self.sndbuf.discard_before(offset);
     }
 
     pub(super) fn is_cancelled(&self) -> bool {
@@ -155,6 +312,9 @@ impl ReadySender {
         if let Some(waker) = self.cancel_waker.take() {
             waker.wake();
         }
+        if let Some(waker) = self.finished_waker.take() {
+            waker.wake();
+        }
     }
 }
 
@@ -164,11 +324,14 @@ impl From<&mut ReadySender> for SendingSender {
         SendingSender {
             sndbuf: std::mem::take(&mut value.sndbuf),
             cancel_state: value.cancel_state.take(),
+            reliable_size: value.reliable_size,
             flush_waker: value.flush_waker.take(),
             shutdown_waker: value.shutdown_waker.take(),
             cancel_waker: value.cancel_waker.take(),
+            finished_waker: value.finished_waker.take(),
             writable_waker: value.writable_waker.take(),
             max_data_size: value.max_data_size,
+            blocked: std::mem::take(&mut value.blocked),
         }
     }
 }
@@ -179,9 +342,12 @@ impl From<&mut ReadySender> for DataSentSender {
         DataSentSender {
             sndbuf: std::mem::take(&mut value.sndbuf),
             cancel_state: value.cancel_state.take(),
+            reliable_size: value.reliable_size,
             flush_waker: value.flush_waker.take(),
             shutdown_waker: value.shutdown_waker.take(),
             cancel_waker: value.cancel_waker.take(),
+            finished_waker: value.finished_waker.take(),
+            max_data_size: value.max_data_size,
             fin_state: FinState::None,
         }
     }
@@ -191,11 +357,14 @@ impl From<&mut ReadySender> for DataSentSender {
 pub struct SendingSender {
     sndbuf: SendBuf,
     cancel_state: Option<u64>,
+    reliable_size: u64,
     flush_waker: Option<Waker>,
     shutdown_waker: Option<Waker>,
     cancel_waker: Option<Waker>,
+    finished_waker: Option<Waker>,
     writable_waker: Option<Waker>,
     max_data_size: u64,
+    blocked: Blocked,
 }
 
 type StreamData<'s> = (u64, bool, (&'s [u8], &'s [u8]), bool);
@@ -205,6 +374,14 @@ impl SendingSender {
         &mut self,
         cx: &mut Context<'_>,
         buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[IoSlice::new(buf)])
+    }
+
+    pub(super) fn poll_write_vectored(
+        &mut self,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
     ) -> Poll<io::Result<usize>> {
         if let Some(err_code) = self.cancel_state {
             Poll::Ready(Err(io::Error::new(
@@ -214,16 +391,62 @@ impl SendingSender {
         } else {
             let send_buf_len = self.sndbuf.len();
             if send_buf_len < self.max_data_size {
-                let n = std::cmp::min((self.max_data_size - send_buf_len) as usize, buf.len());
-                Poll::Ready(Ok(self.sndbuf.write(&buf[..n])))
+                let wnd = (self.max_data_size - send_buf_len) as usize;
+                let len = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+                // 流控之外，还受发送缓冲区的上限约束，等待数据被确认
+                let n = ready!(self.sndbuf.poll_reserve(cx, wnd.min(len)));
+                Poll::Ready(Ok(write_vectored(&mut self.sndbuf, bufs, n)))
             } else {
                 self.writable_waker = Some(cx.waker().clone());
+                self.blocked.on_blocked(self.max_data_size);
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Like [`poll_write_vectored`], but the data of up to `max` bytes is filled into the
+    /// [`SendBuf`] directly by `fill`, see [`SendBuf::write_with`].
+    ///
+    /// [`poll_write_vectored`]: Self::poll_write_vectored
+    pub(super) fn poll_write_with<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        max: usize,
+        fill: F,
+    ) -> Poll<io::Result<usize>>
+    where
+        F: FnOnce(&mut [u8], &mut [u8]) -> io::Result<usize>,
+    {
+        if let Some(err_code) = self.cancel_state {
+            Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                format!("cancelled by app with error code {err_code}"),
+            )))
+        } else {
+            let send_buf_len = self.sndbuf.len();
+            if send_buf_len < self.max_data_size {
+                let wnd = (self.max_data_size - send_buf_len) as usize;
+                let n = ready!(self.sndbuf.poll_reserve(cx, wnd.min(max)));
+                Poll::Ready(self.sndbuf.write_reserved_with(n, fill))
+            } else {
+                self.writable_waker = Some(cx.waker().clone());
+                self.blocked.on_blocked(self.max_data_size);
                 Poll::Pending
             }
         }
     }
 
     /// 传输层使用
+    /// The amount of data can be written without pending, bounded by both the flow control and
+    /// the send buffer limits.
+    pub(super) fn capacity(&self) -> usize {
+        if self.cancel_state.is_some() {
+            return 0;
+        }
+        let wnd = self.max_data_size.saturating_sub(self.sndbuf.len());
+        (wnd as usize).min(self.sndbuf.available())
+    }
+
     pub(super) fn update_window(&mut self, max_data_size: u64) {
         if max_data_size > self.max_data_size {
             self.max_data_size = max_data_size;
@@ -261,6 +484,10 @@ impl SendingSender {
         self.sndbuf.may_loss_data(range)
     }
 
+    pub(super) fn has_lost_data(&self) -> bool {
+        self.sndbuf.has_lost_data()
+    }
+
     pub(super) fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         if let Some(err_code) = self.cancel_state {
             Poll::Ready(Err(io::Error::new(
@@ -287,22 +514,50 @@ impl SendingSender {
         }
     }
 
+    /// 传输层使用，应用层被流控阻塞时，返回阻塞时的流控上限，以发送STREAM_DATA_BLOCKED帧
+    pub(super) fn poll_blocked(&mut self, cx: &mut Context<'_>) -> Poll<u64> {
+        self.blocked.poll_blocked(cx, self.max_data_size)
+    }
+
     /// 传输层使用
-    pub(super) fn poll_cancel(&mut self, cx: &mut Context<'_>) -> Poll<(u64, u64)> {
+    pub(super) fn poll_cancel(&mut self, cx: &mut Context<'_>) -> Poll<(u64, u64, u64)> {
         if let Some(err_code) = self.cancel_state {
-            Poll::Ready((self.sndbuf.len(), err_code))
+            Poll::Ready((self.sndbuf.len(), self.reliable_size, err_code))
         } else {
             self.cancel_waker = Some(cx.waker().clone());
             Poll::Pending
         }
     }
 
-    pub(super) fn cancel(&mut self, err_code: u64) {
+    pub(super) fn cancel(&mut self, err_code: u64, reliable_size: u64) {
         assert!(self.cancel_state.is_none());
         self.cancel_state = Some(err_code);
+        // 被丢弃的数据无法可靠地送达
+        let reliable_end = self.sndbuf.first_dropped().unwrap_or(u64::MAX);
+        self.reliable_size = reliable_size.min(self.sndbuf.len()).min(reliable_end);
         if let Some(waker) = self.cancel_waker.take() {
             waker.wake();
         }
+        if let Some(waker) = self.finished_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// 应用层使用，等待对端确认收到所有数据和fin。尚未shutdown，只能等待
+    pub(super) fn poll_finished(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(err_code) = self.cancel_state {
+            Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                format!("cancelled by app with error code {err_code}"),
+            )))
+        } else {
+            self.finished_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    pub(super) fn discard_before(&mut self, offset: u64) {
+        self.sndbuf.discard_before(offset);
     }
 
     pub(super) fn is_cancelled(&self) -> bool {
@@ -323,6 +578,9 @@ impl SendingSender {
         if let Some(waker) = self.cancel_waker.take() {
             waker.wake();
         }
+        if let Some(waker) = self.finished_waker.take() {
+            waker.wake();
+        }
     }
 
     /// 传输层使用
@@ -339,9 +597,12 @@ impl From<&mut SendingSender> for DataSentSender {
         DataSentSender {
             sndbuf: std::mem::take(&mut value.sndbuf),
             cancel_state: value.cancel_state.take(),
+            reliable_size: value.reliable_size,
             flush_waker: value.flush_waker.take(),
             shutdown_waker: value.shutdown_waker.take(),
             cancel_waker: value.cancel_waker.take(),
+            finished_waker: value.finished_waker.take(),
+            max_data_size: value.max_data_size,
             fin_state: FinState::None,
         }
     }
@@ -360,9 +621,12 @@ enum FinState {
 pub struct DataSentSender {
     sndbuf: SendBuf,
     cancel_state: Option<u64>,
+    reliable_size: u64,
     flush_waker: Option<Waker>,
     shutdown_waker: Option<Waker>,
     cancel_waker: Option<Waker>,
+    finished_waker: Option<Waker>,
+    max_data_size: u64,
     fin_state: FinState,
 }
 
@@ -408,6 +672,9 @@ impl DataSentSender {
             if let Some(waker) = self.shutdown_waker.take() {
                 waker.wake();
             }
+            if let Some(waker) = self.finished_waker.take() {
+                waker.wake();
+            }
         }
     }
 
@@ -419,6 +686,10 @@ impl DataSentSender {
         self.sndbuf.may_loss_data(range)
     }
 
+    pub(super) fn has_lost_data(&self) -> bool {
+        self.sndbuf.has_lost_data()
+    }
+
     pub(super) fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         if let Some(err_code) = self.cancel_state {
             Poll::Ready(Err(io::Error::new(
@@ -447,25 +718,46 @@ impl DataSentSender {
         }
     }
 
-    pub(super) fn poll_cancel(&mut self, cx: &mut Context<'_>) -> Poll<(u64, u64)> {
+    pub(super) fn poll_cancel(&mut self, cx: &mut Context<'_>) -> Poll<(u64, u64, u64)> {
         if let Some(err_code) = self.cancel_state {
-            Poll::Ready((self.sndbuf.len(), err_code))
+            Poll::Ready((self.sndbuf.len(), self.reliable_size, err_code))
         } else {
             self.cancel_waker = Some(cx.waker().clone());
             Poll::Pending
         }
     }
 
-    pub(super) fn cancel(&mut self, err_code: u64) {
+    pub(super) fn cancel(&mut self, err_code: u64, reliable_size: u64) {
         assert!(self.cancel_state.is_none());
         self.cancel_state = Some(err_code);
+        // 被丢弃的数据无法可靠地送达
+        let reliable_end = self.sndbuf.first_dropped().unwrap_or(u64::MAX);
+        self.reliable_size = reliable_size.min(self.sndbuf.len()).min(reliable_end);
         if let Some(waker) = self.cancel_waker.take() {
             waker.wake();
         }
+        if let Some(waker) = self.finished_waker.take() {
+            waker.wake();
+        }
     }
 
-    pub(super) fn is_cancelled(&self) -> bool {
-        self.cancel_state.is_some()
+    /// 应用层使用，等待对端确认收到所有数据和fin
+    pub(super) fn poll_finished(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(err_code) = self.cancel_state {
+            Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                format!("cancelled by app with error code {err_code}"),
+            )))
+        } else if self.is_all_rcvd() {
+            Poll::Ready(Ok(()))
+        } else {
+            self.finished_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    pub(super) fn discard_before(&mut self, offset: u64) {
+        self.sndbuf.discard_before(offset);
     }
 
     pub(super) fn wake_all(&mut self) {
@@ -479,6 +771,9 @@ impl DataSentSender {
         if let Some(waker) = self.cancel_waker.take() {
             waker.wake();
         }
+        if let Some(waker) = self.finished_waker.take() {
+            waker.wake();
+        }
     }
 
     pub(super) fn stop(&mut self) -> u64 {
@@ -488,19 +783,177 @@ impl DataSentSender {
     }
 }
 
+/// 状态转换，应用层带可靠大小取消发送流后，ReadySender/SendingSender/DataSentSender => ResetAtSender
+macro_rules! impl_into_reset_at_sender {
+    ($($sender:ty),*) => {
+        $(
+            impl From<&mut $sender> for ResetAtSender {
+                fn from(value: &mut $sender) -> Self {
+                    let mut sndbuf = std::mem::take(&mut value.sndbuf);
+                    sndbuf.keep_before(value.reliable_size);
+                    ResetAtSender {
+                        sndbuf,
+                        reliable_size: value.reliable_size,
+                        reset: StreamReset(value.cancel_state.expect("must be cancelled")),
+                        is_reset_acked: false,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_into_reset_at_sender!(ReadySender, SendingSender, DataSentSender);
+
+/// The stream is reset with a reliable size, see [Reliable QUIC Stream Resets](https://www.ietf.org/archive/id/draft-ietf-quic-reliable-stream-reset-06.html).
+///
+/// The data before the reliable size are still sent and retransmitted, neither the data after it
+/// nor the fin will be sent. Only after the RESET_STREAM_AT frame and all the data before the
+/// reliable size are acknowledged, the stream enters the "Reset Recvd" state.
+#[derive(Debug)]
+pub struct ResetAtSender {
+    sndbuf: SendBuf,
+    reliable_size: u64,
+    reset: StreamReset,
+    is_reset_acked: bool,
+}
+
+impl ResetAtSender {
+    pub(super) fn pick_up<P>(&mut self, predicate: P, flow_limit: usize) -> Option<StreamData<'_>>
+    where
+        P: Fn(u64) -> Option<usize>,
+    {
+        let reliable_size = self.reliable_size;
+        // 只发送可靠大小之前的数据
+        let predicate = |offset: u64| {
+            if offset >= reliable_size {
+                return None;
+            }
+            predicate(offset).map(|n| n.min((reliable_size - offset) as usize))
+        };
+        self.sndbuf
+            .pick_up(predicate, flow_limit)
+            .map(|(offset, is_fresh, data)| (offset, is_fresh, data, false))
+    }
+
+    pub(super) fn on_data_acked(&mut self, range: &Range<u64>) {
+        self.sndbuf.on_data_acked(range);
+    }
+
+    pub(super) fn may_loss_data(&mut self, range: &Range<u64>) {
+        self.sndbuf.may_loss_data(range)
+    }
+
+    pub(super) fn has_lost_data(&self) -> bool {
+        self.sndbuf.has_lost_data()
+    }
+
+    pub(super) fn on_reset_acked(&mut self) {
+        self.is_reset_acked = true;
+    }
+
+    pub(super) fn is_all_rcvd(&self) -> bool {
+        self.is_reset_acked && self.sndbuf.is_rcvd_before(self.reliable_size)
+    }
+
+    pub(super) fn reset(&self) -> StreamReset {
+        self.reset
+    }
+}
+
+/// The states of the sending part of a stream, see [Section 3.1](https://www.rfc-editor.org/rfc/rfc9000.html#section-3.1)
+/// of RFC 9000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendState {
+    Ready,
+    Send,
+    DataSent,
+    /// Reset by the application, with or without a reliable size.
+    ResetSent,
+    DataRecvd,
+    ResetRecvd,
+}
+
+/// The statistics of the sending part of a stream, returned by [`Outgoing::stats`].
+///
+/// The counters are zero in the states the data is no longer buffered, that is
+/// [`SendState::DataRecvd`], [`SendState::ResetRecvd`], or [`SendState::ResetSent`] without a
+/// reliable size.
+///
+/// [`Outgoing::stats`]: super::Outgoing::stats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendStats {
+    pub state: SendState,
+    /// The data written by the application.
+    pub written: u64,
+    /// The data sent at least once.
+    pub sent: u64,
+    /// The data acknowledged continuously from the beginning of the stream.
+    pub acked: u64,
+    /// The data retransmitted, each retransmission is counted.
+    pub retransmitted: u64,
+    /// The flow control limit of the stream granted by the peer, which makes no difference once
+    /// all the data is written, and is zero after the stream is reset.
+    pub max_stream_data: u64,
+    /// The application error code the stream is reset with.
+    pub reset: Option<u64>,
+}
+
+impl SendStats {
+    fn new(state: SendState, sndbuf: Option<&SendBuf>, max_stream_data: u64) -> Self {
+        SendStats {
+            state,
+            written: sndbuf.map_or(0, SendBuf::len),
+            sent: sndbuf.map_or(0, SendBuf::sent),
+            acked: sndbuf.map_or(0, SendBuf::acked),
+            retransmitted: sndbuf.map_or(0, SendBuf::retransmitted),
+            max_stream_data,
+            reset: None,
+        }
+    }
+
+    fn with_reset(self, reset: Option<u64>) -> Self {
+        SendStats { reset, ..self }
+    }
+}
+
 #[derive(Debug)]
 pub(super) enum Sender {
     Ready(ReadySender),
     Sending(SendingSender),
     DataSent(DataSentSender),
     ResetSent(StreamReset),
+    ResetAtSent(ResetAtSender),
     DataRcvd,
     ResetRcvd(StreamReset),
 }
 
 impl Sender {
-    pub fn with_wnd_size(wnd_size: u64) -> Self {
-        Sender::Ready(ReadySender::with_wnd_size(wnd_size))
+    pub fn with_wnd_size(wnd_size: u64, budget: ArcSendBudget) -> Self {
+        Sender::Ready(ReadySender::with_wnd_size(wnd_size, budget))
+    }
+
+    pub(super) fn stats(&self) -> SendStats {
+        match self {
+            Sender::Ready(s) => SendStats::new(SendState::Ready, Some(&s.sndbuf), s.max_data_size)
+                .with_reset(s.cancel_state),
+            Sender::Sending(s) => SendStats::new(SendState::Send, Some(&s.sndbuf), s.max_data_size)
+                .with_reset(s.cancel_state),
+            Sender::DataSent(s) => {
+                SendStats::new(SendState::DataSent, Some(&s.sndbuf), s.max_data_size)
+                    .with_reset(s.cancel_state)
+            }
+            Sender::ResetSent(r) => {
+                SendStats::new(SendState::ResetSent, None, 0).with_reset(Some(r.0))
+            }
+            Sender::ResetAtSent(s) => {
+                SendStats::new(SendState::ResetSent, Some(&s.sndbuf), 0).with_reset(Some(s.reset.0))
+            }
+            Sender::DataRcvd => SendStats::new(SendState::DataRecvd, None, 0),
+            Sender::ResetRcvd(r) => {
+                SendStats::new(SendState::ResetRecvd, None, 0).with_reset(Some(r.0))
+            }
+        }
     }
 }
 
@@ -518,13 +971,21 @@ impl Sender {
 pub struct ArcSender {
     sender: Arc<Mutex<Result<Sender, Error>>>,
     sid: StreamId,
+    priority: Arc<AtomicU8>,
+    drop_policy: Arc<Mutex<DropPolicy>>,
 }
 
 impl ArcSender {
     #[doc(hidden)]
-    pub(crate) fn new(wnd_size: u64, sid: StreamId) -> Self {
-        let sender = Arc::new(Mutex::new(Ok(Sender::with_wnd_size(wnd_size))));
-        ArcSender { sender, sid }
+    pub(crate) fn new(wnd_size: u64, sid: StreamId, budget: ArcSendBudget) -> Self {
+        let sender = Arc::new(Mutex::new(Ok(Sender::with_wnd_size(wnd_size, budget))));
+        let priority = Arc::new(AtomicU8::new(DEFAULT_PRIORITY));
+        ArcSender {
+            sender,
+            sid,
+            priority,
+            drop_policy: Arc::default(),
+        }
     }
 
     pub(super) fn sender(&self) -> MutexGuard<Result<Sender, Error>> {
@@ -534,4 +995,20 @@ impl ArcSender {
     pub(super) fn sid(&self) -> StreamId {
         self.sid
     }
+
+    pub(super) fn priority(&self) -> u8 {
+        self.priority.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_priority(&self, priority: u8) {
+        self.priority.store(priority, Ordering::Relaxed);
+    }
+
+    pub(super) fn drop_policy(&self) -> DropPolicy {
+        *self.drop_policy.lock().unwrap()
+    }
+
+    pub(crate) fn set_drop_policy(&self, policy: DropPolicy) {
+        *self.drop_policy.lock().unwrap() = policy;
+    }
 }
@@ -2,9 +2,13 @@ use std::{
     cmp::Ordering,
     collections::VecDeque,
     fmt::{Debug, Display},
+    io,
     ops::Range,
+    task::{Context, Poll},
 };
 
+use super::ArcSendBudget;
+
 /// To indicate the state of a data segment, it is colored.
 #[derive(Default, PartialEq, Eq, Clone, Copy, Debug)]
 enum Color {
@@ -174,6 +178,11 @@ impl BufMap {
             })
     }
 
+    // 是否有被判定丢失、等待重传的数据
+    fn has_lost(&self) -> bool {
+        self.0.iter().any(|state| state.color() == Color::Lost)
+    }
+
     // 收到了ack确认，确认的数据不需再发送，对于头部连续确认的数据，就可以删掉。
     // 寻找到ack区间所在的位置，将这些区间都染成Recved，然后检查前后是否有需要合并的区间，合并之。
     // ack区间，不能ack到Pending的数据，因为Pending的数据尚未发送过，当然无法被ack。
@@ -297,6 +306,37 @@ impl BufMap {
         }
     }
 
+    // 不再重传pos之前的数据，其中等待重传的Lost区间被当作Recved，以便shift越过它们归还空间，
+    // 返回其中最小的位置
+    fn drop_lost_before(&mut self, pos: u64) -> Option<u64> {
+        let mut first_dropped = None;
+        let mut idx = 0;
+        while idx < self.0.len() {
+            let (start, color) = self.0[idx].decode();
+            if start >= pos {
+                break;
+            }
+            if color == Color::Lost {
+                first_dropped.get_or_insert(start);
+                let end = self.0.get(idx + 1).map_or(self.1, |s| s.offset());
+                self.0[idx].set_color(Color::Recved);
+                if end > pos {
+                    self.0.insert(idx + 1, State::encode(pos, Color::Lost));
+                    break;
+                }
+            }
+            idx += 1;
+        }
+        // 合并相邻的同色区间
+        let mut pre_color = None;
+        self.0.retain(|s| {
+            let is_same = pre_color == Some(s.color());
+            pre_color = Some(s.color());
+            !is_same
+        });
+        first_dropped
+    }
+
     // 判定某部分数据丢失，但不一定真的丢失，判定可能有误；丢失的数据需要优先重传。
     // 寻找到丢失区间覆盖的范围，其中若遇到Recved的区间，则忽略；只有Flighting/Lost的才可以丢失。
     // 然后检查Lost区间前后是否有需要合并的区间，合并之。
@@ -514,6 +554,11 @@ impl BufMap {
 /// The data picked up may not continuous, the [`receive buffer`] will assemble the data into continuous before
 /// passing them to the application layer.
 ///
+/// The data buffered by the streams is bounded by an [`ArcSendBudget`], which is acquired by
+/// [`poll_reserve`] before writing, and released once the data is acknowledged or the buffer is
+/// dropped.
+///
+/// [`poll_reserve`]: SendBuf::poll_reserve
 /// [`pick_up`]: SendBuf::pick_up
 /// [`on_data_acked`]: SendBuf::on_data_acked
 /// [`may_loss_data`]: SendBuf::may_loss_data
@@ -524,6 +569,16 @@ pub struct SendBuf {
     // 写入数据的环形队列，与接收队列不同的是，它是连续的
     data: VecDeque<u8>,
     state: BufMap,
+    // 发送缓冲区的数据量受流和连接的上限约束，Crypto流则没有
+    budget: Option<ArcSendBudget>,
+    // 新数据是按序发送的，即发送过的数据的末尾
+    sent: u64,
+    // 每次重传的数据量的累计
+    retransmitted: u64,
+    // 此前的数据不再重传
+    discard: u64,
+    // 被丢弃的数据的最小位置，对方无法读取到此后的数据
+    first_dropped: Option<u64>,
 }
 
 impl SendBuf {
@@ -533,9 +588,41 @@ impl SendBuf {
             offset: 0,
             data: VecDeque::with_capacity(n),
             state: BufMap::default(),
+            budget: None,
+            sent: 0,
+            retransmitted: 0,
+            discard: 0,
+            first_dropped: None,
         }
     }
 
+    /// Create a new [`SendBuf`] with the given size, the data buffered is bounded by the `budget`.
+    pub fn with_budget(n: usize, budget: ArcSendBudget) -> Self {
+        let mut sndbuf = Self::with_capacity(n);
+        sndbuf.budget = Some(budget);
+        sndbuf
+    }
+
+    /// Reserve the budget to write up to `n` bytes, returns the amount that can be written, which
+    /// must be written right after that.
+    ///
+    /// Return Pending if the data buffered reaches the bound of the stream or the connection, the
+    /// task will be woken up once some data is acknowledged. It's always ready without a budget.
+    pub fn poll_reserve(&mut self, cx: &mut Context<'_>, n: usize) -> Poll<usize> {
+        match &self.budget {
+            Some(budget) => budget.poll_acquire(cx, self.data.len(), n),
+            None => Poll::Ready(n),
+        }
+    }
+
+    /// Return the amount of data that can be written without pending, within the bound of the
+    /// budget.
+    pub fn available(&self) -> usize {
+        self.budget
+            .as_ref()
+            .map_or(usize::MAX, |budget| budget.available(self.data.len()))
+    }
+
     /// Write data to the [`SendBuf`].
     ///
     /// Return the number of bytes written, always equal to the length of the `data`.
@@ -557,6 +644,49 @@ impl SendBuf {
         n
     }
 
+    /// Write up to `n` bytes to the [`SendBuf`] by `fill`, which fills the buffer in place and
+    /// returns how many bytes are filled, so the data can be read from the source, such as a file,
+    /// without a staging copy.
+    ///
+    /// The space to fill is in two slices, since the internal buffer is a ring buffer, the first
+    /// one is filled before the second one. Nothing is written if `fill` fails.
+    ///
+    /// Like [`SendBuf::write`], the amount of data is limited by the caller.
+    pub fn write_with<F>(&mut self, n: usize, fill: F) -> io::Result<usize>
+    where
+        F: FnOnce(&mut [u8], &mut [u8]) -> io::Result<usize>,
+    {
+        let origin = self.data.len();
+        self.data.resize(origin + n, 0);
+        // 新增的n个字节恰好在环形队列的末尾
+        let (l, r) = self.data.as_mut_slices();
+        let (s1, s2) = if origin < l.len() {
+            (&mut l[origin..], r)
+        } else {
+            (&mut [][..], &mut r[origin - l.len()..])
+        };
+        let result = fill(s1, s2).map(|filled| filled.min(n));
+        let filled = *result.as_ref().unwrap_or(&0);
+        self.data.truncate(origin + filled);
+        if filled > 0 {
+            self.state.extend_to(self.len() + filled as u64);
+        }
+        result
+    }
+
+    /// [`SendBuf::write_with`] the `n` bytes reserved by [`SendBuf::poll_reserve`], the budget of
+    /// the bytes not filled is returned.
+    pub fn write_reserved_with<F>(&mut self, n: usize, fill: F) -> io::Result<usize>
+    where
+        F: FnOnce(&mut [u8], &mut [u8]) -> io::Result<usize>,
+    {
+        let result = self.write_with(n, fill);
+        if let Some(budget) = &self.budget {
+            budget.release(n - *result.as_ref().unwrap_or(&0));
+        }
+        result
+    }
+
     /// Return whether the [`SendBuf`] is empty.
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
@@ -567,6 +697,21 @@ impl SendBuf {
         self.state.1
     }
 
+    /// Return the amount of data sent at least once, the new data is always sent in order.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Return the amount of data acknowledged continuously from the beginning.
+    pub fn acked(&self) -> u64 {
+        self.offset
+    }
+
+    /// Return the cumulative amount of data retransmitted, each retransmission is counted.
+    pub fn retransmitted(&self) -> u64 {
+        self.retransmitted
+    }
+
     /// Return the number of bytes can be written without reallocation.
     pub fn remaining_mut(&self) -> usize {
         self.data.capacity() - self.data.len()
@@ -603,6 +748,11 @@ impl SendBuf {
         self.state
             .pick(predicate, flow_limit)
             .map(|(range, is_fresh)| {
+                if is_fresh {
+                    self.sent = self.sent.max(range.end);
+                } else {
+                    self.retransmitted += range.end - range.start;
+                }
                 let start = (range.start - self.offset) as usize;
                 let end = (range.end - self.offset) as usize;
 
@@ -620,11 +770,19 @@ impl SendBuf {
     // ack只能确认Flighting/Lost状态的区间；如果确认的是Lost区间，意味着之前的判定丢包是错误的。
     pub fn on_data_acked(&mut self, range: &Range<u64>) {
         self.state.ack_rcvd(range);
-        // 对于头部连续确认接收到的，还要前进，以免浪费空间
+        self.advance();
+    }
+
+    // 对于头部连续确认接收到的，或者被丢弃的，还要前进，以免浪费空间
+    fn advance(&mut self) {
         let min_unrecved_pos = self.state.shift();
         if self.offset < min_unrecved_pos {
-            self.data.drain(..(min_unrecved_pos - self.offset) as usize);
+            let acked = (min_unrecved_pos - self.offset) as usize;
+            self.data.drain(..acked);
             self.offset = min_unrecved_pos;
+            if let Some(budget) = &self.budget {
+                budget.release(acked);
+            }
         }
     }
 
@@ -635,17 +793,84 @@ impl SendBuf {
     // 或者距离发送该段数据之后相当长一段时间都没收到它的确认。
     pub fn may_loss_data(&mut self, range: &Range<u64>) {
         self.state.may_loss(range);
+        if range.start < self.discard {
+            self.drop_lost();
+        }
+    }
+
+    /// Stop retransmitting the data before `offset`, the data waiting for retransmission, or lost
+    /// afterwards, is dropped instead. The data never sent is still sent.
+    ///
+    /// The data dropped is removed from the buffer like the data acknowledged, and its budget is
+    /// released, but [`SendBuf::is_all_rcvd`] never holds after that.
+    pub fn discard_before(&mut self, offset: u64) {
+        let offset = offset.min(self.len());
+        if offset > self.discard {
+            self.discard = offset;
+            self.drop_lost();
+        }
+    }
+
+    /// The data before `offset` must be delivered reliably, it's retransmitted even if it's before
+    /// the offset of [`SendBuf::discard_before`].
+    ///
+    /// Nothing before `offset` should have been dropped.
+    pub fn keep_before(&mut self, offset: u64) {
+        debug_assert!(self.first_dropped.is_none_or(|dropped| dropped >= offset));
+        self.discard = self.discard.min(offset);
+    }
+
+    fn drop_lost(&mut self) {
+        if let Some(dropped) = self.state.drop_lost_before(self.discard) {
+            self.first_dropped = Some(self.first_dropped.map_or(dropped, |d| d.min(dropped)));
+            self.advance();
+        }
+    }
+
+    /// Return the smallest offset of the data dropped by [`SendBuf::discard_before`], the peer
+    /// can't read beyond it.
+    pub fn first_dropped(&self) -> Option<u64> {
+        self.first_dropped
+    }
+
+    /// Return whether there is data that may be lost and waits to be retransmitted.
+    ///
+    /// The lost data is always picked up before the new data, see [`SendBuf::pick_up`].
+    pub fn has_lost_data(&self) -> bool {
+        self.state.has_lost()
     }
 
     /// Return whether all data currently written has been received(acknowledged) by the peer.
+    ///
+    /// It's never true if some data is dropped by [`SendBuf::discard_before`].
     pub fn is_all_rcvd(&self) -> bool {
-        self.data.is_empty()
+        self.data.is_empty() && self.first_dropped.is_none()
+    }
+
+    /// Return whether all data before `offset` has been received(acknowledged) by the peer.
+    pub fn is_rcvd_before(&self, offset: u64) -> bool {
+        self.offset >= offset
+    }
+}
+
+impl Drop for SendBuf {
+    // 未确认的数据不会再发送了，归还额度
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.release(self.data.len());
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{BufMap, Color, State};
+    use std::{
+        io,
+        task::{Context, Poll},
+    };
+
+    use super::{ArcSendBudget, BufMap, Color, SendBuf, State};
+    use crate::send::SendBufferLimits;
 
     #[test]
     fn test_bufmap_empty() {
@@ -1084,4 +1309,106 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_sndbuf_write_with() {
+        let mut sndbuf = SendBuf::with_capacity(8);
+        sndbuf.write(b"012345");
+        sndbuf.pick_up(|_| Some(4), usize::MAX).unwrap();
+        sndbuf.on_data_acked(&(0..4));
+
+        // 环形队列绕回，分两段填充
+        let n = sndbuf
+            .write_with(5, |s1, s2| {
+                assert_eq!(s1.len() + s2.len(), 5);
+                let data = b"6789a";
+                s1.copy_from_slice(&data[..s1.len()]);
+                s2.copy_from_slice(&data[s1.len()..]);
+                Ok(5)
+            })
+            .unwrap();
+        assert_eq!(n, 5);
+        assert_eq!(sndbuf.len(), 11);
+        // 只填充了一部分，或者填充失败
+        assert_eq!(
+            sndbuf
+                .write_with(4, |s1, _| {
+                    s1[0] = b'b';
+                    Ok(1)
+                })
+                .unwrap(),
+            1
+        );
+        assert!(sndbuf
+            .write_with(4, |_, _| Err(io::ErrorKind::Other.into()))
+            .is_err());
+        assert_eq!(sndbuf.len(), 12);
+
+        let (offset, is_fresh, (s1, s2)) = sndbuf.pick_up(|_| Some(100), usize::MAX).unwrap();
+        assert_eq!((offset, is_fresh), (4, true));
+        assert_eq!([s1, s2].concat(), b"456789ab");
+    }
+
+    #[test]
+    fn test_sndbuf_discard_before() {
+        let mut sndbuf = SendBuf::with_capacity(30);
+        sndbuf.write(&[0; 30]);
+        for _ in 0..3 {
+            sndbuf.pick_up(|_| Some(10), usize::MAX).unwrap();
+        }
+
+        // 等待重传的数据被丢弃，越过丢弃位置的部分仍然重传
+        sndbuf.may_loss_data(&(10..20));
+        sndbuf.discard_before(15);
+        assert_eq!(sndbuf.first_dropped(), Some(10));
+        let (offset, is_fresh, (s1, s2)) = sndbuf.pick_up(|_| Some(100), usize::MAX).unwrap();
+        assert_eq!((offset, is_fresh, s1.len() + s2.len()), (15, false, 5));
+        assert!(!sndbuf.has_lost_data());
+
+        // 之后丢失的数据也被丢弃
+        sndbuf.may_loss_data(&(0..10));
+        assert!(!sndbuf.has_lost_data());
+        assert_eq!(sndbuf.first_dropped(), Some(0));
+        assert!(sndbuf.pick_up(|_| Some(100), usize::MAX).is_none());
+        assert_eq!(sndbuf.retransmitted(), 5);
+
+        // 要求可靠送达的数据仍然重传
+        sndbuf.keep_before(0);
+        sndbuf.may_loss_data(&(20..30));
+        let (offset, ..) = sndbuf.pick_up(|_| Some(100), usize::MAX).unwrap();
+        assert_eq!(offset, 20);
+    }
+
+    #[test]
+    fn test_sndbuf_discard_release_budget() {
+        let budget = ArcSendBudget::new(SendBufferLimits {
+            stream: 20,
+            connection: 20,
+        });
+        let mut sndbuf = SendBuf::with_budget(20, budget.clone());
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert_eq!(sndbuf.poll_reserve(&mut cx, 20), Poll::Ready(20));
+        sndbuf.write(&[0; 20]);
+        assert_eq!(sndbuf.poll_reserve(&mut cx, 1), Poll::Pending);
+        for _ in 0..2 {
+            sndbuf.pick_up(|_| Some(10), usize::MAX).unwrap();
+        }
+
+        // 丢弃的数据腾出空间，可以继续写入超过上限的数据
+        sndbuf.may_loss_data(&(0..10));
+        sndbuf.discard_before(10);
+        assert_eq!(budget.used(), 10);
+        assert_eq!(sndbuf.poll_reserve(&mut cx, 10), Poll::Ready(10));
+        sndbuf.write(&[0; 10]);
+        assert_eq!(sndbuf.len(), 30);
+
+        // 之后丢失的数据同样归还空间，但不算作全部被接收
+        sndbuf.discard_before(20);
+        sndbuf.may_loss_data(&(10..20));
+        assert_eq!(budget.used(), 10);
+        sndbuf.pick_up(|_| Some(10), usize::MAX).unwrap();
+        sndbuf.on_data_acked(&(20..30));
+        assert_eq!(budget.used(), 0);
+        assert!(!sndbuf.is_all_rcvd());
+    }
 }
@@ -1,16 +1,46 @@
 use std::{
-    io,
-    ops::DerefMut,
+    fs::File,
+    future::Future,
+    io::{self, IoSlice, IoSliceMut, Read, Seek, SeekFrom},
+    ops::{Deref, DerefMut, Range},
     pin::Pin,
-    task::{Context, Poll},
+    task::{Context, Poll, Waker},
+    time::Duration,
 };
 
 use qbase::streamid::StreamId;
-use tokio::io::AsyncWrite;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 use super::sender::{ArcSender, Sender};
 use crate::send::sender::DataSentSender;
 
+// The most data read from the file into the send buffer at a time, by [`Writer::send_file`].
+const SEND_FILE_CHUNK: usize = 64 * 1024;
+
+/// What to do with the stream when the [`Writer`] is dropped before the stream is finished or
+/// cancelled, see [`Writer::set_drop_policy`].
+///
+/// Once the stream is [`shutdown`] or [`finish`]ed, the `FIN` has been queued, and the data written
+/// is still delivered after the [`Writer`] dropped, whichever the policy is.
+///
+/// [`shutdown`]: tokio::io::AsyncWriteExt::shutdown
+/// [`finish`]: Writer::finish
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Reset the stream with the error code, the data not acknowledged yet is discarded, like
+    /// [`Writer::cancel`]. It's the default, with the error code 0.
+    Reset(u64),
+    /// Finish the stream, the data written is still sent and retransmitted until acknowledged,
+    /// like [`Writer::finish`] without waiting for it.
+    Finish,
+}
+
+impl Default for DropPolicy {
+    fn default() -> Self {
+        Self::Reset(0)
+    }
+}
+
 /// The writer part of a QUIC stream.
 ///
 /// This struct implements the [`AsyncWrite`] trait, allowing you to write data to the stream.
@@ -25,7 +55,8 @@ use crate::send::sender::DataSentSender;
 ///
 /// # Note
 ///
-/// The stream must be cancelled or shutdowned before the [`Writer`] dropped.
+/// The stream should be cancelled or shutdowned before the [`Writer`] dropped, otherwise the stream
+/// is reset or finished according to the [`DropPolicy`], see [`Writer::set_drop_policy`].
 ///
 /// Call [`shutdown`] means that there are no more new data will been written to the stream. If all
 /// of the data written to the stream has been sent and acknowledged by the peer, the stream will be
@@ -78,36 +109,154 @@ impl Writer {
     ///
     /// [`RESET_STREAM frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-reset_stream-frames
     pub fn cancel(self, err_code: u64) {
+        self.cancel_at(err_code, 0);
+    }
+
+    /// Cancels the stream with the given error code, but the data before `reliable_size` are still
+    /// delivered to the peer reliably.
+    ///
+    /// If the peer supports the reliable stream reset extension, a [`RESET_STREAM_AT frame`] will be
+    /// sent to the peer, the data before `reliable_size` will still be sent and retransmitted,
+    /// which is useful for the media streams to deliver the complete frames. Otherwise, its the
+    /// same as [`cancel`].
+    ///
+    /// The `reliable_size` is limited to the amount of data that has been written to the stream.
+    ///
+    /// [`RESET_STREAM_AT frame`]: https://www.ietf.org/archive/id/draft-ietf-quic-reliable-stream-reset-06.html#name-reset_stream_at-frame
+    /// [`cancel`]: Writer::cancel
+    pub fn cancel_at(self, err_code: u64, reliable_size: u64) {
         let mut sender = self.0.sender();
         let inner = sender.deref_mut();
         if let Ok(sending_state) = inner {
             match sending_state {
-                Sender::Ready(s) => s.cancel(err_code),
-                Sender::Sending(s) => s.cancel(err_code),
-                Sender::DataSent(s) => s.cancel(err_code),
+                Sender::Ready(s) => s.cancel(err_code, reliable_size),
+                Sender::Sending(s) => s.cancel(err_code, reliable_size),
+                Sender::DataSent(s) => s.cancel(err_code, reliable_size),
                 _ => (),
             }
         };
     }
 
+    /// Stops retransmitting the data before `offset`, for the live media whose stale data has no
+    /// value. The data waiting for retransmission, or lost afterwards, is dropped instead, but the
+    /// data never sent is still sent.
+    ///
+    /// The peer can't read beyond the data dropped, so the stream won't finish once any data is
+    /// dropped, it should be cancelled after the data written is no longer needed. If the stream
+    /// is cancelled by [`cancel_at`], the reliable size is limited to the first data dropped, the
+    /// peer reads the data before that and then the stream is reset.
+    ///
+    /// The `offset` is limited to the amount of data that has been written to the stream.
+    ///
+    /// [`cancel_at`]: Writer::cancel_at
+    pub fn discard_before(&self, offset: u64) {
+        let mut sender = self.0.sender();
+        match sender.deref_mut() {
+            Ok(Sender::Ready(s)) => s.discard_before(offset),
+            Ok(Sender::Sending(s)) => s.discard_before(offset),
+            Ok(Sender::DataSent(s)) => s.discard_before(offset),
+            _ => (),
+        }
+    }
+
+    /// Returns how many bytes can be written right now without pending.
+    ///
+    /// The writes are bounded by the flow control limit granted by the peer, and the data buffered
+    /// to send, which is either unsent or unacknowledged, is bounded by the [`SendBufferLimits`] of
+    /// the stream and the connection. Returns 0 if the stream can not be written any more.
+    ///
+    /// [`SendBufferLimits`]: crate::send::SendBufferLimits
+    pub fn capacity(&self) -> usize {
+        match self.0.sender().deref() {
+            Ok(Sender::Ready(s)) => s.capacity(),
+            Ok(Sender::Sending(s)) => s.capacity(),
+            _ => 0,
+        }
+    }
+
     /// Returns the stream ID of the stream.
     pub fn stream_id(&self) -> StreamId {
         self.0.sid()
     }
-}
 
-impl AsyncWrite for Writer {
-    /// 往sndbuf里面写数据，直到写满MAX_STREAM_DATA，等通告窗口更新再写
-    fn poll_write(
-        self: Pin<&mut Self>,
+    /// Sets the priority of the stream, the smaller the value, the higher the priority.
+    ///
+    /// The data of the streams with higher priority, including the retransmission of their lost
+    /// data, is sent before the streams with lower priority, and the streams with the same
+    /// priority share the bandwidth fairly. The priority is [`DEFAULT_PRIORITY`] by default.
+    ///
+    /// [`DEFAULT_PRIORITY`]: crate::send::DEFAULT_PRIORITY
+    pub fn set_priority(&self, priority: u8) {
+        self.0.set_priority(priority);
+    }
+
+    /// Returns the priority of the stream, see [`Writer::set_priority`].
+    pub fn priority(&self) -> u8 {
+        self.0.priority()
+    }
+
+    /// Write data from `buf` like [`write`], but fail with [`io::ErrorKind::TimedOut`] if the
+    /// sending window is not available within `timeout`.
+    ///
+    /// Only the pending write is failed, the stream and the connection are not affected, it's okay
+    /// to write again after that.
+    ///
+    /// [`write`]: tokio::io::AsyncWriteExt::write
+    pub async fn write_timeout(&mut self, buf: &[u8], timeout: Duration) -> io::Result<usize> {
+        match tokio::time::timeout(timeout, self.write(buf)).await {
+            Ok(result) => result,
+            Err(_elapsed) => Err(io::Error::new(io::ErrorKind::TimedOut, "write timed out")),
+        }
+    }
+
+    /// Send the `range` of the `file` on the stream, returns how many bytes are sent, which is less
+    /// than the length of the range only if the file ends before the end of the range.
+    ///
+    /// Like `sendfile(2)`, the file is read into the send buffer of the stream directly,
+    /// 64 KiB at most at a time, and the stream frames are built from the send buffer,
+    /// there is no staging buffer like reading the file and then [`write_all`] the data. The send
+    /// buffer is reused as the data is acknowledged, it's bounded by the flow control and the
+    /// [`SendBufferLimits`] as the writes.
+    ///
+    /// The file is read on the current thread, which hardly blocks if the file is cached, such
+    /// as the static contents served frequently.
+    ///
+    /// [`write_all`]: tokio::io::AsyncWriteExt::write_all
+    /// [`SendBufferLimits`]: crate::send::SendBufferLimits
+    pub async fn send_file(&mut self, file: &mut File, range: Range<u64>) -> io::Result<u64> {
+        file.seek(SeekFrom::Start(range.start))?;
+        let total = range.end.saturating_sub(range.start);
+        let mut sent = 0;
+        while sent < total {
+            let max = (total - sent).min(SEND_FILE_CHUNK as u64) as usize;
+            let n = core::future::poll_fn(|cx| {
+                self.poll_write_with(cx, max, |s1, s2| {
+                    file.read_vectored(&mut [IoSliceMut::new(s1), IoSliceMut::new(s2)])
+                })
+            })
+            .await?;
+            if n == 0 {
+                break;
+            }
+            sent += n as u64;
+        }
+        Ok(sent)
+    }
+
+    fn poll_write_with<F>(
+        &self,
         cx: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<io::Result<usize>> {
+        max: usize,
+        fill: F,
+    ) -> Poll<io::Result<usize>>
+    where
+        F: FnOnce(&mut [u8], &mut [u8]) -> io::Result<usize>,
+    {
         let mut sender = self.0.sender();
         let sending_state = sender.as_mut().map_err(|e| e.clone())?;
         match sending_state {
-            Sender::Ready(s) => s.poll_write(cx, buf),
-            Sender::Sending(s) => s.poll_write(cx, buf),
+            Sender::Ready(s) => s.poll_write_with(cx, max, fill),
+            Sender::Sending(s) => s.poll_write_with(cx, max, fill),
             Sender::DataSent(_) => Poll::Ready(Err(io::Error::new(
                 io::ErrorKind::Unsupported,
                 "all data has been written",
@@ -119,37 +268,47 @@ impl AsyncWrite for Writer {
             Sender::ResetSent(reset) => {
                 Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
             }
+            Sender::ResetAtSent(s) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, s.reset())))
+            }
             Sender::ResetRcvd(reset) => {
                 Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
             }
         }
     }
 
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        let mut sender = self.0.sender();
-        let sending_state = sender.as_mut().map_err(|e| e.clone())?;
-        match sending_state {
-            Sender::Ready(s) => s.poll_flush(cx),
-            Sender::Sending(s) => s.poll_flush(cx),
-            Sender::DataSent(s) => {
-                let result = s.poll_flush(cx);
-                if result.is_ready() {
-                    s.wake_all();
-                    *sending_state = Sender::DataRcvd
-                }
-                result
-            }
-            Sender::DataRcvd => Poll::Ready(Ok(())),
-            Sender::ResetSent(reset) => {
-                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
-            }
-            Sender::ResetRcvd(reset) => {
-                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+    /// Finishes the stream, no more data will be written, and returns a future that completes when
+    /// all data written, including the `FIN` flag, has been acknowledged by the peer.
+    ///
+    /// Unlike [`shutdown`], the [`Writer`] is consumed, the data is still delivered even if the
+    /// returned future is dropped. If the stream has been reset, or the connection is closed, the
+    /// future completes with an error.
+    ///
+    /// [`shutdown`]: tokio::io::AsyncWriteExt::shutdown
+    pub fn finish(self) -> impl Future<Output = io::Result<()>> + Send + 'static {
+        let shutdown = self.poll_shutdown_sender(&mut Context::from_waker(Waker::noop()));
+        let finished = self.finished();
+        async move {
+            if let Poll::Ready(Err(e)) = shutdown {
+                return Err(e);
             }
+            finished.await
         }
     }
 
-    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    /// Set what to do with the stream if the [`Writer`] is dropped before the stream is finished
+    /// or cancelled, the default is [`DropPolicy::Reset`] with the error code 0, unless another
+    /// default is set for the connection.
+    pub fn set_drop_policy(&self, policy: DropPolicy) {
+        self.0.set_drop_policy(policy);
+    }
+
+    /// Returns what to do with the stream if the [`Writer`] is dropped, see [`DropPolicy`].
+    pub fn drop_policy(&self) -> DropPolicy {
+        self.0.drop_policy()
+    }
+
+    fn poll_shutdown_sender(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let mut sender = self.0.sender();
         let sending_state = sender.as_mut().map_err(|e| e.clone())?;
         match sending_state {
@@ -191,45 +350,357 @@ impl AsyncWrite for Writer {
             Sender::ResetSent(reset) => {
                 Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
             }
+            Sender::ResetAtSent(s) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, s.reset())))
+            }
             Sender::ResetRcvd(reset) => {
                 Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
             }
         }
     }
-}
 
-impl Drop for Writer {
-    fn drop(&mut self) {
-        let mut sender = self.0.sender();
-        let inner = sender.deref_mut();
-        if let Ok(sending_state) = inner {
+    /// Returns a future that completes when all data written to the stream, including the `FIN`
+    /// flag, has been acknowledged by the peer.
+    ///
+    /// Unlike [`shutdown`], this method does not close the stream by itself, it just waits for the
+    /// stream to be closed, so it can be called at any time, and the returned future does not
+    /// borrow the [`Writer`]. If the stream is reset or the connection is closed, the future will
+    /// complete with an error.
+    ///
+    /// [`shutdown`]: tokio::io::AsyncWriteExt::shutdown
+    pub fn finished(&self) -> impl Future<Output = io::Result<()>> + Send + 'static {
+        let sender = self.0.clone();
+        core::future::poll_fn(move |cx| {
+            let mut sender = sender.sender();
+            let sending_state = sender.as_mut().map_err(|e| e.clone())?;
             match sending_state {
-                Sender::Ready(s) => {
-                    assert!(
-                        s.is_cancelled(),
-                        "SendingStream in Ready State must be 
-                        cancelled with error code before dropped!"
-                    );
+                Sender::Ready(s) => s.poll_finished(cx),
+                Sender::Sending(s) => s.poll_finished(cx),
+                Sender::DataSent(s) => s.poll_finished(cx),
+                Sender::DataRcvd => Poll::Ready(Ok(())),
+                Sender::ResetSent(reset) => {
+                    Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
                 }
-                Sender::Sending(s) => {
-                    assert!(
-                        s.is_cancelled(),
-                        "SendingStream in Sending State must be 
-                        cancelled with error code before dropped!"
-                    );
+                Sender::ResetAtSent(s) => {
+                    Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, s.reset())))
                 }
-                Sender::DataSent(s) => {
-                    assert!(
-                        s.is_cancelled(),
-                        "SendingStream in DataSent State must be 
-                        cancelled with error code before dropped!"
-                    );
+                Sender::ResetRcvd(reset) => {
+                    Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
                 }
-                _ => (),
             }
+        })
+    }
+}
+
+impl AsyncWrite for Writer {
+    /// 往sndbuf里面写数据，直到写满MAX_STREAM_DATA，等通告窗口更新再写
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut sender = self.0.sender();
+        let sending_state = sender.as_mut().map_err(|e| e.clone())?;
+        match sending_state {
+            Sender::Ready(s) => s.poll_write(cx, buf),
+            Sender::Sending(s) => s.poll_write(cx, buf),
+            Sender::DataSent(_) => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "all data has been written",
+            ))),
+            Sender::DataRcvd => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "all data has been received",
+            ))),
+            Sender::ResetSent(reset) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+            }
+            Sender::ResetAtSent(s) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, s.reset())))
+            }
+            Sender::ResetRcvd(reset) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+            }
+        }
+    }
+
+    /// 同[`poll_write`]，但一次写入多个缓冲区，直到写满MAX_STREAM_DATA
+    ///
+    /// [`poll_write`]: AsyncWrite::poll_write
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let mut sender = self.0.sender();
+        let sending_state = sender.as_mut().map_err(|e| e.clone())?;
+        match sending_state {
+            Sender::Ready(s) => s.poll_write_vectored(cx, bufs),
+            Sender::Sending(s) => s.poll_write_vectored(cx, bufs),
+            Sender::DataSent(_) => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "all data has been written",
+            ))),
+            Sender::DataRcvd => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "all data has been received",
+            ))),
+            Sender::ResetSent(reset) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+            }
+            Sender::ResetAtSent(s) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, s.reset())))
+            }
+            Sender::ResetRcvd(reset) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+            }
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut sender = self.0.sender();
+        let sending_state = sender.as_mut().map_err(|e| e.clone())?;
+        match sending_state {
+            Sender::Ready(s) => s.poll_flush(cx),
+            Sender::Sending(s) => s.poll_flush(cx),
+            Sender::DataSent(s) => {
+                let result = s.poll_flush(cx);
+                if result.is_ready() {
+                    s.wake_all();
+                    *sending_state = Sender::DataRcvd
+                }
+                result
+            }
+            Sender::DataRcvd => Poll::Ready(Ok(())),
+            Sender::ResetSent(reset) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+            }
+            Sender::ResetAtSent(s) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, s.reset())))
+            }
+            Sender::ResetRcvd(reset) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, *reset)))
+            }
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_shutdown_sender(cx)
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        let is_unfinished = match self.0.sender().deref() {
+            Ok(Sender::Ready(s)) => !s.is_cancelled(),
+            Ok(Sender::Sending(s)) => !s.is_cancelled(),
+            _ => false,
         };
+        if !is_unfinished {
+            return;
+        }
+        match self.0.drop_policy() {
+            DropPolicy::Reset(err_code) => {
+                let mut sender = self.0.sender();
+                match sender.deref_mut() {
+                    Ok(Sender::Ready(s)) => s.cancel(err_code, 0),
+                    Ok(Sender::Sending(s)) => s.cancel(err_code, 0),
+                    _ => (),
+                }
+            }
+            DropPolicy::Finish => {
+                _ = self.poll_shutdown_sender(&mut Context::from_waker(Waker::noop()));
+            }
+        }
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use qbase::varint::VarInt;
+
+    use super::*;
+    use crate::send::{ArcSendBudget, Outgoing, SendBufferLimits};
+
+    #[tokio::test]
+    async fn test_write_vectored() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut writer = Writer(ArcSender::new(10, sid, Default::default()));
+        let bufs = [IoSlice::new(b"hello "), IoSlice::new(b"world")];
+        assert!(writer.is_write_vectored());
+        assert_eq!(writer.write_vectored(&bufs).await.unwrap(), 10);
+        writer.cancel(0);
+    }
+
+    #[tokio::test]
+    async fn test_write_timeout() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut writer = Writer(ArcSender::new(0, sid, Default::default()));
+        let result = writer
+            .write_timeout(b"hello", Duration::from_millis(10))
+            .await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        writer.cancel(0);
+    }
+
+    #[tokio::test]
+    async fn test_finished() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut writer = Writer(ArcSender::new(10, sid, Default::default()));
+        let outgoing = Outgoing(writer.0.clone());
+        let mut finished = Box::pin(writer.finished());
+
+        writer.write_all(b"hello").await.unwrap();
+        let shutdown = core::future::poll_fn(|cx| Pin::new(&mut writer).poll_shutdown(cx));
+        assert!(futures::poll!(shutdown).is_pending());
+        assert!(futures::poll!(&mut finished).is_pending());
+
+        let mut buf = [0u8; 32];
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 32, 32).unwrap();
+        assert_eq!(len, 5);
+        outgoing.on_data_acked(&frame.range(), frame.is_fin());
+        assert!(finished.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_finish() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut writer = Writer(ArcSender::new(10, sid, Default::default()));
+        let outgoing = Outgoing(writer.0.clone());
+
+        writer.write_all(b"hello").await.unwrap();
+        let mut finished = Box::pin(writer.finish());
+        assert!(futures::poll!(&mut finished).is_pending());
+
+        let mut buf = [0u8; 32];
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 32, 32).unwrap();
+        assert_eq!(len, 5);
+        assert!(frame.is_fin());
+        outgoing.on_data_acked(&frame.range(), frame.is_fin());
+        assert!(finished.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_drop_policy() {
+        // 默认以错误码0重置流
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut writer = Writer(ArcSender::new(20, sid, Default::default()));
+        let outgoing = Outgoing(writer.0.clone());
+        writer.write_all(b"hello").await.unwrap();
+        assert_eq!(writer.drop_policy(), DropPolicy::Reset(0));
+        drop(writer);
+        assert_eq!(outgoing.is_cancelled_by_app().await, Some((5, 0, 0)));
+
+        // 结束流，已写入的数据仍然发送
+        let sid = StreamId::from(VarInt::from_u32(4));
+        let mut writer = Writer(ArcSender::new(20, sid, Default::default()));
+        let outgoing = Outgoing(writer.0.clone());
+        writer.write_all(b"hello").await.unwrap();
+        writer.set_drop_policy(DropPolicy::Finish);
+        drop(writer);
+        let mut buf = [0u8; 32];
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 32, 32).unwrap();
+        assert_eq!(len, 5);
+        assert!(frame.is_fin());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_at() {
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut writer = Writer(ArcSender::new(20, sid, Default::default()));
+        let outgoing = Outgoing(writer.0.clone());
+        let finished = writer.finished();
+
+        writer.write_all(b"hello world").await.unwrap();
+        writer.cancel_at(1, 5);
+        assert_eq!(outgoing.is_cancelled_by_app().await, Some((11, 5, 1)));
+        assert!(finished.await.is_err());
+
+        // 只发送可靠大小之前的数据，且不携带fin
+        let mut buf = [0u8; 32];
+        let (frame, len, is_fresh, _) = outgoing.try_read(sid, &mut buf, 32, 32).unwrap();
+        assert_eq!((frame.range(), len, is_fresh), (0..5, 5, true));
+        assert!(!frame.is_fin());
+        assert!(outgoing.try_read(sid, &mut buf, 32, 32).is_none());
+
+        // 可靠部分的数据丢失后仍要重传
+        outgoing.may_loss_data(&frame.range());
+        let (frame, _, is_fresh, _) = outgoing.try_read(sid, &mut buf, 32, 32).unwrap();
+        assert_eq!((frame.range(), is_fresh), (0..5, false));
+
+        assert!(!outgoing.on_reset_acked());
+        assert!(outgoing.on_data_acked(&frame.range(), false));
+    }
+
+    #[tokio::test]
+    async fn test_send_buffer_limits() {
+        let budget = ArcSendBudget::new(SendBufferLimits {
+            stream: 8,
+            connection: 12,
+        });
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut writer = Writer(ArcSender::new(100, sid, budget.clone()));
+        let outgoing = Outgoing(writer.0.clone());
+        let other_sid = StreamId::from(VarInt::from_u32(4));
+        let mut other = Writer(ArcSender::new(100, other_sid, budget.clone()));
+        assert_eq!(writer.capacity(), 8);
+
+        // 受流的上限约束
+        assert_eq!(writer.write(&[0; 10]).await.unwrap(), 8);
+        assert_eq!(writer.capacity(), 0);
+        let write = writer.write_timeout(&[0; 1], Duration::from_millis(10));
+        assert_eq!(write.await.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        // 受连接的上限约束
+        assert_eq!(other.capacity(), 4);
+        other.write_all(&[0; 4]).await.unwrap();
+        assert_eq!(budget.used(), 12);
+
+        // 发送出去的数据被确认后，才能继续写入
+        let mut buf = [0u8; 32];
+        let (frame, len, ..) = outgoing.try_read(sid, &mut buf, 5, 32).unwrap();
+        assert_eq!(len, 5);
+        assert_eq!(writer.capacity(), 0);
+        outgoing.on_data_acked(&frame.range(), frame.is_fin());
+        assert_eq!(budget.used(), 7);
+        assert_eq!(writer.capacity(), 5);
+        assert_eq!(other.capacity(), 4);
+        assert_eq!(writer.write(&[0; 10]).await.unwrap(), 5);
+
+        // 流取消后，未确认的数据不再占用额度
+        writer.cancel(0);
+        other.cancel(0);
+        drop(outgoing);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_send_file() {
+        let content = (0..70_000u32).map(|i| i as u8).collect::<Vec<u8>>();
+        let path = std::env::temp_dir().join(format!("send_file_{}", std::process::id()));
+        std::fs::write(&path, &content).unwrap();
+        let mut file = File::open(&path).unwrap();
+
+        let sid = StreamId::from(VarInt::from_u32(0));
+        let mut writer = Writer(ArcSender::new(100_000, sid, Default::default()));
+        let outgoing = Outgoing(writer.0.clone());
+        // 文件在范围结束之前就结束了
+        assert_eq!(
+            writer.send_file(&mut file, 1000..80_000).await.unwrap(),
+            69_000
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        let mut received = Vec::new();
+        let mut buf = [0u8; 1500];
+        while let Some((frame, len, _, written)) = outgoing.try_read(sid, &mut buf, 1500, 1500) {
+            assert_eq!(frame.offset(), received.len() as u64);
+            received.extend_from_slice(&buf[written - len..written]);
+        }
+        assert_eq!(received, content[1000..]);
+        writer.cancel(0);
+    }
+}
@@ -28,16 +28,20 @@ use std::{
 use bytes::Bytes;
 pub use data::RawDataStreams;
 use deref_derive::Deref;
-pub use listener::{AcceptBiStream, AcceptUniStream};
+pub use listener::{AcceptBiStream, AcceptBiStreamWithPeek, AcceptUniStream};
 use qbase::{
     config::Parameters,
     error::Error,
+    flow::ArcRtt,
     frame::{ReceiveFrame, SendFrame, StreamCtlFrame, StreamFrame},
     streamid::Role,
 };
 use thiserror::Error;
 
-use crate::{recv::Reader, send::Writer};
+use crate::{
+    recv::{Reader, RecvStats},
+    send::{SendStats, Writer},
+};
 mod data;
 mod listener;
 
@@ -56,8 +60,12 @@ where
     /// Creates a new instance of [`DataStreams`].
     ///
     /// The `ctrl_frames` is the frame sender, read [`RawDataStreams`] for more details.
-    pub fn new(role: Role, local_params: &Parameters, ctrl_frames: T) -> Self {
-        let raw = data::RawDataStreams::new(role, local_params, ctrl_frames);
+    ///
+    /// The receive windows of the streams grow according to the `rtt`, see [`WindowTuner`].
+    ///
+    /// [`WindowTuner`]: qbase::flow::WindowTuner
+    pub fn new(role: Role, local_params: &Parameters, ctrl_frames: T, rtt: ArcRtt) -> Self {
+        let raw = data::RawDataStreams::new(role, local_params, ctrl_frames, rtt);
 
         Self(Arc::new(raw))
     }
@@ -86,6 +94,17 @@ where
         self.0.accept_bi(snd_wnd_size)
     }
 
+    /// Accpet a bidirectional stream once the peer has sent some data on it, see the method of
+    /// the same name on `QuicConnection` for more.
+    #[inline]
+    pub fn accept_bi_with_peek(
+        &self,
+        snd_wnd_size: u64,
+        peek_len: usize,
+    ) -> AcceptBiStreamWithPeek<'_> {
+        self.0.accept_bi_with_peek(snd_wnd_size, peek_len)
+    }
+
     /// Accpet a unidirectional stream, see the method of the same name on `QuicConnection` for more.
     #[inline]
     pub fn accept_uni(&self) -> AcceptUniStream {
@@ -170,6 +189,18 @@ where
     }
 }
 
+/// The statistics of a stream, returned by [`RawDataStreams::stream_stats`], to find out why a
+/// stream is stuck, such as which side the flow control is blocked by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStats {
+    /// The sending part of the stream, [`None`] if the stream can't send, or the sending part
+    /// has ended.
+    pub send: Option<SendStats>,
+    /// The receiving part of the stream, [`None`] if the stream can't receive, or all the data
+    /// has been received or the stream has been reset.
+    pub recv: Option<RecvStats>,
+}
+
 #[derive(Debug, Error, Clone, Copy)]
 #[error("the stream reset with error code {0}")]
 pub struct StreamReset(pub u64);
@@ -1,25 +1,33 @@
 use std::{
     collections::{BTreeMap, HashMap},
-    sync::{Arc, Mutex, MutexGuard},
-    task::{ready, Context, Poll},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    task::{Context, Poll},
 };
 
 use deref_derive::{Deref, DerefMut};
 use qbase::{
     config::Parameters,
     error::{Error as QuicError, ErrorKind},
+    flow::{ArcRtt, BlockedStats, WindowTuner, MAX_STREAM_RECV_WINDOW},
     frame::{
-        BeFrame, FrameType, MaxStreamDataFrame, MaxStreamsFrame, ResetStreamFrame, SendFrame,
-        StopSendingFrame, StreamCtlFrame, StreamFrame, STREAM_FRAME_MAX_ENCODING_SIZE,
+        BeFrame, FrameType, MaxStreamDataFrame, MaxStreamsFrame, ResetStreamAtFrame,
+        ResetStreamFrame, SendFrame, StopSendingFrame, StreamCtlFrame, StreamDataBlockedFrame,
+        StreamFrame, STREAM_FRAME_MAX_ENCODING_SIZE,
     },
-    streamid::{AcceptSid, Dir, ExceedLimitError, Role, StreamId, StreamIds},
+    streamid::{AcceptSid, Dir, ExceedLimitError, MaxStreamsPolicy, Role, StreamId, StreamIds},
     varint::VarInt,
 };
 
-use super::listener::{AcceptBiStream, AcceptUniStream, ArcListener};
+use super::{
+    listener::{AcceptBiStream, AcceptBiStreamWithPeek, AcceptUniStream, ArcListener},
+    StreamStats,
+};
 use crate::{
     recv::{self, ArcRecver, Incoming, Reader},
-    send::{self, ArcSender, Outgoing, Writer},
+    send::{self, ArcSendBudget, ArcSender, DropPolicy, Outgoing, SendBufferLimits, Writer},
 };
 
 #[derive(Default, Debug, Clone, Deref, DerefMut)]
@@ -204,12 +212,23 @@ where
     local_bi_stream_rcvbuf_size: u64,
     // the receive buffer size for the accpeted bidirectional stream created by peer
     remote_bi_stream_rcvbuf_size: u64,
+    // 接收窗口按连接的RTT自动增长
+    rtt: ArcRtt,
+    // 所有流共享的发送缓冲区额度
+    send_budget: ArcSendBudget,
+    // 新建的流的Writer被丢弃时的默认处理
+    drop_policy: Arc<Mutex<DropPolicy>>,
     // 所有流的待写端，要发送数据，就得向这些流索取
     output: ArcOutput,
     // 所有流的待读端，收到了数据，交付给这些流
     input: ArcInput,
     // 对方主动创建的流
     listener: ArcListener,
+    // 双方是否都支持带可靠大小的重置，即RESET_STREAM_AT帧
+    reset_stream_at: Arc<AtomicBool>,
+    // 收到的STREAM_DATA_BLOCKED帧和STREAMS_BLOCKED帧的数量
+    stream_data_blocked: Arc<AtomicU64>,
+    streams_blocked: Arc<AtomicU64>,
 }
 
 fn wrapper_error(fty: FrameType) -> impl FnOnce(ExceedLimitError) -> QuicError {
@@ -226,8 +245,16 @@ where
     ///
     /// It's fair between streams. We have implemented a token bucket algorithm, and the [`try_read_data`]
     /// method will read the data of each stream sequentially. Starting from the first stream, when
-    /// a stream exhausts its tokens (default is 4096, depending on the priority of the stream), or
-    /// there is no data to send, the method will move to the next stream, and so on.
+    /// a stream exhausts its tokens (default is 4096), or there is no data to send, the method will
+    /// move to the next stream, and so on.
+    ///
+    /// # Priority
+    ///
+    /// The fairness only applies between the streams with the same priority, see
+    /// [`Writer::set_priority`]. The streams with higher priority are always read first, then the
+    /// lower ones. Among the streams with the same priority, the ones whose data was lost are read
+    /// first, so that the retransmissions are not delayed by the new data, while the retransmissions
+    /// of the lower priority streams don't delay the higher priority streams either.
     ///
     /// # Flow control
     ///
@@ -252,6 +279,7 @@ where
     /// * [`usize`]: The number of new data writen to the buffer.
     ///
     /// [`try_read_data`]: RawDataStreams::try_read_data
+    /// [`Writer::set_priority`]: crate::send::Writer::set_priority
     /// [`write`]: tokio::io::AsyncWriteExt::write
     pub fn try_read_data(
         &self,
@@ -297,7 +325,15 @@ where
                 .range(..)
                 .map(|(sid, outgoing)| (*sid, outgoing, DEFAULT_TOKENS)),
         };
-        for (sid, outgoing, tokens) in streams.into_iter() {
+        // 按优先级排序，同优先级中丢包重传的流在前，排序是稳定的，不破坏同级流之间的轮转
+        let mut streams = streams
+            .map(|(sid, outgoing, tokens)| {
+                let key = (outgoing.priority(), !outgoing.has_lost_data());
+                (key, sid, outgoing, tokens)
+            })
+            .collect::<Vec<_>>();
+        streams.sort_by_key(|(key, ..)| *key);
+        for (_, sid, outgoing, tokens) in streams {
             if let Some((frame, data_len, is_fresh, written)) =
                 outgoing.try_read(sid, buf, tokens, flow_limit)
             {
@@ -312,14 +348,17 @@ where
     ///
     /// Actually calls the [`Outgoing::on_data_acked`] method of the corresponding stream.
     pub fn on_data_acked(&self, frame: StreamFrame) {
-        if let Ok(set) = self.output.0.lock().unwrap().as_mut() {
-            if set
-                .get(&frame.id)
-                .map(|o| o.on_data_acked(&frame.range(), frame.is_fin()))
-                .is_some_and(|all_data_rcvd| all_data_rcvd)
-            {
-                set.remove(&frame.id);
-            }
+        let all_data_rcvd = self
+            .output
+            .0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .ok()
+            .and_then(|set| set.get(&frame.id))
+            .is_some_and(|o| o.on_data_acked(&frame.range(), frame.is_fin()));
+        if all_data_rcvd {
+            self.release_stream(frame.id, true);
         }
     }
 
@@ -344,11 +383,70 @@ where
     ///
     /// Actually calls the [`Outgoing::on_reset_acked`] method of the corresponding stream.
     pub fn on_reset_acked(&self, reset_frame: ResetStreamFrame) {
-        if let Ok(set) = self.output.0.lock().unwrap().as_mut() {
-            if let Some(o) = set.remove(&reset_frame.stream_id) {
-                o.on_reset_acked();
+        self.on_stream_reset_acked(reset_frame.stream_id);
+    }
+
+    /// Called when the [`RESET_STREAM_AT frame`] sent is acknowledged by peer.
+    ///
+    /// The stream is released after all the data before the reliable size are acknowledged too.
+    ///
+    /// [`RESET_STREAM_AT frame`]: https://www.ietf.org/archive/id/draft-ietf-quic-reliable-stream-reset-06.html#name-reset_stream_at-frame
+    pub fn on_reset_at_acked(&self, reset_frame: ResetStreamAtFrame) {
+        self.on_stream_reset_acked(reset_frame.stream_id);
+    }
+
+    fn on_stream_reset_acked(&self, sid: StreamId) {
+        let is_reset = self
+            .output
+            .0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .ok()
+            .and_then(|set| set.get(&sid))
+            .is_some_and(|o| o.on_reset_acked());
+        // 如果流是双向的，接收部分的流独立地管理结束。其实是上层应用决定接收的部分是否同时结束
+        if is_reset {
+            self.release_stream(sid, true);
+        }
+    }
+
+    /// Returns the statistics of the stream, [`None`] if neither part of the stream is active.
+    pub fn stream_stats(&self, sid: StreamId) -> Option<StreamStats> {
+        let outgoing = {
+            let output = self.output.0.lock().unwrap();
+            output.as_ref().ok().and_then(|set| set.get(&sid).cloned())
+        };
+        let send = outgoing.and_then(|outgoing| outgoing.stats());
+        let recv = self.incoming(sid).and_then(|incoming| incoming.stats());
+        (send.is_some() || recv.is_some()).then_some(StreamStats { send, recv })
+    }
+
+    fn incoming(&self, sid: StreamId) -> Option<Incoming> {
+        let input = self.input.0.lock().unwrap();
+        input.as_ref().ok().and_then(|set| set.get(&sid).cloned())
+    }
+
+    // 流的发送或接收部分结束了，不再收发帧。对方创建的流两部分都结束之后，按照策略为对方补充流的额度
+    fn release_stream(&self, sid: StreamId, is_sending: bool) {
+        let is_closed = {
+            let mut output = self.output.0.lock().unwrap();
+            let mut input = self.input.0.lock().unwrap();
+            let (Ok(output), Ok(input)) = (output.as_mut(), input.as_mut()) else {
+                return;
+            };
+            let released = if is_sending {
+                output.remove(&sid).is_some()
+            } else {
+                input.remove(&sid).is_some()
+            };
+            released && !output.contains_key(&sid) && !input.contains_key(&sid)
+        };
+        if is_closed && sid.role() != self.role {
+            if let Some(frame) = self.stream_ids.remote.on_stream_closed(sid.dir()) {
+                self.ctrl_frames
+                    .send_frame([StreamCtlFrame::MaxStreams(frame)]);
             }
-            // 如果流是双向的，接收部分的流独立地管理结束。其实是上层应用决定接收的部分是否同时结束
         }
     }
 
@@ -377,18 +475,14 @@ where
                 ));
             }
         }
-        let ret = self
-            .input
-            .0
-            .lock()
-            .unwrap()
-            .as_mut()
-            .ok()
-            .and_then(|set| set.get(&sid))
-            .map(|incoming| incoming.recv_data(stream_frame, body.clone()));
-
-        match ret {
-            Some(recv_ret) => recv_ret,
+        match self.incoming(sid) {
+            Some(incoming) => {
+                let new_data_size = incoming.recv_data(stream_frame, body.clone())?;
+                if incoming.is_all_rcvd() {
+                    self.release_stream(sid, false);
+                }
+                Ok(new_data_size)
+            }
             // 该流已结束，收到的数据将被忽略
             None => Ok(0),
         }
@@ -417,9 +511,28 @@ where
                         ));
                     }
                 }
-                if let Ok(set) = self.input.0.lock().unwrap().as_mut() {
-                    if let Some(incoming) = set.remove(&sid) {
-                        incoming.recv_reset(reset)?;
+                if let Some(incoming) = self.incoming(sid) {
+                    self.release_stream(sid, false);
+                    incoming.recv_reset(reset)?;
+                }
+            }
+            StreamCtlFrame::ResetStreamAt(reset) => {
+                let sid = reset.stream_id;
+                // 对方必须是发送端，才能发送此帧
+                if sid.role() != self.role {
+                    self.try_accept_sid(sid)
+                        .map_err(wrapper_error(reset.frame_type()))?;
+                } else if sid.dir() == Dir::Uni {
+                    return Err(QuicError::new(
+                        ErrorKind::StreamState,
+                        reset.frame_type(),
+                        format!("local {sid} cannot receive RESET_STREAM_AT frame"),
+                    ));
+                }
+                // 可靠大小之前的数据仍要继续接收，直到流被重置
+                if let Some(incoming) = self.incoming(sid) {
+                    if incoming.recv_reset_at(reset)? {
+                        self.release_stream(sid, false);
                     }
                 }
             }
@@ -500,7 +613,19 @@ where
                         ));
                     }
                 }
-                // 仅仅起到通知作用?主动更新窗口的，此帧没多大用，或许要进一步放大缓冲区大小；被动更新窗口的，此帧有用
+                // 对方被接收窗口阻塞，下次更新窗口时放大窗口
+                self.stream_data_blocked.fetch_add(1, Ordering::Relaxed);
+                if let Some(incoming) = self
+                    .input
+                    .0
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .ok()
+                    .and_then(|set| set.get(&sid))
+                {
+                    incoming.on_data_blocked(stream_data_blocked.maximum_stream_data.into_inner());
+                }
             }
             StreamCtlFrame::MaxStreams(max_streams) => {
                 // 主要更新我方能创建的单双向流
@@ -518,7 +643,8 @@ where
                 };
             }
             StreamCtlFrame::StreamsBlocked(_streams_blocked) => {
-                // 仅仅起到通知作用?也分主动和被动
+                // 仅仅起到通知作用，流的上限随对方的流关闭按策略增长
+                self.streams_blocked.fetch_add(1, Ordering::Relaxed);
             }
         }
         Ok(())
@@ -560,13 +686,68 @@ where
     pub fn premit_max_sid(&self, dir: Dir, val: u64) {
         self.stream_ids.local.permit_max_sid(dir, val);
     }
+
+    /// Set the policy to replenish the streams the peer can create with the [`MAX_STREAMS frame`]s
+    /// as its streams are closed, see [`MaxStreamsPolicy`].
+    ///
+    /// [`MAX_STREAMS frame`]: https://www.rfc-editor.org/rfc/rfc9000.html#name-max_streams-frames
+    pub fn set_max_streams_policy(&self, policy: MaxStreamsPolicy) {
+        self.stream_ids.remote.set_policy(policy);
+    }
+
+    /// Returns the policy to replenish the streams the peer can create.
+    pub fn max_streams_policy(&self) -> MaxStreamsPolicy {
+        self.stream_ids.remote.policy()
+    }
+
+    /// Set the bounds of the data buffered to send by each stream and by all the streams, the
+    /// writes are pending once reaching the bounds, see [`SendBufferLimits`].
+    pub fn set_send_buffer_limits(&self, limits: SendBufferLimits) {
+        self.send_budget.set_limits(limits);
+    }
+
+    /// Returns the bounds of the data buffered to send.
+    pub fn send_buffer_limits(&self) -> SendBufferLimits {
+        self.send_budget.limits()
+    }
+
+    /// Set the default [`DropPolicy`] of the streams created afterwards, each [`Writer`] can
+    /// still change its own by [`Writer::set_drop_policy`].
+    pub fn set_drop_policy(&self, policy: DropPolicy) {
+        *self.drop_policy.lock().unwrap() = policy;
+    }
+
+    /// Returns the default [`DropPolicy`] of the streams.
+    pub fn drop_policy(&self) -> DropPolicy {
+        *self.drop_policy.lock().unwrap()
+    }
+
+    /// The STREAM_DATA_BLOCKED and STREAMS_BLOCKED frames received from the peer, the
+    /// `data_blocked` is left zero, which is counted by the connection-level flow controller.
+    pub fn blocked_stats(&self) -> BlockedStats {
+        BlockedStats {
+            stream_data_blocked: self.stream_data_blocked.load(Ordering::Relaxed),
+            streams_blocked: self.streams_blocked.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+
+    /// Called when both endpoints support the reliable stream reset extension.
+    ///
+    /// After that, the streams cancelled by [`Writer::cancel_at`] are reset with the
+    /// [`RESET_STREAM_AT frame`], and the data before the reliable size are still sent reliably.
+    ///
+    /// [`RESET_STREAM_AT frame`]: https://www.ietf.org/archive/id/draft-ietf-quic-reliable-stream-reset-06.html#name-reset_stream_at-frame
+    pub fn enable_reset_stream_at(&self) {
+        self.reset_stream_at.store(true, Ordering::Release);
+    }
 }
 
 impl<T> RawDataStreams<T>
 where
     T: SendFrame<StreamCtlFrame> + Clone + Send + 'static,
 {
-    pub(super) fn new(role: Role, local_params: &Parameters, ctrl_frames: T) -> Self {
+    pub(super) fn new(role: Role, local_params: &Parameters, ctrl_frames: T, rtt: ArcRtt) -> Self {
         Self {
             role,
             stream_ids: StreamIds::new(
@@ -577,9 +758,15 @@ where
             uni_stream_rcvbuf_size: local_params.initial_max_stream_data_uni().into(),
             local_bi_stream_rcvbuf_size: local_params.initial_max_stream_data_bidi_local().into(),
             remote_bi_stream_rcvbuf_size: local_params.initial_max_stream_data_bidi_remote().into(),
+            rtt,
+            send_budget: ArcSendBudget::default(),
+            drop_policy: Arc::default(),
             output: ArcOutput::default(),
             input: ArcInput::default(),
             listener: ArcListener::default(),
+            reset_stream_at: Arc::new(AtomicBool::new(false)),
+            stream_data_blocked: Arc::new(AtomicU64::new(0)),
+            streams_blocked: Arc::new(AtomicU64::new(0)),
             ctrl_frames,
         }
     }
@@ -597,7 +784,14 @@ where
             Ok(input) => input,
             Err(e) => return Poll::Ready(Err(e)),
         };
-        if let Some(sid) = ready!(self.stream_ids.local.poll_alloc_sid(cx, Dir::Bi)) {
+        let sid = match self.stream_ids.local.poll_alloc_sid(cx, Dir::Bi) {
+            Poll::Ready(sid) => sid,
+            Poll::Pending => {
+                self.report_streams_blocked(Dir::Bi);
+                return Poll::Pending;
+            }
+        };
+        if let Some(sid) = sid {
             let arc_sender = self.create_sender(sid, snd_wnd_size);
             let arc_recver = self.create_recver(sid, self.local_bi_stream_rcvbuf_size);
             output.insert(sid, Outgoing(arc_sender.clone()));
@@ -617,7 +811,14 @@ where
             Ok(out) => out,
             Err(e) => return Poll::Ready(Err(e)),
         };
-        if let Some(sid) = ready!(self.stream_ids.local.poll_alloc_sid(cx, Dir::Uni)) {
+        let sid = match self.stream_ids.local.poll_alloc_sid(cx, Dir::Uni) {
+            Poll::Ready(sid) => sid,
+            Poll::Pending => {
+                self.report_streams_blocked(Dir::Uni);
+                return Poll::Pending;
+            }
+        };
+        if let Some(sid) = sid {
             let arc_sender = self.create_sender(sid, snd_wnd_size);
             output.insert(sid, Outgoing(arc_sender.clone()));
             Poll::Ready(Ok(Some(Writer(arc_sender))))
@@ -626,10 +827,27 @@ where
         }
     }
 
+    // 本地流的数量达到了对方的上限，通知对方
+    fn report_streams_blocked(&self, dir: Dir) {
+        if let Some(frame) = self.stream_ids.local.streams_blocked(dir) {
+            self.ctrl_frames
+                .send_frame([StreamCtlFrame::StreamsBlocked(frame)]);
+        }
+    }
+
     pub(super) fn accept_bi(&self, snd_wnd_size: u64) -> AcceptBiStream {
         self.listener.accept_bi_stream(snd_wnd_size)
     }
 
+    pub(super) fn accept_bi_with_peek(
+        &self,
+        snd_wnd_size: u64,
+        peek_len: usize,
+    ) -> AcceptBiStreamWithPeek<'_> {
+        self.listener
+            .accept_bi_stream_with_peek(snd_wnd_size, peek_len)
+    }
+
     pub(super) fn accept_uni(&self) -> AcceptUniStream {
         self.listener.accept_uni_stream()
     }
@@ -698,21 +916,55 @@ where
     }
 
     fn create_sender(&self, sid: StreamId, wnd_size: u64) -> ArcSender {
-        let arc_sender = send::new(wnd_size, sid);
+        let arc_sender = send::new(wnd_size, sid, self.send_budget.clone());
+        arc_sender.set_drop_policy(self.drop_policy());
+        // 应用层被流控阻塞时，通知对方
+        tokio::spawn({
+            let outgoing = Outgoing(arc_sender.clone());
+            let ctrl_frames = self.ctrl_frames.clone();
+            async move {
+                while let Some(limit) = outgoing.is_blocked().await {
+                    ctrl_frames.send_frame([StreamCtlFrame::StreamDataBlocked(
+                        StreamDataBlockedFrame {
+                            stream_id: sid,
+                            maximum_stream_data: unsafe { VarInt::from_u64_unchecked(limit) },
+                        },
+                    )]);
+                }
+            }
+        });
         // 创建异步轮询子，监听来自应用层的cancel
         // 一旦cancel，直接向对方发送reset_stream
         // 但要等ResetRecved才能真正释放该流
         tokio::spawn({
             let outgoing = Outgoing(arc_sender.clone());
             let ctrl_frames = self.ctrl_frames.clone();
+            let reset_stream_at = self.reset_stream_at.clone();
             async move {
-                if let Some((final_size, err_code)) = outgoing.is_cancelled_by_app().await {
-                    ctrl_frames.send_frame([StreamCtlFrame::ResetStream(ResetStreamFrame {
-                        stream_id: sid,
-                        app_error_code: VarInt::from_u64(err_code)
-                            .expect("app error code must not exceed VARINT_MAX"),
-                        final_size: unsafe { VarInt::from_u64_unchecked(final_size) },
-                    })]);
+                if let Some((final_size, reliable_size, err_code)) =
+                    outgoing.is_cancelled_by_app().await
+                {
+                    let app_error_code = VarInt::from_u64(err_code)
+                        .expect("app error code must not exceed VARINT_MAX");
+                    let final_size = unsafe { VarInt::from_u64_unchecked(final_size) };
+                    if reliable_size > 0 && reset_stream_at.load(Ordering::Acquire) {
+                        ctrl_frames.send_frame([StreamCtlFrame::ResetStreamAt(
+                            ResetStreamAtFrame {
+                                stream_id: sid,
+                                app_error_code,
+                                final_size,
+                                reliable_size: unsafe { VarInt::from_u64_unchecked(reliable_size) },
+                            },
+                        )]);
+                    } else {
+                        // 对方不支持带可靠大小的重置，可靠部分的数据也不再发送
+                        outgoing.abandon_reliable_data();
+                        ctrl_frames.send_frame([StreamCtlFrame::ResetStream(ResetStreamFrame {
+                            stream_id: sid,
+                            app_error_code,
+                            final_size,
+                        })]);
+                    }
                 }
             }
         });
@@ -720,7 +972,8 @@ where
     }
 
     fn create_recver(&self, sid: StreamId, buf_size: u64) -> ArcRecver {
-        let arc_recver = recv::new(buf_size, sid);
+        let tuner = WindowTuner::new(buf_size, MAX_STREAM_RECV_WINDOW, self.rtt.clone());
+        let arc_recver = recv::new(tuner, sid);
         // Continuously check whether the MaxStreamData window needs to be updated.
         tokio::spawn({
             let incoming = Incoming(arc_recver.clone());
@@ -751,3 +1004,294 @@ where
         arc_recver
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::FutureExt;
+    use qbase::{frame::StreamsBlockedFrame, util::ArcAsyncDeque};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::{recv::RecvState, send::SendState};
+
+    fn open_uni(streams: &RawDataStreams<ArcAsyncDeque<StreamCtlFrame>>) -> Writer {
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        match streams.poll_open_uni_stream(&mut cx, 1000) {
+            Poll::Ready(Ok(Some(writer))) => writer,
+            _ => panic!("failed to open the stream"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retransmit_by_priority() {
+        let streams = RawDataStreams::new(
+            Role::Client,
+            &Parameters::default(),
+            ArcAsyncDeque::<StreamCtlFrame>::new(),
+            ArcRtt::default(),
+        );
+        streams.premit_max_sid(Dir::Uni, 10);
+        let mut bulk = open_uni(&streams);
+        let mut urgent = open_uni(&streams);
+        bulk.set_priority(5);
+        urgent.set_priority(1);
+        bulk.write_all(&[0; 100]).await.unwrap();
+        urgent.write_all(&[1; 100]).await.unwrap();
+
+        let mut buf = [0u8; 1000];
+        let mut read = || {
+            streams
+                .try_read_data(&mut buf, 1000)
+                .map(|(frame, ..)| frame)
+        };
+        // 高优先级的流先发送
+        let urgent_frame = read().unwrap();
+        assert_eq!(urgent_frame.id, urgent.stream_id());
+        let bulk_frame = read().unwrap();
+        assert_eq!(bulk_frame.id, bulk.stream_id());
+        assert!(read().is_none());
+
+        // 重传也按优先级进行，而非丢包判定的顺序
+        streams.may_loss_data(&bulk_frame);
+        streams.may_loss_data(&urgent_frame);
+        let mut read = || {
+            streams
+                .try_read_data(&mut buf, 1000)
+                .map(|(frame, ..)| frame)
+        };
+        assert_eq!(read().unwrap().id, urgent.stream_id());
+        assert_eq!(read().unwrap().id, bulk.stream_id());
+
+        // 同优先级中，丢包重传的数据先于新数据发送
+        bulk.set_priority(1);
+        urgent.write_all(&[1; 100]).await.unwrap();
+        streams.may_loss_data(&bulk_frame);
+        let (frame, _, fresh) = streams.try_read_data(&mut buf, 1000).unwrap();
+        assert_eq!((frame.id, fresh), (bulk.stream_id(), 0));
+        let (frame, _, fresh) = streams.try_read_data(&mut buf, 1000).unwrap();
+        assert_eq!((frame.id, fresh), (urgent.stream_id(), 100));
+
+        bulk.cancel(0);
+        urgent.cancel(0);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_frames() {
+        let ctrl_frames = ArcAsyncDeque::<StreamCtlFrame>::new();
+        let mut params = Parameters::default();
+        params.set_initial_max_streams_uni(VarInt::from_u32(0));
+        let streams = RawDataStreams::new(
+            Role::Client,
+            &params,
+            ctrl_frames.clone(),
+            ArcRtt::default(),
+        );
+        let mut writer = open_uni(&streams);
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        // 流的数量达到上限
+        assert!(streams.poll_open_uni_stream(&mut cx, 1000).is_pending());
+        // 写满流控窗口之后被阻塞
+        writer.write_all(&[0; 1000]).await.unwrap();
+        for _ in 0..2 {
+            let write = tokio::time::timeout(Duration::from_millis(10), writer.write(&[0; 1]));
+            assert!(write.await.is_err());
+        }
+
+        let mut frames = vec![];
+        while let Poll::Ready(Some(frame)) = ctrl_frames.poll_pop(&mut cx) {
+            frames.push(frame);
+        }
+        // 每个上限只通知一次
+        assert_eq!(frames.len(), 2);
+        assert!(matches!(
+            frames[0],
+            StreamCtlFrame::StreamsBlocked(StreamsBlockedFrame::Uni(max)) if max.id() == 0
+        ));
+        assert_eq!(
+            frames[1],
+            StreamCtlFrame::StreamDataBlocked(StreamDataBlockedFrame {
+                stream_id: writer.stream_id(),
+                maximum_stream_data: VarInt::from_u32(1000),
+            })
+        );
+
+        // 对方创建的单向流被本地的接收窗口阻塞
+        let stream_data_blocked = StreamDataBlockedFrame {
+            stream_id: StreamId::from(VarInt::from_u32(3)),
+            maximum_stream_data: VarInt::from_u32(1_250_000),
+        };
+        streams
+            .recv_stream_control(&StreamCtlFrame::StreamDataBlocked(stream_data_blocked))
+            .unwrap();
+        // 本地创建的单向流不可能被对方阻塞
+        let stream_data_blocked = StreamDataBlockedFrame {
+            stream_id: writer.stream_id(),
+            ..stream_data_blocked
+        };
+        assert!(streams
+            .recv_stream_control(&StreamCtlFrame::StreamDataBlocked(stream_data_blocked))
+            .is_err());
+        assert_eq!(streams.blocked_stats().stream_data_blocked, 1);
+        writer.cancel(0);
+    }
+
+    #[tokio::test]
+    async fn test_replenish_max_streams() {
+        let ctrl_frames = ArcAsyncDeque::<StreamCtlFrame>::new();
+        let mut params = Parameters::default();
+        params.set_initial_max_streams_bidi(VarInt::from_u32(1));
+        params.set_initial_max_streams_uni(VarInt::from_u32(1));
+        let streams = RawDataStreams::new(
+            Role::Client,
+            &params,
+            ctrl_frames.clone(),
+            ArcRtt::default(),
+        );
+        let max_streams = || {
+            let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+            let mut frames = vec![];
+            while let Poll::Ready(Some(frame)) = ctrl_frames.poll_pop(&mut cx) {
+                if let StreamCtlFrame::MaxStreams(frame) = frame {
+                    frames.push(frame);
+                }
+            }
+            frames
+        };
+        let recv_all = |sid: u32| {
+            let mut frame = StreamFrame::new(StreamId::from(VarInt::from_u32(sid)), 0, 10);
+            frame.set_eos_flag(true);
+            streams.recv_data(&(frame, bytes::Bytes::from_static(&[0; 10])))
+        };
+
+        // 对方的单向流收完所有数据，即已关闭
+        recv_all(3).unwrap();
+        assert_eq!(max_streams(), [MaxStreamsFrame::Uni(VarInt::from_u32(2))]);
+        assert_eq!(recv_all(3).unwrap(), 0);
+        assert!(max_streams().is_empty());
+
+        // 对方的双向流，要等本地发送的部分也结束
+        recv_all(1).unwrap();
+        let (_reader, writer) = streams.accept_bi(0).await.unwrap();
+        assert!(max_streams().is_empty());
+        writer.cancel(0);
+        // 等待RESET_STREAM帧发出
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        streams.on_reset_acked(ResetStreamFrame {
+            stream_id: StreamId::from(VarInt::from_u32(1)),
+            app_error_code: VarInt::from_u32(0),
+            final_size: VarInt::from_u32(0),
+        });
+        assert_eq!(max_streams(), [MaxStreamsFrame::Bi(VarInt::from_u32(2))]);
+
+        // 按批次补充，且不超过整个连接期间的上限
+        streams.set_max_streams_policy(MaxStreamsPolicy {
+            batch: 2,
+            lifetime_limit: Some(4),
+        });
+        recv_all(7).unwrap();
+        assert!(max_streams().is_empty());
+        recv_all(11).unwrap();
+        assert_eq!(max_streams(), [MaxStreamsFrame::Uni(VarInt::from_u32(4))]);
+        recv_all(15).unwrap();
+        assert!(max_streams().is_empty());
+        assert!(recv_all(23).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_stats() {
+        let streams = RawDataStreams::new(
+            Role::Client,
+            &Parameters::default(),
+            ArcAsyncDeque::<StreamCtlFrame>::new(),
+            ArcRtt::default(),
+        );
+        streams.premit_max_sid(Dir::Uni, 10);
+        let mut writer = open_uni(&streams);
+        let sid = writer.stream_id();
+        writer.write_all(&[0; 100]).await.unwrap();
+        let send_stats = || streams.stream_stats(sid).unwrap().send.unwrap();
+        assert_eq!(send_stats().state, SendState::Ready);
+        assert_eq!(send_stats().written, 100);
+        assert_eq!(send_stats().max_stream_data, 1000);
+
+        let mut buf = [0u8; 1000];
+        let (frame, ..) = streams.try_read_data(&mut buf, 1000).unwrap();
+        assert_eq!(send_stats().state, SendState::Send);
+        assert_eq!(send_stats().sent, 100);
+        streams.may_loss_data(&frame);
+        let (frame, ..) = streams.try_read_data(&mut buf, 1000).unwrap();
+        assert_eq!(send_stats().retransmitted, 100);
+        streams.on_data_acked(frame);
+        assert_eq!(send_stats().acked, 100);
+        assert!(streams.stream_stats(sid).unwrap().recv.is_none());
+
+        // 对方的单向流，收到乱序的数据
+        let sid = StreamId::from(VarInt::from_u32(3));
+        let frame = StreamFrame::new(sid, 5, 10);
+        streams
+            .recv_data(&(frame, bytes::Bytes::from_static(&[0; 10])))
+            .unwrap();
+        let stats = streams.stream_stats(sid).unwrap();
+        assert!(stats.send.is_none());
+        let recv_stats = stats.recv.unwrap();
+        assert_eq!(recv_stats.state, RecvState::Recv);
+        assert_eq!(recv_stats.largest_offset, 15);
+        assert_eq!(recv_stats.received, 0);
+        assert_eq!(recv_stats.final_size, None);
+
+        writer.cancel(0);
+        assert_eq!(send_stats().reset, Some(0));
+        assert!(streams
+            .stream_stats(StreamId::from(VarInt::from_u32(7)))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_accept_bi_with_peek() {
+        let streams = RawDataStreams::new(
+            Role::Client,
+            &Parameters::default(),
+            ArcAsyncDeque::<StreamCtlFrame>::new(),
+            ArcRtt::default(),
+        );
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let recv = |id: u32, offset: u64, data: &'static [u8]| {
+            let sid = StreamId::from(VarInt::from_u32(id));
+            let frame = StreamFrame::new(sid, offset, data.len());
+            streams
+                .recv_data(&(frame, bytes::Bytes::from_static(data)))
+                .unwrap();
+        };
+        let mut accept = || streams.accept_bi_with_peek(1000, 4).poll_unpin(&mut cx);
+
+        // 对方打开了流1和流5，但流1还没有数据，流5的数据不连续
+        recv(5, 2, b"ll");
+        assert!(accept().is_pending());
+
+        recv(5, 0, b"he");
+        let Poll::Ready(Ok((peek, mut reader5, writer5))) = accept() else {
+            panic!("the stream 5 should be accepted")
+        };
+        assert_eq!(reader5.stream_id(), StreamId::from(VarInt::from_u32(5)));
+        assert_eq!(peek, bytes::Bytes::from_static(b"hell"));
+        assert!(accept().is_pending());
+
+        // peek不消费数据
+        let mut buf = [0u8; 8];
+        assert_eq!(reader5.read(&mut buf).await.unwrap(), 4);
+        assert_eq!(&buf[..4], b"hell");
+
+        recv(1, 0, b"hi");
+        let Poll::Ready(Ok((peek, reader1, writer1))) = accept() else {
+            panic!("the stream 1 should be accepted")
+        };
+        assert_eq!(peek, bytes::Bytes::from_static(b"hi"));
+
+        reader1.stop(0);
+        reader5.stop(0);
+        writer1.cancel(0);
+        writer5.cancel(0);
+    }
+}
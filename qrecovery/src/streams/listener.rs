@@ -6,6 +6,7 @@ use std::{
     task::{Context, Poll, Waker},
 };
 
+use bytes::Bytes;
 use qbase::error::Error as QuicError;
 
 use crate::{
@@ -52,6 +53,31 @@ impl RawListener {
         }
     }
 
+    fn poll_accept_bi_stream_with_peek(
+        &mut self,
+        cx: &mut Context<'_>,
+        send_wnd_size: u64,
+        peek_len: usize,
+    ) -> Poll<Result<(Bytes, Reader, Writer), QuicError>> {
+        // 没有数据到达的流留在队列中，每个流到达数据时都会唤醒
+        let arrived = self
+            .bi_streams
+            .iter()
+            .position(|(recver, _)| recver.poll_arrived(cx).is_ready());
+        match arrived.and_then(|index| self.bi_streams.remove(index)) {
+            Some((recver, sender)) => {
+                let outgoing = Outgoing(sender);
+                outgoing.update_window(send_wnd_size);
+                let reader = Reader(recver);
+                Poll::Ready(Ok((reader.peek(peek_len), reader, Writer(outgoing.0))))
+            }
+            None => {
+                self.bi_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
     fn poll_accept_recv_stream(&mut self, cx: &mut Context<'_>) -> Poll<Result<Reader, QuicError>> {
         if let Some(reader) = self.uni_streams.pop_front() {
             Poll::Ready(Ok(Reader(reader)))
@@ -87,6 +113,18 @@ impl ArcListener {
         }
     }
 
+    pub fn accept_bi_stream_with_peek(
+        &self,
+        send_wnd_size: u64,
+        peek_len: usize,
+    ) -> AcceptBiStreamWithPeek<'_> {
+        AcceptBiStreamWithPeek {
+            inner: self,
+            send_wnd_size,
+            peek_len,
+        }
+    }
+
     pub fn accept_uni_stream(&self) -> AcceptUniStream {
         AcceptUniStream { inner: self }
     }
@@ -102,6 +140,18 @@ impl ArcListener {
         }
     }
 
+    pub fn poll_accept_bi_stream_with_peek(
+        &self,
+        cx: &mut Context<'_>,
+        send_wnd_size: u64,
+        peek_len: usize,
+    ) -> Poll<Result<(Bytes, Reader, Writer), QuicError>> {
+        match self.0.lock().unwrap().as_mut() {
+            Ok(set) => set.poll_accept_bi_stream_with_peek(cx, send_wnd_size, peek_len),
+            Err(e) => Poll::Ready(Err(e.clone())),
+        }
+    }
+
     pub fn poll_accept_uni_stream(&self, cx: &mut Context<'_>) -> Poll<Result<Reader, QuicError>> {
         match self.0.lock().unwrap().as_mut() {
             Ok(set) => set.poll_accept_recv_stream(cx),
@@ -165,6 +215,33 @@ impl Future for AcceptBiStream<'_> {
     }
 }
 
+/// Future to accept a bidirectional stream once the peer has sent some data on it.
+///
+/// This future is created by `accept_bi_stream_with_peek` method of `QuicConnection`.
+///
+/// The streams the peer created but sent nothing on yet are not yielded, so the ones opened
+/// speculatively don't occupy a task each. The future resolves with up to `peek_len` bytes of the
+/// data received, which are not consumed from the [`Reader`], to dispatch the stream by its
+/// preamble. The peek is shorter if less data has arrived, and is empty if the stream has been
+/// finished or reset without data.
+///
+/// The streams are yielded in the order the data arrives, rather than the order they were created.
+#[derive(Debug, Clone)]
+pub struct AcceptBiStreamWithPeek<'l> {
+    inner: &'l ArcListener,
+    send_wnd_size: u64,
+    peek_len: usize,
+}
+
+impl Future for AcceptBiStreamWithPeek<'_> {
+    type Output = Result<(Bytes, Reader, Writer), QuicError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.inner
+            .poll_accept_bi_stream_with_peek(cx, self.send_wnd_size, self.peek_len)
+    }
+}
+
 /// Future to accept a bidirectional stream.
 ///
 /// This future is created by `accept_uni_stream` method of `QuicConnection`.
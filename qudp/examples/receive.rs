@@ -24,7 +24,7 @@ async fn main() {
             Ok(n) => {
                 log::info!(
                     "received {} packets, dst {}, src {}",
-                    n,
+                    receiver.datagrams(n).count(),
                     receiver.headers[0].dst,
                     receiver.headers[0].src
                 );
@@ -39,6 +39,7 @@ async fn main() {
         ttl: 64,
         ecn: Some(1),
         seg_size: args.msg_size as u16,
+        stride: 0,
         gso: args.gso,
     };
 
@@ -4,7 +4,10 @@ use std::{
     io::{self, IoSlice, IoSliceMut},
     net::SocketAddr,
     pin::Pin,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     task::{ready, Context, Poll},
 };
 
@@ -33,17 +36,27 @@ cfg_if::cfg_if! {
 
 mod cmsghdr;
 const BUFFER_CAPACITY: usize = 5;
+/// The size of each buffer to receive a datagram, the payload of a larger datagram is truncated.
+pub const RECV_BUFFER_SIZE: usize = 1500;
+/// The number of the buffers to receive the datagrams coalesced by GRO, each of which holds up to
+/// 64KiB, the datagrams of the same `RECV_BUFFER_SIZE` at most.
+const GRO_BATCH_SIZE: usize = 8;
 
 #[derive(Clone, Copy, Debug)]
 pub struct PacketHeader {
     pub src: SocketAddr,
     pub dst: SocketAddr,
     pub ttl: u8,
-    // Explicit congestion notification (ECN)
+    // Explicit congestion notification (ECN), the codepoint in the lowest 2 bits of the TOS or
+    // Traffic Class byte, whose upper 6 bits are the DSCP of the socket
     pub ecn: Option<u8>,
     // packet segment size
     pub seg_size: u16,
-    // use gso
+    // The size of each datagram coalesced by GRO in the received buffer, the last one may be
+    // shorter, 0 if the datagrams are not coalesced
+    pub stride: u16,
+    // Whether to send the datagrams of the same size by GSO, the datagrams are split into the
+    // batches of the same size by qudp, and sent one by one where GSO is unsupported
     pub gso: bool,
 }
 
@@ -56,10 +69,30 @@ impl Default for PacketHeader {
             ecn: None,
             gso: false,
             seg_size: 0,
+            stride: 0,
         }
     }
 }
 
+/// Converts the IPv4-mapped IPv6 address received by a dual-stack socket to the IPv4 one, so the
+/// peer has the same address whichever socket it's received by.
+pub(crate) fn canonical(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+impl PacketHeader {
+    /// Splits the buffer received with this header into the datagrams by the
+    /// [`PacketHeader::stride`], the buffer is truncated to the [`PacketHeader::seg_size`] first.
+    pub fn datagrams<'b>(&self, buf: &'b [u8]) -> impl Iterator<Item = &'b [u8]> {
+        let buf = &buf[..(self.seg_size as usize).min(buf.len())];
+        let stride = match self.stride {
+            0 => buf.len().max(1),
+            stride => stride as usize,
+        };
+        buf.chunks(stride)
+    }
+}
+
 // [`OffloadStatus`] is an enumeration that represents the status of offload features
 #[derive(PartialEq, Eq, Debug, Default)]
 #[allow(dead_code)]
@@ -75,8 +108,15 @@ enum OffloadStatus {
 struct UdpSocketController {
     io: tokio::net::UdpSocket,
     ttl: u8,
+    // The DSCP marked in the TOS or Traffic Class byte of the datagrams sent, 0 by default
+    dscp: u8,
     gso_size: OffloadStatus,
+    // Set once the kernel refused to send by GSO, such as the NIC doesn't support the checksum
+    // offload, then the datagrams are sent without GSO
+    gso_disabled: AtomicBool,
     gro_size: OffloadStatus,
+    // The most messages sent or received in a syscall, in 1..=BATCH_SIZE
+    batch_size: usize,
     bufs: VecDeque<(Vec<u8>, PacketHeader)>,
 }
 
@@ -89,19 +129,31 @@ impl UdpSocketController {
         };
 
         let socket = Socket::new(domain, Type::DGRAM, None).expect("Failed to create socket");
+        // The IPv6 socket is dual-stack, which sends and receives the IPv4 datagrams too by the
+        // IPv4-mapped addresses. It must be set before binding, and is off by default on Windows
+        if addr.is_ipv6() {
+            if let Err(e) = socket.set_only_v6(false) {
+                log::warn!("Failed to make the socket dual-stack: {}", e);
+            }
+        }
         if let Err(e) = socket.bind(&addr.into()) {
             log::error!("Failed to bind socket: {}", e);
             return Err(io::Error::new(io::ErrorKind::AddrInUse, e));
         }
+        // tokio requires the socket registered to be non-blocking
+        socket.set_nonblocking(true)?;
 
         let io =
             tokio::net::UdpSocket::from_std(socket.into()).expect("Failed to create tokio socket");
 
         let mut socket = Self {
             ttl: DEFAULT_TTL as u8,
+            dscp: 0,
             io,
             gso_size: OffloadStatus::Unknown,
+            gso_disabled: AtomicBool::new(false),
             gro_size: OffloadStatus::Unknown,
+            batch_size: BATCH_SIZE,
             bufs: VecDeque::with_capacity(BUFFER_CAPACITY),
         };
         socket.config().expect("Failed to config socket");
@@ -123,6 +175,8 @@ trait Io {
     fn setsockopt(&self, level: libc::c_int, name: libc::c_int, value: libc::c_int);
 
     fn set_ttl(&mut self, ttl: u8) -> io::Result<()>;
+
+    fn set_dscp(&mut self, dscp: u8) -> io::Result<()>;
 }
 
 #[derive(Debug, Clone)]
@@ -183,6 +237,53 @@ impl ArcUsc {
         self.0.lock().unwrap().local_addr()
     }
 
+    /// Returns the DSCP marked in the datagrams sent, 0 by default.
+    pub fn dscp(&self) -> u8 {
+        self.0.lock().unwrap().dscp
+    }
+
+    /// Sets the DSCP, the upper 6 bits of the IPv4 TOS or the IPv6 Traffic Class byte, marked in
+    /// the datagrams sent for the QoS domains. The lowest 2 bits are still the ECN codepoint of
+    /// each datagram, see [`PacketHeader::ecn`]. Returns [`io::ErrorKind::InvalidInput`] if the
+    /// `dscp` is not less than 64.
+    ///
+    /// On Windows, the DSCP is set by the socket option, which the system may ignore without the
+    /// QoS policy.
+    pub fn set_dscp(&self, dscp: u8) -> io::Result<()> {
+        if dscp >= 64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "DSCP must be less than 64",
+            ));
+        }
+        self.0.lock().unwrap().set_dscp(dscp)
+    }
+
+    /// Returns the most messages sent by a sendmmsg or received by a recvmmsg call,
+    /// [`BATCH_SIZE`] by default.
+    pub fn batch_size(&self) -> usize {
+        self.0.lock().unwrap().batch_size
+    }
+
+    /// Sets the most messages sent or received in a syscall, clamped to `1..=BATCH_SIZE`. It
+    /// takes effect on the following calls, the [`Receiver`]s created before keep their buffers.
+    pub fn set_batch_size(&self, batch_size: usize) {
+        self.0.lock().unwrap().batch_size = batch_size.clamp(1, BATCH_SIZE);
+    }
+
+    /// Returns the most datagrams sent in a syscall, the messages of a batch each carry the
+    /// datagrams sent by GSO at once if it's supported.
+    pub fn send_batch_size(&self) -> usize {
+        let controller = self.0.lock().unwrap();
+        let segments = match controller.gso_size {
+            OffloadStatus::Supported(n) if !controller.gso_disabled.load(Ordering::Relaxed) => {
+                n as usize
+            }
+            _ => 1,
+        };
+        controller.batch_size * segments.max(1)
+    }
+
     // Send synchronously, usc saves a small amount of data packets,and USC sends internal asynchronous tasks
     pub fn sync_send(&self, packet: Vec<u8>, hdr: &PacketHeader) -> io::Result<()> {
         let mut guard = self.0.lock().unwrap();
@@ -210,13 +311,27 @@ impl ArcUsc {
         }
     }
 
+    /// Returns the number and the size of the buffers to receive the datagrams in a syscall.
+    pub fn recv_buffers(&self) -> (usize, usize) {
+        // 启用GRO时，内核将多个数据报合并到一个缓冲区中，缓冲区须足以容纳合并后的数据报
+        let controller = self.0.lock().unwrap();
+        match controller.gro_size {
+            OffloadStatus::Supported(n) => (
+                GRO_BATCH_SIZE.min(controller.batch_size),
+                (RECV_BUFFER_SIZE * n as usize).min(u16::MAX as usize),
+            ),
+            _ => (controller.batch_size, RECV_BUFFER_SIZE),
+        }
+    }
+
     pub fn receiver(&self) -> Receiver {
+        let (batch_size, buffer_size) = self.recv_buffers();
         Receiver {
             usc: self.clone(),
-            iovecs: (0..BATCH_SIZE)
-                .map(|_| [0u8; 1500].to_vec())
+            iovecs: (0..batch_size)
+                .map(|_| vec![0u8; buffer_size])
                 .collect::<Vec<_>>(),
-            headers: (0..BATCH_SIZE)
+            headers: (0..batch_size)
                 .map(|_| PacketHeader::default())
                 .collect::<Vec<_>>(),
         }
@@ -284,4 +399,151 @@ impl Receiver {
     pub async fn recv(&mut self) -> io::Result<usize> {
         core::future::poll_fn(|cx| self.poll_recv(cx)).await
     }
+
+    /// Returns the datagrams in the first `msg_count` buffers received, with the headers of the
+    /// buffers they are in. The buffers coalesced by GRO are split into the datagrams by the
+    /// [`PacketHeader::stride`].
+    pub fn datagrams(&self, msg_count: usize) -> impl Iterator<Item = (&PacketHeader, &[u8])> {
+        core::iter::zip(&self.headers, &self.iovecs)
+            .take(msg_count)
+            .flat_map(|(hdr, buf)| hdr.datagrams(buf).map(move |datagram| (hdr, datagram)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_and_recv_batch() {
+        let sender = ArcUsc::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let receiver_usc = ArcUsc::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut receiver = receiver_usc.receiver();
+
+        // 等长的数据报以GSO发送，最后一个更短
+        let payloads = (0..10u8)
+            .map(|i| vec![i; if i == 9 { 300 } else { 1200 }])
+            .collect::<Vec<_>>();
+        let bufs = payloads.iter().map(|p| IoSlice::new(p)).collect::<Vec<_>>();
+        let hdr = PacketHeader {
+            src: sender.local_addr(),
+            dst: receiver_usc.local_addr(),
+            seg_size: 1200,
+            gso: true,
+            ..Default::default()
+        };
+        assert_eq!(sender.send(&bufs, hdr).await.unwrap(), payloads.len());
+
+        // GRO合并的数据报被逐个拆分
+        let mut received = vec![];
+        while received.len() < payloads.len() {
+            let n = receiver.recv().await.unwrap();
+            received.extend(receiver.datagrams(n).map(|(_, datagram)| datagram.to_vec()));
+        }
+        assert_eq!(received, payloads);
+    }
+
+    #[tokio::test]
+    async fn test_batch_size() {
+        let sender = ArcUsc::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let receiver_usc = ArcUsc::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert_eq!(sender.batch_size(), BATCH_SIZE);
+        sender.set_batch_size(0);
+        assert_eq!(sender.batch_size(), 1);
+        sender.set_batch_size(BATCH_SIZE + 1);
+        assert_eq!(sender.batch_size(), BATCH_SIZE);
+
+        // 每次系统调用至多收发一个消息，数据报仍全部发出
+        sender.set_batch_size(1);
+        receiver_usc.set_batch_size(1);
+        let mut receiver = receiver_usc.receiver();
+        assert_eq!(receiver.iovecs.len(), 1);
+
+        let payloads = (0..4u8)
+            .map(|i| vec![i; 100 + i as usize])
+            .collect::<Vec<_>>();
+        let bufs = payloads.iter().map(|p| IoSlice::new(p)).collect::<Vec<_>>();
+        let hdr = PacketHeader {
+            src: sender.local_addr(),
+            dst: receiver_usc.local_addr(),
+            ..Default::default()
+        };
+        let mut sent = 0;
+        while sent < bufs.len() {
+            sent += sender.send(&bufs[sent..], hdr).await.unwrap();
+        }
+
+        let mut received = vec![];
+        while received.len() < payloads.len() {
+            let n = receiver.recv().await.unwrap();
+            assert!(n <= 1);
+            received.extend(receiver.datagrams(n).map(|(_, datagram)| datagram.to_vec()));
+        }
+        assert_eq!(received, payloads);
+    }
+
+    #[tokio::test]
+    async fn test_dual_stack() {
+        let Ok(dual_stack) = ArcUsc::new("[::]:0".parse().unwrap()) else {
+            // 不支持IPv6的环境
+            return;
+        };
+        let v4 = ArcUsc::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let dual_stack_addr: SocketAddr = ([127, 0, 0, 1], dual_stack.local_addr().port()).into();
+
+        let hdr = PacketHeader {
+            dst: dual_stack_addr,
+            ..Default::default()
+        };
+        v4.send(&[IoSlice::new(b"ping")], hdr).await.unwrap();
+        let mut receiver = dual_stack.receiver();
+        let n = receiver.recv().await.unwrap();
+        // IPv4映射的地址被统一为IPv4地址
+        let (hdr, datagram) = receiver.datagrams(n).next().unwrap();
+        assert_eq!(datagram, b"ping");
+        assert_eq!(hdr.src, v4.local_addr());
+        assert_eq!(hdr.dst, dual_stack_addr);
+
+        // 双栈套接字直接向IPv4地址回复
+        let reply = PacketHeader {
+            dst: hdr.src,
+            ..Default::default()
+        };
+        dual_stack
+            .send(&[IoSlice::new(b"pong")], reply)
+            .await
+            .unwrap();
+        let mut receiver = v4.receiver();
+        let n = receiver.recv().await.unwrap();
+        let (hdr, datagram) = receiver.datagrams(n).next().unwrap();
+        assert_eq!(datagram, b"pong");
+        assert_eq!(hdr.src, dual_stack_addr);
+    }
+
+    #[tokio::test]
+    async fn test_dscp_and_ecn() {
+        let sender = ArcUsc::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let receiver_usc = ArcUsc::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut receiver = receiver_usc.receiver();
+        assert!(sender.set_dscp(64).is_err());
+        // AF41
+        sender.set_dscp(34).unwrap();
+        assert_eq!(sender.dscp(), 34);
+
+        // 每个数据报携带各自的ECN，接收时只报告ECN，不含DSCP
+        for ecn in [0b10, 0b01, 0b11] {
+            let payload = [ecn; 100];
+            let hdr = PacketHeader {
+                src: sender.local_addr(),
+                dst: receiver_usc.local_addr(),
+                ecn: Some(ecn),
+                ..Default::default()
+            };
+            sender.send(&[IoSlice::new(&payload)], hdr).await.unwrap();
+            let n = receiver.recv().await.unwrap();
+            let (hdr, datagram) = receiver.datagrams(n).next().unwrap();
+            assert_eq!(datagram, payload);
+            assert_eq!(hdr.ecn, Some(ecn));
+        }
+    }
 }
@@ -7,12 +7,14 @@ use std::{
 use socket2::SockAddr;
 
 use crate::{
+    canonical,
     cmsghdr::{decode, Cmsg, CmsgHdr, Iter, MsgHdr},
     uinx::Gso,
     PacketHeader, UdpSocketController, BATCH_SIZE,
 };
 
-pub(crate) const CMSG_LEN: usize = 88;
+// Enough for all the control messages received, including the segment size of UDP_GRO
+pub(crate) const CMSG_LEN: usize = 128;
 
 #[cfg(target_os = "freebsd")]
 type IpTosTy = libc::c_uchar;
@@ -59,14 +61,15 @@ impl Default for Message {
 }
 
 impl Message {
+    /// Prepare the headers of the messages to send, the message `i` is sent with GSO if
+    /// `segment_sizes[i]` is not 0, see [`gso_batch_len`](crate::uinx::gso_batch_len).
     pub(super) fn prepare_sent(
         &mut self,
         pkt_hdr: &PacketHeader,
         dst: &SockAddr,
-        gso_size: u16,
-        msg_count: usize,
+        segment_sizes: &[u16],
     ) {
-        for (i, hdr) in self.hdrs.iter_mut().enumerate().take(msg_count) {
+        for (i, hdr) in self.hdrs.iter_mut().enumerate().take(segment_sizes.len()) {
             let hdr = msg_hdr!(hdr);
             hdr.msg_name = dst.as_ptr() as *mut _;
             hdr.msg_namelen = dst.len();
@@ -76,16 +79,17 @@ impl Message {
             hdr.msg_controllen = CMSG_LEN as _;
 
             let mut cmsghdr = unsafe { CmsgHdr::new(hdr) };
-            let ecn = pkt_hdr.ecn.unwrap_or(0) as libc::c_int;
+            // The whole TOS byte combined by the sender, the DSCP and the ECN codepoint
+            let tos = pkt_hdr.ecn.unwrap_or(0) as libc::c_int;
 
             if pkt_hdr.dst.is_ipv4() {
-                cmsghdr.append(libc::IPPROTO_IP, libc::IP_TOS, ecn as IpTosTy);
+                cmsghdr.append(libc::IPPROTO_IP, libc::IP_TOS, tos as IpTosTy);
             } else {
-                cmsghdr.append(libc::IPPROTO_IPV6, libc::IPV6_TCLASS, ecn);
+                cmsghdr.append(libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos);
             }
 
-            if gso_size > 1 {
-                UdpSocketController::set_segment_size(&mut cmsghdr, pkt_hdr.seg_size);
+            if segment_sizes[i] > 0 {
+                UdpSocketController::set_segment_size(&mut cmsghdr, segment_sizes[i]);
             }
             cmsghdr.finish();
         }
@@ -126,10 +130,12 @@ impl Message {
             let cmsg_iter = unsafe { Iter::new(hdr) };
 
             let recv_hdr = &mut recv_hdrs[i];
+            recv_hdr.stride = 0;
             for cmsg in cmsg_iter {
                 match (cmsg.cmsg_level, cmsg.cmsg_type) {
+                    // Only the ECN codepoint is reported, the DSCP is masked off
                     (libc::IPPROTO_IP, libc::IP_TOS) | (libc::IPPROTO_IP, libc::IP_RECVTOS) => unsafe {
-                        recv_hdr.ecn = Some(decode::<u8, libc::cmsghdr>(cmsg));
+                        recv_hdr.ecn = Some(decode::<u8, libc::cmsghdr>(cmsg) & 0b11);
                     },
                     (libc::IPPROTO_IPV6, libc::IPV6_TCLASS) => unsafe {
                         // Temporary hack around broken macos ABI. Remove once upstream fixes it.
@@ -139,9 +145,10 @@ impl Message {
                             && cmsg.cmsg_len as usize
                                 == libc::CMSG_LEN(mem::size_of::<u8>() as _) as usize
                         {
-                            recv_hdr.ecn = Some(decode::<u8, libc::cmsghdr>(cmsg));
+                            recv_hdr.ecn = Some(decode::<u8, libc::cmsghdr>(cmsg) & 0b11);
                         } else {
-                            recv_hdr.ecn = Some(decode::<libc::c_int, libc::cmsghdr>(cmsg) as u8);
+                            recv_hdr.ecn =
+                                Some(decode::<libc::c_int, libc::cmsghdr>(cmsg) as u8 & 0b11);
                         }
                     },
                     #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -169,6 +176,10 @@ impl Message {
                     (libc::IPPROTO_IP, libc::IP_RECVTTL) => unsafe {
                         recv_hdr.ttl = decode::<u32, libc::cmsghdr>(cmsg) as u8;
                     },
+                    #[cfg(target_os = "linux")]
+                    (libc::SOL_UDP, libc::UDP_GRO) => unsafe {
+                        recv_hdr.stride = decode::<libc::c_int, libc::cmsghdr>(cmsg) as u16;
+                    },
                     _ => {
                         log::warn!(
                             "read unkown level {} cmsg {}",
@@ -201,6 +212,9 @@ impl Message {
                 }
                 _ => unreachable!(),
             };
+            // 双栈套接字收到的IPv4数据报，地址是IPv4映射的IPv6地址，统一为IPv4地址
+            recv_hdr.src = canonical(recv_hdr.src);
+            recv_hdr.dst = canonical(recv_hdr.dst);
         }
     }
 }
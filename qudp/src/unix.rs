@@ -1,4 +1,4 @@
-use std::{cmp, io::IoSlice, mem, net::SocketAddr, os::fd::AsRawFd};
+use std::{cmp, io::IoSlice, mem, net::SocketAddr, os::fd::AsRawFd, sync::atomic::Ordering};
 
 use socket2::SockAddr;
 
@@ -8,7 +8,10 @@ use crate::{
 };
 
 const OPTION_ON: libc::c_int = 1;
-const OPTION_OFF: libc::c_int = 0;
+// The most segments sent by GSO at once, UDP_MAX_SEGMENTS of Linux
+const MAX_GSO_SEGMENTS: usize = 64;
+// The UDP payload of an IPv4 datagram, which the segments sent by GSO at once can't exceed
+const MAX_GSO_PAYLOAD: usize = u16::MAX as usize - 20 - 8;
 
 pub trait Gso: Io {
     fn max_gso_segments(&self) -> usize;
@@ -62,6 +65,16 @@ impl Io for UdpSocketController {
                 // recvmsg(2) call will return the destination IP address for a UDP	 datagram.
                 self.setsockopt(libc::IPPROTO_IP, libc::IP_RECVDSTADDR, OPTION_ON);
             }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            {
+                // Set the Don't Fragment flag, but ignore the path MTU cached by the kernel,
+                // so that the path MTU discovery of QUIC can send probe packets larger than it.
+                self.setsockopt(
+                    libc::IPPROTO_IP,
+                    libc::IP_MTU_DISCOVER,
+                    libc::IP_PMTUDISC_PROBE,
+                );
+            }
             self.setsockopt(libc::IPPROTO_IP, libc::IP_PKTINFO, OPTION_ON);
             self.setsockopt(libc::IPPROTO_IP, libc::IP_TTL, DEFAULT_TTL);
             // When this flag is set, pass a IP_TTL control message with
@@ -71,14 +84,27 @@ impl Io for UdpSocketController {
         }
         // Options standardized in RFC 3542
         else {
-            //  If this flag is set to false (zero), then the socket can
-            //  be used to send and receive packets to and from an IPv6
-            //  address or an IPv4-mapped IPv6 address.
-            self.setsockopt(libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, OPTION_OFF);
             // Set delivery of the IPV6_PKTINFO control message on incoming datagrams.
             self.setsockopt(libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, OPTION_ON);
             self.setsockopt(libc::IPPROTO_IPV6, libc::IPV6_RECVTCLASS, OPTION_ON);
             self.setsockopt(libc::IPPROTO_IPV6, libc::IPV6_DONTFRAG, OPTION_ON);
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            {
+                // Same as IPv4, the probe packets larger than the cached path MTU can be sent.
+                // The IPv4 datagrams sent by the dual-stack socket follow IP_MTU_DISCOVER
+                self.setsockopt(
+                    libc::IPPROTO_IPV6,
+                    libc::IPV6_MTU_DISCOVER,
+                    libc::IPV6_PMTUDISC_PROBE,
+                );
+                if !io.only_v6()? {
+                    self.setsockopt(
+                        libc::IPPROTO_IP,
+                        libc::IP_MTU_DISCOVER,
+                        libc::IP_PMTUDISC_PROBE,
+                    );
+                }
+            }
             self.setsockopt(libc::IPPROTO_IPV6, libc::IPV6_PKTINFO, OPTION_ON);
             // The received hop limit is returned as ancillary data by recvmsg()
             // only if the application has enabled the IPV6_RECVHOPLIMIT socket option
@@ -96,6 +122,19 @@ impl Io for UdpSocketController {
             1 => OffloadStatus::Unsupported,
             n => OffloadStatus::Supported(n as u16),
         };
+        // The datagrams received are coalesced into the buffers of the receiver, see [`ArcUsc::receiver`]
+        #[cfg(target_os = "linux")]
+        if self.gro_size != OffloadStatus::Unsupported
+            && setsockopt(
+                &self.io.as_raw_fd(),
+                libc::SOL_UDP,
+                libc::UDP_GRO,
+                OPTION_ON,
+            )
+            .is_err()
+        {
+            self.gro_size = OffloadStatus::Unsupported;
+        }
 
         Ok(())
     }
@@ -106,11 +145,14 @@ impl Io for UdpSocketController {
 
     fn sendmsg(&self, bufs: &[IoSlice<'_>], send_hdr: &PacketHeader) -> io::Result<usize> {
         let io = socket2::SockRef::from(&self.io);
+        // 控制消息中的TOS字节，高6位是套接字的DSCP，低2位是数据报的ECN
+        let send_hdr = &PacketHeader {
+            ecn: Some(self.dscp << 2 | send_hdr.ecn.unwrap_or(0) & 0b11),
+            ..*send_hdr
+        };
 
         let gso_size = if send_hdr.gso {
-            let max_gso = self.max_gso_segments();
-            let max_payloads = (u16::MAX / send_hdr.seg_size) as usize;
-            cmp::min(max_gso, max_payloads)
+            cmp::min(self.max_gso_segments(), MAX_GSO_SEGMENTS)
         } else {
             1
         };
@@ -129,7 +171,18 @@ impl Io for UdpSocketController {
         };
 
         #[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "openbsd",)))]
-        return sendmmsg(&self.io, bufs, send_hdr, &dst, gso_size);
+        return match sendmmsg(&self.io, bufs, send_hdr, &dst, gso_size, self.batch_size) {
+            // The kernel refuses GSO with EIO if the NIC doesn't support the checksum offload,
+            // fall back to send the datagrams one by one
+            Err(e)
+                if gso_size > 1 && matches!(e.raw_os_error(), Some(libc::EIO | libc::EINVAL)) =>
+            {
+                log::warn!("GSO is disabled for the sending failed: {}", e);
+                self.gso_disabled.store(true, Ordering::Relaxed);
+                sendmmsg(&self.io, bufs, send_hdr, &dst, 1, self.batch_size)
+            }
+            result => result,
+        };
 
         #[cfg(any(target_os = "macos", target_os = "ios", target_os = "openbsd",))]
         return sendmsg(&self.io, bufs, send_hdr, &dst, gso_size);
@@ -141,7 +194,7 @@ impl Io for UdpSocketController {
         recv_hdrs: &mut [PacketHeader],
     ) -> io::Result<usize> {
         let mut msg = Message::default();
-        let max_msg_count = bufs.len().min(BATCH_SIZE);
+        let max_msg_count = bufs.len().min(self.batch_size);
 
         msg.prepare_recv(bufs, max_msg_count);
         let ret: io::Result<Rcvd>;
@@ -190,8 +243,46 @@ impl Io for UdpSocketController {
         self.ttl = ttl;
         Ok(())
     }
+
+    fn set_dscp(&mut self, dscp: u8) -> io::Result<()> {
+        // 每个数据报的TOS随控制消息发送，这里只是设置未携带控制消息时的默认值
+        let io = socket2::SockRef::from(&self.io);
+        let tos = (dscp << 2) as libc::c_int;
+        if self.local_addr().is_ipv4() || !io.only_v6()? {
+            setsockopt(&self.io, libc::IPPROTO_IP, libc::IP_TOS, tos)?;
+        }
+        if self.local_addr().is_ipv6() {
+            setsockopt(&self.io, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos)?;
+        }
+        self.dscp = dscp;
+        Ok(())
+    }
+}
+
+/// Returns the number of the datagrams at the front of `bufs` sent by GSO as one message, which
+/// are of the same size but the last one may be shorter, up to `max_segments`.
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "openbsd",)))]
+pub(super) fn gso_batch_len(bufs: &[IoSlice<'_>], max_segments: usize) -> usize {
+    let Some(first) = bufs.first() else {
+        return 0;
+    };
+    let seg_size = first.len().max(1);
+    let max_segments = max_segments
+        .min(MAX_GSO_PAYLOAD / seg_size)
+        .min(bufs.len())
+        .max(1);
+    let mut n = 1;
+    while n < max_segments && bufs[n].len() <= seg_size {
+        n += 1;
+        if bufs[n - 1].len() < seg_size {
+            break;
+        }
+    }
+    n
 }
 
+/// Send the datagrams by sendmmsg, up to `batch_size` messages in a call, no more than
+/// [`BATCH_SIZE`], each of which carries up to `gso_size` datagrams by GSO. Returns the number of the datagrams sent.
 #[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "openbsd",)))]
 pub(super) fn sendmmsg(
     io: &impl AsRawFd,
@@ -199,31 +290,45 @@ pub(super) fn sendmmsg(
     send_hdr: &PacketHeader,
     dst: &SockAddr,
     gso_size: usize,
+    batch_size: usize,
 ) -> io::Result<usize> {
     use std::iter;
+    let batch_size = batch_size.clamp(1, BATCH_SIZE);
     let mut iovecs: Vec<Vec<IoSlice>> = iter::repeat_with(|| Vec::with_capacity(gso_size))
         .take(BATCH_SIZE)
         .collect();
-
     let mut message = Message::default();
-    message.prepare_sent(send_hdr, dst, gso_size as u16, BATCH_SIZE);
 
     let mut sent_packets = 0;
-    for batch in bufs.chunks(gso_size * BATCH_SIZE) {
-        let mut mmsg_batch_size: usize = 0;
-        for (i, gso_batch) in batch.chunks(gso_size).enumerate() {
-            mmsg_batch_size += 1;
-            let hdr = &mut message.hdrs[i].msg_hdr;
-            let iovec = &mut iovecs[i];
+    while sent_packets < bufs.len() {
+        // 将数据报分成若干消息，每个消息中的数据报一同以GSO发送
+        let mut segments = [0usize; BATCH_SIZE];
+        let mut segment_sizes = [0u16; BATCH_SIZE];
+        let mut msg_count = 0;
+        let mut offset = sent_packets;
+        while msg_count < batch_size && offset < bufs.len() {
+            let n = gso_batch_len(&bufs[offset..], gso_size);
+            let iovec = &mut iovecs[msg_count];
             iovec.clear();
-            iovec.extend(gso_batch.iter().map(|payload| IoSlice::new(payload)));
-            hdr.msg_iov = iovec.as_ptr() as *mut _;
-            hdr.msg_iovlen = iovec.len() as _;
+            iovec.extend(bufs[offset..offset + n].iter().map(|buf| IoSlice::new(buf)));
+            segments[msg_count] = n;
+            if n > 1 {
+                segment_sizes[msg_count] = bufs[offset].len() as u16;
+            }
+            offset += n;
+            msg_count += 1;
         }
 
-        let mut msgvec = message.hdrs.as_mut_ptr();
-        let mut vlen = mmsg_batch_size as u32;
-        loop {
+        message.prepare_sent(send_hdr, dst, &segment_sizes[..msg_count]);
+        for (hdr, iovec) in message.hdrs.iter_mut().zip(&iovecs).take(msg_count) {
+            hdr.msg_hdr.msg_iov = iovec.as_ptr() as *mut _;
+            hdr.msg_hdr.msg_iovlen = iovec.len() as _;
+        }
+
+        let mut msgs_sent = 0;
+        while msgs_sent < msg_count {
+            let msgvec = message.hdrs[msgs_sent..].as_mut_ptr();
+            let vlen = (msg_count - msgs_sent) as u32;
             let ret =
                 to_result(unsafe { libc::sendmmsg(io.as_raw_fd(), msgvec, vlen, 0) } as isize);
 
@@ -232,21 +337,27 @@ pub(super) fn sendmmsg(
                 // msgvec; if this is less than vlen, the caller can retry with a
                 // further sendmmsg() call to send the remaining messages.
                 Ok(n) => {
-                    sent_packets += n;
-                    if n != vlen as usize {
-                        log::warn!("sendmmsg : only {} messages sent out of {}", n, vlen);
-                        vlen = n as u32 - vlen;
-                        msgvec = message.hdrs[n..].as_mut_ptr();
-                        continue;
-                    }
-                    break;
+                    sent_packets += segments[msgs_sent..msgs_sent + n].iter().sum::<usize>();
+                    msgs_sent += n;
                 }
                 Err(e) => match e.raw_os_error() {
                     Some(libc::EINTR) => continue,
                     Some(libc::EWOULDBLOCK) if sent_packets > 0 => return Ok(sent_packets),
                     Some(libc::EWOULDBLOCK) if sent_packets == 0 => return Err(e),
                     Some(libc::EBADE) | Some(libc::EPIPE) | Some(libc::ENOTCONN) => return Err(e),
-                    _ => break,
+                    // The message sent by GSO is refused, leave it to the caller to fall back
+                    Some(libc::EIO | libc::EINVAL) if segments[msgs_sent] > 1 => {
+                        return match sent_packets {
+                            0 => Err(e),
+                            n => Ok(n),
+                        }
+                    }
+                    // The datagrams of the message failed to send are dropped, like lost
+                    _ => {
+                        log::warn!("sendmmsg: failed to send a message: {}", e);
+                        sent_packets += segments[msgs_sent];
+                        msgs_sent += 1;
+                    }
                 },
             }
         }
@@ -263,7 +374,7 @@ pub(super) fn sendmsg(
     gso_size: usize,
 ) -> io::Result<usize> {
     let mut msg = Message::default();
-    msg.prepare_sent(send_hdr, dst, gso_size as u16, 1);
+    msg.prepare_sent(send_hdr, dst, &[0]);
 
     let mut sent_packets = 0;
     for batch in bufs.chunks(gso_size) {
@@ -351,6 +462,9 @@ fn to_result(code: isize) -> io::Result<usize> {
 #[cfg(target_os = "linux")]
 impl Gso for UdpSocketController {
     fn max_gso_segments(&self) -> usize {
+        if self.gso_disabled.load(Ordering::Relaxed) {
+            return 1;
+        }
         match self.gso_size {
             OffloadStatus::Unsupported => 1,
             OffloadStatus::Supported(n) => n as usize,
@@ -447,3 +561,54 @@ fn setsockopt(
         }
     }
 }
+
+#[cfg(test)]
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "openbsd",)))]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_pmtudisc_probe() {
+        let getsockopt = |fd: libc::c_int, level, name| {
+            let mut value: libc::c_int = 0;
+            let mut len = mem::size_of_val(&value) as libc::socklen_t;
+            let ret =
+                unsafe { libc::getsockopt(fd, level, name, &mut value as *mut _ as _, &mut len) };
+            assert_eq!(ret, 0);
+            value
+        };
+        let Ok(usc) = crate::ArcUsc::new("[::]:0".parse().unwrap()) else {
+            // 不支持IPv6的环境
+            return;
+        };
+        let fd = usc.0.lock().unwrap().io.as_raw_fd();
+        // 双栈套接字发送的IPv4和IPv6数据报，都不受内核缓存的路径MTU限制
+        assert_eq!(
+            getsockopt(fd, libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER),
+            libc::IPV6_PMTUDISC_PROBE
+        );
+        assert_eq!(
+            getsockopt(fd, libc::IPPROTO_IP, libc::IP_MTU_DISCOVER),
+            libc::IP_PMTUDISC_PROBE
+        );
+    }
+
+    #[test]
+    fn test_gso_batch_len() {
+        let batch_len = |sizes: &[usize], max_segments| {
+            let bufs = sizes.iter().map(|&n| vec![0u8; n]).collect::<Vec<_>>();
+            let bufs = bufs.iter().map(|buf| IoSlice::new(buf)).collect::<Vec<_>>();
+            gso_batch_len(&bufs, max_segments)
+        };
+        assert_eq!(batch_len(&[], 64), 0);
+        assert_eq!(batch_len(&[1200, 1200, 1200], 64), 3);
+        // 最后一个数据报可以更短，更长的则另起一批
+        assert_eq!(batch_len(&[1200, 1200, 800, 1200], 64), 3);
+        assert_eq!(batch_len(&[1200, 1400], 64), 1);
+        assert_eq!(batch_len(&[1200, 1200, 1200], 2), 2);
+        assert_eq!(batch_len(&[1200, 1200, 1200], 1), 1);
+        // 一批数据报的总长度不能超过UDP报文的限制
+        assert_eq!(batch_len(&[30000; 4], 64), 2);
+    }
+}
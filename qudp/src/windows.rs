@@ -1,7 +1,7 @@
 use std::{
     ffi::c_int,
     io, mem,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     os::windows::io::AsRawSocket,
     ptr,
 };
@@ -10,6 +10,7 @@ use libc::c_uchar;
 use windows_sys::Win32::Networking::WinSock;
 
 use crate::{
+    canonical,
     cmsghdr::{self, Cmsg, MsgHdr},
     Io, PacketHeader, UdpSocketController, DEFAULT_TTL,
 };
@@ -112,8 +113,13 @@ impl Io for UdpSocketController {
     ) -> std::io::Result<usize> {
         let mut ctrl_buf = Aligned([0; CMSG_LEN]);
 
-        // FIXME: Ipv6 mapped address should be supported
-        let dst = socket2::SockAddr::from(hdr.dst);
+        // The dual-stack socket sends to the IPv4 address by the IPv4-mapped IPv6 address
+        let dst = match hdr.dst {
+            SocketAddr::V4(v4) if self.local_addr().is_ipv6() => socket2::SockAddr::from(
+                SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port()),
+            ),
+            dst => socket2::SockAddr::from(dst),
+        };
         let mut count = 0;
 
         for buf in bufs {
@@ -162,7 +168,8 @@ impl Io for UdpSocketController {
                 }
             }
 
-            let ecn = hdr.ecn.map_or(0, |x| x as c_int);
+            // The DSCP is set by the socket option, only the ECN codepoint is sent by IP_ECN
+            let ecn = hdr.ecn.map_or(0, |x| (x & 0b11) as c_int);
             // True for IPv4 or IPv4-Mapped IPv6
             let is_ipv4 = hdr.dst.is_ipv4()
                 || matches!(hdr.dst.ip(), IpAddr::V6(addr) if addr.to_ipv4_mapped().is_some());
@@ -277,16 +284,17 @@ impl Io for UdpSocketController {
             }
         }
         let dst = if let Some(ip) = dst_ip {
-            crate::SocketAddr::new(ip, self.local_addr().port())
+            SocketAddr::new(ip, self.local_addr().port())
         } else {
             self.local_addr()
         };
         hdr[0] = PacketHeader {
-            src: addr.unwrap(),
-            dst: dst,
+            src: canonical(addr.unwrap()),
+            dst: canonical(dst),
             ttl: DEFAULT_TTL as u8,
-            ecn: Some(ecn_bits as u8),
+            ecn: Some(ecn_bits as u8 & 0b11),
             seg_size: len as u16,
+            stride: 0,
             gso: false,
         };
 
@@ -312,6 +320,17 @@ impl Io for UdpSocketController {
         self.setsockopt(WinSock::IPPROTO_IP, WinSock::IP_TTL, ttl as _);
         Ok(())
     }
+
+    fn set_dscp(&mut self, dscp: u8) -> io::Result<()> {
+        let tos = (dscp << 2) as c_int;
+        if self.local_addr().is_ipv4() {
+            self.setsockopt(WinSock::IPPROTO_IP, WinSock::IP_TOS, tos);
+        } else {
+            self.setsockopt(WinSock::IPPROTO_IPV6, WinSock::IPV6_TCLASS, tos);
+        }
+        self.dscp = dscp;
+        Ok(())
+    }
 }
 
 static WSARECVMSG_PTR: std::sync::LazyLock<WinSock::LPFN_WSARECVMSG> =
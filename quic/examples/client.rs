@@ -62,8 +62,8 @@ async fn run(args: Arguments) -> Result<(), Box<dyn std::error::Error>> {
         .prefer_versions([0x00000001u32])
         .with_root_certificates(Arc::new(root_cert_store))
         .without_cert()
-        .with_keylog(args.keylog)
-        .with_alpn([b"hq-29".as_ref()].iter().map(|s| s.to_vec()))
+        .with_sslkeylogfile(args.keylog)
+        .with_alpn([b"hq-29"])
         .build();
 
     let quic_conn = client.connect(args.domain, args.addr).unwrap();
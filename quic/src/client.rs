@@ -3,21 +3,40 @@ use std::{
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::Path,
     sync::Arc,
+    time::Duration,
 };
 
 use qbase::{
-    cid::ConnectionId,
+    cid::{ConnectionId, RotationPolicy},
     config::{ClientParameters, Parameters},
-    token::{ArcTokenRegistry, TokenSink},
+    packet::QUIC_V1,
+    streamid::MaxStreamsPolicy,
+    token::{ArcTokenRegistry, MemoryTokenSink, TokenSink},
 };
-use qconnection::{connection::ArcConnection, path::Pathway};
+use qcongestion::{AckPolicy, CongestionAlgorithm, CongestionWindowConfig, LossDetectionConfig};
+use qconnection::{
+    connection::{versions::Versions, ArcConnection, ConnectionSettings},
+    path::Pathway,
+    tls::{self, rpk, ClientSessionStore, KeyLog, MemorySessionStore, RawPublicKeyVerifier},
+};
+use qrecovery::send::{DropPolicy, SendBufferLimits};
+use qunreliable::{DatagramPriority, DatagramQueueLimits};
 use rustls::{
-    client::WantsClientCert,
-    pki_types::{CertificateDer, PrivateKeyDer},
+    client::{
+        danger::ServerCertVerifier, AlwaysResolvesClientRawPublicKeys, EchMode, WantsClientCert,
+    },
+    crypto::CryptoProvider,
+    pki_types::{
+        CertificateDer, EchConfigListBytes, PrivateKeyDer, ServerName, SubjectPublicKeyInfoDer,
+    },
     ClientConfig as TlsClientConfig, ConfigBuilder, WantsVerifier,
 };
+use tokio::sync::watch;
 
-use crate::{get_or_create_usc, ConnKey, QuicConnection, CONNECTIONS};
+use crate::{
+    create_exclusive_usc, get_or_create_usc, limit_max_udp_payload_size, ConnKey, QuicConnection,
+    CONNECTIONS,
+};
 
 type TlsClientConfigBuilder<T> = ConfigBuilder<TlsClientConfig, T>;
 
@@ -26,10 +45,16 @@ pub struct QuicClient {
     addresses: Vec<SocketAddr>,
     _reuse_connection: bool,
     _enable_happy_eyepballs: bool,
-    _prefered_versions: Vec<u32>,
+    preferred_versions: Vec<u32>,
     parameters: Parameters,
     tls_config: Arc<TlsClientConfig>,
-    token_sink: Option<Arc<dyn TokenSink>>,
+    token_sink: Arc<dyn TokenSink>,
+    key_log: Option<Arc<dyn KeyLog>>,
+    session_store: Arc<dyn ClientSessionStore>,
+    handshake_timeout: Option<Duration>,
+    cid_rotation: RotationPolicy,
+    settings: ConnectionSettings,
+    zero_length_cid: bool,
 }
 
 impl QuicClient {
@@ -47,6 +72,7 @@ impl QuicClient {
     ///
     /// 为何是绑定一系列地址，因为QUIC本身就是支持多路径的。
     /// 况且，为了推广IPv6，通常都是IPv6、IPv4双栈的的Happly Eyeballs策略。
+    /// 绑定在`[::]`上的套接字是双栈的，没有绑定IPv4地址时，也用它连接IPv4的服务端。
     ///
     /// # Examples
     /// ```
@@ -67,18 +93,46 @@ impl QuicClient {
     /// ```
     pub fn bind(
         addresses: impl IntoIterator<Item = SocketAddr>,
+    ) -> QuicClientBuilder<TlsClientConfigBuilder<WantsVerifier>> {
+        Self::bind_with_provider(addresses, tls::default_provider())
+    }
+
+    /// 同[`bind`]，但显式指定TLS使用的加密库，比如ring或者aws-lc-rs(包括FIPS版本)，
+    /// Initial包的密钥以及后续的包保护、包头保护，都会由该加密库完成。
+    ///
+    /// [`bind`]: QuicClient::bind
+    pub fn bind_with_provider(
+        addresses: impl IntoIterator<Item = SocketAddr>,
+        crypto_provider: Arc<CryptoProvider>,
     ) -> QuicClientBuilder<TlsClientConfigBuilder<WantsVerifier>> {
         QuicClientBuilder {
             addresses: addresses.into_iter().collect(),
             reuse_connection: true,
             enable_happy_eyepballs: false,
-            preferred_versions: vec![1],
+            preferred_versions: vec![QUIC_V1],
             parameters: Parameters::default(),
-            tls_config: TlsClientConfig::builder_with_protocol_versions(&[&rustls::version::TLS13]),
-            token_sink: None,
+            tls_config: TlsClientConfig::builder_with_provider(crypto_provider)
+                .with_protocol_versions(&[&rustls::version::TLS13])
+                .expect("The crypto provider does not support TLS 1.3"),
+            token_sink: Arc::new(MemoryTokenSink::default()),
+            key_log: None,
+            session_store: Arc::new(MemorySessionStore::default()),
+            sslkeylogfile: None,
+            handshake_timeout: None,
+            cid_rotation: RotationPolicy::default(),
+            settings: ConnectionSettings::default(),
+            zero_length_cid: false,
         }
     }
 
+    /// 获取服务端`server_name`拒绝ECH时，要求客户端重试所使用的ECH配置列表，参见[`with_ech`]
+    ///
+    /// [`with_ech`]: QuicClientBuilder::with_ech
+    pub fn ech_retry_configs(&self, server_name: &str) -> Option<EchConfigListBytes<'static>> {
+        let server_name = ServerName::try_from(server_name).ok()?;
+        self.session_store.ech_retry_configs(&server_name)
+    }
+
     /// 重新绑定地址，其后创建的连接，会使用新的绑定地址
     pub fn rebind(&mut self, addresses: impl IntoIterator<Item = SocketAddr>) {
         self.addresses.clear();
@@ -91,7 +145,7 @@ impl QuicClient {
 
     /// 使用QuicClient的usc，去创建一个QuicConnection
     /// 需要注意，usc的地址是v4还是v6的，要跟server_addr保持一致
-    /// server_name要填写在ClientHello中，
+    /// server_name要填写在ClientHello中，可以是域名，也可以是IP地址，以IP地址连接时不会发送SNI；若都不是，则返回错误
     /// server_addr是目标地址，虽然可以从server_name域名解析出来，但是指定使用哪一个，仍有开发者自己决定
     /// parameters是连接参数，将使用QuicClient中设置好的。
     /// token则根据[`with_token_sink`]设置的方法，来决定是否需要填写
@@ -105,10 +159,22 @@ impl QuicClient {
         server_addr: SocketAddr,
     ) -> io::Result<QuicConnection> {
         let server_name = server_name.into();
+        let tls_server_name = ServerName::try_from(server_name.clone()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{server_name} is neither a valid DNS name nor an IP address"),
+            )
+        })?;
         let bind_addr = self
             .addresses
             .iter()
             .find(|addr| addr.is_ipv4() == server_addr.is_ipv4())
+            // 没有同一协议族的地址时，绑定在[::]上的双栈套接字也能与IPv4的服务端通信
+            .or_else(|| {
+                self.addresses
+                    .iter()
+                    .find(|addr| addr.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED))
+            })
             .ok_or(io::Error::new(
                 io::ErrorKind::AddrNotAvailable,
                 format!(
@@ -117,35 +183,52 @@ impl QuicClient {
                 ),
             ))?;
 
-        let usc = get_or_create_usc(bind_addr)?;
+        let mut parameters = self.parameters.clone();
+        let (usc, scid, key, exclusive_recv) = if self.zero_length_cid {
+            // 多路径扩展要求非零长度的连接ID
+            parameters.set_enable_multipath(false);
+            let exclusive_recv = Arc::new(watch::Sender::new(()));
+            let usc = create_exclusive_usc(bind_addr, &exclusive_recv)?;
+            let key = ConnKey::Exclusive(usc.local_addr());
+            (usc, ConnectionId::default(), key, Some(exclusive_recv))
+        } else {
+            let scid = std::iter::repeat_with(Self::gen_cid)
+                .find(|cid| !CONNECTIONS.contains_key(&ConnKey::Client(*cid)))
+                .unwrap();
+            let usc = get_or_create_usc(bind_addr)?;
+            (usc, scid, ConnKey::Client(scid), None)
+        };
 
         let pathway = Pathway::Direct {
             local: usc.local_addr(),
             remote: server_addr,
         };
 
-        let scid = std::iter::repeat_with(Self::gen_cid)
-            .find(|cid| !CONNECTIONS.contains_key(&ConnKey::Client(*cid)))
-            .unwrap();
-
-        let token_registry = match &self.token_sink {
-            Some(sink) => ArcTokenRegistry::with_sink(server_name.clone(), sink.clone()),
-            None => ArcTokenRegistry::default_sink(server_name.clone()),
-        };
+        let token_registry =
+            ArcTokenRegistry::with_sink(server_name.clone(), self.token_sink.clone());
 
         let inner = ArcConnection::new_client(
+            Versions::client(&self.preferred_versions),
             scid,
-            server_name,
-            self.parameters,
+            tls_server_name,
+            parameters,
             self.tls_config.clone(),
             token_registry,
+            self.key_log.clone(),
+            Some(self.session_store.clone()),
+            self.settings.clone(),
         );
+        if let Some(timeout) = self.handshake_timeout {
+            inner.set_handshake_timeout(timeout);
+        }
+        inner.set_cid_rotation(self.cid_rotation);
         let conn = QuicConnection {
-            key: ConnKey::Client(scid),
+            key,
             inner: inner.clone(),
+            exclusive_recv,
         };
 
-        CONNECTIONS.insert(ConnKey::Client(scid), conn.clone());
+        CONNECTIONS.insert(key, conn.clone());
         inner.add_initial_path(pathway, usc);
         Ok(conn)
     }
@@ -158,7 +241,14 @@ pub struct QuicClientBuilder<T> {
     preferred_versions: Vec<u32>,
     parameters: Parameters,
     tls_config: T,
-    token_sink: Option<Arc<dyn TokenSink>>,
+    token_sink: Arc<dyn TokenSink>,
+    key_log: Option<Arc<dyn KeyLog>>,
+    session_store: Arc<dyn ClientSessionStore>,
+    sslkeylogfile: Option<bool>,
+    handshake_timeout: Option<Duration>,
+    cid_rotation: RotationPolicy,
+    settings: ConnectionSettings,
+    zero_length_cid: bool,
 }
 
 impl<T> QuicClientBuilder<T> {
@@ -177,33 +267,175 @@ impl<T> QuicClientBuilder<T> {
 
     /// 当服务端发来版本协商包，其中包含了支持的版本号，那么客户端可以选择使用哪个版本
     /// 将按照客户端设定的versions的顺序优先选择
+    ///
+    /// 其中第一个受支持的版本将用于发起连接，默认为QUIC v1，即[`QUIC_V1`]；
+    /// 若服务端不支持该版本，将根据版本协商包重新握手，因此也可将QUIC v2(RFC 9369)，
+    /// 即[`QUIC_V2`]放在首位
+    ///
+    /// [`QUIC_V1`]: qbase::packet::QUIC_V1
+    /// [`QUIC_V2`]: qbase::packet::QUIC_V2
     pub fn prefer_versions(mut self, versions: impl IntoIterator<Item = u32>) -> Self {
         self.preferred_versions.clear();
         self.preferred_versions.extend(versions);
         self
     }
 
+    /// 设置主动轮换颁发给服务端的连接ID的策略，比如每隔一段时间或者连接迁移之后轮换，
+    /// 使观察者难以将同一连接的数据包关联起来。默认不主动轮换，详见[`RotationPolicy`]
+    pub fn with_cid_rotation(mut self, policy: RotationPolicy) -> Self {
+        self.cid_rotation = policy;
+        self
+    }
+
+    /// 设置确认收到的数据包的策略，包括确认的最大延迟、每收到几个需确认的数据包就立即确认，
+    /// 以及乱序或者收到ECN-CE标记时是否立即确认。对延迟敏感的应用可使用[`AckPolicy::every_packet`]，
+    /// 确认每一个数据包。默认遵循RFC 9000的建议，详见[`AckPolicy`]
+    pub fn with_ack_policy(mut self, policy: AckPolicy) -> Self {
+        self.settings.ack_policy = policy;
+        self
+    }
+
+    /// 设置丢包检测的参数，包括判定丢包的包序阈值、时间阈值，以及PTO的退避倍数和退避次数上限。
+    /// 默认值遵循RFC 9002的建议，乱序严重或者RTT波动较大的网络（如卫星、蜂窝网络）可适当调整，
+    /// 详见[`LossDetectionConfig`]
+    pub fn with_loss_detection(mut self, config: LossDetectionConfig) -> Self {
+        self.settings.loss_detection = config;
+        self
+    }
+
+    /// 设置为对方补充可创建的流的策略：对方的流关闭后，每关闭多少个流发送一次MAX_STREAMS帧，
+    /// 以及整个连接期间对方最多能创建多少个流。对方同时打开的流不超过传输参数中的初始上限，
+    /// 详见[`MaxStreamsPolicy`]
+    pub fn with_max_streams_policy(mut self, policy: MaxStreamsPolicy) -> Self {
+        self.settings.max_streams_policy = policy;
+        self
+    }
+
+    /// 设置发送缓冲区的上限，包括每个流的和整个连接所有流的，未发送或未被确认的数据达到上限后，
+    /// 写入将等待数据被确认，以免应用无限制地写入而占用过多内存。默认每个流4MiB，整个连接16MiB，
+    /// 详见[`SendBufferLimits`]
+    pub fn with_send_buffer_limits(mut self, limits: SendBufferLimits) -> Self {
+        self.settings.send_buffer_limits = limits;
+        self
+    }
+
+    /// 设置流的[`Writer`](qrecovery::send::Writer)在结束或取消之前被丢弃时，如何处理该流。默认以错误码0
+    /// 重置流，丢弃未被确认的数据；也可以结束流，继续发送已写入的数据，详见[`DropPolicy`]
+    pub fn with_drop_policy(mut self, policy: DropPolicy) -> Self {
+        self.settings.drop_policy = policy;
+        self
+    }
+
+    /// 设置收到但应用尚未读取的datagram的队列上限，包括datagram的个数和字节数，超出上限时按
+    /// [`DatagramOverflow`](qunreliable::DatagramOverflow)丢弃，以免应用读取过慢时占用过多内存。
+    /// 默认最多1024个、1MiB，丢弃最早的，详见[`DatagramQueueLimits`]
+    pub fn with_datagram_queue_limits(mut self, limits: DatagramQueueLimits) -> Self {
+        self.settings.datagram_queue_limits = limits;
+        self
+    }
+
+    /// 设置数据包中datagram与流数据的先后，可在流数据之后、之前发送datagram，或二者交替发送。
+    /// 默认在流数据之后，实时的datagram可设置在之前，以免排在大量流数据之后，详见[`DatagramPriority`]
+    pub fn with_datagram_priority(mut self, priority: DatagramPriority) -> Self {
+        self.settings.datagram_priority = priority;
+        self
+    }
+
+    /// 设置连接各路径使用的拥塞控制算法，可选BBR、NewReno、CUBIC，或通过[`CongestionAlgorithm::Custom`]
+    /// 提供自定义的算法。默认使用BBR，详见[`CongestionAlgorithm`]
+    pub fn with_congestion_algorithm(mut self, algorithm: CongestionAlgorithm) -> Self {
+        self.settings.congestion_algorithm = algorithm;
+        self
+    }
+
+    /// 设置各路径的初始拥塞窗口和最小拥塞窗口，单位为数据包。数据中心等可控的网络环境可使用更大的
+    /// 初始窗口，如32个包，以更少的往返完成短传输；受限的网络宜保持RFC 9002的默认值。
+    /// 只作用于内置的拥塞控制算法，详见[`CongestionWindowConfig`]
+    pub fn with_congestion_window(mut self, config: CongestionWindowConfig) -> Self {
+        self.settings.congestion_window = config;
+        self
+    }
+
+    /// 设置每个连接发送数据的最大速率，单位为字节每秒。该限制由连接的所有路径共享，在pacer中执行，
+    /// 与拥塞窗口无关，可用于限制单个连接占用的带宽。默认不限速
+    pub fn with_max_send_rate(mut self, rate: u64) -> Self {
+        self.settings.max_send_rate = Some(rate);
+        self
+    }
+
+    /// 设置握手的超时时间，从发出第一个Initial包开始计时，若在此期间内握手未完成，
+    /// 连接将以CONNECTION_REFUSED错误中止。若不设置，则握手没有超时限制。
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// 使用零长度的源连接ID，以减少每个数据包的开销，适用于受限的链路
+    ///
+    /// 每个连接都会独占一个新绑定的套接字，服务端发来的数据包按四元组而非目标连接ID路由到连接。
+    /// 使用零长度连接ID的连接无法启用多路径扩展，但仍可通过[`QuicConnection::migrate`]迁移
+    ///
+    /// [`QuicConnection::migrate`]: crate::QuicConnection::migrate
+    pub fn with_zero_length_cid(mut self) -> Self {
+        self.zero_length_cid = true;
+        self
+    }
+
     /// 设值客户端连接参数。若不设置，则会使用一组默认参数。
     /// 后续使用该QuicClient创建新连接，会直接使用这些参数。
     /// 可以多次调用该函数，覆盖上一次设置的参数。
+    /// max_udp_payload_size不能超过接收缓冲区的大小[`RECV_BUFFER_SIZE`](crate::RECV_BUFFER_SIZE)，超过时会被削减。
     pub fn with_parameters(mut self, parameters: ClientParameters) -> Self {
         self.parameters = parameters.into();
+        limit_max_udp_payload_size(&mut self.parameters);
         self
     }
 
-    /// 设置客户端的证书，用于传输给服务端验证客户端身份
-    /// 一般情况下，客户端都无需设置证书，只有特别的安全需求，才需要客户端提交证书
-    /// 设置TokenRegisty的方法，当收到服务端的NewToken，客户端自行决定如何保存。
-    /// 如不设置，则会丢弃这些NewToken
-    /// TokenSink会在创建新连接时，尝试根据server_name获取可用Token
-    /// TokenSink还需保存服务端颁发的关联Token，以便未来连接时使用
+    /// 设置保存Token的TokenSink，收到服务端NEW_TOKEN帧颁发的Token时，由它决定如何保存，
+    /// 以便未来连接同一服务端时在Initial包中携带，免去地址验证的Retry。
+    /// 如不设置，则默认使用一个进程内的[`MemoryTokenSink`]，按server_name保存这些Token。
+    /// 创建新连接时，会根据server_name从TokenSink中尝试获取可用的Token。
     pub fn with_token_sink(mut self, sink: Arc<dyn TokenSink>) -> Self {
-        self.token_sink = Some(sink);
+        self.token_sink = sink;
+        self
+    }
+
+    /// 设置每个连接的TLS密钥日志回调，连接的各级密钥会连同本地的初始源连接ID一同交给它，
+    /// 以便解密特定连接的抓包。与[`with_sslkeylogfile`]设置的全局SSLKEYLOGFILE不同，它只作用于
+    /// 该QuicClient创建的连接，且优先于后者。
+    ///
+    /// [`with_sslkeylogfile`]: QuicClientBuilder::with_sslkeylogfile
+    pub fn with_key_log(mut self, key_log: Arc<dyn KeyLog>) -> Self {
+        self.key_log = Some(key_log);
+        self
+    }
+
+    /// 设置会话票据的存储，用于会话恢复和0-RTT。服务端颁发的NewSessionTicket以及服务端的传输参数，
+    /// 会按照server_name保存在其中，之后再连接该server时，会尝试使用它们恢复会话。
+    /// 如不设置，则默认使用一个进程内的[`MemorySessionStore`]；若要跨进程恢复会话，可实现一个
+    /// 持久化的[`ClientSessionStore`]。
+    pub fn with_session_store(mut self, store: Arc<dyn ClientSessionStore>) -> Self {
+        self.session_store = store;
         self
     }
 }
 
 impl QuicClientBuilder<TlsClientConfigBuilder<WantsVerifier>> {
+    /// 启用加密的ClientHello(ECH)，使得Initial包中ClientHello的SNI等敏感信息不再以明文发送。
+    /// - [`EchMode::Enable`]：使用从服务器HTTPS DNS记录中获取的ECH配置列表加密ClientHello
+    /// - [`EchMode::Grease`]：没有ECH配置时，仍发送一个伪造的ECH扩展，以防协议僵化
+    ///
+    /// 构造ECH配置需要HPKE算法套件，目前只有aws-lc-rs提供，即`rustls::crypto::aws_lc_rs::hpke`。
+    /// 若服务端拒绝了ECH并提供了新的配置，连接会失败，新配置则记录在会话存储中，
+    /// 可通过[`QuicClient::ech_retry_configs`]获取，用它重新构建QuicClient后重试。
+    pub fn with_ech(mut self, mode: impl Into<EchMode>) -> Self {
+        let crypto_provider = self.tls_config.crypto_provider().clone();
+        self.tls_config = TlsClientConfig::builder_with_provider(crypto_provider)
+            .with_ech(mode.into())
+            .expect("The crypto provider does not support TLS 1.3");
+        self
+    }
+
     /// 验证服务端证书，是否正常的方法
     pub fn with_root_certificates(
         self,
@@ -217,6 +449,13 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsVerifier>> {
             parameters: self.parameters,
             tls_config: self.tls_config.with_root_certificates(root_store),
             token_sink: self.token_sink,
+            key_log: self.key_log,
+            session_store: self.session_store,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            zero_length_cid: self.zero_length_cid,
         }
     }
     pub fn with_webpki_verifier(
@@ -231,8 +470,54 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsVerifier>> {
             parameters: self.parameters,
             tls_config: self.tls_config.with_webpki_verifier(verifier),
             token_sink: self.token_sink,
+            key_log: self.key_log,
+            session_store: self.session_store,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            zero_length_cid: self.zero_length_cid,
         }
     }
+
+    /// 使用自定义的方法验证服务端证书，而不是WebPKI，适用于私有PKI、证书固定(pinning)以及使用自签名证书的测试环境
+    ///
+    /// 注意，验证器完全决定了服务端是否可信，不当的实现会使连接遭受中间人攻击
+    pub fn with_server_cert_verifier(
+        self,
+        verifier: Arc<dyn ServerCertVerifier>,
+    ) -> QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
+        QuicClientBuilder {
+            addresses: self.addresses,
+            reuse_connection: self.reuse_connection,
+            enable_happy_eyepballs: self.enable_happy_eyepballs,
+            preferred_versions: self.preferred_versions,
+            parameters: self.parameters,
+            tls_config: self
+                .tls_config
+                .dangerous()
+                .with_custom_certificate_verifier(verifier),
+            token_sink: self.token_sink,
+            key_log: self.key_log,
+            session_store: self.session_store,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            zero_length_cid: self.zero_length_cid,
+        }
+    }
+
+    /// 使用RFC 7250的原始公钥验证服务端，而不是X.509证书，服务端的公钥必须是`trusted_keys`之一。
+    /// 适用于难以管理证书的IoT/嵌入式场景。
+    pub fn with_trusted_raw_public_keys(
+        self,
+        trusted_keys: impl IntoIterator<Item = SubjectPublicKeyInfoDer<'static>>,
+    ) -> QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
+        let provider = self.tls_config.crypto_provider().clone();
+        let verifier = RawPublicKeyVerifier::new(trusted_keys, provider);
+        self.with_server_cert_verifier(Arc::new(verifier))
+    }
 }
 
 impl QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
@@ -247,6 +532,15 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
         let key = std::fs::read(key_file).unwrap();
         let key_der = PrivateKeyDer::try_from(key).unwrap();
 
+        self.with_cert_chain(cert_chain, key_der)
+    }
+
+    /// 向服务端提交证书链和私钥，用于双向TLS认证(mTLS)，证书链的第一个为客户端自身的证书
+    pub fn with_cert_chain(
+        self,
+        cert_chain: Vec<CertificateDer<'static>>,
+        key_der: PrivateKeyDer<'static>,
+    ) -> QuicClientBuilder<TlsClientConfig> {
         QuicClientBuilder {
             addresses: self.addresses,
             reuse_connection: self.reuse_connection,
@@ -258,9 +552,27 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
                 .with_client_auth_cert(cert_chain, key_der)
                 .expect("The private key was wrong encoded or failed validation"),
             token_sink: self.token_sink,
+            key_log: self.key_log,
+            session_store: self.session_store,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            zero_length_cid: self.zero_length_cid,
         }
     }
 
+    /// 使用RFC 7250的原始公钥向服务端证明客户端身份，公钥由私钥`key_der`导出
+    pub fn with_raw_public_key(
+        self,
+        key_der: PrivateKeyDer<'static>,
+    ) -> QuicClientBuilder<TlsClientConfig> {
+        let certified_key = rpk::raw_public_key(self.tls_config.crypto_provider(), key_der)
+            .expect("The private key was wrong encoded or failed validation");
+        let resolver = AlwaysResolvesClientRawPublicKeys::new(certified_key);
+        self.with_cert_resolver(Arc::new(resolver))
+    }
+
     pub fn without_cert(self) -> QuicClientBuilder<TlsClientConfig> {
         QuicClientBuilder {
             addresses: self.addresses,
@@ -270,6 +582,13 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
             parameters: self.parameters,
             tls_config: self.tls_config.with_no_client_auth(),
             token_sink: self.token_sink,
+            key_log: self.key_log,
+            session_store: self.session_store,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            zero_length_cid: self.zero_length_cid,
         }
     }
 
@@ -285,6 +604,13 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
             parameters: self.parameters,
             tls_config: self.tls_config.with_client_cert_resolver(cert_resolver),
             token_sink: self.token_sink,
+            key_log: self.key_log,
+            session_store: self.session_store,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            zero_length_cid: self.zero_length_cid,
         }
     }
 }
@@ -292,27 +618,54 @@ impl QuicClientBuilder<TlsClientConfigBuilder<WantsClientCert>> {
 impl QuicClientBuilder<TlsClientConfig> {
     /// Ref. [alpn-protocol-ids](https://www.iana.org/assignments/tls-extensiontype-values/tls-extensiontype-values.xhtml#alpn-protocol-ids)
     /// client_builder.with_alpn(["http/0.9", "http/1.0", "http/1.1", "h3"]);
-    pub fn with_alpn(mut self, alpn: impl IntoIterator<Item = Vec<u8>>) -> Self {
+    ///
+    /// 若设置了ALPN，而服务端不支持其中任何一个协议，握手将以no_application_protocol(0x0178)失败。
+    /// 协商出的协议可以通过[`alpn_protocol`]获得。
+    ///
+    /// [`alpn_protocol`]: qconnection::connection::ArcConnection::alpn_protocol
+    pub fn with_alpn(mut self, alpn: impl IntoIterator<Item = impl AsRef<[u8]>>) -> Self {
+        let alpn = alpn.into_iter().map(|protocol| protocol.as_ref().to_vec());
         self.tls_config.alpn_protocols.extend(alpn);
         self
     }
 
-    pub fn with_keylog(mut self, flag: bool) -> Self {
-        if flag {
-            self.tls_config.key_log = Arc::new(rustls::KeyLogFile::new());
-        }
+    /// 是否在ClientHello中发送SNI，默认发送。
+    /// 以IP地址连接时，无论是否设置都不会发送SNI，服务端证书将按照IP地址验证；
+    /// 以域名连接但服务端不需要SNI时(比如数据中心内部)，可以关闭它以免泄露域名。
+    pub fn with_sni(mut self, enabled: bool) -> Self {
+        self.tls_config.enable_sni = enabled;
         self
     }
 
-    pub fn build(self) -> QuicClient {
+    /// 是否将TLS密钥按照NSS Key Log格式写入SSLKEYLOGFILE环境变量指定的文件，以便Wireshark解密抓包。
+    /// 若不设置，则只要设置了SSLKEYLOGFILE环境变量，就会写入。
+    pub fn with_sslkeylogfile(mut self, flag: bool) -> Self {
+        self.sslkeylogfile = Some(flag);
+        self
+    }
+
+    pub fn build(mut self) -> QuicClient {
+        let sslkeylogfile = self
+            .sslkeylogfile
+            .unwrap_or_else(|| std::env::var_os("SSLKEYLOGFILE").is_some());
+        if sslkeylogfile {
+            self.tls_config.key_log = Arc::new(rustls::KeyLogFile::new());
+        }
+
         QuicClient {
             addresses: self.addresses,
             _reuse_connection: self.reuse_connection,
             _enable_happy_eyepballs: self.enable_happy_eyepballs,
-            _prefered_versions: self.preferred_versions,
+            preferred_versions: self.preferred_versions,
             parameters: self.parameters,
             tls_config: Arc::new(self.tls_config),
             token_sink: self.token_sink,
+            key_log: self.key_log,
+            session_store: self.session_store,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            zero_length_cid: self.zero_length_cid,
         }
     }
 }
@@ -1,23 +1,36 @@
-use std::{io, net::SocketAddr, sync::LazyLock};
+use std::{
+    future::{self, Future},
+    io::{self, IoSliceMut},
+    net::SocketAddr,
+    sync::{Arc, LazyLock},
+};
 
 use bytes::BytesMut;
 use dashmap::DashMap;
 use deref_derive::Deref;
 use qbase::{
     cid::ConnectionId,
-    packet::{header::GetDcid, Packet, PacketReader, RetryHeader, VersionNegotiationHeader},
+    config::Parameters,
+    frame::EcnCodepoint,
+    packet::{
+        header::{long, GetDcid},
+        DataHeader, Packet, PacketReader, RetryHeader, VersionNegotiationHeader,
+    },
+    varint::VarInt,
 };
 use qconnection::{connection::ArcConnection, path::Pathway, router::Router};
 use qudp::ArcUsc;
+use tokio::sync::watch;
 
 pub mod client;
 pub mod server;
 
 pub use client::QuicClient;
+pub use qconnection::usc::{PacketHeader, QuicIo, RECV_BUFFER_SIZE};
 pub use server::QuicServer;
 
 /// 全局的usc注册管理，用于查找已有的usc，key是绑定的本地地址，包括v4和v6的地址
-static USC_REGISTRY: LazyLock<DashMap<SocketAddr, ArcUsc>> = LazyLock::new(DashMap::new);
+static USC_REGISTRY: LazyLock<DashMap<SocketAddr, Arc<dyn QuicIo>>> = LazyLock::new(DashMap::new);
 /// 全局的QuicConnection注册管理，用于查找已有的QuicConnection，key是初期的Pathway
 /// 包括被动接收的连接和主动发起的连接
 static CONNECTIONS: LazyLock<DashMap<ConnKey, QuicConnection>> = LazyLock::new(DashMap::new);
@@ -26,6 +39,8 @@ static CONNECTIONS: LazyLock<DashMap<ConnKey, QuicConnection>> = LazyLock::new(D
 enum ConnKey {
     Client(ConnectionId),
     Server(ConnectionId),
+    /// 使用零长度连接ID的客户端连接，key是其独占的套接字最初绑定的本地地址
+    Exclusive(SocketAddr),
 }
 
 #[derive(Debug, Clone, Deref)]
@@ -33,20 +48,49 @@ pub struct QuicConnection {
     key: ConnKey,
     #[deref]
     inner: ArcConnection,
+    /// 使用零长度连接ID时，独占套接字的接收任务随其关闭，即在连接的所有句柄都被释放后结束
+    exclusive_recv: Option<Arc<watch::Sender<()>>>,
 }
 
 impl QuicConnection {
-    pub fn recv_version_negotiation(&self, _vn: &VersionNegotiationHeader) {
-        // self.inner.recv_version_negotiation(vn);
+    pub fn recv_version_negotiation(&self, vn: &VersionNegotiationHeader) {
+        self.inner.recv_version_negotiation(vn);
     }
 
-    pub fn recv_retry_packet(&self, retry: &RetryHeader) {
-        self.inner.recv_retry_packet(retry);
+    pub fn recv_retry_packet(&self, retry: &RetryHeader, packet: &[u8]) {
+        self.inner.recv_retry_packet(retry, packet);
     }
 
     pub fn update_path_recv_time(&self, pathway: Pathway) {
         self.inner.update_path_recv_time(pathway);
     }
+
+    /// 主动将连接迁移到绑定在`bind_addr`上的套接字，仅客户端可用
+    ///
+    /// 新路径验证通过后，旧路径才会被放弃，详见[`ArcConnection::migrate`]
+    ///
+    /// 使用零长度连接ID的连接会迁移到一个新建的独占套接字上
+    pub async fn migrate(&self, bind_addr: SocketAddr) -> io::Result<()> {
+        let usc = match &self.exclusive_recv {
+            Some(exclusive_recv) => create_exclusive_usc(&bind_addr, exclusive_recv)?,
+            None => get_or_create_usc(&bind_addr)?,
+        };
+        self.inner.migrate(usc).await
+    }
+
+    /// 在绑定于`bind_addr`的套接字上新增一条路径，与已有路径同时使用，仅客户端可用
+    ///
+    /// 要求双方都启用了多路径扩展，详见[`ArcConnection::add_path`]
+    pub async fn add_path(&self, bind_addr: SocketAddr) -> io::Result<()> {
+        if let ConnKey::Exclusive(_) = self.key {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Multipath is not supported with zero-length connection IDs",
+            ));
+        }
+        let usc = get_or_create_usc(&bind_addr)?;
+        self.inner.add_path(usc).await
+    }
 }
 
 impl Drop for QuicConnection {
@@ -55,34 +99,77 @@ impl Drop for QuicConnection {
     }
 }
 
-pub fn get_or_create_usc(bind_addr: &SocketAddr) -> io::Result<ArcUsc> {
-    let recv_task = |usc: ArcUsc, _bind_addr: SocketAddr| {
-        let mut receiver = usc.receiver();
-        tokio::spawn(async move {
-            while let Ok(msg_count) = receiver.recv().await {
-                for (hdr, buf) in
-                    core::iter::zip(&receiver.headers, &receiver.iovecs).take(msg_count)
-                {
-                    let data: BytesMut = buf[0..hdr.seg_size as usize].into();
-                    let pathway = Pathway::Direct {
-                        local: hdr.dst,
-                        remote: hdr.src,
-                    };
-
-                    let reader = PacketReader::new(data, 8);
-                    for pkt in reader.flatten() {
-                        accpet_packet(pkt, pathway, &usc);
-                    }
+/// 启动接收任务，`dcid_len`是短包头中目标连接ID的长度，`canceled`完成后接收任务结束
+fn spawn_recv_task(
+    usc: Arc<dyn QuicIo>,
+    dcid_len: usize,
+    canceled: impl Future<Output = ()> + Send + 'static,
+) {
+    let (batch_size, buffer_size) = usc.recv_buffers();
+    let mut bufs = vec![vec![0u8; buffer_size]; batch_size.max(1)];
+    let mut hdrs = vec![PacketHeader::default(); batch_size.max(1)];
+    tokio::spawn(async move {
+        // 数据报从IoSliceMut中读出，不必每次接收都重新构造
+        let mut io_slices = bufs
+            .iter_mut()
+            .map(|buf| IoSliceMut::new(buf))
+            .collect::<Vec<_>>();
+        tokio::pin!(canceled);
+        loop {
+            let recv = core::future::poll_fn(|cx| usc.poll_recv(cx, &mut io_slices, &mut hdrs));
+            let msg_count = tokio::select! {
+                _ = &mut canceled => {
+                    // 连接可能未关闭就被释放，其按四元组的路由不会被移除
+                    Router::unroute_io(&usc);
+                    break;
+                }
+                recv = recv => match recv {
+                    Ok(msg_count) => msg_count,
+                    Err(_) => break,
+                },
+            };
+            // GRO合并的数据报被逐个拆分，以免包头被当作前一个数据报的一部分
+            let datagrams = core::iter::zip(&hdrs, &io_slices)
+                .take(msg_count)
+                .flat_map(|(hdr, buf)| hdr.datagrams(buf).map(move |datagram| (hdr, datagram)));
+            for (hdr, datagram) in datagrams {
+                let data: BytesMut = datagram.into();
+                let pathway = Pathway::Direct {
+                    local: hdr.dst,
+                    remote: hdr.src,
+                };
+
+                let ecn = hdr.ecn;
+
+                let reader = PacketReader::new(data, dcid_len);
+                for pkt in reader.flatten() {
+                    accpet_packet(pkt, pathway, ecn, &usc);
                 }
             }
-        });
-    };
+        }
+    });
+}
+
+/// 本地通告的max_udp_payload_size不能超过usc接收缓冲区的大小，否则更大的数据报会被截断而无法解密，
+/// 超过时削减为接收缓冲区的大小
+fn limit_max_udp_payload_size(parameters: &mut Parameters) {
+    let max_udp_payload_size = VarInt::from_u32(RECV_BUFFER_SIZE as u32);
+    if parameters.max_udp_payload_size() > max_udp_payload_size {
+        log::warn!(
+            "max_udp_payload_size {} exceeds the receive buffer, limited to {}",
+            parameters.max_udp_payload_size(),
+            max_udp_payload_size
+        );
+        parameters.set_max_udp_payload_size(max_udp_payload_size);
+    }
+}
 
+pub fn get_or_create_usc(bind_addr: &SocketAddr) -> io::Result<Arc<dyn QuicIo>> {
     let usc = USC_REGISTRY
         .entry(*bind_addr)
         .or_try_insert_with(|| {
-            let usc = ArcUsc::new(*bind_addr)?;
-            recv_task(usc.clone(), *bind_addr);
+            let usc: Arc<dyn QuicIo> = Arc::new(ArcUsc::new(*bind_addr)?);
+            spawn_recv_task(usc.clone(), 8, future::pending());
             io::Result::Ok(usc)
         })?
         .value()
@@ -90,15 +177,63 @@ pub fn get_or_create_usc(bind_addr: &SocketAddr) -> io::Result<ArcUsc> {
     Ok(usc)
 }
 
-fn accpet_packet(packet: Packet, pathway: Pathway, usc: &ArcUsc) {
+/// 注册一个自定义的I/O，代替在其本地地址上绑定的qudp套接字，比如模拟的网络、代理的传输或用户态的协议栈
+///
+/// 此后绑定该地址的[`QuicClient`]和[`QuicServer`]都经由它收发数据报，客户端使用零长度连接ID时除外，
+/// 那时仍会新建独占的套接字。若该地址已有注册的I/O，返回[`io::ErrorKind::AddrInUse`]错误
+pub fn register_io(usc: Arc<dyn QuicIo>) -> io::Result<()> {
+    match USC_REGISTRY.entry(usc.local_addr()) {
+        dashmap::Entry::Occupied(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} has been registered", usc.local_addr()),
+        )),
+        dashmap::Entry::Vacant(entry) => {
+            spawn_recv_task(usc.clone(), 8, future::pending());
+            entry.insert(usc);
+            Ok(())
+        }
+    }
+}
+
+/// 为使用零长度连接ID的连接创建一个独占的套接字，不注册到全局的usc注册管理中
+///
+/// 收到的短包头数据包的目标连接ID长度都为0，按四元组路由到连接。`exclusive_recv`是连接的
+/// [`QuicConnection`]持有的，连接被释放后它随之关闭，接收任务也随之结束，不再等待下一个数据报
+fn create_exclusive_usc(
+    bind_addr: &SocketAddr,
+    exclusive_recv: &watch::Sender<()>,
+) -> io::Result<Arc<dyn QuicIo>> {
+    let usc: Arc<dyn QuicIo> = Arc::new(ArcUsc::new(*bind_addr)?);
+    let mut released = exclusive_recv.subscribe();
+    spawn_recv_task(usc.clone(), 0, async move {
+        _ = released.changed().await;
+    });
+    Ok(usc)
+}
+
+fn accpet_packet(
+    packet: Packet,
+    pathway: Pathway,
+    ecn: Option<EcnCodepoint>,
+    usc: &Arc<dyn QuicIo>,
+) {
     match packet {
         Packet::Data(packet) => {
-            if let Err(packet) = Router::try_to_route_packet_from(packet, pathway, usc) {
-                QuicServer::try_to_accept_conn_from(packet, pathway, usc);
+            // The server may upgrade the connection to another compatible version, which the
+            // client follows by restarting the handshake
+            if let DataHeader::Long(long::DataHeader::Initial(initial)) = &packet.header {
+                let key = conn_key_of_client(initial.get_dcid(), usc);
+                if let Some(conn) = CONNECTIONS.get(&key) {
+                    conn.recv_initial_version(initial.version);
+                }
+            }
+            if let Err(packet) = Router::try_to_route_packet_from(packet, pathway, ecn, usc) {
+                QuicServer::try_to_accept_conn_from(packet, pathway, ecn, usc);
             }
         }
         Packet::VN(vn) => {
-            let key = ConnKey::Server(*vn.get_dcid());
+            // The VN packet echoes the source connection ID of the client
+            let key = conn_key_of_client(vn.get_dcid(), usc);
             if let Some(conn) = CONNECTIONS.get(&key) {
                 conn.recv_version_negotiation(&vn);
                 conn.update_path_recv_time(pathway);
@@ -106,10 +241,11 @@ fn accpet_packet(packet: Packet, pathway: Pathway, usc: &ArcUsc) {
                 log::error!("No connection found for VN packet");
             }
         }
-        Packet::Retry(retry) => {
-            let key = ConnKey::Server(*retry.get_dcid());
+        Packet::Retry(retry, bytes) => {
+            // The Retry packet is sent to the source connection ID of the client
+            let key = conn_key_of_client(retry.get_dcid(), usc);
             if let Some(conn) = CONNECTIONS.get(&key) {
-                conn.recv_retry_packet(&retry);
+                conn.recv_retry_packet(&retry, &bytes);
                 conn.update_path_recv_time(pathway);
             } else {
                 log::error!("No connection found for Retry packet");
@@ -117,3 +253,12 @@ fn accpet_packet(packet: Packet, pathway: Pathway, usc: &ArcUsc) {
         }
     }
 }
+
+/// 客户端连接的key，零长度的连接ID由接收的套接字区分
+fn conn_key_of_client(dcid: &ConnectionId, usc: &Arc<dyn QuicIo>) -> ConnKey {
+    if dcid.is_empty() {
+        ConnKey::Exclusive(usc.local_addr())
+    } else {
+        ConnKey::Client(*dcid)
+    }
+}
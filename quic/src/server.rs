@@ -1,33 +1,176 @@
 use std::{
+    future::Future,
     io::{self},
     net::SocketAddr,
     path::Path,
-    sync::{Arc, LazyLock, RwLock},
+    sync::{Arc, LazyLock, Mutex, RwLock, Weak},
+    time::Duration,
 };
 
 use dashmap::DashMap;
+use futures::{future::BoxFuture, FutureExt};
 use qbase::{
-    cid::ConnectionId,
-    config::{Parameters, ServerParameters},
+    cid::{ConnectionId, RotationPolicy},
+    config::{ext::be_parameters, Parameters, ServerParameters},
+    error::{Error, ErrorKind},
+    frame::{ConnectionCloseFrame, EcnCodepoint},
     packet::{
-        header::{GetDcid, GetScid},
-        long, DataHeader, DataPacket, InitialHeader, RetryHeader,
+        header::{
+            long::io::{LongHeaderBuilder, WriteLongHeader},
+            GetDcid, GetScid, GetType,
+        },
+        long,
+        r#type::long::GetVersion,
+        DataHeader, DataPacket, InitialHeader, RetryHeader, Type, QUIC_V1, QUIC_V2,
     },
+    streamid::MaxStreamsPolicy,
     token::{ArcTokenRegistry, TokenProvider},
     util::ArcAsyncDeque,
 };
-use qconnection::{connection::ArcConnection, path::Pathway, router::Router};
-use qudp::ArcUsc;
+use qcongestion::{AckPolicy, CongestionAlgorithm, CongestionWindowConfig, LossDetectionConfig};
+use qconnection::{
+    connection::{
+        scope::initial, versions::Versions, ArcConnection, ConnectionSettings, WeakConnection,
+    },
+    path::{Pathway, ViaPathway},
+    router::Router,
+    tls::{
+        self, rpk, sni, AddressValidator, ArcTlsSession, KeyLog, OcspStapler, RawPublicKeyVerifier,
+    },
+    usc::QuicIo,
+};
+use qrecovery::send::{DropPolicy, SendBufferLimits};
+use qunreliable::{DatagramPriority, DatagramQueueLimits};
 use rustls::{
-    pki_types::{CertificateDer, PrivateKeyDer},
-    server::{danger::ClientCertVerifier, NoClientAuth, ResolvesServerCert, WantsServerCert},
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, PrivateKeyDer, SubjectPublicKeyInfoDer},
+    server::{
+        danger::ClientCertVerifier, AlwaysResolvesServerRawPublicKeys, NoClientAuth,
+        ProducesTickets, ResolvesServerCert, WantsServerCert, WebPkiClientVerifier,
+    },
+    sign::CertifiedKey,
     ConfigBuilder, ServerConfig as TlsServerConfig, WantsVerifier,
 };
 
-use crate::{get_or_create_usc, ConnKey, QuicConnection, CONNECTIONS};
+use crate::{get_or_create_usc, limit_max_udp_payload_size, ConnKey, QuicConnection, CONNECTIONS};
 
 type TlsServerConfigBuilder<T> = ConfigBuilder<TlsServerConfig, T>;
 type QuicListner = ArcAsyncDeque<(QuicConnection, SocketAddr)>;
+type SniConfig = Arc<dyn Fn(&str) -> Arc<TlsServerConfig> + Send + Sync>;
+type EarlyDataFilter = Arc<dyn Fn(&EarlyDataRequest) -> bool + Send + Sync>;
+
+/// 新连接请求0-RTT时的信息，供[`with_early_data_filter`]决定是否接受该连接的0-RTT数据
+///
+/// [`with_early_data_filter`]: QuicServerBuilder::with_early_data_filter
+#[derive(Debug)]
+pub struct EarlyDataRequest<'a> {
+    /// ClientHello中的SNI，若客户端没有发送或者无法从第一个Initial包中解析出来，则为None
+    pub server_name: Option<&'a str>,
+    /// 第一个Initial包携带的Token，可据此判断客户端地址是否已验证，以及Token是否新鲜
+    pub token: &'a [u8],
+    pub remote_addr: SocketAddr,
+}
+
+/// 服务端接受0-RTT数据的策略
+#[derive(Clone, Default)]
+struct EarlyData {
+    enabled: bool,
+    max_size: Option<usize>,
+    filter: Option<EarlyDataFilter>,
+}
+
+/// 服务端使用的每个TLS配置都要经过的处理，包括listen时的、[`QuicServer::set_tls_config`]和
+/// [`QuicServer::set_cert_resolver`]替换的，以及通过SNI选中的
+struct TlsConfigPolicy {
+    /// 写入SSLKEYLOGFILE的密钥日志，None则保留配置自身的
+    key_log: Option<Arc<dyn rustls::KeyLog>>,
+    /// QUIC要求max_early_data_size只能是0或者0xffffffff，0-RTT数据量由流控以及max_early_data限制
+    max_early_data_size: u32,
+    /// listen时配置的ALPN，配置自身没有ALPN时使用
+    alpn_protocols: Vec<Vec<u8>>,
+    /// 通过SNI选中的配置及其处理后的副本，以免每个新连接都复制一次；只持有原配置的弱引用，
+    /// 原配置被释放后其条目随之失效，弱引用同时保证了其地址不会被复用
+    patched: Mutex<Vec<(Weak<TlsServerConfig>, Arc<TlsServerConfig>)>>,
+}
+
+impl TlsConfigPolicy {
+    fn new(
+        sslkeylogfile: Option<bool>,
+        early_data: &EarlyData,
+        tls_config: &TlsServerConfig,
+    ) -> Self {
+        let sslkeylogfile =
+            sslkeylogfile.unwrap_or_else(|| std::env::var_os("SSLKEYLOGFILE").is_some());
+        Self {
+            key_log: sslkeylogfile.then(|| Arc::new(rustls::KeyLogFile::new()) as _),
+            max_early_data_size: if early_data.enabled { u32::MAX } else { 0 },
+            alpn_protocols: tls_config.alpn_protocols.clone(),
+            patched: Mutex::default(),
+        }
+    }
+
+    /// 同[`apply`]，但每个配置只处理一次，之后再选中时复用处理后的副本
+    ///
+    /// [`apply`]: TlsConfigPolicy::apply
+    fn apply_cached(&self, tls_config: Arc<TlsServerConfig>) -> Arc<TlsServerConfig> {
+        let mut patched = self.patched.lock().unwrap();
+        if let Some((_, cached)) = patched
+            .iter()
+            .find(|(origin, _)| std::ptr::eq(origin.as_ptr(), Arc::as_ptr(&tls_config)))
+        {
+            return cached.clone();
+        }
+        patched.retain(|(origin, _)| origin.strong_count() > 0);
+        let origin = Arc::downgrade(&tls_config);
+        let mut tls_config = tls_config;
+        self.apply(&mut tls_config);
+        // 无需修改的配置不必缓存，否则缓存中的强引用会让它永远不被释放
+        if !std::ptr::eq(origin.as_ptr(), Arc::as_ptr(&tls_config)) {
+            patched.push((origin, tls_config.clone()));
+        }
+        tls_config
+    }
+
+    /// 只在需要修改时才复制配置
+    fn apply(&self, tls_config: &mut Arc<TlsServerConfig>) {
+        let key_log = self
+            .key_log
+            .as_ref()
+            .filter(|key_log| !Arc::ptr_eq(key_log, &tls_config.key_log));
+        let inherit_alpn = tls_config.alpn_protocols.is_empty() && !self.alpn_protocols.is_empty();
+        if key_log.is_none()
+            && !inherit_alpn
+            && tls_config.max_early_data_size == self.max_early_data_size
+        {
+            return;
+        }
+        let tls_config = Arc::make_mut(tls_config);
+        if let Some(key_log) = key_log {
+            tls_config.key_log = key_log.clone();
+        }
+        if inherit_alpn {
+            tls_config.alpn_protocols = self.alpn_protocols.clone();
+        }
+        tls_config.max_early_data_size = self.max_early_data_size;
+    }
+}
+
+/// 服务端要求新连接通过Retry进行地址验证的策略
+struct AddressValidation {
+    validator: Arc<AddressValidator>,
+    threshold: usize,
+    /// 正在握手的连接，用以衡量服务端的负载；只持有弱引用，以免延长已结束的连接的生命
+    handshaking: Mutex<Vec<WeakConnection>>,
+}
+
+impl AddressValidation {
+    /// 正在握手的连接数达到阈值时，认为服务端处于高负载
+    fn is_under_load(&self) -> bool {
+        let mut handshaking = self.handshaking.lock().unwrap();
+        handshaking.retain(|conn| conn.upgrade().is_some_and(|conn| conn.is_handshaking()));
+        handshaking.len() >= self.threshold
+    }
+}
 
 /// 理应全局只有一个server
 static SERVER: LazyLock<RwLock<Option<QuicServer>>> = LazyLock::new(RwLock::default);
@@ -40,11 +183,8 @@ impl ResolvesServerCert for VirtualHosts {
         &self,
         client_hello: rustls::server::ClientHello,
     ) -> Option<Arc<rustls::sign::CertifiedKey>> {
-        self.0.get(client_hello.server_name()?).map(|host| {
-            let cert =
-                rustls::sign::CertifiedKey::new(host.cert_chain.clone(), host.private_key.clone());
-            Arc::new(cert)
-        })
+        let host = self.0.get(client_hello.server_name()?)?;
+        host.stapler.resolve(client_hello)
     }
 }
 
@@ -56,11 +196,19 @@ struct RawQuicServer {
     addresses: Vec<SocketAddr>,
     listener: QuicListner,
     _restrict: bool,
-    _supported_versions: Vec<u32>,
+    supported_versions: Vec<u32>,
     _load_balance: Arc<dyn Fn(InitialHeader) -> Option<RetryHeader> + Send + Sync + 'static>,
-    _parameters: DashMap<String, Parameters>,
-    tls_config: Arc<TlsServerConfig>,
+    parameters: DashMap<String, Parameters>,
+    tls_config: RwLock<Arc<TlsServerConfig>>,
+    tls_policy: TlsConfigPolicy,
     token_provider: Option<Arc<dyn TokenProvider + Send + Sync + 'static>>,
+    key_log: Option<Arc<dyn KeyLog>>,
+    sni_config: Option<SniConfig>,
+    handshake_timeout: Option<Duration>,
+    cid_rotation: RotationPolicy,
+    settings: ConnectionSettings,
+    early_data: EarlyData,
+    address_validation: Option<AddressValidation>,
 }
 
 #[derive(Clone)]
@@ -74,19 +222,37 @@ impl QuicServer {
     pub fn bind(
         addresses: impl IntoIterator<Item = SocketAddr>,
         restrict: bool,
+    ) -> QuicServerBuilder<TlsServerConfigBuilder<WantsVerifier>> {
+        Self::bind_with_provider(addresses, restrict, tls::default_provider())
+    }
+
+    /// 同[`bind`]，但显式指定TLS使用的加密库，比如ring或者aws-lc-rs(包括FIPS版本)
+    ///
+    /// [`bind`]: QuicServer::bind
+    pub fn bind_with_provider(
+        addresses: impl IntoIterator<Item = SocketAddr>,
+        restrict: bool,
+        crypto_provider: Arc<CryptoProvider>,
     ) -> QuicServerBuilder<TlsServerConfigBuilder<WantsVerifier>> {
         QuicServerBuilder {
             addresses: addresses.into_iter().collect(),
             restrict,
-            supported_versions: Vec::with_capacity(2),
+            supported_versions: vec![QUIC_V1, QUIC_V2],
             load_balance: Arc::new(|_| None),
             parameters: DashMap::new(),
-            tls_config: TlsServerConfig::builder_with_provider(
-                rustls::crypto::ring::default_provider().into(),
-            )
-            .with_protocol_versions(&[&rustls::version::TLS13])
-            .unwrap(),
+            tls_config: TlsServerConfig::builder_with_provider(crypto_provider)
+                .with_protocol_versions(&[&rustls::version::TLS13])
+                .expect("The crypto provider does not support TLS 1.3"),
             token_provider: None,
+            key_log: None,
+            sni_config: None,
+            sslkeylogfile: None,
+            handshake_timeout: None,
+            cid_rotation: RotationPolicy::default(),
+            settings: ConnectionSettings::default(),
+            early_data: EarlyData::default(),
+            address_validation: None,
+            ocsp_refresh: None,
         }
     }
 
@@ -102,7 +268,39 @@ impl QuicServer {
         self.0.listener.pop().await.ok_or_else(listening_stopped)
     }
 
-    pub(crate) fn try_to_accept_conn_from(packet: DataPacket, pathway: Pathway, usc: &ArcUsc) {
+    /// 替换新连接使用的TLS配置，比如证书更新后，无需重启服务端即可生效
+    /// 已经建立或正在握手的连接不受影响，仍使用其创建时的配置
+    /// 若设置了[`with_sni_config`]，通过SNI选中的配置不受影响
+    /// 同listen时的配置一样，新配置会应用[`with_sslkeylogfile`]和[`with_early_data`]的设置，
+    /// 若新配置没有设置ALPN，则沿用listen时的ALPN
+    ///
+    /// [`with_sni_config`]: QuicServerBuilder::with_sni_config
+    /// [`with_sslkeylogfile`]: QuicServerBuilder::with_sslkeylogfile
+    /// [`with_early_data`]: QuicServerBuilder::with_early_data
+    pub fn set_tls_config(&self, mut tls_config: Arc<TlsServerConfig>) {
+        self.0.tls_policy.apply(&mut tls_config);
+        *self.0.tls_config.write().unwrap() = tls_config;
+    }
+
+    /// 仅替换新连接所用TLS配置中的证书，其余配置保持不变
+    /// 同[`set_tls_config`]，只影响之后到来的新连接
+    ///
+    /// [`set_tls_config`]: QuicServer::set_tls_config
+    pub fn set_cert_resolver(&self, cert_resolver: Arc<dyn ResolvesServerCert>) {
+        let mut tls_config = self.0.tls_config.write().unwrap();
+        let mut new_config = TlsServerConfig::clone(&tls_config);
+        new_config.cert_resolver = cert_resolver;
+        let mut new_config = Arc::new(new_config);
+        self.0.tls_policy.apply(&mut new_config);
+        *tls_config = new_config;
+    }
+
+    pub(crate) fn try_to_accept_conn_from(
+        packet: DataPacket,
+        pathway: Pathway,
+        ecn: Option<EcnCodepoint>,
+        usc: &Arc<dyn QuicIo>,
+    ) {
         let server = SERVER.read().unwrap();
         let Some(server) = server.as_ref().map(|s| &s.0) else {
             return;
@@ -114,6 +312,18 @@ impl QuicServer {
             }
             _ => return,
         };
+        let Type::Long(long_type) = packet.header.get_type() else {
+            return;
+        };
+        let version = long_type.get_version();
+        if !server.supported_versions.contains(&version) {
+            log::debug!("ignore the incoming connection in unsupported version {version:#x}");
+            return;
+        }
+        let token = match &packet.header {
+            DataHeader::Long(long::DataHeader::Initial(initial)) => initial.token.as_slice(),
+            _ => &[],
+        };
         let initial_scid =
             std::iter::repeat_with(|| ConnectionId::random_gen_with_mark(8, 0, 0x7F))
                 .find(|cid| !CONNECTIONS.contains_key(&ConnKey::Server(*cid)))
@@ -124,49 +334,300 @@ impl QuicServer {
             None => ArcTokenRegistry::default_provider(),
         };
 
-        let initial_keys = server.initial_server_keys(client_initial_dcid);
+        // 尚不知道SNI，先用listen时的配置解密ClientHello
+        let peek_keys = initial_server_keys(&server.tls_config(), version, client_initial_dcid);
+        let client_hello = sni::peek_client_hello(&packet, &peek_keys);
+        let server_name = client_hello
+            .as_deref()
+            .and_then(sni::parse_server_name)
+            .map(ToOwned::to_owned);
+        // 使用SNI对应的连接参数，没有则使用通配的；preferred_address中的连接ID和无状态重置令牌由新连接填入
+        let mut parameters = server
+            .parameters
+            .get(server_name.as_deref().unwrap_or("*"))
+            .or_else(|| server.parameters.get("*"))
+            .map(|params| params.clone())
+            .unwrap_or_default();
+        parameters.set_original_destination_connection_id(Some(client_initial_dcid));
+        // 地址验证：Retry包中的Token证明了客户端的地址；之前的连接中通过NEW_TOKEN帧颁发的Token同样可以，
+        // 这样再次连接时就无需再Retry了。若均无效且服务端负载过高，则发送Retry包要求客户端验证地址
+        let remote = pathway.remote_addr();
+        let mut address_validated = false;
+        let retry_odcid = server
+            .address_validation
+            .as_ref()
+            .and_then(|validation| validation.validator.validate_retry_token(remote, token));
+        if let Some(odcid) = retry_odcid {
+            // 客户端跟随了Retry，原始的目标连接ID在Token中，当前的目标连接ID则是Retry包的源连接ID
+            parameters.set_original_destination_connection_id(Some(odcid));
+            parameters.set_retry_source_connection_id(Some(client_initial_dcid));
+            address_validated = true;
+        } else if !token.is_empty()
+            && server.token_provider.as_ref().is_some_and(|provider| {
+                provider.validate_token(server_name.clone().unwrap_or_default(), remote, token)
+            })
+        {
+            address_validated = true;
+        } else if server.address_validation.is_some() && AddressValidator::is_retry_token(token) {
+            // Retry包中的Token无效(如过期、地址变了)，客户端无法再次重试，应立即以INVALID_TOKEN关闭连接，
+            // 而非静默丢弃让客户端等到超时，见RFC 9000 §8.1.3
+            send_invalid_token_close(
+                version,
+                initial_scid,
+                server_initial_dcid,
+                &peek_keys,
+                pathway,
+                usc,
+            );
+            return;
+        } else if let Some(validation) = server
+            .address_validation
+            .as_ref()
+            .filter(|validation| validation.is_under_load())
+        {
+            // 0-RTT包不能触发Retry，直接丢弃，等待客户端的Initial包
+            if matches!(
+                packet.header,
+                DataHeader::Long(long::DataHeader::Initial(_))
+            ) {
+                let token = validation
+                    .validator
+                    .issue_retry_token(remote, &client_initial_dcid);
+                send_retry(
+                    version,
+                    server_initial_dcid,
+                    client_initial_dcid,
+                    token,
+                    pathway,
+                    usc,
+                );
+            }
+            return;
+        }
+
+        // 兼容版本协商：客户端在传输参数中给出了可用版本时，升级到服务端更偏好的兼容版本
+        let client_version_info = client_hello
+            .as_deref()
+            .and_then(sni::parse_transport_parameters)
+            .and_then(|params| be_parameters(params).ok())
+            .and_then(|(_, params)| params.version_information().clone());
+        let versions = Versions::server(
+            version,
+            &server.supported_versions,
+            client_version_info.as_ref(),
+        );
+        // 在创建TlsSession之前，根据ClientHello中的SNI选择TLS配置
+        let mut tls_config = server
+            .sni_config
+            .as_ref()
+            .zip(server_name.as_deref())
+            .map(|(select, server_name)| server.tls_policy.apply_cached(select(server_name)))
+            .unwrap_or_else(|| server.tls_config());
+        if let Some(filter) = &server.early_data.filter {
+            let request = EarlyDataRequest {
+                server_name: server_name.as_deref(),
+                token,
+                remote_addr: pathway.remote_addr(),
+            };
+            if tls_config.max_early_data_size != 0 && !filter(&request) {
+                Arc::make_mut(&mut tls_config).max_early_data_size = 0;
+            }
+        }
+        // 客户端的0-RTT数据包仍是原版本的，升级后无法解密，只能拒绝0-RTT
+        if versions.is_upgraded() && tls_config.max_early_data_size != 0 {
+            Arc::make_mut(&mut tls_config).max_early_data_size = 0;
+        }
+        // 连接的初始密钥使用SNI选中的配置的密码库
+        let initial_keys =
+            initial_server_keys(&tls_config, versions.version(), client_initial_dcid);
+        let original_keys = versions
+            .is_upgraded()
+            .then(|| initial_server_keys(&tls_config, version, client_initial_dcid));
         let inner = ArcConnection::new_server(
+            versions,
             initial_scid,
             server_initial_dcid,
-            Parameters::default(), // &self.parameters,
+            parameters,
             initial_keys,
-            server.tls_config.clone(),
+            tls_config,
             token_provider,
+            server.key_log.clone(),
+            server.settings.clone(),
         );
-        inner.add_initial_path(pathway, usc.clone());
+        if let Some(original_keys) = original_keys {
+            inner.accept_original_version(version, original_keys);
+        }
+        if let Some(timeout) = server.handshake_timeout {
+            inner.set_handshake_timeout(timeout);
+        }
+        inner.set_cid_rotation(server.cid_rotation);
+        if let Some(max_early_data) = server.early_data.max_size {
+            inner.set_max_early_data(max_early_data);
+        }
+        if address_validated {
+            inner.add_validated_initial_path(pathway, usc.clone());
+        } else {
+            inner.add_initial_path(pathway, usc.clone());
+        }
+        if let Some(validation) = &server.address_validation {
+            validation
+                .handshaking
+                .lock()
+                .unwrap()
+                .push(inner.downgrade());
+        }
         let conn = QuicConnection {
             key: ConnKey::Server(initial_scid),
             inner,
+            exclusive_recv: None,
         };
         log::info!("incoming connection established");
         server
             .listener
             .push_back((conn.clone(), pathway.remote_addr()));
-        _ = Router::try_to_route_packet_from(packet, pathway, usc);
+        _ = Router::try_to_route_packet_from(packet, pathway, ecn, usc);
+    }
+}
+
+/// 服务端通告的preferred_address也须监听，客户端握手完成后会迁移过去，全零的地址表示不支持该地址族
+fn preferred_addresses(parameters: &DashMap<String, Parameters>) -> Vec<SocketAddr> {
+    parameters
+        .iter()
+        .filter_map(|params| params.preferred_address())
+        .flat_map(|addr| {
+            [
+                SocketAddr::V4(addr.address_v4()),
+                SocketAddr::V6(addr.address_v6()),
+            ]
+        })
+        .filter(|addr| !addr.ip().is_unspecified() && addr.port() != 0)
+        .collect()
+}
+
+/// 回复一个携带`token`的Retry包，要求客户端以新的目标连接ID重新发送Initial包
+fn send_retry(
+    version: u32,
+    client_scid: ConnectionId,
+    odcid: ConnectionId,
+    token: Vec<u8>,
+    pathway: Pathway,
+    usc: &Arc<dyn QuicIo>,
+) {
+    let retry_scid = std::iter::repeat_with(|| ConnectionId::random_gen_with_mark(8, 0, 0x7F))
+        .find(|cid| *cid != odcid)
+        .unwrap();
+    let mut retry = LongHeaderBuilder::with_cid(client_scid, retry_scid)
+        .version(version)
+        .retry(token, [0; 16]);
+    let Some(integrity) = tls::retry::integrity_tag(&odcid, &retry) else {
+        return;
+    };
+    retry.integrity = integrity;
+    let mut packet = Vec::with_capacity(64 + retry.token.len());
+    packet.put_long_header(&retry);
+    if let Err(e) = usc.clone().sync_send_via_path_way(packet, pathway) {
+        log::warn!("failed to send the Retry packet: {e}");
+    }
+}
+
+/// 以INVALID_TOKEN的CONNECTION_CLOSE帧拒绝Token无效的客户端，`keys`是由客户端的目标连接ID推导的初始密钥
+fn send_invalid_token_close(
+    version: u32,
+    scid: ConnectionId,
+    client_scid: ConnectionId,
+    keys: &rustls::quic::Keys,
+    pathway: Pathway,
+    usc: &Arc<dyn QuicIo>,
+) {
+    let error = Error::with_default_fty(ErrorKind::InvalidToken, "invalid Retry token");
+    let ccf = ConnectionCloseFrame::from(error);
+    let mut packet = vec![0u8; 128];
+    let Some(size) =
+        initial::assemble_initial_ccf_packet(&mut packet, keys, version, scid, client_scid, &ccf)
+    else {
+        return;
+    };
+    packet.truncate(size);
+    if let Err(e) = usc.clone().sync_send_via_path_way(packet, pathway) {
+        log::warn!("failed to send the CONNECTION_CLOSE packet: {e}");
     }
 }
 
 impl RawQuicServer {
-    /// 获取所有监听的地址，因为客户端创建的每一个usc都可以成为监听端口
-    pub fn initial_server_keys(&self, dcid: ConnectionId) -> rustls::quic::Keys {
-        let suite = self
-            .tls_config
-            .crypto_provider()
-            .cipher_suites
-            .iter()
-            .find_map(|cs| match (cs.suite(), cs.tls13()) {
-                (rustls::CipherSuite::TLS13_AES_128_GCM_SHA256, Some(suite)) => suite.quic_suite(),
-                _ => None,
-            })
-            .unwrap();
-        suite.keys(&dcid, rustls::Side::Server, rustls::quic::Version::V1)
+    fn tls_config(&self) -> Arc<TlsServerConfig> {
+        self.tls_config.read().unwrap().clone()
+    }
+}
+
+/// 由客户端的目标连接ID`dcid`推导`version`版本的初始密钥，使用`tls_config`的密码库
+fn initial_server_keys(
+    tls_config: &TlsServerConfig,
+    version: u32,
+    dcid: ConnectionId,
+) -> rustls::quic::Keys {
+    ArcTlsSession::initial_keys(
+        tls_config.crypto_provider(),
+        version,
+        rustls::Side::Server,
+        dcid,
+    )
+}
+
+/// 定期刷新装订的OCSP响应，在开始监听时启动
+struct OcspRefresh {
+    stapler: Arc<OcspStapler>,
+    interval: Duration,
+    refresh: Arc<dyn Fn() -> BoxFuture<'static, Option<Vec<u8>>> + Send + Sync>,
+}
+
+impl OcspRefresh {
+    fn new<F>(
+        stapler: Arc<OcspStapler>,
+        interval: Duration,
+        refresh: impl Fn() -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = Option<Vec<u8>>> + Send + 'static,
+    {
+        Self {
+            stapler,
+            interval,
+            refresh: Arc::new(move || refresh().boxed()),
+        }
+    }
+
+    /// 在当前的tokio运行时中启动刷新任务，不在运行时中时返回错误
+    fn spawn(self) -> io::Result<()> {
+        let runtime = tokio::runtime::Handle::try_current().map_err(|e| {
+            io::Error::other(format!(
+                "refreshing the OCSP response requires a tokio runtime: {e}"
+            ))
+        })?;
+        // 只持有弱引用，服务端不再使用该证书时(比如被set_tls_config替换)，停止刷新
+        let stapler = Arc::downgrade(&self.stapler);
+        let refresh = self.refresh;
+        let mut interval = tokio::time::interval(self.interval);
+        runtime.spawn(async move {
+            loop {
+                interval.tick().await;
+                let ocsp = refresh().await;
+                let Some(stapler) = stapler.upgrade() else {
+                    break;
+                };
+                match ocsp {
+                    Some(ocsp) => stapler.staple(ocsp),
+                    None => log::warn!("failed to refresh the OCSP response, keep the stale one"),
+                }
+            }
+        });
+        Ok(())
     }
 }
 
+/// 虚拟主机的证书，装订的OCSP响应可被定期刷新
 #[derive(Debug)]
 struct Host {
-    cert_chain: Vec<rustls::pki_types::CertificateDer<'static>>,
-    private_key: Arc<dyn rustls::sign::SigningKey>,
+    stapler: Arc<OcspStapler>,
 }
 
 pub struct QuicServerBuilder<T> {
@@ -177,6 +638,15 @@ pub struct QuicServerBuilder<T> {
     parameters: DashMap<String, Parameters>,
     tls_config: T,
     token_provider: Option<Arc<dyn TokenProvider + Send + Sync + 'static>>,
+    key_log: Option<Arc<dyn KeyLog>>,
+    sni_config: Option<SniConfig>,
+    sslkeylogfile: Option<bool>,
+    handshake_timeout: Option<Duration>,
+    cid_rotation: RotationPolicy,
+    settings: ConnectionSettings,
+    early_data: EarlyData,
+    address_validation: Option<AddressValidation>,
+    ocsp_refresh: Option<OcspRefresh>,
 }
 
 pub struct QuicServerSniBuilder<T> {
@@ -188,9 +658,23 @@ pub struct QuicServerSniBuilder<T> {
     parameters: DashMap<String, Parameters>,
     tls_config: T,
     token_provider: Option<Arc<dyn TokenProvider + Send + Sync + 'static>>,
+    key_log: Option<Arc<dyn KeyLog>>,
+    sni_config: Option<SniConfig>,
+    sslkeylogfile: Option<bool>,
+    handshake_timeout: Option<Duration>,
+    cid_rotation: RotationPolicy,
+    settings: ConnectionSettings,
+    early_data: EarlyData,
+    address_validation: Option<AddressValidation>,
+    ocsp_refresh: Vec<OcspRefresh>,
 }
 
 impl<T> QuicServerBuilder<T> {
+    /// 设置服务端支持的QUIC版本，客户端以其他版本发起的连接将被忽略
+    /// 默认支持QUIC v1和v2(RFC 9369)，即[`QUIC_V1`]和[`QUIC_V2`]
+    ///
+    /// [`QUIC_V1`]: qbase::packet::QUIC_V1
+    /// [`QUIC_V2`]: qbase::packet::QUIC_V2
     pub fn with_supported_versions(mut self, versions: impl IntoIterator<Item = u32>) -> Self {
         self.supported_versions.clear();
         self.supported_versions.extend(versions);
@@ -208,16 +692,174 @@ impl<T> QuicServerBuilder<T> {
     }
 
     /// TokenProvider有2个功能：
-    /// TokenProvider需要向客户端颁发新Token
-    /// 同时，收到新连接，TokenProvider也要验证客户端的Initial包中的Token
+    /// TokenProvider需要向客户端颁发新Token，握手完成后会通过NEW_TOKEN帧发给客户端
+    /// 同时，收到新连接，TokenProvider也要验证客户端的Initial包中的Token，验证通过的客户端地址
+    /// 不再受3倍放大限制，也无需Retry
+    ///
+    /// 可以使用[`AddressValidator`]，它颁发的Token与客户端的IP地址以及server_name绑定
     pub fn with_token_provider(mut self, token_provider: Arc<dyn TokenProvider>) -> Self {
         self.token_provider = Some(token_provider);
         self
     }
+
+    /// 启用Retry地址验证：当正在握手的连接数达到`threshold`时，不再直接创建新连接，而是回复一个
+    /// 携带Token的Retry包，客户端带着该Token重新发送Initial包，证明其确实拥有该地址后，才创建连接。
+    /// 以此抵御伪造源地址的握手洪泛，代价是多一个往返。`threshold`为0则总是要求地址验证。
+    /// Token由`validator`加密保护，内含客户端地址、原始的目标连接ID以及签发时间，服务端无需为此保存状态；
+    /// 服务器集群需使用相同密钥的[`AddressValidator`]。
+    pub fn with_retry(mut self, validator: Arc<AddressValidator>, threshold: usize) -> Self {
+        self.address_validation = Some(AddressValidation {
+            validator,
+            threshold,
+            handshaking: Mutex::default(),
+        });
+        self
+    }
+
+    /// 设置握手的超时时间，从收到新连接的第一个Initial包开始计时，若在此期间内握手未完成，
+    /// 连接将以CONNECTION_REFUSED错误中止，释放握手阶段的所有状态，防止半开的握手长期占用资源。
+    /// 若不设置，则握手没有超时限制。
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// 设置主动轮换颁发给客户端的连接ID的策略，比如每隔一段时间轮换，使观察者难以将同一连接的
+    /// 数据包关联起来。服务端不会主动迁移，[`RotationPolicy::after_migration`]对其无效。
+    /// 默认不主动轮换，详见[`RotationPolicy`]
+    pub fn with_cid_rotation(mut self, policy: RotationPolicy) -> Self {
+        self.cid_rotation = policy;
+        self
+    }
+
+    /// 设置确认收到的数据包的策略，包括确认的最大延迟、每收到几个需确认的数据包就立即确认，
+    /// 以及乱序或者收到ECN-CE标记时是否立即确认。对延迟敏感的应用可使用[`AckPolicy::every_packet`]，
+    /// 确认每一个数据包。默认遵循RFC 9000的建议，详见[`AckPolicy`]
+    pub fn with_ack_policy(mut self, policy: AckPolicy) -> Self {
+        self.settings.ack_policy = policy;
+        self
+    }
+
+    /// 设置丢包检测的参数，包括判定丢包的包序阈值、时间阈值，以及PTO的退避倍数和退避次数上限。
+    /// 默认值遵循RFC 9002的建议，乱序严重或者RTT波动较大的网络（如卫星、蜂窝网络）可适当调整，
+    /// 详见[`LossDetectionConfig`]
+    pub fn with_loss_detection(mut self, config: LossDetectionConfig) -> Self {
+        self.settings.loss_detection = config;
+        self
+    }
+
+    /// 设置为对方补充可创建的流的策略：对方的流关闭后，每关闭多少个流发送一次MAX_STREAMS帧，
+    /// 以及整个连接期间对方最多能创建多少个流。对方同时打开的流不超过传输参数中的初始上限，
+    /// 详见[`MaxStreamsPolicy`]
+    pub fn with_max_streams_policy(mut self, policy: MaxStreamsPolicy) -> Self {
+        self.settings.max_streams_policy = policy;
+        self
+    }
+
+    /// 设置发送缓冲区的上限，包括每个流的和整个连接所有流的，未发送或未被确认的数据达到上限后，
+    /// 写入将等待数据被确认，以免应用无限制地写入而占用过多内存。默认每个流4MiB，整个连接16MiB，
+    /// 详见[`SendBufferLimits`]
+    pub fn with_send_buffer_limits(mut self, limits: SendBufferLimits) -> Self {
+        self.settings.send_buffer_limits = limits;
+        self
+    }
+
+    /// 设置流的[`Writer`](qrecovery::send::Writer)在结束或取消之前被丢弃时，如何处理该流。默认以错误码0
+    /// 重置流，丢弃未被确认的数据；也可以结束流，继续发送已写入的数据，详见[`DropPolicy`]
+    pub fn with_drop_policy(mut self, policy: DropPolicy) -> Self {
+        self.settings.drop_policy = policy;
+        self
+    }
+
+    /// 设置收到但应用尚未读取的datagram的队列上限，包括datagram的个数和字节数，超出上限时按
+    /// [`DatagramOverflow`](qunreliable::DatagramOverflow)丢弃，以免应用读取过慢时占用过多内存。
+    /// 默认最多1024个、1MiB，丢弃最早的，详见[`DatagramQueueLimits`]
+    pub fn with_datagram_queue_limits(mut self, limits: DatagramQueueLimits) -> Self {
+        self.settings.datagram_queue_limits = limits;
+        self
+    }
+
+    /// 设置数据包中datagram与流数据的先后，可在流数据之后、之前发送datagram，或二者交替发送。
+    /// 默认在流数据之后，实时的datagram可设置在之前，以免排在大量流数据之后，详见[`DatagramPriority`]
+    pub fn with_datagram_priority(mut self, priority: DatagramPriority) -> Self {
+        self.settings.datagram_priority = priority;
+        self
+    }
+
+    /// 设置连接各路径使用的拥塞控制算法，可选BBR、NewReno、CUBIC，或通过[`CongestionAlgorithm::Custom`]
+    /// 提供自定义的算法。默认使用BBR，详见[`CongestionAlgorithm`]
+    pub fn with_congestion_algorithm(mut self, algorithm: CongestionAlgorithm) -> Self {
+        self.settings.congestion_algorithm = algorithm;
+        self
+    }
+
+    /// 设置各路径的初始拥塞窗口和最小拥塞窗口，单位为数据包。数据中心等可控的网络环境可使用更大的
+    /// 初始窗口，如32个包，以更少的往返完成短传输；受限的网络宜保持RFC 9002的默认值。
+    /// 只作用于内置的拥塞控制算法，详见[`CongestionWindowConfig`]
+    pub fn with_congestion_window(mut self, config: CongestionWindowConfig) -> Self {
+        self.settings.congestion_window = config;
+        self
+    }
+
+    /// 设置每个连接发送数据的最大速率，单位为字节每秒。该限制由连接的所有路径共享，在pacer中执行，
+    /// 与拥塞窗口无关，可用于限制单个连接占用的带宽。默认不限速
+    pub fn with_max_send_rate(mut self, rate: u64) -> Self {
+        self.settings.max_send_rate = Some(rate);
+        self
+    }
+
+    /// 是否接受客户端恢复会话时发送的0-RTT数据，默认不接受。
+    /// 0-RTT数据可能被重放，只应在应用层协议能容忍重放时启用；且需配合[`with_ticketer`]，
+    /// 否则客户端无法恢复会话，也就不会发送0-RTT数据。
+    ///
+    /// [`with_ticketer`]: QuicServerBuilder::with_ticketer
+    pub fn with_early_data(mut self, enabled: bool) -> Self {
+        self.early_data.enabled = enabled;
+        self
+    }
+
+    /// 限制每个连接在握手完成前接受的0-RTT数据量(包负载的字节数)，超出的0-RTT包会被丢弃，
+    /// 客户端会在1-RTT包中重传这些数据。若不设置，则只受流控的限制。
+    pub fn with_max_early_data(mut self, max_early_data: usize) -> Self {
+        self.early_data.max_size = Some(max_early_data);
+        self
+    }
+
+    /// 为每个新连接决定是否接受其0-RTT数据，比如根据SNI或者Token的新鲜度，返回false则拒绝，
+    /// 客户端将在握手完成后以1-RTT重发。只在启用了0-RTT时生效，参见[`with_early_data`]。
+    ///
+    /// [`with_early_data`]: QuicServerBuilder::with_early_data
+    pub fn with_early_data_filter(
+        mut self,
+        filter: impl Fn(&EarlyDataRequest) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.early_data.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// 设置每个连接的TLS密钥日志回调，连接的各级密钥会连同本地的初始源连接ID一同交给它，
+    /// 以便解密特定连接的抓包。
+    pub fn with_key_log(mut self, key_log: Arc<dyn KeyLog>) -> Self {
+        self.key_log = Some(key_log);
+        self
+    }
+
+    /// 根据新连接的ClientHello中的SNI，选择该连接使用的TLS配置，从而在同一个监听地址上，
+    /// 为不同的server_name提供不同的证书和ALPN集合。
+    /// 若ClientHello中没有SNI，或者无法从第一个Initial包中解析出来，则使用当前的TLS配置，见[`QuicServer::set_tls_config`]。
+    pub fn with_sni_config(
+        mut self,
+        select: impl Fn(&str) -> Arc<TlsServerConfig> + Send + Sync + 'static,
+    ) -> Self {
+        self.sni_config = Some(Arc::new(select));
+        self
+    }
 }
 
 impl QuicServerBuilder<TlsServerConfigBuilder<WantsVerifier>> {
     /// Choose how to verify client certificates.
+    ///
+    /// Any user-provided [`ClientCertVerifier`] can be used, for private PKI or certificate pinning.
     pub fn with_cert_verifier(
         self,
         client_cert_verifier: Arc<dyn ClientCertVerifier>,
@@ -232,9 +874,50 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsVerifier>> {
                 .tls_config
                 .with_client_cert_verifier(client_cert_verifier),
             token_provider: self.token_provider,
+            key_log: self.key_log,
+            sni_config: self.sni_config,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            early_data: self.early_data,
+            address_validation: self.address_validation,
+            ocsp_refresh: self.ocsp_refresh,
         }
     }
 
+    /// Require the client to present a certificate issued by one of the `roots`(mTLS).
+    ///
+    /// The connection will be closed with a CRYPTO_ERROR if the client does not present a valid
+    /// certificate, the authenticated client identity can be obtained from [`peer_identity`].
+    ///
+    /// [`peer_identity`]: qconnection::connection::ArcConnection::peer_identity
+    pub fn with_client_cert_roots(
+        self,
+        roots: impl Into<Arc<rustls::RootCertStore>>,
+    ) -> QuicServerBuilder<TlsServerConfigBuilder<WantsServerCert>> {
+        let verifier = WebPkiClientVerifier::builder_with_provider(
+            roots.into(),
+            self.tls_config.crypto_provider().clone(),
+        )
+        .build()
+        .expect("The root certificates are invalid");
+        self.with_cert_verifier(verifier)
+    }
+
+    /// Require the client to authenticate with a [RFC 7250] raw public key, which must be one of
+    /// the `trusted_keys`.
+    ///
+    /// [RFC 7250]: https://www.rfc-editor.org/rfc/rfc7250
+    pub fn with_trusted_raw_public_keys(
+        self,
+        trusted_keys: impl IntoIterator<Item = SubjectPublicKeyInfoDer<'static>>,
+    ) -> QuicServerBuilder<TlsServerConfigBuilder<WantsServerCert>> {
+        let provider = self.tls_config.crypto_provider().clone();
+        let verifier = RawPublicKeyVerifier::new(trusted_keys, provider);
+        self.with_cert_verifier(Arc::new(verifier))
+    }
+
     /// Disable client authentication.
     pub fn without_cert_verifier(
         self,
@@ -249,6 +932,15 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsVerifier>> {
                 .tls_config
                 .with_client_cert_verifier(Arc::new(NoClientAuth)),
             token_provider: self.token_provider,
+            key_log: self.key_log,
+            sni_config: self.sni_config,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            early_data: self.early_data,
+            address_validation: self.address_validation,
+            ocsp_refresh: self.ocsp_refresh,
         }
     }
 }
@@ -258,11 +950,15 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsServerCert>> {
     /// 后续接受新的连接，会直接使用这些参数。不过在sni模式下，各个host可以有不同的参数，该函数将失去意义。
     /// 因此，它最好配合[`with_single_cert`]或者[`with_single_cert_with_ocsp`]一起使用
     /// 可以多次调用该函数，会覆盖上一次设置的参数。
+    /// 若设置了preferred_address，服务端会同时监听其中的地址，客户端握手完成后验证并迁移到该地址，
+    /// 其中的连接ID及无状态重置令牌无需设置，各连接会填入自己的。
     ///
     /// [`with_single_cert`]: QuicServerBuilder::with_single_cert
     /// [`with_single_cert_with_ocsp`]: QuicServerBuilder::with_single_cert_with_ocsp
     pub fn with_parameters(self, parameters: ServerParameters) -> Self {
-        self.parameters.insert("*".to_owned(), parameters.into());
+        let mut parameters = parameters.into();
+        limit_max_udp_payload_size(&mut parameters);
+        self.parameters.insert("*".to_owned(), parameters);
         self
     }
 
@@ -289,6 +985,15 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsServerCert>> {
                 .with_single_cert(cert_chain, key_der)
                 .expect("The private key was wrong encoded or failed validation"),
             token_provider: self.token_provider,
+            key_log: self.key_log,
+            sni_config: self.sni_config,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            early_data: self.early_data,
+            address_validation: self.address_validation,
+            ocsp_refresh: self.ocsp_refresh,
         }
     }
 
@@ -315,6 +1020,92 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsServerCert>> {
                 .with_single_cert_with_ocsp(cert_chain, key_der, ocsp)
                 .expect("The private key was wrong encoded or failed validation"),
             token_provider: self.token_provider,
+            key_log: self.key_log,
+            sni_config: self.sni_config,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            early_data: self.early_data,
+            address_validation: self.address_validation,
+            ocsp_refresh: self.ocsp_refresh,
+        }
+    }
+
+    /// 同[`with_single_cert`]，但会定期调用`refresh`获取新的OCSP响应，装订(stapling)到证书上，
+    /// 使得要求校验吊销状态的客户端无需额外的往返即可验证证书。
+    /// 开始监听时立即获取一次，之后每隔`interval`刷新一次，`interval`不能为0。
+    /// 若`refresh`返回None，表示获取失败，会继续装订上一次的响应。
+    ///
+    /// [`with_single_cert`]: QuicServerBuilder::with_single_cert
+    pub fn with_single_cert_with_ocsp_refresh<F>(
+        self,
+        cert_file: impl AsRef<Path>,
+        key_file: impl AsRef<Path>,
+        interval: Duration,
+        refresh: impl Fn() -> F + Send + Sync + 'static,
+    ) -> QuicServerBuilder<TlsServerConfig>
+    where
+        F: Future<Output = Option<Vec<u8>>> + Send + 'static,
+    {
+        let cert = std::fs::read(cert_file).unwrap();
+        let cert_chain = vec![CertificateDer::from(cert)];
+
+        let key = std::fs::read(key_file).unwrap();
+        let key_der = PrivateKeyDer::try_from(key).unwrap();
+        let private_key = self
+            .tls_config
+            .crypto_provider()
+            .key_provider
+            .load_private_key(key_der)
+            .expect("The private key was wrong encoded or failed validation");
+
+        let stapler = Arc::new(OcspStapler::new(CertifiedKey::new(cert_chain, private_key)));
+        QuicServerBuilder {
+            addresses: self.addresses,
+            restrict: self.restrict,
+            supported_versions: self.supported_versions,
+            load_balance: self.load_balance,
+            parameters: self.parameters,
+            tls_config: self.tls_config.with_cert_resolver(stapler.clone()),
+            token_provider: self.token_provider,
+            key_log: self.key_log,
+            sni_config: self.sni_config,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            early_data: self.early_data,
+            address_validation: self.address_validation,
+            ocsp_refresh: Some(OcspRefresh::new(stapler, interval, refresh)),
+        }
+    }
+
+    /// 使用RFC 7250的原始公钥而不是X.509证书向客户端证明身份，公钥由私钥`key_der`导出
+    pub fn with_raw_public_key(
+        self,
+        key_der: PrivateKeyDer<'static>,
+    ) -> QuicServerBuilder<TlsServerConfig> {
+        let certified_key = rpk::raw_public_key(self.tls_config.crypto_provider(), key_der)
+            .expect("The private key was wrong encoded or failed validation");
+        let resolver = AlwaysResolvesServerRawPublicKeys::new(certified_key);
+        QuicServerBuilder {
+            addresses: self.addresses,
+            restrict: self.restrict,
+            supported_versions: self.supported_versions,
+            load_balance: self.load_balance,
+            parameters: self.parameters,
+            tls_config: self.tls_config.with_cert_resolver(Arc::new(resolver)),
+            token_provider: self.token_provider,
+            key_log: self.key_log,
+            sni_config: self.sni_config,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            early_data: self.early_data,
+            address_validation: self.address_validation,
+            ocsp_refresh: self.ocsp_refresh,
         }
     }
 
@@ -332,6 +1123,15 @@ impl QuicServerBuilder<TlsServerConfigBuilder<WantsServerCert>> {
                 .with_cert_resolver(Arc::new(VirtualHosts(hosts.clone()))),
             hosts,
             token_provider: self.token_provider,
+            key_log: self.key_log,
+            sni_config: self.sni_config,
+            sslkeylogfile: self.sslkeylogfile,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            early_data: self.early_data,
+            address_validation: self.address_validation,
+            ocsp_refresh: self.ocsp_refresh.into_iter().collect(),
         }
     }
 }
@@ -347,6 +1147,40 @@ impl QuicServerSniBuilder<TlsServerConfig> {
         key_file: impl AsRef<Path>,
         parameters: Parameters,
     ) -> Self {
+        self.insert_host(server_name, cert_file, key_file, parameters)
+            .0
+    }
+
+    /// 同[`add_host`]，但会定期调用`refresh`获取该服务器证书新的OCSP响应并装订，
+    /// 详见[`QuicServerBuilder::with_single_cert_with_ocsp_refresh`]
+    ///
+    /// [`add_host`]: QuicServerSniBuilder::add_host
+    pub fn add_host_with_ocsp_refresh<F>(
+        self,
+        server_name: impl Into<String>,
+        cert_file: impl AsRef<Path>,
+        key_file: impl AsRef<Path>,
+        parameters: Parameters,
+        interval: Duration,
+        refresh: impl Fn() -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = Option<Vec<u8>>> + Send + 'static,
+    {
+        let (mut builder, stapler) = self.insert_host(server_name, cert_file, key_file, parameters);
+        builder
+            .ocsp_refresh
+            .push(OcspRefresh::new(stapler, interval, refresh));
+        builder
+    }
+
+    fn insert_host(
+        self,
+        server_name: impl Into<String>,
+        cert_file: impl AsRef<Path>,
+        key_file: impl AsRef<Path>,
+        mut parameters: Parameters,
+    ) -> (Self, Arc<OcspStapler>) {
         let cert = std::fs::read(cert_file).unwrap();
         let cert_chain = vec![CertificateDer::from(cert)];
 
@@ -361,26 +1195,65 @@ impl QuicServerSniBuilder<TlsServerConfig> {
             .unwrap();
 
         let server_name = server_name.into();
+        limit_max_udp_payload_size(&mut parameters);
         self.parameters.insert(server_name.clone(), parameters);
+        let stapler = Arc::new(OcspStapler::new(CertifiedKey::new(cert_chain, private_key)));
         self.hosts.insert(
             server_name,
             Host {
-                cert_chain,
-                private_key,
+                stapler: stapler.clone(),
             },
         );
-        self
+        (self, stapler)
     }
 }
 
 impl QuicServerBuilder<TlsServerConfig> {
-    pub fn with_alpn(mut self, alpn: impl IntoIterator<Item = Vec<u8>>) -> Self {
+    /// 设置服务端支持的应用层协议，按优先级排列。客户端提供的协议与之没有交集时，
+    /// 握手将以no_application_protocol(0x0178)失败。
+    pub fn with_alpn(mut self, alpn: impl IntoIterator<Item = impl AsRef<[u8]>>) -> Self {
+        let alpn = alpn.into_iter().map(|protocol| protocol.as_ref().to_vec());
         self.tls_config.alpn_protocols.extend(alpn);
         self
     }
 
-    pub fn listen(self) -> QuicServer {
-        for addr in &self.addresses {
+    /// 设置握手完成后，服务端向客户端颁发的会话票据(NewSessionTicket)数量，默认为2。
+    /// 设置为0则不颁发会话票据，客户端将无法恢复会话。
+    pub fn with_session_tickets(mut self, count: usize) -> Self {
+        self.tls_config.send_tls13_tickets = count;
+        self
+    }
+
+    /// 设置会话票据的加密器，它同时决定了会话票据的有效期。若不设置，则不能恢复会话。
+    /// 服务器集群若想跨节点恢复会话，需使用相同密钥的[`RotatingTicketer`]，并同步轮换密钥。
+    ///
+    /// [`RotatingTicketer`]: qconnection::tls::RotatingTicketer
+    pub fn with_ticketer(mut self, ticketer: Arc<dyn ProducesTickets>) -> Self {
+        self.tls_config.ticketer = ticketer;
+        self
+    }
+
+    /// 是否将TLS密钥按照NSS Key Log格式写入SSLKEYLOGFILE环境变量指定的文件，以便Wireshark解密抓包。
+    /// 若不设置，则只要设置了SSLKEYLOGFILE环境变量，就会写入。
+    pub fn with_sslkeylogfile(mut self, flag: bool) -> Self {
+        self.sslkeylogfile = Some(flag);
+        self
+    }
+
+    /// 开始监听，设置了定期刷新OCSP响应时，须在tokio运行时中调用，否则返回错误
+    pub fn listen(mut self) -> io::Result<QuicServer> {
+        if let Some(ocsp_refresh) = self.ocsp_refresh.take() {
+            ocsp_refresh.spawn()?;
+        }
+        let tls_policy =
+            TlsConfigPolicy::new(self.sslkeylogfile, &self.early_data, &self.tls_config);
+        let mut tls_config = Arc::new(self.tls_config);
+        tls_policy.apply(&mut tls_config);
+        for addr in self
+            .addresses
+            .iter()
+            .chain(&preferred_addresses(&self.parameters))
+        {
             if let Err(e) = get_or_create_usc(addr) {
                 log::error!("faild to listen on {addr}: {e}");
             }
@@ -389,25 +1262,71 @@ impl QuicServerBuilder<TlsServerConfig> {
             addresses: self.addresses,
             listener: Default::default(),
             _restrict: self.restrict,
-            _supported_versions: self.supported_versions,
+            supported_versions: self.supported_versions,
             _load_balance: self.load_balance,
-            _parameters: self.parameters,
-            tls_config: Arc::new(self.tls_config),
+            parameters: self.parameters,
+            tls_config: RwLock::new(tls_config),
+            tls_policy,
             token_provider: self.token_provider,
+            key_log: self.key_log,
+            sni_config: self.sni_config,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            early_data: self.early_data,
+            address_validation: self.address_validation,
         }));
         *SERVER.write().unwrap() = Some(quic_server.clone());
-        quic_server
+        Ok(quic_server)
     }
 }
 
 impl QuicServerSniBuilder<TlsServerConfig> {
-    pub fn with_alpn(mut self, alpn: impl IntoIterator<Item = Vec<u8>>) -> Self {
+    /// 设置服务端支持的应用层协议，按优先级排列。客户端提供的协议与之没有交集时，
+    /// 握手将以no_application_protocol(0x0178)失败。
+    pub fn with_alpn(mut self, alpn: impl IntoIterator<Item = impl AsRef<[u8]>>) -> Self {
+        let alpn = alpn.into_iter().map(|protocol| protocol.as_ref().to_vec());
         self.tls_config.alpn_protocols.extend(alpn);
         self
     }
 
-    pub fn listen(self) -> QuicServer {
-        for addr in &self.addresses {
+    /// 设置握手完成后，服务端向客户端颁发的会话票据(NewSessionTicket)数量，默认为2。
+    /// 设置为0则不颁发会话票据，客户端将无法恢复会话。
+    pub fn with_session_tickets(mut self, count: usize) -> Self {
+        self.tls_config.send_tls13_tickets = count;
+        self
+    }
+
+    /// 设置会话票据的加密器，它同时决定了会话票据的有效期。若不设置，则不能恢复会话。
+    /// 服务器集群若想跨节点恢复会话，需使用相同密钥的[`RotatingTicketer`]，并同步轮换密钥。
+    ///
+    /// [`RotatingTicketer`]: qconnection::tls::RotatingTicketer
+    pub fn with_ticketer(mut self, ticketer: Arc<dyn ProducesTickets>) -> Self {
+        self.tls_config.ticketer = ticketer;
+        self
+    }
+
+    /// 是否将TLS密钥按照NSS Key Log格式写入SSLKEYLOGFILE环境变量指定的文件，以便Wireshark解密抓包。
+    /// 若不设置，则只要设置了SSLKEYLOGFILE环境变量，就会写入。
+    pub fn with_sslkeylogfile(mut self, flag: bool) -> Self {
+        self.sslkeylogfile = Some(flag);
+        self
+    }
+
+    /// 开始监听，有主机定期刷新OCSP响应时，须在tokio运行时中调用，否则返回错误
+    pub fn listen(self) -> io::Result<QuicServer> {
+        for ocsp_refresh in self.ocsp_refresh {
+            ocsp_refresh.spawn()?;
+        }
+        let tls_policy =
+            TlsConfigPolicy::new(self.sslkeylogfile, &self.early_data, &self.tls_config);
+        let mut tls_config = Arc::new(self.tls_config);
+        tls_policy.apply(&mut tls_config);
+        for addr in self
+            .addresses
+            .iter()
+            .chain(&preferred_addresses(&self.parameters))
+        {
             if let Err(e) = get_or_create_usc(addr) {
                 log::error!("faild to listen on {addr}: {e}");
             }
@@ -416,13 +1335,21 @@ impl QuicServerSniBuilder<TlsServerConfig> {
             addresses: self.addresses,
             listener: Default::default(),
             _restrict: self.restrict,
-            _supported_versions: self.supported_versions,
+            supported_versions: self.supported_versions,
             _load_balance: self.load_balance,
-            _parameters: self.parameters,
-            tls_config: Arc::new(self.tls_config),
+            parameters: self.parameters,
+            tls_config: RwLock::new(tls_config),
+            tls_policy,
             token_provider: self.token_provider,
+            key_log: self.key_log,
+            sni_config: self.sni_config,
+            handshake_timeout: self.handshake_timeout,
+            cid_rotation: self.cid_rotation,
+            settings: self.settings,
+            early_data: self.early_data,
+            address_validation: self.address_validation,
         }));
         *SERVER.write().unwrap() = Some(quic_server.clone());
-        quic_server
+        Ok(quic_server)
     }
 }
@@ -9,11 +9,46 @@ use qbase::{
 };
 
 use super::{
-    reader::{DatagramReader, RawDatagramReader},
+    reader::{DatagramQueueLimits, DatagramReader, RawDatagramReader},
     writer::{DatagramWriter, RawDatagramWriter},
 };
 use crate::{DatagramIncoming, DatagramOutgoing};
 
+/// How the datagrams to send are scheduled against the stream data in a packet.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DatagramPriority {
+    /// The datagrams are sent once no more stream data can be sent, the default. The datagrams
+    /// may wait behind the bulk stream data for long.
+    #[default]
+    AfterStreams,
+    /// The datagrams are sent before any stream data, which suits the real-time datagrams. The
+    /// streams may be starved if the application sends the datagrams too fast.
+    BeforeStreams,
+    /// The datagrams and the stream frames are sent alternately.
+    Interleaved,
+}
+
+/// The statistics of the datagrams of a connection, see [`DatagramFlow::stats`].
+///
+/// The sizes are of the datagrams' payload, excluding the frame headers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DatagramStats {
+    /// The number of the datagrams sent in the packets.
+    pub sent: u64,
+    /// The bytes of the datagrams sent in the packets.
+    pub sent_bytes: u64,
+    /// The number of the datagrams received from the peer, including the dropped ones.
+    pub received: u64,
+    /// The bytes of the datagrams received from the peer, including the dropped ones.
+    pub received_bytes: u64,
+    /// The number of the datagrams received but dropped for the receive queue is full, see
+    /// [`DatagramQueueLimits`].
+    pub dropped_on_recv: u64,
+    /// The number of the datagrams never sent, for exceeding the peer's `max_datagram_frame_size`
+    /// or the TTL elapsed, see [`DatagramWriter::send_with_ttl`].
+    pub dropped_on_send: u64,
+}
+
 /// Combination of [`DatagramIncoming`] and [`DatagramOutgoing`]
 #[derive(Debug, Clone)]
 pub struct DatagramFlow {
@@ -21,6 +56,8 @@ pub struct DatagramFlow {
     incoming: DatagramIncoming,
     /// The outgoing datagram frame, see type's doc for more details.
     outgoing: DatagramOutgoing,
+    /// How the datagrams are scheduled against the stream data, see [`DatagramPriority`].
+    priority: Arc<Mutex<DatagramPriority>>,
 }
 
 impl DatagramFlow {
@@ -38,6 +75,7 @@ impl DatagramFlow {
         Self {
             incoming: DatagramIncoming(Arc::new(Mutex::new(Ok(reader)))),
             outgoing: DatagramOutgoing(Arc::new(Mutex::new(Ok(writer)))),
+            priority: Arc::default(),
         }
     }
 
@@ -47,6 +85,27 @@ impl DatagramFlow {
         self.outgoing.try_read_datagram(buf)
     }
 
+    /// See [`DatagramOutgoing::try_read_tracked_datagram`] for more details.
+    #[inline]
+    pub fn try_read_tracked_datagram(
+        &self,
+        buf: &mut [u8],
+    ) -> Option<(DatagramFrame, usize, Option<u64>)> {
+        self.outgoing.try_read_tracked_datagram(buf)
+    }
+
+    /// See [`DatagramOutgoing::on_datagram_acked`] for more details.
+    #[inline]
+    pub fn on_datagram_acked(&self, id: u64) {
+        self.outgoing.on_datagram_acked(id);
+    }
+
+    /// See [`DatagramOutgoing::on_datagram_lost`] for more details.
+    #[inline]
+    pub fn on_datagram_lost(&self, id: u64) {
+        self.outgoing.on_datagram_lost(id);
+    }
+
     /// Create a new **unique** instance of [`DatagramReader`].
     ///
     /// Return an error if the connection is closing or already closed, or there is already a reader exist.
@@ -57,6 +116,16 @@ impl DatagramFlow {
         self.incoming.new_reader()
     }
 
+    /// Create a new broadcast [`DatagramReader`], with its own queue bounded by the `limits`.
+    ///
+    /// Return an error if the connection is closing or already closed.
+    ///
+    /// See [`DatagramIncoming::subscribe`] for more details.
+    #[inline]
+    pub fn subscribe(&self, limits: DatagramQueueLimits) -> io::Result<DatagramReader> {
+        self.incoming.subscribe(limits)
+    }
+
     /// Create a new instance of [`DatagramWriter`].
     ///
     /// Return an error if the connection is closing or already closed,
@@ -67,6 +136,59 @@ impl DatagramFlow {
         self.outgoing.new_writer(max_datagram_frame_size)
     }
 
+    /// Create a new instance of [`DatagramWriter`] without waiting for the transport parameters of
+    /// the peer, if its `max_datagram_frame_size` is known already.
+    ///
+    /// See [`DatagramOutgoing::try_new_writer`] for more details.
+    #[inline]
+    pub fn try_writer(&self) -> io::Result<Option<DatagramWriter>> {
+        self.outgoing.try_new_writer()
+    }
+
+    /// Set the remote protocol parameter `max_datagram_frame_size` once the peer advertised it, see
+    /// [`DatagramOutgoing::set_max_frame_size`].
+    #[inline]
+    pub fn set_remote_max_frame_size(&self, max_datagram_frame_size: u64) {
+        self.outgoing.set_max_frame_size(max_datagram_frame_size);
+    }
+
+    /// Set the bounds of the datagrams received but not read yet, see [`DatagramIncoming::set_limits`].
+    #[inline]
+    pub fn set_queue_limits(&self, limits: DatagramQueueLimits) {
+        self.incoming.set_limits(limits);
+    }
+
+    /// Returns the bounds of the datagrams received but not read yet.
+    #[inline]
+    pub fn queue_limits(&self) -> DatagramQueueLimits {
+        self.incoming.limits()
+    }
+
+    /// Set how the datagrams to send are scheduled against the stream data, see [`DatagramPriority`].
+    #[inline]
+    pub fn set_priority(&self, priority: DatagramPriority) {
+        *self.priority.lock().unwrap() = priority;
+    }
+
+    /// Returns how the datagrams to send are scheduled against the stream data.
+    #[inline]
+    pub fn priority(&self) -> DatagramPriority {
+        *self.priority.lock().unwrap()
+    }
+
+    /// Returns the statistics of the datagrams sent and received, see [`DatagramStats`].
+    ///
+    /// The statistics are reset to the default once the connection is closing or closed.
+    pub fn stats(&self) -> DatagramStats {
+        let outgoing = self.outgoing.stats();
+        DatagramStats {
+            sent: outgoing.sent,
+            sent_bytes: outgoing.sent_bytes,
+            dropped_on_send: outgoing.dropped_on_send,
+            ..self.incoming.stats()
+        }
+    }
+
     /// See [`DatagramOutgoing::on_conn_error`] and [`DatagramIncoming::on_conn_error`] for more details.
     #[inline]
     pub fn on_conn_error(&self, error: &Error) {
@@ -87,3 +209,43 @@ impl ReceiveFrame<(DatagramFrame, bytes::Bytes)> for DatagramFlow {
         self.incoming.recv_datagram(frame, body.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_datagram_stats() {
+        let flow = DatagramFlow::new(1024);
+        let writer = flow.writer(16).unwrap();
+        writer.send_bytes(Bytes::from_static(b"hello")).unwrap();
+        // 超过对端的限制
+        assert!(writer.send_bytes(Bytes::from(vec![0; 16])).is_err());
+        let mut buffer = [0; 1024];
+        assert!(flow.try_read_datagram(&mut buffer).is_some());
+
+        let _reader = flow.reader().unwrap();
+        flow.set_queue_limits(DatagramQueueLimits {
+            max_datagrams: 1,
+            ..Default::default()
+        });
+        for data in [&b"a"[..], b"bc"] {
+            let frame = (DatagramFrame::new(None), Bytes::from_static(data));
+            flow.recv_frame(&frame).unwrap();
+        }
+
+        assert_eq!(
+            flow.stats(),
+            DatagramStats {
+                sent: 1,
+                sent_bytes: 5,
+                received: 2,
+                received_bytes: 3,
+                dropped_on_recv: 1,
+                dropped_on_send: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+/// The fate of a datagram sent by [`DatagramWriter::send_tracked`], reported by the
+/// [`DatagramHandle`].
+///
+/// [`DatagramWriter::send_tracked`]: crate::DatagramWriter::send_tracked
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DatagramFate {
+    /// The datagram is still in the internal queue.
+    #[default]
+    Queued,
+    /// The datagram has been sent in a packet, which is neither acknowledged nor declared lost yet.
+    Sent,
+    /// The packet containing the datagram has been acknowledged by the peer.
+    Acked,
+    /// The packet containing the datagram has been declared lost, the datagram is never
+    /// retransmitted, the application may send it again.
+    Lost,
+    /// The datagram was dropped before sent, for its TTL elapsed, being discarded, or the
+    /// connection error. The fate of the datagrams in flight when the connection error occurs is
+    /// unknown, they are reported as this too.
+    Dropped,
+}
+
+impl DatagramFate {
+    /// Returns whether the fate is final, that is, [`DatagramFate::Acked`], [`DatagramFate::Lost`]
+    /// or [`DatagramFate::Dropped`].
+    pub fn is_settled(&self) -> bool {
+        matches!(self, Self::Acked | Self::Lost | Self::Dropped)
+    }
+}
+
+#[derive(Debug, Default)]
+struct RawDatagramHandle {
+    fate: DatagramFate,
+    wakers: Vec<Waker>,
+}
+
+/// The completion handle of a datagram sent by [`DatagramWriter::send_tracked`].
+///
+/// The handle is a [`Future`] resolves with the final fate of the datagram, once the packet
+/// containing it is acknowledged or declared lost by the loss detection, so the application can
+/// retransmit the selected datagrams on its own. [`DatagramHandle::sent`] waits for the datagram
+/// to be sent in a packet, and [`DatagramHandle::fate`] reports the current fate.
+///
+/// Dropping the handle doesn't affect the datagram.
+///
+/// [`DatagramWriter::send_tracked`]: crate::DatagramWriter::send_tracked
+#[derive(Debug, Default, Clone)]
+pub struct DatagramHandle(Arc<Mutex<RawDatagramHandle>>);
+
+impl DatagramHandle {
+    /// Returns the current fate of the datagram.
+    pub fn fate(&self) -> DatagramFate {
+        self.0.lock().unwrap().fate
+    }
+
+    /// Waits until the datagram leaves the internal queue, resolves with [`DatagramFate::Sent`],
+    /// or the final fate if it has been settled already.
+    pub fn sent(&self) -> impl Future<Output = DatagramFate> + '_ {
+        core::future::poll_fn(|cx| self.poll_fate(cx, |fate| fate != DatagramFate::Queued))
+    }
+
+    fn poll_fate(
+        &self,
+        cx: &mut Context<'_>,
+        is_ready: impl Fn(DatagramFate) -> bool,
+    ) -> Poll<DatagramFate> {
+        let mut raw = self.0.lock().unwrap();
+        if is_ready(raw.fate) {
+            return Poll::Ready(raw.fate);
+        }
+        if !raw.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            raw.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    /// Updates the fate of the datagram, the settled fate is never changed.
+    pub(crate) fn update(&self, fate: DatagramFate) {
+        let mut raw = self.0.lock().unwrap();
+        if raw.fate.is_settled() {
+            return;
+        }
+        raw.fate = fate;
+        for waker in raw.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl Future for DatagramHandle {
+    type Output = DatagramFate;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.poll_fate(cx, |fate| fate.is_settled())
+    }
+}
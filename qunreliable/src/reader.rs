@@ -2,18 +2,62 @@ use std::{
     collections::VecDeque,
     future::Future,
     io,
-    ops::DerefMut,
+    ops::{Deref, DerefMut},
     pin::Pin,
     sync::{Arc, Mutex},
     task::{ready, Context, Poll, Waker},
 };
 
 use bytes::{BufMut, Bytes};
+use futures::Stream;
 use qbase::{
     error::{Error, ErrorKind},
     frame::{BeFrame, DatagramFrame},
 };
 
+use crate::DatagramStats;
+
+/// What to do with a datagram received when the queue of the [`DatagramReader`] is full, see
+/// [`DatagramQueueLimits`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DatagramOverflow {
+    /// Drop the oldest datagrams queued to make room for the new one, the default, which suits
+    /// the real-time data whose latest value matters most.
+    #[default]
+    DropOldest,
+    /// Drop the new datagram, the datagrams queued are kept.
+    DropNewest,
+    /// Drop the new datagram like [`DatagramOverflow::DropNewest`], and the next
+    /// [`DatagramReader::recv`] returns an error with the number of the datagrams dropped since the
+    /// last report, before the datagrams queued. The error is not fatal, the reader can go on.
+    Report,
+}
+
+/// The bounds of the datagrams received but not read by the application yet, so a fast sender
+/// can't exhaust the memory when the application reads slowly.
+///
+/// The datagrams are unreliable, so the datagrams beyond the bounds are dropped according to the
+/// [`DatagramOverflow`], see [`DatagramReader::dropped`] for the number of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatagramQueueLimits {
+    /// The most datagrams queued, 1024 by default.
+    pub max_datagrams: usize,
+    /// The most bytes of the datagrams queued, 1 MiB by default.
+    pub max_bytes: usize,
+    /// What to do with the datagrams beyond the bounds.
+    pub overflow: DatagramOverflow,
+}
+
+impl Default for DatagramQueueLimits {
+    fn default() -> Self {
+        Self {
+            max_datagrams: 1024,
+            max_bytes: 1 << 20,
+            overflow: DatagramOverflow::default(),
+        }
+    }
+}
+
 /// The [`RawDatagramReader`] struct represents a queue for receiving [`DatagramFrame`] frames from peer.
 ///
 /// The protocol layer will push the received datagrams into the internal FIFO queue or set the internal queue to an error state
@@ -32,6 +76,18 @@ pub struct RawDatagramReader {
     local_max_size: usize,
     /// The internal queue for caching the received datagrams.
     queue: VecDeque<Bytes>,
+    /// The bounds of the internal queue.
+    limits: DatagramQueueLimits,
+    /// The bytes of the datagrams in the internal queue.
+    queued_bytes: usize,
+    /// The number of the datagrams dropped for the queue is full.
+    dropped: u64,
+    /// The number and the bytes of the datagrams received, including the dropped ones.
+    received: u64,
+    received_bytes: u64,
+    /// The number of the datagrams dropped but not reported to the application yet, only with
+    /// [`DatagramOverflow::Report`].
+    unreported: u64,
     /// The waker for waking up the task that is waiting for the data to be read.
     ///
     /// When a datagram is received, the waker will be used to wake up the task.
@@ -40,6 +96,9 @@ pub struct RawDatagramReader {
     ///
     /// See [`DatagramReader`] for more.
     reader_exist: bool,
+    /// The queues of the broadcast readers, each of which receives a copy of every datagram, see
+    /// [`DatagramIncoming::subscribe`].
+    subscribers: Vec<ArcDatagramReader>,
 }
 
 impl RawDatagramReader {
@@ -47,9 +106,63 @@ impl RawDatagramReader {
         Self {
             local_max_size,
             queue: Default::default(),
+            limits: Default::default(),
+            queued_bytes: 0,
+            dropped: 0,
+            received: 0,
+            received_bytes: 0,
+            unreported: 0,
             waker: Default::default(),
             reader_exist: false,
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Push the datagram into the queue, and wake up the reader if necessary.
+    fn deliver(&mut self, data: Bytes) {
+        if self.push(data) {
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn is_full(&self, len: usize) -> bool {
+        self.queue.len() >= self.limits.max_datagrams
+            || self.queued_bytes + len > self.limits.max_bytes
+    }
+
+    /// Push the datagram into the queue, dropping the datagrams beyond the bounds.
+    ///
+    /// Returns whether the reader should be woken up.
+    fn push(&mut self, data: Bytes) -> bool {
+        while self.is_full(data.len()) {
+            match self.limits.overflow {
+                DatagramOverflow::DropOldest if !self.queue.is_empty() => {
+                    let oldest = self.queue.pop_front().unwrap();
+                    self.queued_bytes -= oldest.len();
+                    self.dropped += 1;
+                }
+                DatagramOverflow::Report => {
+                    self.dropped += 1;
+                    self.unreported += 1;
+                    return true;
+                }
+                _ => {
+                    self.dropped += 1;
+                    return false;
+                }
+            }
         }
+        self.queued_bytes += data.len();
+        self.queue.push_back(data);
+        true
+    }
+
+    fn pop(&mut self) -> Option<Bytes> {
+        let data = self.queue.pop_front()?;
+        self.queued_bytes -= data.len();
+        Some(data)
     }
 }
 
@@ -93,6 +206,8 @@ impl DatagramIncoming {
 
     /// Receives a datagram and pushes it into the internal FIFO queue for the application to read.
     ///
+    /// If the queue is full, the datagrams are dropped according to the [`DatagramQueueLimits`].
+    ///
     /// If the size of the received datagram exceeds the maximum size set by the local protocol parameters `max_datagram_frame_size`,
     /// a connection error occurs.
     ///
@@ -117,14 +232,83 @@ impl DatagramIncoming {
             ));
         }
 
-        reader.queue.push_back(data);
-        if let Some(waker) = reader.waker.take() {
-            waker.wake();
+        reader.received += 1;
+        reader.received_bytes += data.len() as u64;
+        // 广播给每个订阅者，已释放的订阅者被移除
+        reader
+            .subscribers
+            .retain(|subscriber| match subscriber.lock().unwrap().deref_mut() {
+                Ok(subscriber) if subscriber.reader_exist => {
+                    subscriber.deliver(data.clone());
+                    true
+                }
+                _ => false,
+            });
+        // 有订阅者时，仅在独占的读者存在时才为其缓存
+        if reader.reader_exist || reader.subscribers.is_empty() {
+            reader.deliver(data);
         }
 
         Ok(())
     }
 
+    /// Creates a new broadcast [`DatagramReader`], which receives a copy of every datagram received
+    /// after this call, in its own queue bounded by the `limits`.
+    ///
+    /// Unlike the unique reader created by [`DatagramIncoming::new_reader`], any number of the
+    /// broadcast readers can exist at the same time, and a slow one only drops the datagrams in its
+    /// own queue. Once there is a broadcast reader, the datagrams are queued for the unique reader
+    /// only while it exists.
+    ///
+    /// Returns an error when the connection is closing or already closed.
+    pub fn subscribe(&self, limits: DatagramQueueLimits) -> io::Result<DatagramReader> {
+        match self.0.lock().unwrap().deref_mut() {
+            Ok(raw) => {
+                let subscriber = RawDatagramReader {
+                    local_max_size: raw.local_max_size,
+                    limits,
+                    reader_exist: true,
+                    ..Default::default()
+                };
+                let subscriber = Arc::new(Mutex::new(Ok(subscriber)));
+                raw.subscribers.push(subscriber.clone());
+                Ok(DatagramReader(subscriber))
+            }
+            Err(e) => Err(io::Error::from(e.clone())),
+        }
+    }
+
+    /// Set the bounds of the datagrams queued, the datagrams already queued beyond the new bounds
+    /// are kept, see [`DatagramQueueLimits`].
+    pub fn set_limits(&self, limits: DatagramQueueLimits) {
+        if let Ok(reader) = self.0.lock().unwrap().deref_mut() {
+            reader.limits = limits;
+        }
+    }
+
+    /// Returns the bounds of the datagrams queued.
+    pub fn limits(&self) -> DatagramQueueLimits {
+        match self.0.lock().unwrap().deref() {
+            Ok(reader) => reader.limits,
+            Err(_) => DatagramQueueLimits::default(),
+        }
+    }
+
+    /// Returns the statistics of the datagrams received, the sending side is left default.
+    ///
+    /// Returns the default when the connection is closing or already closed.
+    pub fn stats(&self) -> DatagramStats {
+        match self.0.lock().unwrap().deref() {
+            Ok(reader) => DatagramStats {
+                received: reader.received,
+                received_bytes: reader.received_bytes,
+                dropped_on_recv: reader.dropped,
+                ..Default::default()
+            },
+            Err(_) => DatagramStats::default(),
+        }
+    }
+
     /// When a connection error occurs, the error will be set to the reader.
     ///
     /// Any subsequent calls to [`DatagramIncoming::new_reader`], [`DatagramReader::read`] and [`DatagramReader::read_buf`] will return an error.
@@ -139,6 +323,9 @@ impl DatagramIncoming {
             if let Some(waker) = reader.waker.take() {
                 waker.wake();
             }
+            for subscriber in reader.subscribers.drain(..) {
+                DatagramIncoming(subscriber).on_conn_error(error);
+            }
             *inner = Err(error.clone());
         }
     }
@@ -150,6 +337,9 @@ impl DatagramIncoming {
 /// Because the internal datagram queue is a mpsc queue, the reader (consumer) is unique, only one reader can exist at the same time.
 /// See [`DatagramIncoming::new_reader`] for more.
 ///
+/// To deliver every datagram to several consumers, create the broadcast readers by the
+/// [`DatagramIncoming::subscribe`] method, each of which has its own queue.
+///
 /// The application can read the received datagrams from the reader by calling the [`DatagramReader::read`] or [`DatagramReader::read_buf`] method.
 ///
 /// These methods are asynchronous, they return a future that resolves to the number of bytes read into the buffer.
@@ -169,12 +359,22 @@ impl DatagramReader {
     /// If the connection is closing or already closed, this method will return [`Poll::Ready`] with
     /// an error as [`Err`].
     ///
+    /// With [`DatagramOverflow::Report`], if some datagrams were dropped since the last call, this
+    /// method returns [`Poll::Ready`] with an error of [`io::ErrorKind::Other`] once, and the
+    /// datagrams queued are returned by the following calls.
+    ///
     /// If the datagram is not ready, and the connection is active,the method will return [`Poll::Pending`]
     /// and set the waker for waking up the task when the datagram is received.
     pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<io::Result<Bytes>> {
         let mut reader = self.0.lock().unwrap();
         match reader.deref_mut() {
-            Ok(reader) => match reader.queue.pop_front() {
+            Ok(reader) if reader.unreported > 0 => {
+                let dropped = std::mem::take(&mut reader.unreported);
+                Poll::Ready(Err(io::Error::other(format!(
+                    "{dropped} datagrams dropped for the receive queue is full"
+                ))))
+            }
+            Ok(reader) => match reader.pop() {
                 Some(bytes) => Poll::Ready(Ok(bytes)),
                 None => {
                     reader.waker = Some(cx.waker().clone());
@@ -185,6 +385,67 @@ impl DatagramReader {
         }
     }
 
+    /// The internal implementation of the [`DatagramReader::recv_batch`] method.
+    ///
+    /// Like [`DatagramReader::poll_recv`], but moves up to `max` datagrams received into `buf`
+    /// at once, returns the number of them. It's [`Poll::Pending`] only if no datagram is queued.
+    pub fn poll_recv_batch(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut Vec<Bytes>,
+        max: usize,
+    ) -> Poll<io::Result<usize>> {
+        let mut reader = self.0.lock().unwrap();
+        match reader.deref_mut() {
+            Ok(reader) if reader.unreported > 0 => {
+                let dropped = std::mem::take(&mut reader.unreported);
+                Poll::Ready(Err(io::Error::other(format!(
+                    "{dropped} datagrams dropped for the receive queue is full"
+                ))))
+            }
+            Ok(reader) if reader.queue.is_empty() && max > 0 => {
+                reader.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            Ok(reader) => {
+                let n = max.min(reader.queue.len());
+                buf.extend((0..n).filter_map(|_| reader.pop()));
+                Poll::Ready(Ok(n))
+            }
+            Err(e) => Poll::Ready(Err(io::Error::from(e.clone()))),
+        }
+    }
+
+    /// Receives up to `max` datagrams from peer at once, into `buf`, with the internal queue
+    /// locked once.
+    ///
+    /// This method is asynchronous and returns a future that resolves to the number of the
+    /// datagrams received, which are appended to `buf`.
+    ///
+    /// ``` rust, ignore
+    /// pub async fn recv_batch(&self, buf: &mut Vec<Bytes>, max: usize) -> io::Result<usize>
+    /// ```
+    ///
+    /// The future waits until at least one datagram is received, unless `max` is 0.
+    ///
+    /// If the connection is closing or already closed, the future will yield an error as [`Err`].
+    pub fn recv_batch<'b>(&'b mut self, buf: &'b mut Vec<Bytes>, max: usize) -> RecvBatch<'b> {
+        RecvBatch {
+            reader: self,
+            buf,
+            max,
+        }
+    }
+
+    /// Returns the number of the datagrams dropped for the receive queue is full, see
+    /// [`DatagramQueueLimits`].
+    pub fn dropped(&self) -> u64 {
+        match self.0.lock().unwrap().deref() {
+            Ok(reader) => reader.dropped,
+            Err(_) => 0,
+        }
+    }
+
     /// Try to receive a datagram from peer.
     ///
     /// This method is asynchronous and returns a future that resolves to the received datagram.
@@ -246,6 +507,31 @@ impl Drop for DatagramReader {
     }
 }
 
+/// The datagrams received, the stream ends once the connection is closing or closed.
+///
+/// Unlike [`DatagramReader::recv`], the datagrams dropped for the receive queue is full are not
+/// reported even with [`DatagramOverflow::Report`], see [`DatagramReader::dropped`] instead.
+impl Stream for DatagramReader {
+    type Item = Bytes;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut reader = self.0.lock().unwrap();
+        match reader.deref_mut() {
+            Ok(reader) => {
+                reader.unreported = 0;
+                match reader.pop() {
+                    Some(bytes) => Poll::Ready(Some(bytes)),
+                    None => {
+                        reader.waker = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            }
+            Err(_) => Poll::Ready(None),
+        }
+    }
+}
+
 /// The [`Future`] created by [`DatagramReader::recv`], see [`DatagramReader::recv`] for more.
 pub struct RecvDatagram<'a> {
     reader: &'a mut DatagramReader,
@@ -259,6 +545,22 @@ impl Future for RecvDatagram<'_> {
     }
 }
 
+/// The [`Future`] created by [`DatagramReader::recv_batch`], see [`DatagramReader::recv_batch`] for more.
+pub struct RecvBatch<'a> {
+    reader: &'a mut DatagramReader,
+    buf: &'a mut Vec<Bytes>,
+    max: usize,
+}
+
+impl Future for RecvBatch<'_> {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let s = self.get_mut();
+        s.reader.poll_recv_batch(cx, s.buf, s.max)
+    }
+}
+
 /// the [`Future`] created by [`DatagramReader::read`], see [`DatagramReader::read`] for more.
 pub struct ReadIntoSlice<'a> {
     reader: &'a mut DatagramReader,
@@ -342,4 +644,151 @@ mod tests {
         assert!(new_reader.is_err());
         assert_eq!(new_reader.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
     }
+
+    #[tokio::test]
+    async fn test_datagram_reader_recv_batch() {
+        let incoming = DatagramIncoming(Arc::new(Mutex::new(Ok(RawDatagramReader::new(1024)))));
+        let mut reader = incoming.new_reader().unwrap();
+        for data in [b"a", b"b", b"c"] {
+            let frame = DatagramFrame::new(None);
+            incoming
+                .recv_datagram(&frame, Bytes::from_static(data))
+                .unwrap();
+        }
+
+        let mut buf = vec![];
+        assert_eq!(reader.recv_batch(&mut buf, 2).await.unwrap(), 2);
+        assert_eq!(reader.recv_batch(&mut buf, 2).await.unwrap(), 1);
+        assert_eq!(buf, [Bytes::from("a"), Bytes::from("b"), Bytes::from("c")]);
+        assert_eq!(reader.recv_batch(&mut buf, 0).await.unwrap(), 0);
+
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert!(reader.poll_recv_batch(&mut cx, &mut buf, 2).is_pending());
+    }
+
+    #[tokio::test]
+    async fn test_datagram_reader_stream() {
+        use futures::StreamExt;
+
+        let incoming = DatagramIncoming(Arc::new(Mutex::new(Ok(RawDatagramReader::new(1024)))));
+        let reader = incoming.new_reader().unwrap();
+        for data in [b"a", b"b"] {
+            let frame = DatagramFrame::new(None);
+            incoming
+                .recv_datagram(&frame, Bytes::from_static(data))
+                .unwrap();
+        }
+        incoming.on_conn_error(&Error::new(
+            ErrorKind::ProtocolViolation,
+            FrameType::Datagram(0),
+            "protocol violation",
+        ));
+        // 连接出错后，流结束，未读的数据被丢弃
+        assert_eq!(reader.collect::<Vec<_>>().await, Vec::<Bytes>::new());
+
+        let incoming = DatagramIncoming(Arc::new(Mutex::new(Ok(RawDatagramReader::new(1024)))));
+        let mut reader = incoming.new_reader().unwrap();
+        let frame = DatagramFrame::new(None);
+        incoming
+            .recv_datagram(&frame, Bytes::from_static(b"a"))
+            .unwrap();
+        assert_eq!(reader.next().await, Some(Bytes::from("a")));
+    }
+
+    #[test]
+    fn test_datagram_queue_limits() {
+        let incoming = DatagramIncoming(Arc::new(Mutex::new(Ok(RawDatagramReader::new(1024)))));
+        let reader = incoming.new_reader().unwrap();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut recv = |datagrams: &[&'static [u8]], overflow| {
+            incoming.set_limits(DatagramQueueLimits {
+                max_datagrams: 2,
+                max_bytes: 8,
+                overflow,
+            });
+            for data in datagrams {
+                let frame = DatagramFrame::new(None);
+                incoming
+                    .recv_datagram(&frame, Bytes::from_static(data))
+                    .unwrap();
+            }
+            let mut received = vec![];
+            while let Poll::Ready(result) = reader.poll_recv(&mut cx) {
+                received.push(result.map_err(|e| e.kind()));
+            }
+            received
+        };
+
+        // 受数量的限制，丢弃最早的
+        let received = recv(&[b"a", b"b", b"c"], DatagramOverflow::DropOldest);
+        assert_eq!(received, [Ok(Bytes::from("b")), Ok(Bytes::from("c"))]);
+        // 受字节数的限制，丢弃最新的
+        let received = recv(&[b"hello", b"world"], DatagramOverflow::DropNewest);
+        assert_eq!(received, [Ok(Bytes::from("hello"))]);
+        // 丢弃最新的，并报告给应用
+        let received = recv(&[b"a", b"b", b"c", b"d"], DatagramOverflow::Report);
+        assert_eq!(
+            received,
+            [
+                Err(io::ErrorKind::Other),
+                Ok(Bytes::from("a")),
+                Ok(Bytes::from("b"))
+            ]
+        );
+        assert_eq!(reader.dropped(), 4);
+    }
+
+    #[test]
+    fn test_datagram_subscribe() {
+        let incoming = DatagramIncoming(Arc::new(Mutex::new(Ok(RawDatagramReader::new(1024)))));
+        let limits = DatagramQueueLimits {
+            max_datagrams: 1,
+            ..Default::default()
+        };
+        let fast = incoming.subscribe(Default::default()).unwrap();
+        let slow = incoming.subscribe(limits).unwrap();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let recv = |data: &'static [u8]| {
+            let frame = DatagramFrame::new(None);
+            incoming
+                .recv_datagram(&frame, Bytes::from_static(data))
+                .unwrap();
+        };
+
+        recv(b"a");
+        recv(b"b");
+        // 每个订阅者各自收到全部数据报，慢的只丢弃自己队列中的
+        for data in [b"a", b"b"] {
+            let result = fast.poll_recv(&mut cx).map(Result::unwrap);
+            assert_eq!(result, Poll::Ready(Bytes::from_static(data)));
+        }
+        let result = slow.poll_recv(&mut cx).map(Result::unwrap);
+        assert_eq!(result, Poll::Ready(Bytes::from_static(b"b")));
+        assert_eq!(slow.dropped(), 1);
+
+        // 有订阅者时，不为尚不存在的独占读者缓存
+        drop(slow);
+        recv(b"c");
+        assert_eq!(
+            incoming
+                .0
+                .lock()
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .subscribers
+                .len(),
+            1
+        );
+        let reader = incoming.new_reader().unwrap();
+        assert!(reader.poll_recv(&mut cx).is_pending());
+
+        incoming.on_conn_error(&Error::new(
+            ErrorKind::ProtocolViolation,
+            FrameType::Datagram(0),
+            "test",
+        ));
+        assert!(fast.poll_recv(&mut cx).is_ready());
+        assert!(incoming.subscribe(limits).is_err());
+    }
 }
@@ -1,17 +1,24 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
+    future::Future,
     io,
-    ops::DerefMut,
+    ops::{Deref, DerefMut},
+    pin::Pin,
     sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
 };
 
 use bytes::Bytes;
+use futures::Sink;
 use qbase::{
     error::Error,
     frame::{io::WriteDataFrame, BeFrame, DatagramFrame},
     varint::VarInt,
 };
 
+use crate::{DatagramFate, DatagramHandle, DatagramStats};
+
 /// The [`RawDatagramWriter`] struct represents a queue for sending [`DatagramFrame`].
 ///
 /// The protocol layer will read the datagram from the queue and send it to the peer, or set the internal queue to an error state
@@ -23,15 +30,104 @@ use qbase::{
 /// [`DatagramWriter`] is created by [`DatagramOutgoing::new_writer`], and they share the same [`RawDatagramWriter`](wrapped in [`ArcDatagramWriter`]).
 #[derive(Debug)]
 pub struct RawDatagramWriter {
-    /// The queue for storing the datagram frame to send.
-    queue: VecDeque<Bytes>,
+    /// The queue for storing the datagram frame to send, with the time after which the datagram is
+    /// dropped instead of sent, see [`DatagramWriter::send_with_ttl`], and the handle to report
+    /// its fate, see [`DatagramWriter::send_tracked`].
+    queue: VecDeque<(Bytes, Option<Instant>, Option<DatagramHandle>)>,
+    /// The tracked datagrams sent in the packets, which are neither acknowledged nor declared lost.
+    in_flight: HashMap<u64, DatagramHandle>,
+    /// The id of the next tracked datagram sent.
+    next_id: u64,
+    /// The number of the datagrams dropped for expired before sent.
+    expired: u64,
+    /// The number of the datagrams rejected for exceeding the size limit.
+    oversized: u64,
+    /// The number and the bytes of the datagrams sent.
+    sent: u64,
+    sent_bytes: u64,
+    /// The [`DatagramWriter`]s waiting for the queue to drain, or for the size limit.
+    wakers: Vec<Waker>,
+    /// The maximum size of the datagram frame that can be sent to the peer, [`None`] until the
+    /// peer has advertised the transport parameter.
+    ///
+    /// See [RFC](https://www.rfc-editor.org/rfc/rfc9221.html#name-transport-parameter) for more details.
+    max_frame_size: Option<usize>,
 }
 
+/// The most datagrams queued before [`DatagramWriter::poll_send_ready`] is pending.
+const SEND_QUEUE_LIMIT: usize = 256;
+
 impl RawDatagramWriter {
     pub(crate) fn new() -> Self {
         Self {
             queue: Default::default(),
+            in_flight: HashMap::new(),
+            next_id: 0,
+            expired: 0,
+            oversized: 0,
+            sent: 0,
+            sent_bytes: 0,
+            wakers: Vec::new(),
+            max_frame_size: None,
+        }
+    }
+
+    fn check_size(&mut self, len: usize) -> io::Result<()> {
+        match self.max_frame_size {
+            None => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "the peer hasn't advertised max_datagram_frame_size yet",
+            )),
+            // Only consider the smallest encoding method: 1 byte
+            Some(max_frame_size) if (1 + len) > max_frame_size => {
+                self.oversized += 1;
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "datagram frame size exceeds the limit",
+                ))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn wake_all(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn register(&mut self, cx: &mut Context<'_>) {
+        if !self.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            self.wakers.push(cx.waker().clone());
+        }
+    }
+
+    /// Returns the first datagram to send, the expired ones before it are dropped.
+    fn front(&mut self, now: Instant) -> Option<&Bytes> {
+        while let Some((_, Some(deadline), _)) = self.queue.front() {
+            if *deadline > now {
+                break;
+            }
+            let (_, _, handle) = self.queue.pop_front().unwrap();
+            if let Some(handle) = handle {
+                handle.update(DatagramFate::Dropped);
+            }
+            self.expired += 1;
+            self.wake_all();
+        }
+        self.queue.front().map(|(data, ..)| data)
+    }
+
+    /// Drops all the datagrams queued, returns the number of them.
+    fn discard(&mut self) -> usize {
+        let discarded = self.queue.len();
+        for (_, _, handle) in self.queue.drain(..) {
+            if let Some(handle) = handle {
+                handle.update(DatagramFate::Dropped);
+            }
         }
+        self.wake_all();
+        discarded
     }
 }
 
@@ -45,7 +141,8 @@ pub struct DatagramOutgoing(pub(crate) ArcDatagramWriter);
 impl DatagramOutgoing {
     /// Creates a new instance of [`DatagramWriter`].
     ///
-    /// This method takes the remote protocol parameters `max_datagram_frame_size`.
+    /// This method takes the remote protocol parameters `max_datagram_frame_size`, see
+    /// [`DatagramOutgoing::set_max_frame_size`].
     ///
     /// Returns an error when the connection is closing or already closed.
     ///
@@ -53,15 +150,40 @@ impl DatagramOutgoing {
     ///
     /// [`DatagramReader`]: crate::reader::DatagramReader
     pub fn new_writer(&self, max_datagram_frame_size: u64) -> io::Result<DatagramWriter> {
+        self.set_max_frame_size(max_datagram_frame_size);
         match self.0.lock().unwrap().deref_mut() {
             Ok(..) => Ok(DatagramWriter {
                 writer: self.0.clone(),
-                max_datagram_frame_size: max_datagram_frame_size as _,
             }),
             Err(e) => Err(io::Error::from(e.clone())),
         }
     }
 
+    /// Creates a new instance of [`DatagramWriter`] if the `max_datagram_frame_size` of the peer
+    /// is known already, such as the one remembered from the previous connection to send the
+    /// datagrams in the 0-RTT packets, see [`DatagramOutgoing::set_max_frame_size`].
+    ///
+    /// Returns [`None`] if the size is unknown yet, or an error when the connection is closing or
+    /// already closed.
+    pub fn try_new_writer(&self) -> io::Result<Option<DatagramWriter>> {
+        match self.0.lock().unwrap().deref_mut() {
+            Ok(writer) if writer.max_frame_size.is_none() => Ok(None),
+            Ok(..) => Ok(Some(DatagramWriter {
+                writer: self.0.clone(),
+            })),
+            Err(e) => Err(io::Error::from(e.clone())),
+        }
+    }
+
+    /// Set the remote protocol parameters `max_datagram_frame_size` once the peer advertised it,
+    /// the [`DatagramWriter`]s waiting for it in [`DatagramWriter::poll_send_ready`] are woken up.
+    pub fn set_max_frame_size(&self, max_datagram_frame_size: u64) {
+        if let Ok(writer) = self.0.lock().unwrap().deref_mut() {
+            writer.max_frame_size = Some(max_datagram_frame_size as _);
+            writer.wake_all();
+        }
+    }
+
     /// Attempts to encode the datagram frame into the buffer.
     ///
     /// If the datagram frame is successfully encoded, the method will return the datagram frame and the number of bytes written to the buffer.
@@ -71,6 +193,8 @@ impl DatagramOutgoing {
     ///
     /// If the internal queue is empty (no [`DatagramFrame`] needs to be sent), the method will return [`None`].
     ///
+    /// The datagrams whose TTL has elapsed are dropped silently, see [`DatagramWriter::send_with_ttl`].
+    ///
     /// # Encoding
     ///
     /// [`DatagramFrame`] has two types:
@@ -90,10 +214,27 @@ impl DatagramOutgoing {
     /// If the buffer is not enough to encode the length, it will encode the [`DatagramFrame`] without the data's length (frame type `0x30`).
     /// Because no frame can be put after the datagram frame without length, this method will put padding frames before to fill the buffer.
     /// In this case, the buffer will be filled.
-    pub fn try_read_datagram(&self, mut buf: &mut [u8]) -> Option<(DatagramFrame, usize)> {
+    ///
+    /// The tracked datagrams are reported [`DatagramFate::Sent`] but never settled, the protocol
+    /// layer should call [`DatagramOutgoing::try_read_tracked_datagram`] instead.
+    pub fn try_read_datagram(&self, buf: &mut [u8]) -> Option<(DatagramFrame, usize)> {
+        self.try_read_tracked_datagram(buf)
+            .map(|(frame, written, _)| (frame, written))
+    }
+
+    /// Like [`DatagramOutgoing::try_read_datagram`], and returns the id of the datagram if it's
+    /// sent by [`DatagramWriter::send_tracked`].
+    ///
+    /// The id should be recorded with the packet containing the datagram, and be reported by
+    /// [`DatagramOutgoing::on_datagram_acked`] or [`DatagramOutgoing::on_datagram_lost`] once the
+    /// fate of the packet is known.
+    pub fn try_read_tracked_datagram(
+        &self,
+        mut buf: &mut [u8],
+    ) -> Option<(DatagramFrame, usize, Option<u64>)> {
         let mut guard = self.0.lock().unwrap();
         let writer = guard.as_mut().ok()?;
-        let datagram = writer.queue.front()?;
+        let datagram = writer.front(Instant::now())?;
 
         let available = buf.len();
 
@@ -102,7 +243,17 @@ impl DatagramOutgoing {
             return None;
         }
 
-        let datagram = writer.queue.pop_front()?;
+        let (datagram, _, handle) = writer.queue.pop_front()?;
+        writer.wake_all();
+        writer.sent += 1;
+        writer.sent_bytes += datagram.len() as u64;
+        let id = handle.map(|handle| {
+            let id = writer.next_id;
+            writer.next_id += 1;
+            handle.update(DatagramFate::Sent);
+            writer.in_flight.insert(id, handle);
+            id
+        });
         let frame_without_len = DatagramFrame::new(None);
         let frame_with_len = DatagramFrame::new(Some(VarInt::try_from(datagram.len()).unwrap()));
         match max_encoding_size {
@@ -110,7 +261,7 @@ impl DatagramOutgoing {
             n if n >= frame_with_len.encoding_size() => {
                 buf.put_data_frame(&frame_with_len, &datagram);
                 let written = frame_with_len.encoding_size() + datagram.len();
-                Some((frame_with_len, written))
+                Some((frame_with_len, written, id))
             }
             // Do not encode length, may need padding
             n => {
@@ -118,7 +269,40 @@ impl DatagramOutgoing {
                 buf = &mut buf[n - frame_without_len.encoding_size()..];
                 buf.put_data_frame(&frame_without_len, &datagram);
                 let written = n + datagram.len();
-                Some((frame_without_len, written))
+                Some((frame_without_len, written, id))
+            }
+        }
+    }
+
+    /// Returns the statistics of the datagrams sent, the receiving side is left default.
+    ///
+    /// Returns the default when the connection is closing or already closed.
+    pub fn stats(&self) -> DatagramStats {
+        match self.0.lock().unwrap().deref() {
+            Ok(writer) => DatagramStats {
+                sent: writer.sent,
+                sent_bytes: writer.sent_bytes,
+                dropped_on_send: writer.oversized + writer.expired,
+                ..Default::default()
+            },
+            Err(_) => DatagramStats::default(),
+        }
+    }
+
+    /// Called when the packet containing the tracked datagram `id` is acknowledged.
+    pub fn on_datagram_acked(&self, id: u64) {
+        self.settle(id, DatagramFate::Acked);
+    }
+
+    /// Called when the packet containing the tracked datagram `id` is declared lost.
+    pub fn on_datagram_lost(&self, id: u64) {
+        self.settle(id, DatagramFate::Lost);
+    }
+
+    fn settle(&self, id: u64, fate: DatagramFate) {
+        if let Ok(writer) = self.0.lock().unwrap().deref_mut() {
+            if let Some(handle) = writer.in_flight.remove(&id) {
+                handle.update(fate);
             }
         }
     }
@@ -127,10 +311,15 @@ impl DatagramOutgoing {
     ///
     /// Any subsequent calls to [`DatagramWriter::send`] or [`DatagramWriter::send_bytes`] will return an error.
     ///
-    /// All datagrams in the internal queue will be dropped.
+    /// All datagrams in the internal queue will be dropped, and the tracked datagrams are reported
+    /// [`DatagramFate::Dropped`].
     pub fn on_conn_error(&self, error: &Error) {
         let writer = &mut self.0.lock().unwrap();
-        if writer.is_ok() {
+        if let Ok(raw) = writer.as_mut() {
+            raw.discard();
+            for (_, handle) in raw.in_flight.drain() {
+                handle.update(DatagramFate::Dropped);
+            }
             **writer = Err(error.clone());
         }
     }
@@ -139,14 +328,6 @@ impl DatagramOutgoing {
 #[derive(Debug, Clone)]
 pub struct DatagramWriter {
     writer: ArcDatagramWriter,
-    /// The maximum size of the datagram frame that can be sent to the peer.
-    ///
-    /// The value is set by the remote peer, and the protocol layer will use this value to limit the size of the datagram frame.
-    ///
-    /// If the size of the datagram frame exceeds this value, the protocol layer will return an error.
-    ///
-    /// See [RFC](https://www.rfc-editor.org/rfc/rfc9221.html#name-transport-parameter) for more details.
-    max_datagram_frame_size: usize,
 }
 
 impl DatagramWriter {
@@ -158,16 +339,85 @@ impl DatagramWriter {
     /// Returns [`Ok`] when the data is successfully pushed into the internal queue.
     /// Returns [`Err`] when the connection is closing or already closed.
     pub fn send_bytes(&self, data: Bytes) -> io::Result<()> {
+        self.push(data, None, None)
+    }
+
+    /// Send a batch of datagrams to the peer, with the internal queue locked once.
+    ///
+    /// The datagrams are pushed into the internal queue in order, either all of them or none, so
+    /// the protocol layer can coalesce them into the packets when sending.
+    ///
+    /// Returns [`Err`] if any of the datagrams exceeds the limit, or when the connection is closing
+    /// or already closed.
+    pub fn send_batch(&self, datagrams: &[Bytes]) -> io::Result<()> {
         match self.writer.lock().unwrap().deref_mut() {
             Ok(writer) => {
-                // Only consider the smallest encoding method: 1 byte
-                if (1 + data.len()) > self.max_datagram_frame_size {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "datagram frame size exceeds the limit",
-                    ));
+                for data in datagrams {
+                    writer.check_size(data.len())?;
                 }
-                writer.queue.push_back(data.clone());
+                let datagrams = datagrams.iter().map(|data| (data.clone(), None, None));
+                writer.queue.extend(datagrams);
+                Ok(())
+            }
+            Err(e) => Err(io::Error::from(e.clone())),
+        }
+    }
+
+    /// Send bytes to the peer, unless they are not sent within the `ttl`.
+    ///
+    /// Like [`DatagramWriter::send_bytes`], the data is pushed into the internal queue, but if the
+    /// data is still in the queue after the `ttl`, for the congestion or the datagrams queued
+    /// before, it's dropped silently rather than sent, which suits the real-time data whose stale
+    /// value is worthless. See [`DatagramWriter::expired`] for the number of the datagrams dropped.
+    ///
+    /// Returns [`Err`] when the connection is closing or already closed.
+    pub fn send_with_ttl(&self, data: Bytes, ttl: Duration) -> io::Result<()> {
+        self.push(data, Some(Instant::now() + ttl), None)
+    }
+
+    /// Send bytes to the peer, and returns a [`DatagramHandle`] which reports whether the datagram
+    /// is sent in a packet, and whether the packet is acknowledged or lost.
+    ///
+    /// Like the other datagrams, the tracked datagram is never retransmitted by the protocol layer,
+    /// the application can retransmit it on its own once it's reported [`DatagramFate::Lost`].
+    ///
+    /// Returns [`Err`] when the connection is closing or already closed.
+    pub fn send_tracked(&self, data: Bytes) -> io::Result<DatagramHandle> {
+        let handle = DatagramHandle::default();
+        self.push(data, None, Some(handle.clone()))?;
+        Ok(handle)
+    }
+
+    /// Drops all the datagrams queued but not sent yet, by all the [`DatagramWriter`]s of the
+    /// connection, returns the number of them.
+    ///
+    /// Returns [`Err`] when the connection is closing or already closed.
+    pub fn discard_queued(&self) -> io::Result<usize> {
+        match self.writer.lock().unwrap().deref_mut() {
+            Ok(writer) => Ok(writer.discard()),
+            Err(e) => Err(io::Error::from(e.clone())),
+        }
+    }
+
+    /// Returns the number of the datagrams dropped for their TTL elapsed before sent, see
+    /// [`DatagramWriter::send_with_ttl`].
+    pub fn expired(&self) -> u64 {
+        match self.writer.lock().unwrap().deref_mut() {
+            Ok(writer) => writer.expired,
+            Err(_) => 0,
+        }
+    }
+
+    fn push(
+        &self,
+        data: Bytes,
+        deadline: Option<Instant>,
+        handle: Option<DatagramHandle>,
+    ) -> io::Result<()> {
+        match self.writer.lock().unwrap().deref_mut() {
+            Ok(writer) => {
+                writer.check_size(data.len())?;
+                writer.queue.push_back((data, deadline, handle));
                 Ok(())
             }
             Err(e) => Err(io::Error::from(e.clone())),
@@ -186,14 +436,83 @@ impl DatagramWriter {
     }
 
     /// Returns the maximum size of the datagram frame that can be sent to the peer.
-    /// Returns an error when the connection is closing or already closed.
+    /// Returns an error when the connection is closing or already closed, or the peer hasn't
+    /// advertised the size yet.
     pub fn max_datagram_frame_size(&self) -> io::Result<usize> {
         match self.writer.lock().unwrap().deref_mut() {
-            Ok(..) => Ok(self.max_datagram_frame_size),
+            Ok(writer) => writer.max_frame_size.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "the peer hasn't advertised max_datagram_frame_size yet",
+                )
+            }),
             Err(e) => Err(io::Error::from(e.clone())),
         }
     }
+
+    /// Polls whether a datagram can be sent now.
+    ///
+    /// Returns [`Poll::Pending`] while the peer hasn't advertised its `max_datagram_frame_size`,
+    /// or 256 datagrams are queued but not sent yet, and the task is woken up once the datagrams
+    /// can be sent. The datagrams sent without waiting for this are still queued.
+    ///
+    /// Returns [`Err`] if the peer doesn't support the datagrams, that is, its
+    /// `max_datagram_frame_size` is 0, or when the connection is closing or already closed.
+    pub fn poll_send_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.writer.lock().unwrap().deref_mut() {
+            Ok(writer) => match writer.max_frame_size {
+                Some(0) => Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "the peer doesn't support the datagrams",
+                ))),
+                Some(_) if writer.queue.len() < SEND_QUEUE_LIMIT => Poll::Ready(Ok(())),
+                _ => {
+                    writer.register(cx);
+                    Poll::Pending
+                }
+            },
+            Err(e) => Poll::Ready(Err(io::Error::from(e.clone()))),
+        }
+    }
+
+    /// Waits until a datagram can be sent, see [`DatagramWriter::poll_send_ready`].
+    pub fn send_ready(&self) -> impl Future<Output = io::Result<()>> + '_ {
+        core::future::poll_fn(|cx| self.poll_send_ready(cx))
+    }
+}
+/// The datagrams are pushed into the internal queue like [`DatagramWriter::send_bytes`], but the
+/// sink is ready only if [`DatagramWriter::poll_send_ready`] is, so an unbounded producer is paced
+/// by the connection.
+///
+/// The sink is flushed once all the datagrams queued, by all the [`DatagramWriter`]s of the
+/// connection, have been sent. Closing the sink just flushes it.
+impl Sink<Bytes> for DatagramWriter {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_send_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        self.send_bytes(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.writer.lock().unwrap().deref_mut() {
+            Ok(writer) if !writer.queue.is_empty() => {
+                writer.register(cx);
+                Poll::Pending
+            }
+            Ok(_) => Poll::Ready(Ok(())),
+            Err(e) => Poll::Ready(Err(io::Error::from(e.clone()))),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
 }
+
 #[cfg(test)]
 mod tests {
 
@@ -304,6 +623,150 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_datagram_writer_send_batch() {
+        let writer = Arc::new(Mutex::new(Ok(RawDatagramWriter::new())));
+        let outgoing = DatagramOutgoing(writer);
+        let writer = outgoing.new_writer(8).unwrap();
+
+        let too_large = [Bytes::from_static(b"a"), Bytes::from_static(b"hello world")];
+        assert!(writer.send_batch(&too_large).is_err());
+
+        let batch = [Bytes::from_static(b"a"), Bytes::from_static(b"bc")];
+        writer.send_batch(&batch).unwrap();
+        // 多个datagram合并到一个包中
+        let mut buffer = [0; 1024];
+        let (_, n) = outgoing.try_read_datagram(&mut buffer).unwrap();
+        assert_eq!(&buffer[..n], &[0x31, 1, b'a']);
+        let (_, n) = outgoing.try_read_datagram(&mut buffer).unwrap();
+        assert_eq!(&buffer[..n], &[0x31, 2, b'b', b'c']);
+        assert!(outgoing.try_read_datagram(&mut buffer).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_datagram_writer_sink() {
+        use futures::SinkExt;
+
+        let writer = Arc::new(Mutex::new(Ok(RawDatagramWriter::new())));
+        let outgoing = DatagramOutgoing(writer);
+        let mut writer = outgoing.new_writer(1024).unwrap();
+
+        for _ in 0..SEND_QUEUE_LIMIT {
+            writer.feed(Bytes::from_static(b"hello")).await.unwrap();
+        }
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert!(Pin::new(&mut writer).poll_ready(&mut cx).is_pending());
+        assert!(Pin::new(&mut writer).poll_flush(&mut cx).is_pending());
+
+        // 发送一个之后即可继续写入
+        let mut buffer = [0; 1024];
+        outgoing.try_read_datagram(&mut buffer).unwrap();
+        assert!(Pin::new(&mut writer).poll_ready(&mut cx).is_ready());
+        while outgoing.try_read_datagram(&mut buffer).is_some() {}
+        writer.flush().await.unwrap();
+    }
+
+    #[test]
+    fn test_datagram_writer_ttl() {
+        let writer = Arc::new(Mutex::new(Ok(RawDatagramWriter::new())));
+        let outgoing = DatagramOutgoing(writer);
+        let writer = outgoing.new_writer(1024).unwrap();
+
+        writer
+            .send_with_ttl(Bytes::from_static(b"stale"), Duration::ZERO)
+            .unwrap();
+        writer.send_bytes(Bytes::from_static(b"hello")).unwrap();
+        writer
+            .send_with_ttl(Bytes::from_static(b"fresh"), Duration::from_secs(60))
+            .unwrap();
+
+        // 过期的datagram在发送时被丢弃
+        let mut buffer = [0; 1024];
+        let (_, written) = outgoing.try_read_datagram(&mut buffer).unwrap();
+        assert_eq!(&buffer[2..written], b"hello");
+        assert_eq!(writer.expired(), 1);
+        let (_, written) = outgoing.try_read_datagram(&mut buffer).unwrap();
+        assert_eq!(&buffer[2..written], b"fresh");
+
+        writer.send_bytes(Bytes::from_static(b"hello")).unwrap();
+        assert_eq!(writer.discard_queued().unwrap(), 1);
+        assert!(outgoing.try_read_datagram(&mut buffer).is_none());
+    }
+
+    #[test]
+    fn test_datagram_writer_send_ready() {
+        let writer = Arc::new(Mutex::new(Ok(RawDatagramWriter::new())));
+        let outgoing = DatagramOutgoing(writer);
+        let writer = DatagramWriter {
+            writer: outgoing.0.clone(),
+        };
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+
+        // 对方还未通告max_datagram_frame_size
+        assert!(writer.poll_send_ready(&mut cx).is_pending());
+        assert_eq!(
+            writer.send(b"hello").unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        outgoing.set_max_frame_size(1024);
+        assert!(writer.poll_send_ready(&mut cx).is_ready());
+
+        // 对方不支持datagram
+        outgoing.set_max_frame_size(0);
+        let ready = writer.poll_send_ready(&mut cx);
+        assert!(matches!(ready, Poll::Ready(Err(e)) if e.kind() == io::ErrorKind::Unsupported));
+    }
+
+    #[tokio::test]
+    async fn test_datagram_writer_tracked() {
+        let writer = Arc::new(Mutex::new(Ok(RawDatagramWriter::new())));
+        let outgoing = DatagramOutgoing(writer);
+        let writer = outgoing.new_writer(1024).unwrap();
+
+        writer.send_bytes(Bytes::from_static(b"untracked")).unwrap();
+        let acked = writer.send_tracked(Bytes::from_static(b"acked")).unwrap();
+        let lost = writer.send_tracked(Bytes::from_static(b"lost")).unwrap();
+        let dropped = writer.send_tracked(Bytes::from_static(b"dropped")).unwrap();
+        assert_eq!(acked.fate(), DatagramFate::Queued);
+
+        let mut buffer = [0; 1024];
+        let (.., id) = outgoing.try_read_tracked_datagram(&mut buffer).unwrap();
+        assert_eq!(id, None);
+        let (.., acked_id) = outgoing.try_read_tracked_datagram(&mut buffer).unwrap();
+        let (.., lost_id) = outgoing.try_read_tracked_datagram(&mut buffer).unwrap();
+        assert_eq!(acked.fate(), DatagramFate::Sent);
+        assert_eq!(lost.fate(), DatagramFate::Sent);
+
+        outgoing.on_datagram_acked(acked_id.unwrap());
+        outgoing.on_datagram_lost(lost_id.unwrap());
+        // 已确定的结果不再改变
+        outgoing.on_datagram_acked(lost_id.unwrap());
+        assert_eq!(acked.await, DatagramFate::Acked);
+        assert_eq!(lost.await, DatagramFate::Lost);
+
+        // 连接出错时，队列中的数据报被丢弃
+        outgoing.on_conn_error(&Error::new(
+            ErrorKind::ProtocolViolation,
+            FrameType::Datagram(0),
+            "test",
+        ));
+        assert_eq!(dropped.await, DatagramFate::Dropped);
+    }
+
+    #[test]
+    fn test_datagram_writer_early() {
+        let writer = Arc::new(Mutex::new(Ok(RawDatagramWriter::new())));
+        let outgoing = DatagramOutgoing(writer);
+        assert!(outgoing.try_new_writer().unwrap().is_none());
+
+        // 依据上次连接记住的参数，握手完成前即可发送
+        outgoing.set_max_frame_size(1024);
+        let writer = outgoing.try_new_writer().unwrap().unwrap();
+        writer.send_bytes(Bytes::from_static(b"early")).unwrap();
+        let mut buffer = [0; 1024];
+        assert!(outgoing.try_read_datagram(&mut buffer).is_some());
+    }
+
     #[test]
     fn test_datagram_writer_on_conn_error() {
         let writer = Arc::new(Mutex::new(Ok(RawDatagramWriter::new())));